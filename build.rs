@@ -3,9 +3,11 @@ extern crate capnpc;
 
 #[cfg(feature = "capnp-recompile")]
 fn main() {
+    // The generated code is shipped in src/record_capnp.rs, so that the Cap'n Proto compiler is only needed
+    // after a change to the schema
     ::capnpc::CompilerCommand::new()
-        .src_prefix("src/flowgger")
         .file("record.capnp")
+        .output_path("src")
         .run()
         .expect("schema compiled comand");
 }
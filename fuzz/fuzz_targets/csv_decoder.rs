@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+const CONFIG: &str = "[input]\nformat = \"csv\"\ncsv_columns = [\"time\", \"host\", \"level\", \"status\", \"message\"]\n[input.csv_schema]\nstatus = \"u64\"\n";
+
+fuzz_target!(|data: &[u8]| {
+    flowgger::fuzzing::decode(CONFIG, data);
+});
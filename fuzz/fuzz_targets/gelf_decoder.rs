@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+const CONFIG: &str = "[input]\nformat = \"gelf\"\ngelf_nested = \"flatten\"\n";
+
+fuzz_target!(|data: &[u8]| {
+    flowgger::fuzzing::decode(CONFIG, data);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+const CONFIG: &str = "[input]\nformat = \"ltsv\"\n[input.ltsv_schema]\ncounter = \"u64\"\nscore = \"i64\"\nmean = \"f64\"\ndone = \"bool\"\n";
+
+fuzz_target!(|data: &[u8]| {
+    flowgger::fuzzing::decode(CONFIG, data);
+});
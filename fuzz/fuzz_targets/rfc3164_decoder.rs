@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+const CONFIG: &str = "[input]\nformat = \"rfc3164\"\n";
+
+fuzz_target!(|data: &[u8]| {
+    flowgger::fuzzing::decode(CONFIG, data);
+});
@@ -0,0 +1,320 @@
+// The commands are only reachable through the unix socket
+#![cfg_attr(not(unix), allow(dead_code))]
+
+use crate::flowgger::config::Config;
+use crate::flowgger::decoder::{
+    is_paused, is_paused_by_output, peer_stats, set_paused, set_tap, tap_enabled,
+};
+use crate::flowgger::queue_monitor::QueueStats;
+use crate::flowgger::utils::rotating_file::request_rotation;
+use serde::Deserialize;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DRAIN_TIMEOUT_SECS: u64 = 60;
+const DRAIN_POLL_INTERVAL_MS: u64 = 100;
+const HELP: &str = "Commands:
+  stats              Counters of the queue, the tap, the accounting, the redaction rules and the blackhole output
+  tap [on|off]       Show, enable or disable the tap copying raw records to stdout
+  peers [count]      Peers of the TCP and TLS inputs that sent the most, with input.peer_stats: address,
+                     connections, records, bytes, decoding errors and time of the last record
+  pause              Stop reading from the inputs
+  resume             Resume reading from the inputs
+  drain              Pause the inputs, and wait until the queue, the rate limiter and the circuit breaker
+                     of the output are empty
+  rotate             Rotate the output files, or open them again if they are not rotated
+";
+
+type StatsFn = Box<dyn Fn() -> Vec<(String, u64)> + Send>;
+type PendingFn = Box<dyn Fn() -> bool + Send>;
+
+/// Counters reported by the "stats" command, registered by the components that keep them
+static STATS: Mutex<Vec<StatsFn>> = Mutex::new(Vec::new());
+static QUEUE_STATS: OnceLock<Arc<QueueStats>> = OnceLock::new();
+/// Whether the components between the queue and the output still hold records, checked by the "drain" command
+static PENDING: Mutex<Vec<PendingFn>> = Mutex::new(Vec::new());
+
+/// Add counters to the output of the "stats" command
+pub fn register_stats<F>(stats: F)
+where
+    F: Fn() -> Vec<(String, u64)> + Send + 'static,
+{
+    STATS.lock().unwrap().push(Box::new(stats));
+}
+
+/// Make the "drain" command also wait for a component that forwards the records of the queue to the output,
+/// i.e. the rate limiter, until `pending` returns false
+pub fn register_pending<F>(pending: F)
+where
+    F: Fn() -> bool + Send + 'static,
+{
+    PENDING.lock().unwrap().push(Box::new(pending));
+}
+
+/// Settings of the admin socket, in the `[admin]` section
+#[derive(Deserialize)]
+pub struct AdminSettings {
+    socket: Option<String>,
+}
+
+/// Start the admin socket, a local control API: every line sent to it is a command, answered with its
+/// output, if any, followed by "OK" or "ERR <reason>". It can be used interactively with
+/// `socat - UNIX-CONNECT:/run/flowgger.sock`, or as `echo stats | nc -U /run/flowgger.sock`.
+///
+/// # Parameters
+/// - 'admin.socket': Optional. Path of the unix socket, created with 0600 permissions. The admin socket is
+///   only enabled when this is set.
+pub fn start(config: &Config, queue_stats: Arc<QueueStats>) {
+    let _ = QUEUE_STATS.set(queue_stats);
+    let settings: AdminSettings = config.settings("admin");
+    let path = match settings.socket {
+        None => return,
+        Some(path) => path,
+    };
+    listen(&path);
+}
+
+#[cfg(unix)]
+fn listen(path: &str) {
+    use crate::flowgger::utils::threads;
+    use std::fs;
+    use std::io::{stderr, BufRead, BufReader, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixListener;
+
+    let _ = fs::remove_file(path);
+    let listener = UnixListener::bind(path)
+        .unwrap_or_else(|e| panic!("Unable to bind the admin socket [{}]: {}", path, e));
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .unwrap_or_else(|e| panic!("Unable to restrict the admin socket [{}]: {}", path, e));
+    threads::spawn("flowgger-admin".to_owned(), None, move || {
+        for client in listener.incoming() {
+            let client = match client {
+                Ok(client) => client,
+                Err(e) => {
+                    let _ = writeln!(stderr(), "Admin socket error: {}", e);
+                    continue;
+                }
+            };
+            // Clients are served one at a time, commands are quick except "drain"
+            let mut writer = match client.try_clone() {
+                Ok(writer) => writer,
+                Err(_) => continue,
+            };
+            for line in BufReader::new(client).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if writer.write_all(command(&line).as_bytes()).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn listen(_path: &str) {
+    panic!("admin.socket is only supported on Unix");
+}
+
+/// Run a command, and return its output
+fn command(line: &str) -> String {
+    let mut args = line.split_whitespace();
+    let res = match (args.next().unwrap_or_default(), args.next(), args.next()) {
+        ("stats", None, _) => Ok(stats()),
+        ("peers", None, _) => Ok(peers(usize::MAX)),
+        ("peers", Some(count), None) => match count.parse() {
+            Ok(count) => Ok(peers(count)),
+            Err(_) => Err(format!("Invalid number of peers [{}]", count)),
+        },
+        ("tap", None, _) => Ok(format!("tap {}\n", on_off(tap_enabled()))),
+        ("tap", Some("on"), None) => {
+            set_tap(true);
+            Ok(String::new())
+        }
+        ("tap", Some("off"), None) => {
+            set_tap(false);
+            Ok(String::new())
+        }
+        ("pause", None, _) => {
+            set_paused(true);
+            Ok(String::new())
+        }
+        ("resume", None, _) => {
+            set_paused(false);
+            Ok(String::new())
+        }
+        ("drain", None, _) => drain(),
+        ("rotate", None, _) => {
+            request_rotation();
+            Ok(String::new())
+        }
+        ("help", None, _) => Ok(HELP.to_owned()),
+        _ => Err(format!("Unknown command [{}], try \"help\"", line.trim())),
+    };
+    match res {
+        Ok(output) => output + "OK\n",
+        Err(e) => format!("ERR {}\n", e),
+    }
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn stats() -> String {
+    let mut output = String::new();
+    for (name, value) in counters() {
+        let _ = writeln!(output, "{} {}", name, value);
+    }
+    output
+}
+
+fn peers(count: usize) -> String {
+    let mut output = String::new();
+    for (peer, counters) in peer_stats().iter().take(count) {
+        let _ = writeln!(
+            output,
+            "{} connections={} records={} bytes={} errors={} last_activity={}",
+            peer,
+            counters.connections(),
+            counters.records(),
+            counters.bytes(),
+            counters.errors(),
+            counters.last_activity()
+        );
+    }
+    output
+}
+
+/// Counters of the queue, of the inputs and of every component that registered some
+pub fn counters() -> Vec<(String, u64)> {
+    let mut counters = Vec::new();
+    if let Some(queue) = QUEUE_STATS.get() {
+        counters.push(("queue.occupancy".to_owned(), queue.occupancy() as u64));
+        counters.push(("queue.capacity".to_owned(), queue.capacity() as u64));
+        counters.push(("queue.peak".to_owned(), queue.peak() as u64));
+        counters.push(("queue.warnings".to_owned(), queue.warnings()));
+        if let Some(budget) = queue.budget() {
+            counters.push(("queue.bytes".to_owned(), budget.used() as u64));
+            counters.push(("queue.max_bytes".to_owned(), budget.max_bytes() as u64));
+        }
+    }
+    counters.push(("inputs.paused".to_owned(), is_paused() as u64));
+    counters.push((
+        "inputs.paused_by_output".to_owned(),
+        is_paused_by_output() as u64,
+    ));
+    counters.push(("tap.enabled".to_owned(), tap_enabled() as u64));
+    for stats in STATS.lock().unwrap().iter() {
+        counters.extend(stats());
+    }
+    counters
+}
+
+/// Pause the inputs, and wait until the outputs have taken every record from the queue
+fn drain() -> Result<String, String> {
+    set_paused(true);
+    let queue = match QUEUE_STATS.get() {
+        None => return Ok(String::new()),
+        Some(queue) => queue,
+    };
+    let pending = || PENDING.lock().unwrap().iter().any(|pending| pending());
+    wait_drained(queue, pending, Duration::from_secs(DRAIN_TIMEOUT_SECS))
+}
+
+fn wait_drained<F>(queue: &QueueStats, pending: F, timeout: Duration) -> Result<String, String>
+where
+    F: Fn() -> bool,
+{
+    let deadline = Instant::now() + timeout;
+    // A record is briefly neither in the queue nor marked as pending while it is handed over,
+    // so the pipeline has to be seen empty twice in a row
+    let mut seen_empty = false;
+    loop {
+        let occupancy = queue.occupancy();
+        if occupancy == 0 && !pending() {
+            if seen_empty {
+                return Ok(String::new());
+            }
+            seen_empty = true;
+        } else {
+            seen_empty = false;
+            if Instant::now() >= deadline {
+                return Err(if occupancy > 0 {
+                    format!(
+                        "The queue still holds {} records, the inputs stay paused",
+                        occupancy
+                    )
+                } else {
+                    "The rate limiter or the circuit breaker of the output still holds records, the inputs stay paused"
+                        .to_owned()
+                });
+            }
+        }
+        thread::sleep(Duration::from_millis(DRAIN_POLL_INTERVAL_MS));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admin_commands() {
+        register_stats(|| vec![("test.counter".to_owned(), 42)]);
+        let output = command("stats");
+        assert!(output.contains("test.counter 42\n"));
+        assert!(output.ends_with("OK\n"));
+        assert!(command("tap").starts_with("tap o"));
+        assert!(command("peers 10").ends_with("OK\n"));
+        assert!(command("peers many").starts_with("ERR Invalid number of peers [many]"));
+        assert!(command("tap sideways").starts_with("ERR Unknown command [tap sideways]"));
+        assert!(command("help").starts_with("Commands:\n"));
+    }
+
+    #[test]
+    fn test_admin_drain() {
+        use crate::flowgger::record_queue::bounded;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let (tx, rx) = bounded(10);
+        let queue = QueueStats::new(rx.occupancy());
+        let held = Arc::new(AtomicBool::new(false));
+        let pending = || held.load(Ordering::Relaxed);
+        tx.send(b"record".to_vec()).unwrap();
+        let timeout = Duration::from_millis(DRAIN_POLL_INTERVAL_MS);
+        assert_eq!(
+            wait_drained(&queue, pending, timeout).unwrap_err(),
+            "The queue still holds 1 records, the inputs stay paused"
+        );
+
+        // The record left the queue, but the rate limiter still holds it
+        rx.recv().unwrap();
+        held.store(true, Ordering::Relaxed);
+        assert!(wait_drained(&queue, pending, timeout)
+            .unwrap_err()
+            .starts_with(
+                "The rate limiter or the circuit breaker of the output still holds records"
+            ));
+
+        let output_held = Arc::clone(&held);
+        let output = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(DRAIN_POLL_INTERVAL_MS * 2));
+            output_held.store(false, Ordering::Relaxed);
+        });
+        assert!(wait_drained(&queue, pending, Duration::from_secs(5)).is_ok());
+        output.join().unwrap();
+    }
+}
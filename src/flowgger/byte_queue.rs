@@ -0,0 +1,123 @@
+use crate::flowgger::config::Config;
+use crate::flowgger::queue_monitor::QueueStats;
+use crate::flowgger::record_queue::{self, RecordReceiver, RecordSender};
+use serde::Deserialize;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Bytes of the records waiting in the queue
+pub struct ByteBudget {
+    max_bytes: usize,
+    used: Mutex<usize>,
+    released: Condvar,
+}
+
+impl ByteBudget {
+    fn new(max_bytes: usize) -> ByteBudget {
+        ByteBudget {
+            max_bytes,
+            used: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Wait until `len` more bytes fit, and take them. A record larger than the whole budget is admitted
+    /// once the queue is empty, rather than blocking forever.
+    pub fn acquire(&self, len: usize) {
+        let mut used = self.used.lock().unwrap();
+        while *used > 0 && *used + len > self.max_bytes {
+            used = self.released.wait(used).unwrap();
+        }
+        *used += len;
+    }
+
+    pub fn release(&self, len: usize) {
+        let mut used = self.used.lock().unwrap();
+        *used -= len;
+        self.released.notify_all();
+    }
+
+    /// Bytes currently queued
+    pub fn used(&self) -> usize {
+        *self.used.lock().unwrap()
+    }
+
+    /// Maximum number of bytes the queue holds
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+}
+
+/// Settings of the size of the queue, in the `[input]` section
+#[derive(Deserialize)]
+pub struct ByteQueueSettings {
+    queue_max_bytes: Option<NonZeroUsize>,
+}
+
+/// Queue between the inputs and the outputs bounded by the size of the encoded records, on top of their
+/// number, so that memory usage doesn't depend on the size of the records, i.e. with large GELF
+/// `full_message` payloads.
+///
+/// Records are counted from the time they are queued until an output takes them. Inputs block once the
+/// queue is full, like with the bounded number of records.
+pub struct ByteQueue {
+    budget: Arc<ByteBudget>,
+}
+
+impl ByteQueue {
+    /// # Parameters
+    /// - 'input.queue_max_bytes': Optional. Maximum size, in bytes, of the records waiting in the queue.
+    ///   The queue is only bounded by size when this is set.
+    pub fn from_config(config: &Config) -> Option<ByteQueue> {
+        let settings: ByteQueueSettings = config.settings("input");
+        let max_bytes = settings.queue_max_bytes?.get();
+        Some(ByteQueue {
+            budget: Arc::new(ByteBudget::new(max_bytes)),
+        })
+    }
+
+    /// # Returns
+    /// The sender of the inputs, the receiver of the outputs, and the statistics of the queue, that also
+    /// holds at most `queue_size` records
+    pub fn start(self, queue_size: usize) -> (RecordSender, RecordReceiver, QueueStats) {
+        let (tx, rx) = record_queue::with_budget(queue_size, self.budget);
+        let stats = QueueStats::new(rx.occupancy());
+        (tx, rx, stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_byte_queue() {
+        let config = Config::from_string("[input]\nqueue_max_bytes = 10\n").unwrap();
+        let (tx, rx, stats) = ByteQueue::from_config(&config).unwrap().start(100);
+        let budget = stats.budget().unwrap();
+        tx.send(vec![0; 6]).unwrap();
+        tx.send(vec![0; 4]).unwrap();
+
+        // The queue is full: the next record waits until the first one has been taken
+        let sender = thread::spawn(move || tx.send(vec![0; 5]).unwrap());
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(budget.used(), 10);
+        assert_eq!(rx.recv().unwrap().len(), 6);
+        sender.join().unwrap();
+        assert_eq!(rx.recv().unwrap().len(), 4);
+        assert_eq!(rx.recv().unwrap().len(), 5);
+        assert!(rx.recv().is_err());
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn test_byte_budget_large_record() {
+        let budget = ByteBudget::new(10);
+        budget.acquire(25);
+        assert_eq!(budget.used(), 25);
+        budget.release(25);
+        assert_eq!(budget.used(), 0);
+    }
+}
@@ -1,7 +1,13 @@
+use serde::de::DeserializeOwned;
+#[cfg(test)]
+use serde::de::{self, Deserializer, Visitor};
+use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::{Error, ErrorKind};
 use std::path::Path;
+use toml::value::Table;
 use toml::Value;
 
 /// [`Configuration`][] storage for flowgger configs
@@ -37,7 +43,7 @@ impl Config {
     ///
     /// - `Ok`: Containing the Config object
     /// - `Err`: if the file doesn't exist, is not readable, cannot be parsed into a string or is
-    /// not valid [`TOML`][https://github.com/toml-lang/toml#user-content-array]
+    ///   not valid [`TOML`][https://github.com/toml-lang/toml#user-content-array]
     ///
     /// # Errors
     ///
@@ -68,7 +74,7 @@ impl Config {
     /// # Errors
     ///
     /// - `InvalidData: Syntax error - config file is not valid TOML`: will be returned if the toml
-    /// string is not valid toml and cannot be parsed
+    ///   string is not valid toml and cannot be parsed
     ///
     pub fn from_string(toml: &str) -> Result<Config, Error> {
         let config: Value = match toml.parse() {
@@ -98,19 +104,171 @@ impl Config {
         let mut current_value = &(self.config);
         for index in path_parts.iter() {
             if current_value.is_table() {
-                current_value = match current_value.get(index) {
-                    Some(value) => value,
-                    None => return None,
-                };
+                current_value = current_value.get(index)?;
             }
         }
-        Some(&current_value)
+        Some(current_value)
+    }
+
+    /// Typed settings of a component, deserialized from a section shared with other components, whose
+    /// settings are ignored. A missing section has no settings.
+    ///
+    /// Every component reads its settings this way. The settings types are listed in `config_check`, whose
+    /// tests check that their fields are registered.
+    ///
+    /// # Parameters
+    /// - `section`: name of the section, i.e. 'output'
+    ///
+    /// # Type parameters
+    /// - `T`: settings of the component, named after the keys of the section, optional ones being `Option`s
+    ///
+    /// # Panics
+    /// If a setting is invalid or a required one is missing, with the path of the setting, i.e.
+    /// "Invalid configuration: invalid type: string \"a\", expected u32 for key `output.kafka_threads`"
+    pub fn settings<T: DeserializeOwned>(&self, section: &str) -> T {
+        let table = self
+            .config
+            .get(section)
+            .cloned()
+            .unwrap_or_else(|| Value::Table(Default::default()));
+        let mut sections = Table::new();
+        sections.insert(section.to_owned(), table);
+        Value::Table(sections)
+            .try_into::<BTreeMap<String, T>>()
+            .unwrap_or_else(|e| panic!("Invalid configuration: {}", e))
+            .remove(section)
+            .expect("The section was deserialized")
+    }
+
+    /// Set a value from a string in dotted format, i.e. to override the configuration file from the command
+    /// line. Missing tables are created.
+    ///
+    /// # Parameters
+    /// - `path`: a dotted string like 'input.format`
+    /// - `value`: the value to set
+    pub fn set(&mut self, path: &str, value: Value) {
+        let path_parts: Vec<&str> = path.split('.').collect();
+        let (key, tables) = path_parts.split_last().expect("The path can't be empty");
+        let mut current_table = self
+            .config
+            .as_table_mut()
+            .expect("The configuration must be a table");
+        for name in tables {
+            current_table = current_table
+                .entry(name.to_string())
+                .or_insert_with(|| Value::Table(Default::default()))
+                .as_table_mut()
+                .unwrap_or_else(|| panic!("{} must be a table", name));
+        }
+        current_table.insert(key.to_string(), value);
+    }
+
+    /// Names of the sections of the configuration, i.e. `input` and `output`
+    pub fn sections(&self) -> Vec<&str> {
+        self.config
+            .as_table()
+            .map_or_else(Vec::new, |table| table.keys().map(|x| x.as_str()).collect())
+    }
+
+    /// Configurations of the additional inputs listed in `input.listeners`
+    ///
+    /// Every listener is a table of input settings, i.e. `type`, `listen` and `format`. The configuration of a
+    /// listener is the main configuration, with these settings replacing the ones of the `[input]` section.
+    ///
+    /// # Returns
+    /// A configuration per listener, empty if `input.listeners` is not set
+    pub fn input_listeners(&self) -> Vec<Config> {
+        let listeners = match self.lookup("input.listeners") {
+            None => return Vec::new(),
+            Some(listeners) => listeners
+                .as_array()
+                .expect("input.listeners must be an array of tables"),
+        };
+        let mut input = self.config["input"]
+            .as_table()
+            .expect("input must be a table")
+            .clone();
+        input.remove("listeners");
+        listeners
+            .iter()
+            .map(|listener| {
+                let listener = listener
+                    .as_table()
+                    .expect("input.listeners must be an array of tables");
+                let mut listener_input = input.clone();
+                listener_input.extend(listener.clone());
+                let mut config = self.config.clone();
+                config
+                    .as_table_mut()
+                    .expect("The configuration must be a table")
+                    .insert("input".to_owned(), Value::Table(listener_input));
+                Config { config }
+            })
+            .collect()
+    }
+}
+
+/// Setting that is either a single value or a list of values, i.e. `listen = "0.0.0.0:514"` or
+/// `listen = ["0.0.0.0:514", "[::]:514"]`
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    pub fn into_vec(self) -> Vec<T> {
+        match self {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+/// Names of the settings a settings type is deserialized from, i.e. "output.unix_path"
+#[cfg(test)]
+pub fn setting_names<T: DeserializeOwned>(section: &str) -> Vec<String> {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+        .iter()
+        .map(|field| format!("{}.{}", section, field))
+        .collect()
+}
+
+/// Deserializer keeping the field names of the struct deserialized from it, and failing
+#[cfg(test)]
+struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+#[cfg(test)]
+impl<'de> Deserializer<'de> for FieldNames<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("Settings must be structs"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(de::Error::custom("Only the field names are read"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit
+        unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use serde::Deserialize;
 
     #[test]
     fn test_config_from_string() {
@@ -182,6 +340,103 @@ mod test {
         let _config = Config::from_path("doesnotexist.toml").unwrap();
     }
 
+    #[test]
+    fn test_config_input_listeners() {
+        let config = Config::from_string(
+            r#"[input]
+type = "tcp"
+listen = "0.0.0.0:6514"
+format = "rfc5424"
+[[input.listeners]]
+type = "udp"
+listen = "0.0.0.0:1514"
+format = "rfc3164"
+[[input.listeners]]
+listen = "0.0.0.0:12201"
+format = "gelf"
+[output]
+type = "debug"
+"#,
+        )
+        .unwrap();
+        let listeners = config.input_listeners();
+        assert_eq!(listeners.len(), 2);
+        let setting =
+            |config: &Config, path| config.lookup(path).unwrap().as_str().unwrap().to_owned();
+        assert_eq!(setting(&listeners[0], "input.type"), "udp");
+        assert_eq!(setting(&listeners[0], "input.format"), "rfc3164");
+        assert_eq!(setting(&listeners[1], "input.type"), "tcp");
+        assert_eq!(setting(&listeners[1], "input.listen"), "0.0.0.0:12201");
+        assert_eq!(setting(&listeners[1], "output.type"), "debug");
+        assert!(listeners[1].lookup("input.listeners").is_none());
+        assert_eq!(setting(&config, "input.format"), "rfc5424");
+
+        let config = Config::from_string("[input]\ntype = \"tcp\"\n").unwrap();
+        assert!(config.input_listeners().is_empty());
+    }
+
+    #[test]
+    fn test_config_set() {
+        let mut config = Config::from_string("[input]\nformat = \"rfc5424\"\n").unwrap();
+        config.set("input.format", Value::String("ltsv".to_owned()));
+        config.set("output.format", Value::String("gelf".to_owned()));
+        assert_eq!(
+            config.lookup("input.format").unwrap().as_str(),
+            Some("ltsv")
+        );
+        assert_eq!(
+            config.lookup("output.format").unwrap().as_str(),
+            Some("gelf")
+        );
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct TestSettings {
+        this_is_valid_field: String,
+        integer_value: Option<u32>,
+    }
+
+    #[test]
+    fn test_config_settings() {
+        let config = Config::from_path("tests/resources/good_config.toml").unwrap();
+        let settings: TestSettings = config.settings("this_is_a_valid_section");
+        assert_eq!(
+            settings,
+            TestSettings {
+                this_is_valid_field: "this is a valid value".to_owned(),
+                integer_value: None,
+            }
+        );
+        let settings: BTreeMap<String, u32> = Config::from_string("").unwrap().settings("input");
+        assert!(settings.is_empty());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid configuration: invalid type: string \"x\", expected u32 for key `section.integer_value`"
+    )]
+    fn test_config_settings_invalid() {
+        let config =
+            Config::from_string("[section]\nthis_is_valid_field = \"\"\ninteger_value = \"x\"\n")
+                .unwrap();
+        let _settings: TestSettings = config.settings("section");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid configuration: missing field `this_is_valid_field` for key `section`"
+    )]
+    fn test_config_settings_missing() {
+        let _settings: TestSettings = Config::from_string("").unwrap().settings("section");
+    }
+
+    #[test]
+    fn test_config_sections() {
+        let config = Config::from_string("[input]\ntype = \"tcp\"\n[output]\n").unwrap();
+        assert_eq!(config.sections(), vec!["input", "output"]);
+        assert!(Config::from_string("").unwrap().sections().is_empty());
+    }
+
     #[test]
     fn test_config_clone() {
         let config = Config::from_path("tests/resources/good_config.toml").unwrap();
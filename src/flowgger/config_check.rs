@@ -0,0 +1,677 @@
+use super::config::Config;
+use super::{default_input_format, DEFAULT_INPUT_TYPE, DEFAULT_OUTPUT_FORMAT, DEFAULT_OUTPUT_TYPE};
+use serde::Deserialize;
+use std::io::{stderr, Write};
+use toml::Value;
+
+/// Settings that apply whatever the input, the output and their formats are
+const COMMON_SETTINGS: &[&str] = &[
+    "admin.socket",
+    "config.strict",
+    "daemon.group",
+    "daemon.user",
+    "input.charset",
+    "input.cpu_affinity",
+    "input.dead_letter",
+    "input.dead_letter_kafka_brokers",
+    "input.dead_letter_kafka_topic",
+    "input.dead_letter_path",
+    "input.decode_error_min_records",
+    "input.decode_error_warn_path",
+    "input.decode_error_warn_percent",
+    "input.decode_error_warn_sink",
+    "input.decode_error_window",
+    "input.format",
+    "input.invalid_utf8",
+    "input.listeners",
+    "input.msg_uid",
+    "input.queue_low_percent",
+    "input.queue_max_bytes",
+    "input.queue_warn_percent",
+    "input.queuesize",
+    "input.received_ts",
+    "input.schema_max_lengths",
+    "input.schema_required",
+    "input.schema_types",
+    "input.script",
+    "input.script_max_operations",
+    "input.script_timeout",
+    "input.sd_limit_policy",
+    "input.sd_max_pairs",
+    "input.sd_max_value_length",
+    "input.syslog_sign_key",
+    "input.syslog_sign_window",
+    "input.tap_sample",
+    "input.tenant",
+    "input.trace_context",
+    "input.trace_context_fields",
+    "input.trace_context_patterns",
+    "input.type",
+    "input.wasm_fuel",
+    "input.wasm_max_memory",
+    "input.wasm_transform",
+    "output.accounting_interval",
+    "output.accounting_key",
+    "output.accounting_max_keys",
+    "output.cpu_affinity",
+    "output.format",
+    "output.framing",
+    "output.framing_delimiter",
+    "output.max_msg_length",
+    "output.rate_limit",
+    "output.rate_limit_burst",
+    "output.redact",
+    "output.redact_fields",
+    "output.redact_report_interval",
+    "output.sanitize",
+    "output.sequence_file",
+    "output.spill_dir",
+    "output.spill_max_size",
+    "output.spill_threshold",
+    "output.syslog_sign_count",
+    "output.syslog_sign_hash",
+    "output.syslog_sign_hostname",
+    "output.syslog_sign_interval",
+    "output.syslog_sign_key",
+    "output.truncation_marker",
+    "output.type",
+    "stats.appname",
+    "stats.facility",
+    "stats.format",
+    "stats.hostname",
+    "stats.interval",
+    "stats.severity",
+];
+
+const TCP_INPUT_TYPES: &[&str] = &[
+    "tcp",
+    "syslog-tcp",
+    "tcp_co",
+    "tcpco",
+    "syslog-tcp_co",
+    "syslog-tcpco",
+];
+const TLS_INPUT_TYPES: &[&str] = &[
+    "tls",
+    "syslog-tls",
+    "tls_co",
+    "tlsco",
+    "syslog-tls_co",
+    "syslog-tlsco",
+];
+
+/// Settings of the stream inputs, whether they use TLS or not
+const STREAM_INPUT_SETTINGS: &[&str] = &[
+    "input.connection_events",
+    "input.connection_events_appname",
+    "input.decompress",
+    "input.dedup_max_sources",
+    "input.dedup_window",
+    "input.framed",
+    "input.framing",
+    "input.framing_delimiter",
+    "input.ipv6_only",
+    "input.listen",
+    "input.peer_stats",
+    "input.peer_stats_max_peers",
+    "input.timeout",
+];
+
+/// Settings of the TLS inputs, also used by the TCP inputs to upgrade connections with STARTTLS
+const TLS_ACCEPTOR_SETTINGS: &[&str] = &[
+    "input.tls_allowed_fingerprints",
+    "input.tls_allowed_fingerprints_file",
+    "input.tls_ca_file",
+    "input.tls_cert",
+    "input.tls_ciphers",
+    "input.tls_ciphersuites",
+    "input.tls_compatibility_level",
+    "input.tls_compression",
+    "input.tls_key",
+    "input.tls_min_version",
+    "input.tls_session_resumption",
+    "input.tls_verify_peer",
+];
+
+/// Settings of the components, by setting selecting the component, names of the component, and settings
+const COMPONENT_SETTINGS: &[(&str, &[&str], &[&str])] = &[
+    ("input.type", TCP_INPUT_TYPES, STREAM_INPUT_SETTINGS),
+    (
+        "input.type",
+        TCP_INPUT_TYPES,
+        &["input.tcp_starttls", "input.tcp_threads"],
+    ),
+    ("input.type", TCP_INPUT_TYPES, TLS_ACCEPTOR_SETTINGS),
+    ("input.type", TLS_INPUT_TYPES, STREAM_INPUT_SETTINGS),
+    ("input.type", TLS_INPUT_TYPES, TLS_ACCEPTOR_SETTINGS),
+    ("input.type", TLS_INPUT_TYPES, &["input.tls_threads"]),
+    (
+        "input.type",
+        &["udp"],
+        &["input.decompress", "input.ipv6_only", "input.listen"],
+    ),
+    (
+        "input.type",
+        &["statsd"],
+        &["input.ipv6_only", "input.listen"],
+    ),
+    (
+        "input.type",
+        &["relp"],
+        &[
+            "input.ipv6_only",
+            "input.listen",
+            "input.relp_max_frame_size",
+            "input.timeout",
+        ],
+    ),
+    (
+        "input.type",
+        &["redis"],
+        &[
+            "input.redis_connect",
+            "input.redis_queue_key",
+            "input.redis_recovery_delay_init",
+            "input.redis_recovery_delay_max",
+            "input.redis_recovery_probe_time",
+            "input.redis_sentinel_master",
+            "input.redis_stream_claim_idle",
+            "input.redis_stream_consumer",
+            "input.redis_stream_field",
+            "input.redis_stream_group",
+            "input.redis_stream_key",
+            "input.redis_threads",
+        ],
+    ),
+    (
+        "input.type",
+        &["stdin"],
+        &["input.framing", "input.framing_delimiter"],
+    ),
+    ("input.type", &["file"], &["input.checkpoint", "input.src"]),
+    (
+        "input.type",
+        &["replay"],
+        &[
+            "input.framing",
+            "input.framing_delimiter",
+            "input.replay_format",
+            "input.replay_port",
+            "input.replay_rate",
+            "input.src",
+        ],
+    ),
+    (
+        "input.type",
+        &["generator"],
+        &[
+            "input.generator_appnames",
+            "input.generator_count",
+            "input.generator_format",
+            "input.generator_hosts",
+            "input.generator_msg_length",
+            "input.generator_rate",
+        ],
+    ),
+    (
+        "input.format",
+        &["csv", "w3c"],
+        &[
+            "input.csv_columns",
+            "input.csv_delimiter",
+            "input.csv_schema",
+        ],
+    ),
+    (
+        "input.format",
+        &["auto", "gelf"],
+        &["input.gelf_nested", "input.gelf_sd_id"],
+    ),
+    (
+        "input.format",
+        &["ltsv"],
+        &[
+            "input.ltsv_host_label",
+            "input.ltsv_level_label",
+            "input.ltsv_message_label",
+            "input.ltsv_missing_host",
+            "input.ltsv_schema",
+            "input.ltsv_suffixes",
+            "input.ltsv_time_formats",
+            "input.ltsv_time_label",
+        ],
+    ),
+    ("input.format", &["wasm"], &["input.wasm_decoder"]),
+    (
+        "output.type",
+        &["blackhole", "null"],
+        &[
+            "output.blackhole_latency",
+            "output.blackhole_report_interval",
+            "output.blackhole_threads",
+        ],
+    ),
+    (
+        "output.type",
+        &["kafka"],
+        &[
+            "output.kafka_acks",
+            "output.kafka_brokers",
+            "output.kafka_coalesce",
+            "output.kafka_compression",
+            "output.kafka_header_fields",
+            "output.kafka_headers",
+            "output.kafka_linger_ms",
+            "output.kafka_startup_check",
+            "output.kafka_startup_retry_delay",
+            "output.kafka_threads",
+            "output.kafka_timeout",
+            "output.kafka_topic",
+            "output.kafka_topic_field",
+        ],
+    ),
+    (
+        "output.type",
+        &["mqtt"],
+        &[
+            "output.mqtt_broker",
+            "output.mqtt_client_id",
+            "output.mqtt_keep_alive",
+            "output.mqtt_password",
+            "output.mqtt_qos",
+            "output.mqtt_tls",
+            "output.mqtt_tls_ca_file",
+            "output.mqtt_tls_cert",
+            "output.mqtt_tls_key",
+            "output.mqtt_topic",
+            "output.mqtt_username",
+        ],
+    ),
+    (
+        "output.type",
+        &["postgres", "postgresql"],
+        &[
+            "output.pause_inputs",
+            "output.postgres_columns",
+            "output.postgres_connections",
+            "output.postgres_record_column",
+            "output.postgres_table",
+            "output.postgres_url",
+        ],
+    ),
+    (
+        "output.type",
+        &["tls", "syslog-tls"],
+        &[
+            "output.compress",
+            "output.compress_level",
+            "output.connect",
+            "output.connect_attempt_delay",
+            "output.connect_timeout",
+            "output.dns_refresh",
+            "output.ip_preference",
+            "output.pause_inputs",
+            "output.proxy_url",
+            "output.timeout",
+            "output.tls_async",
+            "output.tls_ca_file",
+            "output.tls_cert",
+            "output.tls_ciphers",
+            "output.tls_ciphersuites",
+            "output.tls_compression",
+            "output.tls_flush_bytes",
+            "output.tls_flush_interval",
+            "output.tls_key",
+            "output.tls_min_version",
+            "output.tls_recovery_delay_init",
+            "output.tls_recovery_delay_max",
+            "output.tls_recovery_probe_time",
+            "output.tls_session_resumption",
+            "output.tls_threads",
+            "output.tls_verify_peer",
+        ],
+    ),
+    (
+        "output.type",
+        &["file"],
+        &[
+            "output.file_buffer_size",
+            "output.file_compression",
+            "output.file_errors_path",
+            "output.file_hash_chain_key",
+            "output.file_path",
+            "output.file_path_template",
+            "output.file_rotation_calendar",
+            "output.file_rotation_manifest",
+            "output.file_rotation_maxfiles",
+            "output.file_rotation_size",
+            "output.file_rotation_time",
+            "output.file_rotation_timeformat",
+            "output.file_rotation_timezone",
+            "output.file_split_by",
+        ],
+    ),
+    (
+        "output.type",
+        &["relp"],
+        &[
+            "output.connect",
+            "output.pause_inputs",
+            "output.relp_window",
+            "output.timeout",
+        ],
+    ),
+    (
+        "output.type",
+        &["sqlite"],
+        &[
+            "output.sqlite_columns",
+            "output.sqlite_max_age",
+            "output.sqlite_max_records",
+            "output.sqlite_max_size",
+            "output.sqlite_path",
+            "output.sqlite_table",
+        ],
+    ),
+    (
+        "output.type",
+        &["unix"],
+        &[
+            "output.unix_path",
+            "output.unix_reconnect_delay",
+            "output.unix_socket_type",
+        ],
+    ),
+    ("output.format", &["capnp"], &["output.capnp_extra"]),
+    (
+        "output.format",
+        &["gelf", "json"],
+        &[
+            "output.ecs",
+            "output.gelf_extra",
+            "output.gelf_sd_id",
+            "output.syslog_names",
+        ],
+    ),
+    ("output.format", &["logfmt"], &["output.syslog_names"]),
+    (
+        "output.format",
+        &["ltsv"],
+        &[
+            "output.ltsv_extra",
+            "output.ltsv_fields",
+            "output.syslog_names",
+        ],
+    ),
+    (
+        "output.format",
+        &["rfc3164", "passthrough"],
+        &["output.syslog_prepend_timestamp"],
+    ),
+    (
+        "output.format",
+        &["rfc5424"],
+        &["output.rfc5424_keep_utc_offset"],
+    ),
+];
+
+/// Settings of the check of the configuration, in the `[config]` section
+#[derive(Deserialize)]
+pub struct CheckSettings {
+    strict: Option<bool>,
+}
+
+/// Report the settings of the configuration that flowgger doesn't recognize, i.e. misspelled, or that don't
+/// apply to the selected input, output and formats, since they are silently ignored otherwise.
+///
+/// # Parameters
+/// - 'config.strict': Optional. Refuse to start if settings are not recognized, instead of reporting them
+///   on stderr. Default is false.
+///
+/// # Panics
+/// If 'config.strict' is set and settings are not recognized
+pub fn check_settings(config: &Config) {
+    let settings: CheckSettings = config.settings("config");
+    let strict = settings.strict.unwrap_or(false);
+    let unknown = unknown_settings(config);
+    if unknown.is_empty() {
+        return;
+    }
+    if strict {
+        panic!(
+            "Settings not recognized in the configuration: {}",
+            unknown.join(", ")
+        );
+    }
+    for setting in unknown {
+        let _ = writeln!(stderr(), "Setting not recognized, ignored: {}", setting);
+    }
+}
+
+/// Settings of the configuration that are not recognized, in the dotted format, listener settings being
+/// reported as `input.listeners.<name>`
+pub fn unknown_settings(config: &Config) -> Vec<String> {
+    let mut configs = config.input_listeners();
+    configs.push(config.clone());
+    let recognized = |setting: &str| {
+        COMMON_SETTINGS.contains(&setting)
+            || configs.iter().any(|config| {
+                COMPONENT_SETTINGS
+                    .iter()
+                    .any(|&(selector, names, settings)| {
+                        settings.contains(&setting)
+                            && selected(config, selector)
+                                .iter()
+                                .any(|name| names.contains(&name.as_str()))
+                    })
+            })
+    };
+    let mut unknown = Vec::new();
+    for section in config.sections() {
+        let table = match config.lookup(section).and_then(Value::as_table) {
+            Some(table) => table,
+            None => {
+                unknown.push(section.to_owned());
+                continue;
+            }
+        };
+        for name in table.keys() {
+            let setting = format!("{}.{}", section, name);
+            if !recognized(&setting) {
+                unknown.push(setting);
+            }
+        }
+    }
+    let listeners = config.lookup("input.listeners").and_then(Value::as_array);
+    for listener in listeners.into_iter().flatten().filter_map(Value::as_table) {
+        for name in listener.keys() {
+            if name != "listeners" && !recognized(&format!("input.{}", name)) {
+                unknown.push(format!("input.listeners.{}", name));
+            }
+        }
+    }
+    unknown.sort();
+    unknown.dedup();
+    unknown
+}
+
+/// Names of the components selected by a setting, or of the default component if it is not set
+fn selected(config: &Config, selector: &str) -> Vec<String> {
+    match config.lookup(selector) {
+        Some(Value::String(name)) => vec![name.to_owned()],
+        Some(Value::Array(names)) => names
+            .iter()
+            .filter_map(|x| x.as_str().map(|x| x.to_owned()))
+            .collect(),
+        Some(_) => Vec::new(),
+        None => vec![match selector {
+            "input.type" => DEFAULT_INPUT_TYPE,
+            "input.format" => default_input_format(
+                config
+                    .lookup("input.type")
+                    .and_then(Value::as_str)
+                    .unwrap_or(DEFAULT_INPUT_TYPE),
+            ),
+            "output.type" => DEFAULT_OUTPUT_TYPE,
+            _ => DEFAULT_OUTPUT_FORMAT,
+        }
+        .to_owned()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::admin::AdminSettings;
+    use crate::flowgger::byte_queue::ByteQueueSettings;
+    use crate::flowgger::config::setting_names;
+    use crate::flowgger::config_init;
+    use crate::flowgger::daemon::DaemonSettings;
+    use crate::flowgger::impstats::StatsSettings;
+    use crate::flowgger::queue_monitor::QueueMonitorSettings;
+    #[cfg(feature = "tls")]
+    use crate::flowgger::utils::proxy::ProxySettings;
+    #[cfg(feature = "tls")]
+    use crate::flowgger::utils::resolver::ResolverSettings;
+    use crate::flowgger::utils::threads::CpuAffinitySettings;
+    #[cfg(feature = "tls")]
+    use crate::flowgger::utils::tls::TlsProtocolSettings;
+    use crate::flowgger::{decoder, encoder, input, merger, output, splitter};
+    use crate::flowgger::{InputSettings, OutputSettings};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_unknown_settings() {
+        let config = Config::from_string(
+            r#"[input]
+type = "udp"
+listen = "0.0.0.0:514"
+tls_cert = "flowgger.pem"
+[[input.listeners]]
+type = "tls"
+tls_cert = "flowgger.pem"
+tls_kye = "flowgger.pem"
+[output]
+type = "kafka"
+kakfa_topic = "logs"
+format = "ltsv"
+ltsv_fields = [ "host" ]
+gelf_extra = { x = "y" }
+[ouput]
+type = "file"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            unknown_settings(&config),
+            vec![
+                "input.listeners.tls_kye",
+                "ouput.type",
+                "output.gelf_extra",
+                "output.kakfa_topic"
+            ]
+        );
+
+        let config = Config::from_string("[input]\nlisten = \"0.0.0.0:6514\"\ntls_cert = \"flowgger.pem\"\ncsv_columns = []\n[output]\necs = true\nsyslog_prepend_timestamp = \"[%F %T]\"\n").unwrap();
+        assert_eq!(
+            unknown_settings(&config),
+            vec!["input.csv_columns", "output.syslog_prepend_timestamp"]
+        );
+    }
+
+    #[test]
+    fn test_config_init_settings() {
+        for (input_type, output_type) in [
+            ("tls", "kafka"),
+            ("tcp", "tls"),
+            ("udp", "file"),
+            ("stdin", "debug"),
+            ("relp", "relp"),
+        ] {
+            if let Ok(toml) = config_init(Some(input_type), Some(output_type)) {
+                let config = Config::from_string(&toml).unwrap();
+                assert!(
+                    unknown_settings(&config).is_empty(),
+                    "{:?}",
+                    unknown_settings(&config)
+                );
+            }
+        }
+    }
+
+    fn source_settings(path: &Path, settings: &mut Vec<String>) {
+        if path.is_dir() {
+            for entry in fs::read_dir(path).unwrap() {
+                source_settings(&entry.unwrap().path(), settings);
+            }
+        } else if path.extension().is_some_and(|x| x == "rs") && !path.ends_with("config_check.rs")
+        {
+            let source = fs::read_to_string(path).unwrap();
+            for literal in source.split('"') {
+                let is_setting = literal.split_once('.').is_some_and(|(section, name)| {
+                    ["admin", "config", "daemon", "input", "output", "stats"].contains(&section)
+                        && !name.is_empty()
+                        && name
+                            .chars()
+                            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+                });
+                if is_setting {
+                    settings.push(literal.to_owned());
+                }
+            }
+        }
+    }
+
+    /// Settings of the components whose settings are deserialized with `Config::settings`
+    fn typed_settings() -> Vec<String> {
+        let mut settings = setting_names::<StatsSettings>("stats");
+        settings.extend(setting_names::<AdminSettings>("admin"));
+        settings.extend(setting_names::<ByteQueueSettings>("input"));
+        settings.extend(setting_names::<CheckSettings>("config"));
+        settings.extend(setting_names::<CpuAffinitySettings>("input"));
+        settings.extend(setting_names::<CpuAffinitySettings>("output"));
+        settings.extend(setting_names::<DaemonSettings>("daemon"));
+        settings.extend(setting_names::<InputSettings>("input"));
+        settings.extend(setting_names::<OutputSettings>("output"));
+        settings.extend(setting_names::<QueueMonitorSettings>("input"));
+        #[cfg(feature = "tls")]
+        settings.extend(setting_names::<ProxySettings>("output"));
+        #[cfg(feature = "tls")]
+        settings.extend(setting_names::<ResolverSettings>("output"));
+        #[cfg(feature = "tls")]
+        settings.extend(setting_names::<TlsProtocolSettings>("input"));
+        #[cfg(feature = "tls")]
+        settings.extend(setting_names::<TlsProtocolSettings>("output"));
+        settings.extend(decoder::typed_settings());
+        settings.extend(encoder::typed_settings());
+        settings.extend(input::typed_settings());
+        settings.extend(output::typed_settings());
+        settings.extend(setting_names::<merger::DelimiterSettings>("output"));
+        settings.extend(setting_names::<splitter::DelimiterSettings>("input"));
+        settings
+    }
+
+    #[test]
+    fn test_settings_registered() {
+        let mut settings = typed_settings();
+        assert!(settings.contains(&"output.relp_window".to_owned()));
+        source_settings(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("src"),
+            &mut settings,
+        );
+        assert!(settings.len() > 100);
+        for setting in settings {
+            let registered = COMMON_SETTINGS.contains(&setting.as_str())
+                || COMPONENT_SETTINGS
+                    .iter()
+                    .any(|(_, _, settings)| settings.contains(&setting.as_str()));
+            // File names used in tests, not settings
+            assert!(
+                registered || setting == "output.log",
+                "{} must be registered in config_check.rs",
+                setting
+            );
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use super::{
+    default_input_format, default_output_framing, input, output, DEFAULT_INPUT_TYPE,
+    DEFAULT_OUTPUT_FORMAT, DEFAULT_OUTPUT_TYPE, DEFAULT_QUEUE_SIZE,
+};
+
+/// Starter configuration for an input type and an output type, with the settings they require, and their
+/// main optional settings commented out along with their default values.
+///
+/// # Parameters
+/// - `input_type`: Optional. Type of the input. Default is the default input type.
+/// - `output_type`: Optional. Type of the output. Default is the default output type.
+///
+/// # Returns
+/// The configuration, or an error if a type doesn't exist or if its support hasn't been compiled in
+pub fn config_init(input_type: Option<&str>, output_type: Option<&str>) -> Result<String, String> {
+    let input_type = input_type.unwrap_or(DEFAULT_INPUT_TYPE);
+    let output_type = output_type.unwrap_or(DEFAULT_OUTPUT_TYPE);
+    let input_settings = input::sample_config(input_type).ok_or_else(|| {
+        format!(
+            "Unknown input type, or its support hasn't been compiled in: {}",
+            input_type
+        )
+    })?;
+    let output_settings = output::sample_config(output_type).ok_or_else(|| {
+        format!(
+            "Unknown output type, or its support hasn't been compiled in: {}",
+            output_type
+        )
+    })?;
+    Ok(format!(
+        "# Flowgger configuration for the {input_type} input and the {output_type} output\n\
+         \n\
+         [input]\n\
+         type = \"{input_type}\"\n\
+         {input_settings}\
+         # Format of the records: \"rfc5424\", \"rfc3164\", \"ltsv\", \"gelf\", \"csv\", \"logfmt\", \"statsd\",\n\
+         # \"capnp\", \"passthrough\" or \"auto\"\n\
+         format = \"{input_format}\"\n\
+         # Maximum number of records waiting for the output\n\
+         # queuesize = {queue_size}\n\
+         \n\
+         [output]\n\
+         type = \"{output_type}\"\n\
+         {output_settings}\
+         # Format of the records: \"gelf\", \"rfc5424\", \"rfc3164\", \"ltsv\", \"logfmt\", \"capnp\" or \"passthrough\"\n\
+         format = \"{output_format}\"\n\
+         # Framing of the records: \"noop\", \"line\", \"nul\", \"syslen\", \"delimiter\" or \"json-seq\"\n\
+         # framing = \"{output_framing}\"\n",
+        input_format = default_input_format(input_type),
+        queue_size = DEFAULT_QUEUE_SIZE,
+        output_format = DEFAULT_OUTPUT_FORMAT,
+        output_framing = default_output_framing(DEFAULT_OUTPUT_FORMAT, output_type),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::config::Config;
+
+    #[test]
+    fn test_config_init() {
+        let toml = config_init(Some("stdin"), Some("debug")).unwrap();
+        let config = Config::from_string(&toml).unwrap();
+        assert_eq!(config.lookup("input.type").unwrap().as_str(), Some("stdin"));
+        assert_eq!(
+            config.lookup("input.format").unwrap().as_str(),
+            Some("rfc5424")
+        );
+        assert_eq!(
+            config.lookup("output.type").unwrap().as_str(),
+            Some("debug")
+        );
+        assert!(toml.contains("# framing = \"line\"\n"));
+
+        let input_types = [
+            "file",
+            "generator",
+            "redis",
+            "relp",
+            "replay",
+            "statsd",
+            "stdin",
+            "tcp",
+            "tcp_co",
+            "tls",
+            "tls_co",
+            "udp",
+        ];
+        let output_types = [
+            "debug",
+            "blackhole",
+            "kafka",
+            "mqtt",
+            "postgres",
+            "tls",
+            "file",
+            "relp",
+            "sqlite",
+            "unix",
+        ];
+        for input_type in input_types {
+            for output_type in output_types {
+                if let Ok(toml) = config_init(Some(input_type), Some(output_type)) {
+                    assert!(
+                        Config::from_string(&toml).is_ok(),
+                        "Invalid configuration for {} and {}:\n{}",
+                        input_type,
+                        output_type,
+                        toml
+                    );
+                }
+            }
+        }
+
+        assert_eq!(
+            config_init(Some("carrier-pigeon"), None),
+            Err(
+                "Unknown input type, or its support hasn't been compiled in: carrier-pigeon"
+                    .to_owned()
+            )
+        );
+    }
+}
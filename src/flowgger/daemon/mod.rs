@@ -0,0 +1,229 @@
+#[cfg(unix)]
+mod unix;
+#[cfg(windows)]
+pub mod windows_service;
+
+#[cfg(unix)]
+pub use self::unix::{background, on_shutdown, on_signal};
+#[cfg(windows)]
+pub use self::windows_service::on_shutdown;
+
+use crate::flowgger::config::Config;
+use serde::Deserialize;
+use std::fs;
+use std::io::{stderr, Write};
+use std::panic;
+use std::path::Path;
+use std::process::exit;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread::{self, ThreadId};
+
+/// Exit codes, following sysexits.h, so that init scripts can tell why flowgger stopped
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_DATAERR: i32 = 65;
+pub const EXIT_UNAVAILABLE: i32 = 69;
+pub const EXIT_SOFTWARE: i32 = 70;
+pub const EXIT_OSERR: i32 = 71;
+pub const EXIT_CANTCREAT: i32 = 73;
+pub const EXIT_CONFIG: i32 = 78;
+
+static STARTUP: Startup = Startup::new();
+/// Thread that called `exit_on_panic`, that flowgger can't keep running without
+static MAIN_THREAD: OnceLock<ThreadId> = OnceLock::new();
+/// Called before the process exits, once the output stopped
+static EXIT_HOOKS: Mutex<Vec<Box<dyn FnOnce() + Send>>> = Mutex::new(Vec::new());
+
+/// Startup stages: reading the configuration, then binding the inputs, then running once every input
+/// is listening
+struct Startup {
+    /// Exit code of a startup failure at the current stage, 0 once every input is listening
+    exit_code: AtomicI32,
+    pending_inputs: AtomicUsize,
+    /// Called once every input is listening, i.e. to tell the parent process or the service manager
+    ready: Mutex<Option<Box<dyn FnOnce() + Send>>>,
+}
+
+impl Startup {
+    const fn new() -> Startup {
+        Startup {
+            exit_code: AtomicI32::new(EXIT_CONFIG),
+            pending_inputs: AtomicUsize::new(0),
+            ready: Mutex::new(None),
+        }
+    }
+
+    fn exit_code(&self) -> i32 {
+        self.exit_code.load(Ordering::Acquire)
+    }
+
+    /// The configuration has been read, `inputs` inputs are going to bind their sockets
+    fn binding(&self, inputs: usize) {
+        self.pending_inputs.store(inputs, Ordering::Release);
+        self.exit_code.store(EXIT_OSERR, Ordering::Release);
+    }
+
+    /// An input is listening
+    ///
+    /// # Returns
+    /// `true` for the last one, when startup is complete
+    fn listening(&self) -> bool {
+        let pending =
+            self.pending_inputs
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |pending| {
+                    pending.checked_sub(1)
+                });
+        pending == Ok(1)
+    }
+
+    fn running(&self) {
+        self.exit_code.store(0, Ordering::Release);
+        if let Some(ready) = self.ready.lock().unwrap().take() {
+            ready();
+        }
+    }
+}
+
+/// Print an error and exit with the given code
+pub fn fail(code: i32, msg: &str) -> ! {
+    let _ = writeln!(stderr(), "{}", msg);
+    exit(code)
+}
+
+/// Make a panic exit the process, with a code depending on the stage it failed at: `EXIT_CONFIG` while
+/// reading the configuration, `EXIT_OSERR` while binding the inputs, `EXIT_SOFTWARE` once running. The
+/// functions registered with `before_exit` run first, i.e. to remove the pid file.
+///
+/// Once running, a panic in another thread than the calling one only stops the thread it happens in,
+/// unless panics abort the process, as they do in release builds.
+pub fn exit_on_panic() {
+    let _ = MAIN_THREAD.set(thread::current().id());
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        let fatal = cfg!(panic = "abort") || MAIN_THREAD.get() == Some(&thread::current().id());
+        let code = match STARTUP.exit_code() {
+            0 if fatal => EXIT_SOFTWARE,
+            0 => return,
+            code => code,
+        };
+        run_exit_hooks();
+        exit(code);
+    }));
+}
+
+/// Write the process id to a file, for init scripts to find the process. The file is removed before the
+/// process exits.
+pub fn write_pidfile(path: &Path) {
+    if let Err(e) = fs::write(path, format!("{}\n", std::process::id())) {
+        fail(
+            EXIT_CANTCREAT,
+            &format!("Unable to write the pid file [{}]: {}", path.display(), e),
+        );
+    }
+    let path = path.to_owned();
+    before_exit(move || {
+        let _ = fs::remove_file(path);
+    });
+}
+
+/// Run a function once every input is listening
+pub fn on_ready<F>(f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    *STARTUP.ready.lock().unwrap() = Some(Box::new(f));
+}
+
+/// Run a function before the process exits, once the output stopped, i.e. to flush a sink that decoders write to
+pub fn before_exit<F>(f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    EXIT_HOOKS.lock().unwrap().push(Box::new(f));
+}
+
+/// Run the functions registered with `before_exit`
+pub fn run_exit_hooks() {
+    let hooks = std::mem::take(&mut *EXIT_HOOKS.lock().unwrap());
+    for hook in hooks {
+        hook();
+    }
+}
+
+/// Settings of the process, in the `[daemon]` section
+#[derive(Deserialize)]
+pub struct DaemonSettings {
+    user: Option<String>,
+    group: Option<String>,
+}
+
+/// Read the 'daemon.user' and 'daemon.group' settings. Startup is complete, and privileges are dropped,
+/// once `inputs` inputs have called `listening()`.
+///
+/// # Parameters
+/// - 'daemon.user': Optional. Name or id of the user to run as.
+/// - 'daemon.group': Optional. Name or id of the group to run as, the primary group of 'daemon.user'
+///   by default.
+pub fn init(config: &Config, inputs: usize) {
+    #[cfg(unix)]
+    unix::init_privilege_drop(config);
+    #[cfg(not(unix))]
+    {
+        let settings: DaemonSettings = config.settings("daemon");
+        if settings.user.is_some() || settings.group.is_some() {
+            panic!("daemon.user and daemon.group are only supported on Unix");
+        }
+    }
+    STARTUP.binding(inputs);
+}
+
+/// To be called by every input once its socket is bound, or right away if it doesn't listen
+pub fn listening() {
+    if !STARTUP.listening() {
+        return;
+    }
+    #[cfg(unix)]
+    unix::drop_privileges();
+    STARTUP.running();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_startup_stages() {
+        let startup = Startup::new();
+        assert_eq!(startup.exit_code(), EXIT_CONFIG);
+        startup.binding(2);
+        assert_eq!(startup.exit_code(), EXIT_OSERR);
+        let ready = Arc::new(AtomicUsize::new(0));
+        let ready_count = Arc::clone(&ready);
+        *startup.ready.lock().unwrap() = Some(Box::new(move || {
+            ready_count.fetch_add(1, Ordering::Relaxed);
+        }));
+
+        assert!(!startup.listening());
+        assert!(startup.listening());
+        startup.running();
+        assert_eq!(startup.exit_code(), 0);
+        assert_eq!(ready.load(Ordering::Relaxed), 1);
+
+        // Extra calls don't complete the startup again
+        assert!(!startup.listening());
+    }
+
+    #[test]
+    fn test_exit_hooks() {
+        let ran = Arc::new(AtomicUsize::new(0));
+        let hook_ran = Arc::clone(&ran);
+        before_exit(move || {
+            hook_ran.fetch_add(1, Ordering::Relaxed);
+        });
+        run_exit_hooks();
+        run_exit_hooks();
+        assert_eq!(ran.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,222 @@
+use super::{fail, on_ready, run_exit_hooks, DaemonSettings, EXIT_OSERR, EXIT_SOFTWARE};
+use crate::flowgger::config::Config;
+use crate::flowgger::utils::threads;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{stderr, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+/// How often the shutdown thread checks whether the process was asked to stop
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static PRIVILEGE_DROP: OnceLock<PrivilegeDrop> = OnceLock::new();
+/// Set by SIGTERM and SIGINT
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// User and group to switch to once every input is listening, so that privileged ports such as 514
+/// can be bound as root without running as root afterwards
+struct PrivilegeDrop {
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+}
+
+/// Call `handler` whenever the process receives `signal`. The handler runs in signal context: it must
+/// only update atomics.
+pub fn on_signal(signal: libc::c_int, handler: extern "C" fn(libc::c_int)) {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+            panic!("Unable to install the handler of signal {}", signal);
+        }
+    }
+}
+
+extern "C" fn request_shutdown(_signal: libc::c_int) {
+    SHUTDOWN_REQUESTED.store(true, Ordering::Release);
+}
+
+/// Call `shutdown` and the functions registered with `before_exit`, then exit, once the process receives
+/// SIGTERM or SIGINT, i.e. so that the output delivers the records it holds back. Must be called once.
+pub fn on_shutdown<F>(shutdown: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    on_signal(libc::SIGTERM, request_shutdown);
+    on_signal(libc::SIGINT, request_shutdown);
+    threads::spawn("flowgger-shutdown".to_owned(), None, move || {
+        while !SHUTDOWN_REQUESTED.load(Ordering::Acquire) {
+            thread::sleep(SHUTDOWN_POLL_INTERVAL);
+        }
+        shutdown();
+        run_exit_hooks();
+        exit(0);
+    });
+}
+
+/// Detach from the terminal and run in the background, from the root directory. The parent process only
+/// exits once every input is listening, or with the exit code of the daemon if it failed to start.
+/// Errors are written to stderr until then, and discarded afterwards.
+/// This must be called before any thread is spawned.
+pub fn background() {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        fail(EXIT_OSERR, "Unable to create a pipe");
+    }
+    let (mut reader, mut writer) =
+        unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    match unsafe { libc::fork() } {
+        -1 => fail(EXIT_OSERR, "Unable to fork"),
+        0 => {
+            drop(reader);
+            if unsafe { libc::setsid() } == -1 {
+                fail(EXIT_OSERR, "Unable to create a new session");
+            }
+            // Don't keep the directory flowgger was started from in use
+            let root = CString::new("/").unwrap();
+            if unsafe { libc::chdir(root.as_ptr()) } != 0 {
+                fail(EXIT_OSERR, "Unable to change to the root directory");
+            }
+            let null = match File::options().read(true).write(true).open("/dev/null") {
+                Ok(null) => null,
+                Err(_) => fail(EXIT_OSERR, "Unable to open /dev/null"),
+            };
+            unsafe {
+                libc::dup2(null.as_raw_fd(), libc::STDIN_FILENO);
+                libc::dup2(null.as_raw_fd(), libc::STDOUT_FILENO);
+            }
+            on_ready(move || {
+                let _ = writer.write_all(&[1]);
+                // Startup errors went to the terminal, the parent process now exits and leaves it
+                unsafe {
+                    libc::dup2(null.as_raw_fd(), libc::STDERR_FILENO);
+                }
+            });
+        }
+        pid => {
+            drop(writer);
+            if reader.read_exact(&mut [0]).is_ok() {
+                exit(0);
+            }
+            let mut status = 0;
+            if unsafe { libc::waitpid(pid, &mut status, 0) } == pid && libc::WIFEXITED(status) {
+                exit(libc::WEXITSTATUS(status));
+            }
+            exit(EXIT_SOFTWARE);
+        }
+    }
+}
+
+/// Read the 'daemon.user' and 'daemon.group' settings
+pub fn init_privilege_drop(config: &Config) {
+    let settings: DaemonSettings = config.settings("daemon");
+    let user = settings.user.as_deref();
+    let group = settings.group.as_deref();
+    let (uid, user_gid) = match user {
+        None => (None, None),
+        Some(user) => match lookup_user(user) {
+            Some((uid, gid)) => (Some(uid), Some(gid)),
+            None => panic!("Unknown user in daemon.user: {}", user),
+        },
+    };
+    let gid = match group {
+        None => user_gid,
+        Some(group) => match lookup_group(group) {
+            Some(gid) => Some(gid),
+            None => panic!("Unknown group in daemon.group: {}", group),
+        },
+    };
+    if uid.is_none() && gid.is_none() {
+        return;
+    }
+    let privilege_drop = PrivilegeDrop {
+        uid: uid.unwrap_or_else(|| unsafe { libc::getuid() }),
+        gid: gid.unwrap_or_else(|| unsafe { libc::getgid() }),
+    };
+    if PRIVILEGE_DROP.set(privilege_drop).is_err() {
+        panic!("Privileges can only be configured once");
+    }
+}
+
+/// Switch to the configured user and group, if any
+pub fn drop_privileges() {
+    if let Some(privilege_drop) = PRIVILEGE_DROP.get() {
+        privilege_drop.apply();
+    }
+}
+
+impl PrivilegeDrop {
+    fn apply(&self) {
+        unsafe {
+            if libc::getuid() == self.uid && libc::getgid() == self.gid {
+                return;
+            }
+            if libc::setgroups(1, &self.gid) != 0 {
+                panic!("Unable to drop supplementary groups, flowgger must be started as root");
+            }
+            if libc::setgid(self.gid) != 0 {
+                panic!("Unable to switch to group {}", self.gid);
+            }
+            if libc::setuid(self.uid) != 0 {
+                panic!("Unable to switch to user {}", self.uid);
+            }
+        }
+        let _ = writeln!(
+            stderr(),
+            "Running as user {} and group {}",
+            self.uid,
+            self.gid
+        );
+    }
+}
+
+/// Uid and primary gid of a user, given by name or by id
+fn lookup_user(user: &str) -> Option<(libc::uid_t, libc::gid_t)> {
+    let name = CString::new(user).ok()?;
+    let pw = unsafe { libc::getpwnam(name.as_ptr()) };
+    if !pw.is_null() {
+        return unsafe { Some(((*pw).pw_uid, (*pw).pw_gid)) };
+    }
+    let uid = user.parse().ok()?;
+    let pw = unsafe { libc::getpwuid(uid) };
+    if pw.is_null() {
+        return None;
+    }
+    unsafe { Some((uid, (*pw).pw_gid)) }
+}
+
+/// Gid of a group, given by name or by id
+fn lookup_group(group: &str) -> Option<libc::gid_t> {
+    let name = CString::new(group).ok()?;
+    let gr = unsafe { libc::getgrnam(name.as_ptr()) };
+    if !gr.is_null() {
+        return unsafe { Some((*gr).gr_gid) };
+    }
+    group.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_user() {
+        assert_eq!(lookup_user("root"), Some((0, 0)));
+        assert_eq!(lookup_user("0"), Some((0, 0)));
+        assert_eq!(lookup_user("flowgger-no-such-user"), None);
+    }
+
+    #[test]
+    fn test_lookup_group() {
+        assert_eq!(lookup_group("root"), Some(0));
+        assert_eq!(lookup_group("4242"), Some(4242));
+        assert_eq!(lookup_group("flowgger-no-such-group"), None);
+    }
+}
@@ -0,0 +1,298 @@
+use super::{exit_on_panic, fail, on_ready, run_exit_hooks, EXIT_CONFIG, EXIT_OSERR};
+use crate::flowgger::utils::threads;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::iter;
+use std::mem;
+use std::os::windows::io::FromRawHandle;
+use std::path::Path;
+use std::process::exit;
+use std::ptr;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{
+    self, ServiceControlHandlerResult, ServiceStatusHandle,
+};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+use windows_sys::Win32::System::Console::{SetStdHandle, STD_ERROR_HANDLE};
+use windows_sys::Win32::System::EventLog::{
+    RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE, EVENTLOG_WARNING_TYPE,
+};
+use windows_sys::Win32::System::Pipes::CreatePipe;
+use windows_sys::Win32::System::Registry::{
+    RegCloseKey, RegCreateKeyExW, RegDeleteKeyW, RegSetValueExW, HKEY, HKEY_LOCAL_MACHINE,
+    KEY_SET_VALUE, REG_DWORD, REG_EXPAND_SZ, REG_OPTION_NON_VOLATILE,
+};
+
+const SERVICE_NAME: &str = "flowgger";
+const SERVICE_DISPLAY_NAME: &str = "Flowgger";
+const SERVICE_DESCRIPTION: &str = "A fast, simple and lightweight data collector";
+const EVENT_SOURCE_KEY: &str = r"SYSTEM\CurrentControlSet\Services\EventLog\Application\flowgger";
+/// Message file of the .NET framework, that displays the logged strings as they are
+const EVENT_MESSAGE_FILE: &str =
+    r"%SystemRoot%\Microsoft.NET\Framework\v4.0.30319\EventLogMessages.dll";
+const SERVICE_START_WAIT_HINT: u64 = 30;
+const SERVICE_STOP_WAIT_HINT: u64 = 30;
+
+static CONFIG_FILE: OnceLock<String> = OnceLock::new();
+static STATUS_HANDLE: OnceLock<ServiceStatusHandle> = OnceLock::new();
+static SHUTDOWN: Mutex<Option<Box<dyn FnOnce() + Send>>> = Mutex::new(None);
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Register a service starting flowgger at boot with `config_file`, and the event log source it logs to
+pub fn install(config_file: &str) {
+    let config_file = match Path::new(config_file).canonicalize() {
+        Ok(config_file) => config_file,
+        Err(e) => fail(
+            EXIT_CONFIG,
+            &format!("Unable to find the config file [{}]: {}", config_file, e),
+        ),
+    };
+    let executable_path = match std::env::current_exe() {
+        Ok(executable_path) => executable_path,
+        Err(e) => fail(EXIT_OSERR, &format!("Unable to find flowgger.exe: {}", e)),
+    };
+    let service_info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from(SERVICE_DISPLAY_NAME),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path,
+        launch_arguments: vec![
+            OsString::from("--service"),
+            OsString::from("run"),
+            config_file.into_os_string(),
+        ],
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+    let manager_access = ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE;
+    let res = ServiceManager::local_computer(None::<&str>, manager_access).and_then(|manager| {
+        let service = manager.create_service(&service_info, ServiceAccess::CHANGE_CONFIG)?;
+        service.set_description(SERVICE_DESCRIPTION)
+    });
+    if let Err(e) = res {
+        fail(EXIT_OSERR, &format!("Unable to install the service: {}", e));
+    }
+    if let Err(e) = register_event_source() {
+        fail(
+            EXIT_OSERR,
+            &format!("Unable to register the event log source: error {}", e),
+        );
+    }
+    println!("Service [{}] installed", SERVICE_NAME);
+}
+
+/// Remove the service and the event log source
+pub fn uninstall() {
+    let res = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .and_then(|manager| manager.open_service(SERVICE_NAME, ServiceAccess::DELETE))
+        .and_then(|service| service.delete());
+    if let Err(e) = res {
+        fail(
+            EXIT_OSERR,
+            &format!("Unable to uninstall the service: {}", e),
+        );
+    }
+    unsafe { RegDeleteKeyW(HKEY_LOCAL_MACHINE, wide(EVENT_SOURCE_KEY).as_ptr()) };
+    println!("Service [{}] uninstalled", SERVICE_NAME);
+}
+
+/// Run as the service, which is how the service manager starts flowgger once installed
+pub fn run(config_file: &str) {
+    let _ = CONFIG_FILE.set(config_file.to_owned());
+    if let Err(e) = service_dispatcher::start(SERVICE_NAME, ffi_service_main) {
+        fail(
+            EXIT_OSERR,
+            &format!(
+                "Unable to run as a service, it must be started by the service manager: {}",
+                e
+            ),
+        );
+    }
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    log_to_event_log();
+    exit_on_panic();
+    match service_control_handler::register(SERVICE_NAME, handle_control) {
+        Ok(status_handle) => {
+            let _ = STATUS_HANDLE.set(status_handle);
+        }
+        Err(e) => fail(
+            EXIT_OSERR,
+            &format!("Unable to register the service control handler: {}", e),
+        ),
+    }
+    set_status(ServiceState::StartPending, 0);
+    on_ready(|| set_status(ServiceState::Running, 0));
+    let config_file = CONFIG_FILE.get().expect("The config file is missing");
+    crate::flowgger::start(config_file, None);
+    set_status(ServiceState::Stopped, 0);
+}
+
+/// Call `shutdown` and the functions registered with `before_exit`, then exit, once the service manager
+/// stops the service, i.e. so that the output delivers the records it holds back. Must be called once.
+pub fn on_shutdown<F>(shutdown: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    *SHUTDOWN.lock().unwrap() = Some(Box::new(shutdown));
+}
+
+fn handle_control(control: ServiceControl) -> ServiceControlHandlerResult {
+    match control {
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            set_status(ServiceState::StopPending, 0);
+            // The control handler must return quickly, the output may take a while to stop
+            threads::spawn("flowgger-shutdown".to_owned(), None, || {
+                if let Some(shutdown) = SHUTDOWN.lock().unwrap().take() {
+                    shutdown();
+                }
+                run_exit_hooks();
+                set_status(ServiceState::Stopped, 0);
+                exit(0)
+            });
+            ServiceControlHandlerResult::NoError
+        }
+        _ => ServiceControlHandlerResult::NotImplemented,
+    }
+}
+
+fn set_status(state: ServiceState, exit_code: i32) {
+    let status_handle = match STATUS_HANDLE.get() {
+        None => return,
+        Some(status_handle) => status_handle,
+    };
+    let (controls_accepted, wait_hint) = match state {
+        ServiceState::StartPending => (
+            ServiceControlAccept::empty(),
+            Duration::from_secs(SERVICE_START_WAIT_HINT),
+        ),
+        ServiceState::Running => (
+            ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            Duration::default(),
+        ),
+        ServiceState::StopPending => (
+            ServiceControlAccept::empty(),
+            Duration::from_secs(SERVICE_STOP_WAIT_HINT),
+        ),
+        _ => (ServiceControlAccept::empty(), Duration::default()),
+    };
+    let exit_code = match exit_code {
+        0 => ServiceExitCode::Win32(0),
+        code => ServiceExitCode::ServiceSpecific(code as u32),
+    };
+    let _ = status_handle.set_service_status(ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted,
+        exit_code,
+        checkpoint: 0,
+        wait_hint,
+        process_id: None,
+    });
+}
+
+/// Forward everything written to stderr to the event log, one event per line
+fn log_to_event_log() {
+    let source = unsafe { RegisterEventSourceW(ptr::null(), wide(SERVICE_NAME).as_ptr()) };
+    if source == 0 {
+        return;
+    }
+    let (mut read_pipe, mut write_pipe) = (0, 0);
+    unsafe {
+        if CreatePipe(&mut read_pipe, &mut write_pipe, ptr::null(), 0) == 0
+            || SetStdHandle(STD_ERROR_HANDLE, write_pipe) == 0
+        {
+            return;
+        }
+    }
+    let reader = BufReader::new(unsafe { File::from_raw_handle(read_pipe as _) });
+    threads::spawn("flowgger-event-log".to_owned(), None, move || {
+        for line in reader.lines().map_while(Result::ok) {
+            let event_type = if line.contains("panicked") {
+                EVENTLOG_ERROR_TYPE
+            } else {
+                EVENTLOG_WARNING_TYPE
+            };
+            let line = wide(&line);
+            let strings = [line.as_ptr()];
+            unsafe {
+                ReportEventW(
+                    source,
+                    event_type,
+                    0,
+                    0,
+                    ptr::null_mut(),
+                    1,
+                    0,
+                    strings.as_ptr(),
+                    ptr::null(),
+                )
+            };
+        }
+    });
+}
+
+fn register_event_source() -> Result<(), u32> {
+    let mut key: HKEY = 0;
+    let res = unsafe {
+        RegCreateKeyExW(
+            HKEY_LOCAL_MACHINE,
+            wide(EVENT_SOURCE_KEY).as_ptr(),
+            0,
+            ptr::null(),
+            REG_OPTION_NON_VOLATILE,
+            KEY_SET_VALUE,
+            ptr::null(),
+            &mut key,
+            ptr::null_mut(),
+        )
+    };
+    if res != 0 {
+        return Err(res);
+    }
+    let message_file = wide(EVENT_MESSAGE_FILE);
+    let types_supported: u32 = 7;
+    let res = unsafe {
+        match RegSetValueExW(
+            key,
+            wide("EventMessageFile").as_ptr(),
+            0,
+            REG_EXPAND_SZ,
+            message_file.as_ptr() as *const u8,
+            (message_file.len() * mem::size_of::<u16>()) as u32,
+        ) {
+            0 => RegSetValueExW(
+                key,
+                wide("TypesSupported").as_ptr(),
+                0,
+                REG_DWORD,
+                &types_supported as *const u32 as *const u8,
+                mem::size_of::<u32>() as u32,
+            ),
+            res => res,
+        }
+    };
+    unsafe { RegCloseKey(key) };
+    match res {
+        0 => Ok(()),
+        res => Err(res),
+    }
+}
+
+/// Nul-terminated UTF-16 string, for the Windows API
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(iter::once(0)).collect()
+}
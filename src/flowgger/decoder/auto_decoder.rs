@@ -0,0 +1,158 @@
+use super::fallback_decoder::DECODER_KEY;
+use super::Decoder;
+#[cfg(feature = "gelf")]
+use super::GelfDecoder;
+#[cfg(feature = "rfc3164")]
+use super::RFC3164Decoder;
+#[cfg(feature = "rfc5424")]
+use super::RFC5424Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue};
+
+/// Decoder detecting the format of every record, for inputs sharing a single port between devices sending
+/// GELF, RFC5424 and RFC3164 records.
+///
+/// Records starting with `{` are decoded as GELF, records starting with a priority followed by the version
+/// `1` as RFC5424, and any other record as RFC3164. The detected format is stored as the `_decoder`
+/// structured data, as with a list of formats.
+pub struct AutoDecoder {
+    gelf: Option<Box<dyn Decoder + Send>>,
+    rfc5424: Option<Box<dyn Decoder + Send>>,
+    rfc3164: Option<Box<dyn Decoder + Send>>,
+}
+
+impl Clone for AutoDecoder {
+    fn clone(&self) -> AutoDecoder {
+        AutoDecoder {
+            gelf: self.gelf.as_ref().map(|x| x.clone_boxed()),
+            rfc5424: self.rfc5424.as_ref().map(|x| x.clone_boxed()),
+            rfc3164: self.rfc3164.as_ref().map(|x| x.clone_boxed()),
+        }
+    }
+}
+
+impl AutoDecoder {
+    /// The decoders of the formats whose support has been compiled in, with the settings of the input
+    pub fn new(config: &Config) -> AutoDecoder {
+        AutoDecoder {
+            gelf: gelf_decoder(config),
+            rfc5424: rfc5424_decoder(config),
+            rfc3164: rfc3164_decoder(config),
+        }
+    }
+}
+
+impl Decoder for AutoDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        let format = sniff(line);
+        let decoder = match format {
+            "gelf" => &self.gelf,
+            "rfc5424" => &self.rfc5424,
+            _ => &self.rfc3164,
+        };
+        let decoder = decoder
+            .as_ref()
+            .ok_or("Support for the format of the record hasn't been compiled in")?;
+        let mut record = decoder.decode(line)?;
+        record.push_sd_pair(DECODER_KEY, SDValue::String(format.into()));
+        Ok(record)
+    }
+}
+
+/// Format of a record, from its first bytes
+fn sniff(line: &str) -> &'static str {
+    let line = line.trim_start();
+    if line.starts_with('{') {
+        return "gelf";
+    }
+    let version = line
+        .strip_prefix('<')
+        .and_then(|x| x.split_once('>'))
+        .filter(|(pri, _)| {
+            !pri.is_empty() && pri.len() <= 3 && pri.bytes().all(|c| c.is_ascii_digit())
+        })
+        .map(|(_, x)| x);
+    match version {
+        Some(x) if x.starts_with("1 ") => "rfc5424",
+        _ => "rfc3164",
+    }
+}
+
+#[cfg(feature = "gelf")]
+fn gelf_decoder(config: &Config) -> Option<Box<dyn Decoder + Send>> {
+    Some(Box::new(GelfDecoder::new(config)))
+}
+
+#[cfg(not(feature = "gelf"))]
+fn gelf_decoder(_config: &Config) -> Option<Box<dyn Decoder + Send>> {
+    None
+}
+
+#[cfg(feature = "rfc5424")]
+fn rfc5424_decoder(config: &Config) -> Option<Box<dyn Decoder + Send>> {
+    Some(Box::new(RFC5424Decoder::new(config)))
+}
+
+#[cfg(not(feature = "rfc5424"))]
+fn rfc5424_decoder(_config: &Config) -> Option<Box<dyn Decoder + Send>> {
+    None
+}
+
+#[cfg(feature = "rfc3164")]
+fn rfc3164_decoder(config: &Config) -> Option<Box<dyn Decoder + Send>> {
+    Some(Box::new(RFC3164Decoder::new(config)))
+}
+
+#[cfg(not(feature = "rfc3164"))]
+fn rfc3164_decoder(_config: &Config) -> Option<Box<dyn Decoder + Send>> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_decoder_sniff() {
+        assert_eq!(sniff(r#"{"version":"1.1","host":"example.org"}"#), "gelf");
+        assert_eq!(sniff("  {}"), "gelf");
+        assert_eq!(
+            sniff("<23>1 2015-08-05T15:53:45Z example.org app 69 42 - hello"),
+            "rfc5424"
+        );
+        assert_eq!(
+            sniff("<13>Aug  6 11:15:24 example.org app: hello"),
+            "rfc3164"
+        );
+        assert_eq!(sniff("<13>10 Aug 11:15:24 example.org"), "rfc3164");
+        assert_eq!(sniff("<1234>1 2015-08-05T15:53:45Z"), "rfc3164");
+        assert_eq!(sniff("Aug  6 11:15:24 example.org app: hello"), "rfc3164");
+    }
+
+    #[cfg(all(feature = "gelf", feature = "rfc5424", feature = "rfc3164"))]
+    #[test]
+    fn test_auto_decoder() {
+        let decoder = AutoDecoder::new(&Config::from_string("").unwrap()).clone();
+        for (line, format, hostname) in &[
+            (
+                r#"{"version":"1.1","host":"example.org","short_message":"hello","timestamp":1438790025}"#,
+                "gelf",
+                "example.org",
+            ),
+            (
+                "<23>1 2015-08-05T15:53:45Z example.net app 69 42 - hello",
+                "rfc5424",
+                "example.net",
+            ),
+            (
+                "<13>2015 Aug  6 11:15:24 example.com app: hello",
+                "rfc3164",
+                "example.com",
+            ),
+        ] {
+            let record = decoder.decode(line).unwrap();
+            assert_eq!(&record.hostname, hostname);
+            assert_eq!(record.field("decoder").as_deref(), Some(*format));
+        }
+    }
+}
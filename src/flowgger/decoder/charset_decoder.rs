@@ -0,0 +1,98 @@
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::Record;
+use encoding_rs::{Encoding, REPLACEMENT, UTF_16BE, UTF_16LE};
+use serde::Deserialize;
+use std::borrow::Cow;
+
+/// Settings of the charset, in the `[input]` section
+#[derive(Deserialize)]
+pub struct CharsetSettings {
+    charset: Option<String>,
+}
+
+/// Decoder wrapper transcoding the records from the character set of the input to UTF-8.
+/// Invalid sequences are replaced with U+FFFD.
+pub struct CharsetDecoder {
+    decoder: Box<dyn Decoder + Send>,
+    encoding: &'static Encoding,
+}
+
+impl Clone for CharsetDecoder {
+    fn clone(&self) -> CharsetDecoder {
+        CharsetDecoder {
+            decoder: self.decoder.clone_boxed(),
+            encoding: self.encoding,
+        }
+    }
+}
+
+impl CharsetDecoder {
+    /// # Parameters
+    /// - 'input.charset': Optional. Name of the character set of the input, as defined by the WHATWG
+    ///   Encoding Standard, i.e. "latin1", "windows-1252", "shift_jis" or "gb18030". UTF-16 is not supported,
+    ///   as records are split before being transcoded.
+    ///
+    /// # Returns
+    /// The decoder as is if 'input.charset' is not set or is UTF-8, or wrapped so that it transcodes the records
+    pub fn wrap(config: &Config, decoder: Box<dyn Decoder + Send>) -> Box<dyn Decoder + Send> {
+        let settings: CharsetSettings = config.settings("input");
+        let label = match settings.charset {
+            None => return decoder,
+            Some(label) => label,
+        };
+        let encoding = match Encoding::for_label(label.as_bytes()) {
+            None => panic!("Unknown input.charset: {}", label),
+            Some(encoding) if [UTF_16LE, UTF_16BE, REPLACEMENT].contains(&encoding) => {
+                panic!("input.charset {} is not supported", label)
+            }
+            Some(encoding) if encoding == encoding_rs::UTF_8 => return decoder,
+            Some(encoding) => encoding,
+        };
+        Box::new(CharsetDecoder { decoder, encoding })
+    }
+}
+
+impl Decoder for CharsetDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        self.decoder.decode(line)
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        match self.encoding.decode_without_bom_handling(line).0 {
+            Cow::Borrowed(line) => self.decoder.decode(line),
+            Cow::Owned(line) => self.decoder.decode(&line).map(Record::into_owned),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::utils::test_utils::record_test_utils::TestDecoder;
+
+    fn decode(charset: &str, line: &[u8]) -> Option<String> {
+        let config = Config::from_string(&format!("[input]\ncharset = \"{}\"\n", charset)).unwrap();
+        CharsetDecoder::wrap(&config, Box::new(TestDecoder))
+            .decode_bytes(line)
+            .unwrap()
+            .msg
+            .map(Cow::into_owned)
+    }
+
+    #[test]
+    fn test_charset_decoder() {
+        assert_eq!(decode("latin1", b"caf\xe9"), Some("café".to_owned()));
+        assert_eq!(
+            decode("shift_jis", b"\x93\xfa\x96\x7b"),
+            Some("日本".to_owned())
+        );
+        assert_eq!(decode("utf-8", "café".as_bytes()), Some("café".to_owned()));
+    }
+
+    #[test]
+    #[should_panic(expected = "input.charset utf-16 is not supported")]
+    fn test_charset_decoder_utf16() {
+        decode("utf-16", b"");
+    }
+}
@@ -0,0 +1,444 @@
+use super::{Decoder, DROPPED};
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{
+    Facility, Record, SDValue, SDValueType, Severity, StructuredData, Timestamp,
+};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::sync::Arc;
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+use time::{Date, OffsetDateTime, PrimitiveDateTime, Time};
+
+const DEFAULT_CSV_DELIMITER: &str = ",";
+const W3C_FIELDS_DIRECTIVE: &str = "#Fields:";
+
+/// Record field a column is mapped to
+#[derive(Clone, Debug)]
+enum Column {
+    Timestamp,
+    /// W3C date, combined with the time column
+    Date,
+    /// W3C time, combined with the date column
+    Time,
+    Hostname,
+    Appname,
+    Procid,
+    Msgid,
+    Message,
+    Severity,
+    Facility,
+    Ignored,
+    /// Structured data pair, typed according to the schema
+    Pair(String, SDValueType),
+}
+
+/// Settings of the CSV and W3C decoders, in the `[input]` section
+#[derive(Deserialize)]
+pub struct CsvSettings {
+    csv_columns: Option<Vec<String>>,
+    csv_delimiter: Option<String>,
+    csv_schema: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Clone)]
+pub struct CsvDecoder {
+    delimiter: char,
+    w3c: bool,
+    schema: Arc<HashMap<String, SDValueType>>,
+    /// Columns, either configured, or set by the last `#Fields` directive of a W3C log
+    columns: RefCell<Arc<[Column]>>,
+}
+
+impl CsvDecoder {
+    /// Decoder for CSV records, with a configured list of columns
+    ///
+    /// # Parameters
+    /// - 'input.csv_columns': Names of the columns, in order. Columns named "time", "host", "appname",
+    ///   "procid", "msgid", "message", "level" and "facility" are mapped to the corresponding record fields,
+    ///   columns named "-" are ignored, and other columns are stored as structured data.
+    /// - 'input.csv_delimiter': Optional. Column delimiter, "," by default.
+    /// - 'input.csv_schema': Optional. Types of the structured data columns, as with 'input.ltsv_schema'.
+    pub fn new(config: &Config) -> CsvDecoder {
+        let settings: CsvSettings = config.settings("input");
+        let delimiter = settings
+            .csv_delimiter
+            .as_deref()
+            .unwrap_or(DEFAULT_CSV_DELIMITER);
+        let mut delimiter_chars = delimiter.chars();
+        let delimiter = match (delimiter_chars.next(), delimiter_chars.next()) {
+            (Some(delimiter), None) if delimiter != '"' => delimiter,
+            _ => panic!("input.csv_delimiter must be a single character"),
+        };
+        let schema = Arc::new(schema(settings.csv_schema));
+        let names = settings.csv_columns.expect("input.csv_columns is required");
+        let columns = names
+            .iter()
+            .map(|name| column(name, false, &schema))
+            .collect();
+        CsvDecoder {
+            delimiter,
+            w3c: false,
+            schema,
+            columns: RefCell::new(columns),
+        }
+    }
+
+    /// Decoder for W3C extended log files, i.e. from IIS. The columns are set by the `#Fields` directive.
+    /// Directive lines are dropped, not reported as invalid records.
+    ///
+    /// # Parameters
+    /// - 'input.csv_schema': Optional. Types of the structured data columns, as with 'input.ltsv_schema'.
+    pub fn w3c(config: &Config) -> CsvDecoder {
+        let settings: CsvSettings = config.settings("input");
+        CsvDecoder {
+            delimiter: ' ',
+            w3c: true,
+            schema: Arc::new(schema(settings.csv_schema)),
+            columns: RefCell::new(Arc::new([])),
+        }
+    }
+}
+
+fn schema(pairs: Option<BTreeMap<String, String>>) -> HashMap<String, SDValueType> {
+    let pairs = match pairs {
+        None => return HashMap::new(),
+        Some(pairs) => pairs,
+    };
+    pairs
+        .into_iter()
+        .map(|(name, sdtype)| {
+            let sdtype = match sdtype.to_lowercase().as_ref() {
+                "string" => SDValueType::String,
+                "bool" => SDValueType::Bool,
+                "f64" => SDValueType::F64,
+                "i64" => SDValueType::I64,
+                "u64" => SDValueType::U64,
+                _ => panic!("Unsupported type in input.csv_schema for name [{}]", name),
+            };
+            (name, sdtype)
+        })
+        .collect()
+}
+
+fn column(name: &str, w3c: bool, schema: &HashMap<String, SDValueType>) -> Column {
+    match name {
+        "-" | "" => Column::Ignored,
+        "date" if w3c => Column::Date,
+        "time" if w3c => Column::Time,
+        "time" | "timestamp" => Column::Timestamp,
+        "host" | "hostname" => Column::Hostname,
+        "s-computername" if w3c => Column::Hostname,
+        "appname" => Column::Appname,
+        "procid" => Column::Procid,
+        "msgid" => Column::Msgid,
+        "message" | "msg" => Column::Message,
+        "level" | "severity" => Column::Severity,
+        "facility" => Column::Facility,
+        name => Column::Pair(
+            name.to_owned(),
+            schema.get(name).cloned().unwrap_or(SDValueType::String),
+        ),
+    }
+}
+
+impl Decoder for CsvDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        if self.w3c && line.starts_with('#') {
+            if let Some(names) = line.strip_prefix(W3C_FIELDS_DIRECTIVE) {
+                let columns = names
+                    .split_whitespace()
+                    .map(|name| column(name, true, &self.schema))
+                    .collect();
+                *self.columns.borrow_mut() = columns;
+            }
+            return Err(DROPPED);
+        }
+        let columns = Arc::clone(&self.columns.borrow());
+        if columns.is_empty() {
+            return Err("Missing #Fields directive before W3C records");
+        }
+        let values = split_values(line, self.delimiter)?;
+        if values.len() != columns.len() {
+            return Err("Unexpected number of columns");
+        }
+
+        let mut record = Record {
+            ts: Timestamp::default(),
+            utc_offset: None,
+            hostname: Cow::Borrowed("-"),
+            facility: None,
+            severity: None,
+            appname: None,
+            procid: None,
+            msgid: None,
+            msg: None,
+            full_msg: Some(Cow::Borrowed(line)),
+            sd: None,
+        };
+        let mut sd = StructuredData::new(None);
+        let (mut ts, mut date, mut time) = (None, None, None);
+        for (column, value) in columns.iter().zip(values) {
+            // W3C uses "-" for missing values
+            if self.w3c && value == "-" {
+                continue;
+            }
+            match column {
+                Column::Timestamp => ts = Some(parse_ts(&value)?),
+                Column::Date => date = Some(value),
+                Column::Time => time = Some(value),
+                Column::Hostname => record.hostname = value,
+                Column::Appname => record.appname = Some(value),
+                Column::Procid => record.procid = Some(value),
+                Column::Msgid => record.msgid = Some(value),
+                Column::Message => record.msg = Some(value),
+                Column::Severity => {
+                    let severity: u8 = value.parse().or(Err("Invalid severity level"))?;
+                    let severity =
+                        Severity::try_from(severity).or(Err("Severity level should be <= 7"))?;
+                    record.severity = Some(severity);
+                }
+                Column::Facility => {
+                    let facility: u8 = value.parse().or(Err("Invalid facility"))?;
+                    record.facility = Some(Facility::try_from(facility)?)
+                }
+                Column::Ignored => {}
+                Column::Pair(name, sdtype) => {
+                    sd.pairs
+                        .push((format!("_{}", name).into(), sd_value(sdtype, value)?));
+                }
+            }
+        }
+        record.ts = match (ts, date, time) {
+            (Some(ts), _, _) => ts,
+            (None, Some(date), Some(time)) => parse_w3c_ts(&date, &time)?,
+            _ => return Err("Missing timestamp"),
+        };
+        if !sd.pairs.is_empty() {
+            record.sd = Some(vec![sd]);
+        }
+        Ok(record)
+    }
+}
+
+fn sd_value<'a>(sdtype: &SDValueType, value: Cow<'a, str>) -> Result<SDValue<'a>, &'static str> {
+    let value = match sdtype {
+        SDValueType::String => SDValue::String(value),
+        SDValueType::Bool => SDValue::Bool(
+            value
+                .parse::<bool>()
+                .or(Err("Type error; boolean was expected"))?,
+        ),
+        SDValueType::F64 => SDValue::F64(
+            value
+                .parse::<f64>()
+                .or(Err("Type error; f64 was expected"))?,
+        ),
+        SDValueType::I64 => SDValue::I64(
+            value
+                .parse::<i64>()
+                .or(Err("Type error; i64 was expected"))?,
+        ),
+        SDValueType::U64 => SDValue::U64(
+            value
+                .parse::<u64>()
+                .or(Err("Type error; u64 was expected"))?,
+        ),
+    };
+    Ok(value)
+}
+
+/// Split a line into values. Values can be quoted, with quotes inside quoted values escaped by doubling them.
+/// Values are sliced from the line, except the quoted values with escaped quotes.
+fn split_values(line: &str, delimiter: char) -> Result<Vec<Cow<'_, str>>, &'static str> {
+    let mut values = Vec::new();
+    let mut rest = line;
+    loop {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            // Copy of the value, only made once an escaped quote is found
+            let mut unescaped: Option<String> = None;
+            let mut start = 0;
+            let end = loop {
+                let quote = start
+                    + quoted[start..]
+                        .find('"')
+                        .ok_or("Unterminated quoted value")?;
+                if quoted[quote + 1..].starts_with('"') {
+                    unescaped
+                        .get_or_insert_with(String::new)
+                        .push_str(&quoted[start..=quote]);
+                    start = quote + 2;
+                    continue;
+                }
+                break quote;
+            };
+            values.push(match unescaped {
+                None => Cow::Borrowed(&quoted[..end]),
+                Some(mut value) => {
+                    value.push_str(&quoted[start..end]);
+                    Cow::Owned(value)
+                }
+            });
+            let mut after = quoted[end + 1..].chars();
+            match after.next() {
+                None => return Ok(values),
+                Some(c) if c == delimiter => rest = after.as_str(),
+                Some(_) => return Err("Unexpected character after a quoted value"),
+            }
+        } else {
+            match rest.split_once(delimiter) {
+                None => {
+                    values.push(Cow::Borrowed(rest));
+                    return Ok(values);
+                }
+                Some((value, after)) => {
+                    values.push(Cow::Borrowed(value));
+                    rest = after;
+                }
+            }
+        }
+    }
+}
+
+fn parse_ts(value: &str) -> Result<Timestamp, &'static str> {
+    if let Ok(ts) = value.parse() {
+        return Ok(ts);
+    }
+    match OffsetDateTime::parse(value, &Rfc3339) {
+        Ok(date) => Ok(Timestamp::from_offset_datetime(date)),
+        Err(_) => Err("Unable to parse the timestamp"),
+    }
+}
+
+/// W3C dates and times are in UTC
+fn parse_w3c_ts(date: &str, time: &str) -> Result<Timestamp, &'static str> {
+    let date = Date::parse(date, format_description!("[year]-[month]-[day]"))
+        .or(Err("Unable to parse the W3C date"))?;
+    let time = Time::parse(time, format_description!("[hour]:[minute]:[second]"))
+        .or_else(|_| {
+            Time::parse(
+                time,
+                format_description!("[hour]:[minute]:[second].[subsecond]"),
+            )
+        })
+        .or(Err("Unable to parse the W3C time"))?;
+    Ok(Timestamp::from_primitive_datetime(PrimitiveDateTime::new(
+        date, time,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair<'a>(record: &'a Record<'a>, name: &str) -> &'a SDValue<'a> {
+        let sd = &record.sd.as_ref().unwrap()[0];
+        &sd.pairs.iter().find(|(key, _)| key == name).unwrap().1
+    }
+
+    #[test]
+    fn test_split_values() {
+        assert_eq!(
+            split_values("a,b,,c", ',').unwrap(),
+            vec!["a", "b", "", "c"]
+        );
+        assert_eq!(
+            split_values(r#""a,1","say ""hi""",c"#, ',').unwrap(),
+            vec!["a,1", r#"say "hi""#, "c"]
+        );
+        assert_eq!(split_values(r#"a,"b""#, ',').unwrap(), vec!["a", "b"]);
+        assert!(split_values(r#"a,"b"#, ',').is_err());
+        assert!(split_values(r#""a"b,c"#, ',').is_err());
+    }
+
+    #[test]
+    fn test_csv_decoder() {
+        let config = Config::from_string(
+            r#"[input]
+format = "csv"
+csv_columns = ["time", "host", "level", "-", "status", "message"]
+[input.csv_schema]
+status = "u64"
+"#,
+        )
+        .unwrap();
+        let decoder = CsvDecoder::new(&config);
+        let record = decoder
+            .decode(r#"2015-08-05T15:53:45.637824Z,example.org,3,ignored,404,"Not found, really""#)
+            .unwrap();
+        assert_eq!(record.ts.unix_nanos(), 1_438_790_025_637_824_000);
+        assert_eq!(record.hostname, "example.org");
+        assert_eq!(record.severity, Some(Severity::Error));
+        assert_eq!(record.msg.as_deref(), Some("Not found, really"));
+        assert!(matches!(pair(&record, "_status"), SDValue::U64(404)));
+        assert_eq!(record.sd.as_ref().unwrap()[0].pairs.len(), 1);
+
+        assert!(decoder.decode("1438790025,example.org,3").is_err());
+        assert!(decoder
+            .decode("1438790025,example.org,3,-,not a number,msg")
+            .is_err());
+    }
+
+    #[test]
+    fn test_w3c_decoder() {
+        let config = Config::from_string("[input]\nformat = \"w3c\"\n").unwrap();
+        let decoder = CsvDecoder::w3c(&config);
+        assert_eq!(
+            decoder.decode("2023-01-02 03:04:05 GET /").unwrap_err(),
+            "Missing #Fields directive before W3C records"
+        );
+        assert_eq!(
+            decoder
+                .decode("#Software: Microsoft Internet Information Services 10.0")
+                .unwrap_err(),
+            DROPPED
+        );
+        assert_eq!(
+            decoder
+                .decode(
+                    "#Fields: date time s-computername cs-method cs-uri-stem cs(User-Agent) sc-status"
+                )
+                .unwrap_err(),
+            DROPPED
+        );
+
+        let record = decoder
+            .decode("2023-01-02 03:04:05 WEB01 GET /index.html Mozilla/5.0+(Windows) -")
+            .unwrap();
+        assert_eq!(record.ts, Timestamp::from_unix_secs(1_672_628_645));
+        assert_eq!(record.hostname, "WEB01");
+        assert!(matches!(pair(&record, "_cs-method"), SDValue::String(method) if method == "GET"));
+        assert!(
+            matches!(pair(&record, "_cs(User-Agent)"), SDValue::String(ua) if ua == "Mozilla/5.0+(Windows)")
+        );
+        // Missing values are skipped
+        assert!(record.sd.as_ref().unwrap()[0]
+            .pairs
+            .iter()
+            .all(|(key, _)| key != "_sc-status"));
+
+        // Clones keep the fields of the decoder they were cloned from
+        assert!(decoder
+            .clone()
+            .decode("2023-01-02 03:04:05.250 WEB01 GET / - 200")
+            .is_ok());
+
+        // A new #Fields directive replaces the columns, the other directives keep them
+        assert_eq!(
+            decoder.decode("#Date: 2023-01-02 04:00:00").unwrap_err(),
+            DROPPED
+        );
+        assert!(decoder
+            .decode("2023-01-02 04:00:00 WEB01 GET / - 200")
+            .is_ok());
+        assert_eq!(
+            decoder.decode("#Fields: date time c-ip").unwrap_err(),
+            DROPPED
+        );
+        let record = decoder.decode("2023-01-02 04:00:01 192.0.2.1").unwrap();
+        assert!(matches!(pair(&record, "_c-ip"), SDValue::String(ip) if ip == "192.0.2.1"));
+    }
+}
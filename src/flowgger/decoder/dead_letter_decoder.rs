@@ -0,0 +1,379 @@
+use super::{input_source, Decoder, DROPPED};
+use crate::flowgger::config::Config;
+use crate::flowgger::daemon;
+use crate::flowgger::record::Record;
+#[cfg(feature = "kafka-output")]
+use rdkafka::config::ClientConfig;
+#[cfg(feature = "kafka-output")]
+use rdkafka::producer::{BaseRecord, DeliveryResult, Producer, ProducerContext, ThreadedProducer};
+#[cfg(feature = "kafka-output")]
+use rdkafka::ClientContext;
+use serde::Deserialize;
+use std::fmt::Write as FmtWrite;
+use std::fs::{File, OpenOptions};
+use std::io::{stderr, Write};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "kafka-output")]
+use std::time::Duration;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// How long to wait for the records of the Kafka sink to be delivered, before the process exits
+#[cfg(feature = "kafka-output")]
+const DEAD_LETTER_KAFKA_FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Settings of the dead letter sink, in the `[input]` section
+#[derive(Deserialize)]
+pub struct DeadLetterSettings {
+    dead_letter: Option<String>,
+    dead_letter_path: Option<String>,
+    #[cfg_attr(not(feature = "kafka-output"), allow(dead_code))]
+    dead_letter_kafka_brokers: Option<Vec<String>>,
+    #[cfg_attr(not(feature = "kafka-output"), allow(dead_code))]
+    dead_letter_kafka_topic: Option<String>,
+}
+
+/// Brokers of the Kafka output, in the `[output]` section, the default ones of the Kafka sink
+#[cfg(feature = "kafka-output")]
+#[derive(Deserialize)]
+struct KafkaBrokersSettings {
+    kafka_brokers: Option<Vec<String>>,
+}
+
+/// Where the records that could not be decoded are written
+pub enum DeadLetterSink {
+    /// One JSON object per line
+    File(Mutex<File>),
+    /// One JSON object per Kafka record
+    #[cfg(feature = "kafka-output")]
+    Kafka {
+        producer: ThreadedProducer<DeadLetterContext>,
+        topic: String,
+    },
+}
+
+/// Reports the records the brokers didn't accept, that can't be retried from the decoder
+#[cfg(feature = "kafka-output")]
+pub struct DeadLetterContext;
+
+#[cfg(feature = "kafka-output")]
+impl ClientContext for DeadLetterContext {}
+
+#[cfg(feature = "kafka-output")]
+impl ProducerContext for DeadLetterContext {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _opaque: ()) {
+        if let Err((e, _)) = result {
+            let _ = writeln!(stderr(), "Unable to write to the dead letter sink: {}", e);
+        }
+    }
+}
+
+impl DeadLetterSink {
+    /// # Parameters
+    /// - 'input.dead_letter': Optional. "file" or "kafka" (requires the "kafka-output" feature).
+    /// - 'input.dead_letter_path': Path of the file, appended to.
+    /// - 'input.dead_letter_kafka_topic': Kafka topic.
+    /// - 'input.dead_letter_kafka_brokers': Optional. Kafka brokers, 'output.kafka_brokers' by default.
+    ///
+    /// # Returns
+    /// The sink, that is flushed before the process exits, or `None` if 'input.dead_letter' is not set
+    pub fn from_config(config: &Config) -> Option<Arc<DeadLetterSink>> {
+        let sink = Arc::new(DeadLetterSink::new(config)?);
+        let exit_sink = Arc::clone(&sink);
+        daemon::before_exit(move || exit_sink.flush());
+        Some(sink)
+    }
+
+    fn new(config: &Config) -> Option<DeadLetterSink> {
+        let settings: DeadLetterSettings = config.settings("input");
+        match settings.dead_letter.as_deref()? {
+            "file" => {
+                let path = settings
+                    .dead_letter_path
+                    .as_deref()
+                    .expect("input.dead_letter_path is required");
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .unwrap_or_else(|e| panic!("Unable to open the dead letter file: {}", e));
+                Some(DeadLetterSink::File(Mutex::new(file)))
+            }
+            "kafka" => Some(Self::new_kafka(config, settings)),
+            _ => panic!(r#"input.dead_letter must be a string set to "file" or "kafka""#),
+        }
+    }
+
+    #[cfg(feature = "kafka-output")]
+    fn new_kafka(config: &Config, settings: DeadLetterSettings) -> DeadLetterSink {
+        let brokers = settings
+            .dead_letter_kafka_brokers
+            .or_else(|| {
+                let output: KafkaBrokersSettings = config.settings("output");
+                output.kafka_brokers
+            })
+            .expect("input.dead_letter_kafka_brokers is required")
+            .join(",");
+        let topic = settings
+            .dead_letter_kafka_topic
+            .expect("input.dead_letter_kafka_topic is required");
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .create_with_context(DeadLetterContext)
+            .unwrap_or_else(|e| panic!("Unable to create the dead letter Kafka producer: {}", e));
+        DeadLetterSink::Kafka { producer, topic }
+    }
+
+    #[cfg(not(feature = "kafka-output"))]
+    fn new_kafka(_config: &Config, _settings: DeadLetterSettings) -> DeadLetterSink {
+        panic!("Support for Kafka hasn't been compiled in")
+    }
+
+    /// Write an entry with the time, the input `source`, the `error` and the `raw` record
+    pub fn write_entry(&self, source: &str, error: &str, raw: &str) {
+        self.write(&dead_letter_entry(source, error, raw));
+    }
+
+    fn write(&self, entry: &str) {
+        let res = match self {
+            DeadLetterSink::File(file) => {
+                let mut line = String::with_capacity(entry.len() + 1);
+                line.push_str(entry);
+                line.push('\n');
+                file.lock()
+                    .unwrap()
+                    .write_all(line.as_bytes())
+                    .map_err(|e| e.to_string())
+            }
+            #[cfg(feature = "kafka-output")]
+            DeadLetterSink::Kafka { producer, topic } => producer
+                .send(BaseRecord::<(), _>::to(topic).payload(entry))
+                .map_err(|(e, _)| e.to_string()),
+        };
+        if let Err(e) = res {
+            let _ = writeln!(stderr(), "Unable to write to the dead letter sink: {}", e);
+        }
+    }
+
+    /// Wait for the records written to be delivered
+    fn flush(&self) {
+        let res = match self {
+            DeadLetterSink::File(file) => {
+                file.lock().unwrap().sync_data().map_err(|e| e.to_string())
+            }
+            #[cfg(feature = "kafka-output")]
+            DeadLetterSink::Kafka { producer, .. } => producer
+                .flush(DEAD_LETTER_KAFKA_FLUSH_TIMEOUT)
+                .map_err(|e| e.to_string()),
+        };
+        if let Err(e) = res {
+            let _ = writeln!(stderr(), "Unable to flush the dead letter sink: {}", e);
+        }
+    }
+}
+
+/// Decoder wrapper writing the records that could not be decoded to a dead letter sink, along with the input
+/// they were received from, the time and the error, instead of only logging them.
+///
+/// Records are written once every transformation and the schema validation have been applied to them, so that
+/// the records rejected by these are written too. Records dropped on purpose are not. Records that are not valid
+/// UTF-8 are written with the invalid sequences replaced with U+FFFD.
+pub struct DeadLetterDecoder {
+    decoder: Box<dyn Decoder + Send>,
+    sink: Arc<DeadLetterSink>,
+    source: Arc<str>,
+}
+
+impl Clone for DeadLetterDecoder {
+    fn clone(&self) -> DeadLetterDecoder {
+        DeadLetterDecoder {
+            decoder: self.decoder.clone_boxed(),
+            sink: Arc::clone(&self.sink),
+            source: Arc::clone(&self.source),
+        }
+    }
+}
+
+impl DeadLetterDecoder {
+    /// # Returns
+    /// The decoder as is without a `sink`, or wrapped so that failures are written to it
+    pub fn wrap(
+        config: &Config,
+        sink: Option<Arc<DeadLetterSink>>,
+        decoder: Box<dyn Decoder + Send>,
+    ) -> Box<dyn Decoder + Send> {
+        let sink = match sink {
+            None => return decoder,
+            Some(sink) => sink,
+        };
+        Box::new(DeadLetterDecoder {
+            decoder,
+            sink,
+            source: input_source(config).into(),
+        })
+    }
+}
+
+impl Decoder for DeadLetterDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        self.decoder.decode(line).inspect_err(|e| {
+            if *e != DROPPED {
+                self.sink.write_entry(&self.source, e, line);
+            }
+        })
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        self.decoder.decode_bytes(line).inspect_err(|e| {
+            if *e != DROPPED {
+                self.sink
+                    .write_entry(&self.source, e, &String::from_utf8_lossy(line));
+            }
+        })
+    }
+}
+
+fn dead_letter_entry(source: &str, error: &str, raw: &str) -> String {
+    let time = OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_default();
+    let mut entry = String::with_capacity(raw.len() + 128);
+    entry.push('{');
+    for (i, (key, value)) in [
+        ("time", time.as_str()),
+        ("source", source),
+        ("error", error),
+        ("raw", raw),
+    ]
+    .iter()
+    .enumerate()
+    {
+        if i > 0 {
+            entry.push(',');
+        }
+        push_json_string(&mut entry, key);
+        entry.push(':');
+        push_json_string(&mut entry, value);
+    }
+    entry.push('}');
+    entry
+}
+
+fn push_json_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[derive(Clone)]
+    struct FailingDecoder;
+
+    impl Decoder for FailingDecoder {
+        fn decode<'a>(&self, _line: &'a str) -> Result<Record<'a>, &'static str> {
+            Err("Invalid record")
+        }
+    }
+
+    #[test]
+    fn test_dead_letter_file() {
+        let temp_dir = TempDir::new("test_dead_letter").unwrap();
+        let path = temp_dir.path().join("dead_letter.log");
+        let config = Config::from_string(&format!(
+            "[input]\ntype = \"udp\"\nlisten = \"0.0.0.0:514\"\ndead_letter = \"file\"\ndead_letter_path = {:?}\n",
+            path.to_str().unwrap()
+        ))
+        .unwrap();
+        let sink = DeadLetterSink::from_config(&config);
+        let decoder = DeadLetterDecoder::wrap(&config, sink, Box::new(FailingDecoder));
+        assert_eq!(
+            decoder.decode("bad \"record\"\n").unwrap_err(),
+            "Invalid record"
+        );
+        assert!(decoder.clone_boxed().decode("another\tone").is_err());
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(r#"{"time":""#));
+        assert!(lines[0].ends_with(
+            r#","source":"udp 0.0.0.0:514","error":"Invalid record","raw":"bad \"record\"\n"}"#
+        ));
+        assert!(lines[1].ends_with(r#""raw":"another\tone"}"#));
+    }
+
+    #[cfg(feature = "kafka-output")]
+    #[test]
+    fn test_dead_letter_kafka() {
+        use rdkafka::consumer::{BaseConsumer, Consumer};
+        use rdkafka::mocking::MockCluster;
+        use rdkafka::{Message, Offset, TopicPartitionList};
+
+        let cluster = MockCluster::new(1).unwrap();
+        cluster.create_topic("dead_letter", 1, 1).unwrap();
+        let config = Config::from_string(&format!(
+            "[input]\ntype = \"udp\"\nlisten = \"0.0.0.0:514\"\ndead_letter = \"kafka\"\ndead_letter_kafka_brokers = [{:?}]\ndead_letter_kafka_topic = \"dead_letter\"\n",
+            cluster.bootstrap_servers()
+        ))
+        .unwrap();
+        let sink = DeadLetterSink::new(&config).unwrap();
+        sink.write_entry("udp 0.0.0.0:514", "Invalid record", "bad");
+        sink.flush();
+        match sink {
+            DeadLetterSink::Kafka { ref producer, .. } => assert_eq!(producer.in_flight_count(), 0),
+            _ => panic!("The sink must be a Kafka sink"),
+        }
+
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", cluster.bootstrap_servers())
+            .set("group.id", "test_dead_letter_kafka")
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .unwrap();
+        let mut partitions = TopicPartitionList::new();
+        partitions
+            .add_partition_offset("dead_letter", 0, Offset::Beginning)
+            .unwrap();
+        consumer.assign(&partitions).unwrap();
+        let message = consumer
+            .poll(Duration::from_secs(10))
+            .expect("The entry must have been delivered")
+            .unwrap();
+        let entry = std::str::from_utf8(message.payload().unwrap()).unwrap();
+        assert!(entry.ends_with(r#""error":"Invalid record","raw":"bad"}"#));
+    }
+
+    #[test]
+    fn test_dead_letter_disabled() {
+        let config = Config::from_string("[input]\n").unwrap();
+        assert!(DeadLetterSink::from_config(&config).is_none());
+        let decoder = DeadLetterDecoder::wrap(&config, None, Box::new(FailingDecoder));
+        assert!(decoder.decode("bad").is_err());
+    }
+
+    #[test]
+    fn test_push_json_string() {
+        let mut out = String::new();
+        push_json_string(&mut out, "a\"b\\c\u{1}é");
+        assert_eq!(out, r#""a\"b\\c\u0001é""#);
+    }
+}
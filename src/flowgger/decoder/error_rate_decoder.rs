@@ -0,0 +1,373 @@
+use super::{input_source, DeadLetterSink, Decoder, DROPPED};
+use crate::flowgger::config::Config;
+use crate::flowgger::record::Record;
+use crate::flowgger::utils::threads;
+use serde::Deserialize;
+use std::fs::{File, OpenOptions};
+use std::io::{stderr, Write};
+use std::num::NonZeroU64;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+const DEFAULT_DECODE_ERROR_WINDOW: u64 = 60;
+const DEFAULT_DECODE_ERROR_MIN_RECORDS: u64 = 10;
+const DEFAULT_DECODE_ERROR_WARN_SINK: &str = "stderr";
+
+/// Settings of the decoding failure rate monitor, in the `[input]` section
+#[derive(Deserialize)]
+pub struct ErrorRateSettings {
+    decode_error_warn_percent: Option<u64>,
+    decode_error_window: Option<NonZeroU64>,
+    decode_error_min_records: Option<u64>,
+    decode_error_warn_sink: Option<String>,
+    decode_error_warn_path: Option<String>,
+}
+
+/// Number of records an input decoded, or failed to decode
+#[derive(Default)]
+pub struct DecodeStats {
+    decoded: AtomicU64,
+    failed: AtomicU64,
+    warnings: AtomicU64,
+}
+
+impl DecodeStats {
+    /// Number of records successfully decoded
+    pub fn decoded(&self) -> u64 {
+        self.decoded.load(Ordering::Relaxed)
+    }
+
+    /// Number of records that could not be decoded
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Number of times the failure rate went above the warning threshold
+    pub fn warnings(&self) -> u64 {
+        self.warnings.load(Ordering::Relaxed)
+    }
+}
+
+/// Decoder wrapper counting decoding failures, for the monitor to warn when too many records of an input
+/// can't be decoded, i.e. after a device changed its log format.
+pub struct ErrorRateDecoder {
+    decoder: Box<dyn Decoder + Send>,
+    stats: Arc<DecodeStats>,
+}
+
+impl Clone for ErrorRateDecoder {
+    fn clone(&self) -> ErrorRateDecoder {
+        ErrorRateDecoder {
+            decoder: self.decoder.clone_boxed(),
+            stats: Arc::clone(&self.stats),
+        }
+    }
+}
+
+impl ErrorRateDecoder {
+    /// # Returns
+    /// The decoder as is if 'input.decode_error_warn_percent' is not set, or wrapped so that a monitor
+    /// checks its failure rate. Warnings can be written to the `dead_letter` sink.
+    pub fn wrap(
+        config: &Config,
+        dead_letter: Option<Arc<DeadLetterSink>>,
+        decoder: Box<dyn Decoder + Send>,
+    ) -> Box<dyn Decoder + Send> {
+        match ErrorRateMonitor::new(config, dead_letter) {
+            None => decoder,
+            Some(monitor) => {
+                let stats = Arc::new(DecodeStats::default());
+                monitor.start(Arc::clone(&stats));
+                Box::new(ErrorRateDecoder { decoder, stats })
+            }
+        }
+    }
+}
+
+impl ErrorRateDecoder {
+    fn count<'a>(&self, res: Result<Record<'a>, &'static str>) -> Result<Record<'a>, &'static str> {
+        let counter = match res {
+            Ok(_) => &self.stats.decoded,
+            Err(DROPPED) => return res,
+            Err(_) => &self.stats.failed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        res
+    }
+}
+
+impl Decoder for ErrorRateDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        self.count(self.decoder.decode(line))
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        self.count(self.decoder.decode_bytes(line))
+    }
+}
+
+/// Where the warnings of the monitor are written
+enum WarningSink {
+    Stderr,
+    /// One line per warning, after its time
+    File(Mutex<File>),
+    /// One entry per warning, with the warning as the error and no record
+    DeadLetter(Arc<DeadLetterSink>),
+}
+
+impl WarningSink {
+    /// # Parameters
+    /// - 'input.decode_error_warn_sink': Optional. "stderr", "file" or "dead_letter". Default is "stderr".
+    /// - 'input.decode_error_warn_path': Path of the file the warnings are appended to, with "file".
+    fn new(settings: &ErrorRateSettings, dead_letter: Option<Arc<DeadLetterSink>>) -> WarningSink {
+        let sink_type = settings
+            .decode_error_warn_sink
+            .as_deref()
+            .unwrap_or(DEFAULT_DECODE_ERROR_WARN_SINK);
+        match sink_type {
+            "stderr" => WarningSink::Stderr,
+            "file" => {
+                let path = settings
+                    .decode_error_warn_path
+                    .as_deref()
+                    .expect("input.decode_error_warn_path is required");
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .unwrap_or_else(|e| panic!("Unable to open the decoding warnings file: {}", e));
+                WarningSink::File(Mutex::new(file))
+            }
+            "dead_letter" => WarningSink::DeadLetter(dead_letter.expect(
+                r#"input.decode_error_warn_sink = "dead_letter" requires input.dead_letter"#,
+            )),
+            _ => {
+                panic!(r#"input.decode_error_warn_sink must be "stderr", "file" or "dead_letter""#)
+            }
+        }
+    }
+
+    fn write(&self, source: &str, warning: &str) {
+        match self {
+            WarningSink::Stderr => {
+                let _ = writeln!(stderr(), "{}", warning);
+            }
+            WarningSink::File(file) => {
+                let time = OffsetDateTime::now_utc()
+                    .format(&Rfc3339)
+                    .unwrap_or_default();
+                let line = format!("{} {}\n", time, warning);
+                if let Err(e) = file.lock().unwrap().write_all(line.as_bytes()) {
+                    let _ = writeln!(
+                        stderr(),
+                        "Unable to write to the decoding warnings file: {}",
+                        e
+                    );
+                }
+            }
+            WarningSink::DeadLetter(sink) => sink.write_entry(source, warning, ""),
+        }
+    }
+}
+
+/// Checks the decoding failure rate of an input over fixed windows, and warns when it crosses the threshold.
+/// Once the warning has been emitted, it is not repeated until a window with a failure rate under the
+/// threshold.
+struct ErrorRateMonitor {
+    warn_percent: u64,
+    window: Duration,
+    min_records: u64,
+    source: String,
+    sink: WarningSink,
+}
+
+impl ErrorRateMonitor {
+    /// # Parameters
+    /// - 'input.decode_error_warn_percent': Optional. Must be an integer between 1 and 100. The monitor is
+    ///   only enabled when this is set.
+    /// - 'input.decode_error_window': Optional. Length of the windows, in seconds. Default is 60.
+    /// - 'input.decode_error_min_records': Optional. Windows with fewer records are not checked. Default is 10.
+    ///
+    /// See `WarningSink::new()` for where the warnings are written.
+    fn new(config: &Config, dead_letter: Option<Arc<DeadLetterSink>>) -> Option<ErrorRateMonitor> {
+        let settings: ErrorRateSettings = config.settings("input");
+        let warn_percent = settings.decode_error_warn_percent?;
+        if !(1..=100).contains(&warn_percent) {
+            panic!("input.decode_error_warn_percent must be between 1 and 100");
+        }
+        let window = settings
+            .decode_error_window
+            .map_or(DEFAULT_DECODE_ERROR_WINDOW, NonZeroU64::get);
+        let min_records = settings
+            .decode_error_min_records
+            .map_or(DEFAULT_DECODE_ERROR_MIN_RECORDS, |min_records| {
+                min_records.max(1)
+            });
+        Some(ErrorRateMonitor {
+            warn_percent,
+            window: Duration::from_secs(window),
+            min_records,
+            source: input_source(config),
+            sink: WarningSink::new(&settings, dead_letter),
+        })
+    }
+
+    fn start(self, stats: Arc<DecodeStats>) {
+        threads::spawn("flowgger-decode-monitor".to_owned(), None, move || {
+            let mut above = false;
+            let mut last = (0, 0);
+            loop {
+                thread::sleep(self.window);
+                let current = (stats.decoded(), stats.failed());
+                let (decoded, failed) = (current.0 - last.0, current.1 - last.1);
+                last = current;
+                above = self.check(&stats, decoded, failed, above);
+            }
+        });
+    }
+
+    /// Check the records of a window, and return whether the failure rate is above the threshold
+    fn check(&self, stats: &DecodeStats, decoded: u64, failed: u64, above: bool) -> bool {
+        let total = decoded + failed;
+        if total < self.min_records {
+            return above;
+        }
+        let percent = failed * 100 / total;
+        if !above && percent >= self.warn_percent {
+            stats.warnings.fetch_add(1, Ordering::Relaxed);
+            let warning = format!(
+                "WARNING: {}% of the records received by [{}] could not be decoded ({}/{} records in {}s)",
+                percent,
+                self.source,
+                failed,
+                total,
+                self.window.as_secs()
+            );
+            self.sink.write(&self.source, &warning);
+            true
+        } else if above && percent < self.warn_percent {
+            let warning = format!(
+                "Decoding failures of [{}] are back to {}% ({}/{} records in {}s, warnings: {})",
+                self.source,
+                percent,
+                failed,
+                total,
+                self.window.as_secs(),
+                stats.warnings()
+            );
+            self.sink.write(&self.source, &warning);
+            false
+        } else {
+            above
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::utils::test_utils::record_test_utils::TestDecoder;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_error_rate_disabled() {
+        let config = Config::from_string("[input]\n").unwrap();
+        assert!(ErrorRateMonitor::new(&config, None).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "input.decode_error_warn_percent must be between 1 and 100")]
+    fn test_error_rate_invalid_percent() {
+        let config = Config::from_string("[input]\ndecode_error_warn_percent = 0\n").unwrap();
+        let _ = ErrorRateMonitor::new(&config, None);
+    }
+
+    #[test]
+    fn test_error_rate_counts() {
+        let stats = Arc::new(DecodeStats::default());
+        let decoder = ErrorRateDecoder {
+            decoder: Box::new(TestDecoder),
+            stats: Arc::clone(&stats),
+        };
+        for line in &["ok", "invalid", "ok"] {
+            let _ = decoder.clone().decode(line);
+        }
+        assert_eq!(stats.decoded(), 2);
+        assert_eq!(stats.failed(), 1);
+    }
+
+    #[test]
+    fn test_error_rate_threshold() {
+        let config = Config::from_string(
+            "[input]\ntype = \"udp\"\nlisten = \"0.0.0.0:514\"\ndecode_error_warn_percent = 20\n",
+        )
+        .unwrap();
+        let monitor = ErrorRateMonitor::new(&config, None).unwrap();
+        assert_eq!(monitor.source, "udp 0.0.0.0:514");
+        let stats = DecodeStats::default();
+
+        // Too few records to be checked
+        assert!(!monitor.check(&stats, 0, 5, false));
+        assert!(!monitor.check(&stats, 90, 10, false));
+        assert!(monitor.check(&stats, 80, 20, false));
+        assert!(monitor.check(&stats, 50, 50, true));
+        assert_eq!(stats.warnings(), 1);
+        assert!(!monitor.check(&stats, 90, 9, true));
+    }
+
+    #[test]
+    fn test_error_rate_warn_file() {
+        let temp_dir = TempDir::new("test_error_rate_warn_file").unwrap();
+        let path = temp_dir.path().join("decode_errors.log");
+        let config = Config::from_string(&format!(
+            "[input]\ntype = \"udp\"\nlisten = \"0.0.0.0:514\"\ndecode_error_warn_percent = 20\ndecode_error_warn_sink = \"file\"\ndecode_error_warn_path = {:?}\n",
+            path.to_str().unwrap()
+        ))
+        .unwrap();
+        let monitor = ErrorRateMonitor::new(&config, None).unwrap();
+        let stats = DecodeStats::default();
+        assert!(monitor.check(&stats, 50, 50, false));
+        assert!(!monitor.check(&stats, 100, 0, true));
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(
+            " WARNING: 50% of the records received by [udp 0.0.0.0:514] could not be decoded (50/100 records in 60s)"
+        ));
+        assert!(lines[1].contains(" Decoding failures of [udp 0.0.0.0:514] are back to 0%"));
+    }
+
+    #[test]
+    fn test_error_rate_warn_dead_letter() {
+        let temp_dir = TempDir::new("test_error_rate_warn_dead_letter").unwrap();
+        let path = temp_dir.path().join("dead_letter.log");
+        let config = Config::from_string(&format!(
+            "[input]\ntype = \"udp\"\nlisten = \"0.0.0.0:514\"\ndecode_error_warn_percent = 20\ndecode_error_warn_sink = \"dead_letter\"\ndead_letter = \"file\"\ndead_letter_path = {:?}\n",
+            path.to_str().unwrap()
+        ))
+        .unwrap();
+        let monitor = ErrorRateMonitor::new(&config, DeadLetterSink::from_config(&config)).unwrap();
+        assert!(monitor.check(&DecodeStats::default(), 50, 50, false));
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.contains(
+            r#""source":"udp 0.0.0.0:514","error":"WARNING: 50% of the records received by [udp 0.0.0.0:514] could not be decoded (50/100 records in 60s)","raw":""}"#
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "requires input.dead_letter")]
+    fn test_error_rate_warn_dead_letter_missing() {
+        let config = Config::from_string(
+            "[input]\ndecode_error_warn_percent = 20\ndecode_error_warn_sink = \"dead_letter\"\n",
+        )
+        .unwrap();
+        let _ = ErrorRateMonitor::new(&config, None);
+    }
+}
@@ -0,0 +1,88 @@
+use super::Decoder;
+use crate::flowgger::record::{Record, SDValue};
+
+pub const DECODER_KEY: &str = "_decoder";
+
+/// Decoder trying several formats in order, for inputs receiving records in different formats.
+/// The first decoder that succeeds is used, and its format is stored as the `_decoder` structured data.
+pub struct FallbackDecoder {
+    decoders: Vec<(String, Box<dyn Decoder + Send>)>,
+}
+
+impl Clone for FallbackDecoder {
+    fn clone(&self) -> FallbackDecoder {
+        FallbackDecoder {
+            decoders: self
+                .decoders
+                .iter()
+                .map(|(format, decoder)| (format.clone(), decoder.clone_boxed()))
+                .collect(),
+        }
+    }
+}
+
+impl FallbackDecoder {
+    /// # Parameters
+    /// - 'decoders': The decoders to try, in order, along with the name of their format
+    pub fn new(decoders: Vec<(String, Box<dyn Decoder + Send>)>) -> FallbackDecoder {
+        assert!(!decoders.is_empty(), "input.format cannot be an empty list");
+        FallbackDecoder { decoders }
+    }
+}
+
+impl Decoder for FallbackDecoder {
+    /// # Returns
+    /// The record decoded by the first decoder that succeeded, or the error of the first decoder
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        let mut first_error = None;
+        for (format, decoder) in &self.decoders {
+            match decoder.decode(line) {
+                Ok(mut record) => {
+                    record.push_sd_pair(DECODER_KEY, SDValue::String(format.clone().into()));
+                    return Ok(record);
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        Err(first_error.unwrap_or("No decoder"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::utils::test_utils::record_test_utils::TestDecoder;
+
+    /// Decoder of the lines starting with a prefix, as the test decoder without it
+    #[derive(Clone)]
+    struct PrefixDecoder(&'static str);
+
+    impl Decoder for PrefixDecoder {
+        fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+            TestDecoder.decode(line.strip_prefix(self.0).ok_or(self.0)?)
+        }
+    }
+
+    fn decoder() -> FallbackDecoder {
+        FallbackDecoder::new(vec![
+            ("a".to_owned(), Box::new(PrefixDecoder("a:"))),
+            ("b".to_owned(), Box::new(PrefixDecoder("b:"))),
+        ])
+    }
+
+    #[test]
+    fn test_fallback_decoder() {
+        for (line, format) in &[("a:msg", "a"), ("b:msg", "b")] {
+            let record = decoder().clone().decode(line).unwrap();
+            assert_eq!(record.msg.as_deref(), Some("msg"));
+            assert_eq!(record.field("decoder").as_deref(), Some(*format));
+        }
+    }
+
+    #[test]
+    fn test_fallback_decoder_no_match() {
+        assert_eq!(decoder().decode("c:msg").unwrap_err(), "a:");
+    }
+}
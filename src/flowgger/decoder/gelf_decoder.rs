@@ -1,20 +1,105 @@
 use super::Decoder;
 use crate::flowgger::config::Config;
-use crate::flowgger::record::{Record, SDValue, StructuredData, SEVERITY_MAX};
-use crate::flowgger::utils;
+use crate::flowgger::record::{
+    Record, SDValue, Severity, StructuredData, Timestamp, GELF_DEFAULT_SD_ID,
+};
+use serde::Deserialize;
 use serde_json::de;
 use serde_json::error::Error::Syntax;
 use serde_json::error::ErrorCode;
+use serde_json::ser;
 use serde_json::value::Value;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+
+/// Settings of the GELF decoder, in the `[input]` section
+#[derive(Deserialize)]
+pub struct GelfSettings {
+    gelf_sd_id: Option<String>,
+    gelf_nested: Option<String>,
+}
+
+/// What to do with the additional fields whose values are objects or arrays
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GelfNested {
+    Reject,
+    Flatten,
+    Json,
+}
 
 #[derive(Clone)]
-pub struct GelfDecoder;
+pub struct GelfDecoder {
+    sd_id: Option<String>,
+    nested: GelfNested,
+}
 
 impl GelfDecoder {
-    /// The GELF decoder doesn't support any configuration, the config is passed as an argument
-    /// just to respect the interface https://docs.graylog.org/en/3.1/pages/gelf.html
-    pub fn new(_config: &Config) -> GelfDecoder {
-        GelfDecoder
+    /// GELF decoder constructor https://docs.graylog.org/en/3.1/pages/gelf.html
+    ///
+    /// # Parameters
+    /// - 'input.gelf_sd_id': Optional. SD-ID of the structured data the additional fields are stored in,
+    ///   "gelf@32473" by default. The GELF encoder writes the fields of this SD-ID back as additional fields,
+    ///   so that records converted to RFC5424 and back keep them. An empty string leaves the SD-ID unset.
+    /// - 'input.gelf_nested': Optional. What to do with additional fields whose values are objects or arrays,
+    ///   that GELF doesn't allow but some libraries send. "reject" (default) rejects the record, "flatten" stores
+    ///   every nested value as a field of its own, named after its path (`_parent_child`, `_list_0`), and
+    ///   "json" stores the nested value serialized as a JSON string.
+    pub fn new(config: &Config) -> GelfDecoder {
+        let settings: GelfSettings = config.settings("input");
+        let sd_id = settings
+            .gelf_sd_id
+            .unwrap_or_else(|| GELF_DEFAULT_SD_ID.to_owned());
+        let nested = match settings.gelf_nested.as_deref().unwrap_or("reject") {
+            "reject" => GelfNested::Reject,
+            "flatten" => GelfNested::Flatten,
+            "json" => GelfNested::Json,
+            _ => panic!(r#"input.gelf_nested must be "reject", "flatten" or "json""#),
+        };
+        GelfDecoder {
+            nested,
+            sd_id: if sd_id.is_empty() { None } else { Some(sd_id) },
+        }
+    }
+}
+
+impl GelfDecoder {
+    /// Store an additional field, flattening or serializing it if it is an object or an array
+    fn push_pair(
+        &self,
+        sd: &mut StructuredData<'_>,
+        name: String,
+        value: &Value,
+    ) -> Result<(), &'static str> {
+        let sd_value: SDValue = match *value {
+            Value::String(ref value) => SDValue::String(value.to_owned().into()),
+            Value::Bool(value) => SDValue::Bool(value),
+            Value::F64(value) => SDValue::F64(value),
+            Value::I64(value) => SDValue::I64(value),
+            Value::U64(value) => SDValue::U64(value),
+            Value::Null => SDValue::Null,
+            Value::Object(ref obj) if self.nested == GelfNested::Flatten => {
+                for (key, value) in obj {
+                    self.push_pair(sd, format!("{}_{}", name, key), value)?;
+                }
+                return Ok(());
+            }
+            Value::Array(ref values) if self.nested == GelfNested::Flatten => {
+                for (i, value) in values.iter().enumerate() {
+                    self.push_pair(sd, format!("{}_{}", name, i), value)?;
+                }
+                return Ok(());
+            }
+            Value::Object(_) | Value::Array(_) if self.nested == GelfNested::Json => {
+                SDValue::String(
+                    ser::to_string(value)
+                        .or(Err("Unable to serialize a nested value"))?
+                        .into(),
+                )
+            }
+            _ => return Err("Invalid value type in structured data"),
+        };
+        sd.pairs.push((name.into(), sd_value));
+        Ok(())
     }
 }
 
@@ -30,9 +115,10 @@ impl Decoder for GelfDecoder {
     ///
     /// - `Ok`: A record containing all the line parsed as a Record data struct
     /// - `Err`: if there was any error parsing the line, that could be missing values, bad json or wrong
-    /// types associated with specific fields
-    fn decode(&self, line: &str) -> Result<Record, &'static str> {
+    ///   types associated with specific fields
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
         let mut sd = StructuredData::new(None);
+        sd.sd_id = self.sd_id.clone().map(Cow::Owned);
         let mut ts = None;
         let mut hostname = None;
         let mut msg = None;
@@ -50,7 +136,10 @@ impl Decoder for GelfDecoder {
         let obj = obj.as_object().ok_or("Empty GELF input")?;
         for (key, value) in obj {
             match key.as_ref() {
-                "timestamp" => ts = Some(value.as_f64().ok_or("Invalid GELF timestamp")?),
+                "timestamp" => {
+                    let ts_f64 = value.as_f64().ok_or("Invalid GELF timestamp")?;
+                    ts = Some(Timestamp::from_secs_f64(ts_f64))
+                }
                 "host" => {
                     hostname = Some(
                         value
@@ -81,33 +170,26 @@ impl Decoder for GelfDecoder {
                 },
                 "level" => {
                     let severity_given = value.as_u64().ok_or("Invalid severity level")?;
-                    if severity_given > u64::from(SEVERITY_MAX) {
-                        return Err("Invalid severity level (too high)");
-                    }
-                    severity = Some(severity_given as u8)
+                    let severity_given = u8::try_from(severity_given)
+                        .ok()
+                        .and_then(|severity| Severity::try_from(severity).ok())
+                        .ok_or("Invalid severity level (too high)")?;
+                    severity = Some(severity_given)
                 }
                 name => {
-                    let sd_value: SDValue = match *value {
-                        Value::String(ref value) => SDValue::String(value.to_owned()),
-                        Value::Bool(value) => SDValue::Bool(value),
-                        Value::F64(value) => SDValue::F64(value),
-                        Value::I64(value) => SDValue::I64(value),
-                        Value::U64(value) => SDValue::U64(value),
-                        Value::Null => SDValue::Null,
-                        _ => return Err("Invalid value type in structured data"),
-                    };
                     let name = if name.starts_with('_') {
                         name.to_owned()
                     } else {
                         format!("_{}", name)
                     };
-                    sd.pairs.push((name, sd_value));
+                    self.push_pair(&mut sd, name, value)?;
                 }
             }
         }
         let record = Record {
-            ts: ts.unwrap_or_else(|| utils::PreciseTimestamp::now().as_f64()),
-            hostname: hostname.ok_or("Missing hostname")?,
+            ts: ts.unwrap_or_else(Timestamp::now),
+            utc_offset: None,
+            hostname: Cow::Owned(hostname.ok_or("Missing hostname")?),
             facility: None,
             severity,
             appname: None,
@@ -118,8 +200,8 @@ impl Decoder for GelfDecoder {
             } else {
                 Some(vec![sd])
             },
-            msg,
-            full_msg,
+            msg: msg.map(Cow::Owned),
+            full_msg: full_msg.map(Cow::Owned),
         };
         Ok(record)
     }
@@ -128,79 +210,104 @@ impl Decoder for GelfDecoder {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::flowgger::record::SEVERITY_MAX;
+
+    fn decoder() -> GelfDecoder {
+        GelfDecoder::new(&Config::from_string("").unwrap())
+    }
 
     #[test]
     fn test_gelf_decoder() {
         let msg = r#"{"version":"1.1", "host": "example.org","short_message": "A short message that helps you identify what is going on", "full_message": "Backtrace here\n\nmore stuff", "timestamp": 1385053862.3072, "level": 1, "_user_id": 9001, "_some_info": "foo", "_some_env_var": "bar"}"#;
-        let res = GelfDecoder.decode(msg).unwrap();
-        assert!(res.ts == 1_385_053_862.307_2);
+        let res = decoder().decode(msg).unwrap();
+        assert_eq!(res.ts.unix_nanos(), 1_385_053_862_307_200_000);
         assert!(res.hostname == "example.org");
         assert!(res.msg.unwrap() == "A short message that helps you identify what is going on");
         assert!(res.full_msg.unwrap() == "Backtrace here\n\nmore stuff");
-        assert!(res.severity.unwrap() == 1);
+        assert!(res.severity.unwrap() == Severity::Alert);
 
         let sd = &res.sd.unwrap();
         assert!(sd.len() == 1);
+        assert_eq!(sd[0].sd_id.as_deref(), Some(GELF_DEFAULT_SD_ID));
         let pairs = &sd[0].pairs;
-        assert!(pairs
-            .iter()
-            .cloned()
-            .any(|(k, v)| if let SDValue::U64(v) = v {
-                k == "_user_id" && v == 9001
-            } else {
-                false
-            }));
-        assert!(pairs
-            .iter()
-            .cloned()
-            .any(|(k, v)| if let SDValue::String(v) = v {
-                k == "_some_info" && v == "foo"
-            } else {
-                false
-            }));
-        assert!(pairs
-            .iter()
-            .cloned()
-            .any(|(k, v)| if let SDValue::String(v) = v {
-                k == "_some_env_var" && v == "bar"
-            } else {
-                false
-            }));
+        assert!(pairs.iter().any(|(k, v)| if let SDValue::U64(v) = v {
+            k == "_user_id" && *v == 9001
+        } else {
+            false
+        }));
+        assert!(pairs.iter().any(|(k, v)| if let SDValue::String(v) = v {
+            k == "_some_info" && *v == "foo"
+        } else {
+            false
+        }));
+        assert!(pairs.iter().any(|(k, v)| if let SDValue::String(v) = v {
+            k == "_some_env_var" && *v == "bar"
+        } else {
+            false
+        }));
+    }
+
+    #[test]
+    fn test_gelf_decoder_sd_id() {
+        let msg = r#"{"host": "example.org", "_some_info": "foo"}"#;
+        let config = Config::from_string("[input]\ngelf_sd_id = \"\"\n").unwrap();
+        let res = GelfDecoder::new(&config).decode(msg).unwrap();
+        assert!(res.sd.unwrap()[0].sd_id.is_none());
+    }
+
+    #[test]
+    fn test_gelf_decoder_nested() {
+        let msg = r#"{"version":"1.1", "host": "example.org", "short_message": "A short message", "_http": {"status": 200, "headers": {"host": "a"}}, "_tags": ["x", true]}"#;
+        assert!(decoder().decode(msg).is_err());
+
+        let config = Config::from_string("[input]\ngelf_nested = \"flatten\"\n").unwrap();
+        let res = GelfDecoder::new(&config).decode(msg).unwrap();
+        let sd = &res.sd.unwrap()[0];
+        assert_eq!(
+            sd.to_string(),
+            r#"[gelf@32473 http_headers_host="a" http_status="200" tags_0="x" tags_1="true"]"#
+        );
+
+        let config = Config::from_string("[input]\ngelf_nested = \"json\"\n").unwrap();
+        let res = GelfDecoder::new(&config).decode(msg).unwrap();
+        assert_eq!(
+            res.field("http").as_deref(),
+            Some(r#"{"headers":{"host":"a"},"status":200}"#)
+        );
+        assert_eq!(res.field("tags").as_deref(), Some(r#"["x",true]"#));
     }
 
     #[test]
     #[should_panic(expected = "Invalid value type in structured data")]
     fn test_gelf_decoder_bad_key() {
         let msg = r#"{"some_key": []}"#;
-        let _res = GelfDecoder.decode(&msg).unwrap();
+        let _res = decoder().decode(msg).unwrap();
     }
 
     #[test]
     #[should_panic(expected = "Invalid GELF timestamp")]
     fn test_gelf_decoder_bad_timestamp() {
         let msg = r#"{"timestamp": "a string not a timestamp", "host": "anhostname"}"#;
-        let _res = GelfDecoder.decode(&msg).unwrap();
+        let _res = decoder().decode(msg).unwrap();
     }
 
     #[test]
     #[should_panic(expected = "Invalid GELF input, unable to parse as a JSON object")]
     fn test_gelf_decoder_invalid_input() {
-        let _res = GelfDecoder.decode("{some_key = \"some_value\"}").unwrap();
+        let _res = decoder().decode("{some_key = \"some_value\"}").unwrap();
     }
 
     #[test]
     #[should_panic(expected = "Unsupported GELF version")]
     fn test_gelf_decoder_wrong_version() {
         let msg = r#"{"version":"42"}"#;
-        let _res = GelfDecoder.decode(msg).unwrap();
+        let _res = decoder().decode(msg).unwrap();
     }
 
     #[test]
     #[should_panic(expected = "Invalid severity level (too high)")]
     fn test_gelf_decoder_severity_to_high() {
-        let _res = GelfDecoder
-            .decode(format!("{{\"level\": {}}}", SEVERITY_MAX + 1).as_str())
+        let _res = decoder()
+            .decode(format!("{{\"level\": {}}}", Severity::Debug as u8 + 1).as_str())
             .unwrap();
     }
 }
@@ -12,7 +12,7 @@ impl InvalidDecoder {
 }
 
 impl Decoder for InvalidDecoder {
-    fn decode(&self, _line: &str) -> Result<Record, &'static str> {
+    fn decode<'a>(&self, _line: &'a str) -> Result<Record<'a>, &'static str> {
         panic!("Unsupported input format for this input type");
     }
 }
@@ -0,0 +1,138 @@
+use super::{Decoder, DROPPED};
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, Timestamp};
+use serde::Deserialize;
+use std::fmt::Write;
+use std::str;
+
+/// What to do with records that are not valid UTF-8
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum InvalidUtf8 {
+    /// Replace the invalid sequences with U+FFFD
+    Lossy,
+    /// Decode the record as ISO-8859-1
+    Latin1,
+    /// Don't decode the record, but forward it as is, hex-encoded in `full_msg`
+    Hex,
+    /// Drop the record without logging it
+    Drop,
+}
+
+/// Settings of the invalid UTF-8 records, in the `[input]` section
+#[derive(Deserialize)]
+pub struct InvalidUtf8Settings {
+    invalid_utf8: Option<String>,
+}
+
+/// Decoder wrapper converting the records that are not valid UTF-8, that are otherwise rejected
+pub struct InvalidUtf8Decoder {
+    decoder: Box<dyn Decoder + Send>,
+    policy: InvalidUtf8,
+}
+
+impl Clone for InvalidUtf8Decoder {
+    fn clone(&self) -> InvalidUtf8Decoder {
+        InvalidUtf8Decoder {
+            decoder: self.decoder.clone_boxed(),
+            policy: self.policy,
+        }
+    }
+}
+
+impl InvalidUtf8Decoder {
+    /// # Parameters
+    /// - 'input.invalid_utf8': Optional. "reject" (default), "lossy", "latin1", "hex" or "drop".
+    ///
+    /// # Returns
+    /// The decoder as is if invalid records are rejected, or wrapped so that it converts them
+    pub fn wrap(config: &Config, decoder: Box<dyn Decoder + Send>) -> Box<dyn Decoder + Send> {
+        let settings: InvalidUtf8Settings = config.settings("input");
+        let policy = match settings.invalid_utf8.as_deref().unwrap_or("reject") {
+            "reject" => return decoder,
+            "lossy" => InvalidUtf8::Lossy,
+            "latin1" | "iso-8859-1" => InvalidUtf8::Latin1,
+            "hex" => InvalidUtf8::Hex,
+            "drop" => InvalidUtf8::Drop,
+            _ => {
+                panic!(r#"input.invalid_utf8 must be "reject", "lossy", "latin1", "hex" or "drop""#)
+            }
+        };
+        Box::new(InvalidUtf8Decoder { decoder, policy })
+    }
+}
+
+impl Decoder for InvalidUtf8Decoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        self.decoder.decode(line)
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        if let Ok(line) = str::from_utf8(line) {
+            return self.decoder.decode(line);
+        }
+        match self.policy {
+            InvalidUtf8::Lossy => self
+                .decoder
+                .decode(&String::from_utf8_lossy(line))
+                .map(Record::into_owned),
+            InvalidUtf8::Latin1 => {
+                let line: String = line.iter().map(|&c| char::from(c)).collect();
+                self.decoder.decode(&line).map(Record::into_owned)
+            }
+            InvalidUtf8::Hex => Ok(hex_record(line)),
+            InvalidUtf8::Drop => Err(DROPPED),
+        }
+    }
+}
+
+/// Record carrying raw bytes, with a readable version of them as the message
+fn hex_record(line: &[u8]) -> Record<'static> {
+    let mut hex = String::with_capacity(line.len() * 2);
+    for c in line {
+        let _ = write!(hex, "{:02x}", c);
+    }
+    Record {
+        ts: Timestamp::now(),
+        utc_offset: None,
+        hostname: "unknown".into(),
+        facility: None,
+        severity: None,
+        appname: None,
+        procid: None,
+        msgid: None,
+        msg: Some(String::from_utf8_lossy(line).trim_end().to_owned().into()),
+        full_msg: Some(hex.into()),
+        sd: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::utils::test_utils::record_test_utils::TestDecoder;
+
+    fn decode<'a>(policy: &str, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        let config =
+            Config::from_string(&format!("[input]\ninvalid_utf8 = \"{}\"\n", policy)).unwrap();
+        InvalidUtf8Decoder::wrap(&config, Box::new(TestDecoder)).decode_bytes(line)
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let line = b"caf\xe9";
+        assert_eq!(decode("reject", line).unwrap_err(), "Invalid UTF-8 input");
+        assert_eq!(
+            decode("lossy", line).unwrap().msg.as_deref(),
+            Some("caf\u{fffd}")
+        );
+        assert_eq!(decode("latin1", line).unwrap().msg.as_deref(), Some("café"));
+        let record = decode("hex", line).unwrap();
+        assert_eq!(record.hostname, "unknown");
+        assert_eq!(record.full_msg.as_deref(), Some("636166e9"));
+        assert_eq!(decode("drop", line).unwrap_err(), DROPPED);
+        assert_eq!(
+            decode("hex", b"caf\xc3\xa9").unwrap().msg.as_deref(),
+            Some("café")
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue, Severity, StructuredData, Timestamp};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::iter::Peekable;
+use std::str::CharIndices;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Decoder for logfmt records, i.e. `time=2015-08-05T15:53:45Z level=info msg="hello world" user=42`,
+/// as written by many Go services and Heroku-style platforms.
+///
+/// The "time"/"ts", "host"/"hostname", "level"/"lvl", "msg"/"message", "app"/"appname", "pid"/"procid" and
+/// "msgid" keys are mapped to the record fields, and other keys are stored as structured data. Keys without
+/// a value are flags, stored as `true`. Records without a timestamp are stamped with the time they are
+/// decoded at.
+#[derive(Clone)]
+pub struct LogfmtDecoder;
+
+impl LogfmtDecoder {
+    pub fn new(_config: &Config) -> LogfmtDecoder {
+        LogfmtDecoder
+    }
+}
+
+impl Decoder for LogfmtDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        let mut record = Record {
+            ts: Timestamp::default(),
+            utc_offset: None,
+            hostname: Cow::Borrowed("-"),
+            facility: None,
+            severity: None,
+            appname: None,
+            procid: None,
+            msgid: None,
+            msg: None,
+            full_msg: Some(Cow::Borrowed(line)),
+            sd: None,
+        };
+        let mut sd = StructuredData::new(None);
+        let mut ts = None;
+        let pairs = parse_pairs(line)?;
+        if pairs.is_empty() {
+            return Err("Empty logfmt record");
+        }
+        for (key, value) in pairs {
+            let value = match value {
+                None => {
+                    sd.pairs
+                        .push((format!("_{}", key).into(), SDValue::Bool(true)));
+                    continue;
+                }
+                Some(value) => value,
+            };
+            match key {
+                "time" | "ts" => ts = Some(parse_ts(&value)?),
+                "host" | "hostname" => record.hostname = value,
+                "level" | "lvl" => record.severity = Some(parse_severity(&value)?),
+                "msg" | "message" => record.msg = Some(value),
+                "app" | "appname" => record.appname = Some(value),
+                "pid" | "procid" => record.procid = Some(value),
+                "msgid" => record.msgid = Some(value),
+                _ => sd
+                    .pairs
+                    .push((format!("_{}", key).into(), SDValue::String(value))),
+            }
+        }
+        record.ts = ts.unwrap_or_else(Timestamp::now);
+        if !sd.pairs.is_empty() {
+            record.sd = Some(vec![sd]);
+        }
+        Ok(record)
+    }
+}
+
+/// Split a logfmt line into keys and optional values. Values can be quoted, with `\"`, `\\`, `\n`, `\r`
+/// and `\t` escapes. Keys and values are sliced from the line, except the values with escapes.
+#[allow(clippy::type_complexity)]
+fn parse_pairs(line: &str) -> Result<Vec<(&str, Option<Cow<'_, str>>)>, &'static str> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let mut pairs = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    // Offset of the next character, or the end of the line
+    let offset = |chars: &mut Peekable<CharIndices>| chars.peek().map_or(line.len(), |&(i, _)| i);
+    loop {
+        while chars.next_if(|&(_, c)| c == ' ' || c == '\t').is_some() {}
+        if chars.peek().is_none() {
+            return Ok(pairs);
+        }
+        let start = offset(&mut chars);
+        while chars
+            .next_if(|&(_, c)| c > ' ' && c != '=' && c != '"')
+            .is_some()
+        {}
+        let key = &line[start..offset(&mut chars)];
+        if key.is_empty() {
+            return Err("Invalid logfmt key");
+        }
+        if chars.next_if(|&(_, c)| c == '=').is_none() {
+            pairs.push((key, None));
+            continue;
+        }
+        let value = if chars.next_if(|&(_, c)| c == '"').is_some() {
+            let start = offset(&mut chars);
+            // Copy of the value, only made once an escape sequence is found
+            let mut unescaped: Option<String> = None;
+            loop {
+                match chars.next() {
+                    None => return Err("Unterminated quoted value"),
+                    Some((end, '"')) => {
+                        break unescaped.map_or(Cow::Borrowed(&line[start..end]), Cow::Owned)
+                    }
+                    Some((i, '\\')) => {
+                        let value = unescaped.get_or_insert_with(|| line[start..i].to_owned());
+                        match chars.next() {
+                            Some((_, 'n')) => value.push('\n'),
+                            Some((_, 'r')) => value.push('\r'),
+                            Some((_, 't')) => value.push('\t'),
+                            Some((_, c @ ('"' | '\\'))) => value.push(c),
+                            _ => return Err("Invalid escape sequence in a quoted value"),
+                        }
+                    }
+                    Some((_, c)) => {
+                        if let Some(value) = unescaped.as_mut() {
+                            value.push(c);
+                        }
+                    }
+                }
+            }
+        } else {
+            let start = offset(&mut chars);
+            while chars.next_if(|&(_, c)| c != ' ' && c != '\t').is_some() {}
+            Cow::Borrowed(&line[start..offset(&mut chars)])
+        };
+        pairs.push((key, Some(value)));
+    }
+}
+
+fn parse_ts(value: &str) -> Result<Timestamp, &'static str> {
+    if let Ok(ts) = value.parse() {
+        return Ok(ts);
+    }
+    match OffsetDateTime::parse(value, &Rfc3339) {
+        Ok(date) => Ok(Timestamp::from_offset_datetime(date)),
+        Err(_) => Err("Unable to parse the timestamp"),
+    }
+}
+
+/// Severity from a syslog keyword, a common alias used by logging libraries, or a number
+fn parse_severity(value: &str) -> Result<Severity, &'static str> {
+    let value = value.to_ascii_lowercase();
+    if let Some(severity) = Severity::from_name(&value) {
+        return Ok(severity);
+    }
+    match value.as_str() {
+        "emergency" | "panic" => Ok(Severity::Emergency),
+        "critical" | "fatal" => Ok(Severity::Critical),
+        "err" => Ok(Severity::Error),
+        "warn" => Ok(Severity::Warning),
+        "information" | "informational" => Ok(Severity::Informational),
+        "trace" => Ok(Severity::Debug),
+        _ => value
+            .parse::<u8>()
+            .or(Err("Invalid severity level"))
+            .and_then(Severity::try_from),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logfmt_decoder() {
+        let config = Config::from_string("[input]\nformat = \"logfmt\"\n").unwrap();
+        let decoder = LogfmtDecoder::new(&config);
+        let line = r#"time=2015-08-05T15:53:45.637824Z level=warn msg="disk \"/\" is almost full" host=example.org used=93% dry_run"#;
+        let record = decoder.decode(line).unwrap();
+        assert_eq!(record.ts.unix_nanos(), 1_438_790_025_637_824_000);
+        assert_eq!(record.severity, Some(Severity::Warning));
+        assert_eq!(record.msg.as_deref(), Some(r#"disk "/" is almost full"#));
+        assert_eq!(record.hostname, "example.org");
+        assert_eq!(record.full_msg.as_deref(), Some(line));
+        let pairs = &record.sd.as_ref().unwrap()[0].pairs;
+        assert_eq!(pairs.len(), 2);
+        assert!(
+            matches!(&pairs[0], (key, SDValue::String(value)) if key == "_used" && value == "93%")
+        );
+        assert!(matches!(&pairs[1], (key, SDValue::Bool(true)) if key == "_dry_run"));
+    }
+
+    #[test]
+    fn test_logfmt_decoder_heroku() {
+        let config = Config::from_string("[input]\nformat = \"logfmt\"\n").unwrap();
+        let decoder = LogfmtDecoder::new(&config);
+        let record = decoder
+            .decode("at=info method=GET path=\"/\" status=200 bytes= \n")
+            .unwrap();
+        assert!(record.ts > Timestamp::default());
+        assert_eq!(record.hostname, "-");
+        let pairs = &record.sd.as_ref().unwrap()[0].pairs;
+        assert_eq!(pairs.len(), 5);
+        assert!(
+            matches!(&pairs[4], (key, SDValue::String(value)) if key == "_bytes" && value.is_empty())
+        );
+    }
+
+    #[test]
+    fn test_logfmt_decoder_invalid() {
+        let config = Config::from_string("[input]\nformat = \"logfmt\"\n").unwrap();
+        let decoder = LogfmtDecoder::new(&config);
+        assert!(decoder.decode("").is_err());
+        assert!(decoder.decode(r#"msg="unterminated"#).is_err());
+        assert!(decoder.decode("=value").is_err());
+        assert!(decoder.decode("level=verbose").is_err());
+        assert!(decoder.decode("time=yesterday").is_err());
+        assert_eq!(parse_severity("ERROR"), Ok(Severity::Error));
+        assert_eq!(parse_severity("5"), Ok(Severity::Notice));
+    }
+}
@@ -1,10 +1,24 @@
 use super::Decoder;
 use crate::flowgger::config::Config;
-use crate::flowgger::record::{Record, SDValue, SDValueType, StructuredData};
+use crate::flowgger::record::{Record, SDValue, SDValueType, Severity, StructuredData, Timestamp};
 use crate::flowgger::utils;
-use std::collections::HashMap;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
 use time::format_description::well_known::Rfc3339;
-use time::{format_description, OffsetDateTime};
+use time::format_description::{self, FormatItem, OwnedFormatItem};
+use time::macros::format_description;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+const ENGLISH_TIME_FORMAT: &[FormatItem<'_>] = format_description!(
+    "[day padding:none]/[month repr:short]/[year]:[hour]:[minute]:[second] \
+     [offset_hour sign:mandatory][offset_minute]"
+);
+const ENGLISH_TIME_FORMAT_SUBSECOND: &[FormatItem<'_>] = format_description!(
+    "[day padding:none]/[month repr:short]/[year]:[hour]:[minute]:[second].[subsecond] \
+     [offset_hour sign:mandatory][offset_minute]"
+);
 
 #[derive(Clone)]
 struct Suffixes {
@@ -14,28 +28,59 @@ struct Suffixes {
     s_u64: Option<String>,
 }
 
+/// Labels of the LTSV fields mapped to the record header
+#[derive(Clone)]
+struct Labels {
+    time: String,
+    host: String,
+    message: String,
+    level: String,
+}
+
+/// Settings of the LTSV decoder, in the `[input]` section
+#[derive(Deserialize)]
+pub struct LTSVSettings {
+    ltsv_schema: Option<BTreeMap<String, String>>,
+    ltsv_suffixes: Option<BTreeMap<String, String>>,
+    ltsv_time_label: Option<String>,
+    ltsv_host_label: Option<String>,
+    ltsv_message_label: Option<String>,
+    ltsv_level_label: Option<String>,
+    ltsv_time_formats: Option<Vec<String>>,
+    ltsv_missing_host: Option<String>,
+}
+
 #[derive(Clone)]
 pub struct LTSVDecoder {
     schema: Option<HashMap<String, SDValueType>>,
     suffixes: Suffixes,
+    labels: Labels,
+    time_formats: Vec<OwnedFormatItem>,
+    missing_host: Option<String>,
 }
 
 impl LTSVDecoder {
+    /// # Parameters
+    /// - 'input.ltsv_schema':        Optional. Types of the fields, by label: "string", "bool", "f64", "i64" or
+    ///   "u64". Fields are strings by default.
+    /// - 'input.ltsv_suffixes':      Optional. Suffixes added to the names of the typed fields, by type.
+    /// - 'input.ltsv_time_label':    Optional. Label of the timestamp. Default is "time".
+    /// - 'input.ltsv_host_label':    Optional. Label of the hostname. Default is "host".
+    /// - 'input.ltsv_message_label': Optional. Label of the message. Default is "message".
+    /// - 'input.ltsv_level_label':   Optional. Label of the severity. Default is "level".
+    /// - 'input.ltsv_time_formats':  Optional. Formats of the timestamps, tried in order before the UNIX,
+    ///   RFC3339 and English formats. See https://docs.rs/time/0.3.7/time/format_description/index.html,
+    ///   timestamps without an offset are in UTC.
+    /// - 'input.ltsv_missing_host':  Optional. What to do with the records without a hostname: "reject"
+    ///   (default), "local" to use the name of the host flowgger runs on, or "unknown".
     pub fn new(config: &Config) -> LTSVDecoder {
-        let schema = match config.lookup("input.ltsv_schema") {
+        let settings: LTSVSettings = config.settings("input");
+        let schema = match settings.ltsv_schema {
             None => None,
             Some(pairs) => {
                 let mut schema = HashMap::new();
-                for (name, sdtype) in pairs
-                    .as_table()
-                    .expect("input.ltsv_schema must be a list of key/type pairs")
-                {
-                    let sdtype = match sdtype
-                        .as_str()
-                        .expect("input.ltsv_schema types must be strings")
-                        .to_lowercase()
-                        .as_ref()
-                    {
+                for (name, sdtype) in pairs {
+                    let sdtype = match sdtype.to_lowercase().as_ref() {
                         "string" => SDValueType::String,
                         "bool" => SDValueType::Bool,
                         "f64" => SDValueType::F64,
@@ -43,7 +88,7 @@ impl LTSVDecoder {
                         "u64" => SDValueType::U64,
                         _ => panic!("Unsupported type in input.ltsv_schema for name [{}]", name),
                     };
-                    schema.insert(name.to_owned(), sdtype);
+                    schema.insert(name, sdtype);
                 }
                 Some(schema)
             }
@@ -54,17 +99,10 @@ impl LTSVDecoder {
             s_i64: None,
             s_u64: None,
         };
-        match config.lookup("input.ltsv_suffixes") {
+        match settings.ltsv_suffixes {
             None => {}
             Some(pairs) => {
-                for (sdtype, suffix) in pairs
-                    .as_table()
-                    .expect("input.ltsv_suffixes must be a list of type/suffixes pairs")
-                {
-                    let suffix = suffix
-                        .as_str()
-                        .expect("input.ltsv_suffixes suffixes must be strings")
-                        .to_owned();
+                for (sdtype, suffix) in pairs {
                     match sdtype.to_lowercase().as_ref() {
                         "string" => panic!("Strings cannot be suffixed"),
                         "bool" => suffixes.s_bool = Some(suffix),
@@ -79,12 +117,57 @@ impl LTSVDecoder {
                 }
             }
         };
-        LTSVDecoder { schema, suffixes }
+        let label =
+            |label: Option<String>, default: &str| label.unwrap_or_else(|| default.to_owned());
+        let labels = Labels {
+            time: label(settings.ltsv_time_label, "time"),
+            host: label(settings.ltsv_host_label, "host"),
+            message: label(settings.ltsv_message_label, "message"),
+            level: label(settings.ltsv_level_label, "level"),
+        };
+        let time_formats = settings.ltsv_time_formats.map_or(Vec::new(), |formats| {
+            formats
+                .iter()
+                .map(|format| {
+                    format_description::parse_owned::<2>(format).unwrap_or_else(|e| {
+                        panic!(
+                            "Invalid time format in input.ltsv_time_formats [{}]: {}",
+                            format, e
+                        )
+                    })
+                })
+                .collect()
+        });
+        let missing_host = match settings.ltsv_missing_host.as_deref().unwrap_or("reject") {
+            "reject" => None,
+            "local" => Some(utils::local_hostname().unwrap_or_else(|| "unknown".to_owned())),
+            "unknown" => Some("unknown".to_owned()),
+            _ => panic!(r#"input.ltsv_missing_host must be "reject", "local" or "unknown""#),
+        };
+        LTSVDecoder {
+            schema,
+            suffixes,
+            labels,
+            time_formats,
+            missing_host,
+        }
+    }
+
+    fn parse_ts(&self, ts: &str) -> Result<Timestamp, &'static str> {
+        for format in &self.time_formats {
+            if let Ok(date) = OffsetDateTime::parse(ts, format) {
+                return Ok(Timestamp::from_offset_datetime(date));
+            }
+            if let Ok(date) = PrimitiveDateTime::parse(ts, format) {
+                return Ok(Timestamp::from_primitive_datetime(date));
+            }
+        }
+        parse_ts(ts)
     }
 }
 
 impl Decoder for LTSVDecoder {
-    fn decode(&self, line: &str) -> Result<Record, &'static str> {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
         let mut sd = StructuredData::new(None);
         let mut ts = None;
         let mut hostname = None;
@@ -101,101 +184,99 @@ impl Decoder for LTSVDecoder {
                 (None, Some(value)) => println!("Missing name for value '{}'", value),
                 (Some(name), Some(value)) => {
                     match name {
-                        "time" => {
+                        _ if name == self.labels.time => {
                             let ts_s = if value.starts_with('[') && value.ends_with(']') {
                                 &value[1..(value.len() - 1)]
                             } else {
                                 value
                             };
-                            ts = Some(parse_ts(ts_s)?);
+                            ts = Some(self.parse_ts(ts_s)?);
                         }
-                        "host" => hostname = Some(value.to_owned()),
-                        "message" => msg = Some(value.to_owned()),
-                        "level" => {
+                        _ if name == self.labels.host => hostname = Some(Cow::Borrowed(value)),
+                        _ if name == self.labels.message => msg = Some(Cow::Borrowed(value)),
+                        _ if name == self.labels.level => {
                             let severity_given: u8 =
                                 value.parse().or(Err("Invalid severity level"))?;
-                            if severity_given > 7 {
-                                return Err("Severity level should be <= 7");
-                            }
+                            let severity_given = Severity::try_from(severity_given)
+                                .or(Err("Severity level should be <= 7"))?;
                             severity = Some(severity_given);
                         }
                         name => {
-                            let (final_name, value): (String, SDValue) = if let Some(ref schema) =
-                                self.schema
-                            {
-                                match schema.get(name) {
-                                    None | Some(&SDValueType::String) => {
-                                        (format!("_{}", name), SDValue::String(value.to_owned()))
-                                    }
-                                    Some(&SDValueType::Bool) => {
-                                        let final_name = match self.suffixes.s_bool {
-                                            Some(ref suffix) if !name.ends_with(suffix) => {
-                                                format!("_{}{}", name, suffix)
-                                            }
-                                            _ => format!("_{}", name),
-                                        };
-                                        (
-                                            final_name,
-                                            SDValue::Bool(
-                                                value
-                                                    .parse::<bool>()
-                                                    .or(Err("Type error; boolean was expected"))?,
-                                            ),
-                                        )
-                                    }
-                                    Some(&SDValueType::F64) => {
-                                        let final_name = match self.suffixes.s_f64 {
-                                            Some(ref suffix) if !name.ends_with(suffix) => {
-                                                format!("_{}{}", name, suffix)
-                                            }
-                                            _ => format!("_{}", name),
-                                        };
-                                        (
-                                            final_name,
-                                            SDValue::F64(
-                                                value
-                                                    .parse::<f64>()
-                                                    .or(Err("Type error; f64 was expected"))?,
-                                            ),
-                                        )
-                                    }
-                                    Some(&SDValueType::I64) => {
-                                        let final_name = match self.suffixes.s_i64 {
-                                            Some(ref suffix) if !name.ends_with(suffix) => {
-                                                format!("_{}{}", name, suffix)
-                                            }
-                                            _ => format!("_{}", name),
-                                        };
-                                        (
-                                            final_name,
-                                            SDValue::I64(
-                                                value
-                                                    .parse::<i64>()
-                                                    .or(Err("Type error; i64 was expected"))?,
-                                            ),
-                                        )
+                            let (final_name, value): (String, SDValue<'_>) =
+                                if let Some(ref schema) = self.schema {
+                                    match schema.get(name) {
+                                        None | Some(&SDValueType::String) => {
+                                            (format!("_{}", name), SDValue::String(value.into()))
+                                        }
+                                        Some(&SDValueType::Bool) => {
+                                            let final_name = match self.suffixes.s_bool {
+                                                Some(ref suffix) if !name.ends_with(suffix) => {
+                                                    format!("_{}{}", name, suffix)
+                                                }
+                                                _ => format!("_{}", name),
+                                            };
+                                            (
+                                                final_name,
+                                                SDValue::Bool(
+                                                    value.parse::<bool>().or(Err(
+                                                        "Type error; boolean was expected",
+                                                    ))?,
+                                                ),
+                                            )
+                                        }
+                                        Some(&SDValueType::F64) => {
+                                            let final_name = match self.suffixes.s_f64 {
+                                                Some(ref suffix) if !name.ends_with(suffix) => {
+                                                    format!("_{}{}", name, suffix)
+                                                }
+                                                _ => format!("_{}", name),
+                                            };
+                                            (
+                                                final_name,
+                                                SDValue::F64(
+                                                    value
+                                                        .parse::<f64>()
+                                                        .or(Err("Type error; f64 was expected"))?,
+                                                ),
+                                            )
+                                        }
+                                        Some(&SDValueType::I64) => {
+                                            let final_name = match self.suffixes.s_i64 {
+                                                Some(ref suffix) if !name.ends_with(suffix) => {
+                                                    format!("_{}{}", name, suffix)
+                                                }
+                                                _ => format!("_{}", name),
+                                            };
+                                            (
+                                                final_name,
+                                                SDValue::I64(
+                                                    value
+                                                        .parse::<i64>()
+                                                        .or(Err("Type error; i64 was expected"))?,
+                                                ),
+                                            )
+                                        }
+                                        Some(&SDValueType::U64) => {
+                                            let final_name = match self.suffixes.s_u64 {
+                                                Some(ref suffix) if !name.ends_with(suffix) => {
+                                                    format!("_{}{}", name, suffix)
+                                                }
+                                                _ => format!("_{}", name),
+                                            };
+                                            (
+                                                final_name,
+                                                SDValue::U64(
+                                                    value
+                                                        .parse::<u64>()
+                                                        .or(Err("Type error; u64 was expected"))?,
+                                                ),
+                                            )
+                                        }
                                     }
-                                    Some(&SDValueType::U64) => {
-                                        let final_name = match self.suffixes.s_u64 {
-                                            Some(ref suffix) if !name.ends_with(suffix) => {
-                                                format!("_{}{}", name, suffix)
-                                            }
-                                            _ => format!("_{}", name),
-                                        };
-                                        (
-                                            final_name,
-                                            SDValue::U64(
-                                                value
-                                                    .parse::<u64>()
-                                                    .or(Err("Type error; u64 was expected"))?,
-                                            ),
-                                        )
-                                    }
-                                }
-                            } else {
-                                (format!("_{}", name), SDValue::String(value.to_owned()))
-                            };
-                            sd.pairs.push((final_name, value));
+                                } else {
+                                    (format!("_{}", name), SDValue::String(value.into()))
+                                };
+                            sd.pairs.push((final_name.into(), value));
                         }
                     };
                 }
@@ -203,7 +284,10 @@ impl Decoder for LTSVDecoder {
         }
         let record = Record {
             ts: ts.ok_or("Missing timestamp")?,
-            hostname: hostname.ok_or("Missing hostname")?,
+            utc_offset: None,
+            hostname: hostname
+                .or_else(|| self.missing_host.clone().map(Cow::Owned))
+                .ok_or("Missing hostname")?,
             facility: None,
             severity,
             appname: None,
@@ -215,20 +299,20 @@ impl Decoder for LTSVDecoder {
                 Some(vec![sd])
             },
             msg,
-            full_msg: Some(line.to_owned()),
+            full_msg: Some(Cow::Borrowed(line)),
         };
         Ok(record)
     }
 }
 
-fn rfc3339_to_unix(rfc3339: &str) -> Result<f64, &'static str> {
+fn rfc3339_to_unix(rfc3339: &str) -> Result<Timestamp, &'static str> {
     match OffsetDateTime::parse(rfc3339, &Rfc3339) {
-        Ok(date) => Ok(utils::PreciseTimestamp::from_offset_datetime(date).as_f64()),
+        Ok(date) => Ok(Timestamp::from_offset_datetime(date)),
         Err(_) => Err("Unable to parse the date from RFC3339 to Unix in LTSV decoder"),
     }
 }
 
-fn english_time_to_unix(et: &str) -> Result<f64, &'static str> {
+fn english_time_to_unix(et: &str) -> Result<Timestamp, &'static str> {
     english_time_to_unix_with_subsecond(et, false)
         .or_else(|_| english_time_to_unix_with_subsecond(et, true))
 }
@@ -236,31 +320,26 @@ fn english_time_to_unix(et: &str) -> Result<f64, &'static str> {
 fn english_time_to_unix_with_subsecond(
     et: &str,
     with_subsecond: bool,
-) -> Result<f64, &'static str> {
-    let mut format_str =
-        "[day padding:none]/[month repr:short]/[year]:[hour]:[minute]:[second].[subsecond] \
-        [offset_hour sign:mandatory][offset_minute]"
-            .to_string();
-
-    if !with_subsecond {
-        format_str = format_str.replace(".[subsecond]", "");
-    }
-
-    let format_item = format_description::parse(&format_str).unwrap();
-    match OffsetDateTime::parse(&et, &format_item) {
-        Ok(date) => Ok(utils::PreciseTimestamp::from_offset_datetime(date).as_f64()),
+) -> Result<Timestamp, &'static str> {
+    let format_item = if with_subsecond {
+        ENGLISH_TIME_FORMAT_SUBSECOND
+    } else {
+        ENGLISH_TIME_FORMAT
+    };
+    match OffsetDateTime::parse(et, format_item) {
+        Ok(date) => Ok(Timestamp::from_offset_datetime(date)),
         Err(_) => Err("Unable to parse the English to Unix timestamp in LTSV decoder"),
     }
 }
 
-fn unix_strtime_to_unix(et: &str) -> Result<f64, &'static str> {
-    match et.parse::<f64>() {
+fn unix_strtime_to_unix(et: &str) -> Result<Timestamp, &'static str> {
+    match et.parse() {
         Ok(ts) => Ok(ts),
         Err(_) => Err("Unable to parse the date from Unix strtime to Unix in LTSV decoder"),
     }
 }
 
-fn parse_ts(line: &str) -> Result<f64, &'static str> {
+fn parse_ts(line: &str) -> Result<Timestamp, &'static str> {
     unix_strtime_to_unix(line)
         .or_else(|_| rfc3339_to_unix(line))
         .or_else(|_| english_time_to_unix(line))
@@ -281,38 +360,26 @@ fn test_ltsv_suffixes() {
     let res = ltsv_decoder.decode(msg).unwrap();
     let sd = &res.sd.unwrap()[0];
     let pairs = &sd.pairs;
-    assert!(pairs
-        .iter()
-        .cloned()
-        .any(|(k, v)| if let SDValue::U64(v) = v {
-            k == "_counter_u64" && v == 42
-        } else {
-            false
-        }));
-    assert!(pairs
-        .iter()
-        .cloned()
-        .any(|(k, v)| if let SDValue::I64(v) = v {
-            k == "_score_i64" && v == -1
-        } else {
-            false
-        }));
-    assert!(pairs
-        .iter()
-        .cloned()
-        .any(|(k, v)| if let SDValue::F64(v) = v {
-            k == "_mean_f64" && f64::abs(v - 0.42) < 1e-5
-        } else {
-            false
-        }));
-    assert!(pairs
-        .iter()
-        .cloned()
-        .any(|(k, v)| if let SDValue::Bool(v) = v {
-            k == "_done_bool" && v
-        } else {
-            false
-        }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::U64(v) = v {
+        k == "_counter_u64" && *v == 42
+    } else {
+        false
+    }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::I64(v) = v {
+        k == "_score_i64" && *v == -1
+    } else {
+        false
+    }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::F64(v) = v {
+        k == "_mean_f64" && f64::abs(*v - 0.42) < 1e-5
+    } else {
+        false
+    }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::Bool(v) = v {
+        k == "_done_bool" && *v
+    } else {
+        false
+    }));
 }
 
 #[test]
@@ -331,38 +398,26 @@ fn test_ltsv_suffixes_2() {
     let res = ltsv_decoder.decode(msg).unwrap();
     let sd = &res.sd.unwrap()[0];
     let pairs = &sd.pairs;
-    assert!(pairs
-        .iter()
-        .cloned()
-        .any(|(k, v)| if let SDValue::U64(v) = v {
-            k == "_counter_u64" && v == 42
-        } else {
-            false
-        }));
-    assert!(pairs
-        .iter()
-        .cloned()
-        .any(|(k, v)| if let SDValue::I64(v) = v {
-            k == "_score_i64" && v == -1
-        } else {
-            false
-        }));
-    assert!(pairs
-        .iter()
-        .cloned()
-        .any(|(k, v)| if let SDValue::F64(v) = v {
-            k == "_mean_f64" && f64::abs(v - 0.42) < 1e-5
-        } else {
-            false
-        }));
-    assert!(pairs
-        .iter()
-        .cloned()
-        .any(|(k, v)| if let SDValue::Bool(v) = v {
-            k == "_done_bool" && v
-        } else {
-            false
-        }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::U64(v) = v {
+        k == "_counter_u64" && *v == 42
+    } else {
+        false
+    }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::I64(v) = v {
+        k == "_score_i64" && *v == -1
+    } else {
+        false
+    }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::F64(v) = v {
+        k == "_mean_f64" && f64::abs(*v - 0.42) < 1e-5
+    } else {
+        false
+    }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::Bool(v) = v {
+        k == "_done_bool" && *v
+    } else {
+        false
+    }));
 }
 
 #[test]
@@ -375,7 +430,7 @@ fn test_ltsv() {
     let msg = "time:1438790025.99\thost:testhostname\tname1:value1\tname 2: value \
                2\tn3:v3";
     let res = ltsv_decoder.decode(msg).unwrap();
-    assert!(res.ts == 1_438_790_025.99);
+    assert_eq!(res.ts.unix_nanos(), 1_438_790_025_990_000_000);
 }
 
 #[test]
@@ -389,7 +444,7 @@ fn test_ltsv2() {
                2\tn3:v3";
     let res = ltsv_decoder.decode(msg).unwrap();
     println!("{}", res.ts);
-    assert!(res.ts == 1_438_790_025.637_824);
+    assert_eq!(res.ts.unix_nanos(), 1_438_790_025_637_824_000);
 }
 
 #[test]
@@ -403,8 +458,8 @@ fn test_ltsv_3() {
                -0700]\tdone:true\tscore:-1\tmean:0.42\tcounter:42\tlevel:3\thost:\
                testhostname\tname1:value1\tname 2: value 2\tn3:v3\tmessage:this is a test";
     let res = ltsv_decoder.decode(msg).unwrap();
-    assert!(res.ts == 971_211_336.3);
-    assert!(res.severity.unwrap() == 3);
+    assert_eq!(res.ts.unix_nanos(), 971_211_336_300_000_000);
+    assert!(res.severity.unwrap() == Severity::Error);
 
     assert!(res.hostname == "testhostname");
     assert!(res.msg.unwrap() == "this is a test");
@@ -413,62 +468,41 @@ fn test_ltsv_3() {
     assert!(sd.len() == 1);
     let pairs = &sd[0].pairs;
 
-    assert!(pairs
-        .iter()
-        .cloned()
-        .any(|(k, v)| if let SDValue::String(v) = v {
-            k == "_name1" && v == "value1"
-        } else {
-            false
-        }));
-    assert!(pairs
-        .iter()
-        .cloned()
-        .any(|(k, v)| if let SDValue::String(v) = v {
-            k == "_name 2" && v == " value 2"
-        } else {
-            false
-        }));
-    assert!(pairs
-        .iter()
-        .cloned()
-        .any(|(k, v)| if let SDValue::String(v) = v {
-            k == "_n3" && v == "v3"
-        } else {
-            false
-        }));
-    assert!(pairs
-        .iter()
-        .cloned()
-        .any(|(k, v)| if let SDValue::U64(v) = v {
-            k == "_counter" && v == 42
-        } else {
-            false
-        }));
-    assert!(pairs
-        .iter()
-        .cloned()
-        .any(|(k, v)| if let SDValue::I64(v) = v {
-            k == "_score" && v == -1
-        } else {
-            false
-        }));
-    assert!(pairs
-        .iter()
-        .cloned()
-        .any(|(k, v)| if let SDValue::F64(v) = v {
-            k == "_mean" && f64::abs(v - 0.42) < 1e-5
-        } else {
-            false
-        }));
-    assert!(pairs
-        .iter()
-        .cloned()
-        .any(|(k, v)| if let SDValue::Bool(v) = v {
-            k == "_done" && v == true
-        } else {
-            false
-        }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::String(v) = v {
+        k == "_name1" && *v == "value1"
+    } else {
+        false
+    }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::String(v) = v {
+        k == "_name 2" && *v == " value 2"
+    } else {
+        false
+    }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::String(v) = v {
+        k == "_n3" && *v == "v3"
+    } else {
+        false
+    }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::U64(v) = v {
+        k == "_counter" && *v == 42
+    } else {
+        false
+    }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::I64(v) = v {
+        k == "_score" && *v == -1
+    } else {
+        false
+    }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::F64(v) = v {
+        k == "_mean" && f64::abs(*v - 0.42) < 1e-5
+    } else {
+        false
+    }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::Bool(v) = v {
+        k == "_done" && *v
+    } else {
+        false
+    }));
 }
 
 #[test]
@@ -483,5 +517,27 @@ fn test_ltsv4() {
                2\tn3:v3";
     let res = ltsv_decoder.decode(msg).unwrap();
     println!("{}", res.ts);
-    assert!(res.ts == 1_438_790_025.637_824);
+    assert_eq!(res.ts.unix_nanos(), 1_438_790_025_637_824_000);
+}
+
+#[test]
+fn test_ltsv_labels() {
+    let config = Config::from_string(
+        "[input]\nltsv_time_label = \"reqtime\"\nltsv_host_label = \"vhost\"\nltsv_message_label = \"req\"\nltsv_time_formats = [\"[year]-[month]-[day] [hour]:[minute]:[second]\"]\n",
+    );
+    let ltsv_decoder = LTSVDecoder::new(&config.unwrap());
+    let msg = "reqtime:2015-08-05 15:53:45\tvhost:testhostname\treq:GET /\ttime:other";
+    let res = ltsv_decoder.decode(msg).unwrap();
+    assert_eq!(res.ts, Timestamp::from_unix_secs(1_438_790_025));
+    assert_eq!(res.hostname, "testhostname");
+    assert_eq!(res.msg.as_deref(), Some("GET /"));
+    assert_eq!(res.field("time").as_deref(), Some("other"));
+
+    let msg = "reqtime:1438790025\treq:GET /";
+    assert_eq!(ltsv_decoder.decode(msg).unwrap_err(), "Missing hostname");
+    let config = Config::from_string(
+        "[input]\nltsv_time_label = \"reqtime\"\nltsv_missing_host = \"unknown\"\n",
+    );
+    let res = LTSVDecoder::new(&config.unwrap()).decode(msg).unwrap();
+    assert_eq!(res.hostname, "unknown");
 }
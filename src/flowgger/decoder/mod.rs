@@ -1,24 +1,140 @@
+mod auto_decoder;
+#[cfg(feature = "charset")]
+mod charset_decoder;
+#[cfg(feature = "csv")]
+mod csv_decoder;
+mod dead_letter_decoder;
+mod error_rate_decoder;
+mod fallback_decoder;
 #[cfg(feature = "gelf")]
 mod gelf_decoder;
 mod invalid_decoder;
+mod invalid_utf8_decoder;
+#[cfg(feature = "logfmt")]
+mod logfmt_decoder;
 #[cfg(feature = "ltsv")]
 mod ltsv_decoder;
+mod msg_uid_decoder;
+#[cfg(feature = "passthrough")]
+mod passthrough_decoder;
+mod pause_decoder;
+mod peer_stats_decoder;
+mod received_ts_decoder;
 #[cfg(feature = "rfc3164")]
 mod rfc3164_decoder;
 #[cfg(feature = "rfc5424")]
 mod rfc5424_decoder;
+mod schema_decoder;
+#[cfg(feature = "script")]
+mod script_decoder;
+mod sd_limit_decoder;
+mod sequence_dedup_decoder;
+#[cfg(feature = "statsd")]
+mod statsd_decoder;
+#[cfg(feature = "syslog-sign")]
+mod syslog_sign_decoder;
+mod tap_decoder;
+mod tenant_decoder;
+#[cfg(feature = "trace-context")]
+mod trace_context_decoder;
+#[cfg(feature = "wasm")]
+mod wasm_decoder;
 
+pub use self::auto_decoder::AutoDecoder;
+#[cfg(feature = "charset")]
+pub use self::charset_decoder::CharsetDecoder;
+#[cfg(feature = "csv")]
+pub use self::csv_decoder::CsvDecoder;
+pub use self::dead_letter_decoder::{DeadLetterDecoder, DeadLetterSink};
+pub use self::error_rate_decoder::ErrorRateDecoder;
+pub use self::fallback_decoder::FallbackDecoder;
 #[cfg(feature = "gelf")]
 pub use self::gelf_decoder::GelfDecoder;
 pub use self::invalid_decoder::InvalidDecoder;
+pub use self::invalid_utf8_decoder::InvalidUtf8Decoder;
+#[cfg(feature = "logfmt")]
+pub use self::logfmt_decoder::LogfmtDecoder;
 #[cfg(feature = "ltsv")]
 pub use self::ltsv_decoder::LTSVDecoder;
+pub use self::msg_uid_decoder::MsgUidDecoder;
+#[cfg(feature = "passthrough")]
+pub use self::passthrough_decoder::PassthroughDecoder;
+pub use self::pause_decoder::{
+    is_paused, is_paused_by_output, set_paused, set_paused_by_output, PauseDecoder,
+};
+pub use self::peer_stats_decoder::{peer_stats, PeerStats};
+pub use self::received_ts_decoder::ReceivedTsDecoder;
 #[cfg(feature = "rfc3164")]
 pub use self::rfc3164_decoder::RFC3164Decoder;
 #[cfg(feature = "rfc5424")]
 pub use self::rfc5424_decoder::RFC5424Decoder;
+pub use self::schema_decoder::SchemaDecoder;
+#[cfg(feature = "script")]
+pub use self::script_decoder::ScriptDecoder;
+pub use self::sd_limit_decoder::SdLimitDecoder;
+pub use self::sequence_dedup_decoder::SequenceDedup;
+#[cfg(feature = "statsd")]
+pub use self::statsd_decoder::StatsdDecoder;
+#[cfg(feature = "syslog-sign")]
+pub use self::syslog_sign_decoder::SyslogSignDecoder;
+pub use self::tap_decoder::{set_tap, tap_enabled, TapDecoder};
+pub use self::tenant_decoder::TenantDecoder;
+#[cfg(feature = "trace-context")]
+pub use self::trace_context_decoder::TraceContextDecoder;
+#[cfg(feature = "wasm")]
+pub use self::wasm_decoder::WasmDecoder;
 
+use crate::flowgger::config::Config;
 use crate::flowgger::record::Record;
+use std::io::{stderr, Write};
+use std::str;
+
+/// Names of the settings the decoders deserialize with `Config::settings`
+#[cfg(test)]
+pub fn typed_settings() -> Vec<String> {
+    use crate::flowgger::config::setting_names;
+
+    let mut settings = setting_names::<dead_letter_decoder::DeadLetterSettings>("input");
+    settings.extend(setting_names::<error_rate_decoder::ErrorRateSettings>(
+        "input",
+    ));
+    settings.extend(setting_names::<invalid_utf8_decoder::InvalidUtf8Settings>(
+        "input",
+    ));
+    settings.extend(setting_names::<msg_uid_decoder::MsgUidSettings>("input"));
+    settings.extend(setting_names::<peer_stats_decoder::PeerStatsSettings>(
+        "input",
+    ));
+    settings.extend(setting_names::<received_ts_decoder::ReceivedTsSettings>(
+        "input",
+    ));
+    settings.extend(setting_names::<schema_decoder::SchemaSettings>("input"));
+    settings.extend(setting_names::<sd_limit_decoder::SdLimitSettings>("input"));
+    settings.extend(setting_names::<sequence_dedup_decoder::DedupSettings>(
+        "input",
+    ));
+    settings.extend(setting_names::<tap_decoder::TapSettings>("input"));
+    settings.extend(setting_names::<tenant_decoder::TenantSettings>("input"));
+    #[cfg(feature = "charset")]
+    settings.extend(setting_names::<charset_decoder::CharsetSettings>("input"));
+    #[cfg(feature = "csv")]
+    settings.extend(setting_names::<csv_decoder::CsvSettings>("input"));
+    #[cfg(feature = "gelf")]
+    settings.extend(setting_names::<gelf_decoder::GelfSettings>("input"));
+    #[cfg(feature = "ltsv")]
+    settings.extend(setting_names::<ltsv_decoder::LTSVSettings>("input"));
+    #[cfg(feature = "script")]
+    settings.extend(setting_names::<script_decoder::ScriptSettings>("input"));
+    #[cfg(feature = "syslog-sign")]
+    settings.extend(setting_names::<syslog_sign_decoder::SyslogSignSettings>(
+        "input",
+    ));
+    #[cfg(feature = "trace-context")]
+    settings.extend(setting_names::<trace_context_decoder::TraceContextSettings>("input"));
+    #[cfg(feature = "wasm")]
+    settings.extend(setting_names::<wasm_decoder::WasmSettings>("input"));
+    settings
+}
 
 pub trait CloneBoxedDecoder {
     fn clone_boxed<'a>(&self) -> Box<dyn Decoder + Send + 'a>
@@ -41,6 +157,57 @@ impl Clone for Box<dyn Decoder> {
     }
 }
 
+/// Error of the records dropped on purpose, that are not worth logging
+pub const DROPPED: &str = "Dropped";
+
 pub trait Decoder: CloneBoxedDecoder {
-    fn decode(&self, line: &str) -> Result<Record, &'static str>;
+    /// Decode a record, borrowing the text of its fields from `line` when it can
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str>;
+
+    /// Decode a record received as bytes. Records that are not valid UTF-8 are rejected, unless
+    /// `InvalidUtf8Decoder` converts them.
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        match str::from_utf8(line) {
+            Ok(line) => self.decode(line),
+            Err(_) => Err("Invalid UTF-8 input"),
+        }
+    }
+}
+
+/// Log a record that could not be handled, unless it was dropped on purpose
+pub fn log_rejected(e: &str, line: &[u8]) {
+    if e != DROPPED {
+        let _ = writeln!(
+            stderr(),
+            "{}: [{}]",
+            e,
+            String::from_utf8_lossy(line).trim()
+        );
+    }
+}
+
+/// Description of the input, i.e. "udp 0.0.0.0:514"
+fn input_source(config: &Config) -> String {
+    let input_type = config
+        .lookup("input.type")
+        .and_then(|x| x.as_str())
+        .unwrap_or("-");
+    match ["input.listen", "input.src", "input.redis_queue_key"]
+        .iter()
+        .find_map(|key| {
+            let value = config.lookup(key)?;
+            match value.as_array() {
+                Some(values) => Some(
+                    values
+                        .iter()
+                        .filter_map(|x| x.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+                None => value.as_str().map(|x| x.to_owned()),
+            }
+        }) {
+        Some(address) => format!("{} {}", input_type, address),
+        None => input_type.to_owned(),
+    }
 }
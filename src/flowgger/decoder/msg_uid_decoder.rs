@@ -0,0 +1,150 @@
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue};
+use rand::Rng;
+use serde::Deserialize;
+use sha1_smol::Sha1;
+
+pub const MSG_UID_KEY: &str = "_msg_uid";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MsgUid {
+    /// Random UUID (version 4), unique for every record
+    Uuid,
+    /// SHA-1 of the raw input, identical for records received several times
+    Hash,
+}
+
+/// Settings of the message ids, in the `[input]` section
+#[derive(Deserialize)]
+pub struct MsgUidSettings {
+    msg_uid: Option<String>,
+}
+
+/// Decoder wrapper stamping every record with a unique id, stored as the `_msg_uid` structured data.
+/// The id is generated once, at ingestion, so that every copy of a record delivered downstream carries
+/// the same id and can be deduplicated.
+pub struct MsgUidDecoder {
+    decoder: Box<dyn Decoder + Send>,
+    msg_uid: MsgUid,
+}
+
+impl Clone for MsgUidDecoder {
+    fn clone(&self) -> MsgUidDecoder {
+        MsgUidDecoder {
+            decoder: self.decoder.clone_boxed(),
+            msg_uid: self.msg_uid,
+        }
+    }
+}
+
+impl MsgUidDecoder {
+    /// # Parameters
+    /// - 'input.msg_uid': Optional. "uuid" for a random id, or "hash" for a hash of the raw record.
+    ///   With "hash", identical records get the same id even if they are not duplicates.
+    ///
+    /// # Returns
+    /// The decoder as is if 'input.msg_uid' is not set, or wrapped so that it adds the id
+    pub fn wrap(config: &Config, decoder: Box<dyn Decoder + Send>) -> Box<dyn Decoder + Send> {
+        let settings: MsgUidSettings = config.settings("input");
+        let msg_uid = match settings.msg_uid {
+            None => return decoder,
+            Some(msg_uid) => match msg_uid.as_str() {
+                "uuid" => MsgUid::Uuid,
+                "hash" => MsgUid::Hash,
+                _ => panic!(r#"input.msg_uid must be a string set to "uuid" or "hash""#),
+            },
+        };
+        Box::new(MsgUidDecoder { decoder, msg_uid })
+    }
+}
+
+impl MsgUidDecoder {
+    fn stamp<'a>(&self, mut record: Record<'a>, line: &[u8]) -> Record<'a> {
+        let uid = match self.msg_uid {
+            MsgUid::Uuid => uuid_v4(rand::thread_rng().gen()),
+            MsgUid::Hash => Sha1::from(line).digest().to_string(),
+        };
+        record.push_sd_pair(MSG_UID_KEY, SDValue::String(uid.into()));
+        record
+    }
+}
+
+impl Decoder for MsgUidDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        let record = self.decoder.decode(line)?;
+        Ok(self.stamp(record, line.as_bytes()))
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        let record = self.decoder.decode_bytes(line)?;
+        Ok(self.stamp(record, line))
+    }
+}
+
+/// Format random bits as a version 4 UUID (RFC4122)
+fn uuid_v4(bits: u128) -> String {
+    let bits = (bits & !(0xf << 76) & !(0x3 << 62)) | (0x4 << 76) | (0x2 << 62);
+    let hex = format!("{:032x}", bits);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::utils::test_utils::record_test_utils::TestDecoder;
+
+    fn msg_uid(config: &str, line: &str) -> String {
+        let config = Config::from_string(config).unwrap();
+        let record = MsgUidDecoder::wrap(&config, Box::new(TestDecoder))
+            .decode(line)
+            .unwrap();
+        let sd = record.sd.unwrap();
+        match &sd[0].pairs[..] {
+            [(key, SDValue::String(uid))] if key == MSG_UID_KEY => uid.to_string(),
+            pairs => panic!("Unexpected structured data: {:?}", pairs),
+        }
+    }
+
+    #[test]
+    fn test_msg_uid_disabled() {
+        let config = Config::from_string("[input]\n").unwrap();
+        let record = MsgUidDecoder::wrap(&config, Box::new(TestDecoder))
+            .decode("test")
+            .unwrap();
+        assert!(record.sd.is_none());
+    }
+
+    #[test]
+    fn test_msg_uid_uuid() {
+        let config = "[input]\nmsg_uid = \"uuid\"\n";
+        let uid = msg_uid(config, "test");
+        assert_eq!(uid.len(), 36);
+        assert_eq!(&uid[14..15], "4");
+        assert!(matches!(&uid[19..20], "8" | "9" | "a" | "b"));
+        assert_ne!(uid, msg_uid(config, "test"));
+    }
+
+    #[test]
+    fn test_msg_uid_hash() {
+        let config = "[input]\nmsg_uid = \"hash\"\n";
+        assert_eq!(
+            msg_uid(config, "test"),
+            "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3"
+        );
+        assert_ne!(msg_uid(config, "test"), msg_uid(config, "test2"));
+    }
+
+    #[test]
+    fn test_uuid_v4() {
+        assert_eq!(uuid_v4(0), "00000000-0000-4000-8000-000000000000");
+        assert_eq!(uuid_v4(u128::MAX), "ffffffff-ffff-4fff-bfff-ffffffffffff");
+    }
+}
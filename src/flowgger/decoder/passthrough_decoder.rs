@@ -0,0 +1,46 @@
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, Timestamp};
+
+/// Decoder accepting any line as is, as the message of a record timestamped at reception.
+/// Mostly useful as the last decoder of a fallback chain, so that records in an unknown format are kept.
+#[derive(Clone)]
+pub struct PassthroughDecoder;
+
+impl PassthroughDecoder {
+    pub fn new(_config: &Config) -> PassthroughDecoder {
+        PassthroughDecoder
+    }
+}
+
+impl Decoder for PassthroughDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        if line.is_empty() {
+            return Err("Empty message");
+        }
+        Ok(Record {
+            ts: Timestamp::now(),
+            utc_offset: None,
+            hostname: "-".into(),
+            facility: None,
+            severity: None,
+            appname: None,
+            procid: None,
+            msgid: None,
+            msg: Some(line.into()),
+            full_msg: Some(line.into()),
+            sd: None,
+        })
+    }
+}
+
+#[test]
+fn test_passthrough_decoder() {
+    let config = Config::from_string("[input]\nformat = \"passthrough\"\n").unwrap();
+    let decoder = PassthroughDecoder::new(&config);
+    let record = decoder.decode("not a syslog message").unwrap();
+    assert_eq!(record.msg.as_deref(), Some("not a syslog message"));
+    assert_eq!(record.full_msg.as_deref(), Some("not a syslog message"));
+    assert!(record.ts > Timestamp::default());
+    assert!(decoder.decode("").is_err());
+}
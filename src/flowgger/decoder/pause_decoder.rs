@@ -0,0 +1,142 @@
+use super::Decoder;
+use crate::flowgger::record::Record;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+
+/// Whether the inputs are paused, checked without locking on every record
+static PAUSED: AtomicBool = AtomicBool::new(false);
+/// Whether the inputs are paused by an output that cannot deliver records, independently of `PAUSED`
+static PAUSED_BY_OUTPUT: AtomicBool = AtomicBool::new(false);
+static PAUSE_LOCK: Mutex<()> = Mutex::new(());
+static RESUMED: Condvar = Condvar::new();
+
+/// Pause or resume every input. Paused inputs stop reading: TCP senders are slowed down by the
+/// flow control, and the kernel drops the UDP datagrams once the socket buffer is full.
+#[cfg_attr(not(unix), allow(dead_code))]
+pub fn set_paused(paused: bool) {
+    let _lock = PAUSE_LOCK.lock().unwrap();
+    PAUSED.store(paused, Ordering::Release);
+    if !paused {
+        RESUMED.notify_all();
+    }
+}
+
+/// Whether the inputs are paused
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::Acquire)
+}
+
+/// Pause or resume every input on behalf of an output, i.e. while it is disconnected from all its servers.
+/// The inputs only resume once neither the output nor the admin socket pause them.
+pub fn set_paused_by_output(paused: bool) {
+    let _lock = PAUSE_LOCK.lock().unwrap();
+    PAUSED_BY_OUTPUT.store(paused, Ordering::Release);
+    if !paused {
+        RESUMED.notify_all();
+    }
+}
+
+/// Whether the inputs are paused by an output
+pub fn is_paused_by_output() -> bool {
+    PAUSED_BY_OUTPUT.load(Ordering::Acquire)
+}
+
+fn is_blocked() -> bool {
+    is_paused() || is_paused_by_output()
+}
+
+fn wait_while_paused() {
+    if !is_blocked() {
+        return;
+    }
+    let mut lock = PAUSE_LOCK.lock().unwrap();
+    while is_blocked() {
+        lock = RESUMED.wait(lock).unwrap();
+    }
+}
+
+/// Decoder wrapper blocking the input threads while the inputs are paused
+pub struct PauseDecoder {
+    decoder: Box<dyn Decoder + Send>,
+}
+
+impl Clone for PauseDecoder {
+    fn clone(&self) -> PauseDecoder {
+        PauseDecoder {
+            decoder: self.decoder.clone_boxed(),
+        }
+    }
+}
+
+impl PauseDecoder {
+    pub fn wrap(decoder: Box<dyn Decoder + Send>) -> Box<dyn Decoder + Send> {
+        Box::new(PauseDecoder { decoder })
+    }
+}
+
+impl Decoder for PauseDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        wait_while_paused();
+        self.decoder.decode(line)
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        wait_while_paused();
+        self.decoder.decode_bytes(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::utils::test_utils::record_test_utils::TestDecoder;
+    use std::sync::mpsc::{channel, RecvTimeoutError};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Resumes the inputs even if the test fails, not to block the other tests
+    struct Resume;
+
+    impl Drop for Resume {
+        fn drop(&mut self) {
+            set_paused(false);
+            set_paused_by_output(false);
+        }
+    }
+
+    #[test]
+    fn test_pause_decoder() {
+        let _resume = Resume;
+        let decoder = PauseDecoder::wrap(Box::new(TestDecoder));
+        assert_eq!(decoder.decode("record").unwrap().msg.unwrap(), "record");
+
+        set_paused(true);
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            let _ = tx.send(decoder.decode("record"));
+        });
+        let blocked = Duration::from_millis(50);
+        assert!(matches!(
+            rx.recv_timeout(blocked),
+            Err(RecvTimeoutError::Timeout)
+        ));
+
+        // The inputs stay paused as long as either the admin socket or an output pauses them
+        set_paused_by_output(true);
+        set_paused(false);
+        assert!(is_paused_by_output() && !is_paused());
+        assert!(matches!(
+            rx.recv_timeout(blocked),
+            Err(RecvTimeoutError::Timeout)
+        ));
+        set_paused_by_output(false);
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(5))
+                .unwrap()
+                .unwrap()
+                .msg
+                .unwrap(),
+            "record"
+        );
+    }
+}
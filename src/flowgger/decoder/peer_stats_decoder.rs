@@ -0,0 +1,246 @@
+use super::{Decoder, DROPPED};
+use crate::flowgger::config::Config;
+use crate::flowgger::record::Record;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_PEER_STATS: bool = false;
+const DEFAULT_PEER_STATS_MAX_PEERS: usize = 10_000;
+
+/// Counters of every peer, shared by all the inputs that track them
+static PEERS: Mutex<Option<HashMap<IpAddr, Arc<PeerCounters>>>> = Mutex::new(None);
+static MAX_PEERS: AtomicUsize = AtomicUsize::new(DEFAULT_PEER_STATS_MAX_PEERS);
+
+/// Settings of the statistics per peer, in the `[input]` section
+#[derive(Deserialize)]
+pub struct PeerStatsSettings {
+    peer_stats: Option<bool>,
+    peer_stats_max_peers: Option<NonZeroUsize>,
+}
+
+/// Counters of a peer, over all its connections
+#[derive(Default)]
+pub struct PeerCounters {
+    connections: AtomicU64,
+    records: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+    /// Time of the last connection or record, in milliseconds since the Unix epoch
+    last_seen: AtomicU64,
+}
+
+impl PeerCounters {
+    /// Number of connections accepted from the peer
+    pub fn connections(&self) -> u64 {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    /// Number of records decoded
+    pub fn records(&self) -> u64 {
+        self.records.load(Ordering::Relaxed)
+    }
+
+    /// Size of the records received, including the ones that could not be decoded
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Number of records that could not be decoded
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Time of the last connection or record, in seconds since the Unix epoch
+    pub fn last_activity(&self) -> u64 {
+        self.last_seen.load(Ordering::Relaxed) / 1000
+    }
+
+    fn seen(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis() as u64);
+        self.last_seen.store(now, Ordering::Relaxed);
+    }
+}
+
+/// The peers and their counters, the ones that sent the most bytes first
+pub fn peer_stats() -> Vec<(IpAddr, Arc<PeerCounters>)> {
+    let mut peers: Vec<(IpAddr, Arc<PeerCounters>)> = match *PEERS.lock().unwrap() {
+        None => return Vec::new(),
+        Some(ref peers) => peers
+            .iter()
+            .map(|(peer, counters)| (*peer, Arc::clone(counters)))
+            .collect(),
+    };
+    peers.sort_by_key(|(peer, counters)| (std::cmp::Reverse(counters.bytes()), *peer));
+    peers
+}
+
+/// Statistics of the records received from every peer of the stream inputs, to find out which of many
+/// devices floods an input or sends records that can't be decoded.
+///
+/// Peers are tracked by IP address, over all their connections.
+#[derive(Clone)]
+pub struct PeerStats {
+    enabled: bool,
+}
+
+impl PeerStats {
+    /// # Parameters
+    /// - 'input.peer_stats':           Optional. Count the connections, records, bytes and decoding errors of
+    ///   every peer, as listed by the "peers" command of the admin socket. Default is false.
+    /// - 'input.peer_stats_max_peers': Optional. Maximum number of peers tracked, the least recently seen
+    ///   being forgotten to track new ones beyond it. Default is 10000.
+    pub fn new(config: &Config) -> PeerStats {
+        let settings: PeerStatsSettings = config.settings("input");
+        if let Some(max_peers) = settings.peer_stats_max_peers {
+            MAX_PEERS.store(max_peers.get(), Ordering::Relaxed);
+        }
+        let enabled = settings.peer_stats.unwrap_or(DEFAULT_PEER_STATS);
+        PeerStats { enabled }
+    }
+
+    /// # Returns
+    /// The decoder of a new connection from `peer` as is if peers are not tracked or the peer is unknown,
+    /// or wrapped so that it counts the records of the peer
+    pub fn wrap(
+        &self,
+        peer: Option<IpAddr>,
+        decoder: Box<dyn Decoder + Send>,
+    ) -> Box<dyn Decoder + Send> {
+        match peer {
+            Some(peer) if self.enabled => {
+                let counters = counters(peer);
+                counters.connections.fetch_add(1, Ordering::Relaxed);
+                counters.seen();
+                Box::new(PeerStatsDecoder { decoder, counters })
+            }
+            _ => decoder,
+        }
+    }
+}
+
+/// The counters of `peer`
+fn counters(peer: IpAddr) -> Arc<PeerCounters> {
+    let mut peers = PEERS.lock().unwrap();
+    let peers = peers.get_or_insert_with(HashMap::new);
+    peer_counters(peers, peer, MAX_PEERS.load(Ordering::Relaxed))
+}
+
+/// The counters of `peer` in `peers`, the least recently seen peer being forgotten if there are already
+/// `max_peers` of them, so that new devices are still tracked while many others keep sending
+fn peer_counters(
+    peers: &mut HashMap<IpAddr, Arc<PeerCounters>>,
+    peer: IpAddr,
+    max_peers: usize,
+) -> Arc<PeerCounters> {
+    if !peers.contains_key(&peer) && peers.len() >= max_peers {
+        let oldest = peers
+            .iter()
+            .min_by_key(|(_, counters)| counters.last_seen.load(Ordering::Relaxed))
+            .map(|(peer, _)| *peer);
+        if let Some(oldest) = oldest {
+            peers.remove(&oldest);
+        }
+    }
+    Arc::clone(peers.entry(peer).or_default())
+}
+
+/// Decoder wrapper counting the records of a connection
+pub struct PeerStatsDecoder {
+    decoder: Box<dyn Decoder + Send>,
+    counters: Arc<PeerCounters>,
+}
+
+impl Clone for PeerStatsDecoder {
+    fn clone(&self) -> PeerStatsDecoder {
+        PeerStatsDecoder {
+            decoder: self.decoder.clone_boxed(),
+            counters: Arc::clone(&self.counters),
+        }
+    }
+}
+
+impl PeerStatsDecoder {
+    fn count<'a>(
+        &self,
+        len: usize,
+        res: Result<Record<'a>, &'static str>,
+    ) -> Result<Record<'a>, &'static str> {
+        let counters = &self.counters;
+        counters.bytes.fetch_add(len as u64, Ordering::Relaxed);
+        match res {
+            Ok(_) => counters.records.fetch_add(1, Ordering::Relaxed),
+            Err(e) if e != DROPPED => counters.errors.fetch_add(1, Ordering::Relaxed),
+            Err(_) => 0,
+        };
+        counters.seen();
+        res
+    }
+}
+
+impl Decoder for PeerStatsDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        self.count(line.len(), self.decoder.decode(line))
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        self.count(line.len(), self.decoder.decode_bytes(line))
+    }
+}
+
+#[cfg(all(test, feature = "rfc5424"))]
+mod tests {
+    use super::*;
+    use crate::flowgger::decoder::RFC5424Decoder;
+
+    #[test]
+    fn test_peer_stats() {
+        let config = Config::from_string("[input]\npeer_stats = true\n").unwrap();
+        let stats = PeerStats::new(&config);
+        let peer: IpAddr = "192.0.2.34".parse().unwrap();
+        let decoder = stats.wrap(Some(peer), Box::new(RFC5424Decoder::new(&config)));
+        let line = "<23>1 2015-08-05T15:53:45Z testhostname appname 69 42 - test";
+        assert!(decoder.decode(line).is_ok());
+        assert!(decoder.decode_bytes(b"garbage").is_err());
+
+        let (_, counters) = peer_stats()
+            .into_iter()
+            .find(|(stats_peer, _)| *stats_peer == peer)
+            .unwrap();
+        assert_eq!(counters.connections(), 1);
+        assert_eq!(counters.records(), 1);
+        assert_eq!(counters.errors(), 1);
+        assert_eq!(counters.bytes(), (line.len() + 7) as u64);
+        assert!(counters.last_activity() > 0);
+    }
+
+    #[test]
+    fn test_peer_stats_eviction() {
+        let mut peers = HashMap::new();
+        let peer = |i: u8| IpAddr::from([192, 0, 2, i]);
+        for i in 1..=3 {
+            peer_counters(&mut peers, peer(i), 3)
+                .last_seen
+                .store(u64::from(i), Ordering::Relaxed);
+        }
+        peer_counters(&mut peers, peer(1), 3)
+            .last_seen
+            .store(4, Ordering::Relaxed);
+
+        // The least recently seen peer is forgotten, and the new one is kept
+        peer_counters(&mut peers, peer(4), 3)
+            .last_seen
+            .store(5, Ordering::Relaxed);
+        assert_eq!(peers.len(), 3);
+        assert!(!peers.contains_key(&peer(2)));
+        peer_counters(&mut peers, peer(5), 3);
+        assert!(!peers.contains_key(&peer(3)));
+        assert!(peers.contains_key(&peer(4)));
+    }
+}
@@ -0,0 +1,81 @@
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue, Timestamp};
+use serde::Deserialize;
+
+pub const RECEIVED_TS_KEY: &str = "_received_ts";
+
+/// Settings of the time of reception, in the `[input]` section
+#[derive(Deserialize)]
+pub struct ReceivedTsSettings {
+    received_ts: Option<bool>,
+}
+
+/// Decoder wrapper stamping every record with the time flowgger received it, stored as the `_received_ts`
+/// structured data, as a UNIX timestamp with a fractional part. The timestamp of the record is the one set by
+/// the device, so that downstream can compute its clock skew.
+pub struct ReceivedTsDecoder {
+    decoder: Box<dyn Decoder + Send>,
+}
+
+impl Clone for ReceivedTsDecoder {
+    fn clone(&self) -> ReceivedTsDecoder {
+        ReceivedTsDecoder {
+            decoder: self.decoder.clone_boxed(),
+        }
+    }
+}
+
+impl ReceivedTsDecoder {
+    /// # Parameters
+    /// - 'input.received_ts': Optional. Must be a boolean. Default is false.
+    ///
+    /// # Returns
+    /// The decoder as is if 'input.received_ts' is not set, or wrapped so that it adds the time of reception
+    pub fn wrap(config: &Config, decoder: Box<dyn Decoder + Send>) -> Box<dyn Decoder + Send> {
+        let settings: ReceivedTsSettings = config.settings("input");
+        if !settings.received_ts.unwrap_or(false) {
+            return decoder;
+        }
+        Box::new(ReceivedTsDecoder { decoder })
+    }
+
+    fn stamp<'a>(&self, mut record: Record<'a>, received_ts: f64) -> Record<'a> {
+        record.push_sd_pair(RECEIVED_TS_KEY, SDValue::F64(received_ts));
+        record
+    }
+}
+
+impl Decoder for ReceivedTsDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        let received_ts = now();
+        Ok(self.stamp(self.decoder.decode(line)?, received_ts))
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        let received_ts = now();
+        Ok(self.stamp(self.decoder.decode_bytes(line)?, received_ts))
+    }
+}
+
+fn now() -> f64 {
+    Timestamp::now().as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::utils::test_utils::record_test_utils::{TestDecoder, TEST_TS};
+
+    #[test]
+    fn test_received_ts_decoder() {
+        let config = Config::from_string("[input]\nreceived_ts = true\n").unwrap();
+        let before = now();
+        let record = ReceivedTsDecoder::wrap(&config, Box::new(TestDecoder))
+            .decode_bytes(b"message")
+            .unwrap();
+        assert_eq!(record.ts, Timestamp::from_secs_f64(TEST_TS));
+        let received_ts: f64 = record.field("received_ts").unwrap().parse().unwrap();
+        assert!(received_ts >= before && received_ts <= now());
+    }
+}
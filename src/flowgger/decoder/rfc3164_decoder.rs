@@ -1,12 +1,18 @@
 use super::Decoder;
 use crate::flowgger::config::Config;
-use crate::flowgger::record::Record;
-use crate::flowgger::utils;
+use crate::flowgger::record::{split_pri, Facility, Record, Severity, Timestamp};
+use std::borrow::Cow;
+use std::fmt::Write as _;
 use std::io::{stderr, Write};
-use time::{format_description, OffsetDateTime, PrimitiveDateTime};
+use time::format_description::FormatItem;
+use time::macros::format_description;
+use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
 use time_tz::timezones::get_by_name;
 use time_tz::PrimitiveDateTimeExt;
 
+const TIME_FORMAT: &[FormatItem<'_>] =
+    format_description!("[year] [month repr:short] [day padding:none] [hour]:[minute]:[second]");
+
 #[derive(Clone)]
 pub struct RFC3164Decoder {}
 
@@ -28,7 +34,7 @@ impl Decoder for RFC3164Decoder {
     /// # Returns
     /// * Record object containing the log info extracted
     ///
-    fn decode(&self, line: &str) -> Result<Record, &'static str> {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
         // Get the optional pri part and remove it from the string
         let (pri, _msg) = parse_strip_pri(line)?;
 
@@ -49,11 +55,15 @@ impl Decoder for RFC3164Decoder {
 }
 
 struct Pri {
-    facility: Option<u8>,
-    severity: Option<u8>,
+    facility: Option<Facility>,
+    severity: Option<Severity>,
 }
 
-fn decode_rfc_standard(pri: &Pri, msg: &str, line: &str) -> Result<Record, &'static str> {
+fn decode_rfc_standard<'a>(
+    pri: &Pri,
+    msg: &'a str,
+    line: &'a str,
+) -> Result<Record<'a>, &'static str> {
     // Decoding "recommended" rfc input as advised in the rfc: [<pri>]<datetime> <hostname> <message>
 
     // The event may have several consecutive spaces as separator
@@ -62,22 +72,25 @@ fn decode_rfc_standard(pri: &Pri, msg: &str, line: &str) -> Result<Record, &'sta
     // If we have less than 4 tokens, the input can't be valid
     if tokens_vec.len() > 3 {
         // Parse the date, the next token is the hostname
-        let (ts, _log_tokens) = parse_date_token(&tokens_vec)?;
-        let _hostname = _log_tokens[0];
+        let (ts, utc_offset, log_tokens) = parse_date_token(&tokens_vec)?;
+        let hostname = log_tokens
+            .first()
+            .ok_or("Malformed RFC3164 event: Missing hostname")?;
 
         // All that remains is the message that may contain several spaces, so rebuild it
-        let _message = _log_tokens[1..].join(" ");
+        let message = join_tokens(msg, &log_tokens[1..]);
 
         let record = Record {
             ts,
-            hostname: _hostname.to_owned(),
+            utc_offset,
+            hostname: Cow::Borrowed(hostname),
             facility: pri.facility,
             severity: pri.severity,
             appname: None,
             procid: None,
             msgid: None,
-            msg: Some(_message.to_owned()),
-            full_msg: Some(line.trim_end().to_owned()),
+            msg: Some(message),
+            full_msg: Some(Cow::Borrowed(line.trim_end())),
             sd: None,
         };
         Ok(record)
@@ -86,41 +99,60 @@ fn decode_rfc_standard(pri: &Pri, msg: &str, line: &str) -> Result<Record, &'sta
     }
 }
 
-fn decode_rfc_custom(pri: &Pri, msg: &str, line: &str) -> Result<Record, &'static str> {
-    // Decoding custom rfc input formatted as : [<pri>]<hostname>: <datetime>: <message>
-
-    // The event separator for hostname/timestamp/message is ": "
-    let tokens_vec = msg.split(": ").collect::<Vec<&str>>();
-
-    // If we have less than 2 tokens, the input can't be valid
-    if tokens_vec.len() > 2 {
-        let _hostname = tokens_vec[0];
-
-        // The date is space separated, but make sure to remove consecutive spaces
-        let date_tokens_vec = tokens_vec[1].split_whitespace().collect::<Vec<&str>>();
-        let (ts, _) = parse_date_token(&date_tokens_vec)?;
-
-        // All that remains is the message, rebuild it
-        let _message = tokens_vec[2..].join(": ");
-
-        let record = Record {
-            ts,
-            hostname: _hostname.to_owned(),
-            facility: pri.facility,
-            severity: pri.severity,
-            appname: None,
-            procid: None,
-            msgid: None,
-            msg: Some(_message.to_owned()),
-            full_msg: Some(line.trim_end().to_owned()),
-            sd: None,
-        };
-        Ok(record)
+/// Tokens of `text` separated by single spaces, sliced from `text` when they already are
+fn join_tokens<'a>(text: &'a str, tokens: &[&'a str]) -> Cow<'a, str> {
+    let (first, last) = match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Cow::Borrowed(""),
+    };
+    let start = first.as_ptr() as usize - text.as_ptr() as usize;
+    let end = last.as_ptr() as usize + last.len() - text.as_ptr() as usize;
+    let joined = &text[start..end];
+    let tokens_len: usize = tokens.iter().map(|token| token.len()).sum();
+    // Tokens don't contain whitespace, so any other than the single spaces would separate them
+    if joined.len() == tokens_len + tokens.len() - 1
+        && !joined.contains(|c: char| c.is_whitespace() && c != ' ')
+    {
+        Cow::Borrowed(joined)
     } else {
-        Err("Malformed RFC3164 event: Invalid timestamp or hostname")
+        Cow::Owned(tokens.join(" "))
     }
 }
 
+fn decode_rfc_custom<'a>(
+    pri: &Pri,
+    msg: &'a str,
+    line: &'a str,
+) -> Result<Record<'a>, &'static str> {
+    // Decoding custom rfc input formatted as : [<pri>]<hostname>: <datetime>: <message>
+
+    // The event separator for hostname/timestamp/message is ": ", the message keeps the remaining ones
+    let mut tokens = msg.splitn(3, ": ");
+    let (hostname, date, message) = match (tokens.next(), tokens.next(), tokens.next()) {
+        (Some(hostname), Some(date), Some(message)) => (hostname, date, message),
+        _ => return Err("Malformed RFC3164 event: Invalid timestamp or hostname"),
+    };
+
+    // The date is space separated, but make sure to remove consecutive spaces
+    let date_tokens_vec = date.split_whitespace().collect::<Vec<&str>>();
+    let (ts, utc_offset, _) = parse_date_token(&date_tokens_vec)?;
+
+    let record = Record {
+        ts,
+        utc_offset,
+        hostname: Cow::Borrowed(hostname),
+        facility: pri.facility,
+        severity: pri.severity,
+        appname: None,
+        procid: None,
+        msgid: None,
+        msg: Some(Cow::Borrowed(message)),
+        full_msg: Some(Cow::Borrowed(line.trim_end())),
+        sd: None,
+    };
+    Ok(record)
+}
+
 fn parse_strip_pri(event: &str) -> Result<(Pri, &str), &'static str> {
     if event.starts_with('<') {
         let pri_end_index = event
@@ -132,10 +164,11 @@ fn parse_strip_pri(event: &str) -> Result<(Pri, &str), &'static str> {
             .trim_end_matches('>')
             .parse()
             .or(Err("Invalid priority"))?;
+        let (facility, severity) = split_pri(npri)?;
         Ok((
             Pri {
-                facility: Some(npri >> 3),
-                severity: Some(npri & 7),
+                facility: Some(facility),
+                severity: Some(severity),
             },
             msg,
         ))
@@ -150,7 +183,9 @@ fn parse_strip_pri(event: &str) -> Result<(Pri, &str), &'static str> {
     }
 }
 
-fn parse_date_token<'a>(ts_tokens: &'a [&str]) -> Result<(f64, Vec<&'a str>), &'static str> {
+fn parse_date_token<'a, 'b>(
+    ts_tokens: &'a [&'b str],
+) -> Result<(Timestamp, Option<UtcOffset>, &'a [&'b str]), &'static str> {
     // If we don't have at least 3 tokens, don't even try, parsing will fail
     if ts_tokens.len() < 3 {
         return Err("Invalid time format");
@@ -159,59 +194,67 @@ fn parse_date_token<'a>(ts_tokens: &'a [&str]) -> Result<(f64, Vec<&'a str>), &'
     parse_date(ts_tokens, false).or_else(|_| parse_date(ts_tokens, true))
 }
 
-fn parse_date<'a>(
-    ts_tokens: &'a [&str],
+fn parse_date<'a, 'b>(
+    ts_tokens: &'a [&'b str],
     has_year: bool,
-) -> Result<(f64, Vec<&'a str>), &'static str> {
-    // Decode the date/time from the given tokens with optional year specified
-    let ts_str;
+) -> Result<(Timestamp, Option<UtcOffset>, &'a [&'b str]), &'static str> {
+    // Decode the date/time from the given tokens with optional year specified, along with the UTC
+    // offset of the timezone that follows them, if any
+    let mut ts_str = String::with_capacity(32);
     let mut idx;
 
     // If no year in the string, parse manually add the current year
     if has_year {
         idx = 4;
-        ts_str = match ts_tokens.get(0..idx) {
-            Some(str) => str.join(" "),
+        match ts_tokens.get(0..idx) {
+            Some(tokens) => push_tokens(&mut ts_str, tokens),
             None => return Err("Unable to parse RFC3164 date with year"),
         };
     } else {
         idx = 3;
         let current_year = OffsetDateTime::now_utc().year();
-        ts_str = match ts_tokens.get(0..idx) {
-            Some(str) => format!("{} {}", current_year, str.join(" ")),
+        match ts_tokens.get(0..idx) {
+            Some(tokens) => {
+                let _ = write!(ts_str, "{}", current_year);
+                ts_str.push(' ');
+                push_tokens(&mut ts_str, tokens)
+            }
             None => return Err("Unable to parse RFC3164 date without year"),
         };
     }
 
-    let format_item = format_description::parse(
-        "[year] [month repr:short] [day padding:none] [hour]:[minute]:[second]",
-    )
-    .unwrap();
-    match PrimitiveDateTime::parse(&ts_str, &format_item) {
+    match PrimitiveDateTime::parse(&ts_str, TIME_FORMAT) {
         Ok(primitive_date) => {
             // See if the next token is a timezone
-            let ts: f64;
-            let tz_res = if ts_tokens.len() > idx {
-                get_by_name(ts_tokens[idx]).ok_or("No timezone".to_string())
-            } else {
-                Err("No timezone".to_string())
-            };
-
-            if let Ok(tz) = tz_res {
-                let dt = primitive_date.assume_timezone(tz);
-                ts = utils::PreciseTimestamp::from_offset_datetime(dt).as_f64();
+            let tz = ts_tokens.get(idx).and_then(|name| get_by_name(name));
+            let (ts, utc_offset) = if let Some(tz) = tz {
+                // A time skipped when the clocks go forward gets the offset in effect at the same UTC time
+                let dt = primitive_date
+                    .assume_timezone(tz)
+                    .take_first()
+                    .unwrap_or_else(|| primitive_date.assume_timezone_utc(tz));
                 idx += 1;
+                (Timestamp::from_offset_datetime(dt), Some(dt.offset()))
             }
             // No timezome, give a timestamp without tz
             else {
-                ts = utils::PreciseTimestamp::from_primitive_datetime(primitive_date).as_f64();
-            }
-            Ok((ts, ts_tokens[idx..].to_vec()))
+                (Timestamp::from_primitive_datetime(primitive_date), None)
+            };
+            Ok((ts, utc_offset, &ts_tokens[idx..]))
         }
         Err(_) => Err("Unable to parse the date in RFC3164 decoder"),
     }
 }
 
+fn push_tokens(res: &mut String, tokens: &[&str]) {
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 {
+            res.push(' ');
+        }
+        res.push_str(token);
+    }
+}
+
 #[cfg(test)]
 use crate::flowgger::utils::test_utils::rfc_test_utils::{
     ts_from_date_time, ts_from_partial_date_time,
@@ -230,12 +273,13 @@ fn test_rfc3164_decode_nopri() {
     assert_eq!(res.facility, None);
     assert_eq!(res.severity, None);
     assert_eq!(res.ts, expected_ts);
+    assert_eq!(res.utc_offset, None);
     assert_eq!(res.hostname, "testhostname");
     assert_eq!(res.appname, None);
     assert_eq!(res.procid, None);
     assert_eq!(res.msgid, None);
-    assert_eq!(res.msg, Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.to_string()));
-    assert_eq!(res.full_msg, Some(msg.to_string()));
+    assert_eq!(res.msg, Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.into()));
+    assert_eq!(res.full_msg, Some(msg.into()));
     assert!(res.sd.is_none());
 }
 
@@ -247,15 +291,15 @@ fn test_rfc3164_decode_with_pri() {
 
     let decoder = RFC3164Decoder::new(&cfg);
     let res = decoder.decode(msg).unwrap();
-    assert_eq!(res.facility, Some(1));
-    assert_eq!(res.severity, Some(5));
+    assert_eq!(res.facility, Some(Facility::User));
+    assert_eq!(res.severity, Some(Severity::Notice));
     assert_eq!(res.ts, expected_ts);
     assert_eq!(res.hostname, "testhostname");
     assert_eq!(res.appname, None);
     assert_eq!(res.procid, None);
     assert_eq!(res.msgid, None);
-    assert_eq!(res.msg, Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.to_string()));
-    assert_eq!(res.full_msg, Some(msg.to_string()));
+    assert_eq!(res.msg, Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.into()));
+    assert_eq!(res.full_msg, Some(msg.into()));
     assert!(res.sd.is_none());
 }
 
@@ -267,15 +311,15 @@ fn test_rfc3164_decode_with_pri_year() {
 
     let decoder = RFC3164Decoder::new(&cfg);
     let res = decoder.decode(msg).unwrap();
-    assert_eq!(res.facility, Some(1));
-    assert_eq!(res.severity, Some(5));
+    assert_eq!(res.facility, Some(Facility::User));
+    assert_eq!(res.severity, Some(Severity::Notice));
     assert_eq!(res.ts, expected_ts);
     assert_eq!(res.hostname, "testhostname");
     assert_eq!(res.appname, None);
     assert_eq!(res.procid, None);
     assert_eq!(res.msgid, None);
-    assert_eq!(res.msg, Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.to_string()));
-    assert_eq!(res.full_msg, Some(msg.to_string()));
+    assert_eq!(res.msg, Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.into()));
+    assert_eq!(res.full_msg, Some(msg.into()));
     assert!(res.sd.is_none());
 }
 
@@ -283,19 +327,20 @@ fn test_rfc3164_decode_with_pri_year() {
 fn test_rfc3164_decode_with_pri_year_tz() {
     let msg = r#"<13>2020 Aug 6 05:15:24 America/Sao_Paulo testhostname appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#;
     let cfg = Config::from_string("[input]\n[input.ltsv_schema]\nformat = \"rfc3164\"\n").unwrap();
-    let expected_ts = ts_from_date_time(2020, Month::August, 6, 08, 15, 24, 0);
+    let expected_ts = ts_from_date_time(2020, Month::August, 6, 8, 15, 24, 0);
 
     let decoder = RFC3164Decoder::new(&cfg);
     let res = decoder.decode(msg).unwrap();
-    assert_eq!(res.facility, Some(1));
-    assert_eq!(res.severity, Some(5));
+    assert_eq!(res.facility, Some(Facility::User));
+    assert_eq!(res.severity, Some(Severity::Notice));
     assert_eq!(res.ts, expected_ts);
+    assert_eq!(res.utc_offset, Some(UtcOffset::from_hms(-3, 0, 0).unwrap()));
     assert_eq!(res.hostname, "testhostname");
     assert_eq!(res.appname, None);
     assert_eq!(res.procid, None);
     assert_eq!(res.msgid, None);
-    assert_eq!(res.msg, Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.to_string()));
-    assert_eq!(res.full_msg, Some(msg.to_string()));
+    assert_eq!(res.msg, Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.into()));
+    assert_eq!(res.full_msg, Some(msg.into()));
     assert!(res.sd.is_none());
 }
 
@@ -314,11 +359,21 @@ fn test_rfc3164_decode_tz_no_year() {
     assert_eq!(res.appname, None);
     assert_eq!(res.procid, None);
     assert_eq!(res.msgid, None);
-    assert_eq!(res.msg, Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.to_string()));
-    assert_eq!(res.full_msg, Some(msg.to_string()));
+    assert_eq!(res.msg, Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.into()));
+    assert_eq!(res.full_msg, Some(msg.into()));
     assert!(res.sd.is_none());
 }
 
+#[test]
+fn test_rfc3164_decode_invalid_pri() {
+    // PRI values are at most 191, facilities 24 to 31 don't exist
+    let msg = "<200>Aug  6 11:15:24 testhostname test message";
+    let cfg = Config::from_string("[input]\nformat = \"rfc3164\"\n").unwrap();
+
+    let decoder = RFC3164Decoder::new(&cfg);
+    assert_eq!(decoder.decode(msg).unwrap_err(), "Invalid priority");
+}
+
 #[test]
 fn test_rfc3164_decode_invalid_event() {
     let msg = "test message";
@@ -354,11 +409,8 @@ fn test_rfc3164_decode_custom_with_year() {
     assert_eq!(res.appname, None);
     assert_eq!(res.procid, None);
     assert_eq!(res.msgid, None);
-    assert_eq!(
-        res.msg,
-        Some(r#"appname 69 42 some test message"#.to_string())
-    );
-    assert_eq!(res.full_msg, Some(msg.to_string()));
+    assert_eq!(res.msg, Some(r#"appname 69 42 some test message"#.into()));
+    assert_eq!(res.full_msg, Some(msg.into()));
     assert!(res.sd.is_none());
 }
 
@@ -377,8 +429,8 @@ fn test_rfc3164_decode_custom_with_year_notz() {
     assert_eq!(res.appname, None);
     assert_eq!(res.procid, None);
     assert_eq!(res.msgid, None);
-    assert_eq!(res.msg, Some(r#"appname: a test message"#.to_string()));
-    assert_eq!(res.full_msg, Some(msg.to_string()));
+    assert_eq!(res.msg, Some(r#"appname: a test message"#.into()));
+    assert_eq!(res.full_msg, Some(msg.into()));
     assert!(res.sd.is_none());
 }
 
@@ -390,15 +442,15 @@ fn test_rfc3164_decode_custom_with_pri() {
 
     let decoder = RFC3164Decoder::new(&cfg);
     let res = decoder.decode(msg).unwrap();
-    assert_eq!(res.facility, Some(1));
-    assert_eq!(res.severity, Some(5));
+    assert_eq!(res.facility, Some(Facility::User));
+    assert_eq!(res.severity, Some(Severity::Notice));
     assert_eq!(res.ts, expected_ts);
     assert_eq!(res.hostname, "testhostname");
     assert_eq!(res.appname, None);
     assert_eq!(res.procid, None);
     assert_eq!(res.msgid, None);
-    assert_eq!(res.msg, Some(r#"appname: test message"#.to_string()));
-    assert_eq!(res.full_msg, Some(msg.to_string()));
+    assert_eq!(res.msg, Some(r#"appname: test message"#.into()));
+    assert_eq!(res.full_msg, Some(msg.into()));
     assert!(res.sd.is_none());
 }
 
@@ -410,8 +462,8 @@ fn test_rfc3164_decode_custom_trimed() {
 
     let decoder = RFC3164Decoder::new(&cfg);
     let res = decoder.decode(msg).unwrap();
-    assert_eq!(res.facility, Some(1));
-    assert_eq!(res.severity, Some(5));
+    assert_eq!(res.facility, Some(Facility::User));
+    assert_eq!(res.severity, Some(Severity::Notice));
     assert_eq!(res.ts, expected_ts);
     assert_eq!(res.hostname, "testhostname");
     assert_eq!(res.appname, None);
@@ -419,7 +471,7 @@ fn test_rfc3164_decode_custom_trimed() {
     assert_eq!(res.msgid, None);
     assert_eq!(
         res.full_msg,
-        Some("<13>testhostname: 2019 Mar 27 12:09:39 UTC: appname: test message".to_string())
+        Some("<13>testhostname: 2019 Mar 27 12:09:39 UTC: appname: test message".into())
     );
     assert!(res.sd.is_none());
 }
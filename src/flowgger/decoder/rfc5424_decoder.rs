@@ -1,9 +1,11 @@
 use super::Decoder;
 use crate::flowgger::config::Config;
-use crate::flowgger::record::{Record, SDValue, StructuredData};
-use crate::flowgger::utils;
+use crate::flowgger::record::{
+    split_pri, Facility, Record, SDValue, Severity, StructuredData, Timestamp,
+};
+use std::borrow::Cow;
 use time::format_description::well_known::Rfc3339;
-use time::OffsetDateTime;
+use time::{OffsetDateTime, UtcOffset};
 
 #[derive(Clone)]
 pub struct RFC5424Decoder;
@@ -15,14 +17,11 @@ impl RFC5424Decoder {
 }
 
 impl Decoder for RFC5424Decoder {
-    fn decode(&self, line: &str) -> Result<Record, &'static str> {
-        let (_bom, line) = match BOM::parse(line, "<") {
-            Ok(bom_line) => bom_line,
-            Err(err) => return Err(err),
-        };
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        let (_bom, line) = Bom::parse(line, "<")?;
         let mut parts = line.splitn(7, ' ');
         let pri_version = parse_pri_version(parts.next().ok_or("Missing priority and version")?)?;
-        let ts = parse_ts(parts.next().ok_or("Missing timestamp")?)?;
+        let (ts, utc_offset) = parse_ts(parts.next().ok_or("Missing timestamp")?)?;
         let hostname = parts.next().ok_or("Missing hostname")?;
         let appname = parts.next().ok_or("Missing application name")?;
         let procid = parts.next().ok_or("Missing process id")?;
@@ -31,46 +30,55 @@ impl Decoder for RFC5424Decoder {
 
         let record = Record {
             ts,
-            hostname: hostname.to_owned(),
+            utc_offset: Some(utc_offset),
+            hostname: Cow::Borrowed(hostname),
             facility: Some(pri_version.facility),
             severity: Some(pri_version.severity),
-            appname: Some(appname.to_owned()),
-            procid: Some(procid.to_owned()),
-            msgid: Some(msgid.to_owned()),
+            appname: parse_nil(appname),
+            procid: parse_nil(procid),
+            msgid: parse_nil(msgid),
             sd: if sd_vec.is_empty() {
                 None
             } else {
                 Some(sd_vec)
             },
             msg,
-            full_msg: Some(line.trim_end().to_owned()),
+            full_msg: Some(Cow::Borrowed(line.trim_end())),
         };
         Ok(record)
     }
 }
 
 struct Pri {
-    facility: u8,
-    severity: u8,
+    facility: Facility,
+    severity: Severity,
 }
 
-enum BOM {
-    NONE,
-    UTF8,
+enum Bom {
+    None,
+    Utf8,
 }
 
-impl BOM {
-    fn parse<'a>(line: &'a str, sep: &str) -> Result<(BOM, &'a str), &'static str> {
-        if line.starts_with('\u{feff}') {
-            Ok((BOM::UTF8, &line[3..]))
+impl Bom {
+    fn parse<'a>(line: &'a str, sep: &str) -> Result<(Bom, &'a str), &'static str> {
+        if let Some(line) = line.strip_prefix('\u{feff}') {
+            Ok((Bom::Utf8, line))
         } else if line.starts_with(sep) {
-            Ok((BOM::NONE, line))
+            Ok((Bom::None, line))
         } else {
             Err("Unsupported BOM")
         }
     }
 }
 
+/// Header fields set to the NILVALUE ("-") are missing
+fn parse_nil(value: &str) -> Option<Cow<'_, str>> {
+    match value {
+        "-" => None,
+        value => Some(Cow::Borrowed(value)),
+    }
+}
+
 fn parse_pri_version(line: &str) -> Result<Pri, &'static str> {
     if !line.starts_with('<') {
         return Err("The priority should be inside brackets");
@@ -85,25 +93,26 @@ fn parse_pri_version(line: &str) -> Result<Pri, &'static str> {
     if version != "1" {
         return Err("Unsupported version");
     }
-    Ok(Pri {
-        facility: pri_encoded >> 3,
-        severity: pri_encoded & 7,
-    })
+    let (facility, severity) = split_pri(pri_encoded)?;
+    Ok(Pri { facility, severity })
 }
 
-fn rfc3339_to_unix(rfc3339: &str) -> Result<f64, &'static str> {
+fn rfc3339_to_unix(rfc3339: &str) -> Result<(Timestamp, UtcOffset), &'static str> {
     match OffsetDateTime::parse(rfc3339, &Rfc3339) {
-        Ok(date) => Ok(utils::PreciseTimestamp::from_offset_datetime(date).as_f64()),
+        Ok(date) => Ok((Timestamp::from_offset_datetime(date), date.offset())),
         Err(_) => Err("Unable to parse the date from RFC3339 to Unix time in RFC5424 decoder"),
     }
 }
 
-fn parse_ts(line: &str) -> Result<f64, &'static str> {
+fn parse_ts(line: &str) -> Result<(Timestamp, UtcOffset), &'static str> {
     rfc3339_to_unix(line)
 }
 
-fn unescape_sd_value(value: &str) -> String {
-    let mut res = "".to_owned();
+fn unescape_sd_value(value: &str) -> Cow<'_, str> {
+    if !value.contains('\\') {
+        return Cow::Borrowed(value);
+    }
+    let mut res = String::with_capacity(value.len());
     let mut esc = false;
 
     for c in value.chars() {
@@ -121,15 +130,16 @@ fn unescape_sd_value(value: &str) -> String {
             }
         }
     }
-    res
+    Cow::Owned(res)
 }
 
-fn parse_data(line: &str) -> Result<(Vec<StructuredData>, Option<String>), &'static str> {
+#[allow(clippy::type_complexity)]
+fn parse_data(line: &str) -> Result<(Vec<StructuredData<'_>>, Option<Cow<'_, str>>), &'static str> {
     let mut sd_vec: Vec<StructuredData> = Vec::new();
     match line.chars().next().ok_or("Missing log message")? {
         '-' => {
             // No SD, just a message
-            return Ok((sd_vec, parse_msg(line, 1)));
+            Ok((sd_vec, parse_msg(line, 1)))
         }
         '[' => {
             // At least one SD
@@ -142,36 +152,37 @@ fn parse_data(line: &str) -> Result<(Vec<StructuredData>, Option<String>), &'sta
                 offset = new_offset;
                 sd_vec.push(sd);
 
-                match leftover[offset..]
-                    .chars()
-                    .next()
-                    .ok_or("Missing log message")?
-                {
+                match leftover[offset..].chars().next() {
                     // Another SD
-                    '[' => next_sd = true,
+                    Some('[') => next_sd = true,
                     // Separator, the rest is the message
-                    ' ' => return Ok((sd_vec, parse_msg(leftover, offset))),
+                    Some(' ') => return Ok((sd_vec, parse_msg(leftover, offset))),
+                    // The message is optional, i.e. in RFC5848 signature blocks
+                    None => return Ok((sd_vec, None)),
                     _ => return Err("Malformated RFC5424 message"),
                 }
             }
-            return Ok((sd_vec, parse_msg(leftover, 1)));
+            Ok((sd_vec, parse_msg(leftover, 1)))
         }
-        _ => return Err("Malformated RFC5424 message"),
-    };
+        _ => Err("Malformated RFC5424 message"),
+    }
 }
 
-fn parse_msg(line: &str, offset: usize) -> Option<String> {
+fn parse_msg(line: &str, offset: usize) -> Option<Cow<'_, str>> {
     if offset > line.len() {
         None
     } else {
         match line[offset..].trim() {
             "" => None,
-            m => Some(m.to_owned()),
+            m => Some(Cow::Borrowed(m)),
         }
     }
 }
 
-fn parse_sd_data(line: &str, offset: usize) -> Result<(StructuredData, &str, usize), &'static str> {
+fn parse_sd_data(
+    line: &str,
+    offset: usize,
+) -> Result<(StructuredData<'_>, &str, usize), &'static str> {
     let mut parts = line[offset..].splitn(2, ' ');
     let sd_id = parts.next().ok_or("Missing structured data id")?;
     let sd = parts.next().ok_or("Missing structured data")?;
@@ -217,15 +228,14 @@ fn parse_sd_data(line: &str, offset: usize) -> Result<(StructuredData, &str, usi
             ('"', false, _, _, _, true) => {
                 in_value = false;
                 let value = unescape_sd_value(&sd[value_start..i]);
-                let pair = (
-                    "_".to_owned()
-                        + name.expect(
-                            "Name in structured data contains an invalid UTF-8 \
-                             sequence",
-                        ),
-                    SDValue::String(value),
+                let sd_name = name.expect(
+                    "Name in structured data contains an invalid UTF-8 \
+                     sequence",
                 );
-                sd_res.pairs.push(pair);
+                let mut key = String::with_capacity(sd_name.len() + 1);
+                key.push('_');
+                key.push_str(sd_name);
+                sd_res.pairs.push((Cow::Owned(key), SDValue::String(value)));
                 name = None;
             }
             (_, _, _, _, _, true) => esc = false,
@@ -245,18 +255,18 @@ fn parse_sd_data(line: &str, offset: usize) -> Result<(StructuredData, &str, usi
 fn test_rfc5424() {
     let msg = r#"<23>1 2015-08-05T15:53:45.637824Z testhostname appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#;
     let res = RFC5424Decoder.decode(msg).unwrap();
-    assert!(res.facility.unwrap() == 2);
-    assert!(res.severity.unwrap() == 7);
-    assert!(res.ts == 1438790025.637824);
+    assert!(res.facility.unwrap() == Facility::Mail);
+    assert!(res.severity.unwrap() == Severity::Debug);
+    assert_eq!(res.ts.unix_nanos(), 1_438_790_025_637_824_000);
     assert!(res.hostname == "testhostname");
-    assert!(res.appname == Some("appname".to_owned()));
-    assert!(res.procid == Some("69".to_owned()));
-    assert!(res.msgid == Some("42".to_owned()));
-    assert!(res.msg == Some("test message".to_owned()));
+    assert!(res.appname == Some("appname".into()));
+    assert!(res.procid == Some("69".into()));
+    assert!(res.msgid == Some("42".into()));
+    assert!(res.msg == Some("test message".into()));
     let sd_vec = res.sd.unwrap();
     assert!(sd_vec.len() == 1);
     let sd = &sd_vec[0];
-    assert!(sd.sd_id == Some("origin@123".to_owned()));
+    assert!(sd.sd_id == Some("origin@123".into()));
     let pairs = &sd.pairs;
 
     assert!(pairs
@@ -277,22 +287,33 @@ fn test_rfc5424() {
         }));
 }
 
+#[test]
+fn test_rfc5424_invalid_pri() {
+    // PRI values are at most 191, facilities 24 to 31 don't exist
+    let msg = "<191>1 2015-08-05T15:53:45Z testhostname appname 69 42 - test message";
+    let res = RFC5424Decoder.decode(msg).unwrap();
+    assert_eq!(res.facility, Some(Facility::Local7));
+    assert_eq!(res.severity, Some(Severity::Debug));
+    let msg = "<192>1 2015-08-05T15:53:45Z testhostname appname 69 42 - test message";
+    assert_eq!(RFC5424Decoder.decode(msg).unwrap_err(), "Invalid priority");
+}
+
 #[test]
 fn test_rfc5424_multiple_sd() {
     let msg = r#"<23>1 2015-08-05T15:53:45.637824Z testhostname appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"][master@456 key="value" key2="value2"] test message"#;
     let res = RFC5424Decoder.decode(msg).unwrap();
-    assert!(res.facility.unwrap() == 2);
-    assert!(res.severity.unwrap() == 7);
-    assert!(res.ts == 1438790025.637824);
+    assert!(res.facility.unwrap() == Facility::Mail);
+    assert!(res.severity.unwrap() == Severity::Debug);
+    assert_eq!(res.ts.unix_nanos(), 1_438_790_025_637_824_000);
     assert!(res.hostname == "testhostname");
-    assert!(res.appname == Some("appname".to_owned()));
-    assert!(res.procid == Some("69".to_owned()));
-    assert!(res.msgid == Some("42".to_owned()));
-    assert!(res.msg == Some("test message".to_owned()));
+    assert!(res.appname == Some("appname".into()));
+    assert!(res.procid == Some("69".into()));
+    assert!(res.msgid == Some("42".into()));
+    assert!(res.msg == Some("test message".into()));
     let sd_vec = res.sd.unwrap();
     assert!(sd_vec.len() == 2);
     let sd = &sd_vec[0];
-    assert!(sd.sd_id == Some("origin@123".to_owned()));
+    assert!(sd.sd_id == Some("origin@123".into()));
     let pairs = &sd.pairs;
 
     assert!(pairs
@@ -312,3 +333,44 @@ fn test_rfc5424_multiple_sd() {
             false
         }));
 }
+
+#[test]
+fn test_rfc5424_sd_without_msg() {
+    let msg = r#"<110>1 2015-08-05T15:53:45.637824Z testhostname appname - - [ssign VER="0121" RSID="1"]"#;
+    let res = RFC5424Decoder.decode(msg).unwrap();
+    assert!(res.msg.is_none());
+    assert_eq!(res.field("RSID"), Some("1".to_owned()));
+}
+
+#[test]
+fn test_rfc5424_utc_offset() {
+    let msg = "<23>1 2015-08-05T17:53:45.637824+02:00 testhostname appname - - - test message";
+    let res = RFC5424Decoder.decode(msg).unwrap();
+    assert_eq!(res.ts.unix_nanos(), 1_438_790_025_637_824_000);
+    assert_eq!(res.utc_offset, Some(UtcOffset::from_hms(2, 0, 0).unwrap()));
+}
+
+#[test]
+fn test_rfc5424_unescape_sd_value() {
+    assert_eq!(unescape_sd_value("plain value"), "plain value");
+    assert_eq!(unescape_sd_value(r#"te\st sc\"ript"#), r#"te\st sc"ript"#);
+    assert_eq!(unescape_sd_value(r#"a\\b\]c"#), r#"a\b]c"#);
+}
+
+#[test]
+fn test_rfc5424_borrows_line() {
+    let msg = r#"<23>1 2015-08-05T15:53:45.637824Z testhostname appname 69 42 [origin@123 software="te\st" swVersion="0.0.1"] test message"#;
+    let res = RFC5424Decoder.decode(msg).unwrap();
+    assert!(matches!(res.hostname, Cow::Borrowed("testhostname")));
+    assert!(matches!(res.appname, Some(Cow::Borrowed("appname"))));
+    assert!(matches!(res.msg, Some(Cow::Borrowed("test message"))));
+    let sd = &res.sd.as_ref().unwrap()[0];
+    assert!(matches!(sd.sd_id, Some(Cow::Borrowed("origin@123"))));
+    assert!(matches!(sd.pairs[0].1, SDValue::String(Cow::Owned(_))));
+    assert!(matches!(
+        sd.pairs[1].1,
+        SDValue::String(Cow::Borrowed("0.0.1"))
+    ));
+    let res = res.into_owned();
+    assert_eq!(res.msg.as_deref(), Some("test message"));
+}
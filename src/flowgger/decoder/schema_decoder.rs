@@ -0,0 +1,223 @@
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue, SDValueType};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+/// What records must look like
+struct Schema {
+    required: Vec<String>,
+    types: Vec<(String, SDValueType)>,
+    max_lengths: Vec<(String, usize)>,
+}
+
+/// Settings of the schema validation, in the `[input]` section
+#[derive(Deserialize)]
+pub struct SchemaSettings {
+    #[serde(default)]
+    schema_required: Vec<String>,
+    #[serde(default)]
+    schema_types: BTreeMap<String, String>,
+    #[serde(default)]
+    schema_max_lengths: BTreeMap<String, usize>,
+}
+
+/// Decoder wrapper validating records against a schema, so that records strongly-typed stores (ClickHouse,
+/// BigQuery...) would reject are caught when they are received. Records are validated once every transformation
+/// (script, plugin, tenant...) has been applied to them, as they will be written.
+///
+/// Records that don't match are rejected, and are written to the dead letter sink along with the violation if
+/// 'input.dead_letter' is set. Fields are header fields ("hostname", "appname", "msg"...) or structured data
+/// pairs, the leading '_' of the pair names being optional.
+pub struct SchemaDecoder {
+    decoder: Box<dyn Decoder + Send>,
+    schema: Arc<Schema>,
+}
+
+impl Clone for SchemaDecoder {
+    fn clone(&self) -> SchemaDecoder {
+        SchemaDecoder {
+            decoder: self.decoder.clone_boxed(),
+            schema: Arc::clone(&self.schema),
+        }
+    }
+}
+
+impl SchemaDecoder {
+    /// # Parameters
+    /// - 'input.schema_required':    Optional. Fields every record must have.
+    /// - 'input.schema_types':       Optional. Table of structured data pairs and their type, "string", "bool",
+    ///   "f64", "i64" or "u64". Strings holding a value of the type, and integers when "f64" is expected, are
+    ///   converted.
+    /// - 'input.schema_max_lengths': Optional. Table of fields and their maximum length, in bytes.
+    ///
+    /// # Returns
+    /// The decoder as is if no schema is set, or wrapped so that it validates the records
+    pub fn wrap(config: &Config, decoder: Box<dyn Decoder + Send>) -> Box<dyn Decoder + Send> {
+        let settings: SchemaSettings = config.settings("input");
+        let required = settings.schema_required;
+        let mut types = Vec::new();
+        for (name, sdtype) in settings.schema_types {
+            let sdtype = match sdtype.to_lowercase().as_ref() {
+                "string" => SDValueType::String,
+                "bool" => SDValueType::Bool,
+                "f64" => SDValueType::F64,
+                "i64" => SDValueType::I64,
+                "u64" => SDValueType::U64,
+                _ => panic!("Unsupported type in input.schema_types for name [{}]", name),
+            };
+            types.push((name, sdtype));
+        }
+        let max_lengths: Vec<(String, usize)> = settings.schema_max_lengths.into_iter().collect();
+        if required.is_empty() && types.is_empty() && max_lengths.is_empty() {
+            return decoder;
+        }
+        Box::new(SchemaDecoder {
+            decoder,
+            schema: Arc::new(Schema {
+                required,
+                types,
+                max_lengths,
+            }),
+        })
+    }
+}
+
+impl Schema {
+    fn validate<'a>(&self, mut record: Record<'a>) -> Result<Record<'a>, &'static str> {
+        for (key, value) in record
+            .sd
+            .iter_mut()
+            .flatten()
+            .flat_map(|sd| sd.pairs.iter_mut())
+        {
+            let name = key.strip_prefix('_').unwrap_or(key);
+            if let Some((_, sdtype)) = self
+                .types
+                .iter()
+                .find(|(typed, _)| typed == key || typed == name)
+            {
+                *value = convert(sdtype, value)?;
+            }
+        }
+        for name in &self.required {
+            if field_len(&record, name).is_none() {
+                return Err("Schema violation; a required field is missing");
+            }
+        }
+        for (name, max_length) in &self.max_lengths {
+            if field_len(&record, name).is_some_and(|len| len > *max_length) {
+                return Err("Schema violation; a field exceeds its maximum length");
+            }
+        }
+        Ok(record)
+    }
+}
+
+/// Length of a field, if the record has it
+fn field_len(record: &Record, name: &str) -> Option<usize> {
+    match name {
+        "msg" => record.msg.as_deref().map(str::len),
+        "full_msg" => record.full_msg.as_deref().map(str::len),
+        _ => record.field(name).map(|value| value.len()),
+    }
+}
+
+/// Convert a value to the expected type, if it holds one
+fn convert<'a>(sdtype: &SDValueType, value: &SDValue<'a>) -> Result<SDValue<'a>, &'static str> {
+    let converted = match (sdtype, value) {
+        (_, SDValue::Null) => Some(SDValue::Null),
+        (SDValueType::String, SDValue::String(value)) => Some(SDValue::String(value.clone())),
+        (SDValueType::Bool, SDValue::Bool(value)) => Some(SDValue::Bool(*value)),
+        (SDValueType::Bool, SDValue::String(value)) => value.parse().ok().map(SDValue::Bool),
+        (SDValueType::F64, SDValue::F64(value)) => Some(SDValue::F64(*value)),
+        (SDValueType::F64, SDValue::I64(value)) => Some(SDValue::F64(*value as f64)),
+        (SDValueType::F64, SDValue::U64(value)) => Some(SDValue::F64(*value as f64)),
+        (SDValueType::F64, SDValue::String(value)) => value.parse().ok().map(SDValue::F64),
+        (SDValueType::I64, SDValue::I64(value)) => Some(SDValue::I64(*value)),
+        (SDValueType::I64, SDValue::U64(value)) => i64::try_from(*value).ok().map(SDValue::I64),
+        (SDValueType::I64, SDValue::String(value)) => value.parse().ok().map(SDValue::I64),
+        (SDValueType::U64, SDValue::U64(value)) => Some(SDValue::U64(*value)),
+        (SDValueType::U64, SDValue::I64(value)) => u64::try_from(*value).ok().map(SDValue::U64),
+        (SDValueType::U64, SDValue::String(value)) => value.parse().ok().map(SDValue::U64),
+        _ => None,
+    };
+    converted.ok_or(match sdtype {
+        SDValueType::String => "Schema violation; string was expected",
+        SDValueType::Bool => "Schema violation; boolean was expected",
+        SDValueType::F64 => "Schema violation; f64 was expected",
+        SDValueType::I64 => "Schema violation; i64 was expected",
+        SDValueType::U64 => "Schema violation; u64 was expected",
+    })
+}
+
+impl Decoder for SchemaDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        self.schema.validate(self.decoder.decode(line)?)
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        self.schema.validate(self.decoder.decode_bytes(line)?)
+    }
+}
+
+#[cfg(all(test, feature = "rfc5424"))]
+mod tests {
+    use super::*;
+    use crate::flowgger::decoder::RFC5424Decoder;
+
+    fn decoder() -> Box<dyn Decoder + Send> {
+        let config = Config::from_string(
+            r#"
+[input]
+schema_required = [ "appname", "msg", "user_id" ]
+[input.schema_types]
+user_id = "u64"
+latency = "f64"
+[input.schema_max_lengths]
+msg = 10
+"#,
+        )
+        .unwrap();
+        SchemaDecoder::wrap(&config, Box::new(RFC5424Decoder::new(&config)))
+    }
+
+    #[test]
+    fn test_schema_valid() {
+        let record = decoder()
+            .decode(r#"<23>1 2015-08-05T15:53:45Z h appname 69 42 [origin@123 user_id="42" latency="1.5"] short"#)
+            .unwrap();
+        let pairs = &record.sd.unwrap()[0].pairs;
+        assert!(matches!(pairs[0].1, SDValue::U64(42)));
+        assert!(matches!(pairs[1].1, SDValue::F64(latency) if latency == 1.5));
+    }
+
+    #[test]
+    fn test_schema_violations() {
+        let decoder = decoder();
+        assert_eq!(
+            decoder
+                .decode(
+                    r#"<23>1 2015-08-05T15:53:45Z h appname 69 42 [origin@123 latency="1.5"] short"#
+                )
+                .unwrap_err(),
+            "Schema violation; a required field is missing"
+        );
+        assert_eq!(
+            decoder
+                .decode(
+                    r#"<23>1 2015-08-05T15:53:45Z h appname 69 42 [origin@123 user_id="-1"] short"#
+                )
+                .unwrap_err(),
+            "Schema violation; u64 was expected"
+        );
+        assert_eq!(
+            decoder
+                .decode(r#"<23>1 2015-08-05T15:53:45Z h appname 69 42 [origin@123 user_id="42"] a longer message"#)
+                .unwrap_err(),
+            "Schema violation; a field exceeds its maximum length"
+        );
+    }
+}
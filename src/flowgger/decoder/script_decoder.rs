@@ -0,0 +1,399 @@
+use super::{Decoder, DROPPED};
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Facility, Record, SDValue, Severity, StructuredData, Timestamp};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{stderr, Write};
+use std::num::NonZeroU64;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const DEFAULT_SCRIPT_MAX_OPERATIONS: u64 = 100_000;
+const DEFAULT_SCRIPT_TIMEOUT: u64 = 10;
+const SCRIPT_MAX_CALL_LEVELS: usize = 32;
+const SCRIPT_MAX_STRING_SIZE: usize = 1024 * 1024;
+const SCRIPT_MAX_COLLECTION_SIZE: usize = 10_000;
+
+thread_local! {
+    /// Time after which the script running on this thread is stopped
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// A compiled script, and the engine running it
+struct Script {
+    engine: Engine,
+    ast: AST,
+    timeout: Duration,
+}
+
+/// Settings of the script, in the `[input]` section
+#[derive(Deserialize)]
+pub struct ScriptSettings {
+    script: Option<String>,
+    script_max_operations: Option<NonZeroU64>,
+    script_timeout: Option<NonZeroU64>,
+}
+
+/// Decoder wrapper running a Rhai script on every record, to transform records in ways the settings don't
+/// cover without recompiling flowgger.
+///
+/// The script gets the record as the `record` object map, with the `ts`, `hostname`, `facility`, `severity`,
+/// `appname`, `procid`, `msgid`, `msg` and `full_msg` fields, and `sd`, an array of `#{ id, pairs }` maps.
+/// Missing fields are `()`. The record is replaced with `record` as the script left it, or dropped if the
+/// script evaluates to `false`, i.e.:
+///
+/// ```rhai
+/// if record.appname == "healthcheck" { return false; }
+/// record.sd[0].pairs.env = "prod";
+/// ```
+///
+/// Scripts are sandboxed: they can't import modules, read files or use `eval`, and are stopped after a
+/// number of operations or a timeout, the record being rejected then.
+pub struct ScriptDecoder {
+    decoder: Box<dyn Decoder + Send>,
+    script: Arc<Script>,
+}
+
+impl Clone for ScriptDecoder {
+    fn clone(&self) -> ScriptDecoder {
+        ScriptDecoder {
+            decoder: self.decoder.clone_boxed(),
+            script: Arc::clone(&self.script),
+        }
+    }
+}
+
+impl ScriptDecoder {
+    /// # Parameters
+    /// - 'input.script':                Optional. Path to a Rhai script run on every record.
+    /// - 'input.script_max_operations': Optional. Maximum number of operations of the script per record.
+    ///   Default is 100000.
+    /// - 'input.script_timeout':        Optional. Maximum time the script can run per record, in
+    ///   milliseconds. Default is 10.
+    ///
+    /// # Returns
+    /// The decoder as is if 'input.script' is not set, or wrapped so that the script transforms the records
+    ///
+    /// # Panics
+    /// `Unable to compile input.script`: the script can't be read, or has a syntax error
+    pub fn wrap(config: &Config, decoder: Box<dyn Decoder + Send>) -> Box<dyn Decoder + Send> {
+        let settings: ScriptSettings = config.settings("input");
+        let path = match settings.script {
+            None => return decoder,
+            Some(ref path) => path,
+        };
+        let max_operations = settings
+            .script_max_operations
+            .map_or(DEFAULT_SCRIPT_MAX_OPERATIONS, NonZeroU64::get);
+        let timeout = settings
+            .script_timeout
+            .map_or(DEFAULT_SCRIPT_TIMEOUT, NonZeroU64::get);
+        let source = fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Unable to compile input.script [{}]: {}", path, e));
+        let script = Script::new(&source, max_operations, Duration::from_millis(timeout))
+            .unwrap_or_else(|e| panic!("Unable to compile input.script [{}]: {}", path, e));
+        Box::new(ScriptDecoder {
+            decoder,
+            script: Arc::new(script),
+        })
+    }
+}
+
+impl Script {
+    fn new(source: &str, max_operations: u64, timeout: Duration) -> Result<Script, String> {
+        let mut engine = Engine::new();
+        engine
+            .set_module_resolver(DummyModuleResolver::new())
+            .disable_symbol("eval")
+            .set_max_operations(max_operations)
+            .set_max_call_levels(SCRIPT_MAX_CALL_LEVELS)
+            .set_max_string_size(SCRIPT_MAX_STRING_SIZE)
+            .set_max_array_size(SCRIPT_MAX_COLLECTION_SIZE)
+            .set_max_map_size(SCRIPT_MAX_COLLECTION_SIZE)
+            .on_print(|text| {
+                let _ = writeln!(stderr(), "{}", text);
+            })
+            .on_debug(|text, _, _| {
+                let _ = writeln!(stderr(), "{}", text);
+            })
+            .on_progress(|_| {
+                let expired = DEADLINE.with(|deadline| {
+                    deadline
+                        .get()
+                        .is_some_and(|deadline| Instant::now() >= deadline)
+                });
+                if expired {
+                    Some(Dynamic::UNIT)
+                } else {
+                    None
+                }
+            });
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        Ok(Script {
+            engine,
+            ast,
+            timeout,
+        })
+    }
+
+    fn run<'a>(&self, record: Record<'a>) -> Result<Record<'a>, &'static str> {
+        let mut scope = Scope::new();
+        scope.push("record", record_to_map(record));
+        DEADLINE.with(|deadline| deadline.set(Some(Instant::now() + self.timeout)));
+        let res = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast);
+        DEADLINE.with(|deadline| deadline.set(None));
+        match res {
+            Ok(res) if res.as_bool() == Ok(false) => return Err(DROPPED),
+            Ok(_) => {}
+            Err(e) => {
+                let _ = writeln!(stderr(), "input.script failed: {}", e);
+                return Err("The script failed");
+            }
+        }
+        scope
+            .get_value::<Map>("record")
+            .ok_or("The script didn't leave record as an object map")
+            .and_then(map_to_record)
+    }
+}
+
+impl Decoder for ScriptDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        self.script.run(self.decoder.decode(line)?)
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        self.script.run(self.decoder.decode_bytes(line)?)
+    }
+}
+
+fn optional<T: Into<Dynamic>>(value: Option<T>) -> Dynamic {
+    value.map_or(Dynamic::UNIT, Into::into)
+}
+
+fn sd_value_to_dynamic(value: SDValue<'_>) -> Dynamic {
+    match value {
+        SDValue::String(value) => value.into_owned().into(),
+        SDValue::Bool(value) => value.into(),
+        SDValue::F64(value) => value.into(),
+        SDValue::I64(value) => value.into(),
+        SDValue::U64(value) => match i64::try_from(value) {
+            Ok(value) => value.into(),
+            Err(_) => (value as f64).into(),
+        },
+        SDValue::Null => Dynamic::UNIT,
+    }
+}
+
+fn dynamic_to_sd_value(value: Dynamic) -> SDValue<'static> {
+    if value.is_unit() {
+        SDValue::Null
+    } else if let Ok(value) = value.as_bool() {
+        SDValue::Bool(value)
+    } else if let Ok(value) = value.as_int() {
+        SDValue::I64(value)
+    } else if let Ok(value) = value.as_float() {
+        SDValue::F64(value)
+    } else {
+        SDValue::String(dynamic_to_string(value).into())
+    }
+}
+
+fn dynamic_to_string(value: Dynamic) -> String {
+    value
+        .into_string()
+        .unwrap_or_else(|type_name| type_name.to_owned())
+}
+
+fn record_to_map(record: Record<'_>) -> Map {
+    let mut map = Map::new();
+    map.insert("ts".into(), record.ts.as_secs_f64().into());
+    map.insert("hostname".into(), record.hostname.into_owned().into());
+    map.insert(
+        "facility".into(),
+        optional(record.facility.map(|facility| facility as i64)),
+    );
+    map.insert(
+        "severity".into(),
+        optional(record.severity.map(|severity| severity as i64)),
+    );
+    map.insert(
+        "appname".into(),
+        optional(record.appname.map(Cow::into_owned)),
+    );
+    map.insert(
+        "procid".into(),
+        optional(record.procid.map(Cow::into_owned)),
+    );
+    map.insert("msgid".into(), optional(record.msgid.map(Cow::into_owned)));
+    map.insert("msg".into(), optional(record.msg.map(Cow::into_owned)));
+    map.insert(
+        "full_msg".into(),
+        optional(record.full_msg.map(Cow::into_owned)),
+    );
+    let sd: Array = record
+        .sd
+        .into_iter()
+        .flatten()
+        .map(|sd| {
+            let mut sd_map = Map::new();
+            sd_map.insert("id".into(), optional(sd.sd_id.map(Cow::into_owned)));
+            let pairs: Map = sd
+                .pairs
+                .into_iter()
+                .map(|(key, value)| (key.into_owned().into(), sd_value_to_dynamic(value)))
+                .collect();
+            sd_map.insert("pairs".into(), pairs.into());
+            sd_map.into()
+        })
+        .collect();
+    map.insert("sd".into(), sd.into());
+    map
+}
+
+fn map_to_record(mut map: Map) -> Result<Record<'static>, &'static str> {
+    let mut take = |name: &str| map.remove(name).unwrap_or(Dynamic::UNIT);
+    let string = |value: Dynamic| {
+        if value.is_unit() {
+            None
+        } else {
+            Some(Cow::Owned(dynamic_to_string(value)))
+        }
+    };
+    fn code<T: TryFrom<u8>>(
+        value: Dynamic,
+        error: &'static str,
+    ) -> Result<Option<T>, &'static str> {
+        if value.is_unit() {
+            return Ok(None);
+        }
+        value
+            .as_int()
+            .ok()
+            .and_then(|code| u8::try_from(code).ok())
+            .and_then(|code| T::try_from(code).ok())
+            .map(Some)
+            .ok_or(error)
+    }
+    let ts = take("ts");
+    let ts = ts
+        .as_float()
+        .or_else(|_| ts.as_int().map(|ts| ts as f64))
+        .map(Timestamp::from_secs_f64)
+        .map_err(|_| "The script set an invalid timestamp")?;
+    let hostname = string(take("hostname")).unwrap_or_default();
+    let facility = code::<Facility>(take("facility"), "The script set an invalid facility")?;
+    let severity = code::<Severity>(take("severity"), "The script set an invalid severity")?;
+    let appname = string(take("appname"));
+    let procid = string(take("procid"));
+    let msgid = string(take("msgid"));
+    let msg = string(take("msg"));
+    let full_msg = string(take("full_msg"));
+    let sd = take("sd");
+    let sd = if sd.is_unit() {
+        None
+    } else {
+        let sd = sd
+            .try_cast::<Array>()
+            .ok_or("The script set structured data that is not an array")?;
+        let sd = sd
+            .into_iter()
+            .map(|sd| {
+                let mut sd = sd
+                    .try_cast::<Map>()
+                    .ok_or("The script set structured data that is not an object map")?;
+                let sd_id = sd.remove("id").and_then(string);
+                let pairs = match sd.remove("pairs") {
+                    None => Vec::new(),
+                    Some(pairs) => pairs
+                        .try_cast::<Map>()
+                        .ok_or("The script set structured data pairs that are not an object map")?
+                        .into_iter()
+                        .map(|(key, value)| (key.to_string().into(), dynamic_to_sd_value(value)))
+                        .collect(),
+                };
+                Ok(StructuredData { sd_id, pairs })
+            })
+            .collect::<Result<Vec<_>, &'static str>>()?;
+        if sd.is_empty() {
+            None
+        } else {
+            Some(sd)
+        }
+    };
+    Ok(Record {
+        ts,
+        utc_offset: None,
+        hostname,
+        facility,
+        severity,
+        appname,
+        procid,
+        msgid,
+        msg,
+        full_msg,
+        sd,
+    })
+}
+
+#[cfg(all(test, feature = "rfc5424"))]
+mod tests {
+    use super::*;
+    use crate::flowgger::decoder::RFC5424Decoder;
+
+    const LINE: &str = r#"<23>1 2015-08-05T15:53:45Z testhostname appname 69 42 [origin@123 ip="192.0.2.1"] password=secret"#;
+
+    fn run(source: &str) -> Result<Record<'static>, &'static str> {
+        let config = Config::from_string("").unwrap();
+        let script = Script::new(source, 10_000, Duration::from_millis(100)).unwrap();
+        script.run(RFC5424Decoder::new(&config).decode(LINE).unwrap())
+    }
+
+    #[test]
+    fn test_script_transform() {
+        let record = run(r#"
+            record.msg.replace("secret", "***");
+            record.severity = 3;
+            record.sd[0].pairs.env = "prod";
+            record.sd[0].pairs.remove("_ip");
+        "#)
+        .unwrap();
+        assert_eq!(record.msg.as_deref(), Some("password=***"));
+        assert_eq!(record.severity, Some(Severity::Error));
+        assert_eq!(record.hostname, "testhostname");
+        assert_eq!(record.field("env").as_deref(), Some("prod"));
+        assert_eq!(record.field("ip"), None);
+
+        // A record that is left as is
+        let record = run("").unwrap();
+        assert_eq!(record.field("ip").as_deref(), Some("192.0.2.1"));
+        assert_eq!(record.procid.as_deref(), Some("69"));
+    }
+
+    #[test]
+    fn test_script_drop() {
+        assert_eq!(run(r#"record.appname != "appname""#).unwrap_err(), DROPPED);
+        assert!(run(r#"record.appname == "appname""#).is_ok());
+    }
+
+    #[test]
+    fn test_script_limits() {
+        assert_eq!(run("loop {}").unwrap_err(), "The script failed");
+        assert_eq!(
+            run("record.severity = 12;").unwrap_err(),
+            "The script set an invalid severity"
+        );
+        assert_eq!(
+            run(r#"import "os" as os;"#).unwrap_err(),
+            "The script failed"
+        );
+        assert!(Script::new(r#"eval("1")"#, 10_000, Duration::from_millis(100)).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue};
+use serde::Deserialize;
+
+/// Settings of the structured data limits, in the `[input]` section
+#[derive(Deserialize)]
+pub struct SdLimitSettings {
+    sd_max_pairs: Option<usize>,
+    sd_max_value_length: Option<usize>,
+    sd_limit_policy: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SdLimitPolicy {
+    Truncate,
+    Drop,
+}
+
+/// Decoder wrapper capping the number of structured data pairs of a record and the length of their values, so
+/// that a sender can't create records with thousands of additional fields, or huge values, that downstream
+/// systems would reject or choke on. Records rejected with the "drop" policy go to the dead letter sink, if any.
+pub struct SdLimitDecoder {
+    decoder: Box<dyn Decoder + Send>,
+    max_pairs: Option<usize>,
+    max_value_length: Option<usize>,
+    policy: SdLimitPolicy,
+}
+
+impl Clone for SdLimitDecoder {
+    fn clone(&self) -> SdLimitDecoder {
+        SdLimitDecoder {
+            decoder: self.decoder.clone_boxed(),
+            max_pairs: self.max_pairs,
+            max_value_length: self.max_value_length,
+            policy: self.policy,
+        }
+    }
+}
+
+impl SdLimitDecoder {
+    /// # Parameters
+    /// - 'input.sd_max_pairs':        Optional. Maximum number of structured data pairs of a record, across
+    ///   every structured data element. Not limited when this is not set.
+    /// - 'input.sd_max_value_length': Optional. Maximum length, in bytes, of a structured data value. Not limited
+    ///   when this is not set.
+    /// - 'input.sd_limit_policy':     Optional. "truncate" to remove the extra pairs and shorten the values,
+    ///   or "drop" to reject the records over the limits. Default is "truncate".
+    ///
+    /// # Returns
+    /// The decoder as is if no limit is set, or wrapped so that it enforces them
+    pub fn wrap(config: &Config, decoder: Box<dyn Decoder + Send>) -> Box<dyn Decoder + Send> {
+        let settings: SdLimitSettings = config.settings("input");
+        let max_pairs = settings.sd_max_pairs;
+        let max_value_length = settings.sd_max_value_length;
+        let policy = match settings.sd_limit_policy.as_deref().unwrap_or("truncate") {
+            "truncate" => SdLimitPolicy::Truncate,
+            "drop" => SdLimitPolicy::Drop,
+            _ => panic!(r#"input.sd_limit_policy must be "truncate" or "drop""#),
+        };
+        if max_pairs.is_none() && max_value_length.is_none() {
+            return decoder;
+        }
+        Box::new(SdLimitDecoder {
+            decoder,
+            max_pairs,
+            max_value_length,
+            policy,
+        })
+    }
+
+    fn limit<'a>(&self, mut record: Record<'a>) -> Result<Record<'a>, &'static str> {
+        let sd = match record.sd.as_mut() {
+            None => return Ok(record),
+            Some(sd) => sd,
+        };
+        let mut remaining = self.max_pairs.unwrap_or(usize::MAX);
+        for sd in sd.iter_mut() {
+            if sd.pairs.len() > remaining {
+                if self.policy == SdLimitPolicy::Drop {
+                    return Err("Too many structured data pairs");
+                }
+                sd.pairs.truncate(remaining);
+            }
+            remaining -= sd.pairs.len();
+            let max_value_length = match self.max_value_length {
+                None => continue,
+                Some(max_value_length) => max_value_length,
+            };
+            for (_, value) in sd.pairs.iter_mut() {
+                if let SDValue::String(value) = value {
+                    if value.len() <= max_value_length {
+                        continue;
+                    }
+                    if self.policy == SdLimitPolicy::Drop {
+                        return Err("Structured data value too long");
+                    }
+                    let mut len = max_value_length;
+                    while !value.is_char_boundary(len) {
+                        len -= 1;
+                    }
+                    value.to_mut().truncate(len);
+                }
+            }
+        }
+        Ok(record)
+    }
+}
+
+impl Decoder for SdLimitDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        self.limit(self.decoder.decode(line)?)
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        self.limit(self.decoder.decode_bytes(line)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::record::StructuredData;
+    use crate::flowgger::utils::test_utils::record_test_utils::TestDecoder;
+
+    /// Test decoder adding two structured data elements
+    #[derive(Clone)]
+    struct SdDecoder;
+
+    impl Decoder for SdDecoder {
+        fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+            let sd = |sd_id: &'static str, pairs: &[(&'static str, &'static str)]| StructuredData {
+                sd_id: Some(sd_id.into()),
+                pairs: pairs
+                    .iter()
+                    .map(|(name, value)| ((*name).into(), SDValue::String((*value).into())))
+                    .collect(),
+            };
+            Ok(Record {
+                sd: Some(vec![
+                    sd("a@1", &[("_a", "short"), ("_b", "été est long")]),
+                    sd("b@1", &[("_c", "1"), ("_d", "2")]),
+                ]),
+                ..TestDecoder.decode(line)?
+            })
+        }
+    }
+
+    #[test]
+    fn test_sd_limit_decoder() {
+        let config =
+            Config::from_string("[input]\nsd_max_pairs = 3\nsd_max_value_length = 6\n").unwrap();
+        let record = SdLimitDecoder::wrap(&config, Box::new(SdDecoder))
+            .decode("")
+            .unwrap();
+        let sd = record.sd.as_ref().unwrap();
+        assert_eq!(sd[0].to_string(), r#"[a@1 a="short" b="été "]"#);
+        assert_eq!(sd[1].to_string(), r#"[b@1 c="1"]"#);
+
+        let config = Config::from_string(
+            "[input]\nsd_max_pairs = 4\nsd_max_value_length = 16\nsd_limit_policy = \"drop\"\n",
+        )
+        .unwrap();
+        assert!(SdLimitDecoder::wrap(&config, Box::new(SdDecoder))
+            .decode("")
+            .is_ok());
+        let config =
+            Config::from_string("[input]\nsd_max_pairs = 3\nsd_limit_policy = \"drop\"\n").unwrap();
+        assert_eq!(
+            SdLimitDecoder::wrap(&config, Box::new(SdDecoder))
+                .decode("")
+                .unwrap_err(),
+            "Too many structured data pairs"
+        );
+    }
+}
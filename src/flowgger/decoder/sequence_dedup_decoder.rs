@@ -0,0 +1,286 @@
+use super::{Decoder, DROPPED};
+use crate::flowgger::admin;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_DEDUP_MAX_SOURCES: usize = 10_000;
+const META_SD_ID: &str = "meta";
+
+/// Settings of the deduplication, in the `[input]` section
+#[derive(Deserialize)]
+pub struct DedupSettings {
+    dedup_window: Option<NonZeroUsize>,
+    dedup_max_sources: Option<NonZeroUsize>,
+}
+
+/// Sender of a sequence: the peer address, and the originator, as a relay forwards the records of several
+/// originators that number their records independently. The process id tells a restarted originator, whose
+/// numbering starts over, from the previous one.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct Source {
+    peer: IpAddr,
+    hostname: String,
+    appname: Option<String>,
+    procid: Option<String>,
+}
+
+/// The last sequence ids seen from a source
+#[derive(Default)]
+struct Window {
+    seen: HashSet<u64>,
+    order: VecDeque<u64>,
+    last_used: u64,
+}
+
+struct Sources {
+    windows: HashMap<Source, Window>,
+    uses: u64,
+}
+
+struct DedupState {
+    sources: Mutex<Sources>,
+    window: usize,
+    max_sources: usize,
+    duplicates: AtomicU64,
+}
+
+impl DedupState {
+    /// Remember `seq` for `source`
+    ///
+    /// # Returns
+    /// `false` if it was already among the last sequence ids of the source
+    fn insert(&self, source: Source, seq: u64) -> bool {
+        let mut sources = self.sources.lock().unwrap();
+        sources.uses += 1;
+        let uses = sources.uses;
+        if !sources.windows.contains_key(&source) && sources.windows.len() >= self.max_sources {
+            let oldest = sources
+                .windows
+                .iter()
+                .min_by_key(|(_, window)| window.last_used)
+                .map(|(source, _)| source.clone());
+            if let Some(oldest) = oldest {
+                sources.windows.remove(&oldest);
+            }
+        }
+        let window = sources.windows.entry(source).or_default();
+        window.last_used = uses;
+        if !window.seen.insert(seq) {
+            return false;
+        }
+        window.order.push_back(seq);
+        if window.order.len() > self.window {
+            if let Some(expired) = window.order.pop_front() {
+                window.seen.remove(&expired);
+            }
+        }
+        true
+    }
+}
+
+/// Duplicate detection shared by the connections of an input, for senders that replay their last records
+/// when they reconnect, i.e. after a connection broke in the middle of a syslen frame. Records are recognized
+/// by the `sequenceId` of their RFC5424 `meta` structured data; records without one are never dropped.
+#[derive(Clone)]
+pub struct SequenceDedup {
+    state: Option<Arc<DedupState>>,
+}
+
+impl SequenceDedup {
+    /// # Parameters
+    /// - 'input.dedup_window':      Optional. Number of most recent sequence ids remembered per sender, records
+    ///   with one of them being dropped as duplicates. Duplicates are only detected when this is set.
+    /// - 'input.dedup_max_sources': Optional. Maximum number of senders tracked, the least recently seen being
+    ///   forgotten beyond it. Default is 10000.
+    pub fn new(config: &Config) -> SequenceDedup {
+        let settings: DedupSettings = config.settings("input");
+        let window = match settings.dedup_window {
+            None => return SequenceDedup { state: None },
+            Some(window) => window.get(),
+        };
+        let max_sources = settings
+            .dedup_max_sources
+            .map_or(DEFAULT_DEDUP_MAX_SOURCES, NonZeroUsize::get);
+        let state = Arc::new(DedupState {
+            sources: Mutex::new(Sources {
+                windows: HashMap::new(),
+                uses: 0,
+            }),
+            window,
+            max_sources,
+            duplicates: AtomicU64::new(0),
+        });
+        let admin_state = Arc::clone(&state);
+        admin::register_stats(move || {
+            vec![(
+                "dedup.duplicates".to_owned(),
+                admin_state.duplicates.load(Ordering::Relaxed),
+            )]
+        });
+        SequenceDedup { state: Some(state) }
+    }
+
+    /// # Returns
+    /// The decoder of a connection from `peer` as is if duplicates are not detected or the peer is unknown, or
+    /// wrapped so that it drops the duplicates
+    pub fn wrap(
+        &self,
+        peer: Option<IpAddr>,
+        decoder: Box<dyn Decoder + Send>,
+    ) -> Box<dyn Decoder + Send> {
+        match (&self.state, peer) {
+            (Some(state), Some(peer)) => Box::new(SequenceDedupDecoder {
+                decoder,
+                peer,
+                state: Arc::clone(state),
+            }),
+            _ => decoder,
+        }
+    }
+}
+
+/// Decoder wrapper dropping the records of a connection whose sequence id has recently been seen from the
+/// same sender
+pub struct SequenceDedupDecoder {
+    decoder: Box<dyn Decoder + Send>,
+    peer: IpAddr,
+    state: Arc<DedupState>,
+}
+
+impl Clone for SequenceDedupDecoder {
+    fn clone(&self) -> SequenceDedupDecoder {
+        SequenceDedupDecoder {
+            decoder: self.decoder.clone_boxed(),
+            peer: self.peer,
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl SequenceDedupDecoder {
+    fn dedup<'a>(&self, record: Record<'a>) -> Result<Record<'a>, &'static str> {
+        let seq = match sequence_id(&record) {
+            None => return Ok(record),
+            Some(seq) => seq,
+        };
+        let source = Source {
+            peer: self.peer,
+            hostname: record.hostname.to_string(),
+            appname: record.appname.as_deref().map(str::to_owned),
+            procid: record.procid.as_deref().map(str::to_owned),
+        };
+        if self.state.insert(source, seq) {
+            Ok(record)
+        } else {
+            self.state.duplicates.fetch_add(1, Ordering::Relaxed);
+            Err(DROPPED)
+        }
+    }
+}
+
+impl Decoder for SequenceDedupDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        self.dedup(self.decoder.decode(line)?)
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        self.dedup(self.decoder.decode_bytes(line)?)
+    }
+}
+
+/// The `sequenceId` of the `meta` structured data of a record, if it has one
+fn sequence_id(record: &Record) -> Option<u64> {
+    record
+        .sd
+        .iter()
+        .flatten()
+        .filter(|sd| sd.sd_id.as_deref() == Some(META_SD_ID))
+        .flat_map(|sd| sd.pairs.iter())
+        .find(|(name, _)| name == "_sequenceId" || name == "sequenceId")
+        .and_then(|(_, value)| match value {
+            SDValue::String(value) => value.parse().ok(),
+            SDValue::U64(value) => Some(*value),
+            SDValue::I64(value) => u64::try_from(*value).ok(),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::record::StructuredData;
+    use crate::flowgger::utils::test_utils::record_test_utils::TestDecoder;
+
+    #[derive(Clone)]
+    struct SequenceDecoder;
+
+    impl Decoder for SequenceDecoder {
+        /// "hostname seq", or "hostname" for a record without a sequence id
+        fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+            let mut parts = line.split(' ');
+            let hostname = parts.next().unwrap().into();
+            let sd = parts.next().map(|seq| {
+                let mut sd = StructuredData::new(Some(META_SD_ID));
+                sd.pairs
+                    .push(("_sequenceId".into(), SDValue::String(seq.into())));
+                vec![sd]
+            });
+            Ok(Record {
+                hostname,
+                sd,
+                ..TestDecoder.decode(line)?
+            })
+        }
+    }
+
+    #[test]
+    fn test_sequence_dedup_decoder() {
+        let config = Config::from_string("[input]\ndedup_window = 2\n").unwrap();
+        let dedup = SequenceDedup::new(&config);
+        let peer: IpAddr = "192.0.2.1".parse().unwrap();
+        let decoder = dedup.wrap(Some(peer), Box::new(SequenceDecoder));
+        assert!(decoder.decode("a 1").is_ok());
+        assert!(decoder.decode("a 2").is_ok());
+        assert!(decoder.decode("b 1").is_ok());
+        assert!(decoder.decode("a").is_ok());
+        assert!(decoder.decode("a").is_ok());
+
+        // A new connection from the same peer replaying its last records
+        let decoder = dedup.wrap(Some(peer), Box::new(SequenceDecoder));
+        assert_eq!(decoder.decode("a 2").unwrap_err(), DROPPED);
+        assert!(decoder.decode("a 3").is_ok());
+        assert!(decoder.decode("a 1").is_ok());
+
+        let other = dedup.wrap(
+            Some("192.0.2.2".parse().unwrap()),
+            Box::new(SequenceDecoder),
+        );
+        assert!(other.decode("a 3").is_ok());
+        assert_eq!(dedup.state.unwrap().duplicates.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_sequence_dedup_max_sources() {
+        let config =
+            Config::from_string("[input]\ndedup_window = 10\ndedup_max_sources = 2\n").unwrap();
+        let dedup = SequenceDedup::new(&config);
+        let decoder = dedup.wrap(
+            Some("192.0.2.1".parse().unwrap()),
+            Box::new(SequenceDecoder),
+        );
+        assert!(decoder.decode("a 1").is_ok());
+        assert!(decoder.decode("b 1").is_ok());
+        assert!(decoder.decode("a 2").is_ok());
+        assert!(decoder.decode("c 1").is_ok());
+        // "b" was the least recently seen sender, and has been forgotten
+        assert!(decoder.decode("b 1").is_ok());
+        assert!(decoder.decode("c 1").is_err());
+    }
+}
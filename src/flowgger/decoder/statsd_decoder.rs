@@ -0,0 +1,189 @@
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue, StructuredData, Timestamp};
+
+/// Decoder for statsd and DogStatsD metrics, i.e. `page.views:1|c|@0.5|#env:prod,canary`, so that simple
+/// metrics can be collected along with the logs.
+///
+/// The metric is stored as typed structured data: `_metric` (its name), `_value` (an i64 or a f64, a string
+/// for sets), `_type` ("counter", "gauge", "timer", "histogram", "set" or "distribution"), `_sample_rate`,
+/// and `_delta` for the gauges with a signed value, that are increments. DogStatsD tags are stored as
+/// `_tag_<name>` pairs, tags without a value being `true`, except the `host` tag that sets the hostname.
+/// Records are stamped with the DogStatsD timestamp if there is one, or with the time they are decoded at.
+#[derive(Clone)]
+pub struct StatsdDecoder;
+
+impl StatsdDecoder {
+    pub fn new(_config: &Config) -> StatsdDecoder {
+        StatsdDecoder
+    }
+}
+
+impl Decoder for StatsdDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        let line = line.trim_end();
+        if line.starts_with("_e{") || line.starts_with("_sc|") {
+            return Err("DogStatsD events and service checks are not supported");
+        }
+        let mut record = Record {
+            ts: Timestamp::default(),
+            utc_offset: None,
+            hostname: "-".into(),
+            facility: None,
+            severity: None,
+            appname: None,
+            procid: None,
+            msgid: None,
+            msg: None,
+            full_msg: Some(line.into()),
+            sd: None,
+        };
+        let mut sd = StructuredData::new(None);
+        let mut sections = line.split('|');
+        let (name, value) = sections
+            .next()
+            .and_then(|metric| metric.split_once(':'))
+            .filter(|(name, _)| !name.is_empty())
+            .ok_or("Missing statsd metric name")?;
+        let metric_type = match sections.next() {
+            Some("c") => "counter",
+            Some("g") => "gauge",
+            Some("ms") => "timer",
+            Some("h") => "histogram",
+            Some("s") => "set",
+            Some("d") => "distribution",
+            Some(_) => return Err("Unsupported statsd metric type"),
+            None => return Err("Missing statsd metric type"),
+        };
+        let value = if metric_type == "set" {
+            SDValue::String(value.into())
+        } else {
+            parse_number(value).ok_or("Invalid statsd metric value")?
+        };
+        sd.pairs
+            .push(("_metric".into(), SDValue::String(name.into())));
+        sd.pairs.push(("_value".into(), value));
+        sd.pairs
+            .push(("_type".into(), SDValue::String(metric_type.into())));
+        if metric_type == "gauge" && value_is_signed(line) {
+            sd.pairs.push(("_delta".into(), SDValue::Bool(true)));
+        }
+        let mut ts = None;
+        for section in sections {
+            if let Some(sample_rate) = section.strip_prefix('@') {
+                let sample_rate = sample_rate
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|&sample_rate| sample_rate > 0.0 && sample_rate <= 1.0)
+                    .ok_or("Invalid statsd sample rate")?;
+                sd.pairs
+                    .push(("_sample_rate".into(), SDValue::F64(sample_rate)));
+            } else if let Some(tags) = section.strip_prefix('#') {
+                for tag in tags.split(',').filter(|tag| !tag.is_empty()) {
+                    match tag.split_once(':') {
+                        Some(("host", host)) => record.hostname = host.into(),
+                        Some((name, value)) => sd.pairs.push((
+                            format!("_tag_{}", name).into(),
+                            SDValue::String(value.into()),
+                        )),
+                        None => sd
+                            .pairs
+                            .push((format!("_tag_{}", tag).into(), SDValue::Bool(true))),
+                    }
+                }
+            } else if let Some(container_id) = section.strip_prefix("c:") {
+                sd.pairs
+                    .push(("_container_id".into(), SDValue::String(container_id.into())));
+            } else if let Some(timestamp) = section.strip_prefix('T') {
+                let timestamp = timestamp.parse().or(Err("Invalid statsd timestamp"))?;
+                ts = Some(Timestamp::from_unix_secs(timestamp));
+            }
+            // Other sections are extensions of newer DogStatsD versions, that are ignored
+        }
+        record.ts = ts.unwrap_or_else(Timestamp::now);
+        record.sd = Some(vec![sd]);
+        Ok(record)
+    }
+}
+
+/// An integer if the value is one, a float otherwise
+fn parse_number(value: &str) -> Option<SDValue<'static>> {
+    match value.parse::<i64>() {
+        Ok(value) => Some(SDValue::I64(value)),
+        Err(_) => value
+            .parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite())
+            .map(SDValue::F64),
+    }
+}
+
+/// Whether the value of a metric has an explicit sign
+fn value_is_signed(line: &str) -> bool {
+    line.split_once(':')
+        .is_some_and(|(_, value)| value.starts_with(['+', '-']))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statsd_decode() {
+        let record = StatsdDecoder
+            .decode("page.views:1|c|@0.5|#env:prod,canary,host:web-1|T1656581400\n")
+            .unwrap();
+        assert_eq!(record.ts, Timestamp::from_unix_secs(1_656_581_400));
+        assert_eq!(record.hostname, "web-1");
+        let pairs = &record.sd.unwrap()[0].pairs;
+        assert!(
+            matches!(&pairs[0], (name, SDValue::String(metric)) if name == "_metric" && metric == "page.views")
+        );
+        assert!(matches!(&pairs[1], (name, SDValue::I64(1)) if name == "_value"));
+        assert!(
+            matches!(&pairs[2], (name, SDValue::String(metric_type)) if name == "_type" && metric_type == "counter")
+        );
+        assert!(
+            matches!(&pairs[3], (name, SDValue::F64(rate)) if name == "_sample_rate" && *rate == 0.5)
+        );
+        assert!(
+            matches!(&pairs[4], (name, SDValue::String(env)) if name == "_tag_env" && env == "prod")
+        );
+        assert!(matches!(&pairs[5], (name, SDValue::Bool(true)) if name == "_tag_canary"));
+        assert_eq!(pairs.len(), 6);
+    }
+
+    #[test]
+    fn test_statsd_decode_types() {
+        let record = StatsdDecoder.decode("queue.depth:-1.5|g").unwrap();
+        assert!(record.ts > Timestamp::default());
+        assert_eq!(record.field("type"), Some("gauge".into()));
+        assert_eq!(record.field("value"), Some("-1.5".into()));
+        assert_eq!(record.field("delta"), Some("true".into()));
+
+        let record = StatsdDecoder.decode("users.unique:alice|s").unwrap();
+        assert_eq!(record.field("type"), Some("set".into()));
+        assert_eq!(record.field("value"), Some("alice".into()));
+
+        assert_eq!(
+            StatsdDecoder.decode("latency:abc|ms").unwrap_err(),
+            "Invalid statsd metric value"
+        );
+        assert_eq!(
+            StatsdDecoder.decode("latency:12|x").unwrap_err(),
+            "Unsupported statsd metric type"
+        );
+        assert_eq!(
+            StatsdDecoder.decode("latency:12").unwrap_err(),
+            "Missing statsd metric type"
+        );
+        assert_eq!(
+            StatsdDecoder.decode("latency:12|ms|@2").unwrap_err(),
+            "Invalid statsd sample rate"
+        );
+        assert_eq!(
+            StatsdDecoder.decode("_e{5,4}:title|text").unwrap_err(),
+            "DogStatsD events and service checks are not supported"
+        );
+    }
+}
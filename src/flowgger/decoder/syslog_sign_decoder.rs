@@ -0,0 +1,246 @@
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue, StructuredData};
+use openssl::base64;
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::{PKey, Public};
+use openssl::sign::Verifier;
+use openssl::x509::X509;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::num::NonZeroUsize;
+use std::str;
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_SYSLOG_SIGN_WINDOW: usize = 100_000;
+/// Maximum number of signature groups whose block counter is tracked
+const MAX_SIGNATURE_GROUPS: usize = 10_000;
+pub const SYSLOG_SIGN_SD_ID: &str = "ssign-verify@32473";
+
+/// Settings of the signature verification, in the `[input]` section
+#[derive(Deserialize)]
+pub struct SyslogSignSettings {
+    syslog_sign_key: Option<String>,
+    syslog_sign_window: Option<NonZeroUsize>,
+}
+
+/// Messages received lately, and the last block of every signature group
+struct VerifyState {
+    /// SHA1 and SHA256 hashes of the last messages, oldest first
+    messages: VecDeque<(Vec<u8>, Vec<u8>)>,
+    /// Number of the last messages having every hash
+    hashes: HashMap<Vec<u8>, u32>,
+    /// Last global block counter, by hostname, reboot session ID and signature group
+    groups: HashMap<(String, String, String), u64>,
+}
+
+struct SignVerifier {
+    key: PKey<Public>,
+    window: usize,
+    state: Mutex<VerifyState>,
+}
+
+/// Decoder wrapper verifying RFC5848 (Signed Syslog Messages) signature blocks, for tamper-evident logs.
+///
+/// The hashes of the messages received are kept, and every signature block and certificate block gets a
+/// `ssign-verify@32473` structured data with `_sign_valid`, whether it was signed with the configured key.
+/// Valid signature blocks also get `_sign_missing`, the number of messages they cover that were not received
+/// before them, either lost or tampered with, `_sign_gap`, the number of blocks of their signature group that
+/// were not received, and `_sign_replayed` if they were already received. Messages are forwarded as they are
+/// received, so that the ones that don't match a block can't be flagged themselves.
+pub struct SyslogSignDecoder {
+    decoder: Box<dyn Decoder + Send>,
+    verifier: Arc<SignVerifier>,
+}
+
+impl Clone for SyslogSignDecoder {
+    fn clone(&self) -> SyslogSignDecoder {
+        SyslogSignDecoder {
+            decoder: self.decoder.clone_boxed(),
+            verifier: Arc::clone(&self.verifier),
+        }
+    }
+}
+
+impl SyslogSignDecoder {
+    /// # Parameters
+    /// - 'input.syslog_sign_key':    Optional. Path to the public key of the signers, or to their certificate,
+    ///   in the PEM format.
+    /// - 'input.syslog_sign_window': Optional. Number of messages whose hashes are kept for the signature
+    ///   blocks to be checked against. Default is 100000.
+    ///
+    /// # Returns
+    /// The decoder as is if 'input.syslog_sign_key' is not set, or wrapped so that it verifies the signatures
+    pub fn wrap(config: &Config, decoder: Box<dyn Decoder + Send>) -> Box<dyn Decoder + Send> {
+        let settings: SyslogSignSettings = config.settings("input");
+        let path = match settings.syslog_sign_key {
+            None => return decoder,
+            Some(path) => path,
+        };
+        let pem = fs::read(&path)
+            .unwrap_or_else(|e| panic!("Unable to read input.syslog_sign_key [{}]: {}", path, e));
+        let key = PKey::public_key_from_pem(&pem)
+            .or_else(|_| X509::from_pem(&pem).and_then(|certificate| certificate.public_key()))
+            .unwrap_or_else(|e| panic!("Invalid input.syslog_sign_key [{}]: {}", path, e));
+        let window = settings
+            .syslog_sign_window
+            .map_or(DEFAULT_SYSLOG_SIGN_WINDOW, NonZeroUsize::get);
+        Box::new(SyslogSignDecoder {
+            decoder,
+            verifier: Arc::new(SignVerifier {
+                key,
+                window,
+                state: Mutex::new(VerifyState {
+                    messages: VecDeque::new(),
+                    hashes: HashMap::new(),
+                    groups: HashMap::new(),
+                }),
+            }),
+        })
+    }
+}
+
+impl SignVerifier {
+    fn check<'a>(&self, line: &[u8], mut record: Record<'a>) -> Record<'a> {
+        let signature_block = match record.sd.iter().flatten().find_map(|sd| {
+            sd.sd_id
+                .as_deref()
+                .filter(|sd_id| sd_id.starts_with("ssign"))
+        }) {
+            Some("ssign") => true,
+            Some("ssign-cert") => false,
+            _ => {
+                self.remember(line);
+                return record;
+            }
+        };
+        let sd = self.verify_block(signature_block, line, &record);
+        record.sd.get_or_insert_with(Vec::new).push(sd);
+        record
+    }
+
+    /// Keep the hashes of a message, for the signature blocks
+    fn remember(&self, line: &[u8]) {
+        let (sha1, sha256) = match (
+            hash(MessageDigest::sha1(), line),
+            hash(MessageDigest::sha256(), line),
+        ) {
+            (Ok(sha1), Ok(sha256)) => (sha1.to_vec(), sha256.to_vec()),
+            _ => return,
+        };
+        let mut state = self.state.lock().unwrap();
+        for hash in [&sha1, &sha256] {
+            *state.hashes.entry(hash.clone()).or_insert(0) += 1;
+        }
+        state.messages.push_back((sha1, sha256));
+        while state.messages.len() > self.window {
+            let (sha1, sha256) = state.messages.pop_front().unwrap();
+            for hash in [sha1, sha256] {
+                if let Some(count) = state.hashes.get_mut(&hash) {
+                    *count -= 1;
+                    if *count == 0 {
+                        state.hashes.remove(&hash);
+                    }
+                }
+            }
+        }
+    }
+
+    fn verify_block(
+        &self,
+        signature_block: bool,
+        line: &[u8],
+        record: &Record,
+    ) -> StructuredData<'static> {
+        let mut sd = StructuredData::new(Some(SYSLOG_SIGN_SD_ID));
+        let digest = match record.field("VER").as_deref() {
+            Some("0111") => MessageDigest::sha1(),
+            Some("0121") => MessageDigest::sha256(),
+            _ => {
+                sd.pairs.push(("_sign_valid".into(), SDValue::Bool(false)));
+                return sd;
+            }
+        };
+        let valid = self.signature_valid(digest, line);
+        sd.pairs.push(("_sign_valid".into(), SDValue::Bool(valid)));
+        if !valid || !signature_block {
+            return sd;
+        }
+        let mut state = self.state.lock().unwrap();
+        let missing = record
+            .field("HB")
+            .unwrap_or_default()
+            .split(' ')
+            .filter(|hash| !hash.is_empty())
+            .filter(|hash| {
+                base64::decode_block(hash).map_or(true, |hash| !state.hashes.contains_key(&hash))
+            })
+            .count();
+        sd.pairs
+            .push(("_sign_missing".into(), SDValue::U64(missing as u64)));
+        if let Some(gbc) = record.field("GBC").and_then(|gbc| gbc.parse::<u64>().ok()) {
+            let group = (
+                record.hostname.to_string(),
+                record.field("RSID").unwrap_or_default(),
+                record.field("SG").unwrap_or_default(),
+            );
+            match state.groups.get(&group).copied() {
+                Some(last) if gbc <= last => {
+                    sd.pairs
+                        .push(("_sign_replayed".into(), SDValue::Bool(true)));
+                    return sd;
+                }
+                Some(last) if gbc > last + 1 => {
+                    sd.pairs
+                        .push(("_sign_gap".into(), SDValue::U64(gbc - last - 1)));
+                }
+                _ => {}
+            }
+            if state.groups.len() >= MAX_SIGNATURE_GROUPS && !state.groups.contains_key(&group) {
+                state.groups.clear();
+            }
+            state.groups.insert(group, gbc);
+        }
+        sd
+    }
+
+    /// Whether the message was signed with the key, the signature covering the message with an empty SIGN value
+    fn signature_valid(&self, digest: MessageDigest, line: &[u8]) -> bool {
+        let line = match str::from_utf8(line) {
+            Ok(line) => line,
+            Err(_) => return false,
+        };
+        let start = match line.rfind(" SIGN=\"") {
+            Some(start) => start + " SIGN=\"".len(),
+            None => return false,
+        };
+        let end = match line[start..].find('"') {
+            Some(len) => start + len,
+            None => return false,
+        };
+        let signature = match base64::decode_block(&line[start..end]) {
+            Ok(signature) => signature,
+            Err(_) => return false,
+        };
+        Verifier::new(digest, &self.key)
+            .and_then(|mut verifier| {
+                verifier.update(&line.as_bytes()[..start])?;
+                verifier.update(&line.as_bytes()[end..])?;
+                verifier.verify(&signature)
+            })
+            .unwrap_or(false)
+    }
+}
+
+impl Decoder for SyslogSignDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        let record = self.decoder.decode(line)?;
+        Ok(self.verifier.check(line.as_bytes(), record))
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        let record = self.decoder.decode_bytes(line)?;
+        Ok(self.verifier.check(line, record))
+    }
+}
@@ -0,0 +1,177 @@
+use super::Decoder;
+use crate::flowgger::config::Config;
+#[cfg(unix)]
+use crate::flowgger::daemon;
+#[cfg(feature = "redact")]
+use crate::flowgger::encoder::RedactRules;
+use crate::flowgger::record::Record;
+use serde::Deserialize;
+#[cfg(feature = "redact")]
+use std::borrow::Cow;
+use std::io::{stdout, Write};
+use std::num::NonZeroU64;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+#[cfg(unix)]
+use std::sync::Once;
+
+const DEFAULT_TAP_SAMPLE: u64 = 100;
+
+/// Whether the tap is currently on, for every input
+static TAP_ENABLED: AtomicBool = AtomicBool::new(false);
+#[cfg(unix)]
+static TAP_SIGNAL: Once = Once::new();
+
+/// Turn the tap on or off
+#[cfg_attr(not(unix), allow(dead_code))]
+pub fn set_tap(enabled: bool) {
+    TAP_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether the tap is on
+pub fn tap_enabled() -> bool {
+    TAP_ENABLED.load(Ordering::Relaxed)
+}
+
+#[cfg(unix)]
+extern "C" fn toggle_tap(_signal: libc::c_int) {
+    TAP_ENABLED.fetch_xor(true, Ordering::Relaxed);
+}
+
+/// Settings of the tap, in the `[input]` section
+#[derive(Deserialize)]
+pub struct TapSettings {
+    tap_sample: Option<NonZeroU64>,
+}
+
+/// Decoder wrapper copying a sample of the raw records to stdout while the tap is on, to see what
+/// remote senders actually emit without restarting flowgger.
+/// The tap is off at startup, and is toggled by sending SIGUSR2 to the process or with the admin socket.
+/// The 'output.redact' rules are applied to the whole copies, as the tap sees the records before the outputs.
+pub struct TapDecoder {
+    decoder: Box<dyn Decoder + Send>,
+    sample: u64,
+    seen: Arc<AtomicU64>,
+    #[cfg(feature = "redact")]
+    redact: Option<Arc<RedactRules>>,
+}
+
+impl Clone for TapDecoder {
+    fn clone(&self) -> TapDecoder {
+        TapDecoder {
+            decoder: self.decoder.clone_boxed(),
+            sample: self.sample,
+            seen: Arc::clone(&self.seen),
+            #[cfg(feature = "redact")]
+            redact: self.redact.clone(),
+        }
+    }
+}
+
+impl TapDecoder {
+    /// # Parameters
+    /// - 'input.tap_sample': Optional. Copy one record out of this many while the tap is on. Default is 100,
+    ///   1 copies every record.
+    /// - 'output.redact': Optional. Rules masking data in the copies, see `RedactEncoder::wrap`.
+    pub fn wrap(config: &Config, decoder: Box<dyn Decoder + Send>) -> Box<dyn Decoder + Send> {
+        let settings: TapSettings = config.settings("input");
+        let sample = settings
+            .tap_sample
+            .map_or(DEFAULT_TAP_SAMPLE, NonZeroU64::get);
+        #[cfg(unix)]
+        TAP_SIGNAL.call_once(|| daemon::on_signal(libc::SIGUSR2, toggle_tap));
+        Box::new(TapDecoder {
+            decoder,
+            sample,
+            seen: Arc::new(AtomicU64::new(0)),
+            #[cfg(feature = "redact")]
+            redact: RedactRules::from_config(config).map(Arc::new),
+        })
+    }
+
+    fn sampled(&self) -> bool {
+        tap_enabled()
+            && self
+                .seen
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(self.sample)
+    }
+
+    #[cfg(feature = "redact")]
+    fn redacted<'a>(&self, line: &'a [u8]) -> Cow<'a, [u8]> {
+        match &self.redact {
+            Some(rules) => match rules.redact(&String::from_utf8_lossy(line)) {
+                Cow::Owned(redacted) => Cow::Owned(redacted.into_bytes()),
+                Cow::Borrowed(_) => Cow::Borrowed(line),
+            },
+            None => Cow::Borrowed(line),
+        }
+    }
+
+    fn tap(&self, line: &[u8]) {
+        if !self.sampled() {
+            return;
+        }
+        #[cfg(feature = "redact")]
+        let line = &*self.redacted(line);
+        let mut stdout = stdout().lock();
+        let _ = stdout.write_all(line);
+        let _ = stdout.write_all(b"\n");
+        let _ = stdout.flush();
+    }
+}
+
+impl Decoder for TapDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        self.tap(line.as_bytes());
+        self.decoder.decode(line)
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        self.tap(line);
+        self.decoder.decode_bytes(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::utils::test_utils::record_test_utils::TestDecoder;
+
+    #[test]
+    fn test_tap_decoder_sampling() {
+        let tap = TapDecoder {
+            decoder: Box::new(TestDecoder),
+            sample: 3,
+            seen: Arc::new(AtomicU64::new(0)),
+            #[cfg(feature = "redact")]
+            redact: None,
+        };
+        assert!(!tap.sampled());
+        TAP_ENABLED.store(true, Ordering::Relaxed);
+        let sampled: Vec<bool> = (0..6).map(|_| tap.sampled()).collect();
+        TAP_ENABLED.store(false, Ordering::Relaxed);
+        assert_eq!(sampled, vec![true, false, false, true, false, false]);
+        assert!(!tap.sampled());
+    }
+
+    #[cfg(feature = "redact")]
+    #[test]
+    fn test_tap_decoder_redact() {
+        let config = Config::from_string(
+            "[output]\n[[output.redact]]\npattern = \"[0-9]{16}\"\n[[output.redact]]\npattern = \"secret=\\\\S+\"\nreplacement = \"secret=***\"\n",
+        )
+        .unwrap();
+        let tap = TapDecoder {
+            decoder: Box::new(TestDecoder),
+            sample: 1,
+            seen: Arc::new(AtomicU64::new(0)),
+            redact: RedactRules::from_config(&config).map(Arc::new),
+        };
+        assert_eq!(
+            &*tap.redacted(b"<13>1 - - - - - [a card=\"4111111111111111\"] secret=abc paid"),
+            &b"<13>1 - - - - - [a card=\"[REDACTED]\"] secret=*** paid"[..]
+        );
+        assert!(matches!(tap.redacted(b"nothing to hide"), Cow::Borrowed(_)));
+    }
+}
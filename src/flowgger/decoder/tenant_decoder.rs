@@ -0,0 +1,107 @@
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue};
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub const TENANT_KEY: &str = "_tenant";
+
+/// Settings of the tenant, in the `[input]` section
+#[derive(Deserialize)]
+pub struct TenantSettings {
+    tenant: Option<String>,
+}
+
+/// Decoder wrapper stamping every record of an input with the tenant it collects for, stored as the `_tenant`
+/// structured data. A tenant set by the sender is replaced, so that it can't pass its logs off as another
+/// tenant's.
+pub struct TenantDecoder {
+    decoder: Box<dyn Decoder + Send>,
+    tenant: Arc<str>,
+}
+
+impl Clone for TenantDecoder {
+    fn clone(&self) -> TenantDecoder {
+        TenantDecoder {
+            decoder: self.decoder.clone_boxed(),
+            tenant: Arc::clone(&self.tenant),
+        }
+    }
+}
+
+impl TenantDecoder {
+    /// # Parameters
+    /// - 'input.tenant': Optional. Name of the tenant, can be set for every listener of 'input.listeners'.
+    ///
+    /// # Returns
+    /// The decoder as is if 'input.tenant' is not set, or wrapped so that it adds the tenant
+    pub fn wrap(config: &Config, decoder: Box<dyn Decoder + Send>) -> Box<dyn Decoder + Send> {
+        let settings: TenantSettings = config.settings("input");
+        let tenant = match settings.tenant {
+            None => return decoder,
+            Some(tenant) => tenant,
+        };
+        Box::new(TenantDecoder {
+            decoder,
+            tenant: tenant.into(),
+        })
+    }
+}
+
+impl TenantDecoder {
+    fn stamp<'a>(&self, mut record: Record<'a>) -> Record<'a> {
+        for sd in record.sd.iter_mut().flatten() {
+            sd.pairs
+                .retain(|(key, _)| key.strip_prefix('_').unwrap_or(key) != "tenant");
+        }
+        record.push_sd_pair(TENANT_KEY, SDValue::String(self.tenant.to_string().into()));
+        record
+    }
+}
+
+impl Decoder for TenantDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        Ok(self.stamp(self.decoder.decode(line)?))
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        Ok(self.stamp(self.decoder.decode_bytes(line)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::record::StructuredData;
+    use crate::flowgger::utils::test_utils::record_test_utils::TestDecoder;
+
+    /// Test decoder adding structured data with a tenant and an IP address
+    #[derive(Clone)]
+    struct OriginDecoder;
+
+    impl Decoder for OriginDecoder {
+        fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+            let mut sd = StructuredData::new(Some("origin"));
+            sd.pairs
+                .push(("_tenant".into(), SDValue::String("teamB".into())));
+            sd.pairs
+                .push(("_ip".into(), SDValue::String("192.0.2.1".into())));
+            Ok(Record {
+                sd: Some(vec![sd]),
+                ..TestDecoder.decode(line)?
+            })
+        }
+    }
+
+    #[test]
+    fn test_tenant_decoder() {
+        let config = Config::from_string("[input]\ntenant = \"teamA\"\n").unwrap();
+        let record = TenantDecoder::wrap(&config, Box::new(OriginDecoder))
+            .decode("message")
+            .unwrap();
+        assert_eq!(record.field("tenant"), Some("teamA".to_owned()));
+        let pairs = &record.sd.unwrap()[0].pairs;
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[0].0, "_ip");
+    }
+}
@@ -0,0 +1,214 @@
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue};
+use regex::Regex;
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub const TRACE_ID_KEY: &str = "_trace_id";
+pub const SPAN_ID_KEY: &str = "_span_id";
+
+/// W3C `traceparent` headers, i.e. "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01", and
+/// `trace_id=...`/`span_id=...` or `"traceId":"..."` notations
+const DEFAULT_TRACE_CONTEXT_PATTERNS: &[&str] = &[
+    r"(?i)\b[0-9a-f]{2}-(?P<trace_id>[0-9a-f]{32})-(?P<span_id>[0-9a-f]{16})-[0-9a-f]{2}\b",
+    r#"(?i)\btrace[_.-]?id"?\s*[=:]\s*"?(?P<trace_id>[0-9a-f]{32})\b"#,
+    r#"(?i)\bspan[_.-]?id"?\s*[=:]\s*"?(?P<span_id>[0-9a-f]{16})\b"#,
+];
+const DEFAULT_TRACE_CONTEXT_FIELDS: &[&str] = &["traceparent"];
+
+/// Where to look for the trace context
+struct Extractor {
+    patterns: Vec<Regex>,
+    fields: Vec<String>,
+}
+
+/// Settings of the trace context extraction, in the `[input]` section
+#[derive(Deserialize)]
+pub struct TraceContextSettings {
+    trace_context: Option<bool>,
+    trace_context_patterns: Option<Vec<String>>,
+    trace_context_fields: Option<Vec<String>>,
+}
+
+/// Decoder wrapper extracting the trace and span identifiers of OpenTelemetry / W3C trace context from the
+/// records, into the `_trace_id` and `_span_id` structured data, so that logs can be correlated with traces
+/// downstream.
+///
+/// Identifiers are looked for in the structured data of 'input.trace_context_fields', then in the message.
+/// They are written in lowercase, and the invalid all-zero identifiers are ignored. Records that already have
+/// a `trace_id` or `span_id` are left as is.
+pub struct TraceContextDecoder {
+    decoder: Box<dyn Decoder + Send>,
+    extractor: Arc<Extractor>,
+}
+
+impl Clone for TraceContextDecoder {
+    fn clone(&self) -> TraceContextDecoder {
+        TraceContextDecoder {
+            decoder: self.decoder.clone_boxed(),
+            extractor: Arc::clone(&self.extractor),
+        }
+    }
+}
+
+impl TraceContextDecoder {
+    /// # Parameters
+    /// - 'input.trace_context':          Optional. Extract the trace context. Default is false.
+    /// - 'input.trace_context_patterns': Optional. Regular expressions with a `trace_id` and/or a `span_id`
+    ///   named group. Default matches `traceparent` headers and `trace_id=`, `span_id=`, `traceId:`... pairs.
+    /// - 'input.trace_context_fields':   Optional. Structured data searched before the message. Default is
+    ///   `["traceparent"]`.
+    ///
+    /// # Returns
+    /// The decoder as is if 'input.trace_context' is not set, or wrapped so that it extracts the trace context
+    pub fn wrap(config: &Config, decoder: Box<dyn Decoder + Send>) -> Box<dyn Decoder + Send> {
+        let settings: TraceContextSettings = config.settings("input");
+        if settings.trace_context != Some(true) {
+            return decoder;
+        }
+        let patterns = match settings.trace_context_patterns {
+            None => DEFAULT_TRACE_CONTEXT_PATTERNS
+                .iter()
+                .map(|pattern| Regex::new(pattern).unwrap())
+                .collect(),
+            Some(patterns) => patterns
+                .iter()
+                .map(|pattern| {
+                    let regex = Regex::new(pattern).unwrap_or_else(|e| {
+                        panic!("Invalid input.trace_context_patterns [{}]: {}", pattern, e)
+                    });
+                    let names: Vec<_> = regex.capture_names().flatten().collect();
+                    if !names.contains(&"trace_id") && !names.contains(&"span_id") {
+                        panic!(
+                            "input.trace_context_patterns [{}] has no trace_id or span_id group",
+                            pattern
+                        );
+                    }
+                    regex
+                })
+                .collect(),
+        };
+        let fields = settings.trace_context_fields.unwrap_or_else(|| {
+            DEFAULT_TRACE_CONTEXT_FIELDS
+                .iter()
+                .map(|field| field.to_string())
+                .collect()
+        });
+        Box::new(TraceContextDecoder {
+            decoder,
+            extractor: Arc::new(Extractor { patterns, fields }),
+        })
+    }
+}
+
+impl Extractor {
+    fn extract<'a>(&self, mut record: Record<'a>) -> Record<'a> {
+        // `Some(None)` for the identifiers the record already has, that are not looked for
+        let mut trace_id = record.field("trace_id").map(|_| None);
+        let mut span_id = record.field("span_id").map(|_| None);
+        let texts = self
+            .fields
+            .iter()
+            .filter_map(|field| record.field(field))
+            .chain(record.msg.as_deref().map(str::to_owned));
+        for text in texts {
+            for regex in &self.patterns {
+                if trace_id.is_some() && span_id.is_some() {
+                    break;
+                }
+                if let Some(captures) = regex.captures(&text) {
+                    if trace_id.is_none() {
+                        trace_id = valid_id(captures.name("trace_id")).map(Some);
+                    }
+                    if span_id.is_none() {
+                        span_id = valid_id(captures.name("span_id")).map(Some);
+                    }
+                }
+            }
+        }
+        if let Some(Some(trace_id)) = trace_id {
+            record.push_sd_pair(TRACE_ID_KEY, SDValue::String(trace_id.into()));
+        }
+        if let Some(Some(span_id)) = span_id {
+            record.push_sd_pair(SPAN_ID_KEY, SDValue::String(span_id.into()));
+        }
+        record
+    }
+}
+
+/// The identifier in lowercase, unless it is the invalid all-zero identifier
+fn valid_id(id: Option<regex::Match>) -> Option<String> {
+    id.map(|id| id.as_str())
+        .filter(|id| id.bytes().any(|c| c != b'0'))
+        .map(str::to_lowercase)
+}
+
+impl Decoder for TraceContextDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        Ok(self.extractor.extract(self.decoder.decode(line)?))
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        Ok(self.extractor.extract(self.decoder.decode_bytes(line)?))
+    }
+}
+
+#[cfg(all(test, feature = "rfc5424"))]
+mod tests {
+    use super::*;
+    use crate::flowgger::decoder::RFC5424Decoder;
+
+    fn decode<'a>(config: &str, line: &'a str) -> Record<'a> {
+        let config = Config::from_string(config).unwrap();
+        TraceContextDecoder::wrap(&config, Box::new(RFC5424Decoder::new(&config)))
+            .decode(line)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_trace_context_default_patterns() {
+        let record = decode(
+            "[input]\ntrace_context = true\n",
+            r#"<23>1 2015-08-05T15:53:45Z h app 69 42 [origin@123 traceparent="00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01"] msg"#,
+        );
+        assert_eq!(
+            record.field("trace_id"),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736".to_owned())
+        );
+        assert_eq!(record.field("span_id"), Some("00f067aa0ba902b7".to_owned()));
+
+        let record = decode(
+            "[input]\ntrace_context = true\n",
+            r#"<23>1 2015-08-05T15:53:45Z h app 69 42 - request done span_id=00f067aa0ba902b7 {"traceId":"4bf92f3577b34da6a3ce929d0e0e4736"}"#,
+        );
+        assert_eq!(
+            record.field("trace_id"),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736".to_owned())
+        );
+        assert_eq!(record.field("span_id"), Some("00f067aa0ba902b7".to_owned()));
+
+        let record = decode(
+            "[input]\ntrace_context = true\n",
+            "<23>1 2015-08-05T15:53:45Z h app 69 42 - trace_id=00000000000000000000000000000000",
+        );
+        assert_eq!(record.field("trace_id"), None);
+    }
+
+    #[test]
+    fn test_trace_context_custom_patterns() {
+        let record = decode(
+            "[input]\ntrace_context = true\ntrace_context_patterns = [ 'dd\\.trace_id=(?P<trace_id>\\d+)' ]\ntrace_context_fields = []\n",
+            r#"<23>1 2015-08-05T15:53:45Z h app 69 42 [origin@123 traceparent="00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"] dd.trace_id=1234"#,
+        );
+        assert_eq!(record.field("trace_id"), Some("1234".to_owned()));
+        assert_eq!(record.field("span_id"), None);
+
+        let record = decode(
+            "[input]\ntrace_context = true\n",
+            r#"<23>1 2015-08-05T15:53:45Z h app 69 42 [origin@123 trace_id="abc"] span_id=00f067aa0ba902b7 trace_id=4bf92f3577b34da6a3ce929d0e0e4736"#,
+        );
+        assert_eq!(record.field("trace_id"), Some("abc".to_owned()));
+        assert_eq!(record.field("span_id"), Some("00f067aa0ba902b7".to_owned()));
+    }
+}
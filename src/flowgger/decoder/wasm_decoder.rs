@@ -0,0 +1,554 @@
+use super::{Decoder, DROPPED};
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Facility, Record, SDValue, Severity, StructuredData, Timestamp};
+use serde::Deserialize;
+use serde_json::value::Value;
+use serde_json::Map;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{stderr, Write};
+use std::num::NonZeroU64;
+use std::sync::{Arc, Mutex};
+use wasmi::{
+    Config as EngineConfig, Engine, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder,
+    TypedFunc,
+};
+
+const DEFAULT_WASM_FUEL: u64 = 10_000_000;
+const DEFAULT_WASM_MAX_MEMORY: u64 = 64;
+const PLUGIN_FAILED: &str = "The WebAssembly plugin failed";
+
+/// A compiled plugin, and the limits of its instances
+struct Plugin {
+    engine: Engine,
+    module: Module,
+    export: &'static str,
+    fuel: u64,
+    max_memory: usize,
+}
+
+/// An instance of a plugin, with its own memory
+struct PluginInstance {
+    store: Store<StoreLimits>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    dealloc: TypedFunc<(i32, i32), ()>,
+    run: TypedFunc<(i32, i32), i64>,
+}
+
+/// What a plugin did with a record
+enum PluginOutput {
+    Dropped,
+    Rejected,
+    /// The resulting record, copied out of the memory of the plugin
+    Record(Vec<u8>),
+}
+
+/// Settings of the WebAssembly plugins, in the `[input]` section
+#[derive(Deserialize)]
+pub struct WasmSettings {
+    wasm_decoder: Option<String>,
+    wasm_transform: Option<String>,
+    wasm_fuel: Option<NonZeroU64>,
+    wasm_max_memory: Option<NonZeroU64>,
+}
+
+/// Decoder running a WebAssembly plugin, so that proprietary formats and transformations can be shipped
+/// independently of flowgger.
+///
+/// Plugins are modules built for `wasm32-unknown-unknown`, exporting:
+/// - `memory`;
+/// - `alloc(len: i32) -> i32`: a buffer of `len` bytes the input is written to;
+/// - `dealloc(ptr: i32, len: i32)`: frees a buffer, called by the host with the input buffer and the returned
+///   record once it has been read, only once if the plugin returns its input buffer;
+/// - `decode(ptr: i32, len: i32) -> i64`, called with the raw record, for the "wasm" input format, or
+///   `transform(ptr: i32, len: i32) -> i64`, called with the decoded record as JSON, for 'input.wasm_transform'.
+///
+/// Both return the position of the resulting record as `(ptr << 32) | len`, 0 to drop the record, or a negative
+/// value to reject it. Records are exchanged as JSON objects with the `ts` and `hostname` fields, the optional
+/// `facility`, `severity`, `appname`, `procid`, `msgid`, `msg` and `full_msg` fields, and `sd`, an array of
+/// `{"id": ..., "pairs": {...}}` objects.
+///
+/// Plugins are sandboxed: no host function is imported, their memory is limited, and they are stopped after
+/// a number of instructions for every record, the record being rejected then.
+pub struct WasmDecoder {
+    decoder: Option<Box<dyn Decoder + Send>>,
+    plugin: Arc<Plugin>,
+    instance: Mutex<Option<PluginInstance>>,
+}
+
+impl Clone for WasmDecoder {
+    fn clone(&self) -> WasmDecoder {
+        WasmDecoder {
+            decoder: self.decoder.as_ref().map(|decoder| decoder.clone_boxed()),
+            plugin: Arc::clone(&self.plugin),
+            instance: Mutex::new(None),
+        }
+    }
+}
+
+impl WasmDecoder {
+    /// Decoder for the "wasm" input format
+    ///
+    /// # Parameters
+    /// - 'input.wasm_decoder':    Path to a WebAssembly plugin exporting `decode`.
+    /// - 'input.wasm_fuel':       Optional. Maximum number of instructions the plugin can run per record.
+    ///   Default is 10000000.
+    /// - 'input.wasm_max_memory': Optional. Maximum memory of the plugin, in megabytes. Default is 64.
+    ///
+    /// # Panics
+    /// `Unable to load input.wasm_decoder`: the plugin can't be read, or doesn't export the required functions
+    pub fn new(config: &Config) -> WasmDecoder {
+        let settings: WasmSettings = config.settings("input");
+        let path = settings
+            .wasm_decoder
+            .as_deref()
+            .expect("input.wasm_decoder is required for the wasm format");
+        WasmDecoder {
+            decoder: None,
+            plugin: Arc::new(Plugin::load(
+                &settings,
+                "input.wasm_decoder",
+                path,
+                "decode",
+            )),
+            instance: Mutex::new(None),
+        }
+    }
+
+    /// # Parameters
+    /// - 'input.wasm_transform':  Optional. Path to a WebAssembly plugin exporting `transform`, run on every
+    ///   decoded record.
+    /// - 'input.wasm_fuel', 'input.wasm_max_memory': as for the "wasm" input format.
+    ///
+    /// # Returns
+    /// The decoder as is if 'input.wasm_transform' is not set, or wrapped so that the plugin transforms the
+    /// records
+    ///
+    /// # Panics
+    /// `Unable to load input.wasm_transform`: the plugin can't be read, or doesn't export the required
+    /// functions
+    pub fn wrap(config: &Config, decoder: Box<dyn Decoder + Send>) -> Box<dyn Decoder + Send> {
+        let settings: WasmSettings = config.settings("input");
+        let path = match settings.wasm_transform {
+            None => return decoder,
+            Some(ref path) => path,
+        };
+        Box::new(WasmDecoder {
+            decoder: Some(decoder),
+            plugin: Arc::new(Plugin::load(
+                &settings,
+                "input.wasm_transform",
+                path,
+                "transform",
+            )),
+            instance: Mutex::new(None),
+        })
+    }
+
+    /// Run the plugin on `input`, with a new instance if the previous one failed
+    fn call(&self, input: &[u8]) -> Result<Record<'static>, &'static str> {
+        let mut instance = self.instance.lock().unwrap();
+        if instance.is_none() {
+            *instance = Some(self.plugin.instantiate().map_err(|e| {
+                let _ = writeln!(
+                    stderr(),
+                    "Unable to instantiate the WebAssembly plugin: {}",
+                    e
+                );
+                PLUGIN_FAILED
+            })?);
+        }
+        let output = instance
+            .as_mut()
+            .unwrap()
+            .call(input, self.plugin.fuel)
+            .map_err(|e| {
+                let _ = writeln!(stderr(), "The WebAssembly plugin failed: {}", e);
+                *instance = None;
+                PLUGIN_FAILED
+            })?;
+        match output {
+            PluginOutput::Dropped => Err(DROPPED),
+            PluginOutput::Rejected => Err("The WebAssembly plugin rejected the record"),
+            PluginOutput::Record(output) => json_to_record(&output),
+        }
+    }
+}
+
+impl Decoder for WasmDecoder {
+    fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+        match self.decoder {
+            None => self.call(line.as_bytes()),
+            Some(ref decoder) => self.call(&record_to_json(decoder.decode(line)?)?),
+        }
+    }
+
+    fn decode_bytes<'a>(&self, line: &'a [u8]) -> Result<Record<'a>, &'static str> {
+        match self.decoder {
+            None => self.call(line),
+            Some(ref decoder) => self.call(&record_to_json(decoder.decode_bytes(line)?)?),
+        }
+    }
+}
+
+impl Plugin {
+    fn load(settings: &WasmSettings, name: &str, path: &str, export: &'static str) -> Plugin {
+        let fuel = settings
+            .wasm_fuel
+            .map_or(DEFAULT_WASM_FUEL, NonZeroU64::get);
+        let max_memory = settings
+            .wasm_max_memory
+            .map_or(DEFAULT_WASM_MAX_MEMORY, NonZeroU64::get);
+        let wasm =
+            fs::read(path).unwrap_or_else(|e| panic!("Unable to load {} [{}]: {}", name, path, e));
+        Plugin::new(&wasm, export, fuel, (max_memory * 1024 * 1024) as usize)
+            .unwrap_or_else(|e| panic!("Unable to load {} [{}]: {}", name, path, e))
+    }
+
+    fn new(
+        wasm: &[u8],
+        export: &'static str,
+        fuel: u64,
+        max_memory: usize,
+    ) -> Result<Plugin, wasmi::Error> {
+        let mut engine_config = EngineConfig::default();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config);
+        let module = Module::new(&engine, wasm)?;
+        let plugin = Plugin {
+            engine,
+            module,
+            export,
+            fuel,
+            max_memory,
+        };
+        // Check the exports once, rather than for every connection
+        plugin.instantiate()?;
+        Ok(plugin)
+    }
+
+    fn instantiate(&self) -> Result<PluginInstance, wasmi::Error> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.max_memory)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(self.fuel)?;
+        let instance = Linker::new(&self.engine)
+            .instantiate(&mut store, &self.module)?
+            .start(&mut store)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| wasmi::Error::new("The plugin doesn't export its memory"))?;
+        let alloc = instance.get_typed_func(&store, "alloc")?;
+        let dealloc = instance.get_typed_func(&store, "dealloc")?;
+        let run = instance.get_typed_func(&store, self.export)?;
+        Ok(PluginInstance {
+            store,
+            memory,
+            alloc,
+            dealloc,
+            run,
+        })
+    }
+}
+
+impl PluginInstance {
+    /// Run the plugin on `input`, then free the input buffer and the returned record
+    fn call(&mut self, input: &[u8], fuel: u64) -> Result<PluginOutput, wasmi::Error> {
+        self.store.set_fuel(fuel)?;
+        let len =
+            i32::try_from(input.len()).map_err(|_| wasmi::Error::new("The record is too large"))?;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, input)
+            .map_err(|e| wasmi::Error::new(e.to_string()))?;
+        let res = self.run.call(&mut self.store, (ptr, len))?;
+        let output = match res {
+            0 => PluginOutput::Dropped,
+            res if res < 0 => PluginOutput::Rejected,
+            res => {
+                let (output_ptr, output_len) = ((res >> 32) as i32, res as i32);
+                let start = output_ptr as u32 as usize;
+                let output = self
+                    .memory
+                    .data(&self.store)
+                    .get(start..start + output_len as u32 as usize)
+                    .ok_or_else(|| {
+                        wasmi::Error::new("The plugin returned a record out of its memory")
+                    })?
+                    .to_vec();
+                if output_ptr != ptr {
+                    self.dealloc
+                        .call(&mut self.store, (output_ptr, output_len))?;
+                }
+                PluginOutput::Record(output)
+            }
+        };
+        self.dealloc.call(&mut self.store, (ptr, len))?;
+        Ok(output)
+    }
+}
+
+fn optional_string(value: Option<Cow<'_, str>>) -> Value {
+    value.map_or(Value::Null, |value| Value::String(value.into_owned()))
+}
+
+fn record_to_json(record: Record<'_>) -> Result<Vec<u8>, &'static str> {
+    let mut map = Map::new();
+    map.insert("ts".to_owned(), Value::F64(record.ts.as_secs_f64()));
+    map.insert(
+        "hostname".to_owned(),
+        Value::String(record.hostname.into_owned()),
+    );
+    map.insert(
+        "facility".to_owned(),
+        record
+            .facility
+            .map_or(Value::Null, |facility| Value::U64(facility as u64)),
+    );
+    map.insert(
+        "severity".to_owned(),
+        record
+            .severity
+            .map_or(Value::Null, |severity| Value::U64(severity as u64)),
+    );
+    map.insert("appname".to_owned(), optional_string(record.appname));
+    map.insert("procid".to_owned(), optional_string(record.procid));
+    map.insert("msgid".to_owned(), optional_string(record.msgid));
+    map.insert("msg".to_owned(), optional_string(record.msg));
+    map.insert("full_msg".to_owned(), optional_string(record.full_msg));
+    let sd = record
+        .sd
+        .into_iter()
+        .flatten()
+        .map(|sd| {
+            let mut sd_map = Map::new();
+            sd_map.insert("id".to_owned(), optional_string(sd.sd_id));
+            let pairs = sd
+                .pairs
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        SDValue::String(value) => Value::String(value.into_owned()),
+                        SDValue::Bool(value) => Value::Bool(value),
+                        SDValue::F64(value) => Value::F64(value),
+                        SDValue::I64(value) => Value::I64(value),
+                        SDValue::U64(value) => Value::U64(value),
+                        SDValue::Null => Value::Null,
+                    };
+                    (key.into_owned(), value)
+                })
+                .collect();
+            sd_map.insert("pairs".to_owned(), Value::Object(pairs));
+            Value::Object(sd_map)
+        })
+        .collect();
+    map.insert("sd".to_owned(), Value::Array(sd));
+    serde_json::to_vec(&Value::Object(map)).or(Err("Unable to serialize to JSON"))
+}
+
+fn json_to_record(json: &[u8]) -> Result<Record<'static>, &'static str> {
+    let mut map = match serde_json::from_slice(json) {
+        Ok(Value::Object(map)) => map,
+        _ => return Err("The WebAssembly plugin returned an invalid record"),
+    };
+    let mut string = |name: &str| match map.remove(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) => Ok(Some(Cow::Owned(value))),
+        Some(_) => Err("The WebAssembly plugin returned a field that is not a string"),
+    };
+    let hostname = string("hostname")?.ok_or("The WebAssembly plugin returned no hostname")?;
+    let appname = string("appname")?;
+    let procid = string("procid")?;
+    let msgid = string("msgid")?;
+    let msg = string("msg")?;
+    let full_msg = string("full_msg")?;
+    let ts = match map.remove("ts") {
+        Some(Value::F64(ts)) => Timestamp::from_secs_f64(ts),
+        Some(Value::I64(ts)) => Timestamp::from_unix_secs(ts),
+        Some(Value::U64(ts)) => Timestamp::from_secs_f64(ts as f64),
+        _ => return Err("The WebAssembly plugin returned no valid timestamp"),
+    };
+    fn code<T: TryFrom<u8>>(
+        value: Option<Value>,
+        error: &'static str,
+    ) -> Result<Option<T>, &'static str> {
+        match value {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::U64(code)) => u8::try_from(code)
+                .ok()
+                .and_then(|code| T::try_from(code).ok())
+                .map(Some)
+                .ok_or(error),
+            Some(_) => Err(error),
+        }
+    }
+    let facility = code::<Facility>(
+        map.remove("facility"),
+        "The WebAssembly plugin returned an invalid facility",
+    )?;
+    let severity = code::<Severity>(
+        map.remove("severity"),
+        "The WebAssembly plugin returned an invalid severity",
+    )?;
+    let sd = match map.remove("sd") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Array(sd)) => sd
+            .into_iter()
+            .map(json_to_sd)
+            .collect::<Result<_, &'static str>>()?,
+        Some(_) => {
+            return Err("The WebAssembly plugin returned structured data that is not an array")
+        }
+    };
+    Ok(Record {
+        ts,
+        utc_offset: None,
+        hostname,
+        facility,
+        severity,
+        appname,
+        procid,
+        msgid,
+        msg,
+        full_msg,
+        sd: if sd.is_empty() { None } else { Some(sd) },
+    })
+}
+
+fn json_to_sd(sd: Value) -> Result<StructuredData<'static>, &'static str> {
+    let mut sd = match sd {
+        Value::Object(sd) => sd,
+        _ => return Err("The WebAssembly plugin returned structured data that is not an object"),
+    };
+    let sd_id = match sd.remove("id") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(sd_id)) => Some(Cow::Owned(sd_id)),
+        Some(_) => return Err("The WebAssembly plugin returned an invalid SD-ID"),
+    };
+    let pairs = match sd.remove("pairs") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Object(pairs)) => pairs
+            .into_iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Value::String(value) => SDValue::String(value.into()),
+                    Value::Bool(value) => SDValue::Bool(value),
+                    Value::F64(value) => SDValue::F64(value),
+                    Value::I64(value) => SDValue::I64(value),
+                    Value::U64(value) => SDValue::U64(value),
+                    Value::Null => SDValue::Null,
+                    _ => {
+                        return Err(
+                            "The WebAssembly plugin returned a nested structured data value",
+                        )
+                    }
+                };
+                Ok((key.into(), value))
+            })
+            .collect::<Result<_, &'static str>>()?,
+        Some(_) => return Err("The WebAssembly plugin returned pairs that are not an object"),
+    };
+    Ok(StructuredData { sd_id, pairs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECORD: &str = r#"{"ts":1438790025.5,"hostname":"testhostname","severity":3,"msg":"hello","sd":[{"id":"origin@123","pairs":{"ip":"192.0.2.1"}}]}"#;
+
+    /// Address of the number of buffers freed by the test plugin
+    const FREED: usize = 4096;
+
+    /// A plugin returning `RECORD` when decoding, but dropping records starting with "x" and looping forever
+    /// on records starting with "l", and returning records unchanged when transforming
+    fn plugin(export: &'static str) -> Plugin {
+        let wat = format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (data (i32.const 0) "{}")
+                (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+                (func (export "dealloc") (param i32) (param i32)
+                    (i32.store (i32.const {freed}) (i32.add (i32.load (i32.const {freed})) (i32.const 1))))
+                (func (export "decode") (param $ptr i32) (param $len i32) (result i64)
+                    (if (i32.eq (i32.load8_u (local.get $ptr)) (i32.const 120))
+                        (then (return (i64.const 0))))
+                    (if (i32.eq (i32.load8_u (local.get $ptr)) (i32.const 108))
+                        (then (loop $forever (br $forever))))
+                    (i64.const {}))
+                (func (export "transform") (param $ptr i32) (param $len i32) (result i64)
+                    (i64.or
+                        (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                        (i64.extend_i32_u (local.get $len)))))"#,
+            RECORD.replace('"', "\\\""),
+            RECORD.len(),
+            freed = FREED
+        );
+        Plugin::new(&wat::parse_str(wat).unwrap(), export, 100_000, 1024 * 1024).unwrap()
+    }
+
+    fn decoder(export: &'static str, decoder: Option<Box<dyn Decoder + Send>>) -> WasmDecoder {
+        WasmDecoder {
+            decoder,
+            plugin: Arc::new(plugin(export)),
+            instance: Mutex::new(None),
+        }
+    }
+
+    /// Number of buffers the plugin of `decoder` freed
+    fn freed(decoder: &WasmDecoder) -> u32 {
+        let instance = decoder.instance.lock().unwrap();
+        let instance = instance.as_ref().unwrap();
+        let data = instance.memory.data(&instance.store);
+        u32::from_le_bytes(<[u8; 4]>::try_from(&data[FREED..FREED + 4]).unwrap())
+    }
+
+    #[test]
+    fn test_wasm_decode() {
+        let decoder = decoder("decode", None);
+        let record = decoder.decode("anything").unwrap();
+        assert_eq!(
+            record.ts,
+            Timestamp::from_unix_nanos(1_438_790_025_500_000_000)
+        );
+        assert_eq!(record.hostname, "testhostname");
+        assert_eq!(record.severity, Some(Severity::Error));
+        assert_eq!(record.msg.as_deref(), Some("hello"));
+        assert_eq!(record.field("ip").as_deref(), Some("192.0.2.1"));
+        // Both the input buffer and the returned record are freed
+        assert_eq!(freed(&decoder), 2);
+        assert_eq!(decoder.decode("x").unwrap_err(), DROPPED);
+        assert_eq!(freed(&decoder), 3);
+        // The plugin runs out of fuel, and is instantiated again for the next record
+        assert_eq!(decoder.decode("loop").unwrap_err(), PLUGIN_FAILED);
+        assert!(decoder.clone().decode_bytes(b"anything").is_ok());
+    }
+
+    #[cfg(feature = "rfc5424")]
+    #[test]
+    fn test_wasm_transform() {
+        use crate::flowgger::decoder::RFC5424Decoder;
+
+        let config = Config::from_string("").unwrap();
+        let decoder = decoder("transform", Some(Box::new(RFC5424Decoder::new(&config))));
+        let line = r#"<23>1 2015-08-05T15:53:45Z testhostname appname 69 42 [origin@123 ip="192.0.2.1"] test"#;
+        let record = decoder.decode(line).unwrap();
+        assert_eq!(record.appname.as_deref(), Some("appname"));
+        assert_eq!(record.facility, Some(Facility::Mail));
+        assert_eq!(record.severity, Some(Severity::Debug));
+        assert_eq!(record.msg.as_deref(), Some("test"));
+        assert_eq!(record.field("ip").as_deref(), Some("192.0.2.1"));
+        // The record is returned in the input buffer, that is only freed once
+        assert_eq!(freed(&decoder), 1);
+    }
+
+    #[test]
+    fn test_wasm_missing_export() {
+        assert!(Plugin::new(&wat::parse_str("(module)").unwrap(), "decode", 1, 65536).is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use super::Encoder;
+use crate::flowgger::admin;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::Record;
+use crate::flowgger::utils::threads;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{stderr, Write};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_ACCOUNTING_INTERVAL: u64 = 60;
+const DEFAULT_ACCOUNTING_MAX_KEYS: usize = 10_000;
+/// Key of the records that don't have the field, or that came after 'output.accounting_max_keys' was reached
+const OTHER_KEY: &str = "_other";
+
+/// Settings of the accounting, in the `[output]` section
+#[derive(Deserialize)]
+pub struct AccountingSettings {
+    accounting_key: Option<String>,
+    accounting_interval: Option<u64>,
+    accounting_max_keys: Option<NonZeroUsize>,
+}
+
+/// Number of records and bytes sent by a tenant
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Usage {
+    pub records: u64,
+    pub bytes: u64,
+}
+
+/// Usage of the output per value of the accounting field
+pub struct AccountingStats {
+    usage: Mutex<HashMap<String, Usage>>,
+    max_keys: usize,
+}
+
+impl AccountingStats {
+    /// Usage of every key since flowgger started
+    pub fn snapshot(&self) -> Vec<(String, Usage)> {
+        let mut usage: Vec<_> = self
+            .usage
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, usage)| (key.clone(), *usage))
+            .collect();
+        usage.sort_by(|a, b| a.0.cmp(&b.0));
+        usage
+    }
+
+    fn add(&self, key: Option<String>, bytes: usize) {
+        let mut usage = self.usage.lock().unwrap();
+        let key = match key {
+            Some(key) if usage.contains_key(&key) || usage.len() < self.max_keys => key,
+            _ => OTHER_KEY.to_owned(),
+        };
+        let usage = usage.entry(key).or_default();
+        usage.records += 1;
+        usage.bytes += bytes as u64;
+    }
+}
+
+/// Encoder wrapper counting the records and encoded bytes sent for every value of a field, i.e. the hostname or
+/// a tenant structured data pair, for chargeback on shared logging platforms.
+pub struct AccountingEncoder {
+    encoder: Box<dyn Encoder + Send>,
+    key: Arc<str>,
+    stats: Arc<AccountingStats>,
+}
+
+impl Clone for AccountingEncoder {
+    fn clone(&self) -> AccountingEncoder {
+        AccountingEncoder {
+            encoder: self.encoder.clone_boxed(),
+            key: Arc::clone(&self.key),
+            stats: Arc::clone(&self.stats),
+        }
+    }
+}
+
+impl AccountingEncoder {
+    /// # Parameters
+    /// - 'output.accounting_key':      Optional. Name of the field to account usage by, either a header field
+    ///   or a structured data pair. Accounting is only enabled when this is set.
+    /// - 'output.accounting_interval': Optional. Seconds between the usage reports written to stderr, 0 not to
+    ///   report. Default is 60.
+    /// - 'output.accounting_max_keys': Optional. Maximum number of distinct values accounted separately, the
+    ///   others being accounted as "_other". Default is 10000.
+    ///
+    /// # Returns
+    /// The encoder as is if accounting is not enabled, or wrapped so that it accounts the records
+    pub fn wrap(config: &Config, encoder: Box<dyn Encoder + Send>) -> Box<dyn Encoder + Send> {
+        let settings: AccountingSettings = config.settings("output");
+        let key = match settings.accounting_key {
+            None => return encoder,
+            Some(key) => key,
+        };
+        let interval = settings
+            .accounting_interval
+            .unwrap_or(DEFAULT_ACCOUNTING_INTERVAL);
+        let max_keys = settings
+            .accounting_max_keys
+            .map_or(DEFAULT_ACCOUNTING_MAX_KEYS, NonZeroUsize::get);
+        let stats = Arc::new(AccountingStats {
+            usage: Mutex::new(HashMap::new()),
+            max_keys,
+        });
+        if interval > 0 {
+            report(
+                key.to_owned(),
+                Duration::from_secs(interval),
+                Arc::clone(&stats),
+            );
+        }
+        let admin_stats = Arc::clone(&stats);
+        admin::register_stats(move || {
+            admin_stats
+                .snapshot()
+                .into_iter()
+                .flat_map(|(value, usage)| {
+                    [
+                        (format!("accounting.{}.records", value), usage.records),
+                        (format!("accounting.{}.bytes", value), usage.bytes),
+                    ]
+                })
+                .collect()
+        });
+        Box::new(AccountingEncoder {
+            encoder,
+            key: key.into(),
+            stats,
+        })
+    }
+}
+
+impl Encoder for AccountingEncoder {
+    fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
+        let key = record.field(&self.key);
+        let encoded = self.encoder.encode(record)?;
+        self.stats.add(key, encoded.len());
+        Ok(encoded)
+    }
+}
+
+fn report(key: String, interval: Duration, stats: Arc<AccountingStats>) {
+    threads::spawn("flowgger-accounting".to_owned(), None, move || loop {
+        thread::sleep(interval);
+        let mut stderr = stderr().lock();
+        for (value, usage) in stats.snapshot() {
+            let _ = writeln!(
+                stderr,
+                "Usage of [{}={}]: {} records, {} bytes",
+                key, value, usage.records, usage.bytes
+            );
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::record::{SDValue, StructuredData, Timestamp};
+    use crate::flowgger::utils::test_utils::record_test_utils::MsgEncoder;
+
+    fn record<'a>(tenant: Option<&'a str>, msg: &'a str) -> Record<'a> {
+        let mut record = Record::builder()
+            .ts(Timestamp::default())
+            .hostname("example.org")
+            .msg(msg);
+        if let Some(tenant) = tenant {
+            let mut sd = StructuredData::new(None);
+            sd.pairs
+                .push(("_tenant".into(), SDValue::String(tenant.into())));
+            record = record.sd(sd);
+        }
+        record.build()
+    }
+
+    #[test]
+    fn test_accounting_encoder() {
+        let stats = Arc::new(AccountingStats {
+            usage: Mutex::new(HashMap::new()),
+            max_keys: 2,
+        });
+        let encoder = AccountingEncoder {
+            encoder: Box::new(MsgEncoder),
+            key: "tenant".into(),
+            stats: Arc::clone(&stats),
+        };
+        for (tenant, msg) in [
+            (Some("teamA"), "abc"),
+            (Some("teamB"), "de"),
+            (Some("teamA"), "f"),
+            (Some("teamC"), "ghij"),
+            (None, "k"),
+        ] {
+            encoder.encode(record(tenant, msg)).unwrap();
+        }
+        assert_eq!(
+            stats.snapshot(),
+            vec![
+                (
+                    "_other".to_owned(),
+                    Usage {
+                        records: 2,
+                        bytes: 5
+                    }
+                ),
+                (
+                    "teamA".to_owned(),
+                    Usage {
+                        records: 2,
+                        bytes: 4
+                    }
+                ),
+                (
+                    "teamB".to_owned(),
+                    Usage {
+                        records: 1,
+                        bytes: 2
+                    }
+                ),
+            ]
+        );
+    }
+}
@@ -1,9 +1,20 @@
 use super::Encoder;
 use crate::flowgger::config::Config;
-use crate::flowgger::record::{Record, SDValue, FACILITY_MISSING, SEVERITY_MISSING};
+use crate::flowgger::record::{
+    Record, SDValue, CAPNP_SCHEMA_VERSION, FACILITY_MISSING, SEVERITY_MISSING,
+};
 use crate::record_capnp;
 use capnp;
 use capnp::message::{Allocator, Builder};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Settings of the Cap'n Proto encoder, in the `[output]` section
+#[derive(Deserialize)]
+pub struct CapnpSettings {
+    #[serde(default)]
+    capnp_extra: BTreeMap<String, String>,
+}
 
 #[derive(Clone)]
 pub struct CapnpEncoder {
@@ -12,22 +23,8 @@ pub struct CapnpEncoder {
 
 impl CapnpEncoder {
     pub fn new(config: &Config) -> CapnpEncoder {
-        let extra = match config.lookup("output.capnp_extra") {
-            None => Vec::new(),
-            Some(extra) => extra
-                .as_table()
-                .expect("output.capnp_extra must be a list of key/value pairs")
-                .iter()
-                .map(|(k, v)| {
-                    (
-                        k.to_owned(),
-                        v.as_str()
-                            .expect("output.capnp_extra values must be strings")
-                            .to_owned(),
-                    )
-                })
-                .collect(),
-        };
+        let settings: CapnpSettings = config.settings("output");
+        let extra = settings.capnp_extra.into_iter().collect();
         CapnpEncoder { extra }
     }
 }
@@ -49,14 +46,15 @@ fn build_record<T: Allocator>(
     extra: &[(String, String)],
 ) {
     let mut root: record_capnp::record::Builder = record_msg.init_root();
-    root.set_ts(record.ts);
+    root.set_version(CAPNP_SCHEMA_VERSION);
+    root.set_ts(record.ts.as_secs_f64());
     root.set_hostname(&record.hostname);
     match record.facility {
-        Some(facility) => root.set_facility(facility),
+        Some(facility) => root.set_facility(facility.into()),
         _ => root.set_facility(FACILITY_MISSING),
     };
     match record.severity {
-        Some(severity) => root.set_severity(severity),
+        Some(severity) => root.set_severity(severity.into()),
         _ => root.set_severity(SEVERITY_MISSING),
     };
     if let Some(appname) = record.appname {
@@ -78,17 +76,16 @@ fn build_record<T: Allocator>(
         // Warning: the current capnp format only support one structured data. Redefining the
         // format would be a breaking change.
         let sd = &sd_vec[0];
-        sd.sd_id.as_ref().and_then(|sd_id| {
+        if let Some(sd_id) = sd.sd_id.as_ref() {
             root.set_sd_id(sd_id);
-            Some(())
-        });
+        }
         let mut pairs = root.reborrow().init_pairs(sd.pairs.len() as u32);
-        for (i, (name, value)) in (&sd.pairs).into_iter().enumerate() {
+        for (i, (name, value)) in sd.pairs.iter().enumerate() {
             let mut pair = pairs.reborrow().get(i as u32);
-            pair.set_key(&name);
+            pair.set_key(name);
             let mut v = pair.init_value();
             match value {
-                SDValue::String(value) => v.set_string(&value),
+                SDValue::String(value) => v.set_string(value),
                 SDValue::Bool(value) => v.set_bool(*value),
                 SDValue::F64(value) => v.set_f64(*value),
                 SDValue::I64(value) => v.set_i64(*value),
@@ -99,7 +96,7 @@ fn build_record<T: Allocator>(
     }
     if !extra.is_empty() {
         let mut pairs = root.init_extra(extra.len() as u32);
-        for (i, &(ref name, ref value)) in extra.iter().enumerate() {
+        for (i, (name, value)) in extra.iter().enumerate() {
             let mut pair = pairs.reborrow().get((i) as u32);
             pair.set_key(name);
             let mut v = pair.init_value();
@@ -112,6 +109,7 @@ fn build_record<T: Allocator>(
 mod tests {
     use super::*;
     use crate::flowgger::record::{SDValue, StructuredData};
+    use crate::flowgger::record::{Severity, Timestamp};
 
     #[test]
     fn test_capnp_encode() {
@@ -119,25 +117,26 @@ mod tests {
         let encoder = CapnpEncoder::new(&config);
 
         let sd = StructuredData {
-            sd_id: Some("someid".to_string()),
-            pairs: vec![("_some_info".to_string(), SDValue::String("foo".to_string()))],
+            sd_id: Some("someid".into()),
+            pairs: vec![("_some_info".into(), SDValue::String("foo".into()))],
         };
         let record = Record {
-            ts: 1385053862.3072,
-            hostname: "example.org".to_string(),
+            ts: Timestamp::from_secs_f64(1385053862.3072),
+            utc_offset: None,
+            hostname: "example.org".into(),
             facility: None,
-            severity: Some(1),
-            appname: Some("appname".to_string()),
-            procid: Some("44".to_string()),
+            severity: Some(Severity::Alert),
+            appname: Some("appname".into()),
+            procid: Some("44".into()),
             msgid: None,
-            msg: Some("A short message that helps you identify what is going on".to_string()),
-            full_msg: Some("Backtrace here\n\nmore stuff".to_string()),
+            msg: Some("A short message that helps you identify what is going on".into()),
+            full_msg: Some("Backtrace here\n\nmore stuff".into()),
             sd: Some(vec![sd]),
         };
 
         assert_eq!(
             String::from_utf8_lossy(&encoder.encode(record).unwrap()),
-            "\u{0}\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{2}\u{0}\t\u{0}*������A�\u{1}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}!\u{0}\u{0}\u{0}b\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}B\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}\u{1a}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}!\u{0}\u{0}\u{0}�\u{1}\u{0}\u{0}=\u{0}\u{0}\u{0}�\u{0}\u{0}\u{0}I\u{0}\u{0}\u{0}:\u{0}\u{0}\u{0}I\u{0}\u{0}\u{0}\'\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}example.org\u{0}\u{0}\u{0}\u{0}\u{0}appname\u{0}44\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}A short message that helps you identify what is going on\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}Backtrace here\n\nmore stuff\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}someid\u{0}\u{0}\u{4}\u{0}\u{0}\u{0}\u{2}\u{0}\u{2}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{5}\u{0}\u{0}\u{0}Z\u{0}\u{0}\u{0}\t\u{0}\u{0}\u{0}\"\u{0}\u{0}\u{0}_some_info\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}foo\u{0}\u{0}\u{0}\u{0}\u{0}"
+            "\u{0}\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{2}\u{0}\t\u{0}*������A�\u{1}\u{1}\u{0}\u{0}\u{0}\u{0}\u{0}!\u{0}\u{0}\u{0}b\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}B\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}\u{1a}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}!\u{0}\u{0}\u{0}�\u{1}\u{0}\u{0}=\u{0}\u{0}\u{0}�\u{0}\u{0}\u{0}I\u{0}\u{0}\u{0}:\u{0}\u{0}\u{0}I\u{0}\u{0}\u{0}\'\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}example.org\u{0}\u{0}\u{0}\u{0}\u{0}appname\u{0}44\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}A short message that helps you identify what is going on\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}Backtrace here\n\nmore stuff\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}someid\u{0}\u{0}\u{4}\u{0}\u{0}\u{0}\u{2}\u{0}\u{2}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{5}\u{0}\u{0}\u{0}Z\u{0}\u{0}\u{0}\t\u{0}\u{0}\u{0}\"\u{0}\u{0}\u{0}_some_info\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}foo\u{0}\u{0}\u{0}\u{0}\u{0}"
         );
     }
 
@@ -155,21 +154,22 @@ mod tests {
         let encoder = CapnpEncoder::new(&config);
 
         let record = Record {
-            ts: 1385053862.3072,
-            hostname: "example.org".to_string(),
+            ts: Timestamp::from_secs_f64(1385053862.3072),
+            utc_offset: None,
+            hostname: "example.org".into(),
             facility: None,
-            severity: Some(1),
-            appname: Some("appname".to_string()),
-            procid: Some("44".to_string()),
+            severity: Some(Severity::Alert),
+            appname: Some("appname".into()),
+            procid: Some("44".into()),
             msgid: None,
-            msg: Some("A short message that helps you identify what is going on".to_string()),
-            full_msg: Some("Backtrace here\n\nmore stuff".to_string()),
+            msg: Some("A short message that helps you identify what is going on".into()),
+            full_msg: Some("Backtrace here\n\nmore stuff".into()),
             sd: None,
         };
 
         assert_eq!(
             String::from_utf8_lossy(&encoder.encode(record).unwrap()),
-            "\u{0}\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{2}\u{0}\t\u{0}*������A�\u{1}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}!\u{0}\u{0}\u{0}b\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}B\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}\u{1a}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}!\u{0}\u{0}\u{0}�\u{1}\u{0}\u{0}=\u{0}\u{0}\u{0}�\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}A\u{0}\u{0}\u{0}\'\u{0}\u{0}\u{0}example.org\u{0}\u{0}\u{0}\u{0}\u{0}appname\u{0}44\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}A short message that helps you identify what is going on\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}Backtrace here\n\nmore stuff\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{4}\u{0}\u{0}\u{0}\u{2}\u{0}\u{2}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{5}\u{0}\u{0}\u{0}R\u{0}\u{0}\u{0}\t\u{0}\u{0}\u{0}r\u{0}\u{0}\u{0}x-header1\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}header1 value\u{0}\u{0}\u{0}"
+            "\u{0}\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{2}\u{0}\t\u{0}*������A�\u{1}\u{1}\u{0}\u{0}\u{0}\u{0}\u{0}!\u{0}\u{0}\u{0}b\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}B\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}\u{1a}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}!\u{0}\u{0}\u{0}�\u{1}\u{0}\u{0}=\u{0}\u{0}\u{0}�\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}A\u{0}\u{0}\u{0}\'\u{0}\u{0}\u{0}example.org\u{0}\u{0}\u{0}\u{0}\u{0}appname\u{0}44\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}A short message that helps you identify what is going on\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}Backtrace here\n\nmore stuff\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{4}\u{0}\u{0}\u{0}\u{2}\u{0}\u{2}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{5}\u{0}\u{0}\u{0}R\u{0}\u{0}\u{0}\t\u{0}\u{0}\u{0}r\u{0}\u{0}\u{0}x-header1\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}header1 value\u{0}\u{0}\u{0}"
         );
     }
 
@@ -180,30 +180,31 @@ mod tests {
 
         let sd_vec = vec![
             StructuredData {
-                sd_id: Some("someid".to_string()),
-                pairs: vec![("_some_info".to_string(), SDValue::String("foo".to_string()))],
+                sd_id: Some("someid".into()),
+                pairs: vec![("_some_info".into(), SDValue::String("foo".into()))],
             },
             StructuredData {
-                sd_id: Some("someid2".to_string()),
-                pairs: vec![("info".to_string(), SDValue::F64(123.456))],
+                sd_id: Some("someid2".into()),
+                pairs: vec![("info".into(), SDValue::F64(123.456))],
             },
         ];
         let record = Record {
-            ts: 1385053862.3072,
-            hostname: "example.org".to_string(),
+            ts: Timestamp::from_secs_f64(1385053862.3072),
+            utc_offset: None,
+            hostname: "example.org".into(),
             facility: None,
-            severity: Some(1),
-            appname: Some("appname".to_string()),
-            procid: Some("44".to_string()),
+            severity: Some(Severity::Alert),
+            appname: Some("appname".into()),
+            procid: Some("44".into()),
             msgid: None,
-            msg: Some("A short message that helps you identify what is going on".to_string()),
-            full_msg: Some("Backtrace here\n\nmore stuff".to_string()),
+            msg: Some("A short message that helps you identify what is going on".into()),
+            full_msg: Some("Backtrace here\n\nmore stuff".into()),
             sd: Some(sd_vec),
         };
 
         assert_eq!(
             String::from_utf8_lossy(&encoder.encode(record).unwrap()),
-            "\u{0}\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{2}\u{0}\t\u{0}*������A�\u{1}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}!\u{0}\u{0}\u{0}b\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}B\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}\u{1a}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}!\u{0}\u{0}\u{0}�\u{1}\u{0}\u{0}=\u{0}\u{0}\u{0}�\u{0}\u{0}\u{0}I\u{0}\u{0}\u{0}:\u{0}\u{0}\u{0}I\u{0}\u{0}\u{0}\'\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}example.org\u{0}\u{0}\u{0}\u{0}\u{0}appname\u{0}44\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}A short message that helps you identify what is going on\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}Backtrace here\n\nmore stuff\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}someid\u{0}\u{0}\u{4}\u{0}\u{0}\u{0}\u{2}\u{0}\u{2}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{5}\u{0}\u{0}\u{0}Z\u{0}\u{0}\u{0}\t\u{0}\u{0}\u{0}\"\u{0}\u{0}\u{0}_some_info\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}foo\u{0}\u{0}\u{0}\u{0}\u{0}"
+            "\u{0}\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{2}\u{0}\t\u{0}*������A�\u{1}\u{1}\u{0}\u{0}\u{0}\u{0}\u{0}!\u{0}\u{0}\u{0}b\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}B\u{0}\u{0}\u{0}%\u{0}\u{0}\u{0}\u{1a}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}!\u{0}\u{0}\u{0}�\u{1}\u{0}\u{0}=\u{0}\u{0}\u{0}�\u{0}\u{0}\u{0}I\u{0}\u{0}\u{0}:\u{0}\u{0}\u{0}I\u{0}\u{0}\u{0}\'\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}example.org\u{0}\u{0}\u{0}\u{0}\u{0}appname\u{0}44\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}A short message that helps you identify what is going on\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}Backtrace here\n\nmore stuff\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}someid\u{0}\u{0}\u{4}\u{0}\u{0}\u{0}\u{2}\u{0}\u{2}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}\u{5}\u{0}\u{0}\u{0}Z\u{0}\u{0}\u{0}\t\u{0}\u{0}\u{0}\"\u{0}\u{0}\u{0}_some_info\u{0}\u{0}\u{0}\u{0}\u{0}\u{0}foo\u{0}\u{0}\u{0}\u{0}\u{0}"
         );
     }
 }
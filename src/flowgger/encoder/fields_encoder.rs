@@ -0,0 +1,176 @@
+use super::Encoder;
+use crate::flowgger::record::Record;
+use std::convert::TryFrom;
+use std::sync::Arc;
+use time::format_description::well_known::Rfc3339;
+
+const MISSING_FIELD: u32 = u32::MAX;
+
+/// Values of the fields (`None` if the record didn't have them), and the encoded record
+pub type SplitFields<'a> = (Vec<Option<&'a [u8]>>, &'a [u8]);
+
+/// Encoder wrapper prepending the values of some fields of the record to the encoded record, for outputs
+/// that need them (i.e. to set Kafka headers) while they only receive encoded records.
+///
+/// Fields are the ones of `Record::field()`, along with "ts" (the timestamp, in RFC3339 format), "msg" and
+/// "full_msg". Every field is stored as a 32-bit little-endian length followed by its value, or `u32::MAX` if the record
+/// doesn't have it. Outputs get them back with `split_fields()`.
+pub struct FieldsEncoder {
+    encoder: Box<dyn Encoder + Send>,
+    fields: Arc<[String]>,
+}
+
+impl Clone for FieldsEncoder {
+    fn clone(&self) -> FieldsEncoder {
+        FieldsEncoder {
+            encoder: self.encoder.clone_boxed(),
+            fields: Arc::clone(&self.fields),
+        }
+    }
+}
+
+impl FieldsEncoder {
+    /// # Returns
+    /// The encoder as is if no fields are required, or wrapped so that it prepends them
+    pub fn wrap(encoder: Box<dyn Encoder + Send>, fields: Vec<String>) -> Box<dyn Encoder + Send> {
+        if fields.is_empty() {
+            return encoder;
+        }
+        Box::new(FieldsEncoder {
+            encoder,
+            fields: fields.into(),
+        })
+    }
+}
+
+impl Encoder for FieldsEncoder {
+    fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
+        let values: Vec<_> = self
+            .fields
+            .iter()
+            .map(|name| field(&record, name))
+            .collect();
+        let encoded = self.encoder.encode(record)?;
+        let fields_len: usize = values.iter().flatten().map(|value| 4 + value.len()).sum();
+        let mut res = Vec::with_capacity(fields_len + 4 * values.len() + encoded.len());
+        for value in &values {
+            match value {
+                Some(value) => {
+                    let len = u32::try_from(value.len()).map_err(|_| "Field too large")?;
+                    res.extend_from_slice(&len.to_le_bytes());
+                    res.extend_from_slice(value.as_bytes());
+                }
+                None => res.extend_from_slice(&MISSING_FIELD.to_le_bytes()),
+            }
+        }
+        res.extend_from_slice(&encoded);
+        Ok(res)
+    }
+}
+
+fn field(record: &Record, name: &str) -> Option<String> {
+    match name {
+        "ts" => record
+            .ts
+            .to_offset_datetime()
+            .ok()
+            .and_then(|ts| ts.format(&Rfc3339).ok()),
+        "msg" => record.msg.as_deref().map(str::to_owned),
+        "full_msg" => record.full_msg.as_deref().map(str::to_owned),
+        _ => record.field(name),
+    }
+}
+
+/// Prefix of a message without any of `count` fields, for messages sent to outputs with record fields
+/// without going through `FieldsEncoder`
+#[cfg(feature = "syslog-sign")]
+pub fn missing_fields(count: usize) -> Vec<u8> {
+    MISSING_FIELD.to_le_bytes().repeat(count)
+}
+
+/// Split a record produced by `FieldsEncoder` into the values of its `count` fields, and the encoded record
+#[cfg_attr(
+    not(any(
+        feature = "kafka-output",
+        feature = "mqtt",
+        feature = "file",
+        feature = "postgres-output",
+        feature = "sqlite-output"
+    )),
+    allow(dead_code)
+)]
+pub fn split_fields(bytes: &[u8], count: usize) -> Result<SplitFields<'_>, &'static str> {
+    let mut values = Vec::with_capacity(count);
+    let mut rest = bytes;
+    for _ in 0..count {
+        if rest.len() < 4 {
+            return Err("Truncated record fields");
+        }
+        let (len, tail) = rest.split_at(4);
+        let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]);
+        if len == MISSING_FIELD {
+            values.push(None);
+            rest = tail;
+            continue;
+        }
+        let len = len as usize;
+        if tail.len() < len {
+            return Err("Truncated record fields");
+        }
+        let (value, tail) = tail.split_at(len);
+        values.push(Some(value));
+        rest = tail;
+    }
+    Ok((values, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::record::{Severity, Timestamp};
+    use crate::flowgger::utils::test_utils::record_test_utils::MsgEncoder;
+
+    fn record() -> Record<'static> {
+        Record::builder()
+            .ts(Timestamp::default())
+            .hostname("example.org")
+            .severity(Severity::Error)
+            .msg("message")
+            .build()
+    }
+
+    #[test]
+    fn test_fields_encoder() {
+        let fields = vec![
+            "hostname".to_owned(),
+            "appname".to_owned(),
+            "severity".to_owned(),
+            "ts".to_owned(),
+            "msg".to_owned(),
+        ];
+        let encoded = FieldsEncoder::wrap(Box::new(MsgEncoder), fields)
+            .encode(record())
+            .unwrap();
+        let (values, payload) = split_fields(&encoded, 5).unwrap();
+        assert_eq!(
+            values,
+            vec![
+                Some(&b"example.org"[..]),
+                None,
+                Some(&b"3"[..]),
+                Some(&b"1970-01-01T00:00:00Z"[..]),
+                Some(&b"message"[..])
+            ]
+        );
+        assert_eq!(payload, b"message");
+        assert!(split_fields(&encoded[..6], 3).is_err());
+    }
+
+    #[test]
+    fn test_fields_encoder_no_fields() {
+        let encoded = FieldsEncoder::wrap(Box::new(MsgEncoder), Vec::new())
+            .encode(record())
+            .unwrap();
+        assert_eq!(encoded, b"message");
+    }
+}
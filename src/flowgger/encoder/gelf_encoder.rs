@@ -1,15 +1,34 @@
-use super::Encoder;
+use super::{config_get_syslog_names, Encoder, SyslogNames};
 use crate::flowgger::config::Config;
-use crate::flowgger::record::{Record, SDValue};
+use crate::flowgger::record::{Record, SDValue, GELF_DEFAULT_SD_ID};
+use serde::Deserialize;
 use serde_json;
 use serde_json::builder::ObjectBuilder;
 use serde_json::value::Value;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use time::format_description::well_known::Rfc3339;
+
+/// Version of the Elastic Common Schema the field names are taken from
+const ECS_VERSION: &str = "8.11.0";
+
+/// Settings of the GELF encoder, in the `[output]` section
+#[derive(Deserialize)]
+pub struct GelfSettings {
+    #[serde(default)]
+    gelf_extra: BTreeMap<String, String>,
+    gelf_sd_id: Option<String>,
+    ecs: Option<bool>,
+}
 
 #[derive(Clone)]
 /// Encoder for GELF Json format
 /// https://docs.graylog.org/en/3.1/pages/gelf.html
 pub struct GelfEncoder {
     extra: Vec<(String, String)>,
+    sd_id: String,
+    syslog_names: SyslogNames,
+    ecs: bool,
 }
 
 impl GelfEncoder {
@@ -19,32 +38,32 @@ impl GelfEncoder {
     /// # Parameters
     ///
     /// - `config`: a configuration file that can contain an output.gelf_extra section of elements,
-    /// or be empty. if the gelf_extra section is present it needs to contain a list of `key =
-    /// "value"` pairs that will be added to the resulting json or overwritten if already present
+    ///   or be empty. if the gelf_extra section is present it needs to contain a list of `key =
+    /// "value"` pairs that will be added to the resulting json or overwritten if already present.
+    ///   `output.gelf_sd_id` is the SD-ID of the structured data written as plain additional fields, without
+    ///   an "sd_id" field, "gelf@32473" by default as with the GELF decoder.
+    ///   `output.syslog_names` set to "add" or "replace" adds the `_facility_name` and `_level_name` fields. The
+    ///   level stays numeric, as required by GELF.
+    ///   `output.ecs` set to true writes the fields with their Elastic Common Schema names (`@timestamp`,
+    ///   `host.name`, `log.level`, `process.pid`, `event.original`...) instead of the GELF ones, structured data
+    ///   pairs becoming `labels`, for Elasticsearch and OpenSearch dashboards expecting ECS documents.
     ///
     /// # Panics
     ///
-    /// All the possible failures are relative to parsing the configuration file
-    /// - `output.gelf_extra must be a list of key/value pairs`
-    /// - `output.gelf_extra values must be strings`
+    /// All the possible failures are relative to parsing the configuration file, i.e. `output.gelf_extra`
+    /// not being a table of strings
     pub fn new(config: &Config) -> GelfEncoder {
-        let extra = match config.lookup("output.gelf_extra") {
-            None => Vec::new(),
-            Some(extra) => extra
-                .as_table()
-                .expect("output.gelf_extra must be a list of key/value pairs")
-                .iter()
-                .map(|(k, v)| {
-                    (
-                        k.to_owned(),
-                        v.as_str()
-                            .expect("output.gelf_extra values must be strings")
-                            .to_owned(),
-                    )
-                })
-                .collect(),
-        };
-        GelfEncoder { extra }
+        let settings: GelfSettings = config.settings("output");
+        let extra = settings.gelf_extra.into_iter().collect();
+        let sd_id = settings
+            .gelf_sd_id
+            .unwrap_or_else(|| GELF_DEFAULT_SD_ID.to_owned());
+        GelfEncoder {
+            extra,
+            sd_id,
+            syslog_names: config_get_syslog_names(config),
+            ecs: settings.ecs.unwrap_or(false),
+        }
     }
 }
 
@@ -57,6 +76,9 @@ impl Encoder for GelfEncoder {
     /// - `Ok` Containing a byte vector rapresenting a valid GELF JSON
     /// - `Err` if the Record could not be serialized to a valid JSON
     fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
+        if self.ecs {
+            return self.encode_ecs(record);
+        }
         let mut map = ObjectBuilder::new()
             .insert("version".to_owned(), Value::String("1.1".to_owned()))
             .insert(
@@ -64,46 +86,58 @@ impl Encoder for GelfEncoder {
                 Value::String(if record.hostname.is_empty() {
                     "unknown".to_owned()
                 } else {
-                    record.hostname
+                    record.hostname.into_owned()
                 }),
             )
             .insert(
                 "short_message".to_owned(),
-                Value::String(record.msg.unwrap_or_else(|| "-".to_owned())),
+                Value::String(record.msg.map_or_else(|| "-".to_owned(), Cow::into_owned)),
             )
-            .insert("timestamp".to_owned(), Value::F64(record.ts));
+            .insert("timestamp".to_owned(), Value::F64(record.ts.as_secs_f64()));
         if let Some(severity) = record.severity {
-            map = map.insert("level".to_owned(), Value::U64(u64::from(severity)));
+            map = map.insert("level".to_owned(), Value::U64(severity as u64));
+        }
+        if self.syslog_names != SyslogNames::Numeric {
+            if let Some(facility) = record.facility {
+                map = map.insert(
+                    "_facility_name".to_owned(),
+                    Value::String(facility.name().to_owned()),
+                );
+            }
+            if let Some(severity) = record.severity {
+                map = map.insert(
+                    "_level_name".to_owned(),
+                    Value::String(severity.name().to_owned()),
+                );
+            }
         }
         if let Some(full_msg) = record.full_msg {
-            map = map.insert("full_message".to_owned(), Value::String(full_msg));
+            map = map.insert(
+                "full_message".to_owned(),
+                Value::String(full_msg.into_owned()),
+            );
         }
         if let Some(appname) = record.appname {
-            map = map.insert("application_name".to_owned(), Value::String(appname));
+            map = map.insert(
+                "application_name".to_owned(),
+                Value::String(appname.into_owned()),
+            );
         }
         if let Some(procid) = record.procid {
-            map = map.insert("process_id".to_owned(), Value::String(procid));
+            map = map.insert("process_id".to_owned(), Value::String(procid.into_owned()));
         }
         if let Some(sd_vec) = record.sd {
-            for &ref sd in &sd_vec {
+            for sd in &sd_vec {
                 // Warning: Gelf doesn't have a concept of structued data. In case there are
                 // several, all their attributes will be aded as fields. So if several structured
                 // data have the same key, only the last value will show as it will overwrite the
                 // others. We could use the sd_id to prefix the field to sove this but this is a
                 // breaking change.
-                if let Some(sd_id) = &sd.sd_id {
+                if let Some(sd_id) = sd.sd_id.as_ref().filter(|sd_id| **sd_id != self.sd_id) {
                     map = map.insert("sd_id".to_owned(), Value::String(sd_id.to_string()));
                 }
                 for (name, value) in &sd.pairs {
-                    let value = match value {
-                        SDValue::String(value) => Value::String(value.to_string()),
-                        SDValue::Bool(value) => Value::Bool(*value),
-                        SDValue::F64(value) => Value::F64(*value),
-                        SDValue::I64(value) => Value::I64(*value),
-                        SDValue::U64(value) => Value::U64(*value),
-                        SDValue::Null => Value::Null,
-                    };
-                    map = map.insert(name, value);
+                    map = map.insert(name.as_ref(), json_value(value));
                 }
             }
         }
@@ -115,29 +149,185 @@ impl Encoder for GelfEncoder {
     }
 }
 
+impl GelfEncoder {
+    /// Encode a record as an Elastic Common Schema document, with dotted field names
+    fn encode_ecs(&self, record: Record) -> Result<Vec<u8>, &'static str> {
+        let ts = record
+            .ts
+            .to_offset_datetime()
+            .ok()
+            .and_then(|ts| ts.format(&Rfc3339).ok())
+            .ok_or("Failed to parse date as Rfc3339 format")?;
+        let mut map = ObjectBuilder::new()
+            .insert("@timestamp".to_owned(), Value::String(ts))
+            .insert(
+                "ecs.version".to_owned(),
+                Value::String(ECS_VERSION.to_owned()),
+            );
+        if !record.hostname.is_empty() {
+            map = map.insert(
+                "host.name".to_owned(),
+                Value::String(record.hostname.into_owned()),
+            );
+        }
+        if let Some(msg) = record.msg {
+            map = map.insert("message".to_owned(), Value::String(msg.into_owned()));
+        }
+        if let Some(full_msg) = record.full_msg {
+            map = map.insert(
+                "event.original".to_owned(),
+                Value::String(full_msg.into_owned()),
+            );
+        }
+        if let Some(severity) = record.severity {
+            map = map
+                .insert(
+                    "log.level".to_owned(),
+                    Value::String(severity.name().to_owned()),
+                )
+                .insert(
+                    "log.syslog.severity.code".to_owned(),
+                    Value::U64(severity as u64),
+                )
+                .insert(
+                    "log.syslog.severity.name".to_owned(),
+                    Value::String(severity.name().to_owned()),
+                );
+        }
+        if let Some(facility) = record.facility {
+            map = map
+                .insert(
+                    "log.syslog.facility.code".to_owned(),
+                    Value::U64(facility as u64),
+                )
+                .insert(
+                    "log.syslog.facility.name".to_owned(),
+                    Value::String(facility.name().to_owned()),
+                );
+        }
+        if let Some(appname) = record.appname {
+            map = map.insert(
+                "process.name".to_owned(),
+                Value::String(appname.into_owned()),
+            );
+        }
+        // `process.pid` is numeric, other process identifiers are kept as syslog fields
+        if let Some(procid) = record.procid {
+            map = match procid.parse() {
+                Ok(pid) => map.insert("process.pid".to_owned(), Value::U64(pid)),
+                Err(_) => map.insert(
+                    "log.syslog.procid".to_owned(),
+                    Value::String(procid.into_owned()),
+                ),
+            };
+        }
+        if let Some(msgid) = record.msgid {
+            map = map.insert(
+                "log.syslog.msgid".to_owned(),
+                Value::String(msgid.into_owned()),
+            );
+        }
+        for sd in record.sd.iter().flatten() {
+            for (name, value) in &sd.pairs {
+                let name = name.strip_prefix('_').unwrap_or(name);
+                map = map.insert(format!("labels.{}", name), json_value(value));
+            }
+        }
+        for (name, value) in self.extra.iter().cloned() {
+            map = map.insert(name, Value::String(value));
+        }
+        let json = serde_json::to_vec(&map.build()).or(Err("Unable to serialize to JSON"))?;
+        Ok(json)
+    }
+}
+
+fn json_value(value: &SDValue) -> Value {
+    match value {
+        SDValue::String(value) => Value::String(value.to_string()),
+        SDValue::Bool(value) => Value::Bool(*value),
+        SDValue::F64(value) => Value::F64(*value),
+        SDValue::I64(value) => Value::I64(*value),
+        SDValue::U64(value) => Value::U64(*value),
+        SDValue::Null => Value::Null,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::flowgger::record::{Facility, Severity, Timestamp};
     use crate::flowgger::record::{SDValue, StructuredData};
 
+    #[test]
+    fn test_gelf_encode_syslog_names() {
+        let expected_msg = r#"{"_facility_name":"local0","_level_name":"alert","host":"example.org","level":1,"short_message":"-","timestamp":1385053862.3072,"version":"1.1"}"#;
+        let cfg = Config::from_string("[output]\nsyslog_names = \"replace\"\n").unwrap();
+        let record = Record {
+            ts: Timestamp::from_secs_f64(1385053862.3072),
+            utc_offset: None,
+            hostname: "example.org".into(),
+            facility: Some(Facility::Local0),
+            severity: Some(Severity::Alert),
+            appname: None,
+            procid: None,
+            msgid: None,
+            msg: None,
+            full_msg: None,
+            sd: None,
+        };
+        let res = GelfEncoder::new(&cfg).encode(record).unwrap();
+        assert_eq!(String::from_utf8_lossy(&res), expected_msg);
+    }
+
+    #[test]
+    fn test_gelf_encode_ecs() {
+        let expected_msg = r#"{"@timestamp":"2013-11-21T17:11:02.3072Z","ecs.version":"8.11.0","event.dataset":"app.logs","event.original":"<9>1 full","host.name":"example.org","labels.user_id":42,"log.level":"alert","log.syslog.facility.code":1,"log.syslog.facility.name":"user","log.syslog.msgid":"login","log.syslog.severity.code":1,"log.syslog.severity.name":"alert","message":"short","process.name":"appname","process.pid":44}"#;
+        let config = Config::from_string(
+            "[output]\necs = true\n[output.gelf_extra]\n\"event.dataset\" = \"app.logs\"",
+        )
+        .unwrap();
+        let sd = StructuredData {
+            sd_id: Some("someid".into()),
+            pairs: vec![("_user_id".into(), SDValue::U64(42))],
+        };
+        let record = Record::builder()
+            .ts(Timestamp::from_secs_f64(1385053862.3072))
+            .hostname("example.org")
+            .facility(Facility::User)
+            .severity(Severity::Alert)
+            .appname("appname")
+            .procid("44")
+            .msgid("login")
+            .msg("short")
+            .full_msg("<9>1 full")
+            .sd(sd)
+            .build();
+        let encoder = GelfEncoder::new(&config);
+        assert_eq!(
+            String::from_utf8_lossy(&encoder.encode(record).unwrap()),
+            expected_msg
+        );
+    }
+
     #[test]
     fn test_gelf_encode() {
         let expected_msg = r#"{"_some_info":"foo","application_name":"appname","full_message":"Backtrace here\n\nmore stuff","host":"example.org","level":1,"process_id":"44","sd_id":"someid","secret-token":"secret","short_message":"A short message that helps you identify what is going on","timestamp":1385053862.3072,"version":"1.1"}"#;
         let config = Config::from_string("[output.gelf_extra]\nsecret-token = \"secret\"").unwrap();
         let sd = StructuredData {
-            sd_id: Some("someid".to_string()),
-            pairs: vec![("_some_info".to_string(), SDValue::String("foo".to_string()))],
+            sd_id: Some("someid".into()),
+            pairs: vec![("_some_info".into(), SDValue::String("foo".into()))],
         };
         let record = Record {
-            ts: 1385053862.3072,
-            hostname: "example.org".to_string(),
+            ts: Timestamp::from_secs_f64(1385053862.3072),
+            utc_offset: None,
+            hostname: "example.org".into(),
             facility: None,
-            severity: Some(1),
-            appname: Some("appname".to_string()),
-            procid: Some("44".to_string()),
+            severity: Some(Severity::Alert),
+            appname: Some("appname".into()),
+            procid: Some("44".into()),
             msgid: None,
-            msg: Some("A short message that helps you identify what is going on".to_string()),
-            full_msg: Some("Backtrace here\n\nmore stuff".to_string()),
+            msg: Some("A short message that helps you identify what is going on".into()),
+            full_msg: Some("Backtrace here\n\nmore stuff".into()),
             sd: Some(vec![sd]),
         };
         let encoder = GelfEncoder::new(&config);
@@ -152,14 +342,15 @@ mod tests {
         let expected_msg = r#"{"host":"unknown","level":1,"short_message":"A short message that helps you identify what is going on","timestamp":1385053862.3072,"version":"1.1"}"#;
         let config = Config::from_string("").unwrap();
         let record = Record {
-            ts: 1385053862.3072,
-            hostname: "".to_string(),
+            ts: Timestamp::from_secs_f64(1385053862.3072),
+            utc_offset: None,
+            hostname: "".into(),
             facility: None,
-            severity: Some(1),
+            severity: Some(Severity::Alert),
             appname: None,
             procid: None,
             msgid: None,
-            msg: Some("A short message that helps you identify what is going on".to_string()),
+            msg: Some("A short message that helps you identify what is going on".into()),
             full_msg: None,
             sd: None,
         };
@@ -176,16 +367,17 @@ mod tests {
         let config = Config::from_string("[output.gelf_extra]\na_key = \"bar\"").unwrap();
         let mut sd = StructuredData::new(None);
         sd.pairs
-            .push(("a_key".to_string(), SDValue::String("foo".to_string())));
+            .push(("a_key".into(), SDValue::String("foo".into())));
         let record = Record {
-            ts: 1385053862.3072,
-            hostname: "".to_string(),
+            ts: Timestamp::from_secs_f64(1385053862.3072),
+            utc_offset: None,
+            hostname: "".into(),
             facility: None,
-            severity: Some(1),
+            severity: Some(Severity::Alert),
             appname: None,
             procid: None,
             msgid: None,
-            msg: Some("A short message that helps you identify what is going on".to_string()),
+            msg: Some("A short message that helps you identify what is going on".into()),
             full_msg: None,
             sd: Some(vec![sd]),
         };
@@ -196,15 +388,35 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "rfc5424")]
+    #[test]
+    fn test_gelf_rfc5424_round_trip() {
+        use crate::flowgger::decoder::{Decoder, GelfDecoder, RFC5424Decoder};
+        use crate::flowgger::encoder::RFC5424Encoder;
+
+        let config = Config::from_string("").unwrap();
+        let msg = r#"{"host":"example.org","short_message":"hello","timestamp":1385053862.307,"_some_info":"a \"quoted\" [value]"}"#;
+        let record = GelfDecoder::new(&config).decode(msg).unwrap();
+        let rfc5424 = RFC5424Encoder::new(&config).encode(record).unwrap();
+        let rfc5424 = String::from_utf8(rfc5424).unwrap();
+        assert!(rfc5424.contains(r#"[gelf@32473 some_info="a \"quoted\" [value\]"]"#));
+
+        let record = RFC5424Decoder::new(&config).decode(&rfc5424).unwrap();
+        let gelf = GelfEncoder::new(&config).encode(record).unwrap();
+        let gelf = String::from_utf8(gelf).unwrap();
+        assert!(gelf.contains(r#""_some_info":"a \"quoted\" [value]""#));
+        assert!(!gelf.contains("sd_id"));
+    }
+
     #[test]
-    #[should_panic(expected = "output.gelf_extra must be a list of key/value pairs")]
+    #[should_panic(expected = "expected a map for key `output.gelf_extra`")]
     fn test_gelf_encoder_config_extra_should_be_section() {
         let _encoder =
             GelfEncoder::new(&Config::from_string("[output]\ngelf_extra = \"bar\"").unwrap());
     }
 
     #[test]
-    #[should_panic(expected = "output.gelf_extra values must be strings")]
+    #[should_panic(expected = "expected a string for key `output.gelf_extra._some_info`")]
     fn test_gelf_encoder_config_extra_bad_type() {
         let _encoder =
             GelfEncoder::new(&Config::from_string("[output.gelf_extra]\n_some_info = 42").unwrap());
@@ -216,24 +428,25 @@ mod tests {
         let config = Config::from_string("[output.gelf_extra]\nsecret-token = \"secret\"").unwrap();
         let sd_vec = vec![
             StructuredData {
-                sd_id: Some("someid".to_string()),
-                pairs: vec![("_some_info".to_string(), SDValue::String("foo".to_string()))],
+                sd_id: Some("someid".into()),
+                pairs: vec![("_some_info".into(), SDValue::String("foo".into()))],
             },
             StructuredData {
-                sd_id: Some("someid2".to_string()),
-                pairs: vec![("info".to_string(), SDValue::F64(123.456))],
+                sd_id: Some("someid2".into()),
+                pairs: vec![("info".into(), SDValue::F64(123.456))],
             },
         ];
         let record = Record {
-            ts: 1385053862.3072,
-            hostname: "example.org".to_string(),
+            ts: Timestamp::from_secs_f64(1385053862.3072),
+            utc_offset: None,
+            hostname: "example.org".into(),
             facility: None,
-            severity: Some(1),
-            appname: Some("appname".to_string()),
-            procid: Some("44".to_string()),
+            severity: Some(Severity::Alert),
+            appname: Some("appname".into()),
+            procid: Some("44".into()),
             msgid: None,
-            msg: Some("A short message that helps you identify what is going on".to_string()),
-            full_msg: Some("Backtrace here\n\nmore stuff".to_string()),
+            msg: Some("A short message that helps you identify what is going on".into()),
+            full_msg: Some("Backtrace here\n\nmore stuff".into()),
             sd: Some(sd_vec),
         };
         let encoder = GelfEncoder::new(&config);
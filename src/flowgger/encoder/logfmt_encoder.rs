@@ -0,0 +1,165 @@
+use super::{config_get_syslog_names, Encoder, SyslogNames};
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue};
+use time::format_description::well_known::Rfc3339;
+
+/// Encoder for logfmt records: `time`, `host`, `level`, `msg`, `app`, `pid` and `msgid` keys, followed by
+/// the structured data pairs. Severities are written as syslog keywords.
+#[derive(Clone)]
+pub struct LogfmtEncoder {
+    syslog_names: SyslogNames,
+}
+
+impl LogfmtEncoder {
+    /// # Parameters
+    /// - 'output.syslog_names': Optional. "numeric" (default) writes the facility as a code, "add" adds its name
+    ///   as `facility_name`, and "replace" writes its name instead. Severities are always names.
+    pub fn new(config: &Config) -> LogfmtEncoder {
+        LogfmtEncoder {
+            syslog_names: config_get_syslog_names(config),
+        }
+    }
+}
+
+struct LogfmtString {
+    out: String,
+}
+
+impl LogfmtString {
+    fn new() -> LogfmtString {
+        LogfmtString { out: String::new() }
+    }
+
+    fn insert(&mut self, key: &str, value: &str) {
+        if !self.out.is_empty() {
+            self.out.push(' ');
+        }
+        for c in key.chars() {
+            self.out.push(if c <= ' ' || c == '=' || c == '"' {
+                '_'
+            } else {
+                c
+            });
+        }
+        self.out.push('=');
+        if !value.is_empty()
+            && !value
+                .chars()
+                .any(|c| c <= ' ' || c == '=' || c == '"' || c == '\\')
+        {
+            self.out.push_str(value);
+            return;
+        }
+        self.out.push('"');
+        for c in value.chars() {
+            match c {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                c if c < ' ' => self.out.push(' '),
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+    }
+
+    fn finalize(self) -> String {
+        self.out
+    }
+}
+
+impl Encoder for LogfmtEncoder {
+    fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
+        let mut res = LogfmtString::new();
+        let date = record
+            .ts
+            .to_offset_datetime()
+            .or(Err("Failed to parse date"))?
+            .format(&Rfc3339)
+            .or(Err("Failed to parse date as Rfc3339 format"))?;
+        res.insert("time", &date);
+        res.insert("host", &record.hostname);
+        if let Some(severity) = record.severity {
+            res.insert("level", severity.name());
+        }
+        if let Some(msg) = record.msg {
+            res.insert("msg", &msg);
+        }
+        if let Some(appname) = record.appname {
+            res.insert("app", &appname);
+        }
+        if let Some(procid) = record.procid {
+            res.insert("pid", &procid);
+        }
+        if let Some(msgid) = record.msgid {
+            res.insert("msgid", &msgid);
+        }
+        if let Some(facility) = record.facility {
+            match self.syslog_names {
+                SyslogNames::Numeric => res.insert("facility", &facility.to_string()),
+                SyslogNames::Add => {
+                    res.insert("facility", &facility.to_string());
+                    res.insert("facility_name", facility.name());
+                }
+                SyslogNames::Replace => res.insert("facility", facility.name()),
+            }
+        }
+        if let Some(sd_vec) = record.sd {
+            // As with LTSV, pairs of all the structured data elements are written as keys, and their
+            // SD-ID is lost
+            for sd in &sd_vec {
+                for (name, value) in &sd.pairs {
+                    let name = name.strip_prefix('_').unwrap_or(name);
+                    match *value {
+                        SDValue::String(ref value) => res.insert(name, value),
+                        SDValue::Bool(ref value) => res.insert(name, &value.to_string()),
+                        SDValue::F64(ref value) => res.insert(name, &value.to_string()),
+                        SDValue::I64(ref value) => res.insert(name, &value.to_string()),
+                        SDValue::U64(ref value) => res.insert(name, &value.to_string()),
+                        SDValue::Null => res.insert(name, ""),
+                    }
+                }
+            }
+        }
+        Ok(res.finalize().into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::record::StructuredData;
+    use crate::flowgger::record::{Severity, Timestamp};
+
+    #[test]
+    fn test_logfmt_encoder() {
+        let config = Config::from_string("[output]\nformat = \"logfmt\"\n").unwrap();
+        let record = Record {
+            ts: Timestamp::from_secs_f64(1438790025.637),
+            utc_offset: None,
+            hostname: "example.org".into(),
+            facility: None,
+            severity: Some(Severity::Warning),
+            appname: Some("app".into()),
+            procid: None,
+            msgid: None,
+            msg: Some("disk \"/\" is\talmost full".into()),
+            full_msg: None,
+            sd: Some(vec![StructuredData {
+                sd_id: Some("someid".into()),
+                pairs: vec![
+                    ("_used".into(), SDValue::U64(93)),
+                    ("_path".into(), SDValue::String("C:\\".into())),
+                    ("_bad key".into(), SDValue::Null),
+                ],
+            }]),
+        };
+        let res = LogfmtEncoder::new(&config).encode(record).unwrap();
+        assert_eq!(
+            String::from_utf8(res).unwrap(),
+            r#"time=2015-08-05T15:53:45.637Z host=example.org level=warning msg="disk \"/\" is\talmost full" app=app used=93 path="C:\\" bad_key="""#
+        );
+    }
+}
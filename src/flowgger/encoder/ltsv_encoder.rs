@@ -1,31 +1,89 @@
-use super::Encoder;
+use super::{config_get_syslog_names, Encoder, SyslogNames};
 use crate::flowgger::config::Config;
 use crate::flowgger::record::{Record, SDValue};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Settings of the LTSV encoder, in the `[output]` section
+#[derive(Deserialize)]
+pub struct LTSVSettings {
+    #[serde(default)]
+    ltsv_extra: BTreeMap<String, String>,
+    ltsv_fields: Option<Vec<String>>,
+}
 
 #[derive(Clone)]
 pub struct LTSVEncoder {
     extra: Vec<(String, String)>,
+    fields: Option<Vec<(String, String)>>,
+    syslog_names: SyslogNames,
 }
 
 impl LTSVEncoder {
+    /// # Parameters
+    /// - 'output.ltsv_extra':  Optional. Fields added to every record, as label/value pairs.
+    /// - 'output.ltsv_fields': Optional. Fields to write, in order, instead of every field of the record. Every
+    ///   entry is either the name of a field, also used as its label, or "label=field". Fields are "time",
+    ///   "host", "message", "full_message", "level", "facility", "appname", "procid", "msgid", or structured
+    ///   data names. Missing fields are skipped, and 'output.ltsv_extra' fields are written after them.
+    /// - 'output.syslog_names': Optional. "numeric" (default), "add" to add the "facility_name" and "level_name"
+    ///   fields, or "replace" to write the names in "facility" and "level". The names can also be selected as
+    ///   "facility_name" and "level_name" in 'output.ltsv_fields'.
     pub fn new(config: &Config) -> LTSVEncoder {
-        let extra = match config.lookup("output.ltsv_extra") {
-            None => Vec::new(),
-            Some(extra) => extra
-                .as_table()
-                .expect("output.ltsv_extra must be a list of key/value pairs")
-                .iter()
-                .map(|(k, v)| {
-                    (
-                        k.to_owned(),
-                        v.as_str()
-                            .expect("output.ltsv_extra values must be strings")
-                            .to_owned(),
-                    )
+        let settings: LTSVSettings = config.settings("output");
+        let extra = settings.ltsv_extra.into_iter().collect();
+        let fields = settings.ltsv_fields.map(|fields| {
+            fields
+                .into_iter()
+                .map(|field| match field.split_once('=') {
+                    Some((label, name)) => (label.to_owned(), name.to_owned()),
+                    None => (field.clone(), field),
                 })
-                .collect(),
-        };
-        LTSVEncoder { extra }
+                .collect()
+        });
+        LTSVEncoder {
+            extra,
+            fields,
+            syslog_names: config_get_syslog_names(config),
+        }
+    }
+
+    /// Write a facility or a severity as a code, a name, or both
+    fn insert_code(&self, res: &mut LTSVString, key: &str, code: u8, name: &str) {
+        match self.syslog_names {
+            SyslogNames::Numeric => res.insert(key, &code.to_string()),
+            SyslogNames::Add => {
+                res.insert(key, &code.to_string());
+                res.insert(&format!("{}_name", key), name);
+            }
+            SyslogNames::Replace => res.insert(key, name),
+        }
+    }
+
+    fn encode_fields(&self, record: &Record, fields: &[(String, String)]) -> LTSVString {
+        let mut res = LTSVString::new();
+        for (label, name) in fields {
+            let value = match name.as_str() {
+                "time" => Some(record.ts.to_string()),
+                "host" => Some(record.hostname.to_string()),
+                "message" => record.msg.as_deref().map(str::to_owned),
+                "full_message" => record.full_msg.as_deref().map(str::to_owned),
+                "level" if self.syslog_names == SyslogNames::Replace => {
+                    record.severity.map(|severity| severity.name().to_owned())
+                }
+                "level" => record.severity.map(|severity| severity.to_string()),
+                "level_name" => record.severity.map(|severity| severity.name().to_owned()),
+                "facility" if self.syslog_names == SyslogNames::Replace => {
+                    record.facility.map(|facility| facility.name().to_owned())
+                }
+                "facility_name" => record.facility.map(|facility| facility.name().to_owned()),
+                name => record.field(name),
+            };
+            if let Some(value) = value {
+                res.insert(label, &value);
+            }
+        }
+        res
     }
 }
 
@@ -64,15 +122,22 @@ impl LTSVString {
 
 impl Encoder for LTSVEncoder {
     fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
+        if let Some(fields) = &self.fields {
+            let mut res = self.encode_fields(&record, fields);
+            for (name, value) in &self.extra {
+                res.insert(name.strip_prefix('_').unwrap_or(name), value);
+            }
+            return Ok(res.finalize().into_bytes());
+        }
         let mut res = LTSVString::new();
         if let Some(sd_vec) = record.sd {
-            for &ref sd in &sd_vec {
+            for sd in &sd_vec {
                 // Warning: LTSV doesn't have a concept of structued data. In case there are
                 // several, all their attributes will be aded as fields. So if several structured
                 // data have the same key, only the last value will show as it will overwrite the
                 // others. We could use the sd_id to prefix the field to sove this but this is a
                 // breaking change.
-                for &(ref name, ref value) in &sd.pairs {
+                for (name, value) in &sd.pairs {
                     let name = if (*name).starts_with('_') {
                         &name[1..] as &str
                     } else {
@@ -89,7 +154,7 @@ impl Encoder for LTSVEncoder {
                 }
             }
         }
-        for &(ref name, ref value) in &self.extra {
+        for (name, value) in &self.extra {
             let name = if (*name).starts_with('_') {
                 &name[1..] as &str
             } else {
@@ -106,10 +171,10 @@ impl Encoder for LTSVEncoder {
             res.insert("full_message", &full_msg);
         }
         if let Some(severity) = record.severity {
-            res.insert("level", &format!("{}", severity));
+            self.insert_code(&mut res, "level", severity.into(), severity.name());
         }
         if let Some(facility) = record.facility {
-            res.insert("facility", &format!("{}", facility));
+            self.insert_code(&mut res, "facility", facility.into(), facility.name());
         }
         if let Some(appname) = record.appname {
             res.insert("appname", &appname);
@@ -125,7 +190,7 @@ impl Encoder for LTSVEncoder {
 }
 
 #[cfg(test)]
-use crate::flowgger::record::StructuredData;
+use crate::flowgger::record::{Facility, Severity, StructuredData, Timestamp};
 #[cfg(test)]
 use crate::flowgger::utils::test_utils::rfc_test_utils::ts_from_partial_date_time;
 #[cfg(test)]
@@ -140,14 +205,15 @@ fn test_ltsv_full_encode_no_sd() {
 
     let record = Record {
         ts,
-        hostname: "testhostname".to_string(),
-        facility: Some(2),
-        severity: Some(7),
-        appname: Some("appname".to_string()),
-        procid: Some("69".to_string()),
-        msgid: Some("42".to_string()),
-        msg: Some(r#"some test message"#.to_string()),
-        full_msg: Some(full_msg.to_string()),
+        utc_offset: None,
+        hostname: "testhostname".into(),
+        facility: Some(Facility::Mail),
+        severity: Some(Severity::Debug),
+        appname: Some("appname".into()),
+        procid: Some("69".into()),
+        msgid: Some("42".into()),
+        msg: Some(r#"some test message"#.into()),
+        full_msg: Some(full_msg.into()),
         sd: None,
     };
 
@@ -165,27 +231,28 @@ fn test_ltsv_full_encode_multiple_sd() {
 
     let record = Record {
         ts,
-        hostname: "testhostname".to_string(),
-        facility: Some(2),
-        severity: Some(7),
-        appname: Some("appname".to_string()),
-        procid: Some("69".to_string()),
-        msgid: Some("42".to_string()),
-        msg: Some(r#"some test message"#.to_string()),
-        full_msg: Some(full_msg.to_string()),
+        utc_offset: None,
+        hostname: "testhostname".into(),
+        facility: Some(Facility::Mail),
+        severity: Some(Severity::Debug),
+        appname: Some("appname".into()),
+        procid: Some("69".into()),
+        msgid: Some("42".into()),
+        msg: Some(r#"some test message"#.into()),
+        full_msg: Some(full_msg.into()),
         sd: Some(vec![
             StructuredData {
-                sd_id: Some("someid".to_string()),
+                sd_id: Some("someid".into()),
                 pairs: vec![
-                    ("a".to_string(), SDValue::String("b".to_string())),
-                    ("c".to_string(), SDValue::U64(123456)),
+                    ("a".into(), SDValue::String("b".into())),
+                    ("c".into(), SDValue::U64(123456)),
                 ],
             },
             StructuredData {
-                sd_id: Some("someid2".to_string()),
+                sd_id: Some("someid2".into()),
                 pairs: vec![
-                    ("a2".to_string(), SDValue::String("b2".to_string())),
-                    ("c2".to_string(), SDValue::U64(123456)),
+                    ("a2".into(), SDValue::String("b2".into())),
+                    ("c2".into(), SDValue::U64(123456)),
                 ],
             },
         ]),
@@ -195,3 +262,64 @@ fn test_ltsv_full_encode_multiple_sd() {
     let res = encoder.encode(record).unwrap();
     assert_eq!(String::from_utf8_lossy(&res), expected_msg);
 }
+
+#[test]
+fn test_ltsv_encode_fields() {
+    let cfg = Config::from_string(
+        "[output]\nltsv_fields = [\"time\", \"vhost=host\", \"req=message\", \"status\", \"missing\"]\n[output.ltsv_extra]\nenv = \"prod\"\n",
+    )
+    .unwrap();
+    let record = Record {
+        ts: Timestamp::from_secs_f64(1438790025.5),
+        utc_offset: None,
+        hostname: "testhostname".into(),
+        facility: Some(Facility::Mail),
+        severity: Some(Severity::Debug),
+        appname: Some("appname".into()),
+        procid: None,
+        msgid: None,
+        msg: Some("GET /".into()),
+        full_msg: None,
+        sd: Some(vec![StructuredData {
+            sd_id: None,
+            pairs: vec![
+                ("_status".into(), SDValue::U64(200)),
+                ("_other".into(), SDValue::Null),
+            ],
+        }]),
+    };
+    let res = LTSVEncoder::new(&cfg).encode(record).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&res),
+        "time:1438790025.5\tvhost:testhostname\treq:GET /\tstatus:200\tenv:prod"
+    );
+}
+
+#[test]
+fn test_ltsv_syslog_names() {
+    let record = || Record {
+        ts: Timestamp::from_secs_f64(1438790025.5),
+        utc_offset: None,
+        hostname: "testhostname".into(),
+        facility: Some(Facility::Daemon),
+        severity: Some(Severity::Warning),
+        appname: None,
+        procid: None,
+        msgid: None,
+        msg: None,
+        full_msg: None,
+        sd: None,
+    };
+    let cfg = Config::from_string("[output]\nsyslog_names = \"add\"\n").unwrap();
+    let res = LTSVEncoder::new(&cfg).encode(record()).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&res),
+        "host:testhostname\ttime:1438790025.5\tlevel:4\tlevel_name:warning\tfacility:3\tfacility_name:daemon"
+    );
+    let cfg = Config::from_string("[output]\nsyslog_names = \"replace\"\n").unwrap();
+    let res = LTSVEncoder::new(&cfg).encode(record()).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&res),
+        "host:testhostname\ttime:1438790025.5\tlevel:warning\tfacility:daemon"
+    );
+}
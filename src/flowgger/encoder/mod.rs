@@ -1,35 +1,95 @@
-#[cfg(feature = "capnp-recompile")]
+mod accounting_encoder;
+#[cfg(feature = "capnp")]
 mod capnp_encoder;
+mod fields_encoder;
 #[cfg(feature = "gelf")]
 mod gelf_encoder;
+#[cfg(feature = "logfmt")]
+mod logfmt_encoder;
 #[cfg(feature = "ltsv")]
 mod ltsv_encoder;
 #[cfg(feature = "passthrough")]
 mod passthrough_encoder;
+#[cfg(feature = "redact")]
+mod redact_encoder;
 #[cfg(feature = "rfc3164")]
 mod rfc3164_encoder;
 #[cfg(feature = "rfc5424")]
 mod rfc5424_encoder;
+mod sanitize_encoder;
+mod sequence_encoder;
+#[cfg(feature = "syslog-sign")]
+mod syslog_sign_encoder;
+mod truncate_encoder;
 
-#[cfg(feature = "capnp-recompile")]
+pub use self::accounting_encoder::AccountingEncoder;
+#[cfg(feature = "capnp")]
 pub use self::capnp_encoder::CapnpEncoder;
+#[cfg(any(feature = "kafka-output", feature = "mqtt", feature = "file"))]
+pub use self::fields_encoder::split_fields;
+pub use self::fields_encoder::FieldsEncoder;
 #[cfg(feature = "gelf")]
 pub use self::gelf_encoder::GelfEncoder;
+#[cfg(feature = "logfmt")]
+pub use self::logfmt_encoder::LogfmtEncoder;
 #[cfg(feature = "ltsv")]
 pub use self::ltsv_encoder::LTSVEncoder;
 #[cfg(feature = "passthrough")]
 pub use self::passthrough_encoder::PassthroughEncoder;
+#[cfg(feature = "redact")]
+pub use self::redact_encoder::{RedactEncoder, RedactRules};
 #[cfg(feature = "rfc3164")]
 pub use self::rfc3164_encoder::RFC3164Encoder;
 #[cfg(feature = "rfc5424")]
 pub use self::rfc5424_encoder::RFC5424Encoder;
+pub use self::sanitize_encoder::SanitizeEncoder;
+pub use self::sequence_encoder::SequenceEncoder;
+#[cfg(feature = "syslog-sign")]
+pub use self::syslog_sign_encoder::SyslogSignEncoder;
+pub use self::truncate_encoder::TruncateEncoder;
 
 use crate::flowgger::record::Record;
 use crate::flowgger::{config::Config, validate_time_format_input};
+use serde::Deserialize;
 use time::{format_description, OffsetDateTime};
 
 const SYSLOG_PREPEND_DEFAULT_TIME_FORMAT: &str = "[year][month][day]T[hour][minute][second]Z";
 
+/// Names of the settings the encoders deserialize with `Config::settings`
+#[cfg(test)]
+pub fn typed_settings() -> Vec<String> {
+    use crate::flowgger::config::setting_names;
+
+    let mut settings = setting_names::<accounting_encoder::AccountingSettings>("output");
+    settings.extend(setting_names::<PrependTsSettings>("output"));
+    settings.extend(setting_names::<sanitize_encoder::SanitizeSettings>(
+        "output",
+    ));
+    settings.extend(setting_names::<sequence_encoder::SequenceSettings>(
+        "output",
+    ));
+    settings.extend(setting_names::<truncate_encoder::TruncateSettings>(
+        "output",
+    ));
+    #[cfg(any(feature = "gelf", feature = "logfmt", feature = "ltsv"))]
+    settings.extend(setting_names::<SyslogNamesSettings>("output"));
+    #[cfg(feature = "capnp")]
+    settings.extend(setting_names::<capnp_encoder::CapnpSettings>("output"));
+    #[cfg(feature = "gelf")]
+    settings.extend(setting_names::<gelf_encoder::GelfSettings>("output"));
+    #[cfg(feature = "ltsv")]
+    settings.extend(setting_names::<ltsv_encoder::LTSVSettings>("output"));
+    #[cfg(feature = "redact")]
+    settings.extend(setting_names::<redact_encoder::RedactSettings>("output"));
+    #[cfg(feature = "rfc5424")]
+    settings.extend(setting_names::<rfc5424_encoder::RFC5424Settings>("output"));
+    #[cfg(feature = "syslog-sign")]
+    settings.extend(setting_names::<syslog_sign_encoder::SyslogSignSettings>(
+        "output",
+    ));
+    settings
+}
+
 pub trait CloneBoxedEncoder {
     fn clone_boxed<'a>(&self) -> Box<dyn Encoder + Send + 'a>
     where
@@ -55,18 +115,44 @@ pub trait Encoder: CloneBoxedEncoder {
     fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str>;
 }
 
+/// How the facility and the severity are written by the encoders that support names
+#[cfg(any(feature = "gelf", feature = "logfmt", feature = "ltsv"))]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogNames {
+    /// Codes only
+    Numeric,
+    /// Codes, and names in additional fields
+    Add,
+    /// Names instead of codes
+    Replace,
+}
+
+/// Settings of the encoders that support names, in the `[output]` section
+#[cfg(any(feature = "gelf", feature = "logfmt", feature = "ltsv"))]
+#[derive(Deserialize)]
+pub struct SyslogNamesSettings {
+    syslog_names: Option<SyslogNames>,
+}
+
+/// Settings of the encoders that can prepend a timestamp, in the `[output]` section
+#[derive(Deserialize)]
+pub struct PrependTsSettings {
+    syslog_prepend_timestamp: Option<String>,
+}
+
+/// # Parameters
+/// - 'output.syslog_names': Optional. "numeric" (default), "add" to add the facility and severity names, i.e.
+///   "daemon" and "warning", next to their codes, or "replace" to write the names instead of the codes.
+#[cfg(any(feature = "gelf", feature = "logfmt", feature = "ltsv"))]
+pub fn config_get_syslog_names(config: &Config) -> SyslogNames {
+    let settings: SyslogNamesSettings = config.settings("output");
+    settings.syslog_names.unwrap_or(SyslogNames::Numeric)
+}
+
 pub fn config_get_prepend_ts(config: &Config) -> Option<String> {
-    let prepend_ts = config
-        .lookup("output.syslog_prepend_timestamp")
-        .map_or(None, |bs| {
-            Some(
-                bs.as_str()
-                    .expect("output.syslog_prepend_timestamp should be a string")
-                    .to_string(),
-            )
-        });
-
-    match prepend_ts {
+    let settings: PrependTsSettings = config.settings("output");
+    match settings.syslog_prepend_timestamp {
         Some(time_format) => {
             let actual_time_format = validate_time_format_input(
                 "syslog_prepend_timestamp",
@@ -82,7 +168,7 @@ pub fn config_get_prepend_ts(config: &Config) -> Option<String> {
 
 pub fn build_prepend_ts(format_str: &str) -> Result<String, &'static str> {
     let current_time = OffsetDateTime::now_utc();
-    let format_item = match format_description::parse(format_str) {
+    let format_item = match format_description::parse_borrowed::<1>(format_str) {
         Ok(item) => item,
         Err(_) => return Err("Failed to format date"),
     };
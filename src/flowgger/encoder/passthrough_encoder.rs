@@ -25,8 +25,8 @@ impl Encoder for PassthroughEncoder {
         // Only push messages where the raw message is specified
         if let Some(msg) = record.full_msg {
             // First, if specified, prepend a header
-            if self.header_time_format.is_some() {
-                let ts = match build_prepend_ts(self.header_time_format.as_ref().unwrap()) {
+            if let Some(header_time_format) = &self.header_time_format {
+                let ts = match build_prepend_ts(header_time_format) {
                     Ok(ts) => ts,
                     Err(_) => {
                         return Err(
@@ -46,6 +46,8 @@ impl Encoder for PassthroughEncoder {
     }
 }
 
+#[cfg(test)]
+use crate::flowgger::record::Timestamp;
 #[cfg(test)]
 use time::{format_description, OffsetDateTime};
 
@@ -56,15 +58,16 @@ fn test_passthrough_encode() {
         Config::from_string("[input]\n[input.ltsv_schema]\nformat = \"passthrough\"\n").unwrap();
 
     let record = Record {
-        ts: 1.2,
-        hostname: "abcd".to_string(),
+        ts: Timestamp::from_secs_f64(1.2),
+        utc_offset: None,
+        hostname: "abcd".into(),
         facility: None,
         severity: None,
         appname: None,
         procid: None,
         msgid: None,
-        msg: Some(r#"test message"#.to_string()),
-        full_msg: Some(expected_msg.to_string()),
+        msg: Some(r#"test message"#.into()),
+        full_msg: Some(expected_msg.into()),
         sd: None,
     };
 
@@ -82,7 +85,7 @@ fn test_passthrough_encode_with_prepend() {
     ))
     .unwrap();
     let now = OffsetDateTime::now_utc();
-    let format_item = format_description::parse(TIME_FORMAT).unwrap();
+    let format_item = format_description::parse_borrowed::<1>(TIME_FORMAT).unwrap();
     let dt_str = now.format(&format_item).unwrap().to_string();
     let input_msg = format!(
         r#"{}Aug  6 11:15:24 testhostname appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#,
@@ -91,15 +94,16 @@ fn test_passthrough_encode_with_prepend() {
     let expected_msg = format!(r#"{}{}"#, dt_str, input_msg);
 
     let record = Record {
-        ts: 1.2,
-        hostname: "abcd".to_string(),
+        ts: Timestamp::from_secs_f64(1.2),
+        utc_offset: None,
+        hostname: "abcd".into(),
         facility: None,
         severity: None,
         appname: None,
         procid: None,
         msgid: None,
-        msg: Some(r#"test message"#.to_string()),
-        full_msg: Some(input_msg.to_string()),
+        msg: Some(r#"test message"#.into()),
+        full_msg: Some(input_msg.into()),
         sd: None,
     };
 
@@ -109,7 +113,7 @@ fn test_passthrough_encode_with_prepend() {
 }
 
 #[test]
-#[should_panic(expected = "output.syslog_prepend_timestamp should be a string")]
+#[should_panic(expected = "expected a string for key `output.syslog_prepend_timestamp`")]
 fn test_passthrough_encode_invalid_prepend() {
     let cfg =
         Config::from_string("[output]\nformat = \"passthrough\"\nsyslog_prepend_timestamp=123")
@@ -126,14 +130,15 @@ fn test_passthrough_encode_no_msg() {
     .unwrap();
 
     let record = Record {
-        ts: 1.2,
-        hostname: "abcd".to_string(),
+        ts: Timestamp::from_secs_f64(1.2),
+        utc_offset: None,
+        hostname: "abcd".into(),
         facility: None,
         severity: None,
         appname: None,
         procid: None,
         msgid: None,
-        msg: Some(r#"test message"#.to_string()),
+        msg: Some(r#"test message"#.into()),
         full_msg: None,
         sd: None,
     };
@@ -0,0 +1,297 @@
+use super::Encoder;
+use crate::flowgger::admin;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue};
+use crate::flowgger::utils::threads;
+use regex::Regex;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::io::{stderr, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_REDACT_REPLACEMENT: &str = "[REDACTED]";
+const DEFAULT_REDACT_REPORT_INTERVAL: u64 = 60;
+
+/// Settings of the redaction, in the `[output]` section
+#[derive(Deserialize)]
+pub struct RedactSettings {
+    redact: Option<Vec<RedactRuleSettings>>,
+    #[serde(default)]
+    redact_fields: Vec<String>,
+    redact_report_interval: Option<u64>,
+}
+
+/// Rule of 'output.redact'
+#[derive(Deserialize)]
+struct RedactRuleSettings {
+    pattern: String,
+    replacement: Option<String>,
+    name: Option<String>,
+}
+
+/// Rule replacing the matches of a regular expression
+struct RedactRule {
+    name: String,
+    regex: Regex,
+    replacement: String,
+}
+
+/// Rules of 'output.redact', also applied by the tap so that it doesn't leak what the outputs mask
+pub struct RedactRules {
+    rules: Vec<RedactRule>,
+}
+
+impl RedactRules {
+    /// # Parameters
+    /// - 'output.redact': Optional. List of rules, tables with:
+    ///   - 'pattern': Regular expression of the data to mask.
+    ///   - 'replacement': Optional. What to replace them with, `$1` referring to a capture group.
+    ///     Default is "[REDACTED]".
+    ///   - 'name': Optional. Name of the rule in the reports. Default is the pattern.
+    ///
+    /// # Returns
+    /// The rules, or None if 'output.redact' is not set
+    pub fn from_config(config: &Config) -> Option<RedactRules> {
+        let settings: RedactSettings = config.settings("output");
+        let rules = settings
+            .redact?
+            .into_iter()
+            .map(|rule| {
+                let regex = Regex::new(&rule.pattern).unwrap_or_else(|e| {
+                    panic!("Invalid output.redact pattern [{}]: {}", rule.pattern, e)
+                });
+                RedactRule {
+                    name: rule.name.unwrap_or(rule.pattern),
+                    regex,
+                    replacement: rule
+                        .replacement
+                        .unwrap_or_else(|| DEFAULT_REDACT_REPLACEMENT.to_owned()),
+                }
+            })
+            .collect();
+        Some(RedactRules { rules })
+    }
+
+    /// `text` with the matches of every rule replaced, without counting them
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        self.rules.iter().fold(Cow::Borrowed(text), |text, rule| {
+            match rule.regex.replace_all(&text, rule.replacement.as_str()) {
+                Cow::Owned(redacted) => Cow::Owned(redacted),
+                Cow::Borrowed(_) => text,
+            }
+        })
+    }
+}
+
+/// Number of matches every rule replaced
+pub struct RedactStats {
+    names: Vec<String>,
+    counts: Vec<AtomicU64>,
+}
+
+impl RedactStats {
+    /// Name of every rule, with the number of matches it replaced since flowgger started
+    pub fn snapshot(&self) -> Vec<(String, u64)> {
+        self.names
+            .iter()
+            .cloned()
+            .zip(
+                self.counts
+                    .iter()
+                    .map(|count| count.load(Ordering::Relaxed)),
+            )
+            .collect()
+    }
+}
+
+/// Encoder wrapper masking personal data (i.e. card numbers, emails or IP addresses) in the messages and in
+/// selected structured data, before records reach any output.
+pub struct RedactEncoder {
+    encoder: Box<dyn Encoder + Send>,
+    rules: Arc<RedactRules>,
+    fields: Arc<[String]>,
+    stats: Arc<RedactStats>,
+}
+
+impl Clone for RedactEncoder {
+    fn clone(&self) -> RedactEncoder {
+        RedactEncoder {
+            encoder: self.encoder.clone_boxed(),
+            rules: Arc::clone(&self.rules),
+            fields: Arc::clone(&self.fields),
+            stats: Arc::clone(&self.stats),
+        }
+    }
+}
+
+impl RedactEncoder {
+    /// # Parameters
+    /// - 'output.redact': Optional. List of rules, see `RedactRules::from_config`.
+    /// - 'output.redact_fields': Optional. Structured data to redact besides the messages.
+    /// - 'output.redact_report_interval': Optional. Seconds between the reports of the redactions written
+    ///   to stderr, 0 not to report. Default is 60.
+    ///
+    /// # Returns
+    /// The encoder as is if 'output.redact' is not set, or wrapped so that it redacts the records
+    pub fn wrap(config: &Config, encoder: Box<dyn Encoder + Send>) -> Box<dyn Encoder + Send> {
+        let rules = match RedactRules::from_config(config) {
+            None => return encoder,
+            Some(rules) => rules,
+        };
+        let settings: RedactSettings = config.settings("output");
+        let fields = settings
+            .redact_fields
+            .iter()
+            .map(|field| field.trim_start_matches('_').to_owned())
+            .collect::<Vec<String>>();
+        let interval = settings
+            .redact_report_interval
+            .unwrap_or(DEFAULT_REDACT_REPORT_INTERVAL);
+        let stats = Arc::new(RedactStats {
+            names: rules.rules.iter().map(|rule| rule.name.clone()).collect(),
+            counts: rules.rules.iter().map(|_| AtomicU64::new(0)).collect(),
+        });
+        if interval > 0 {
+            report(Duration::from_secs(interval), Arc::clone(&stats));
+        }
+        let admin_stats = Arc::clone(&stats);
+        admin::register_stats(move || {
+            admin_stats
+                .snapshot()
+                .into_iter()
+                .map(|(name, count)| (format!("redact.{}.matches", name), count))
+                .collect()
+        });
+        Box::new(RedactEncoder {
+            encoder,
+            rules: Arc::new(rules),
+            fields: fields.into(),
+            stats,
+        })
+    }
+
+    fn redact(&self, text: &mut Cow<'_, str>) {
+        for (rule, count) in self.rules.rules.iter().zip(self.stats.counts.iter()) {
+            let matches = rule.regex.find_iter(text.as_ref()).count();
+            if matches == 0 {
+                continue;
+            }
+            count.fetch_add(matches as u64, Ordering::Relaxed);
+            if let Cow::Owned(redacted) = rule
+                .regex
+                .replace_all(text.as_ref(), rule.replacement.as_str())
+            {
+                *text = Cow::Owned(redacted);
+            }
+        }
+    }
+}
+
+impl Encoder for RedactEncoder {
+    fn encode(&self, mut record: Record) -> Result<Vec<u8>, &'static str> {
+        if let Some(msg) = record.msg.as_mut() {
+            self.redact(msg);
+        }
+        if let Some(full_msg) = record.full_msg.as_mut() {
+            self.redact(full_msg);
+        }
+        if !self.fields.is_empty() {
+            for (key, value) in record
+                .sd
+                .iter_mut()
+                .flatten()
+                .flat_map(|sd| sd.pairs.iter_mut())
+            {
+                if let SDValue::String(value) = value {
+                    if self
+                        .fields
+                        .iter()
+                        .any(|field| field == key.trim_start_matches('_'))
+                    {
+                        self.redact(value);
+                    }
+                }
+            }
+        }
+        self.encoder.encode(record)
+    }
+}
+
+fn report(interval: Duration, stats: Arc<RedactStats>) {
+    threads::spawn("flowgger-redact-report".to_owned(), None, move || {
+        let mut last_total = 0;
+        loop {
+            thread::sleep(interval);
+            let counts = stats.snapshot();
+            let total: u64 = counts.iter().map(|(_, count)| count).sum();
+            if total == last_total {
+                continue;
+            }
+            last_total = total;
+            let counts: Vec<_> = counts
+                .iter()
+                .map(|(name, count)| format!("[{}]: {}", name, count))
+                .collect();
+            let _ = writeln!(stderr(), "Redactions: {}", counts.join(", "));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::record::StructuredData;
+
+    #[derive(Clone)]
+    struct TestEncoder;
+
+    impl Encoder for TestEncoder {
+        fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
+            let email = record.field("email").unwrap_or_default();
+            let ip = record.field("ip").unwrap_or_default();
+            Ok(format!("{}|{}|{}", record.msg.unwrap_or_default(), email, ip).into_bytes())
+        }
+    }
+
+    #[test]
+    fn test_redact_encoder() {
+        let config = Config::from_string(
+            r#"[output]
+redact_fields = ["email"]
+redact_report_interval = 0
+[[output.redact]]
+name = "card"
+pattern = '\b(?:\d{4}[ -]?){3}(\d{4})\b'
+replacement = "XXXX-XXXX-XXXX-$1"
+[[output.redact]]
+pattern = '[\w.+-]+@[\w-]+\.[\w.]+'
+"#,
+        )
+        .unwrap();
+        let encoder = RedactEncoder::wrap(&config, Box::new(TestEncoder));
+        let mut sd = StructuredData::new(None);
+        sd.pairs
+            .push(("_email".into(), SDValue::String("jane@example.com".into())));
+        sd.pairs
+            .push(("_ip".into(), SDValue::String("test@example.com".into())));
+        let record = Record::builder()
+            .hostname("example.org")
+            .msg("Paid with 4111 1111 1111 1234 by john@example.com")
+            .sd(sd)
+            .build();
+        assert_eq!(
+            String::from_utf8(encoder.encode(record).unwrap()).unwrap(),
+            "Paid with XXXX-XXXX-XXXX-1234 by [REDACTED]|[REDACTED]|test@example.com"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid output.redact pattern")]
+    fn test_redact_encoder_invalid_pattern() {
+        let config = Config::from_string("[[output.redact]]\npattern = \"(\"\n").unwrap();
+        let _ = RedactEncoder::wrap(&config, Box::new(TestEncoder));
+    }
+}
@@ -1,7 +1,13 @@
 use super::{build_prepend_ts, config_get_prepend_ts, Encoder};
 use crate::flowgger::config::Config;
-use crate::flowgger::record::Record;
-use time::{format_description, OffsetDateTime};
+use crate::flowgger::record::{pri, Record};
+use std::fmt::Write;
+use time::format_description::FormatItem;
+use time::macros::format_description;
+use time::OffsetDateTime;
+
+const TIME_FORMAT: &[FormatItem<'_>] =
+    format_description!("[month repr:short]  [day padding:none] [hour]:[minute]:[second] ");
 
 #[derive(Clone)]
 pub struct RFC3164Encoder {
@@ -26,11 +32,13 @@ impl Encoder for RFC3164Encoder {
     /// * Array of chars containing the encoded object as a string
     ///
     fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
-        let mut res = String::new();
+        let mut res = String::with_capacity(
+            record.hostname.len() + record.msg.as_ref().map_or(0, |msg| msg.len()) + 64,
+        );
 
         // First, if specified, prepend a header
-        if self.header_time_format.is_some() {
-            let ts = match build_prepend_ts(self.header_time_format.as_ref().unwrap()) {
+        if let Some(header_time_format) = &self.header_time_format {
+            let ts = match build_prepend_ts(header_time_format) {
                 Ok(ts) => ts,
                 Err(_) => {
                     return Err("Failed to format date when building prepend timestamp for header while encoding RFC3164")
@@ -40,24 +48,17 @@ impl Encoder for RFC3164Encoder {
         }
 
         // If a priority is specified, add it
-        if record.facility.is_some() && record.severity.is_some() {
-            let npri: u8 =
-                ((record.facility.unwrap() << 3) & 0xF8) + (record.severity.unwrap() & 0x7);
-            res.push_str(&format!("<{}>", npri));
+        if let (Some(facility), Some(severity)) = (record.facility, record.severity) {
+            let _ = write!(res, "<{}>", pri(facility, severity));
         }
 
         // Add timestamp + space
-        let dt = match OffsetDateTime::from_unix_timestamp(record.ts as i64) {
+        let dt = match OffsetDateTime::from_unix_timestamp(record.ts.unix_secs()) {
             Ok(date) => date,
             Err(_) => return Err("Failed to parse unix timestamp in RFC3164 encoder"),
         };
 
-        let format_item = format_description::parse(
-            "[month repr:short]  [day padding:none] [hour]:[minute]:[second] ",
-        )
-        .unwrap();
-
-        let dt_str = match dt.format(&format_item) {
+        let dt_str = match dt.format(TIME_FORMAT) {
             Ok(date_str) => date_str,
             Err(_) => return Err("Failed to format date in RFC3164 encoder"),
         };
@@ -73,7 +74,7 @@ impl Encoder for RFC3164Encoder {
             res.push_str(&appname);
         }
         if let Some(procid) = record.procid {
-            res.push_str(&format!("[{}]:", procid));
+            let _ = write!(res, "[{}]:", procid);
             res.push(' ');
         }
         if let Some(msgid) = record.msgid {
@@ -83,8 +84,8 @@ impl Encoder for RFC3164Encoder {
 
         // Encode structured data is present, although not part of rfc3164
         if let Some(sd_vec) = record.sd {
-            for &ref sd in &sd_vec {
-                res.push_str(&sd.to_string());
+            for sd in &sd_vec {
+                let _ = write!(res, "{}", sd);
             }
             res.push(' ');
         }
@@ -98,11 +99,11 @@ impl Encoder for RFC3164Encoder {
 }
 
 #[cfg(test)]
-use crate::flowgger::record::{SDValue, StructuredData};
+use crate::flowgger::record::{Facility, SDValue, Severity, StructuredData};
 #[cfg(test)]
 use crate::flowgger::utils::test_utils::rfc_test_utils::ts_from_partial_date_time;
 #[cfg(test)]
-use time::Month;
+use time::{format_description, Month};
 
 #[test]
 fn test_rfc3164_encode() {
@@ -112,14 +113,15 @@ fn test_rfc3164_encode() {
 
     let record = Record {
         ts,
-        hostname: "testhostname".to_string(),
+        utc_offset: None,
+        hostname: "testhostname".into(),
         facility: None,
         severity: None,
         appname: None,
         procid: None,
         msgid: None,
-        msg: Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.to_string()),
-        full_msg: Some(expected_msg.to_string()),
+        msg: Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.into()),
+        full_msg: Some(expected_msg.into()),
         sd: None,
     };
 
@@ -136,14 +138,15 @@ fn test_rfc3164_withpri_encode() {
 
     let record = Record {
         ts,
-        hostname: "testhostname".to_string(),
-        facility: Some(2),
-        severity: Some(7),
+        utc_offset: None,
+        hostname: "testhostname".into(),
+        facility: Some(Facility::Mail),
+        severity: Some(Severity::Debug),
         appname: None,
         procid: None,
         msgid: None,
-        msg: Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.to_string()),
-        full_msg: Some(expected_msg.to_string()),
+        msg: Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.into()),
+        full_msg: Some(expected_msg.into()),
         sd: None,
     };
 
@@ -162,7 +165,7 @@ fn test_rfc3164_encode_with_prepend() {
     let cfg = Config::from_string(config_str).unwrap();
     let ts = ts_from_partial_date_time(Month::August, 6, 11, 15, 24);
     let now = OffsetDateTime::now_utc();
-    let format_item = format_description::parse(TIME_FORMAT).unwrap();
+    let format_item = format_description::parse_borrowed::<1>(TIME_FORMAT).unwrap();
     let dt_str = now.format(&format_item).unwrap().to_string();
     let expected_msg = format!(
         r#"{}Aug  6 11:15:24 testhostname appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#,
@@ -171,14 +174,15 @@ fn test_rfc3164_encode_with_prepend() {
 
     let record = Record {
         ts,
-        hostname: "testhostname".to_string(),
+        utc_offset: None,
+        hostname: "testhostname".into(),
         facility: None,
         severity: None,
         appname: None,
         procid: None,
         msgid: None,
-        msg: Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.to_string()),
-        full_msg: Some(expected_msg.to_string()),
+        msg: Some(r#"appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#.into()),
+        full_msg: Some(expected_msg.as_str().into()),
         sd: None,
     };
 
@@ -188,7 +192,7 @@ fn test_rfc3164_encode_with_prepend() {
 }
 
 #[test]
-#[should_panic(expected = "output.syslog_prepend_timestamp should be a string")]
+#[should_panic(expected = "expected a string for key `output.syslog_prepend_timestamp`")]
 fn test_rfc3164_invalid_prepend() {
     let cfg = Config::from_string("[output]\nformat = \"rfc3164\"\nsyslog_prepend_timestamp=123")
         .unwrap();
@@ -203,19 +207,20 @@ fn test_rfc3164_full_encode() {
 
     let record = Record {
         ts,
-        hostname: "testhostname".to_string(),
-        facility: Some(2),
-        severity: Some(7),
-        appname: Some("appname".to_string()),
-        procid: Some("69".to_string()),
-        msgid: Some("42".to_string()),
-        msg: Some(r#"some test message"#.to_string()),
-        full_msg: Some(expected_msg.to_string()),
+        utc_offset: None,
+        hostname: "testhostname".into(),
+        facility: Some(Facility::Mail),
+        severity: Some(Severity::Debug),
+        appname: Some("appname".into()),
+        procid: Some("69".into()),
+        msgid: Some("42".into()),
+        msg: Some(r#"some test message"#.into()),
+        full_msg: Some(expected_msg.into()),
         sd: Some(vec![StructuredData {
-            sd_id: Some("someid".to_string()),
+            sd_id: Some("someid".into()),
             pairs: vec![
-                ("a".to_string(), SDValue::String("b".to_string())),
-                ("c".to_string(), SDValue::U64(123456)),
+                ("a".into(), SDValue::String("b".into())),
+                ("c".into(), SDValue::U64(123456)),
             ],
         }]),
     };
@@ -233,27 +238,28 @@ fn test_rfc3164_full_encode_multiple_sd() {
 
     let record = Record {
         ts,
-        hostname: "testhostname".to_string(),
-        facility: Some(2),
-        severity: Some(7),
-        appname: Some("appname".to_string()),
-        procid: Some("69".to_string()),
-        msgid: Some("42".to_string()),
-        msg: Some(r#"some test message"#.to_string()),
-        full_msg: Some(expected_msg.to_string()),
+        utc_offset: None,
+        hostname: "testhostname".into(),
+        facility: Some(Facility::Mail),
+        severity: Some(Severity::Debug),
+        appname: Some("appname".into()),
+        procid: Some("69".into()),
+        msgid: Some("42".into()),
+        msg: Some(r#"some test message"#.into()),
+        full_msg: Some(expected_msg.into()),
         sd: Some(vec![
             StructuredData {
-                sd_id: Some("someid".to_string()),
+                sd_id: Some("someid".into()),
                 pairs: vec![
-                    ("a".to_string(), SDValue::String("b".to_string())),
-                    ("c".to_string(), SDValue::U64(123456)),
+                    ("a".into(), SDValue::String("b".into())),
+                    ("c".into(), SDValue::U64(123456)),
                 ],
             },
             StructuredData {
-                sd_id: Some("someid2".to_string()),
+                sd_id: Some("someid2".into()),
                 pairs: vec![
-                    ("a2".to_string(), SDValue::String("b2".to_string())),
-                    ("c2".to_string(), SDValue::U64(123456)),
+                    ("a2".into(), SDValue::String("b2".into())),
+                    ("c2".into(), SDValue::U64(123456)),
                 ],
             },
         ]),
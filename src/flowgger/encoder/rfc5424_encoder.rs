@@ -1,18 +1,33 @@
 use super::Encoder;
 use crate::flowgger::config::Config;
-use crate::flowgger::record::Record;
+use crate::flowgger::record::{pri, Record, Timestamp};
+use serde::Deserialize;
+use std::fmt::Write;
 use time::format_description::well_known::Rfc3339;
-use time::OffsetDateTime;
 
 const DEFAULT_PRIORITY: &str = "<13>";
 const DEFAULT_SYSLOG_VERSION: char = '1';
 
+/// Settings of the RFC5424 encoder, in the `[output]` section
+#[derive(Deserialize)]
+pub struct RFC5424Settings {
+    rfc5424_keep_utc_offset: Option<bool>,
+}
+
 #[derive(Clone)]
-pub struct RFC5424Encoder;
+pub struct RFC5424Encoder {
+    keep_utc_offset: bool,
+}
 
 impl RFC5424Encoder {
-    pub fn new(_config: &Config) -> RFC5424Encoder {
-        RFC5424Encoder
+    /// # Parameters
+    /// - 'output.rfc5424_keep_utc_offset': Optional. Render timestamps with the UTC offset they were sent
+    ///   with, for records decoded from a format carrying it, instead of in UTC. Default is false.
+    pub fn new(config: &Config) -> RFC5424Encoder {
+        let settings: RFC5424Settings = config.settings("output");
+        RFC5424Encoder {
+            keep_utc_offset: settings.rfc5424_keep_utc_offset.unwrap_or(false),
+        }
     }
 }
 
@@ -26,25 +41,30 @@ impl Encoder for RFC5424Encoder {
     /// * Array of chars containing the encoded object as a string
     ///
     fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
-        let mut res = String::new();
+        let mut res = String::with_capacity(
+            record.hostname.len() + record.msg.as_ref().map_or(0, |msg| msg.len()) + 64,
+        );
 
         // If a priority is specified, add it
-        if record.facility.is_some() && record.severity.is_some() {
-            let npri: u8 =
-                ((record.facility.unwrap() << 3) & 0xF8) + (record.severity.unwrap() & 0x7);
-            res.push_str(&format!("<{}>", npri));
+        if let (Some(facility), Some(severity)) = (record.facility, record.severity) {
+            let _ = write!(res, "<{}>", pri(facility, severity));
         } else {
             res.push_str(DEFAULT_PRIORITY);
         }
         res.push(DEFAULT_SYSLOG_VERSION);
         res.push(' ');
 
-        // Convert the float timestamp in seconds into a number of secs and nanosecs (rounded to ms) to create a date object
-        let ts_ns = ((record.ts * 1000.0) as i128) * 1_000_000;
-        let dt = match OffsetDateTime::from_unix_timestamp_nanos(ts_ns) {
+        // RFC5424 timestamps have at most 6 decimals, the timestamp is truncated to the µs
+        let ts_us = record.ts.unix_nanos().div_euclid(1000);
+        let mut dt = match Timestamp::from_unix_nanos(ts_us * 1000).to_offset_datetime() {
             Ok(date) => date,
             Err(_) => return Err("Failed to parse date"),
         };
+        if let (true, Some(utc_offset)) = (self.keep_utc_offset, record.utc_offset) {
+            dt = dt
+                .checked_to_offset(utc_offset)
+                .ok_or("Failed to parse date")?;
+        }
 
         // Add timestamp + space
         let date = match dt.format(&Rfc3339) {
@@ -61,10 +81,12 @@ impl Encoder for RFC5424Encoder {
         // Add appname/procid/msgid if specified
         if let Some(appname) = record.appname {
             res.push_str(&appname);
-            res.push(' ');
+        } else {
+            res.push('-');
         }
+        res.push(' ');
         if let Some(procid) = record.procid {
-            res.push_str(&procid.to_string());
+            res.push_str(&procid);
         } else {
             res.push('-');
         }
@@ -77,8 +99,8 @@ impl Encoder for RFC5424Encoder {
         res.push(' ');
 
         if let Some(sd_vec) = record.sd {
-            for &ref sd in &sd_vec {
-                res.push_str(&sd.to_string());
+            for sd in &sd_vec {
+                let _ = write!(res, "{}", sd);
             }
             res.push(' ');
         } else {
@@ -94,7 +116,7 @@ impl Encoder for RFC5424Encoder {
 }
 
 #[cfg(test)]
-use crate::flowgger::record::{SDValue, StructuredData};
+use crate::flowgger::record::{Facility, SDValue, Severity, StructuredData};
 #[cfg(test)]
 use crate::flowgger::utils::test_utils::rfc_test_utils::ts_from_date_time;
 #[cfg(test)]
@@ -102,20 +124,21 @@ use time::Month;
 
 #[test]
 fn test_rfc5424_encode() {
-    let expected_msg = r#"<13>1 2015-08-06T11:15:24.638Z testhostname - - - some test message"#;
+    let expected_msg = r#"<13>1 2015-08-06T11:15:24.638Z testhostname - - - - some test message"#;
     let cfg = Config::from_string("[input]\n[input.ltsv_schema]\nformat = \"rfc5424\"\n").unwrap();
     let ts = ts_from_date_time(2015, Month::August, 6, 11, 15, 24, 638);
 
     let record = Record {
         ts,
-        hostname: "testhostname".to_string(),
+        utc_offset: None,
+        hostname: "testhostname".into(),
         facility: None,
         severity: None,
         appname: None,
         procid: None,
         msgid: None,
-        msg: Some("some test message".to_string()),
-        full_msg: Some(expected_msg.to_string()),
+        msg: Some("some test message".into()),
+        full_msg: Some(expected_msg.into()),
         sd: None,
     };
 
@@ -124,6 +147,48 @@ fn test_rfc5424_encode() {
     assert_eq!(String::from_utf8_lossy(&res), expected_msg);
 }
 
+#[test]
+fn test_rfc5424_encode_microseconds() {
+    let cfg = Config::from_string("").unwrap();
+    let record = Record::builder()
+        .ts(Timestamp::from_unix_nanos(1_438_790_025_637_824_999))
+        .hostname("testhostname")
+        .msg("some test message")
+        .build();
+
+    let res = RFC5424Encoder::new(&cfg).encode(record).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&res),
+        "<13>1 2015-08-05T15:53:45.637824Z testhostname - - - - some test message"
+    );
+}
+
+#[test]
+fn test_rfc5424_encode_utc_offset() {
+    let record = || {
+        Record::builder()
+            .ts(Timestamp::from_unix_nanos(1_438_790_025_637_824_000))
+            .utc_offset(time::UtcOffset::from_hms(-7, 0, 0).unwrap())
+            .hostname("testhostname")
+            .msg("some test message")
+            .build()
+    };
+
+    let cfg = Config::from_string("").unwrap();
+    let res = RFC5424Encoder::new(&cfg).encode(record()).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&res),
+        "<13>1 2015-08-05T15:53:45.637824Z testhostname - - - - some test message"
+    );
+
+    let cfg = Config::from_string("[output]\nrfc5424_keep_utc_offset = true\n").unwrap();
+    let res = RFC5424Encoder::new(&cfg).encode(record()).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&res),
+        "<13>1 2015-08-05T08:53:45.637824-07:00 testhostname - - - - some test message"
+    );
+}
+
 #[test]
 fn test_rfc5424_full_encode() {
     let expected_msg = r#"<25>1 2015-08-05T15:53:45.382Z testhostname appname 69 42 [origin@123 software="test sc\"ript" swVersion="0.0.1"] test message"#;
@@ -132,25 +197,20 @@ fn test_rfc5424_full_encode() {
 
     let record = Record {
         ts,
-        hostname: "testhostname".to_string(),
-        facility: Some(3),
-        severity: Some(1),
-        appname: Some("appname".to_string()),
-        procid: Some("69".to_string()),
-        msgid: Some("42".to_string()),
-        msg: Some("test message".to_string()),
-        full_msg: Some(expected_msg.to_string()),
+        utc_offset: None,
+        hostname: "testhostname".into(),
+        facility: Some(Facility::Daemon),
+        severity: Some(Severity::Alert),
+        appname: Some("appname".into()),
+        procid: Some("69".into()),
+        msgid: Some("42".into()),
+        msg: Some("test message".into()),
+        full_msg: Some(expected_msg.into()),
         sd: Some(vec![StructuredData {
-            sd_id: Some("origin@123".to_string()),
+            sd_id: Some("origin@123".into()),
             pairs: vec![
-                (
-                    "software".to_string(),
-                    SDValue::String(r#"test sc\"ript"#.to_string()),
-                ),
-                (
-                    "swVersion".to_string(),
-                    SDValue::String("0.0.1".to_string()),
-                ),
+                ("software".into(), SDValue::String(r#"test sc"ript"#.into())),
+                ("swVersion".into(), SDValue::String("0.0.1".into())),
             ],
         }]),
     };
@@ -168,33 +228,28 @@ fn test_rfc5424_full_encode_multiple_sd() {
 
     let record = Record {
         ts,
-        hostname: "testhostname".to_string(),
-        facility: Some(3),
-        severity: Some(1),
-        appname: Some("appname".to_string()),
-        procid: Some("69".to_string()),
-        msgid: Some("42".to_string()),
-        msg: Some("test message".to_string()),
-        full_msg: Some(expected_msg.to_string()),
+        utc_offset: None,
+        hostname: "testhostname".into(),
+        facility: Some(Facility::Daemon),
+        severity: Some(Severity::Alert),
+        appname: Some("appname".into()),
+        procid: Some("69".into()),
+        msgid: Some("42".into()),
+        msg: Some("test message".into()),
+        full_msg: Some(expected_msg.into()),
         sd: Some(vec![
             StructuredData {
-                sd_id: Some("origin@123".to_string()),
+                sd_id: Some("origin@123".into()),
                 pairs: vec![
-                    (
-                        "software".to_string(),
-                        SDValue::String(r#"test sc\"ript"#.to_string()),
-                    ),
-                    (
-                        "swVersion".to_string(),
-                        SDValue::String("0.0.1".to_string()),
-                    ),
+                    ("software".into(), SDValue::String(r#"test sc"ript"#.into())),
+                    ("swVersion".into(), SDValue::String("0.0.1".into())),
                 ],
             },
             StructuredData {
-                sd_id: Some("master@456".to_string()),
+                sd_id: Some("master@456".into()),
                 pairs: vec![
-                    ("key1".to_string(), SDValue::String(r#"value1"#.to_string())),
-                    ("key2".to_string(), SDValue::String("value2".to_string())),
+                    ("key1".into(), SDValue::String(r#"value1"#.into())),
+                    ("key2".into(), SDValue::String("value2".into())),
                 ],
             },
         ]),
@@ -0,0 +1,125 @@
+use super::Encoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::Record;
+use serde::Deserialize;
+use std::borrow::Cow;
+
+const ESC: char = '\x1b';
+const BEL: char = '\x07';
+
+/// Settings of the sanitization, in the `[output]` section
+#[derive(Deserialize)]
+pub struct SanitizeSettings {
+    sanitize: Option<bool>,
+}
+
+/// Encoder wrapper removing ANSI escape sequences and control characters from the messages, so that records
+/// can't inject fake lines or terminal escapes into the consoles they are later viewed in.
+/// Tabs are kept, and so are line feeds in `full_msg`, that usually holds multi-line messages.
+pub struct SanitizeEncoder {
+    encoder: Box<dyn Encoder + Send>,
+}
+
+impl Clone for SanitizeEncoder {
+    fn clone(&self) -> SanitizeEncoder {
+        SanitizeEncoder {
+            encoder: self.encoder.clone_boxed(),
+        }
+    }
+}
+
+impl SanitizeEncoder {
+    /// # Parameters
+    /// - 'output.sanitize': Optional. Must be a boolean. Default is false.
+    ///
+    /// # Returns
+    /// The encoder as is if 'output.sanitize' is not set, or wrapped so that it sanitizes the records
+    pub fn wrap(config: &Config, encoder: Box<dyn Encoder + Send>) -> Box<dyn Encoder + Send> {
+        let settings: SanitizeSettings = config.settings("output");
+        if !settings.sanitize.unwrap_or(false) {
+            return encoder;
+        }
+        Box::new(SanitizeEncoder { encoder })
+    }
+}
+
+impl Encoder for SanitizeEncoder {
+    fn encode(&self, mut record: Record) -> Result<Vec<u8>, &'static str> {
+        if let Some(msg) = record.msg.as_mut() {
+            sanitize(msg, false);
+        }
+        if let Some(full_msg) = record.full_msg.as_mut() {
+            sanitize(full_msg, true);
+        }
+        self.encoder.encode(record)
+    }
+}
+
+/// Remove the escape sequences (CSI, OSC and two-character sequences) and the control characters of `text`
+fn sanitize(text: &mut Cow<'_, str>, keep_lf: bool) {
+    if !text
+        .chars()
+        .any(|c| c.is_control() && c != '\t' && !(keep_lf && c == '\n'))
+    {
+        return;
+    }
+    let mut sanitized = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ESC => match chars.next() {
+                // CSI: parameters and intermediate bytes, up to a final byte in @..~
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC, DCS, and other strings terminated by BEL or ST (ESC \)
+                Some(']') | Some('P') | Some('X') | Some('^') | Some('_') => {
+                    while let Some(c) = chars.next() {
+                        if c == BEL || (c == ESC && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\t' => sanitized.push(c),
+            '\n' if keep_lf => sanitized.push(c),
+            c if c.is_control() => {}
+            c => sanitized.push(c),
+        }
+    }
+    *text = Cow::Owned(sanitized);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sanitized(text: &str, keep_lf: bool) -> String {
+        let mut text = Cow::Borrowed(text);
+        sanitize(&mut text, keep_lf);
+        text.into_owned()
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitized("plain\ttext", false), "plain\ttext");
+        assert_eq!(
+            sanitized("\x1b[1;31mred\x1b[0m and \x1b]0;title\x07bold", false),
+            "red and bold"
+        );
+        assert_eq!(
+            sanitized("\x1b]8;;http://evil\x1b\\link\x1b]8;;\x1b\\", false),
+            "link"
+        );
+        assert_eq!(
+            sanitized("login ok\r\nlogin failed\x00\x7f\u{9b}", false),
+            "login oklogin failed"
+        );
+        assert_eq!(sanitized("line 1\r\nline 2", true), "line 1\nline 2");
+    }
+}
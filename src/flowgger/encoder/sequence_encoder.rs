@@ -0,0 +1,170 @@
+use super::Encoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue};
+use serde::Deserialize;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const SEQUENCE_KEY: &str = "_seq";
+/// Sequence numbers reserved in the sequence file at once, the most that are skipped after a restart
+const SEQUENCE_RESERVATION: u64 = 1000;
+
+struct SequenceState {
+    next: u64,
+    /// First sequence number not reserved in the sequence file yet
+    reserved: u64,
+}
+
+struct Sequence {
+    path: PathBuf,
+    state: Mutex<SequenceState>,
+}
+
+/// Settings of the sequence numbers, in the `[output]` section
+#[derive(Deserialize)]
+pub struct SequenceSettings {
+    sequence_file: Option<String>,
+}
+
+/// Encoder wrapper stamping every record with a `_seq` structured data, a sequence number increasing by one
+/// with every record sent to the output, for downstream consumers to detect the records that were lost.
+///
+/// Sequence numbers are reserved by blocks in the sequence file, so that they are never used twice, even after
+/// a crash: when flowgger starts again, the sequence continues after the last block, leaving a gap of less
+/// than 1000 numbers that marks the restart. Numbers are given in the order the records are encoded, that may
+/// slightly differ from the order of the output when there are several input threads.
+pub struct SequenceEncoder {
+    encoder: Box<dyn Encoder + Send>,
+    sequence: Arc<Sequence>,
+}
+
+impl Clone for SequenceEncoder {
+    fn clone(&self) -> SequenceEncoder {
+        SequenceEncoder {
+            encoder: self.encoder.clone_boxed(),
+            sequence: Arc::clone(&self.sequence),
+        }
+    }
+}
+
+impl SequenceEncoder {
+    /// # Parameters
+    /// - 'output.sequence_file': Optional. Path to the file where the sequence is persisted. Records are only
+    ///   numbered when this is set.
+    ///
+    /// # Returns
+    /// The encoder as is if 'output.sequence_file' is not set, or wrapped so that it numbers the records
+    pub fn wrap(config: &Config, encoder: Box<dyn Encoder + Send>) -> Box<dyn Encoder + Send> {
+        let settings: SequenceSettings = config.settings("output");
+        let path = match settings.sequence_file {
+            None => return encoder,
+            Some(path) => path,
+        };
+        let sequence = Sequence::load(Path::new(&path))
+            .unwrap_or_else(|e| panic!("Unable to load output.sequence_file [{}]: {}", path, e));
+        Box::new(SequenceEncoder {
+            encoder,
+            sequence: Arc::new(sequence),
+        })
+    }
+}
+
+impl Sequence {
+    fn load(path: &Path) -> Result<Sequence, String> {
+        let next = match fs::read_to_string(path) {
+            Ok(contents) => contents
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid sequence number [{}]", contents.trim()))?,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => 1,
+            Err(e) => return Err(e.to_string()),
+        };
+        Ok(Sequence {
+            path: path.to_owned(),
+            state: Mutex::new(SequenceState {
+                next,
+                reserved: next,
+            }),
+        })
+    }
+
+    /// Next sequence number, reserving a new block in the sequence file when the current one is exhausted
+    fn next(&self) -> Result<u64, &'static str> {
+        let mut state = self.state.lock().unwrap();
+        if state.next >= state.reserved {
+            let reserved = state.next + SEQUENCE_RESERVATION;
+            self.save(reserved)
+                .map_err(|_| "Unable to save the output sequence")?;
+            state.reserved = reserved;
+        }
+        let seq = state.next;
+        state.next += 1;
+        Ok(seq)
+    }
+
+    /// Atomically replace the sequence file
+    fn save(&self, reserved: u64) -> Result<(), std::io::Error> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let mut tmp_file = File::create(&tmp_path)?;
+        writeln!(tmp_file, "{}", reserved)?;
+        tmp_file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
+
+impl Encoder for SequenceEncoder {
+    fn encode(&self, mut record: Record) -> Result<Vec<u8>, &'static str> {
+        record.push_sd_pair(SEQUENCE_KEY, SDValue::U64(self.sequence.next()?));
+        self.encoder.encode(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[derive(Clone)]
+    struct TestEncoder;
+
+    impl Encoder for TestEncoder {
+        fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
+            Ok(record.field("seq").unwrap_or_default().into_bytes())
+        }
+    }
+
+    fn encode(encoder: &dyn Encoder) -> String {
+        let record = Record::builder()
+            .hostname("example.org")
+            .msg("test")
+            .build();
+        String::from_utf8(encoder.encode(record).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_sequence_encoder() {
+        let temp_dir = TempDir::new("test_sequence_encoder").unwrap();
+        let path = temp_dir.path().join("sequence");
+        let config = Config::from_string(&format!(
+            "[output]\nsequence_file = \"{}\"\n",
+            path.to_str().unwrap()
+        ))
+        .unwrap();
+        let encoder = SequenceEncoder::wrap(&config, Box::new(TestEncoder));
+        assert_eq!(encode(&*encoder), "1");
+        assert_eq!(encode(&*encoder.clone_boxed()), "2");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1001\n");
+
+        // After a restart, the sequence continues after the reserved block
+        let encoder = SequenceEncoder::wrap(&config, Box::new(TestEncoder));
+        assert_eq!(encode(&*encoder), "1001");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2001\n");
+
+        let encoder =
+            SequenceEncoder::wrap(&Config::from_string("").unwrap(), Box::new(TestEncoder));
+        assert_eq!(encode(&*encoder), "");
+    }
+}
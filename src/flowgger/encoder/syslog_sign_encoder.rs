@@ -0,0 +1,423 @@
+use super::fields_encoder::missing_fields;
+use super::Encoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::Record;
+use crate::flowgger::record_queue::RecordSender;
+use crate::flowgger::utils::{local_hostname, threads};
+use openssl::base64;
+use openssl::error::ErrorStack;
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::{Id, PKey, Private};
+use openssl::sign::Signer;
+use serde::Deserialize;
+use std::fs;
+use std::io::{stderr, Write};
+use std::mem;
+use std::num::NonZeroU64;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+const DEFAULT_SYSLOG_SIGN_HASH: &str = "sha256";
+const DEFAULT_SYSLOG_SIGN_COUNT: usize = 25;
+const DEFAULT_SYSLOG_SIGN_INTERVAL: u64 = 10;
+/// Maximum number of hashes of a signature block
+const SYSLOG_SIGN_COUNT_MAX: usize = 99;
+/// log audit, info
+const SIGN_BLOCK_PRI: u8 = 110;
+const SIGN_BLOCK_APPNAME: &str = "flowgger";
+/// Time without new messages after which an incomplete block is sent, so that it follows the messages it covers
+const SETTLE_DELAY: Duration = Duration::from_millis(100);
+
+/// Settings of the signature blocks, in the `[output]` section
+#[derive(Deserialize)]
+pub struct SyslogSignSettings {
+    syslog_sign_key: Option<String>,
+    syslog_sign_hash: Option<String>,
+    syslog_sign_count: Option<usize>,
+    syslog_sign_interval: Option<NonZeroU64>,
+    syslog_sign_hostname: Option<String>,
+    format: Option<String>,
+}
+
+/// Signature blocks being built
+struct SignState {
+    /// Base64-encoded hashes of the messages not signed yet
+    hashes: Vec<String>,
+    /// Number of the first message of the block being built
+    fmn: u64,
+    /// Global block counter of the last block
+    gbc: u64,
+    /// Complete blocks, sent before the next message is hashed, or by the flush thread
+    pending: Vec<Vec<u8>>,
+    last_message: Instant,
+}
+
+/// Signer of RFC5848 signature blocks, all the messages being in the signature group 0
+struct BlockSigner {
+    key: PKey<Private>,
+    digest: MessageDigest,
+    ver: &'static str,
+    hostname: String,
+    rsid: u64,
+    count: usize,
+    tx: RecordSender,
+    /// Record fields of the output, all missing, prepended to the blocks
+    fields_prefix: Vec<u8>,
+    state: Mutex<SignState>,
+}
+
+/// Encoder wrapper signing the records as described in RFC5848 (Signed Syslog Messages), for tamper-evident
+/// logs: the hashes of the encoded records are sent in signature blocks, syslog messages signed with a DSA key,
+/// that receivers verify with the public key.
+///
+/// A certificate block with the public key is sent at startup. Signature blocks are sent once they hold
+/// 'output.syslog_sign_count' hashes, and incomplete blocks after 'output.syslog_sign_interval' seconds.
+/// The reboot session ID is the time flowgger started at, and the signatures are DER-encoded, as with OpenSSL.
+///
+/// Records are hashed before the record fields of the output are prepended, so that the hashes cover what
+/// receivers get, and blocks are sent with all the record fields missing.
+pub struct SyslogSignEncoder {
+    encoder: Box<dyn Encoder + Send>,
+    signer: Arc<BlockSigner>,
+}
+
+impl Clone for SyslogSignEncoder {
+    fn clone(&self) -> SyslogSignEncoder {
+        SyslogSignEncoder {
+            encoder: self.encoder.clone_boxed(),
+            signer: Arc::clone(&self.signer),
+        }
+    }
+}
+
+impl SyslogSignEncoder {
+    /// # Parameters
+    /// - 'output.syslog_sign_key':      Optional. Path to the DSA private key the blocks are signed with, in
+    ///   the PEM format. Requires the "rfc5424" output format.
+    /// - 'output.syslog_sign_hash':     Optional. Hash of the messages and of the signatures, "sha1" or
+    ///   "sha256". Default is "sha256".
+    /// - 'output.syslog_sign_count':    Optional. Number of hashes per signature block, up to 99. Default
+    ///   is 25.
+    /// - 'output.syslog_sign_interval': Optional. Seconds after which incomplete blocks are sent. Default is 10.
+    /// - 'output.syslog_sign_hostname': Optional. Hostname of the signature blocks. Default is the local
+    ///   hostname.
+    ///
+    /// # Returns
+    /// The encoder as is if 'output.syslog_sign_key' is not set, or wrapped so that it signs the records, the
+    /// signature blocks being sent to `tx`, prefixed with `fields_count` missing record fields
+    pub fn wrap(
+        config: &Config,
+        encoder: Box<dyn Encoder + Send>,
+        tx: RecordSender,
+        fields_count: usize,
+    ) -> Box<dyn Encoder + Send> {
+        let settings: SyslogSignSettings = config.settings("output");
+        let path = match settings.syslog_sign_key {
+            None => return encoder,
+            Some(path) => path,
+        };
+        if settings.format.as_deref() != Some("rfc5424") {
+            panic!("output.syslog_sign_key requires the rfc5424 output format");
+        }
+        let pem = fs::read(&path)
+            .unwrap_or_else(|e| panic!("Unable to read output.syslog_sign_key [{}]: {}", path, e));
+        let key = PKey::private_key_from_pem(&pem)
+            .unwrap_or_else(|e| panic!("Invalid output.syslog_sign_key [{}]: {}", path, e));
+        if key.id() != Id::DSA {
+            panic!("output.syslog_sign_key must be a DSA key");
+        }
+        let (digest, ver) = match settings
+            .syslog_sign_hash
+            .as_deref()
+            .unwrap_or(DEFAULT_SYSLOG_SIGN_HASH)
+        {
+            "sha1" => (MessageDigest::sha1(), "0111"),
+            "sha256" => (MessageDigest::sha256(), "0121"),
+            _ => panic!(r#"output.syslog_sign_hash must be "sha1" or "sha256""#),
+        };
+        let count = settings
+            .syslog_sign_count
+            .unwrap_or(DEFAULT_SYSLOG_SIGN_COUNT);
+        if !(1..=SYSLOG_SIGN_COUNT_MAX).contains(&count) {
+            panic!("output.syslog_sign_count must be between 1 and 99");
+        }
+        let interval = settings
+            .syslog_sign_interval
+            .map_or(DEFAULT_SYSLOG_SIGN_INTERVAL, NonZeroU64::get);
+        let hostname = settings
+            .syslog_sign_hostname
+            .unwrap_or_else(|| local_hostname().unwrap_or_else(|| "localhost".to_owned()));
+        let rsid = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+        let signer = Arc::new(BlockSigner {
+            key,
+            digest,
+            ver,
+            hostname,
+            rsid,
+            count,
+            tx,
+            fields_prefix: missing_fields(fields_count),
+            state: Mutex::new(SignState {
+                hashes: Vec::with_capacity(count),
+                fmn: 1,
+                gbc: 0,
+                pending: Vec::new(),
+                last_message: Instant::now(),
+            }),
+        });
+        match signer.certificate_block() {
+            Ok(block) => signer.send(block),
+            Err(e) => panic!("Unable to sign the certificate block: {}", e),
+        }
+        let flusher = Arc::clone(&signer);
+        threads::spawn("flowgger-syslog-sign".to_owned(), None, move || loop {
+            thread::sleep(Duration::from_secs(interval));
+            flusher.flush();
+        });
+        Box::new(SyslogSignEncoder { encoder, signer })
+    }
+}
+
+impl BlockSigner {
+    /// Add the hash of a message to the block being built. The complete blocks are sent once the state is
+    /// unlocked, so that a full output queue doesn't block the other encoder threads on it.
+    fn add(&self, message: &[u8]) -> Result<(), ErrorStack> {
+        let hash = base64::encode_block(&hash(self.digest, message)?);
+        let pending = {
+            let mut state = self.state.lock().unwrap();
+            let pending = mem::take(&mut state.pending);
+            state.hashes.push(hash);
+            state.last_message = Instant::now();
+            if state.hashes.len() >= self.count {
+                let block = self.signature_block(&mut state)?;
+                state.pending.push(block);
+            }
+            pending
+        };
+        for block in pending {
+            self.send(block);
+        }
+        Ok(())
+    }
+
+    /// Send the complete blocks, and the incomplete one if no messages were added lately
+    fn flush(&self) {
+        let pending = {
+            let mut state = self.state.lock().unwrap();
+            let mut pending = mem::take(&mut state.pending);
+            if !state.hashes.is_empty() && state.last_message.elapsed() >= SETTLE_DELAY {
+                match self.signature_block(&mut state) {
+                    Ok(block) => pending.push(block),
+                    Err(e) => {
+                        let _ = writeln!(stderr(), "Unable to sign a signature block: {}", e);
+                    }
+                }
+            }
+            pending
+        };
+        for block in pending {
+            self.send(block);
+        }
+    }
+
+    fn send(&self, block: Vec<u8>) {
+        let block = if self.fields_prefix.is_empty() {
+            block
+        } else {
+            [&self.fields_prefix[..], &block].concat()
+        };
+        let _ = self.tx.send(block);
+    }
+
+    fn signature_block(&self, state: &mut SignState) -> Result<Vec<u8>, ErrorStack> {
+        state.gbc += 1;
+        let cnt = state.hashes.len();
+        let params = format!(
+            r#"ssign VER="{}" RSID="{}" SG="0" SPRI="0" GBC="{}" FMN="{}" CNT="{}" HB="{}""#,
+            self.ver,
+            self.rsid,
+            state.gbc,
+            state.fmn,
+            cnt,
+            state.hashes.join(" ")
+        );
+        state.fmn += cnt as u64;
+        state.hashes.clear();
+        self.signed_message(&params)
+    }
+
+    /// Certificate block with the public key, in a single fragment
+    fn certificate_block(&self) -> Result<Vec<u8>, ErrorStack> {
+        let payload = format!(
+            "{} K {}",
+            now(),
+            base64::encode_block(&self.key.public_key_to_der()?)
+        );
+        let params = format!(
+            r#"ssign-cert VER="{}" RSID="{}" SG="0" SPRI="0" TPBL="{}" INDEX="1" FLEN="{}" FRAG="{}""#,
+            self.ver,
+            self.rsid,
+            payload.len(),
+            payload.len(),
+            payload
+        );
+        self.signed_message(&params)
+    }
+
+    /// Message with a structured data element, signed over the message with an empty SIGN value
+    fn signed_message(&self, params: &str) -> Result<Vec<u8>, ErrorStack> {
+        let head = format!(
+            "<{}>1 {} {} {} - - [{} SIGN=\"",
+            SIGN_BLOCK_PRI,
+            now(),
+            self.hostname,
+            SIGN_BLOCK_APPNAME,
+            params
+        );
+        let mut signer = Signer::new(self.digest, &self.key)?;
+        signer.update(head.as_bytes())?;
+        signer.update(b"\"]")?;
+        let signature = base64::encode_block(&signer.sign_to_vec()?);
+        Ok(format!("{}{}\"]", head, signature).into_bytes())
+    }
+}
+
+fn now() -> String {
+    OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_else(|_| "-".to_owned())
+}
+
+impl Encoder for SyslogSignEncoder {
+    fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
+        let encoded = self.encoder.encode(record)?;
+        self.signer
+            .add(&encoded)
+            .or(Err("Unable to hash the record for its signature block"))?;
+        Ok(encoded)
+    }
+}
+
+#[cfg(all(test, feature = "rfc5424"))]
+mod tests {
+    use super::*;
+    use crate::flowgger::decoder::{RFC5424Decoder, SyslogSignDecoder};
+    use crate::flowgger::encoder::{split_fields, FieldsEncoder, RFC5424Encoder};
+    use crate::flowgger::record::{Facility, Severity, Timestamp};
+    use crate::flowgger::record_queue::unbounded;
+    use openssl::dsa::Dsa;
+    use tempdir::TempDir;
+
+    fn record(msg: &str) -> Record<'_> {
+        Record::builder()
+            .ts(Timestamp::from_secs_f64(1385053862.3072))
+            .hostname("example.org")
+            .facility(Facility::User)
+            .severity(Severity::Informational)
+            .appname("appname")
+            .msg(msg)
+            .build()
+    }
+
+    fn verify<'a>(config: &Config, messages: &'a [String]) -> Vec<Record<'a>> {
+        let decoder = SyslogSignDecoder::wrap(config, Box::new(RFC5424Decoder::new(config)));
+        messages
+            .iter()
+            .map(|message| decoder.decode(message).unwrap())
+            .collect()
+    }
+
+    /// Configuration signing blocks of 2 records with a new key, and verifying them
+    fn config(temp_dir: &TempDir) -> Config {
+        let key = PKey::from_dsa(Dsa::generate(2048).unwrap()).unwrap();
+        let private_path = temp_dir.path().join("private.pem");
+        let public_path = temp_dir.path().join("public.pem");
+        fs::write(&private_path, key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+        fs::write(&public_path, key.public_key_to_pem().unwrap()).unwrap();
+        Config::from_string(&format!(
+            "[input]\nsyslog_sign_key = {:?}\n[output]\nformat = \"rfc5424\"\nsyslog_sign_key = {:?}\nsyslog_sign_count = 2\n",
+            public_path, private_path
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_syslog_sign_round_trip() {
+        let temp_dir = TempDir::new("test_syslog_sign").unwrap();
+        let config = config(&temp_dir);
+        let (tx, rx) = unbounded();
+        let encoder = SyslogSignEncoder::wrap(
+            &config,
+            Box::new(RFC5424Encoder::new(&config)),
+            tx.clone(),
+            0,
+        );
+        for msg in ["first", "second", "third"] {
+            tx.send(encoder.encode(record(msg)).unwrap()).unwrap();
+        }
+        // The block of the first two messages is sent before the third one
+        let messages: Vec<_> = rx
+            .try_iter()
+            .map(|message| String::from_utf8(message).unwrap())
+            .collect();
+        assert_eq!(messages.len(), 5);
+        assert!(messages[0].contains("[ssign-cert "));
+        assert!(messages[3].contains(r#"[ssign VER="0121" "#));
+        assert!(messages[3].contains(r#" GBC="1" FMN="1" CNT="2" "#));
+
+        let records = verify(&config, &messages);
+        assert_eq!(records[0].field("sign_valid"), Some("true".to_owned()));
+        assert_eq!(records[1].field("sign_valid"), None);
+        assert_eq!(records[3].field("sign_valid"), Some("true".to_owned()));
+        assert_eq!(records[3].field("sign_missing"), Some("0".to_owned()));
+
+        let mut tampered = messages.clone();
+        tampered[2] = tampered[2].replace("second", "secund");
+        let records = verify(&config, &tampered);
+        assert_eq!(records[3].field("sign_valid"), Some("true".to_owned()));
+        assert_eq!(records[3].field("sign_missing"), Some("1".to_owned()));
+
+        let mut forged = messages.clone();
+        forged[3] = forged[3].replace(r#"GBC="1""#, r#"GBC="2""#);
+        let records = verify(&config, &forged);
+        assert_eq!(records[3].field("sign_valid"), Some("false".to_owned()));
+        assert_eq!(records[3].field("sign_missing"), None);
+    }
+
+    #[test]
+    fn test_syslog_sign_record_fields() {
+        let temp_dir = TempDir::new("test_syslog_sign_fields").unwrap();
+        let config = config(&temp_dir);
+        let (tx, rx) = unbounded();
+        let encoder = SyslogSignEncoder::wrap(
+            &config,
+            Box::new(RFC5424Encoder::new(&config)),
+            tx.clone(),
+            1,
+        );
+        let encoder = FieldsEncoder::wrap(encoder, vec!["hostname".to_owned()]);
+        for msg in ["first", "second"] {
+            tx.send(encoder.encode(record(msg)).unwrap()).unwrap();
+        }
+        encoder.encode(record("third")).unwrap();
+
+        // Outputs with record fields split every message, and only send the payload
+        let mut hostnames = Vec::new();
+        let mut messages = Vec::new();
+        for message in rx.try_iter() {
+            let (fields, payload) = split_fields(&message, 1).unwrap();
+            hostnames.push(fields[0].map(|x| String::from_utf8(x.to_vec()).unwrap()));
+            messages.push(String::from_utf8(payload.to_vec()).unwrap());
+        }
+        let example = Some("example.org".to_owned());
+        assert_eq!(hostnames, vec![None, example.clone(), example, None]);
+        let records = verify(&config, &messages);
+        assert_eq!(records[3].field("sign_valid"), Some("true".to_owned()));
+        assert_eq!(records[3].field("sign_missing"), Some("0".to_owned()));
+    }
+}
@@ -0,0 +1,133 @@
+use super::Encoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+pub const TRUNCATED_KEY: &str = "_truncated";
+const DEFAULT_TRUNCATION_MARKER: &str = "[...]";
+
+/// Settings of the truncation, in the `[output]` section
+#[derive(Deserialize)]
+pub struct TruncateSettings {
+    max_msg_length: Option<NonZeroUsize>,
+    truncation_marker: Option<String>,
+}
+
+/// Encoder wrapper truncating the messages longer than a maximum length, i.e. multi-megabyte stack traces
+/// that downstream systems would reject. Truncated records get a marker at the end of their messages, and
+/// a `_truncated` structured data set to true.
+pub struct TruncateEncoder {
+    encoder: Box<dyn Encoder + Send>,
+    max_len: usize,
+    marker: Arc<str>,
+}
+
+impl Clone for TruncateEncoder {
+    fn clone(&self) -> TruncateEncoder {
+        TruncateEncoder {
+            encoder: self.encoder.clone_boxed(),
+            max_len: self.max_len,
+            marker: Arc::clone(&self.marker),
+        }
+    }
+}
+
+impl TruncateEncoder {
+    /// # Parameters
+    /// - 'output.max_msg_length': Optional. Maximum length of `msg` and `full_msg` in bytes, the marker
+    ///   excluded. Messages are cut at a character boundary.
+    /// - 'output.truncation_marker': Optional. Appended to the truncated messages. Default is "[...]".
+    ///
+    /// # Returns
+    /// The encoder as is if 'output.max_msg_length' is not set, or wrapped so that it truncates the messages
+    pub fn wrap(config: &Config, encoder: Box<dyn Encoder + Send>) -> Box<dyn Encoder + Send> {
+        let settings: TruncateSettings = config.settings("output");
+        let max_len = match settings.max_msg_length {
+            None => return encoder,
+            Some(max_len) => max_len.get(),
+        };
+        let marker = settings
+            .truncation_marker
+            .as_deref()
+            .unwrap_or(DEFAULT_TRUNCATION_MARKER);
+        Box::new(TruncateEncoder {
+            encoder,
+            max_len,
+            marker: marker.into(),
+        })
+    }
+
+    /// # Returns
+    /// `true` if the text had to be truncated
+    fn truncate(&self, text: &mut Cow<'_, str>) -> bool {
+        if text.len() <= self.max_len {
+            return false;
+        }
+        let mut len = self.max_len;
+        while !text.is_char_boundary(len) {
+            len -= 1;
+        }
+        let text = text.to_mut();
+        text.truncate(len);
+        text.push_str(&self.marker);
+        true
+    }
+}
+
+impl Encoder for TruncateEncoder {
+    fn encode(&self, mut record: Record) -> Result<Vec<u8>, &'static str> {
+        let mut truncated = false;
+        if let Some(msg) = record.msg.as_mut() {
+            truncated |= self.truncate(msg);
+        }
+        if let Some(full_msg) = record.full_msg.as_mut() {
+            truncated |= self.truncate(full_msg);
+        }
+        if truncated {
+            record.push_sd_pair(TRUNCATED_KEY, SDValue::Bool(true));
+        }
+        self.encoder.encode(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct TestEncoder;
+
+    impl Encoder for TestEncoder {
+        fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
+            let truncated = record.field("truncated").unwrap_or_default();
+            Ok(format!(
+                "{}|{}|{}",
+                record.msg.unwrap_or_default(),
+                record.full_msg.unwrap_or_default(),
+                truncated
+            )
+            .into_bytes())
+        }
+    }
+
+    fn encode(msg: &str, full_msg: &str) -> String {
+        let config = Config::from_string("[output]\nmax_msg_length = 6\n").unwrap();
+        let encoder = TruncateEncoder::wrap(&config, Box::new(TestEncoder));
+        let record = Record::builder()
+            .hostname("example.org")
+            .msg(msg)
+            .full_msg(full_msg)
+            .build();
+        String::from_utf8(encoder.encode(record).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_truncate_encoder() {
+        assert_eq!(encode("short", "short"), "short|short|");
+        assert_eq!(encode("short", "café au lait"), "short|café [...]|true");
+        assert_eq!(encode("caféé", "short"), "café[...]|short|true");
+    }
+}
@@ -0,0 +1,45 @@
+//! Entry points of the cargo-fuzz targets in `fuzz/`, only compiled with `--cfg fuzzing`, that cargo-fuzz sets.
+//!
+//! Decoders and encoders must reject hostile input with an error, never panic: every panic found here is a
+//! remote crash of the collector.
+
+use super::config::Config;
+use super::{get_format_decoder, get_format_encoder};
+#[cfg(feature = "capnp")]
+use crate::flowgger::splitter::{CapnpSplitter, Splitter};
+#[cfg(feature = "capnp")]
+use std::io::BufReader;
+
+/// Output formats the accepted records are encoded to
+const ENCODERS: &[&str] = &["capnp", "gelf", "logfmt", "ltsv", "rfc3164", "rfc5424"];
+
+/// Decode `data` with the decoder configured by `config`, the TOML of a configuration file, and encode the
+/// accepted record with every encoder
+pub fn decode(config: &str, data: &[u8]) {
+    let config = Config::from_string(config).unwrap();
+    let input_format = config
+        .lookup("input.format")
+        .and_then(|x| x.as_str())
+        .expect("input.format is required");
+    let decoder = get_format_decoder(input_format, &config);
+    if decoder.decode_bytes(data).is_err() {
+        return;
+    }
+    for output_format in ENCODERS {
+        let encoder = get_format_encoder(output_format, &config);
+        // Records can't be cloned, the input is decoded again for every encoder
+        if let Ok(record) = decoder.decode_bytes(data) {
+            let _ = encoder.encode(record);
+        }
+    }
+}
+
+/// Read Cap'n Proto records from `data`, as the capnp input framing does, and encode them as RFC5424
+#[cfg(feature = "capnp")]
+pub fn split_capnp(data: &[u8]) {
+    let config = Config::from_string("").unwrap();
+    let (tx, _rx) = crate::flowgger::record_queue::unbounded();
+    let decoder = get_format_decoder("capnp", &config);
+    let encoder = get_format_encoder("rfc5424", &config);
+    CapnpSplitter.run(BufReader::new(data), tx, decoder, encoder);
+}
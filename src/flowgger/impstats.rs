@@ -0,0 +1,268 @@
+use crate::flowgger::admin;
+use crate::flowgger::config::Config;
+use crate::flowgger::encoder::Encoder;
+use crate::flowgger::record::{Facility, Record, Severity, Timestamp};
+use crate::flowgger::record_queue::RecordSender;
+use crate::flowgger::utils::{local_hostname, threads};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::io::{stderr, Write};
+use std::num::NonZeroU64;
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_STATS_FORMAT: StatsFormat = StatsFormat::Legacy;
+const DEFAULT_STATS_FACILITY: Facility = Facility::Syslog;
+const DEFAULT_STATS_SEVERITY: Severity = Severity::Informational;
+const DEFAULT_STATS_APPNAME: &str = "rsyslogd-pstats";
+
+/// Counters of the queue, named as the ones of the main queue of rsyslog
+const QUEUE_COUNTERS: &[(&str, &str)] = &[
+    ("queue.occupancy", "size"),
+    ("queue.peak", "maxqsize"),
+    ("queue.capacity", "capacity"),
+    ("queue.warnings", "warnings"),
+    ("queue.bytes", "bytes"),
+    ("queue.max_bytes", "maxbytes"),
+];
+
+/// Layout of the messages, as the formats of rsyslog impstats
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum StatsFormat {
+    /// `main Q: origin=core.queue size=0 maxqsize=12`
+    Legacy,
+    /// `{ "name": "main Q", "origin": "core.queue", "size": 0, "maxqsize": 12 }`
+    Json,
+    /// The JSON format, after a "@cee: " cookie
+    Cee,
+}
+
+/// Settings of the statistics records, in the `[stats]` section
+#[derive(Deserialize)]
+pub struct StatsSettings {
+    interval: Option<NonZeroU64>,
+    format: Option<StatsFormat>,
+    facility: Option<u8>,
+    severity: Option<u8>,
+    appname: Option<String>,
+    hostname: Option<String>,
+}
+
+/// Counters of a component, reported in one record
+#[derive(Debug, PartialEq)]
+struct Stats {
+    name: String,
+    origin: String,
+    counters: Vec<(String, u64)>,
+}
+
+/// Periodic statistics records, sent through the encoder and the output like the records of the inputs, in
+/// the format of the impstats module of rsyslog, so that the dashboards and alerts built on them keep working.
+///
+/// There is one record per component: the queue, reported as the main queue of rsyslog ("main Q"), and the
+/// components whose counters are reported by the "stats" command of the admin socket, named after the prefix of
+/// their counters (i.e. "accounting", "blackhole").
+pub struct StatsRecords {
+    interval: Duration,
+    format: StatsFormat,
+    hostname: String,
+    appname: String,
+    facility: Facility,
+    severity: Severity,
+}
+
+impl StatsRecords {
+    /// # Parameters
+    /// - 'stats.interval': Optional. Seconds between two reports. Statistics records are only emitted when this
+    ///   is set.
+    /// - 'stats.format':   Optional. "legacy", "json" or "cee", as the formats of rsyslog impstats. Default is
+    ///   "legacy".
+    /// - 'stats.facility': Optional. Facility of the records. Default is 5 (syslog).
+    /// - 'stats.severity': Optional. Severity of the records. Default is 6 (info).
+    /// - 'stats.appname':  Optional. Application name of the records. Default is "rsyslogd-pstats", the tag of
+    ///   the records of impstats.
+    /// - 'stats.hostname': Optional. Host name of the records. Default is the name of the local host.
+    pub fn new(config: &Config) -> Option<StatsRecords> {
+        let settings: StatsSettings = config.settings("stats");
+        let interval = settings.interval?.get();
+        let facility = settings.facility.map_or(DEFAULT_STATS_FACILITY, |code| {
+            Facility::try_from(code).expect("stats.facility must be an integer between 0 and 23")
+        });
+        let severity = settings.severity.map_or(DEFAULT_STATS_SEVERITY, |code| {
+            Severity::try_from(code).expect("stats.severity must be an integer between 0 and 7")
+        });
+        let hostname = settings
+            .hostname
+            .unwrap_or_else(|| local_hostname().unwrap_or_else(|| "localhost".to_owned()));
+        Some(StatsRecords {
+            interval: Duration::from_secs(interval),
+            format: settings.format.unwrap_or(DEFAULT_STATS_FORMAT),
+            hostname,
+            appname: settings
+                .appname
+                .unwrap_or_else(|| DEFAULT_STATS_APPNAME.to_owned()),
+            facility,
+            severity,
+        })
+    }
+    /// Send the statistics records to `tx` every interval
+    pub fn start(self, tx: RecordSender, encoder: Box<dyn Encoder + Send>) {
+        threads::spawn("flowgger-stats".to_owned(), None, move || loop {
+            thread::sleep(self.interval);
+            for record in self.records(group(admin::counters()), Timestamp::now()) {
+                match encoder.encode(record) {
+                    Ok(encoded) => {
+                        if tx.send(encoded).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        let _ = writeln!(stderr(), "Unable to encode the statistics: {}", e);
+                    }
+                }
+            }
+        });
+    }
+
+    fn records(&self, stats: Vec<Stats>, ts: Timestamp) -> Vec<Record<'_>> {
+        stats
+            .into_iter()
+            .map(|stats| Record {
+                ts,
+                utc_offset: None,
+                hostname: self.hostname.as_str().into(),
+                facility: Some(self.facility),
+                severity: Some(self.severity),
+                appname: Some(self.appname.as_str().into()),
+                procid: None,
+                msgid: None,
+                msg: Some(self.format_stats(&stats).into()),
+                full_msg: None,
+                sd: None,
+            })
+            .collect()
+    }
+
+    fn format_stats(&self, stats: &Stats) -> String {
+        if self.format == StatsFormat::Legacy {
+            let mut msg = format!("{}: origin={}", stats.name, stats.origin);
+            for (name, value) in &stats.counters {
+                msg.push_str(&format!(" {}={}", name, value));
+            }
+            return msg;
+        }
+        let mut json = format!(
+            r#"{{ "name": {}, "origin": {}"#,
+            json_string(&stats.name),
+            json_string(&stats.origin)
+        );
+        for (name, value) in &stats.counters {
+            json.push_str(&format!(", {}: {}", json_string(name), value));
+        }
+        json.push_str(" }");
+        match self.format {
+            StatsFormat::Cee => format!("@cee: {}", json),
+            _ => json,
+        }
+    }
+}
+
+/// Group the counters by component, the part of their names before the first dot
+fn group(counters: Vec<(String, u64)>) -> Vec<Stats> {
+    let mut groups: Vec<Stats> = Vec::new();
+    for (name, value) in counters {
+        let (group, counter) = match QUEUE_COUNTERS.iter().find(|(queue, _)| *queue == name) {
+            Some((_, counter)) => ("main Q", counter.to_string()),
+            None => match name.split_once('.') {
+                Some((group, counter)) => (group, counter.to_owned()),
+                None => (name.as_str(), "value".to_owned()),
+            },
+        };
+        match groups.iter_mut().find(|stats| stats.name == group) {
+            Some(stats) => stats.counters.push((counter, value)),
+            None => groups.push(Stats {
+                name: group.to_owned(),
+                origin: match group {
+                    "main Q" => "core.queue".to_owned(),
+                    _ => format!("flowgger.{}", group),
+                },
+                counters: vec![(counter, value)],
+            }),
+        }
+    }
+    groups
+}
+
+fn json_string(value: &str) -> String {
+    let mut res = String::with_capacity(value.len() + 2);
+    res.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counters() -> Vec<(String, u64)> {
+        [
+            ("queue.occupancy", 3),
+            ("queue.capacity", 10000),
+            ("queue.peak", 12),
+            ("queue.warnings", 0),
+            ("tap.enabled", 0),
+            ("accounting.tenant \"a\".records", 7),
+            ("accounting.tenant \"a\".bytes", 700),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), *value))
+        .collect()
+    }
+
+    #[test]
+    fn test_stats_records() {
+        let config = Config::from_string("[stats]\ninterval = 60\nhostname = \"h\"\n").unwrap();
+        let stats = StatsRecords::new(&config).unwrap();
+        let records = stats.records(group(counters()), Timestamp::from_secs_f64(1385053862.3072));
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].hostname, "h");
+        assert_eq!(records[0].appname.as_deref(), Some("rsyslogd-pstats"));
+        assert_eq!(
+            (records[0].facility, records[0].severity),
+            (Some(Facility::Syslog), Some(Severity::Informational))
+        );
+        assert_eq!(
+            records[0].msg.as_deref(),
+            Some("main Q: origin=core.queue size=3 capacity=10000 maxqsize=12 warnings=0")
+        );
+        assert_eq!(
+            records[1].msg.as_deref(),
+            Some("tap: origin=flowgger.tap enabled=0")
+        );
+
+        let config = Config::from_string("[stats]\ninterval = 60\nformat = \"cee\"\n").unwrap();
+        let stats = StatsRecords::new(&config).unwrap();
+        let records = stats.records(group(counters()), Timestamp::from_secs_f64(1385053862.3072));
+        assert_eq!(
+            records[2].msg.as_deref(),
+            Some(
+                r#"@cee: { "name": "accounting", "origin": "flowgger.accounting", "tenant \"a\".records": 7, "tenant \"a\".bytes": 700 }"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_stats_records_disabled() {
+        let config = Config::from_string("[stats]\nformat = \"json\"\n").unwrap();
+        assert!(StatsRecords::new(&config).is_none());
+    }
+}
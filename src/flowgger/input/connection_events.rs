@@ -0,0 +1,157 @@
+use crate::flowgger::config::Config;
+use crate::flowgger::encoder::Encoder;
+use crate::flowgger::record::{Facility, Record, SDValue, Severity, StructuredData, Timestamp};
+use crate::flowgger::record_queue::RecordSender;
+use crate::flowgger::utils::local_hostname;
+use serde::Deserialize;
+use std::io::{stderr, Write};
+use std::net::SocketAddr;
+
+const DEFAULT_CONNECTION_EVENTS: bool = false;
+const DEFAULT_CONNECTION_EVENTS_APPNAME: &str = "flowgger";
+const CONNECTION_EVENTS_SD_ID: &str = "connection@32473";
+const CONNECTION_EVENTS_FACILITY: Facility = Facility::Syslog;
+
+/// What happened to a connection of a stream input
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConnectionEvent {
+    Connected,
+    Disconnected,
+    HandshakeFailed(String),
+}
+
+/// Settings of the connection events, in the `[input]` section
+#[derive(Deserialize)]
+pub struct ConnectionEventsSettings {
+    connection_events: Option<bool>,
+    connection_events_appname: Option<String>,
+}
+
+/// Records of the connections of the stream inputs, sent through the encoder and the output like the records
+/// they receive, so that the connectivity of the senders can be audited with the logs themselves.
+///
+/// The records have the "CONNECT", "DISCONNECT" or "HANDSHAKE_FAILED" message id, and the peer, the
+/// transport and the reason of a failure as structured data:
+/// `[connection@32473 peer="192.0.2.1:54321" transport="tls" reason="..."]`
+#[derive(Clone)]
+pub struct ConnectionEvents {
+    transport: &'static str,
+    hostname: String,
+    appname: String,
+}
+
+impl ConnectionEvents {
+    /// # Parameters
+    /// - `transport`: "tcp" or "tls"
+    /// - 'input.connection_events':         Optional. Emit a record when a peer connects or disconnects, or when
+    ///   its TLS handshake fails. Default is false.
+    /// - 'input.connection_events_appname': Optional. Application name of the records. Default is "flowgger".
+    pub fn new(config: &Config, transport: &'static str) -> Option<ConnectionEvents> {
+        let settings: ConnectionEventsSettings = config.settings("input");
+        if !settings
+            .connection_events
+            .unwrap_or(DEFAULT_CONNECTION_EVENTS)
+        {
+            return None;
+        }
+        let appname = settings
+            .connection_events_appname
+            .unwrap_or_else(|| DEFAULT_CONNECTION_EVENTS_APPNAME.to_owned());
+        Some(ConnectionEvents {
+            transport,
+            hostname: local_hostname().unwrap_or_else(|| "localhost".to_owned()),
+            appname,
+        })
+    }
+
+    /// Send the record of an event of the connection from `peer`
+    pub fn emit(
+        &self,
+        event: ConnectionEvent,
+        peer: Option<SocketAddr>,
+        tx: &RecordSender,
+        encoder: &dyn Encoder,
+    ) {
+        let record = self.record(event, peer);
+        match encoder.encode(record) {
+            Ok(encoded) => {
+                let _ = tx.send(encoded);
+            }
+            Err(e) => {
+                let _ = writeln!(stderr(), "Unable to encode a connection event: {}", e);
+            }
+        }
+    }
+
+    fn record(&self, event: ConnectionEvent, peer: Option<SocketAddr>) -> Record<'_> {
+        let peer = peer.map_or("unknown".to_owned(), |peer| peer.to_string());
+        let transport = self.transport.to_uppercase();
+        let mut sd = StructuredData::new(Some(CONNECTION_EVENTS_SD_ID));
+        sd.pairs
+            .push(("peer".into(), SDValue::String(peer.clone().into())));
+        sd.pairs
+            .push(("transport".into(), SDValue::String(self.transport.into())));
+        let (msgid, severity, msg) = match event {
+            ConnectionEvent::Connected => (
+                "CONNECT",
+                Severity::Informational,
+                format!("Connection over {} from [{}]", transport, peer),
+            ),
+            ConnectionEvent::Disconnected => (
+                "DISCONNECT",
+                Severity::Informational,
+                format!("Connection over {} from [{}] closed", transport, peer),
+            ),
+            ConnectionEvent::HandshakeFailed(reason) => {
+                let msg = format!("{} handshake with [{}] failed: {}", transport, peer, reason);
+                sd.pairs
+                    .push(("reason".into(), SDValue::String(reason.into())));
+                ("HANDSHAKE_FAILED", Severity::Warning, msg)
+            }
+        };
+        Record {
+            ts: Timestamp::now(),
+            utc_offset: None,
+            hostname: self.hostname.as_str().into(),
+            facility: Some(CONNECTION_EVENTS_FACILITY),
+            severity: Some(severity),
+            appname: Some(self.appname.as_str().into()),
+            procid: None,
+            msgid: Some(msgid.into()),
+            msg: Some(msg.into()),
+            full_msg: None,
+            sd: Some(vec![sd]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_events() {
+        let config = Config::from_string("[input]\n").unwrap();
+        assert!(ConnectionEvents::new(&config, "tcp").is_none());
+
+        let config = Config::from_string(
+            "[input]\nconnection_events = true\nconnection_events_appname = \"audit\"\n",
+        )
+        .unwrap();
+        let events = ConnectionEvents::new(&config, "tls").unwrap();
+        let peer = Some("192.0.2.1:54321".parse().unwrap());
+        let record = events.record(ConnectionEvent::Connected, peer);
+        assert_eq!(record.msgid.as_deref(), Some("CONNECT"));
+        assert_eq!(record.appname.as_deref(), Some("audit"));
+        assert_eq!(record.field("peer").as_deref(), Some("192.0.2.1:54321"));
+
+        let reason = "certificate verify failed".to_owned();
+        let record = events.record(ConnectionEvent::HandshakeFailed(reason), peer);
+        assert_eq!(record.msgid.as_deref(), Some("HANDSHAKE_FAILED"));
+        assert_eq!(record.severity, Some(Severity::Warning));
+        assert_eq!(
+            record.field("reason").as_deref(),
+            Some("certificate verify failed")
+        );
+    }
+}
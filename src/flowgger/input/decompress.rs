@@ -0,0 +1,123 @@
+use crate::flowgger::config::Config;
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use serde::Deserialize;
+use std::io::{self, Read};
+
+/// Settings of the decompression, in the `[input]` section
+#[derive(Deserialize)]
+pub struct DecompressSettings {
+    decompress: Option<String>,
+}
+
+/// Compression of the streams received by the TCP and TLS inputs
+///
+/// Senders have to flush their compressor (i.e. `Z_SYNC_FLUSH`) after every record or batch of records,
+/// otherwise records are only received once the compressor decides to emit a block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decompression {
+    None,
+    Gzip,
+    Zlib,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Decompression {
+    /// # Parameters
+    /// - 'input.decompress': Optional. "gzip", "zlib" or "zstd" (requires the "zstd" feature).
+    ///   Default is no decompression.
+    pub fn from_config(config: &Config) -> Decompression {
+        let settings: DecompressSettings = config.settings("input");
+        let decompress = match settings.decompress {
+            None => return Decompression::None,
+            Some(decompress) => decompress,
+        };
+        match decompress.as_str() {
+            "none" => Decompression::None,
+            "gzip" => Decompression::Gzip,
+            "zlib" => Decompression::Zlib,
+            #[cfg(feature = "zstd")]
+            "zstd" => Decompression::Zstd,
+            #[cfg(not(feature = "zstd"))]
+            "zstd" => panic!("Support for zstd is not compiled in"),
+            _ => panic!(r#"input.decompress must be a string set to "gzip", "zlib" or "zstd""#),
+        }
+    }
+
+    /// Wrap a stream so that the splitter reads decompressed data
+    pub fn reader<'a, T: Read + 'a>(self, stream: T) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Decompression::None => Box::new(stream),
+            Decompression::Gzip => Box::new(MultiGzDecoder::new(stream)),
+            Decompression::Zlib => Box::new(ZlibDecoder::new(stream)),
+            #[cfg(feature = "zstd")]
+            Decompression::Zstd => Box::new(zstd::stream::read::Decoder::new(stream)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn decompress(config: &str, compressed: &[u8]) -> String {
+        let config = Config::from_string(config).unwrap();
+        let mut decompressed = String::new();
+        Decompression::from_config(&config)
+            .reader(compressed)
+            .unwrap()
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        decompressed
+    }
+
+    #[test]
+    fn test_decompress_none() {
+        assert_eq!(decompress("[input]\n", b"line1\nline2\n"), "line1\nline2\n");
+    }
+
+    #[test]
+    fn test_decompress_gzip_members() {
+        let mut compressed = Vec::new();
+        for line in &["line1\n", "line2\n"] {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(line.as_bytes()).unwrap();
+            compressed.extend(encoder.finish().unwrap());
+        }
+        assert_eq!(
+            decompress("[input]\ndecompress = \"gzip\"\n", &compressed),
+            "line1\nline2\n"
+        );
+    }
+
+    #[test]
+    fn test_decompress_zlib() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"line1\nline2\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(
+            decompress("[input]\ndecompress = \"zlib\"\n", &compressed),
+            "line1\nline2\n"
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_decompress_zstd() {
+        let compressed = zstd::stream::encode_all(&b"line1\nline2\n"[..], 0).unwrap();
+        assert_eq!(
+            decompress("[input]\ndecompress = \"zstd\"\n", &compressed),
+            "line1\nline2\n"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "input.decompress must be a string")]
+    fn test_decompress_invalid() {
+        let config = Config::from_string("[input]\ndecompress = \"lzma\"\n").unwrap();
+        let _ = Decompression::from_config(&config);
+    }
+}
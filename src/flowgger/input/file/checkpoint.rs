@@ -0,0 +1,247 @@
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{stderr, BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::flowgger::config::Config;
+use crate::flowgger::input::check_counted_notifications;
+use crate::flowgger::output::Notifier;
+use crate::flowgger::record_queue::RecordSender;
+use crate::flowgger::utils::threads;
+
+const CHECKPOINT_FLUSH_INTERVAL_MS: u64 = 1000;
+
+#[derive(Default)]
+struct CheckpointState {
+    /// File and offset right after every record sent to the queue and not delivered yet, in queue order
+    pending: VecDeque<(Arc<str>, u64)>,
+    /// Offset up to which every record of a file has been delivered
+    offsets: HashMap<Arc<str>, u64>,
+    /// Files with records that could not be delivered, whose offsets are not advanced any more, so that they
+    /// are read again from the first of them after a restart
+    failed: HashSet<Arc<str>>,
+    dirty: bool,
+}
+
+/// Settings of the checkpoints, in the `[input]` section
+#[derive(Deserialize)]
+pub struct CheckpointSettings {
+    checkpoint: Option<PathBuf>,
+}
+
+/// Offsets of the files read by the file input, only advanced once the output reported the delivery
+/// of the corresponding records. After a restart, files are read again from the saved offsets, so that
+/// records that were still in flight are sent again (at-least-once delivery).
+///
+/// Deliveries are matched with records by counting, so this relies on the queue and the output
+/// preserving the order of the records: a single output thread is required, and every record of the queue
+/// has to come from the file input, so neither stats records nor signature blocks can be enabled.
+/// Outputs notify every record once, with its final outcome. Once a record of a file could not be
+/// delivered, the offset of that file isn't advanced any more until a restart, that reads the file again
+/// from that record.
+pub struct Checkpoint {
+    path: PathBuf,
+    state: Mutex<CheckpointState>,
+    /// Serializes the sends of the file workers, so that `pending` matches the order of the queue
+    send_lock: Mutex<()>,
+}
+
+impl Checkpoint {
+    /// Build a checkpoint from the configuration, loading the previously saved offsets
+    ///
+    /// # Parameters
+    /// - 'input.checkpoint': Optional. Path to the file where the offsets are saved. Checkpointing is
+    ///   only enabled when this is set.
+    pub fn new(config: &Config) -> Option<Checkpoint> {
+        let settings: CheckpointSettings = config.settings("input");
+        let path = settings.checkpoint?;
+        check_counted_notifications(config, "input.checkpoint");
+        Some(Self::load(&path))
+    }
+
+    fn load(path: &Path) -> Checkpoint {
+        let mut offsets = HashMap::new();
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line.expect("Unable to read the checkpoint file");
+                    let entry = line
+                        .split_once(' ')
+                        .and_then(|(offset, file)| Some((Arc::from(file), offset.parse().ok()?)));
+                    match entry {
+                        Some((file, offset)) => {
+                            offsets.insert(file, offset);
+                        }
+                        None => panic!("Invalid entry in the checkpoint file: [{}]", line),
+                    }
+                }
+            }
+            Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => panic!("Unable to open the checkpoint file: {}", e),
+        }
+        Checkpoint {
+            path: path.to_owned(),
+            state: Mutex::new(CheckpointState {
+                offsets,
+                ..Default::default()
+            }),
+            send_lock: Mutex::new(()),
+        }
+    }
+
+    /// Periodically save the offsets, when they changed
+    pub fn start(self: &Arc<Self>) {
+        let checkpoint = Arc::clone(self);
+        threads::spawn(
+            "flowgger-input-file-checkpoint".to_owned(),
+            None,
+            move || loop {
+                thread::sleep(Duration::from_millis(CHECKPOINT_FLUSH_INTERVAL_MS));
+                if let Err(e) = checkpoint.save() {
+                    let _ = writeln!(stderr(), "Unable to save the checkpoint: {}", e);
+                }
+            },
+        );
+    }
+
+    /// Offset up to which a file has been delivered, if it has been read before
+    pub fn offset(&self, file: &str) -> Option<u64> {
+        self.state.lock().unwrap().offsets.get(file).copied()
+    }
+
+    /// Send a record read from `file`, that ends at `offset`
+    pub fn send(&self, tx: &RecordSender, record: Vec<u8>, file: &Arc<str>, offset: u64) {
+        let _send_lock = self.send_lock.lock().unwrap();
+        self.state
+            .lock()
+            .unwrap()
+            .pending
+            .push_back((Arc::clone(file), offset));
+        tx.send(record).unwrap();
+    }
+
+    /// Mark the `count` oldest pending records as delivered, or as failed
+    fn ack(&self, count: usize, delivered: bool) {
+        let mut state = self.state.lock().unwrap();
+        for _ in 0..count {
+            let (file, offset) = match state.pending.pop_front() {
+                Some(entry) => entry,
+                None => break,
+            };
+            if !delivered {
+                if state.failed.insert(Arc::clone(&file)) {
+                    let _ = writeln!(
+                        stderr(),
+                        "Records of [{}] could not be delivered, its checkpoint won't advance until a restart",
+                        file
+                    );
+                }
+            } else if !state.failed.contains(&file) {
+                state.offsets.insert(file, offset);
+                state.dirty = true;
+            }
+        }
+    }
+
+    /// Atomically replace the checkpoint file with the current offsets
+    fn save(&self) -> Result<(), std::io::Error> {
+        let contents = {
+            let mut state = self.state.lock().unwrap();
+            if !state.dirty {
+                return Ok(());
+            }
+            state.dirty = false;
+            state
+                .offsets
+                .iter()
+                .map(|(file, offset)| format!("{} {}\n", offset, file))
+                .collect::<String>()
+        };
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let mut tmp_file = File::create(&tmp_path)?;
+        tmp_file.write_all(contents.as_bytes())?;
+        tmp_file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
+
+impl Notifier for Checkpoint {
+    fn notify(&self, records: &[Vec<u8>], result: Result<(), &str>) {
+        self.ack(records.len(), result.is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::record_queue::unbounded;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_checkpoint_disabled() {
+        let config = Config::from_string("[input]\ntype = \"file\"\n").unwrap();
+        assert!(Checkpoint::new(&config).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "input.checkpoint requires a single output thread")]
+    fn test_checkpoint_multiple_output_threads() {
+        let config = Config::from_string(
+            "[input]\ncheckpoint = \"/nonexistent\"\n[output]\nkafka_threads = 2\n",
+        )
+        .unwrap();
+        let _ = Checkpoint::new(&config);
+    }
+
+    #[test]
+    fn test_checkpoint_ack_and_reload() {
+        let temp_dir = TempDir::new("test_checkpoint").unwrap();
+        let path = temp_dir.path().join("checkpoint");
+        let checkpoint = Checkpoint::load(&path);
+        let (tx, rx) = unbounded();
+        let (a, b): (Arc<str>, Arc<str>) = (Arc::from("/var/log/a b.log"), Arc::from("/b.log"));
+
+        checkpoint.send(&tx, b"1".to_vec(), &a, 10);
+        checkpoint.send(&tx, b"2".to_vec(), &b, 5);
+        checkpoint.send(&tx, b"3".to_vec(), &a, 20);
+        assert_eq!(rx.len(), 3);
+        assert_eq!(checkpoint.offset(&a), None);
+
+        checkpoint.notify(&[b"1".to_vec(), b"2".to_vec()], Ok(()));
+        assert_eq!(checkpoint.offset(&a), Some(10));
+        assert_eq!(checkpoint.offset(&b), Some(5));
+        checkpoint.save().unwrap();
+
+        // Records still in flight are read again after a restart
+        let reloaded = Checkpoint::load(&path);
+        assert_eq!(reloaded.offset(&a), Some(10));
+        assert_eq!(reloaded.offset(&b), Some(5));
+
+        checkpoint.notify(&[b"3".to_vec()], Ok(()));
+        checkpoint.save().unwrap();
+        assert_eq!(Checkpoint::load(&path).offset(&a), Some(20));
+
+        // Once a record failed, the file is read again from it after a restart
+        checkpoint.send(&tx, b"4".to_vec(), &a, 30);
+        checkpoint.send(&tx, b"5".to_vec(), &b, 15);
+        checkpoint.send(&tx, b"6".to_vec(), &a, 40);
+        checkpoint.notify(&[b"4".to_vec()], Err("failed"));
+        checkpoint.notify(&[b"5".to_vec(), b"6".to_vec()], Ok(()));
+        assert_eq!(checkpoint.offset(&a), Some(20));
+        assert_eq!(checkpoint.offset(&b), Some(15));
+    }
+
+    #[test]
+    #[should_panic(expected = "stats.interval can't be set")]
+    fn test_checkpoint_stats_records() {
+        let config =
+            Config::from_string("[input]\ncheckpoint = \"/nonexistent\"\n[stats]\ninterval = 60\n")
+                .unwrap();
+        let _ = Checkpoint::new(&config);
+    }
+}
@@ -1,6 +1,7 @@
+use std::cell::Cell;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::{channel, Receiver, SyncSender};
-use std::thread;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
 use std::time::Duration;
 
 use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
@@ -9,23 +10,31 @@ use glob::{glob, Pattern};
 
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
+use crate::flowgger::input::file::checkpoint::Checkpoint;
 use crate::flowgger::input::file::worker::FileWorker;
+use crate::flowgger::record_queue::RecordSender;
+use crate::flowgger::utils::threads::{self, CpuAffinity};
 
 pub struct FileDiscovery {
     watcher: RecommendedWatcher,
     event_rx: Receiver<DebouncedEvent>,
     path_match: Pattern,
-    log_tx: SyncSender<Vec<u8>>,
+    log_tx: RecordSender,
     decoder: Box<dyn Decoder + Send>,
     encoder: Box<dyn Encoder + Send>,
+    checkpoint: Option<Arc<Checkpoint>>,
+    affinity: CpuAffinity,
+    workers: Cell<usize>,
 }
 
 impl FileDiscovery {
     pub fn new(
         path_match: &str,
-        log_tx: SyncSender<Vec<u8>>,
+        log_tx: RecordSender,
         decoder: Box<dyn Decoder + Send>,
         encoder: Box<dyn Encoder + Send>,
+        checkpoint: Option<Arc<Checkpoint>>,
+        affinity: CpuAffinity,
     ) -> FileDiscovery {
         let (tx, rx) = channel();
         let watcher =
@@ -38,6 +47,9 @@ impl FileDiscovery {
             log_tx,
             decoder,
             encoder,
+            checkpoint,
+            affinity,
+            workers: Cell::new(0),
         }
     }
 
@@ -106,8 +118,11 @@ impl FileDiscovery {
         let t = self.log_tx.clone();
         let d: Box<dyn Decoder + Send> = self.decoder.clone_boxed();
         let e: Box<dyn Encoder + Send> = self.encoder.clone_boxed();
-        thread::spawn(move || {
-            let mut worker = FileWorker::new(&p, t, d, e);
+        let c = self.checkpoint.clone();
+        let i = self.workers.replace(self.workers.get() + 1);
+        let name = format!("flowgger-input-file-{}", i);
+        threads::spawn(name, self.affinity.cpu(i), move || {
+            let mut worker = FileWorker::new(&p, t, d, e, c);
             worker.run(from_tail);
         });
     }
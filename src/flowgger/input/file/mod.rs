@@ -1,13 +1,28 @@
+mod checkpoint;
 mod discovery;
 mod worker;
+use self::checkpoint::Checkpoint;
+#[cfg(test)]
+pub use self::checkpoint::CheckpointSettings;
 use self::discovery::FileDiscovery;
 
-use std::sync::mpsc::SyncSender;
+use serde::Deserialize;
+use std::sync::Arc;
 
 use super::Input;
 use crate::flowgger::config::Config;
+use crate::flowgger::daemon;
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
+use crate::flowgger::output::Notifier;
+use crate::flowgger::record_queue::RecordSender;
+use crate::flowgger::utils::threads::CpuAffinity;
+
+/// Settings of the file input, in the `[input]` section
+#[derive(Deserialize)]
+pub struct FileSettings {
+    src: String,
+}
 
 #[derive(Clone)]
 pub struct FileConfig {
@@ -16,27 +31,57 @@ pub struct FileConfig {
 
 pub struct FileInput {
     file_config: FileConfig,
+    checkpoint: Option<Arc<Checkpoint>>,
+    affinity: CpuAffinity,
 }
 
 impl FileInput {
     pub fn new(config: &Config) -> FileInput {
-        let src_path = match config.lookup("input.src") {
-            None => panic!("Missing file path"),
-            Some(src) => src.as_str().expect("OK").to_owned(),
-        };
-        let file_config = FileConfig { src: src_path };
-        FileInput { file_config }
+        let settings: FileSettings = config.settings("input");
+        let file_config = FileConfig { src: settings.src };
+        let checkpoint = Checkpoint::new(config).map(Arc::new);
+        FileInput {
+            file_config,
+            checkpoint,
+            affinity: CpuAffinity::new(config, "input"),
+        }
     }
 }
 
 impl Input for FileInput {
     fn accept(
         &self,
-        tx: SyncSender<Vec<u8>>,
+        tx: RecordSender,
         decoder: Box<dyn Decoder + Send>,
         encoder: Box<dyn Encoder + Send>,
     ) {
-        let mut discovery = FileDiscovery::new(&self.file_config.src, tx, decoder, encoder);
+        daemon::listening();
+        if let Some(checkpoint) = &self.checkpoint {
+            checkpoint.start();
+        }
+        let mut discovery = FileDiscovery::new(
+            &self.file_config.src,
+            tx,
+            decoder,
+            encoder,
+            self.checkpoint.clone(),
+            self.affinity.clone(),
+        );
         discovery.run();
     }
+
+    fn notifier(&self) -> Option<Arc<dyn Notifier>> {
+        self.checkpoint
+            .clone()
+            .map(|checkpoint| checkpoint as Arc<dyn Notifier>)
+    }
+}
+
+/// Settings of the file input, for `flowgger config init`
+pub fn sample_config() -> String {
+    "# Files to read, as a glob pattern\n\
+     src = \"/var/log/app/*.log\"\n\
+     # Save the offsets of the files once their records have been delivered, and resume from them on restart\n\
+     # checkpoint = \"/var/lib/flowgger/checkpoint\"\n"
+        .to_owned()
 }
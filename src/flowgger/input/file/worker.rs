@@ -1,36 +1,41 @@
 use std;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::stderr;
 use std::io::{BufReader, SeekFrom};
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::{channel, SyncSender};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
 use std::time::Duration;
 
 use notify::{watcher, RecursiveMode, Watcher};
 
-use crate::flowgger::decoder::Decoder;
+use crate::flowgger::decoder::{log_rejected, Decoder};
 use crate::flowgger::encoder::Encoder;
+use crate::flowgger::input::file::checkpoint::Checkpoint;
+use crate::flowgger::record_queue::RecordSender;
 
 pub struct FileWorker {
     path: PathBuf,
-    tx: SyncSender<Vec<u8>>,
+    tx: RecordSender,
     decoder: Box<dyn Decoder + Send>,
     encoder: Box<dyn Encoder + Send>,
+    checkpoint: Option<Arc<Checkpoint>>,
 }
 
 impl FileWorker {
     pub fn new(
         path: &Path,
-        tx: SyncSender<Vec<u8>>,
+        tx: RecordSender,
         decoder: Box<dyn Decoder + Send>,
         encoder: Box<dyn Encoder + Send>,
+        checkpoint: Option<Arc<Checkpoint>>,
     ) -> FileWorker {
         FileWorker {
             path: PathBuf::from(path),
             tx,
             decoder,
             encoder,
+            checkpoint,
         }
     }
 
@@ -41,7 +46,10 @@ impl FileWorker {
             .watch(&self.path, RecursiveMode::NonRecursive)
             .unwrap();
 
-        let fr = FollowReader::new(&self.path, from_tail);
+        let file: Arc<str> = Arc::from(self.path.to_string_lossy().as_ref());
+        let resume = self.checkpoint.as_ref().and_then(|x| x.offset(&file));
+        let mut fr = FollowReader::new(&self.path, from_tail, resume);
+        let mut offset = fr.position();
         let mut reader = BufReader::new(fr);
         let mut buffer = Vec::new();
 
@@ -49,14 +57,15 @@ impl FileWorker {
             (self.decoder.clone_boxed(), self.encoder.clone_boxed());
         let mut finish = false;
         while !finish {
-            match rx.recv() {
-                Ok(_) => loop {
+            if rx.recv().is_ok() {
+                loop {
                     let r = reader.read_until(10, &mut buffer);
                     match r {
                         Ok(bytes_read) => {
                             if bytes_read == 0 {
                                 break;
                             }
+                            offset += bytes_read as u64;
                         }
                         Err(_) => {
                             finish = true;
@@ -65,14 +74,18 @@ impl FileWorker {
                     }
                     if buffer[buffer.len() - 1] == 10 {
                         buffer.pop();
-                        let line = String::from_utf8(buffer.clone()).unwrap();
-                        buffer.truncate(0);
-                        if let Err(e) = handle_record(&line, &self.tx, &decoder, &encoder) {
-                            let _ = writeln!(stderr(), "{}: [{}]", e, line.trim());
+                        let line = std::mem::take(&mut buffer);
+                        match handle_record(&line, &*decoder, &*encoder) {
+                            Ok(reencoded) => match &self.checkpoint {
+                                Some(checkpoint) => {
+                                    checkpoint.send(&self.tx, reencoded, &file, offset)
+                                }
+                                None => self.tx.send(reencoded).unwrap(),
+                            },
+                            Err(e) => log_rejected(e, &line),
                         }
                     }
-                },
-                Err(_) => {}
+                }
             }
         }
     }
@@ -84,16 +97,31 @@ pub struct FollowReader {
 }
 
 impl FollowReader {
-    pub fn new(filename: &Path, from_tail: bool) -> FollowReader {
+    /// Open a file, either at its end or at its beginning. `resume` overrides this with the offset
+    /// a previous run stopped at, unless the file got shorter since then.
+    pub fn new(filename: &Path, from_tail: bool, resume: Option<u64>) -> FollowReader {
         let mut f = File::open(filename).expect("Failed to open file");
-        if from_tail {
-            f.seek(SeekFrom::End(0)).unwrap();
+        let len = f.metadata().map_or(0, |x| x.len());
+        match resume {
+            Some(offset) if offset <= len => {
+                f.seek(SeekFrom::Start(offset)).unwrap();
+            }
+            Some(_) => {}
+            None if from_tail => {
+                f.seek(SeekFrom::End(0)).unwrap();
+            }
+            None => {}
         }
         FollowReader {
             file: f,
             path: PathBuf::from(filename),
         }
     }
+
+    /// Current offset in the file
+    pub fn position(&mut self) -> u64 {
+        self.file.stream_position().unwrap()
+    }
 }
 
 impl Read for FollowReader {
@@ -102,19 +130,16 @@ impl Read for FollowReader {
             self.file.sync_data().unwrap();
             self.file.read(buf)
         } else {
-            Err(std::io::Error::new(std::io::ErrorKind::Other, ""))
+            Err(std::io::Error::other(""))
         }
     }
 }
 
 fn handle_record(
-    line: &str,
-    tx: &SyncSender<Vec<u8>>,
-    decoder: &Box<dyn Decoder>,
-    encoder: &Box<dyn Encoder>,
-) -> Result<(), &'static str> {
-    let decoded = decoder.decode(line)?;
-    let reencoded = encoder.encode(decoded)?;
-    tx.send(reencoded).unwrap();
-    Ok(())
+    line: &[u8],
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
+) -> Result<Vec<u8>, &'static str> {
+    let decoded = decoder.decode_bytes(line)?;
+    encoder.encode(decoded)
 }
@@ -0,0 +1,191 @@
+use super::pacer::Pacer;
+use super::Input;
+use crate::flowgger::config::Config;
+use crate::flowgger::daemon;
+use crate::flowgger::decoder::{log_rejected, Decoder};
+use crate::flowgger::encoder::Encoder;
+use crate::flowgger::record_queue::RecordSender;
+use serde::Deserialize;
+use std::io::{stderr, Write};
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::time::Instant;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+const DEFAULT_GENERATOR_HOSTS: u64 = 10;
+const DEFAULT_GENERATOR_APPNAMES: u64 = 10;
+const DEFAULT_GENERATOR_MSG_LENGTH: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GeneratorFormat {
+    Rfc5424,
+    Gelf,
+}
+
+/// Settings of the generator input, in the `[input]` section
+#[derive(Deserialize)]
+pub struct GeneratorSettings {
+    format: Option<String>,
+    generator_format: Option<String>,
+    generator_rate: Option<NonZeroU64>,
+    generator_count: Option<NonZeroU64>,
+    generator_hosts: Option<NonZeroU64>,
+    generator_appnames: Option<NonZeroU64>,
+    generator_msg_length: Option<NonZeroUsize>,
+}
+
+/// Synthetic records, to benchmark the decoder, encoder and output configuration without external load
+/// tools. Records go through the configured decoder, so 'input.format' has to match the generated format.
+pub struct GeneratorInput {
+    format: GeneratorFormat,
+    rate: Option<u64>,
+    count: Option<u64>,
+    hosts: u64,
+    appnames: u64,
+    msg_length: usize,
+}
+
+impl GeneratorInput {
+    /// # Parameters
+    /// - 'input.generator_format':     Optional. "rfc5424" or "gelf". Default is "gelf" if 'input.format'
+    ///   is "gelf", "rfc5424" otherwise.
+    /// - 'input.generator_rate':       Optional. Number of records per second. Records are generated as fast
+    ///   as the pipeline accepts them when this is not set.
+    /// - 'input.generator_count':      Optional. Number of records to generate before exiting. Records are
+    ///   generated until flowgger is stopped when this is not set.
+    /// - 'input.generator_hosts':      Optional. Number of distinct hostnames. Default is 10.
+    /// - 'input.generator_appnames':   Optional. Number of distinct application names. Default is 10.
+    /// - 'input.generator_msg_length': Optional. Length of the messages, in bytes. Default is 100.
+    pub fn new(config: &Config) -> GeneratorInput {
+        let settings: GeneratorSettings = config.settings("input");
+        let format = match settings.generator_format.as_deref() {
+            None if settings.format.as_deref() == Some("gelf") => GeneratorFormat::Gelf,
+            None => GeneratorFormat::Rfc5424,
+            Some("rfc5424") => GeneratorFormat::Rfc5424,
+            Some("gelf") => GeneratorFormat::Gelf,
+            _ => panic!(r#"input.generator_format must be "rfc5424" or "gelf""#),
+        };
+        GeneratorInput {
+            format,
+            rate: settings.generator_rate.map(NonZeroU64::get),
+            count: settings.generator_count.map(NonZeroU64::get),
+            hosts: settings
+                .generator_hosts
+                .map_or(DEFAULT_GENERATOR_HOSTS, NonZeroU64::get),
+            appnames: settings
+                .generator_appnames
+                .map_or(DEFAULT_GENERATOR_APPNAMES, NonZeroU64::get),
+            msg_length: settings
+                .generator_msg_length
+                .map_or(DEFAULT_GENERATOR_MSG_LENGTH, NonZeroUsize::get),
+        }
+    }
+
+    /// Record number `n`. The hostnames vary first and the application names next, so that every
+    /// combination shows up once `hosts * appnames` records have been generated.
+    fn record(&self, n: u64, now: OffsetDateTime) -> Vec<u8> {
+        let hostname = format!("host-{}", n % self.hosts);
+        let appname = format!("app-{}", (n / self.hosts) % self.appnames);
+        let severity = n % 8;
+        let mut msg = format!("Synthetic record {} ", n);
+        while msg.len() < self.msg_length {
+            msg.push((b'a' + (msg.len() % 26) as u8) as char);
+        }
+        msg.truncate(self.msg_length);
+        match self.format {
+            GeneratorFormat::Rfc5424 => format!(
+                "<{}>1 {} {} {} {} - [generator@32473 seq=\"{}\"] {}",
+                8 + severity,
+                now.format(&Rfc3339).unwrap_or_default(),
+                hostname,
+                appname,
+                std::process::id(),
+                n,
+                msg
+            ),
+            GeneratorFormat::Gelf => format!(
+                r#"{{"version":"1.1","host":"{}","short_message":"{}","timestamp":{:.3},"level":{},"_appname":"{}","_seq":{}}}"#,
+                hostname,
+                msg,
+                now.unix_timestamp_nanos() as f64 / 1e9,
+                severity,
+                appname,
+                n
+            ),
+        }
+        .into_bytes()
+    }
+}
+
+impl Input for GeneratorInput {
+    fn accept(
+        &self,
+        tx: RecordSender,
+        decoder: Box<dyn Decoder + Send>,
+        encoder: Box<dyn Encoder + Send>,
+    ) {
+        daemon::listening();
+        let mut pacer = self.rate.map(|rate| Pacer::new(rate, Instant::now()));
+        let mut n = 0;
+        while self.count.is_none_or(|count| n < count) {
+            if let Some(pacer) = &mut pacer {
+                pacer.wait();
+            }
+            let line = self.record(n, OffsetDateTime::now_utc());
+            n += 1;
+            let res = decoder
+                .decode_bytes(&line)
+                .and_then(|decoded| encoder.encode(decoded));
+            match res {
+                Ok(reencoded) => {
+                    if tx.send(reencoded).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => log_rejected(e, &line),
+            }
+        }
+        let _ = writeln!(stderr(), "Generated {} records", n);
+    }
+}
+
+/// Settings of the generator input, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "# Records per second, as fast as possible by default\n\
+         # generator_rate = 10000\n\
+         # Exit after this many records, generate records until stopped by default\n\
+         # generator_count = 1000000\n\
+         # generator_hosts = {}\n\
+         # generator_appnames = {}\n\
+         # generator_msg_length = {}\n",
+        DEFAULT_GENERATOR_HOSTS, DEFAULT_GENERATOR_APPNAMES, DEFAULT_GENERATOR_MSG_LENGTH
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generator_records() {
+        let config = Config::from_string(
+            "[input]\ntype = \"generator\"\ngenerator_hosts = 2\ngenerator_appnames = 3\ngenerator_msg_length = 24\n",
+        )
+        .unwrap();
+        let generator = GeneratorInput::new(&config);
+        assert_eq!(generator.format, GeneratorFormat::Rfc5424);
+        let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let record = String::from_utf8(generator.record(5, now)).unwrap();
+        assert!(record.starts_with("<13>1 2023-11-14T22:13:20Z host-1 app-2 "));
+        assert!(record.ends_with(" [generator@32473 seq=\"5\"] Synthetic record 5 tuvwx"));
+
+        let config =
+            Config::from_string("[input]\ntype = \"generator\"\nformat = \"gelf\"\n").unwrap();
+        let generator = GeneratorInput::new(&config);
+        let record = String::from_utf8(generator.record(12, now)).unwrap();
+        assert!(record.starts_with(r#"{"version":"1.1","host":"host-2","short_message":"#));
+        assert!(record
+            .ends_with(r#""timestamp":1700000000.000,"level":4,"_appname":"app-1","_seq":12}"#));
+    }
+}
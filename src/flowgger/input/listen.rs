@@ -0,0 +1,174 @@
+use crate::flowgger::config::{Config, OneOrMany};
+use crate::flowgger::utils::threads;
+use crossbeam_channel::{unbounded, Receiver};
+use serde::Deserialize;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+
+const TCP_BACKLOG: i32 = 128;
+
+/// Settings of the listening sockets, in the `[input]` section
+#[derive(Deserialize)]
+pub struct ListenSettings {
+    listen: Option<OneOrMany<String>>,
+    ipv6_only: Option<bool>,
+}
+
+/// Addresses an input listens on, i.e. `listen = ["0.0.0.0:514", "[::]:514"]`, with one socket each feeding
+/// the same decoder and queue
+#[derive(Clone, Debug, PartialEq)]
+pub struct Listen {
+    pub addrs: Vec<SocketAddr>,
+    ipv6_only: Option<bool>,
+}
+
+impl Listen {
+    /// # Parameters
+    /// - 'input.listen':    Optional. Address, or list of addresses, to listen on. Default is `default`.
+    /// - 'input.ipv6_only': Optional. Whether IPv6 sockets only accept IPv6 clients, or IPv4 ones as well
+    ///   (dual-stack). Default is true when listening on several addresses, so that "0.0.0.0:514" and "[::]:514"
+    ///   can be listened on together, and the setting of the system otherwise.
+    pub fn new(config: &Config, default: &str) -> Listen {
+        let settings: ListenSettings = config.settings("input");
+        let listen = settings
+            .listen
+            .map_or_else(|| vec![default.to_owned()], OneOrMany::into_vec);
+        if listen.is_empty() {
+            panic!("input.listen cannot be an empty list");
+        }
+        let addrs: Vec<SocketAddr> = listen
+            .iter()
+            .map(|listen| {
+                listen
+                    .to_socket_addrs()
+                    .ok()
+                    .and_then(|mut addrs| addrs.next())
+                    .expect("unable to parse ip:port string from input.listen")
+            })
+            .collect();
+        let ipv6_only = match settings.ipv6_only {
+            Some(ipv6_only) => Some(ipv6_only),
+            None if addrs.len() > 1 => Some(true),
+            None => None,
+        };
+        Listen { addrs, ipv6_only }
+    }
+
+    /// The address, for the inputs that can only listen on one
+    #[cfg(feature = "coroutines")]
+    pub fn single(&self) -> SocketAddr {
+        match self.addrs[..] {
+            [addr] => addr,
+            _ => panic!("input.listen can only be a single address with this input type"),
+        }
+    }
+
+    /// Bind a TCP socket to every address
+    ///
+    /// # Panics
+    /// `Unable to listen to <address>`: the address is already in use, or the permissions are insufficient
+    pub fn tcp_listeners(&self) -> Vec<TcpListener> {
+        self.addrs
+            .iter()
+            .map(|&addr| {
+                self.bind(addr, Type::STREAM, Protocol::TCP)
+                    .and_then(|socket| {
+                        socket.listen(TCP_BACKLOG)?;
+                        Ok(socket.into())
+                    })
+                    .unwrap_or_else(|e| panic!("Unable to listen to {}: {}", addr, e))
+            })
+            .collect()
+    }
+
+    /// Bind a UDP socket to every address
+    ///
+    /// # Panics
+    /// `Unable to listen to <address>`: the address is already in use, or the permissions are insufficient
+    pub fn udp_sockets(&self) -> Vec<UdpSocket> {
+        self.addrs
+            .iter()
+            .map(|&addr| {
+                self.bind(addr, Type::DGRAM, Protocol::UDP)
+                    .map(|socket| socket.into())
+                    .unwrap_or_else(|e| panic!("Unable to listen to {}: {}", addr, e))
+            })
+            .collect()
+    }
+
+    fn bind(&self, addr: SocketAddr, ty: Type, protocol: Protocol) -> io::Result<Socket> {
+        let socket = Socket::new(Domain::for_address(addr), ty, Some(protocol))?;
+        if let (SocketAddr::V6(_), Some(ipv6_only)) = (addr, self.ipv6_only) {
+            socket.set_only_v6(ipv6_only)?;
+        }
+        // As TcpListener::bind, so that the address can be bound again right after a restart, while
+        // connections of the previous process are in TIME_WAIT
+        #[cfg(unix)]
+        if ty == Type::STREAM {
+            socket.set_reuse_address(true)?;
+        }
+        socket.bind(&addr.into())?;
+        Ok(socket)
+    }
+}
+
+/// The connections accepted by all the listeners, in a single stream
+pub fn incoming(listeners: Vec<TcpListener>) -> Receiver<TcpStream> {
+    let (tx, rx) = unbounded();
+    for listener in listeners {
+        let tx = tx.clone();
+        let name = match listener.local_addr() {
+            Ok(addr) => format!("flowgger-listen-{}", addr),
+            Err(_) => "flowgger-listen".to_owned(),
+        };
+        threads::spawn(name, None, move || {
+            for client in listener.incoming().flatten() {
+                if tx.send(client).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_listen_config() {
+        let config = Config::from_string("[input]\nlisten = \"127.0.0.1:514\"\n").unwrap();
+        let listen = Listen::new(&config, "0.0.0.0:514");
+        assert_eq!(listen.addrs, vec!["127.0.0.1:514".parse().unwrap()]);
+        assert_eq!(listen.ipv6_only, None);
+
+        let config =
+            Config::from_string("[input]\nlisten = [\"0.0.0.0:514\", \"[::]:514\"]\n").unwrap();
+        let listen = Listen::new(&config, "0.0.0.0:514");
+        assert_eq!(listen.addrs.len(), 2);
+        assert_eq!(listen.ipv6_only, Some(true));
+
+        let listen = Listen::new(&Config::from_string("").unwrap(), "0.0.0.0:2514");
+        assert_eq!(listen.addrs, vec!["0.0.0.0:2514".parse().unwrap()]);
+    }
+
+    #[test]
+    fn test_listen_incoming() {
+        let config =
+            Config::from_string("[input]\nlisten = [\"127.0.0.1:0\", \"127.0.0.1:0\"]\n").unwrap();
+        let listeners = Listen::new(&config, "0.0.0.0:514").tcp_listeners();
+        let addrs: Vec<SocketAddr> = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap())
+            .collect();
+        let rx = incoming(listeners);
+        for addr in addrs {
+            let mut client = TcpStream::connect(addr).unwrap();
+            client.write_all(b"hello").unwrap();
+            assert_eq!(rx.recv().unwrap().local_addr().unwrap(), addr);
+        }
+    }
+}
@@ -1,7 +1,16 @@
+mod connection_events;
+mod decompress;
 #[cfg(feature = "file")]
 mod file;
+mod generator_input;
+mod listen;
+mod pacer;
 #[cfg(feature = "redis-input")]
 mod redis_input;
+mod relp_input;
+mod replay_input;
+#[cfg(feature = "statsd")]
+mod statsd_input;
 mod stdin_input;
 mod tcp;
 #[cfg(feature = "tls")]
@@ -13,8 +22,13 @@ pub mod udp_input;
 
 #[cfg(feature = "file")]
 pub use self::file::FileInput;
+pub use self::generator_input::GeneratorInput;
 #[cfg(feature = "redis-input")]
 pub use self::redis_input::RedisInput;
+pub use self::relp_input::RelpInput;
+pub use self::replay_input::ReplayInput;
+#[cfg(feature = "statsd")]
+pub use self::statsd_input::StatsdInput;
 pub use self::stdin_input::StdinInput;
 pub use self::tcp::tcp_input::TcpInput;
 #[cfg(feature = "coroutines")]
@@ -26,15 +40,113 @@ pub use self::tls::tlsco_input::TlsCoInput;
 #[cfg(feature = "syslog")]
 pub use self::udp_input::UdpInput;
 
+#[cfg(any(feature = "file", feature = "redis-input"))]
+use crate::flowgger::config::Config;
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
-use std::sync::mpsc::SyncSender;
+use crate::flowgger::output::Notifier;
+use crate::flowgger::record_queue::RecordSender;
+use std::sync::Arc;
+
+/// Names of the settings the inputs deserialize with `Config::settings`
+#[cfg(test)]
+pub fn typed_settings() -> Vec<String> {
+    use crate::flowgger::config::setting_names;
+
+    let mut settings = setting_names::<connection_events::ConnectionEventsSettings>("input");
+    settings.extend(setting_names::<decompress::DecompressSettings>("input"));
+    settings.extend(setting_names::<generator_input::GeneratorSettings>("input"));
+    settings.extend(setting_names::<listen::ListenSettings>("input"));
+    settings.extend(setting_names::<relp_input::RelpSettings>("input"));
+    settings.extend(setting_names::<replay_input::ReplaySettings>("input"));
+    settings.extend(setting_names::<stdin_input::StdinSettings>("input"));
+    settings.extend(setting_names::<tcp::StartTlsSettings>("input"));
+    settings.extend(setting_names::<tcp::TcpSettings>("input"));
+    #[cfg(feature = "file")]
+    settings.extend(setting_names::<file::CheckpointSettings>("input"));
+    #[cfg(feature = "file")]
+    settings.extend(setting_names::<file::FileSettings>("input"));
+    #[cfg(feature = "redis-input")]
+    settings.extend(setting_names::<redis_input::RedisSettings>("input"));
+    #[cfg(feature = "tls")]
+    settings.extend(setting_names::<tls::FingerprintsSettings>("input"));
+    #[cfg(feature = "tls")]
+    settings.extend(setting_names::<tls::TlsSettings>("input"));
+    settings
+}
+
+/// Commented settings of an input type, for `flowgger config init`
+///
+/// # Returns
+/// `None` if the input type doesn't exist, or if its support hasn't been compiled in
+pub fn sample_config(input_type: &str) -> Option<String> {
+    match input_type {
+        #[cfg(feature = "file")]
+        "file" => Some(file::sample_config()),
+        "generator" => Some(generator_input::sample_config()),
+        #[cfg(feature = "redis-input")]
+        "redis" => Some(redis_input::sample_config()),
+        "relp" => Some(relp_input::sample_config()),
+        "replay" => Some(replay_input::sample_config()),
+        #[cfg(feature = "statsd")]
+        "statsd" => Some(statsd_input::sample_config()),
+        "stdin" => Some(stdin_input::sample_config()),
+        "tcp" | "syslog-tcp" => Some(tcp::sample_config()),
+        #[cfg(feature = "coroutines")]
+        "tcp_co" | "tcpco" | "syslog-tcp_co" | "syslog-tcpco" => Some(tcp::sample_config_co()),
+        #[cfg(feature = "tls")]
+        "tls" | "syslog-tls" => Some(tls::sample_config()),
+        #[cfg(feature = "coroutines")]
+        "tls_co" | "tlsco" | "syslog-tls_co" | "syslog-tlsco" => Some(tls::sample_config_co()),
+        #[cfg(feature = "syslog")]
+        "udp" => Some(udp_input::sample_config()),
+        _ => None,
+    }
+}
+
+/// Check that every record of the queue comes from the input, and that a single output thread delivers them
+/// in order, as required by the inputs that match the notifications of the output with their records by
+/// counting them
+///
+/// # Panics
+/// If stats records, signature blocks, or several output threads are enabled
+#[cfg(any(feature = "file", feature = "redis-input"))]
+fn check_counted_notifications(config: &Config, setting: &str) {
+    for key in &["stats.interval", "output.syslog_sign_key"] {
+        if config.lookup(key).is_some() {
+            panic!(
+                "{} requires every record of the queue to come from the input, {} can't be set",
+                setting, key
+            );
+        }
+    }
+    for key in &["output.kafka_threads", "output.tls_threads"] {
+        if config
+            .lookup(key)
+            .and_then(|x| x.as_integer())
+            .is_some_and(|x| x > 1)
+        {
+            panic!(
+                "{} requires a single output thread, {} must be 1",
+                setting, key
+            );
+        }
+    }
+}
+
+/// Size of the read buffers of the network inputs, large enough for the splitters to find many records per read
+const INPUT_BUFFER_SIZE: usize = 64 * 1024;
 
 pub trait Input {
     fn accept(
         &self,
-        tx: SyncSender<Vec<u8>>,
+        tx: RecordSender,
         decoder: Box<dyn Decoder + Send>,
         encoder: Box<dyn Encoder + Send>,
     );
+
+    /// Optional notifier the outputs report deliveries to, for inputs that track what has been delivered
+    fn notifier(&self) -> Option<Arc<dyn Notifier>> {
+        None
+    }
 }
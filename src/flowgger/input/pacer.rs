@@ -0,0 +1,50 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Spaces the records evenly, for the inputs producing them at a given rate
+pub struct Pacer {
+    interval: Duration,
+    next: Instant,
+}
+
+impl Pacer {
+    pub fn new(rate: u64, now: Instant) -> Pacer {
+        Pacer {
+            interval: Duration::from_secs(1) / rate.min(u32::MAX as u64) as u32,
+            next: now,
+        }
+    }
+
+    /// Wait until the next record can be sent
+    pub fn wait(&mut self) {
+        let delay = self.delay(Instant::now());
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+    }
+
+    /// How long to wait before sending the next record. A pipeline slower than the rate doesn't
+    /// accumulate credit to catch up with later.
+    fn delay(&mut self, now: Instant) -> Duration {
+        let delay = self.next.saturating_duration_since(now);
+        self.next = self.next.max(now) + self.interval;
+        delay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pacer() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(4, start);
+        assert_eq!(pacer.delay(start), Duration::ZERO);
+        assert_eq!(pacer.delay(start), Duration::from_millis(250));
+        assert_eq!(pacer.delay(start), Duration::from_millis(500));
+        let later = start + Duration::from_secs(10);
+        assert_eq!(pacer.delay(later), Duration::ZERO);
+        assert_eq!(pacer.delay(later), Duration::from_millis(250));
+    }
+}
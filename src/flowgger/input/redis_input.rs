@@ -1,145 +1,644 @@
-use super::Input;
-use crate::flowgger::config::Config;
+use super::{check_counted_notifications, Input};
+use crate::flowgger::config::{Config, OneOrMany};
+use crate::flowgger::daemon;
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
+use crate::flowgger::output::Notifier;
+use crate::flowgger::record_queue::RecordSender;
+use crate::flowgger::utils;
+use crate::flowgger::utils::threads::{self, CpuAffinity};
+use rand::Rng;
 use redis;
-use redis::{Commands, Connection, RedisResult};
+use redis::streams::{StreamId, StreamReadOptions, StreamReadReply};
+use redis::{from_redis_value, Commands, Connection, RedisError, RedisResult};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::{stderr, Write};
-use std::process::exit;
-use std::sync::mpsc::SyncSender;
+use std::num::{NonZeroU32, NonZeroU64};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 const DEFAULT_CONNECT: &str = "127.0.0.1";
 const DEFAULT_QUEUE_KEY: &str = "logs";
 const DEFAULT_THREADS: u32 = 1;
+const DEFAULT_STREAM_GROUP: &str = "flowgger";
+const DEFAULT_STREAM_FIELD: &str = "message";
+const DEFAULT_STREAM_CLAIM_IDLE: u64 = 60_000;
+const DEFAULT_RECOVERY_DELAY_INIT: u32 = 1;
+const DEFAULT_RECOVERY_DELAY_MAX: u32 = 10_000;
+const DEFAULT_RECOVERY_PROBE_TIME: u32 = 30_000;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Maximum number of stream entries read at once
+const STREAM_READ_COUNT: usize = 100;
+/// How long to wait for new stream entries, before acknowledging the ones delivered in the meantime
+const STREAM_BLOCK_MS: usize = 1000;
+
+/// Settings of the Redis input, in the `[input]` section
+#[derive(Deserialize)]
+pub struct RedisSettings {
+    redis_connect: Option<OneOrMany<String>>,
+    redis_sentinel_master: Option<String>,
+    redis_queue_key: Option<String>,
+    redis_threads: Option<u32>,
+    redis_stream_key: Option<String>,
+    redis_stream_group: Option<String>,
+    redis_stream_consumer: Option<String>,
+    redis_stream_field: Option<String>,
+    redis_stream_claim_idle: Option<NonZeroU64>,
+    redis_recovery_delay_init: Option<NonZeroU32>,
+    redis_recovery_delay_max: Option<u32>,
+    redis_recovery_probe_time: Option<u32>,
+}
 
 pub struct RedisInput {
     config: RedisConfig,
     threads: u32,
+    acks: Arc<StreamAcks>,
+    affinity: CpuAffinity,
 }
 
 struct RedisWorker {
     tid: u32,
     config: RedisConfig,
-    redis_cnx: Connection,
-    tx: SyncSender<Vec<u8>>,
+    tx: RecordSender,
     decoder: Box<dyn Decoder + Send>,
     encoder: Box<dyn Encoder + Send>,
+    acks: Arc<StreamAcks>,
 }
 
 #[derive(Clone)]
 struct RedisConfig {
-    connect: String,
+    connect: Vec<String>,
+    sentinel_master: Option<String>,
     queue_key: String,
+    stream: Option<RedisStream>,
+    recovery_delay_init: u32,
+    recovery_delay_max: u32,
+    recovery_probe_time: u32,
+}
+
+/// Why the connection to the Redis server was lost
+struct ConnectionLost {
+    reason: String,
+    /// Node the key was moved to, to reconnect to it right away
+    redirect: Option<String>,
+}
+
+/// Consumer group of a stream the records are read from, instead of a list
+#[derive(Clone)]
+struct RedisStream {
+    key: String,
+    group: String,
+    consumer: String,
+    field: String,
+    claim_idle: u64,
+}
+
+/// Stream entries sent to the queue, only acknowledged once the output reported their delivery.
+///
+/// Deliveries are matched with entries by counting, as with the checkpoint of the file input. Entries that
+/// could not be delivered are left pending, to be claimed and read again once they have been idle for
+/// 'input.redis_stream_claim_idle'.
+#[derive(Default)]
+struct StreamAcks {
+    state: Mutex<StreamAcksState>,
+    /// Serializes the sends of the workers, so that `queued` matches the order of the queue
+    send_lock: Mutex<()>,
+}
+
+#[derive(Default)]
+struct StreamAcksState {
+    /// Id of every entry sent to the queue and not notified yet, in queue order
+    queued: VecDeque<String>,
+    /// Ids of the queued entries, not to read them again while they are in flight
+    in_flight: HashSet<String>,
+    /// Entries to acknowledge
+    delivered: Vec<String>,
 }
 
 impl RedisInput {
+    /// # Parameters
+    /// - 'input.redis_connect': Optional. Address of the Redis server, or list of addresses of servers tried
+    ///   in turn, i.e. replicas. Servers that are not masters are skipped, and after the connection is lost,
+    ///   the servers are tried starting with the one after it. Default is "127.0.0.1".
+    /// - 'input.redis_sentinel_master': Optional. Name of the master monitored by Redis Sentinel. When set,
+    ///   'input.redis_connect' is the list of sentinels, that are asked for the address of the master every
+    ///   time the connection is lost, so that reading resumes after a failover.
+    /// - 'input.redis_queue_key': Optional. List the records are popped from. Default is "logs".
+    /// - 'input.redis_threads': Optional. Number of connections to the Redis server. Default is 1.
+    /// - 'input.redis_stream_key': Optional. Stream the records are read from, with a consumer group, instead
+    ///   of the list. Several flowgger instances can share the stream, and the entries are acknowledged once
+    ///   the output delivered them. This requires every record of the queue to come from the input.
+    /// - 'input.redis_stream_group': Optional. Consumer group, created along with the stream if it doesn't
+    ///   exist. Default is "flowgger".
+    /// - 'input.redis_stream_consumer': Optional. Name of the consumer in the group, that must be unique to
+    ///   each instance and stay the same across restarts, so that the entries that were not acknowledged are
+    ///   read again. The thread number is appended to it. Default is the local hostname.
+    /// - 'input.redis_stream_field': Optional. Field of the entries holding the record. Default is "message".
+    /// - 'input.redis_stream_claim_idle': Optional. Milliseconds after which the entries that a consumer of
+    ///   the group didn't acknowledge are claimed and read again, i.e. those of an instance that stopped, or
+    ///   those that could not be delivered. Default is 60000.
+    /// - 'input.redis_recovery_delay_init': Optional. Milliseconds before reconnecting after the connection
+    ///   was lost, increased up to 'input.redis_recovery_delay_max' while the connections keep failing.
+    ///   Default is 1.
+    /// - 'input.redis_recovery_delay_max': Optional. Default is 10000.
+    /// - 'input.redis_recovery_probe_time': Optional. Milliseconds after which a connection is considered
+    ///   stable, resetting the delay. Default is 30000.
     pub fn new(config: &Config) -> RedisInput {
-        let connect = config
-            .lookup("input.redis_connect")
-            .map_or(DEFAULT_CONNECT, |x| {
-                x.as_str()
-                    .expect("input.redis_connect must be an ip:port string")
-            })
-            .to_owned();
-        let queue_key = config
-            .lookup("input.redis_queue_key")
-            .map_or(DEFAULT_QUEUE_KEY, |x| {
-                x.as_str().expect("input.redis_queue_key must be a string")
-            })
-            .to_owned();
-        let threads = config
-            .lookup("input.redis_threads")
-            .map_or(DEFAULT_THREADS, |x| {
-                x.as_integer()
-                    .expect("input.redis_threads must be a 32-bit integer") as u32
-            });
-        let redis_config = RedisConfig { connect, queue_key };
+        let settings: RedisSettings = config.settings("input");
+        let connect = settings
+            .redis_connect
+            .map_or_else(|| vec![DEFAULT_CONNECT.to_owned()], OneOrMany::into_vec);
+        if connect.is_empty() {
+            panic!("input.redis_connect cannot be an empty list");
+        }
+        for connect in &connect {
+            if let Err(e) = redis::Client::open(format!("redis://{}/", connect).as_ref()) {
+                panic!(
+                    "Invalid connection string for the Redis server: [{}], error: {}",
+                    connect, e
+                );
+            }
+        }
+        let sentinel_master = settings.redis_sentinel_master;
+        let queue_key = settings
+            .redis_queue_key
+            .unwrap_or_else(|| DEFAULT_QUEUE_KEY.to_owned());
+        let threads = settings.redis_threads.unwrap_or(DEFAULT_THREADS);
+        let stream = match settings.redis_stream_key {
+            None => None,
+            Some(key) => {
+                let group = settings
+                    .redis_stream_group
+                    .unwrap_or_else(|| DEFAULT_STREAM_GROUP.to_owned());
+                let consumer = settings.redis_stream_consumer.unwrap_or_else(|| {
+                    utils::local_hostname().unwrap_or_else(|| "localhost".to_owned())
+                });
+                let field = settings
+                    .redis_stream_field
+                    .unwrap_or_else(|| DEFAULT_STREAM_FIELD.to_owned());
+                let claim_idle = settings
+                    .redis_stream_claim_idle
+                    .map_or(DEFAULT_STREAM_CLAIM_IDLE, NonZeroU64::get);
+                check_counted_notifications(config, "input.redis_stream_key");
+                Some(RedisStream {
+                    key,
+                    group,
+                    consumer,
+                    field,
+                    claim_idle,
+                })
+            }
+        };
+        let recovery_delay_init = settings
+            .redis_recovery_delay_init
+            .map_or(DEFAULT_RECOVERY_DELAY_INIT, NonZeroU32::get);
+        let recovery_delay_max = settings
+            .redis_recovery_delay_max
+            .unwrap_or(DEFAULT_RECOVERY_DELAY_MAX);
+        let recovery_probe_time = settings
+            .redis_recovery_probe_time
+            .unwrap_or(DEFAULT_RECOVERY_PROBE_TIME);
+        if recovery_delay_max < recovery_delay_init {
+            panic!(
+                "input.redis_recovery_delay_max cannot be less than input.redis_recovery_delay_init"
+            );
+        }
+        let redis_config = RedisConfig {
+            connect,
+            sentinel_master,
+            queue_key,
+            stream,
+            recovery_delay_init,
+            recovery_delay_max,
+            recovery_probe_time,
+        };
         RedisInput {
             config: redis_config,
             threads,
+            acks: Arc::new(StreamAcks::default()),
+            affinity: CpuAffinity::new(config, "input"),
         }
     }
 }
 
 impl RedisWorker {
-    fn new(
-        tid: u32,
-        config: RedisConfig,
-        tx: SyncSender<Vec<u8>>,
-        decoder: Box<dyn Decoder + Send>,
-        encoder: Box<dyn Encoder + Send>,
-    ) -> RedisWorker {
-        let redis_cnx = match redis::Client::open(format!("redis://{}/", config.connect).as_ref()) {
-            Err(e) => panic!(
-                "Invalid connection string for the Redis server: [{}], error: {}",
-                config.connect, e
-            ),
-            Ok(client) => match client.get_connection() {
-                Err(e) => panic!(
-                    "Unable to connect to the Redis server: [{}], error: {}",
-                    config.connect, e
-                ),
-                Ok(redis_cnx) => redis_cnx,
-            },
+    /// Read the records, reconnecting with an exponential backoff whenever the connection is lost, to the
+    /// node a key was moved to, to the master currently known by the sentinels, or to the next server.
+    /// Redirections are followed after the backoff as well, so that a key moving back and forth while the
+    /// cluster is resharded doesn't make the workers spin.
+    fn run(self) {
+        let mut rng = rand::thread_rng();
+        let mut recovery_delay = f64::from(self.config.recovery_delay_init);
+        let mut redirect = None;
+        // Index of the server of 'input.redis_connect' tried first
+        let mut next_server = 0;
+        loop {
+            let last_recovery = Instant::now();
+            let res = self.connect(redirect.take(), &mut next_server).and_then(
+                |(connect, mut redis_cnx)| match self.config.stream {
+                    None => self.run_list(&connect, &mut redis_cnx),
+                    Some(ref stream) => self.run_stream(stream, &connect, &mut redis_cnx),
+                },
+            );
+            if let Err(e) = res {
+                let _ = writeln!(stderr(), "Redis connection lost - {}", e.reason);
+                redirect = e.redirect;
+            }
+            if last_recovery.elapsed()
+                > Duration::from_millis(u64::from(self.config.recovery_probe_time))
+            {
+                recovery_delay = f64::from(self.config.recovery_delay_init);
+            } else if recovery_delay < f64::from(self.config.recovery_delay_max) {
+                recovery_delay += rng.gen_range(0.0..recovery_delay);
+            }
+            thread::sleep(Duration::from_millis(recovery_delay.round() as u64));
+            let _ = writeln!(stderr(), "Attempting to reconnect");
+        }
+    }
+
+    /// Connect to the node the key was moved to, to the master known by the sentinels, or to the first master
+    /// that accepts the connection, starting with the server at `next_server`, that is then set to the server
+    /// after it
+    ///
+    /// # Returns
+    /// The address of the server, and the connection
+    fn connect(
+        &self,
+        redirect: Option<String>,
+        next_server: &mut usize,
+    ) -> Result<(String, Connection), ConnectionLost> {
+        let servers = &self.config.connect;
+        let addresses = match (redirect, &self.config.sentinel_master) {
+            (Some(redirect), _) => vec![redirect],
+            (None, Some(master)) => vec![self.sentinel_master(master)?],
+            (None, None) => {
+                let start = *next_server % servers.len();
+                *next_server = start + 1;
+                servers[start..]
+                    .iter()
+                    .chain(&servers[..start])
+                    .cloned()
+                    .collect()
+            }
         };
-        RedisWorker {
-            tid,
-            config,
-            redis_cnx,
-            tx,
-            decoder,
-            encoder,
+        for connect in addresses {
+            match open(&connect).and_then(|mut redis_cnx| {
+                is_master(&mut redis_cnx).map(|master| (redis_cnx, master))
+            }) {
+                Ok((redis_cnx, true)) => {
+                    if let Some(i) = servers.iter().position(|server| *server == connect) {
+                        *next_server = i + 1;
+                    }
+                    return Ok((connect, redis_cnx));
+                }
+                Ok((_, false)) => {
+                    let _ = writeln!(
+                        stderr(),
+                        "Redis server [{}] is a read-only replica, skipping it",
+                        connect
+                    );
+                }
+                Err(e) => {
+                    let _ = writeln!(
+                        stderr(),
+                        "Unable to connect to the Redis server: [{}], error: {}",
+                        connect,
+                        e
+                    );
+                }
+            }
         }
+        Err(ConnectionLost::new("No Redis server available"))
     }
 
-    fn run(self) -> Result<(), String> {
+    /// Address of the master, as known by the first sentinel that answers
+    fn sentinel_master(&self, master: &str) -> Result<String, ConnectionLost> {
+        for sentinel in &self.config.connect {
+            let addr: RedisResult<Option<(String, u16)>> = open(sentinel).and_then(|mut cnx| {
+                redis::cmd("SENTINEL")
+                    .arg("get-master-addr-by-name")
+                    .arg(master)
+                    .query(&mut cnx)
+            });
+            match addr {
+                Ok(Some((host, port))) if host.contains(':') => {
+                    return Ok(format!("[{}]:{}", host, port))
+                }
+                Ok(Some((host, port))) => return Ok(format!("{}:{}", host, port)),
+                Ok(None) => {
+                    let _ = writeln!(
+                        stderr(),
+                        "Redis sentinel [{}] doesn't monitor master [{}]",
+                        sentinel,
+                        master
+                    );
+                }
+                Err(e) => {
+                    let _ = writeln!(
+                        stderr(),
+                        "Unable to query the Redis sentinel: [{}], error: {}",
+                        sentinel,
+                        e
+                    );
+                }
+            }
+        }
+        Err(ConnectionLost::new("No Redis sentinel available"))
+    }
+
+    fn run_list(&self, connect: &str, redis_cnx: &mut Connection) -> Result<(), ConnectionLost> {
         let queue_key: &str = &self.config.queue_key;
         let queue_key_tmp: &str = &format!("{}.tmp.{}", queue_key, self.tid);
-        let redis_cnx = self.redis_cnx;
         println!(
             "Connected to Redis [{}], pulling messages from key [{}]",
-            self.config.connect, queue_key
+            connect, queue_key
         );
         while {
             let dummy: RedisResult<String> = redis_cnx.rpoplpush(queue_key_tmp, queue_key);
             dummy.is_ok()
         } {}
-        let (decoder, encoder): (Box<dyn Decoder>, Box<dyn Encoder>) = (self.decoder, self.encoder);
         loop {
-            let line: String = match redis_cnx.brpoplpush(queue_key, queue_key_tmp, 0) {
-                Err(e) => return Err(format!("Redis protocol error in BRPOPLPUSH: [{}]", e)),
-                Ok(line) => line,
+            let line: String = redis_cnx
+                .brpoplpush(queue_key, queue_key_tmp, 0)
+                .map_err(|e| ConnectionLost::protocol_error("BRPOPLPUSH", e))?;
+            match encode_record(&line, &*self.decoder, &*self.encoder) {
+                Ok(record) => self.tx.send(record).unwrap(),
+                Err(e) => {
+                    let _ = writeln!(stderr(), "{}: [{}]", e, line.trim());
+                }
+            }
+            let _: u8 = redis_cnx
+                .lrem(queue_key_tmp, 1, line)
+                .map_err(|e| ConnectionLost::protocol_error("LREM", e))?;
+        }
+    }
+
+    /// Read the entries of the stream as a consumer of the group, starting with the ones that were delivered
+    /// to this consumer before a restart but not acknowledged, and acknowledge them once delivered
+    fn run_stream(
+        &self,
+        stream: &RedisStream,
+        connect: &str,
+        redis_cnx: &mut Connection,
+    ) -> Result<(), ConnectionLost> {
+        let consumer = format!("{}.{}", stream.consumer, self.tid);
+        let created: RedisResult<()> =
+            redis_cnx.xgroup_create_mkstream(&stream.key, &stream.group, "$");
+        if let Err(e) = created {
+            if e.code() != Some("BUSYGROUP") {
+                return Err(ConnectionLost::protocol_error("XGROUP CREATE", e));
+            }
+        }
+        println!(
+            "Connected to Redis [{}], reading stream [{}] as consumer [{}] of group [{}]",
+            connect, stream.key, consumer, stream.group
+        );
+        // Id after which the entries delivered to this consumer are read again, until there are none left
+        let mut pending_from = Some("0".to_owned());
+        let claim_idle = Duration::from_millis(stream.claim_idle);
+        let mut last_claim = Instant::now();
+        loop {
+            self.ack_delivered(stream, redis_cnx)?;
+            if last_claim.elapsed() >= claim_idle {
+                self.claim(stream, &consumer, redis_cnx)?;
+                last_claim = Instant::now();
+            }
+            let opts = StreamReadOptions::default()
+                .group(&stream.group, &consumer)
+                .count(STREAM_READ_COUNT);
+            let (opts, id) = match pending_from {
+                Some(ref id) => (opts, id.as_str()),
+                None => (opts.block(STREAM_BLOCK_MS), ">"),
             };
-            if let Err(e) = handle_record(&line, &self.tx, &decoder, &encoder) {
-                let _ = writeln!(stderr(), "{}: [{}]", e, line.trim());
+            let reply: StreamReadReply = redis_cnx
+                .xread_options(&[&stream.key], &[id], &opts)
+                .map_err(|e| ConnectionLost::protocol_error("XREADGROUP", e))?;
+            let entries: Vec<StreamId> = reply.keys.into_iter().flat_map(|x| x.ids).collect();
+            if pending_from.is_some() {
+                pending_from = entries.last().map(|entry| entry.id.clone());
             }
-            let res: RedisResult<u8> = redis_cnx.lrem(queue_key_tmp as &str, 1, line as String);
-            if let Err(e) = res {
-                return Err(format!("Redis protocol error in LREM: [{}]", e));
+            self.handle_entries(stream, &entries);
+        }
+    }
+
+    /// Queue the records of the entries, but those already in flight. Entries that can't be decoded are
+    /// acknowledged right away.
+    fn handle_entries(&self, stream: &RedisStream, entries: &[StreamId]) {
+        for entry in entries {
+            if self.acks.is_in_flight(&entry.id) {
+                continue;
+            }
+            let record = match entry.get::<String>(&stream.field) {
+                Some(line) => encode_record(&line, &*self.decoder, &*self.encoder)
+                    .map_err(|e| format!("{}: [{}]", e, line.trim())),
+                None => Err(format!(
+                    "Stream entry without a [{}] field: [{}]",
+                    stream.field, entry.id
+                )),
+            };
+            match record {
+                Ok(record) => self.acks.send(&self.tx, record, &entry.id),
+                Err(e) => {
+                    let _ = writeln!(stderr(), "{}", e);
+                    self.acks.skip(&entry.id);
+                }
+            }
+        }
+    }
+
+    /// Acknowledge the entries delivered by the output
+    fn ack_delivered(
+        &self,
+        stream: &RedisStream,
+        redis_cnx: &mut Connection,
+    ) -> Result<(), ConnectionLost> {
+        let ids = self.acks.take_delivered();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let acked: RedisResult<u64> = redis_cnx.xack(&stream.key, &stream.group, &ids);
+        if let Err(e) = acked {
+            self.acks.restore_delivered(ids);
+            return Err(ConnectionLost::protocol_error("XACK", e));
+        }
+        Ok(())
+    }
+
+    /// Claim and read again the entries that were not acknowledged for 'input.redis_stream_claim_idle'
+    fn claim(
+        &self,
+        stream: &RedisStream,
+        consumer: &str,
+        redis_cnx: &mut Connection,
+    ) -> Result<(), ConnectionLost> {
+        let mut cursor = "0-0".to_owned();
+        loop {
+            let (next, entries) = redis::cmd("XAUTOCLAIM")
+                .arg(&stream.key)
+                .arg(&stream.group)
+                .arg(consumer)
+                .arg(stream.claim_idle)
+                .arg(&cursor)
+                .arg("COUNT")
+                .arg(STREAM_READ_COUNT)
+                .query(redis_cnx)
+                .and_then(|reply| parse_autoclaim(&reply))
+                .map_err(|e| ConnectionLost::protocol_error("XAUTOCLAIM", e))?;
+            self.handle_entries(stream, &entries);
+            if next == "0-0" {
+                return Ok(());
+            }
+            cursor = next;
+        }
+    }
+}
+
+/// Cursor and entries of a XAUTOCLAIM reply, the entries deleted in the meantime having no fields
+fn parse_autoclaim(reply: &redis::Value) -> RedisResult<(String, Vec<StreamId>)> {
+    let invalid = || RedisError::from((redis::ErrorKind::TypeError, "Invalid XAUTOCLAIM reply"));
+    let (cursor, entries) = match reply {
+        redis::Value::Bulk(items) if items.len() >= 2 => (&items[0], &items[1]),
+        _ => return Err(invalid()),
+    };
+    let entries = match entries {
+        redis::Value::Bulk(entries) => entries,
+        _ => return Err(invalid()),
+    };
+    let entries = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            redis::Value::Bulk(entry) if entry.len() == 2 => Some(entry),
+            _ => None,
+        })
+        .map(|entry| {
+            let map = match entry[1] {
+                redis::Value::Nil => HashMap::new(),
+                ref fields => from_redis_value(fields)?,
+            };
+            Ok(StreamId {
+                id: from_redis_value(&entry[0])?,
+                map,
+            })
+        })
+        .collect::<RedisResult<_>>()?;
+    Ok((from_redis_value(cursor)?, entries))
+}
+
+impl StreamAcks {
+    /// Send the record of an entry
+    fn send(&self, tx: &RecordSender, record: Vec<u8>, id: &str) {
+        let _send_lock = self.send_lock.lock().unwrap();
+        {
+            let mut state = self.state.lock().unwrap();
+            state.queued.push_back(id.to_owned());
+            state.in_flight.insert(id.to_owned());
+        }
+        tx.send(record).unwrap();
+    }
+
+    /// Acknowledge an entry that isn't sent
+    fn skip(&self, id: &str) {
+        self.state.lock().unwrap().delivered.push(id.to_owned());
+    }
+
+    fn is_in_flight(&self, id: &str) -> bool {
+        self.state.lock().unwrap().in_flight.contains(id)
+    }
+
+    fn take_delivered(&self) -> Vec<String> {
+        std::mem::take(&mut self.state.lock().unwrap().delivered)
+    }
+
+    /// Give back entries that could not be acknowledged
+    fn restore_delivered(&self, ids: Vec<String>) {
+        self.state.lock().unwrap().delivered.extend(ids);
+    }
+}
+
+impl Notifier for StreamAcks {
+    fn notify(&self, records: &[Vec<u8>], result: Result<(), &str>) {
+        let mut state = self.state.lock().unwrap();
+        for _ in 0..records.len() {
+            let id = match state.queued.pop_front() {
+                Some(id) => id,
+                None => break,
             };
+            state.in_flight.remove(&id);
+            if result.is_ok() {
+                state.delivered.push(id);
+            }
         }
     }
 }
 
+impl ConnectionLost {
+    fn new(reason: &str) -> ConnectionLost {
+        ConnectionLost {
+            reason: reason.to_owned(),
+            redirect: None,
+        }
+    }
+
+    /// Error of a command, along with the node the key was moved to if the server is part of a Redis Cluster
+    fn protocol_error(command: &str, e: RedisError) -> ConnectionLost {
+        ConnectionLost {
+            reason: format!("Redis protocol error in {}: [{}]", command, e),
+            redirect: e.redirect_node().map(|(addr, _slot)| addr.to_owned()),
+        }
+    }
+}
+
+/// Whether the server is a master, that records can be popped from, rather than a read-only replica
+fn is_master(redis_cnx: &mut Connection) -> RedisResult<bool> {
+    let role: RedisResult<Vec<redis::Value>> = redis::cmd("ROLE").query(redis_cnx);
+    match role {
+        Ok(role) => Ok(is_master_role(&role)),
+        // Servers older than 2.8.12 don't support ROLE
+        Err(e) if e.kind() == redis::ErrorKind::ResponseError => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether a ROLE reply is the one of a master
+fn is_master_role(role: &[redis::Value]) -> bool {
+    role.first()
+        .and_then(|role| from_redis_value::<String>(role).ok())
+        .is_some_and(|role| role == "master")
+}
+
+fn open(connect: &str) -> RedisResult<Connection> {
+    redis::Client::open(format!("redis://{}/", connect).as_ref())?
+        .get_connection_with_timeout(CONNECT_TIMEOUT)
+}
+
 impl Input for RedisInput {
     fn accept(
         &self,
-        tx: SyncSender<Vec<u8>>,
+        tx: RecordSender,
         decoder: Box<dyn Decoder + Send>,
         encoder: Box<dyn Encoder + Send>,
     ) {
+        daemon::listening();
         let mut jids = Vec::new();
         for tid in 0..self.threads {
             let config = self.config.clone();
             let (encoder, decoder) = (encoder.clone_boxed(), decoder.clone_boxed());
             let tx = tx.clone();
-            jids.push(thread::spawn(move || {
-                let worker = RedisWorker::new(tid, config, tx, decoder, encoder);
-                if let Err(e) = worker.run() {
-                    let _ = writeln!(stderr(), "Redis connection lost, aborting - {}", e);
-                }
-                exit(1);
+            let acks = Arc::clone(&self.acks);
+            let name = format!("flowgger-input-redis-{}", tid);
+            let cpu = self.affinity.cpu(tid as usize);
+            jids.push(threads::spawn(name, cpu, move || {
+                let worker = RedisWorker {
+                    tid,
+                    config,
+                    tx,
+                    decoder,
+                    encoder,
+                    acks,
+                };
+                worker.run();
             }));
         }
         for jid in jids {
@@ -148,16 +647,174 @@ impl Input for RedisInput {
             }
         }
     }
+
+    /// Entries of a stream are acknowledged once delivered
+    fn notifier(&self) -> Option<Arc<dyn Notifier>> {
+        self.config
+            .stream
+            .as_ref()
+            .map(|_| Arc::clone(&self.acks) as Arc<dyn Notifier>)
+    }
 }
 
-fn handle_record(
+fn encode_record(
     line: &str,
-    tx: &SyncSender<Vec<u8>>,
-    decoder: &Box<dyn Decoder>,
-    encoder: &Box<dyn Encoder>,
-) -> Result<(), &'static str> {
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
+) -> Result<Vec<u8>, &'static str> {
     let decoded = decoder.decode(line)?;
-    let reencoded = encoder.encode(decoded)?;
-    tx.send(reencoded).unwrap();
-    Ok(())
+    encoder.encode(decoded)
+}
+
+/// Settings of the Redis input, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "# Redis server, and list the records are popped from\n\
+         # redis_connect = \"{}\"\n\
+         # redis_queue_key = \"{}\"\n\
+         # redis_threads = {}\n\
+         # Stream read with a consumer group instead of the list\n\
+         # redis_stream_key = \"{}\"\n\
+         # redis_stream_group = \"{}\"\n\
+         # Milliseconds after which the entries that were not acknowledged are read again\n\
+         # redis_stream_claim_idle = {}\n",
+        DEFAULT_CONNECT,
+        DEFAULT_QUEUE_KEY,
+        DEFAULT_THREADS,
+        DEFAULT_QUEUE_KEY,
+        DEFAULT_STREAM_GROUP,
+        DEFAULT_STREAM_CLAIM_IDLE
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redis_stream_config() {
+        let input =
+            RedisInput::new(&Config::from_string("[input]\nredis_queue_key = \"q\"").unwrap());
+        assert!(input.config.stream.is_none());
+        assert_eq!(input.config.connect, vec![DEFAULT_CONNECT.to_owned()]);
+        assert!(input.config.sentinel_master.is_none());
+
+        let config = Config::from_string(
+            "[input]\nredis_stream_key = \"logs\"\nredis_stream_consumer = \"box\"",
+        )
+        .unwrap();
+        let stream = RedisInput::new(&config).config.stream.unwrap();
+        assert_eq!(stream.key, "logs");
+        assert_eq!(stream.group, DEFAULT_STREAM_GROUP);
+        assert_eq!(stream.consumer, "box");
+        assert_eq!(stream.field, DEFAULT_STREAM_FIELD);
+        assert_eq!(stream.claim_idle, DEFAULT_STREAM_CLAIM_IDLE);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "input.redis_stream_key requires every record of the queue to come from the input"
+    )]
+    fn test_redis_stream_stats_records() {
+        let config =
+            Config::from_string("[input]\nredis_stream_key = \"logs\"\n[stats]\ninterval = 60\n")
+                .unwrap();
+        RedisInput::new(&config);
+    }
+
+    #[test]
+    fn test_redis_stream_acks() {
+        let acks = StreamAcks::default();
+        let (tx, rx) = crate::flowgger::record_queue::unbounded();
+        acks.send(&tx, b"a".to_vec(), "1-0");
+        acks.send(&tx, b"b".to_vec(), "2-0");
+        acks.send(&tx, b"c".to_vec(), "3-0");
+        acks.skip("4-0");
+        assert_eq!(rx.len(), 3);
+        assert!(acks.is_in_flight("1-0"));
+        assert_eq!(acks.take_delivered(), vec!["4-0"]);
+
+        // Only the delivered entries are acknowledged, the others being claimed later
+        acks.notify(&[b"a".to_vec()], Ok(()));
+        acks.notify(&[b"b".to_vec()], Err("failed"));
+        assert!(!acks.is_in_flight("1-0"));
+        assert!(!acks.is_in_flight("2-0"));
+        assert!(acks.is_in_flight("3-0"));
+        let delivered = acks.take_delivered();
+        assert_eq!(delivered, vec!["1-0"]);
+        acks.restore_delivered(delivered);
+        acks.notify(&[b"c".to_vec()], Ok(()));
+        assert_eq!(acks.take_delivered(), vec!["1-0", "3-0"]);
+    }
+
+    #[test]
+    fn test_redis_role() {
+        use redis::Value::{Bulk, Data, Int};
+        let data = |s: &str| Data(s.as_bytes().to_vec());
+        assert!(is_master_role(&[
+            data("master"),
+            Int(3129659),
+            Bulk(vec![])
+        ]));
+        assert!(!is_master_role(&[
+            data("slave"),
+            data("127.0.0.1"),
+            Int(9999),
+            data("connected"),
+            Int(3167038)
+        ]));
+        assert!(!is_master_role(&[data("sentinel"), Bulk(vec![])]));
+        assert!(!is_master_role(&[]));
+    }
+
+    #[test]
+    fn test_redis_parse_autoclaim() {
+        use redis::Value::{Bulk, Data, Nil};
+        let data = |s: &str| Data(s.as_bytes().to_vec());
+        let reply = Bulk(vec![
+            data("5-0"),
+            Bulk(vec![
+                Bulk(vec![
+                    data("1-0"),
+                    Bulk(vec![data("message"), data("hello")]),
+                ]),
+                Bulk(vec![data("2-0"), Nil]),
+            ]),
+            Bulk(vec![]),
+        ]);
+        let (cursor, entries) = parse_autoclaim(&reply).unwrap();
+        assert_eq!(cursor, "5-0");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].id, "1-0");
+        assert_eq!(
+            entries[0].get::<String>("message").as_deref(),
+            Some("hello")
+        );
+        assert_eq!(entries[1].id, "2-0");
+        assert!(entries[1].is_empty());
+        assert!(parse_autoclaim(&Nil).is_err());
+    }
+
+    #[test]
+    fn test_redis_sentinel_config() {
+        let config = Config::from_string(
+            "[input]\nredis_connect = [\"10.0.0.1:26379\", \"10.0.0.2:26379\"]\nredis_sentinel_master = \"mymaster\"",
+        )
+        .unwrap();
+        let input = RedisInput::new(&config);
+        assert_eq!(input.config.connect, ["10.0.0.1:26379", "10.0.0.2:26379"]);
+        assert_eq!(input.config.sentinel_master.as_deref(), Some("mymaster"));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "input.redis_recovery_delay_max cannot be less than input.redis_recovery_delay_init"
+    )]
+    fn test_redis_recovery_delay_config() {
+        let config = Config::from_string(
+            "[input]\nredis_recovery_delay_init = 1000\nredis_recovery_delay_max = 10",
+        )
+        .unwrap();
+        RedisInput::new(&config);
+    }
 }
@@ -0,0 +1,274 @@
+use super::Input;
+use crate::flowgger::config::Config;
+use crate::flowgger::daemon;
+use crate::flowgger::decoder::{log_rejected, Decoder};
+use crate::flowgger::encoder::Encoder;
+use crate::flowgger::input::listen::{self, Listen};
+use crate::flowgger::record_queue::RecordSender;
+use crate::flowgger::utils::relp::{self, RELP_SOFTWARE};
+use crate::flowgger::utils::threads::{self, CpuAffinity};
+use serde::Deserialize;
+use std::io::{stderr, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+const DEFAULT_LISTEN: &str = "0.0.0.0:2514";
+const DEFAULT_TIMEOUT: u64 = 3600;
+const DEFAULT_RELP_MAX_FRAME_SIZE: usize = 128 * 1024;
+
+/// Settings of the RELP input, in the `[input]` section
+#[derive(Deserialize)]
+pub struct RelpSettings {
+    timeout: Option<u64>,
+    relp_max_frame_size: Option<NonZeroUsize>,
+}
+
+/// RELP (Reliable Event Logging Protocol) input, as spoken by the omrelp module of rsyslog.
+///
+/// Every record is acknowledged once it has been queued, so that clients know which records to send again
+/// after a connection broke, instead of losing the ones that were still in flight.
+/// Records that can't be decoded are acknowledged as well, after having been logged like with the other
+/// inputs: sending them again would not help.
+pub struct RelpInput {
+    listen: Listen,
+    timeout: Option<Duration>,
+    max_frame_size: usize,
+    affinity: CpuAffinity,
+}
+
+impl RelpInput {
+    /// # Parameters
+    /// - 'input.listen':              Optional. Address, or list of addresses, to listen on. Default is
+    ///   "0.0.0.0:2514".
+    /// - 'input.timeout':             Optional. Seconds without any frame after which a connection is closed.
+    ///   Default is 3600.
+    /// - 'input.relp_max_frame_size': Optional. Maximum size of the data of a frame, larger frames closing the
+    ///   connection. Default is 131072, the default maximum message size of rsyslog.
+    pub fn new(config: &Config) -> RelpInput {
+        let listen = Listen::new(config, DEFAULT_LISTEN);
+        let settings: RelpSettings = config.settings("input");
+        let timeout = settings.timeout.unwrap_or(DEFAULT_TIMEOUT);
+        let max_frame_size = settings
+            .relp_max_frame_size
+            .map_or(DEFAULT_RELP_MAX_FRAME_SIZE, NonZeroUsize::get);
+        RelpInput {
+            listen,
+            timeout: Some(Duration::from_secs(timeout)),
+            max_frame_size,
+            affinity: CpuAffinity::new(config, "input"),
+        }
+    }
+}
+
+impl Input for RelpInput {
+    fn accept(
+        &self,
+        tx: RecordSender,
+        decoder: Box<dyn Decoder + Send>,
+        encoder: Box<dyn Encoder + Send>,
+    ) {
+        let listeners = self.listen.tcp_listeners();
+        daemon::listening();
+        for (i, client) in listen::incoming(listeners).iter().enumerate() {
+            let _ = client.set_read_timeout(self.timeout);
+            let tx = tx.clone();
+            let (decoder, encoder) = (decoder.clone_boxed(), encoder.clone_boxed());
+            let max_frame_size = self.max_frame_size;
+            let name = format!("flowgger-input-relp-{}", i);
+            threads::spawn(name, self.affinity.cpu(i), move || {
+                handle_client(client, tx, decoder, encoder, max_frame_size);
+            });
+        }
+    }
+}
+
+fn handle_client(
+    client: TcpStream,
+    tx: RecordSender,
+    decoder: Box<dyn Decoder>,
+    encoder: Box<dyn Encoder>,
+    max_frame_size: usize,
+) {
+    if let Ok(peer_addr) = client.peer_addr() {
+        println!("Connection over RELP from [{}]", peer_addr);
+    }
+    let writer = match client.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            let _ = writeln!(stderr(), "Unable to set up the RELP session: {}", e);
+            return;
+        }
+    };
+    let session = RelpSession {
+        reader: BufReader::new(client),
+        writer,
+        max_frame_size,
+    };
+    if let Err(e) = session.run(&tx, &*decoder, &*encoder) {
+        let _ = writeln!(stderr(), "RELP session closed: {}", e);
+    }
+}
+
+struct RelpSession<R: Read, W: Write> {
+    reader: BufReader<R>,
+    writer: W,
+    max_frame_size: usize,
+}
+
+impl<R: Read, W: Write> RelpSession<R, W> {
+    /// Answer the commands of the client until it closes the session
+    fn run(
+        mut self,
+        tx: &RecordSender,
+        decoder: &dyn Decoder,
+        encoder: &dyn Encoder,
+    ) -> Result<(), &'static str> {
+        let mut open = false;
+        while let Some(frame) = relp::read_frame(&mut self.reader, self.max_frame_size)? {
+            match (frame.command.as_str(), open) {
+                ("open", false) => {
+                    open = true;
+                    let offers = format!(
+                        "200 OK\nrelp_version=0\nrelp_software={}\ncommands=syslog",
+                        RELP_SOFTWARE
+                    );
+                    self.respond(frame.txnr, offers.as_bytes())?;
+                }
+                ("syslog", true) => {
+                    let line = &frame.data;
+                    if let Err(e) = handle_line(line, tx, decoder, encoder) {
+                        log_rejected(e, line);
+                    }
+                    self.respond(frame.txnr, b"200 OK")?;
+                }
+                ("close", true) => {
+                    self.respond(frame.txnr, b"")?;
+                    return Ok(());
+                }
+                ("open", true) => {
+                    self.respond(frame.txnr, b"500 session already open")?;
+                }
+                (_, false) => {
+                    self.respond(frame.txnr, b"500 session not open")?;
+                    self.server_close()?;
+                    return Err("Command received before the session was open");
+                }
+                (_, true) => {
+                    self.respond(frame.txnr, b"500 command not supported")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn respond(&mut self, txnr: u32, data: &[u8]) -> Result<(), &'static str> {
+        self.send(txnr, "rsp", data)
+    }
+
+    /// Tell the client that the server is closing the session
+    fn server_close(&mut self) -> Result<(), &'static str> {
+        self.send(0, "serverclose", b"")
+    }
+
+    fn send(&mut self, txnr: u32, command: &str, data: &[u8]) -> Result<(), &'static str> {
+        self.writer
+            .write_all(&relp::encode_frame(txnr, command, data))
+            .and_then(|_| self.writer.flush())
+            .or(Err("Connection closed"))
+    }
+}
+
+fn handle_line(
+    line: &[u8],
+    tx: &RecordSender,
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
+) -> Result<(), &'static str> {
+    let decoded = decoder.decode_bytes(line)?;
+    let reencoded = encoder.encode(decoded)?;
+    tx.send(reencoded).unwrap();
+    Ok(())
+}
+
+/// Settings of the RELP input, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "# Address, or list of addresses, to listen on\n\
+         listen = \"{}\"\n\
+         # Seconds after which idle connections are closed\n\
+         # timeout = {}\n\
+         # Larger frames close the connection\n\
+         # relp_max_frame_size = {}\n",
+        DEFAULT_LISTEN, DEFAULT_TIMEOUT, DEFAULT_RELP_MAX_FRAME_SIZE
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::record_queue::unbounded;
+    use crate::flowgger::utils::test_utils::record_test_utils::{MsgEncoder, TestDecoder};
+
+    fn run(input: &[u8]) -> (Result<(), &'static str>, String, Vec<Vec<u8>>) {
+        let (tx, rx) = unbounded();
+        let mut responses = Vec::new();
+        let session = RelpSession {
+            reader: BufReader::new(input),
+            writer: &mut responses,
+            max_frame_size: 64,
+        };
+        let result = session.run(&tx, &TestDecoder, &MsgEncoder);
+        drop(tx);
+        (
+            result,
+            String::from_utf8(responses).unwrap(),
+            rx.iter().collect(),
+        )
+    }
+
+    #[test]
+    fn test_relp_session() {
+        let (result, responses, records) = run(b"1 open 30 relp_version=0\ncommands=syslog\n\
+              2 syslog 11 <13>1 first\n\
+              3 syslog 7 invalid\n\
+              4 syslog 12 <13>1 second\n\
+              5 close 0\n");
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            responses,
+            format!(
+                "1 rsp {} 200 OK\nrelp_version=0\nrelp_software={}\ncommands=syslog\n\
+                 2 rsp 6 200 OK\n3 rsp 6 200 OK\n4 rsp 6 200 OK\n5 rsp 0\n",
+                52 + RELP_SOFTWARE.len(),
+                RELP_SOFTWARE
+            )
+        );
+        assert_eq!(
+            records,
+            vec![b"<13>1 first".to_vec(), b"<13>1 second".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_relp_session_errors() {
+        let (result, responses, records) = run(b"1 syslog 5 hello\n");
+        assert!(result.is_err());
+        assert_eq!(
+            responses,
+            "1 rsp 20 500 session not open\n0 serverclose 0\n"
+        );
+        assert!(records.is_empty());
+
+        let (result, responses, _) = run(b"1 open 0\n2 syslog 5 hello!\n");
+        assert_eq!(result, Err("Missing RELP frame trailer"));
+        assert!(responses.starts_with("1 rsp "));
+
+        let (result, _, _) = run(b"1 open 0\n2 syslog 65 ");
+        assert_eq!(result, Err("RELP frame too large"));
+
+        let (result, responses, _) = run(b"1 open 0\n2 starttls 0\n");
+        assert_eq!(result, Ok(()));
+        assert!(responses.ends_with("2 rsp 25 500 command not supported\n"));
+    }
+}
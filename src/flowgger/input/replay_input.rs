@@ -0,0 +1,408 @@
+use super::pacer::Pacer;
+use super::Input;
+use crate::flowgger::config::Config;
+use crate::flowgger::daemon;
+use crate::flowgger::decoder::{log_rejected, Decoder};
+use crate::flowgger::encoder::Encoder;
+use crate::flowgger::record_queue::{self, RecordSender};
+use crate::flowgger::splitter::{
+    framing_delimiter, DelimiterSplitter, JsonSeqSplitter, LineSplitter, NulSplitter, Splitter,
+    SyslenSplitter,
+};
+use crate::flowgger::utils::threads;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, stderr, BufReader, ErrorKind, Read, Write};
+use std::num::NonZeroU64;
+use std::path::PathBuf;
+use std::time::Instant;
+
+const DEFAULT_FRAMING: &str = "line";
+const REPLAY_QUEUE_SIZE: usize = 64;
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOSECONDS: u32 = 0xa1b2_3c4d;
+const PCAPNG_MAGIC: u32 = 0x0a0d_0d0a;
+const PCAP_HEADER_LEN: usize = 24;
+const PCAP_RECORD_HEADER_LEN: usize = 16;
+const PCAP_MAX_PACKET_LEN: usize = 256 * 1024;
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const IPPROTO_UDP: u8 = 17;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ReplayFormat {
+    Log,
+    Pcap,
+}
+
+/// Settings of the replay input, in the `[input]` section
+#[derive(Deserialize)]
+pub struct ReplaySettings {
+    src: PathBuf,
+    replay_format: Option<String>,
+    replay_rate: Option<NonZeroU64>,
+    replay_port: Option<u16>,
+    framing: Option<String>,
+    framing_delimiter: Option<String>,
+}
+
+/// Feeds a capture through the pipeline, for load testing and for reproducing production issues offline.
+/// Log files are split using the configured framing, and pcap captures are replayed one UDP datagram
+/// per record. Flowgger exits once the whole file has been replayed.
+pub struct ReplayInput {
+    path: PathBuf,
+    format: ReplayFormat,
+    rate: Option<u64>,
+    port: Option<u16>,
+    framing: String,
+    framing_delimiter: Vec<u8>,
+}
+
+impl ReplayInput {
+    /// # Parameters
+    /// - 'input.src':           Path of the file to replay.
+    /// - 'input.replay_format': Optional. "log" or "pcap". Default is "pcap" for files with a .pcap or .cap
+    ///   extension, "log" otherwise.
+    /// - 'input.replay_rate':   Optional. Maximum number of records per second. Records are replayed as
+    ///   fast as the pipeline accepts them when this is not set.
+    /// - 'input.replay_port':   Optional. Only replay the UDP datagrams sent to this port from pcap captures.
+    /// - 'input.framing':       Optional. Framing of log files, as with the stdin input. Default is "line".
+    pub fn new(config: &Config) -> ReplayInput {
+        let settings: ReplaySettings = config.settings("input");
+        let path = settings.src;
+        let format = match settings.replay_format.as_deref() {
+            None => match path.extension().and_then(|x| x.to_str()) {
+                Some("pcap") | Some("cap") => ReplayFormat::Pcap,
+                _ => ReplayFormat::Log,
+            },
+            Some("log") => ReplayFormat::Log,
+            Some("pcap") => ReplayFormat::Pcap,
+            _ => panic!(r#"input.replay_format must be "log" or "pcap""#),
+        };
+        let framing = if settings.framing_delimiter.is_some() {
+            "delimiter"
+        } else {
+            DEFAULT_FRAMING
+        };
+        let framing = settings.framing.unwrap_or_else(|| framing.to_owned());
+        ReplayInput {
+            path,
+            format,
+            rate: settings.replay_rate.map(NonZeroU64::get),
+            port: settings.replay_port,
+            framing,
+            framing_delimiter: framing_delimiter(config),
+        }
+    }
+
+    fn replay_log(
+        &self,
+        file: File,
+        tx: RecordSender,
+        decoder: Box<dyn Decoder + Send>,
+        encoder: Box<dyn Encoder + Send>,
+    ) {
+        let splitter = match &self.framing as &str {
+            "line" => Box::new(LineSplitter) as Box<dyn Splitter<_>>,
+            "syslen" => Box::new(SyslenSplitter) as Box<dyn Splitter<_>>,
+            "nul" => Box::new(NulSplitter) as Box<dyn Splitter<_>>,
+            "json-seq" => Box::new(JsonSeqSplitter) as Box<dyn Splitter<_>>,
+            "delimiter" => Box::new(DelimiterSplitter::new(self.framing_delimiter.clone()))
+                as Box<dyn Splitter<_>>,
+            _ => panic!("Unsupported framing scheme"),
+        };
+        splitter.run(BufReader::new(file), tx, decoder, encoder);
+    }
+
+    fn replay_pcap(
+        &self,
+        file: File,
+        tx: RecordSender,
+        decoder: Box<dyn Decoder + Send>,
+        encoder: Box<dyn Encoder + Send>,
+    ) -> io::Result<()> {
+        let mut pcap = PcapReader::new(BufReader::new(file))?;
+        while let Some(frame) = pcap.next_frame()? {
+            let payload = match udp_payload(pcap.linktype, &frame) {
+                Some((port, payload)) if self.port.is_none_or(|x| x == port) => payload,
+                _ => continue,
+            };
+            let res = decoder
+                .decode_bytes(payload)
+                .and_then(|decoded| encoder.encode(decoded));
+            match res {
+                Ok(reencoded) => {
+                    if tx.send(reencoded).is_err() {
+                        return Ok(());
+                    }
+                }
+                Err(e) => log_rejected(e, payload),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Input for ReplayInput {
+    fn accept(
+        &self,
+        tx: RecordSender,
+        decoder: Box<dyn Decoder + Send>,
+        encoder: Box<dyn Encoder + Send>,
+    ) {
+        let file = File::open(&self.path).unwrap_or_else(|e| {
+            panic!(
+                "Unable to open the file to replay [{}]: {}",
+                self.path.display(),
+                e
+            )
+        });
+        daemon::listening();
+        let (replay_tx, replay_rx) = record_queue::bounded(REPLAY_QUEUE_SIZE);
+        let mut pacer = self.rate.map(|rate| Pacer::new(rate, Instant::now()));
+        let relay = threads::spawn("flowgger-replay".to_owned(), None, move || {
+            let mut count = 0u64;
+            for bytes in replay_rx.iter() {
+                if let Some(pacer) = &mut pacer {
+                    pacer.wait();
+                }
+                if tx.send(bytes).is_err() {
+                    break;
+                }
+                count += 1;
+            }
+            count
+        });
+        match self.format {
+            ReplayFormat::Log => self.replay_log(file, replay_tx, decoder, encoder),
+            ReplayFormat::Pcap => {
+                if let Err(e) = self.replay_pcap(file, replay_tx, decoder, encoder) {
+                    let _ = writeln!(
+                        stderr(),
+                        "Unable to replay [{}]: {}",
+                        self.path.display(),
+                        e
+                    );
+                }
+            }
+        }
+        let count = relay.join().unwrap_or_default();
+        let _ = writeln!(
+            stderr(),
+            "Replayed {} records from [{}]",
+            count,
+            self.path.display()
+        );
+    }
+}
+
+/// Reader of the frames of a classic pcap capture, in either byte order
+struct PcapReader<R: Read> {
+    reader: R,
+    big_endian: bool,
+    linktype: u32,
+}
+
+impl<R: Read> PcapReader<R> {
+    fn new(mut reader: R) -> io::Result<PcapReader<R>> {
+        let mut header = [0u8; PCAP_HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let big_endian = match magic {
+            PCAP_MAGIC | PCAP_MAGIC_NANOSECONDS => false,
+            _ if magic.swap_bytes() == PCAP_MAGIC
+                || magic.swap_bytes() == PCAP_MAGIC_NANOSECONDS =>
+            {
+                true
+            }
+            PCAPNG_MAGIC => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "pcapng captures are not supported, convert them with editcap -F pcap",
+                ))
+            }
+            _ => return Err(io::Error::new(ErrorKind::InvalidData, "Not a pcap capture")),
+        };
+        let mut pcap = PcapReader {
+            reader,
+            big_endian,
+            linktype: 0,
+        };
+        pcap.linktype = pcap.u32_at(&header, 20);
+        Ok(pcap)
+    }
+
+    fn u32_at(&self, bytes: &[u8], offset: usize) -> u32 {
+        let bytes = [
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    /// Next captured frame, or `None` at the end of the capture
+    fn next_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut header = [0u8; PCAP_RECORD_HEADER_LEN];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let len = self.u32_at(&header, 8) as usize;
+        if len > PCAP_MAX_PACKET_LEN {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "Corrupted pcap capture",
+            ));
+        }
+        let mut frame = vec![0u8; len];
+        self.reader.read_exact(&mut frame)?;
+        Ok(Some(frame))
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([
+        *bytes.get(offset)?,
+        *bytes.get(offset + 1)?,
+    ]))
+}
+
+/// Destination port and payload of a UDP datagram. Other protocols and IP fragments are skipped.
+fn udp_payload(linktype: u32, frame: &[u8]) -> Option<(u16, &[u8])> {
+    let (ethertype, packet) = match linktype {
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ethertype = u16_at(frame, offset)?;
+            while ethertype == ETHERTYPE_VLAN {
+                offset += 4;
+                ethertype = u16_at(frame, offset)?;
+            }
+            (ethertype, frame.get(offset + 2..)?)
+        }
+        LINKTYPE_LINUX_SLL => (u16_at(frame, 14)?, frame.get(16..)?),
+        LINKTYPE_RAW | LINKTYPE_NULL => {
+            let packet = if linktype == LINKTYPE_NULL {
+                frame.get(4..)?
+            } else {
+                frame
+            };
+            match packet.first()? >> 4 {
+                4 => (ETHERTYPE_IPV4, packet),
+                6 => (ETHERTYPE_IPV6, packet),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    let datagram = match ethertype {
+        ETHERTYPE_IPV4 => {
+            let header_len = ((packet.first()? & 0x0f) as usize) * 4;
+            let fragment = u16_at(packet, 6)?;
+            if *packet.get(9)? != IPPROTO_UDP || fragment & 0x3fff != 0 {
+                return None;
+            }
+            let total_len = (u16_at(packet, 2)? as usize).min(packet.len());
+            packet.get(header_len..total_len)?
+        }
+        ETHERTYPE_IPV6 => {
+            if *packet.get(6)? != IPPROTO_UDP {
+                return None;
+            }
+            let payload_len = u16_at(packet, 4)? as usize;
+            packet.get(40..(40 + payload_len).min(packet.len()))?
+        }
+        _ => return None,
+    };
+    let port = u16_at(datagram, 2)?;
+    let udp_len = (u16_at(datagram, 4)? as usize).min(datagram.len());
+    Some((port, datagram.get(8..udp_len)?))
+}
+
+/// Settings of the replay input, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "# Log file or pcap capture of UDP syslog traffic to replay, flowgger exiting once it has been replayed\n\
+         src = \"/tmp/capture.pcap\"\n\
+         # Format of the file, \"log\" or \"pcap\", guessed from the extension by default\n\
+         # replay_format = \"pcap\"\n\
+         # Maximum number of records per second, as fast as possible by default\n\
+         # replay_rate = 1000\n\
+         # Only replay the datagrams sent to this port\n\
+         # replay_port = 514\n\
+         # Framing of the records of log files\n\
+         # framing = \"{}\"\n",
+        DEFAULT_FRAMING
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ipv4_udp_frame(port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        let total_len = (20 + 8 + payload.len()) as u16;
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&total_len.to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 0, 64, IPPROTO_UDP, 0, 0]);
+        frame.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        frame.extend_from_slice(&40000u16.to_be_bytes());
+        frame.extend_from_slice(&port.to_be_bytes());
+        frame.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn pcap(frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut pcap = PCAP_MAGIC.to_le_bytes().to_vec();
+        pcap.extend_from_slice(&[2, 0, 4, 0]);
+        pcap.extend_from_slice(&[0; 8]);
+        pcap.extend_from_slice(&65535u32.to_le_bytes());
+        pcap.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        for frame in frames {
+            pcap.extend_from_slice(&[0; 8]);
+            pcap.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            pcap.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            pcap.extend_from_slice(frame);
+        }
+        pcap
+    }
+
+    #[test]
+    fn test_replay_pcap() {
+        let capture = pcap(&[
+            ipv4_udp_frame(514, b"<13>first"),
+            ipv4_udp_frame(53, b"not syslog"),
+            ipv4_udp_frame(514, b"<13>second"),
+        ]);
+        let mut reader = PcapReader::new(&capture[..]).unwrap();
+        assert_eq!(reader.linktype, LINKTYPE_ETHERNET);
+        let mut payloads = Vec::new();
+        while let Some(frame) = reader.next_frame().unwrap() {
+            let (port, payload) = udp_payload(reader.linktype, &frame).unwrap();
+            if port == 514 {
+                payloads.push(payload.to_vec());
+            }
+        }
+        assert_eq!(
+            payloads,
+            vec![b"<13>first".to_vec(), b"<13>second".to_vec()]
+        );
+        assert!(PcapReader::new(&b"0123456789012345678901234"[..]).is_err());
+    }
+}
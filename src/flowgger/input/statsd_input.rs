@@ -0,0 +1,128 @@
+use super::Input;
+use crate::flowgger::config::Config;
+use crate::flowgger::daemon;
+use crate::flowgger::decoder::{Decoder, DROPPED};
+use crate::flowgger::encoder::Encoder;
+use crate::flowgger::input::listen::Listen;
+use crate::flowgger::record_queue::RecordSender;
+use crate::flowgger::utils::threads;
+use std::io::{stderr, Write};
+use std::net::UdpSocket;
+
+const DEFAULT_LISTEN: &str = "0.0.0.0:8125";
+const MAX_UDP_PACKET_SIZE: usize = 65_527;
+
+/// Statsd input, receiving metrics over UDP as statsd and DogStatsD clients send them, several metrics per
+/// datagram being separated by line breaks. Metrics are decoded with the "statsd" format by default.
+pub struct StatsdInput {
+    listen: Listen,
+}
+
+impl StatsdInput {
+    /// # Parameters
+    /// - 'input.listen': Optional. Address, or list of addresses, to listen on. Default is "0.0.0.0:8125".
+    pub fn new(config: &Config) -> StatsdInput {
+        StatsdInput {
+            listen: Listen::new(config, DEFAULT_LISTEN),
+        }
+    }
+}
+
+impl Input for StatsdInput {
+    fn accept(
+        &self,
+        tx: RecordSender,
+        decoder: Box<dyn Decoder + Send>,
+        encoder: Box<dyn Encoder + Send>,
+    ) {
+        let mut sockets = self.listen.udp_sockets();
+        daemon::listening();
+        let last = sockets.pop().expect("input.listen cannot be an empty list");
+        for (i, socket) in sockets.into_iter().enumerate() {
+            let tx = tx.clone();
+            let (decoder, encoder) = (decoder.clone_boxed(), encoder.clone_boxed());
+            threads::spawn(format!("flowgger-input-statsd-{}", i), None, move || {
+                receive(socket, tx, decoder, encoder)
+            });
+        }
+        receive(last, tx, decoder, encoder);
+    }
+}
+
+fn receive(
+    socket: UdpSocket,
+    tx: RecordSender,
+    decoder: Box<dyn Decoder>,
+    encoder: Box<dyn Encoder>,
+) {
+    let mut buf = [0; MAX_UDP_PACKET_SIZE];
+    loop {
+        let (length, _src) = match socket.recv_from(&mut buf) {
+            Ok(res) => res,
+            Err(_) => continue,
+        };
+        handle_datagram(&buf[..length], &tx, &*decoder, &*encoder);
+    }
+}
+
+/// Decode and encode every metric of a datagram, and send them to the queue
+fn handle_datagram(
+    datagram: &[u8],
+    tx: &RecordSender,
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
+) {
+    for line in datagram.split(|&c| c == b'\n') {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        match decoder
+            .decode_bytes(line)
+            .and_then(|record| encoder.encode(record))
+        {
+            Ok(reencoded) => tx.send(reencoded).unwrap(),
+            Err(e) if e != DROPPED => {
+                let _ = writeln!(stderr(), "{}", e);
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// Settings of the statsd input, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "# Address, or list of addresses, to listen on\n\
+         listen = \"{}\"\n",
+        DEFAULT_LISTEN
+    )
+}
+
+#[cfg(all(test, feature = "passthrough"))]
+mod tests {
+    use super::*;
+    use crate::flowgger::decoder::StatsdDecoder;
+    use crate::flowgger::encoder::PassthroughEncoder;
+    use crate::flowgger::record_queue::unbounded;
+
+    #[test]
+    fn test_statsd_input_datagram() {
+        let config = Config::from_string("").unwrap();
+        let (tx, rx) = unbounded();
+        handle_datagram(
+            b"requests:1|c\ninvalid\n\nlatency:12|ms|#route:home\n",
+            &tx,
+            &StatsdDecoder::new(&config),
+            &PassthroughEncoder::new(&config),
+        );
+        drop(tx);
+        let records: Vec<_> = rx.iter().collect();
+        assert_eq!(
+            records,
+            vec![
+                b"requests:1|c".to_vec(),
+                b"latency:12|ms|#route:home".to_vec()
+            ]
+        );
+    }
+}
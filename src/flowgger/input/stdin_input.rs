@@ -1,18 +1,31 @@
 use super::Input;
 use crate::flowgger::config::Config;
+use crate::flowgger::daemon;
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
-#[cfg(feature = "capnp-recompile")]
+use crate::flowgger::record_queue::RecordSender;
+#[cfg(feature = "capnp")]
 use crate::flowgger::splitter::CapnpSplitter;
-use crate::flowgger::splitter::{LineSplitter, NulSplitter, Splitter, SyslenSplitter};
+use crate::flowgger::splitter::{
+    framing_delimiter, DelimiterSplitter, JsonSeqSplitter, LineSplitter, NulSplitter, Splitter,
+    SyslenSplitter,
+};
+use serde::Deserialize;
 use std::io::{stdin, BufReader};
-use std::sync::mpsc::SyncSender;
 
 const DEFAULT_FRAMING: &str = "line";
 
+/// Settings of the stdin input, in the `[input]` section
+#[derive(Deserialize)]
+pub struct StdinSettings {
+    framing: Option<String>,
+    framing_delimiter: Option<String>,
+}
+
 #[derive(Clone)]
 pub struct StdinConfig {
     framing: String,
+    framing_delimiter: Vec<u8>,
 }
 
 pub struct StdinInput {
@@ -21,19 +34,23 @@ pub struct StdinInput {
 
 impl StdinInput {
     pub fn new(config: &Config) -> StdinInput {
-        let framing = config
-            .lookup("input.framing")
-            .map_or(DEFAULT_FRAMING, |x| {
-                x.as_str()
-                    .expect(r#"input.framing must be a string set to "line", "nul" or "syslen""#)
-            })
-            .to_owned();
-        let stdin_config = StdinConfig { framing };
+        let settings: StdinSettings = config.settings("input");
+        let framing = if settings.framing_delimiter.is_some() {
+            "delimiter"
+        } else {
+            DEFAULT_FRAMING
+        };
+        let framing = settings.framing.unwrap_or_else(|| framing.to_owned());
+        let framing_delimiter = framing_delimiter(config);
+        let stdin_config = StdinConfig {
+            framing,
+            framing_delimiter,
+        };
         StdinInput { stdin_config }
     }
 }
 
-#[cfg(feature = "capnp-recompile")]
+#[cfg(feature = "capnp")]
 pub fn get_capnp_splitter<T>() -> Box<dyn Splitter<T>>
 where
     T: std::io::Read,
@@ -41,7 +58,7 @@ where
     Box::new(CapnpSplitter) as Box<dyn Splitter<_>>
 }
 
-#[cfg(not(feature = "capnp-recompile"))]
+#[cfg(not(feature = "capnp"))]
 pub fn get_capnp_splitter() -> ! {
     panic!("Support for CapNProto is not compiled in")
 }
@@ -49,18 +66,32 @@ pub fn get_capnp_splitter() -> ! {
 impl Input for StdinInput {
     fn accept(
         &self,
-        tx: SyncSender<Vec<u8>>,
+        tx: RecordSender,
         decoder: Box<dyn Decoder + Send>,
         encoder: Box<dyn Encoder + Send>,
     ) {
+        daemon::listening();
         let reader = BufReader::new(stdin());
         let splitter = match &self.stdin_config.framing as &str {
             "capnp" => get_capnp_splitter(),
             "line" => Box::new(LineSplitter) as Box<dyn Splitter<_>>,
             "syslen" => Box::new(SyslenSplitter) as Box<dyn Splitter<_>>,
             "nul" => Box::new(NulSplitter) as Box<dyn Splitter<_>>,
+            "json-seq" => Box::new(JsonSeqSplitter) as Box<dyn Splitter<_>>,
+            "delimiter" => Box::new(DelimiterSplitter::new(
+                self.stdin_config.framing_delimiter.clone(),
+            )) as Box<dyn Splitter<_>>,
             _ => panic!("Unsupported framing scheme"),
         };
         splitter.run(reader, tx, decoder, encoder);
     }
 }
+
+/// Settings of the stdin input, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "# Records separated by line breaks, or \"nul\", \"syslen\", \"delimiter\" or \"json-seq\" framing\n\
+         # framing = \"{}\"\n",
+        DEFAULT_FRAMING
+    )
+}
@@ -1,9 +1,19 @@
+use self::starttls::StartTls;
 use crate::flowgger::config::Config;
+use crate::flowgger::decoder::{PeerStats, SequenceDedup};
+use crate::flowgger::input::connection_events::ConnectionEvents;
+use crate::flowgger::input::decompress::Decompression;
+use crate::flowgger::input::listen::Listen;
+use crate::flowgger::splitter::framing_delimiter;
+use serde::Deserialize;
 
+mod starttls;
 pub mod tcp_input;
 #[cfg(feature = "coroutines")]
 pub mod tcpco_input;
 
+#[cfg(test)]
+pub use self::starttls::StartTlsSettings;
 pub use super::Input;
 
 const DEFAULT_FRAMING: &str = "line";
@@ -15,50 +25,80 @@ const DEFAULT_TIMEOUT: u64 = 3600;
 #[derive(Clone)]
 pub struct TcpConfig {
     framing: String,
+    framing_delimiter: Vec<u8>,
+    decompression: Decompression,
+    dedup: SequenceDedup,
+    peer_stats: PeerStats,
+    events: Option<ConnectionEvents>,
+    starttls: Option<StartTls>,
+    #[cfg_attr(not(feature = "coroutines"), allow(dead_code))]
     threads: usize,
 }
 
+/// Settings of the TCP inputs, in the `[input]` section
+#[derive(Deserialize)]
+pub struct TcpSettings {
+    #[cfg_attr(not(feature = "coroutines"), allow(dead_code))]
+    tcp_threads: Option<usize>,
+    timeout: Option<u64>,
+    framed: Option<bool>,
+    framing: Option<String>,
+    framing_delimiter: Option<String>,
+}
+
 #[cfg(feature = "coroutines")]
-fn get_default_threads(config: &Config) -> usize {
-    config
-        .lookup("input.tcp_threads")
-        .map_or(DEFAULT_THREADS, |x| {
-            x.as_integer()
-                .expect("input.tcp_threads must be an unsigned integer") as usize
-        })
+fn get_default_threads(settings: &TcpSettings) -> usize {
+    settings.tcp_threads.unwrap_or(DEFAULT_THREADS)
 }
 
 #[cfg(not(feature = "coroutines"))]
-fn get_default_threads(_config: &Config) -> usize {
+fn get_default_threads(_settings: &TcpSettings) -> usize {
     1
 }
 
-pub fn config_parse(config: &Config) -> (TcpConfig, String, u64) {
-    let listen = config
-        .lookup("input.listen")
-        .map_or(DEFAULT_LISTEN, |x| {
-            x.as_str().expect("input.listen must be an ip:port string")
-        })
-        .to_owned();
-    let threads = get_default_threads(config);
-    let timeout = config.lookup("input.timeout").map_or(DEFAULT_TIMEOUT, |x| {
-        x.as_integer()
-            .expect("input.timeout must be an unsigned integer") as u64
-    });
-    let framing = if config.lookup("input.framed").map_or(false, |x| {
-        x.as_bool().expect("input.framed must be a boolean")
-    }) {
+pub fn config_parse(config: &Config) -> (TcpConfig, Listen, u64) {
+    let settings: TcpSettings = config.settings("input");
+    let listen = Listen::new(config, DEFAULT_LISTEN);
+    let threads = get_default_threads(&settings);
+    let timeout = settings.timeout.unwrap_or(DEFAULT_TIMEOUT);
+    let framing = if settings.framed == Some(true) {
         "syslen"
+    } else if settings.framing_delimiter.is_some() {
+        "delimiter"
     } else {
         DEFAULT_FRAMING
     };
-    let framing = config
-        .lookup("input.framing")
-        .map_or(framing, |x| {
-            x.as_str()
-                .expect(r#"input.framing must be a string set to "line", "nul" or "syslen""#)
-        })
-        .to_owned();
-    let tcp_config = TcpConfig { framing, threads };
+    let framing = settings.framing.unwrap_or_else(|| framing.to_owned());
+    let framing_delimiter = framing_delimiter(config);
+    let decompression = Decompression::from_config(config);
+    let tcp_config = TcpConfig {
+        framing,
+        framing_delimiter,
+        decompression,
+        dedup: SequenceDedup::new(config),
+        peer_stats: PeerStats::new(config),
+        events: ConnectionEvents::new(config, "tcp"),
+        starttls: StartTls::from_config(config),
+        threads,
+    };
     (tcp_config, listen, timeout)
 }
+
+/// Settings of the TCP inputs, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "# Address, or list of addresses, to listen on\n\
+         listen = \"{}\"\n\
+         # Records separated by line breaks, or \"nul\", \"syslen\", \"delimiter\", \"json-seq\" or \"auto\" framing\n\
+         # framing = \"{}\"\n\
+         # Seconds after which idle connections are closed\n\
+         # timeout = {}\n",
+        DEFAULT_LISTEN, DEFAULT_FRAMING, DEFAULT_TIMEOUT
+    )
+}
+
+/// Settings of the TCP input using coroutines, for `flowgger config init`
+#[cfg(feature = "coroutines")]
+pub fn sample_config_co() -> String {
+    format!("{}# tcp_threads = {}\n", sample_config(), DEFAULT_THREADS)
+}
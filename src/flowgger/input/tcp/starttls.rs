@@ -0,0 +1,170 @@
+use crate::flowgger::config::Config;
+#[cfg(feature = "tls")]
+use crate::flowgger::input::tls;
+#[cfg(feature = "tls")]
+use openssl::ssl::SslAcceptor;
+use serde::Deserialize;
+use std::io::Read;
+#[cfg(feature = "tls")]
+use std::io::Write;
+use std::net::TcpStream;
+#[cfg(feature = "tls")]
+use std::thread;
+#[cfg(feature = "tls")]
+use std::time::Duration;
+
+const DEFAULT_STARTTLS: bool = false;
+/// Command sent by the clients to upgrade the connection
+#[cfg(feature = "tls")]
+const STARTTLS_COMMAND: &[u8] = b"STARTTLS";
+/// Answer to the command, after which the client starts the TLS handshake
+#[cfg(feature = "tls")]
+const STARTTLS_REPLY: &[u8] = b"OK\r\n";
+/// Content type of the TLS handshake records, the first byte of a connection starting with TLS
+#[cfg(feature = "tls")]
+const TLS_HANDSHAKE: u8 = 0x16;
+/// Delay between two checks of a command that was partially received, and the number of checks
+#[cfg(feature = "tls")]
+const NEGOTIATION_POLL_DELAY: Duration = Duration::from_millis(10);
+#[cfg(feature = "tls")]
+const NEGOTIATION_POLLS: usize = 500;
+
+/// How a connection starts
+#[cfg(feature = "tls")]
+#[derive(Debug, PartialEq)]
+enum Negotiation {
+    /// Plaintext records
+    Plaintext,
+    /// A TLS handshake, without any command
+    Tls,
+    /// The STARTTLS command, whose line is that long
+    StartTls(usize),
+}
+
+/// Settings of STARTTLS, in the `[input]` section
+#[derive(Deserialize)]
+pub struct StartTlsSettings {
+    tcp_starttls: Option<bool>,
+}
+
+/// Upgrade of the connections of the TCP input to TLS, for the devices that start in plaintext and switch to
+/// TLS on the same port, so that a second listener is not needed.
+///
+/// A connection starting with a `STARTTLS` line is answered with `OK`, after which the client starts the TLS
+/// handshake. A connection starting with a TLS handshake is accepted as a TLS connection. Any other connection
+/// is read as plaintext.
+#[cfg(feature = "tls")]
+#[derive(Clone)]
+pub struct StartTls {
+    acceptor: SslAcceptor,
+}
+
+/// Support for TLS hasn't been compiled in, so that connections can't be upgraded
+#[cfg(not(feature = "tls"))]
+#[derive(Clone)]
+pub enum StartTls {}
+
+impl StartTls {
+    /// # Parameters
+    /// - 'input.tcp_starttls': Optional. Upgrade the connections to TLS when they start with the STARTTLS command
+    ///   or with a TLS handshake, using the settings of the TLS input, i.e. 'input.tls_cert' and
+    ///   'input.tls_key'. Default is false. Not supported by the "tcp_co" input, that refuses to start.
+    pub fn from_config(config: &Config) -> Option<StartTls> {
+        let settings: StartTlsSettings = config.settings("input");
+        if !settings.tcp_starttls.unwrap_or(DEFAULT_STARTTLS) {
+            return None;
+        }
+        StartTls::new(config)
+    }
+
+    #[cfg(feature = "tls")]
+    fn new(config: &Config) -> Option<StartTls> {
+        Some(StartTls {
+            acceptor: tls::acceptor(config),
+        })
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn new(_config: &Config) -> Option<StartTls> {
+        panic!("Support for TLS hasn't been compiled in")
+    }
+
+    /// The stream of the connection, upgraded to TLS if the client asked for it
+    #[cfg(feature = "tls")]
+    pub fn negotiate(&self, mut client: TcpStream) -> Result<Box<dyn Read>, String> {
+        let negotiation = peek_negotiation(&client).map_err(|e| e.to_string())?;
+        if let Negotiation::StartTls(len) = negotiation {
+            client
+                .read_exact(&mut vec![0; len])
+                .and_then(|_| client.write_all(STARTTLS_REPLY))
+                .map_err(|e| e.to_string())?;
+        }
+        match negotiation {
+            Negotiation::Plaintext => Ok(Box::new(client)),
+            _ => match self.acceptor.accept(client) {
+                Ok(sslclient) => Ok(Box::new(sslclient)),
+                Err(e) => Err(e.to_string()),
+            },
+        }
+    }
+
+    #[cfg(not(feature = "tls"))]
+    pub fn negotiate(&self, _client: TcpStream) -> Result<Box<dyn Read>, String> {
+        match *self {}
+    }
+}
+
+/// How the connection starts, waiting for the rest of a command that was partially received
+#[cfg(feature = "tls")]
+fn peek_negotiation(client: &TcpStream) -> std::io::Result<Negotiation> {
+    let mut bytes = [0; STARTTLS_COMMAND.len() + 2];
+    for _ in 0..NEGOTIATION_POLLS {
+        let len = client.peek(&mut bytes)?;
+        if let Some(negotiation) = negotiation(&bytes[..len]) {
+            return Ok(negotiation);
+        }
+        thread::sleep(NEGOTIATION_POLL_DELAY);
+    }
+    Ok(Negotiation::Plaintext)
+}
+
+/// How a connection starts, from its first bytes, or `None` if they may be the start of the command
+#[cfg(feature = "tls")]
+fn negotiation(bytes: &[u8]) -> Option<Negotiation> {
+    if bytes.first() == Some(&TLS_HANDSHAKE) {
+        return Some(Negotiation::Tls);
+    }
+    let (command, end) = bytes.split_at(bytes.len().min(STARTTLS_COMMAND.len()));
+    if bytes.is_empty() || !STARTTLS_COMMAND.starts_with(command) {
+        return Some(Negotiation::Plaintext);
+    }
+    match end {
+        [b'\n', ..] => Some(Negotiation::StartTls(STARTTLS_COMMAND.len() + 1)),
+        [b'\r', b'\n', ..] => Some(Negotiation::StartTls(STARTTLS_COMMAND.len() + 2)),
+        [] | [b'\r'] => None,
+        _ => Some(Negotiation::Plaintext),
+    }
+}
+
+#[cfg(all(test, feature = "tls"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_starttls_negotiation() {
+        assert_eq!(
+            negotiation(b"<13>1 2015-08-05T15:53:45Z"),
+            Some(Negotiation::Plaintext)
+        );
+        assert_eq!(negotiation(b"STARTTLS\n"), Some(Negotiation::StartTls(9)));
+        assert_eq!(
+            negotiation(b"STARTTLS\r\n"),
+            Some(Negotiation::StartTls(10))
+        );
+        assert_eq!(negotiation(b"STARTTLS X"), Some(Negotiation::Plaintext));
+        assert_eq!(negotiation(b"STARTING"), Some(Negotiation::Plaintext));
+        assert_eq!(negotiation(b"STAR"), None);
+        assert_eq!(negotiation(b"STARTTLS\r"), None);
+        assert_eq!(negotiation(b"\x16\x03\x01"), Some(Negotiation::Tls));
+    }
+}
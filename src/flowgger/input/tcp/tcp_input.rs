@@ -1,20 +1,28 @@
 use super::*;
 use crate::flowgger::config::Config;
+use crate::flowgger::daemon;
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
-#[cfg(feature = "capnp-recompile")]
+use crate::flowgger::input::connection_events::ConnectionEvent;
+use crate::flowgger::input::listen::{self, Listen};
+use crate::flowgger::input::INPUT_BUFFER_SIZE;
+use crate::flowgger::record_queue::RecordSender;
+#[cfg(feature = "capnp")]
 use crate::flowgger::splitter::CapnpSplitter;
-use crate::flowgger::splitter::{LineSplitter, NulSplitter, Splitter, SyslenSplitter};
-use std::io::BufReader;
-use std::net::{TcpListener, TcpStream};
-use std::sync::mpsc::SyncSender;
-use std::thread;
+use crate::flowgger::splitter::{
+    AutoSplitter, DelimiterSplitter, JsonSeqSplitter, LineSplitter, NulSplitter, Splitter,
+    SyslenSplitter,
+};
+use crate::flowgger::utils::threads::{self, CpuAffinity};
+use std::io::{stderr, BufReader, Read, Write};
+use std::net::TcpStream;
 use std::time::Duration;
 
 pub struct TcpInput {
-    listen: String,
+    listen: Listen,
     tcp_config: TcpConfig,
     timeout: Option<Duration>,
+    affinity: CpuAffinity,
 }
 
 impl TcpInput {
@@ -24,6 +32,7 @@ impl TcpInput {
             listen,
             tcp_config,
             timeout: Some(Duration::from_secs(timeout)),
+            affinity: CpuAffinity::new(config, "input"),
         }
     }
 }
@@ -31,26 +40,29 @@ impl TcpInput {
 impl Input for TcpInput {
     fn accept(
         &self,
-        tx: SyncSender<Vec<u8>>,
+        tx: RecordSender,
         decoder: Box<dyn Decoder + Send>,
         encoder: Box<dyn Encoder + Send>,
     ) {
-        let listener = TcpListener::bind(&self.listen as &str).unwrap();
-        for client in listener.incoming() {
-            if let Ok(client) = client {
-                let _ = client.set_read_timeout(self.timeout);
-                let tx = tx.clone();
-                let tcp_config = self.tcp_config.clone();
-                let (decoder, encoder) = (decoder.clone_boxed(), encoder.clone_boxed());
-                thread::spawn(move || {
-                    handle_client(client, tx, decoder, encoder, tcp_config);
-                });
-            }
+        let listeners = self.listen.tcp_listeners();
+        daemon::listening();
+        for (i, client) in listen::incoming(listeners).iter().enumerate() {
+            let _ = client.set_read_timeout(self.timeout);
+            let tx = tx.clone();
+            let tcp_config = self.tcp_config.clone();
+            let peer = client.peer_addr().ok().map(|addr| addr.ip());
+            let decoder = self.tcp_config.peer_stats.wrap(peer, decoder.clone_boxed());
+            let decoder = self.tcp_config.dedup.wrap(peer, decoder);
+            let encoder = encoder.clone_boxed();
+            let name = format!("flowgger-input-tcp-{}", i);
+            threads::spawn(name, self.affinity.cpu(i), move || {
+                handle_client(client, tx, decoder, encoder, tcp_config);
+            });
         }
     }
 }
 
-#[cfg(feature = "capnp-recompile")]
+#[cfg(feature = "capnp")]
 pub fn get_capnp_splitter<T>() -> Box<dyn Splitter<T>>
 where
     T: std::io::Read,
@@ -58,27 +70,74 @@ where
     Box::new(CapnpSplitter) as Box<dyn Splitter<_>>
 }
 
-#[cfg(not(feature = "capnp-recompile"))]
+#[cfg(not(feature = "capnp"))]
 pub fn get_capnp_splitter() -> ! {
     panic!("Support for CapNProto is not compiled in")
 }
 
 fn handle_client(
     client: TcpStream,
-    tx: SyncSender<Vec<u8>>,
+    tx: RecordSender,
     decoder: Box<dyn Decoder>,
     encoder: Box<dyn Encoder>,
     tcp_config: TcpConfig,
 ) {
-    if let Ok(peer_addr) = client.peer_addr() {
+    let peer_addr = client.peer_addr().ok();
+    if let Some(peer_addr) = peer_addr {
         println!("Connection over TCP from [{}]", peer_addr);
     }
-    let reader = BufReader::new(client);
+    let events = tcp_config
+        .events
+        .clone()
+        .map(|events| (events, tx.clone(), encoder.clone_boxed()));
+    let stream = match tcp_config.starttls {
+        None => Box::new(client) as Box<dyn Read>,
+        Some(ref starttls) => match starttls.negotiate(client) {
+            Ok(stream) => stream,
+            Err(e) => {
+                let _ = writeln!(stderr(), "STARTTLS negotiation aborted: {}", e);
+                if let Some((events, tx, encoder)) = events {
+                    let reason = ConnectionEvent::HandshakeFailed(e);
+                    events.emit(reason, peer_addr, &tx, &*encoder);
+                }
+                return;
+            }
+        },
+    };
+    if let Some((ref events, ref tx, ref encoder)) = events {
+        events.emit(ConnectionEvent::Connected, peer_addr, tx, &**encoder);
+    }
+    read_client(stream, tx, decoder, encoder, tcp_config);
+    if let Some((events, tx, encoder)) = events {
+        events.emit(ConnectionEvent::Disconnected, peer_addr, &tx, &*encoder);
+    }
+}
+
+fn read_client(
+    client: Box<dyn Read>,
+    tx: RecordSender,
+    decoder: Box<dyn Decoder>,
+    encoder: Box<dyn Encoder>,
+    tcp_config: TcpConfig,
+) {
+    let stream = match tcp_config.decompression.reader(client) {
+        Ok(stream) => stream,
+        Err(e) => {
+            let _ = writeln!(stderr(), "Unable to decompress the stream: {}", e);
+            return;
+        }
+    };
+    let reader = BufReader::with_capacity(INPUT_BUFFER_SIZE, stream);
     let splitter = match &tcp_config.framing as &str {
         "capnp" => get_capnp_splitter(),
+        "auto" => Box::new(AutoSplitter) as Box<dyn Splitter<_>>,
         "line" => Box::new(LineSplitter) as Box<dyn Splitter<_>>,
         "syslen" => Box::new(SyslenSplitter) as Box<dyn Splitter<_>>,
         "nul" => Box::new(NulSplitter) as Box<dyn Splitter<_>>,
+        "json-seq" => Box::new(JsonSeqSplitter) as Box<dyn Splitter<_>>,
+        "delimiter" => {
+            Box::new(DelimiterSplitter::new(tcp_config.framing_delimiter)) as Box<dyn Splitter<_>>
+        }
         _ => panic!("Unsupported framing scheme"),
     };
     splitter.run(reader, tx, decoder, encoder);
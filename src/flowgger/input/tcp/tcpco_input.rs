@@ -1,23 +1,31 @@
 use super::*;
 use crate::flowgger::config::Config;
+use crate::flowgger::daemon;
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
+use crate::flowgger::input::listen::Listen;
+use crate::flowgger::record_queue::RecordSender;
 use crate::flowgger::splitter::{
-    CapnpSplitter, LineSplitter, NulSplitter, Splitter, SyslenSplitter,
+    AutoSplitter, CapnpSplitter, DelimiterSplitter, JsonSeqSplitter, LineSplitter, NulSplitter,
+    Splitter, SyslenSplitter,
 };
 use may::net::{TcpListener, TcpStream};
-use std::io::BufReader;
+use std::io::{stderr, BufReader, Write};
 use std::net::SocketAddr;
-use std::sync::mpsc::SyncSender;
 
 pub struct TcpCoInput {
-    listen: String,
+    listen: Listen,
     tcp_config: TcpConfig,
 }
 
 impl TcpCoInput {
     pub fn new(config: &Config) -> TcpCoInput {
-        let (tcp_config, listen, _timeout) = config_parse(&config);
+        let (tcp_config, listen, _timeout) = config_parse(config);
+        if tcp_config.starttls.is_some() {
+            panic!(
+                r#"input.tcp_starttls is not supported by the "tcp_co" input, use the "tcp" input"#
+            );
+        }
         TcpCoInput { listen, tcp_config }
     }
 }
@@ -25,19 +33,23 @@ impl TcpCoInput {
 impl Input for TcpCoInput {
     fn accept(
         &self,
-        tx: SyncSender<Vec<u8>>,
-        decoder: Box<Decoder + Send>,
-        encoder: Box<Encoder + Send>,
+        tx: RecordSender,
+        decoder: Box<dyn Decoder + Send>,
+        encoder: Box<dyn Encoder + Send>,
     ) {
         let tcp_config = self.tcp_config.clone();
         may::config().set_workers(tcp_config.threads);
 
-        let listen: SocketAddr = self.listen.parse().unwrap();
-        let listener = TcpListener::bind(&listen).unwrap();
+        let listen: SocketAddr = self.listen.single();
+        let listener = TcpListener::bind(listen).unwrap();
+        daemon::listening();
 
-        while let Ok((socket, _)) = listener.accept() {
+        while let Ok((socket, peer)) = listener.accept() {
             let tx = tx.clone();
-            let (decoder, encoder) = (decoder.clone_boxed(), encoder.clone_boxed());
+            let peer = Some(peer.ip());
+            let decoder = tcp_config.peer_stats.wrap(peer, decoder.clone_boxed());
+            let decoder = tcp_config.dedup.wrap(peer, decoder);
+            let encoder = encoder.clone_boxed();
             let tcp_config = tcp_config.clone();
             go!(move || {
                 handle_client(socket, tx, decoder, encoder, tcp_config);
@@ -48,20 +60,32 @@ impl Input for TcpCoInput {
 
 fn handle_client(
     client: TcpStream,
-    tx: SyncSender<Vec<u8>>,
-    decoder: Box<Decoder>,
-    encoder: Box<Encoder>,
+    tx: RecordSender,
+    decoder: Box<dyn Decoder>,
+    encoder: Box<dyn Encoder>,
     tcp_config: TcpConfig,
 ) {
     if let Ok(peer_addr) = client.peer_addr() {
         println!("Connection over TCP from [{}]", peer_addr);
     }
-    let reader = BufReader::new(client);
+    let stream = match tcp_config.decompression.reader(client) {
+        Ok(stream) => stream,
+        Err(e) => {
+            let _ = writeln!(stderr(), "Unable to decompress the stream: {}", e);
+            return;
+        }
+    };
+    let reader = BufReader::new(stream);
     let splitter = match &tcp_config.framing as &str {
-        "capnp" => Box::new(CapnpSplitter) as Box<Splitter<_>>,
-        "line" => Box::new(LineSplitter) as Box<Splitter<_>>,
-        "syslen" => Box::new(SyslenSplitter) as Box<Splitter<_>>,
-        "nul" => Box::new(NulSplitter) as Box<Splitter<_>>,
+        "capnp" => Box::new(CapnpSplitter) as Box<dyn Splitter<_>>,
+        "auto" => Box::new(AutoSplitter) as Box<dyn Splitter<_>>,
+        "line" => Box::new(LineSplitter) as Box<dyn Splitter<_>>,
+        "syslen" => Box::new(SyslenSplitter) as Box<dyn Splitter<_>>,
+        "nul" => Box::new(NulSplitter) as Box<dyn Splitter<_>>,
+        "json-seq" => Box::new(JsonSeqSplitter) as Box<dyn Splitter<_>>,
+        "delimiter" => {
+            Box::new(DelimiterSplitter::new(tcp_config.framing_delimiter)) as Box<dyn Splitter<_>>
+        }
         _ => panic!("Unsupported framing scheme"),
     };
     splitter.run(reader, tx, decoder, encoder);
@@ -0,0 +1,191 @@
+use crate::flowgger::config::Config;
+use openssl::hash::MessageDigest;
+use openssl::x509::X509Ref;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{stderr, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+const SHA256_LEN: usize = 32;
+
+/// Settings of the allowed client certificates, in the `[input]` section
+#[derive(Deserialize)]
+pub struct FingerprintsSettings {
+    tls_allowed_fingerprints: Option<Vec<String>>,
+    tls_allowed_fingerprints_file: Option<PathBuf>,
+}
+
+/// SHA-256 fingerprints of the client certificates allowed to connect.
+/// The fingerprints file is read again whenever it changes, so that devices can be added or revoked
+/// without restarting.
+pub struct AllowedFingerprints {
+    fingerprints: HashSet<Vec<u8>>,
+    path: Option<PathBuf>,
+    file: Mutex<FingerprintsFile>,
+}
+
+#[derive(Default)]
+struct FingerprintsFile {
+    modified: Option<SystemTime>,
+    fingerprints: HashSet<Vec<u8>>,
+}
+
+impl AllowedFingerprints {
+    /// # Parameters
+    /// - 'input.tls_allowed_fingerprints': Optional. List of SHA-256 fingerprints, as hex strings with
+    ///   or without colons.
+    /// - 'input.tls_allowed_fingerprints_file': Optional. File with a fingerprint per line, "#" starting
+    ///   a comment.
+    pub fn from_config(config: &Config) -> Option<AllowedFingerprints> {
+        let settings: FingerprintsSettings = config.settings("input");
+        let fingerprints = settings.tls_allowed_fingerprints.map(|fingerprints| {
+            fingerprints
+                .iter()
+                .map(|fingerprint| {
+                    parse_fingerprint(fingerprint).expect(
+                        "input.tls_allowed_fingerprints must be a list of SHA-256 fingerprints",
+                    )
+                })
+                .collect()
+        });
+        let path = settings.tls_allowed_fingerprints_file;
+        if fingerprints.is_none() && path.is_none() {
+            return None;
+        }
+        let allowed = AllowedFingerprints {
+            fingerprints: fingerprints.unwrap_or_default(),
+            path,
+            file: Mutex::new(FingerprintsFile::default()),
+        };
+        if let Some(path) = &allowed.path {
+            if let Err(e) = fs::metadata(path) {
+                panic!(
+                    "Unable to read input.tls_allowed_fingerprints_file [{}]: {}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+        Some(allowed)
+    }
+
+    pub fn is_allowed(&self, cert: &X509Ref) -> bool {
+        match cert.digest(MessageDigest::sha256()) {
+            Ok(digest) => self.contains(&digest),
+            Err(_) => false,
+        }
+    }
+
+    fn contains(&self, digest: &[u8]) -> bool {
+        if self.fingerprints.contains(digest) {
+            return true;
+        }
+        let path = match &self.path {
+            None => return false,
+            Some(path) => path,
+        };
+        let mut file = self.file.lock().unwrap();
+        let res = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .and_then(|modified| {
+                if file.modified != Some(modified) {
+                    file.fingerprints = parse_fingerprints(&fs::read_to_string(path)?);
+                    file.modified = Some(modified);
+                }
+                Ok(())
+            });
+        if let Err(e) = res {
+            let _ = writeln!(
+                stderr(),
+                "Unable to read the allowed fingerprints [{}]: {}",
+                path.display(),
+                e
+            );
+        }
+        file.fingerprints.contains(digest)
+    }
+}
+
+fn parse_fingerprints(content: &str) -> HashSet<Vec<u8>> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| {
+            let fingerprint = parse_fingerprint(line);
+            if fingerprint.is_none() {
+                let _ = writeln!(stderr(), "Invalid SHA-256 fingerprint: [{}]", line);
+            }
+            fingerprint
+        })
+        .collect()
+}
+
+/// Parse a hex SHA-256 fingerprint, i.e. as printed by `openssl x509 -fingerprint -sha256`
+fn parse_fingerprint(fingerprint: &str) -> Option<Vec<u8>> {
+    let hex: Vec<u8> = fingerprint
+        .bytes()
+        .filter(|&c| c != b':' && !c.is_ascii_whitespace())
+        .collect();
+    if hex.len() != SHA256_LEN * 2 {
+        return None;
+    }
+    hex.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::time::Duration;
+    use tempdir::TempDir;
+
+    const FINGERPRINT_A: &str =
+        "AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89";
+    const FINGERPRINT_B: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+
+    #[test]
+    fn test_parse_fingerprint() {
+        let fingerprint = parse_fingerprint(FINGERPRINT_A).unwrap();
+        assert_eq!(fingerprint.len(), SHA256_LEN);
+        assert_eq!(&fingerprint[..3], &[0xab, 0xcd, 0xef]);
+        assert_eq!(
+            parse_fingerprint(&FINGERPRINT_A.to_lowercase()),
+            Some(fingerprint)
+        );
+        assert_eq!(parse_fingerprint("AB:CD"), None);
+        assert_eq!(parse_fingerprint(&FINGERPRINT_B.replace('1', "g")), None);
+    }
+
+    #[test]
+    fn test_allowed_fingerprints_file_reload() {
+        let temp_dir = TempDir::new("test_allowed_fingerprints").unwrap();
+        let path = temp_dir.path().join("fingerprints");
+        fs::write(&path, format!("# device 1\n{}\n", FINGERPRINT_A)).unwrap();
+        let config = Config::from_string(&format!(
+            "[input]\ntls_allowed_fingerprints = [\"{}\"]\ntls_allowed_fingerprints_file = \"{}\"\n",
+            FINGERPRINT_B,
+            path.display()
+        ))
+        .unwrap();
+        let allowed = AllowedFingerprints::from_config(&config).unwrap();
+        let (a, b) = (
+            parse_fingerprint(FINGERPRINT_A).unwrap(),
+            parse_fingerprint(FINGERPRINT_B).unwrap(),
+        );
+        assert!(allowed.contains(&a));
+        assert!(allowed.contains(&b));
+
+        fs::write(&path, "").unwrap();
+        let file = File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert!(!allowed.contains(&a));
+        assert!(allowed.contains(&b));
+    }
+}
@@ -1,13 +1,27 @@
+use self::fingerprints::AllowedFingerprints;
 use crate::flowgger::config::Config;
+use crate::flowgger::decoder::{PeerStats, SequenceDedup};
+use crate::flowgger::input::connection_events::ConnectionEvents;
+use crate::flowgger::input::decompress::Decompression;
+use crate::flowgger::input::listen::Listen;
+use crate::flowgger::splitter::framing_delimiter;
+use crate::flowgger::utils::tls::{
+    session_resumption, set_protocol_options, set_server_session_resumption,
+};
 use openssl::bn::BigNum;
 use openssl::dh::Dh;
 use openssl::ssl::*;
-use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use std::io::{stderr, Write};
+use std::path::PathBuf;
 
+mod fingerprints;
 pub mod tls_input;
 #[cfg(feature = "coroutines")]
 pub mod tlsco_input;
 
+#[cfg(test)]
+pub use self::fingerprints::FingerprintsSettings;
 pub use super::Input;
 
 const DEFAULT_CERT: &str = "flowgger.pem";
@@ -31,9 +45,33 @@ const DEFAULT_TLS_COMPATIBILITY_LEVEL: &str = "default";
 const DEFAULT_VERIFY_PEER: bool = false;
 const TLS_VERIFY_DEPTH: u32 = 6;
 
+/// Settings of the TLS inputs, in the `[input]` section
+#[derive(Deserialize)]
+pub struct TlsSettings {
+    #[cfg_attr(not(feature = "coroutines"), allow(dead_code))]
+    tls_threads: Option<usize>,
+    timeout: Option<u64>,
+    framed: Option<bool>,
+    framing: Option<String>,
+    framing_delimiter: Option<String>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    tls_ciphers: Option<String>,
+    tls_compatibility_level: Option<String>,
+    tls_verify_peer: Option<bool>,
+    tls_ca_file: Option<PathBuf>,
+    tls_compression: Option<bool>,
+}
+
 #[derive(Clone)]
 pub struct TlsConfig {
     framing: String,
+    framing_delimiter: Vec<u8>,
+    decompression: Decompression,
+    dedup: SequenceDedup,
+    peer_stats: PeerStats,
+    events: Option<ConnectionEvents>,
+    #[cfg_attr(not(feature = "coroutines"), allow(dead_code))]
     threads: usize,
     acceptor: SslAcceptor,
 }
@@ -49,57 +87,61 @@ fn set_fs(ctx: &mut SslContextBuilder) {
 }
 
 #[cfg(feature = "coroutines")]
-fn get_default_threads(config: &Config) -> usize {
-    config
-        .lookup("input.tls_threads")
-        .map_or(DEFAULT_THREADS, |x| {
-            x.as_integer()
-                .expect("input.tls_threads must be an unsigned integer") as usize
-        })
+fn get_default_threads(settings: &TlsSettings) -> usize {
+    settings.tls_threads.unwrap_or(DEFAULT_THREADS)
 }
 
 #[cfg(not(feature = "coroutines"))]
-fn get_default_threads(_config: &Config) -> usize {
+fn get_default_threads(_settings: &TlsSettings) -> usize {
     1
 }
 
-pub fn config_parse(config: &Config) -> (TlsConfig, String, u64) {
-    let listen = config
-        .lookup("input.listen")
-        .map_or(DEFAULT_LISTEN, |x| {
-            x.as_str().expect("input.listen must be an ip:port string")
-        })
-        .to_owned();
-    let threads = get_default_threads(config);
-    let cert = config
-        .lookup("input.tls_cert")
-        .map_or(DEFAULT_CERT, |x| {
-            x.as_str()
-                .expect("input.tls_cert must be a path to a .pem file")
-        })
-        .to_owned();
-    let key = config
-        .lookup("input.tls_key")
-        .map_or(DEFAULT_KEY, |x| {
-            x.as_str()
-                .expect("input.tls_key must be a path to a .pem file")
-        })
-        .to_owned();
-    let ciphers = config
-        .lookup("input.tls_ciphers")
-        .map_or(DEFAULT_CIPHERS, |x| {
-            x.as_str()
-                .expect("input.tls_ciphers must be a string with a cipher suite")
-        })
-        .to_owned();
+pub fn config_parse(config: &Config) -> (TlsConfig, Listen, u64) {
+    let settings: TlsSettings = config.settings("input");
+    let listen = Listen::new(config, DEFAULT_LISTEN);
+    let threads = get_default_threads(&settings);
+    let timeout = settings.timeout.unwrap_or(DEFAULT_TIMEOUT);
+    let framing = if settings.framed == Some(true) {
+        "syslen"
+    } else if settings.framing_delimiter.is_some() {
+        "delimiter"
+    } else {
+        DEFAULT_FRAMING
+    };
+    let framing = settings.framing.unwrap_or_else(|| framing.to_owned());
+    let framing_delimiter = framing_delimiter(config);
+    let decompression = Decompression::from_config(config);
+    let acceptor = acceptor(config);
+    let tls_config = TlsConfig {
+        framing,
+        framing_delimiter,
+        decompression,
+        dedup: SequenceDedup::new(config),
+        peer_stats: PeerStats::new(config),
+        events: ConnectionEvents::new(config, "tls"),
+        threads,
+        acceptor,
+    };
+    (tls_config, listen, timeout)
+}
+
+/// Acceptor of the TLS connections, shared with the TCP input for the connections upgraded with STARTTLS
+pub fn acceptor(config: &Config) -> SslAcceptor {
+    let settings: TlsSettings = config.settings("input");
+    let cert = settings
+        .tls_cert
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CERT));
+    let key = settings
+        .tls_key
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_KEY));
+    let ciphers = settings
+        .tls_ciphers
+        .unwrap_or_else(|| DEFAULT_CIPHERS.to_owned());
 
-    let tls_modern = match config
-        .lookup("input.tls_compatibility_level")
-        .map_or(DEFAULT_TLS_COMPATIBILITY_LEVEL, |x| {
-            x.as_str().expect(
-                "input.tls_compatibility_level must be a string with the comptibility level",
-            )
-        })
+    let tls_modern = match settings
+        .tls_compatibility_level
+        .as_deref()
+        .unwrap_or(DEFAULT_TLS_COMPATIBILITY_LEVEL)
         .to_lowercase()
         .as_ref()
     {
@@ -107,41 +149,18 @@ pub fn config_parse(config: &Config) -> (TlsConfig, String, u64) {
         "modern" => true,
         _ => panic!(r#"TLS compatibility level must be "intermediate" or "modern""#),
     };
-    let verify_peer = config
-        .lookup("input.tls_verify_peer")
-        .map_or(DEFAULT_VERIFY_PEER, |x| {
-            x.as_bool()
-                .expect("input.tls_verify_peer must be a boolean")
-        });
-    let ca_file: Option<PathBuf> = config.lookup("input.tls_ca_file").and_then(|x| {
-        Some(PathBuf::from(
-            x.as_str()
-                .expect("input.tls_ca_file must be a path to a file"),
-        ))
-    });
-    let compression = config
-        .lookup("input.tls_compression")
-        .map_or(DEFAULT_COMPRESSION, |x| {
-            x.as_bool()
-                .expect("input.tls_compression must be a boolean")
-        });
-    let timeout = config.lookup("input.timeout").map_or(DEFAULT_TIMEOUT, |x| {
-        x.as_integer().expect("input.timeout must be an integer") as u64
-    });
-    let framing = if config.lookup("input.framed").map_or(false, |x| {
-        x.as_bool().expect("input.framed must be a boolean")
-    }) {
-        "syslen"
-    } else {
-        DEFAULT_FRAMING
-    };
-    let framing = config
-        .lookup("input.framing")
-        .map_or(framing, |x| {
-            x.as_str()
-                .expect(r#"input.framing must be a string set to "line", "nul" or "syslen""#)
-        })
-        .to_owned();
+    let verify_peer = settings.tls_verify_peer.unwrap_or(DEFAULT_VERIFY_PEER);
+    let ca_file = settings.tls_ca_file;
+    let allowed_fingerprints = AllowedFingerprints::from_config(config);
+    if allowed_fingerprints.is_some() && !verify_peer {
+        panic!("input.tls_allowed_fingerprints requires input.tls_verify_peer to be enabled");
+    }
+    let compression = settings.tls_compression.unwrap_or(DEFAULT_COMPRESSION);
+    let session_resumption = session_resumption(config, "input");
+    if allowed_fingerprints.is_some() && session_resumption {
+        // Resumed handshakes don't go through the verify callback, revoked clients would keep connecting
+        panic!("input.tls_session_resumption can't be enabled along with input.tls_allowed_fingerprints");
+    }
     let mut acceptor_builder = (if tls_modern {
         SslAcceptor::mozilla_modern(SslMethod::tls())
     } else {
@@ -149,7 +168,7 @@ pub fn config_parse(config: &Config) -> (TlsConfig, String, u64) {
     })
     .unwrap();
     {
-        let mut ctx = &mut acceptor_builder;
+        let ctx = &mut acceptor_builder;
         if let Some(ca_file) = ca_file {
             ctx.set_ca_file(&ca_file)
                 .expect("Unable to read the trusted CA file");
@@ -158,7 +177,26 @@ pub fn config_parse(config: &Config) -> (TlsConfig, String, u64) {
             ctx.set_verify(SslVerifyMode::NONE);
         } else {
             ctx.set_verify_depth(TLS_VERIFY_DEPTH);
-            ctx.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+            let mode = SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT;
+            match allowed_fingerprints {
+                None => ctx.set_verify(mode),
+                Some(allowed) => ctx.set_verify_callback(mode, move |preverify_ok, x509_ctx| {
+                    // Only the client certificate itself has to be in the list, not its issuers
+                    if !preverify_ok || x509_ctx.error_depth() != 0 {
+                        return preverify_ok;
+                    }
+                    let allowed = x509_ctx
+                        .current_cert()
+                        .is_some_and(|cert| allowed.is_allowed(cert));
+                    if !allowed {
+                        let _ = writeln!(
+                            stderr(),
+                            "Client certificate rejected: fingerprint not allowed"
+                        );
+                    }
+                    allowed
+                }),
+            }
         }
         let mut opts = SslOptions::CIPHER_SERVER_PREFERENCE
             | SslOptions::NO_SESSION_RESUMPTION_ON_RENEGOTIATION;
@@ -166,19 +204,67 @@ pub fn config_parse(config: &Config) -> (TlsConfig, String, u64) {
             opts |= SslOptions::NO_COMPRESSION;
         }
         ctx.set_options(opts);
-        set_fs(&mut ctx);
-        ctx.set_certificate_chain_file(&Path::new(&cert))
+        set_fs(ctx);
+        ctx.set_certificate_chain_file(&cert)
             .expect("Unable to read the TLS certificate chain");
-        ctx.set_private_key_file(&Path::new(&key), SslFiletype::PEM)
+        ctx.set_private_key_file(&key, SslFiletype::PEM)
             .expect("Unable to read the TLS key");
         ctx.set_cipher_list(&ciphers)
             .expect("Unsupported cipher suite");
+        set_protocol_options(ctx, config, "input");
+        set_server_session_resumption(ctx, session_resumption);
+    }
+    acceptor_builder.build()
+}
+
+/// Settings of the TLS inputs, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "# Address, or list of addresses, to listen on\n\
+         listen = \"{}\"\n\
+         # Records separated by line breaks, or \"nul\", \"syslen\", \"delimiter\", \"json-seq\" or \"auto\" framing\n\
+         # framing = \"{}\"\n\
+         # Seconds after which idle connections are closed\n\
+         # timeout = {}\n\
+         # Certificate and key of the server, in the PEM format\n\
+         tls_cert = \"{}\"\n\
+         tls_key = \"{}\"\n\
+         # Require client certificates signed by this CA\n\
+         # tls_verify_peer = {}\n\
+         # tls_ca_file = \"ca.pem\"\n\
+         # tls_compatibility_level = \"{}\"\n\
+         # tls_compression = {}\n\
+         # tls_ciphers = \"{}\"\n",
+        DEFAULT_LISTEN,
+        DEFAULT_FRAMING,
+        DEFAULT_TIMEOUT,
+        DEFAULT_CERT,
+        DEFAULT_KEY,
+        DEFAULT_VERIFY_PEER,
+        DEFAULT_TLS_COMPATIBILITY_LEVEL,
+        DEFAULT_COMPRESSION,
+        DEFAULT_CIPHERS
+    )
+}
+
+/// Settings of the TLS input using coroutines, for `flowgger config init`
+#[cfg(feature = "coroutines")]
+pub fn sample_config_co() -> String {
+    format!("{}# tls_threads = {}\n", sample_config(), DEFAULT_THREADS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "input.tls_session_resumption can't be enabled")]
+    fn test_session_resumption_with_allowed_fingerprints() {
+        let config = Config::from_string(
+            "[input]\ntls_verify_peer = true\ntls_session_resumption = true\n\
+             tls_allowed_fingerprints = [\"AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89\"]\n",
+        )
+        .unwrap();
+        acceptor(&config);
     }
-    let acceptor = acceptor_builder.build();
-    let tls_config = TlsConfig {
-        framing,
-        threads,
-        acceptor,
-    };
-    (tls_config, listen, timeout)
 }
@@ -1,19 +1,27 @@
 use super::*;
 use crate::flowgger::config::Config;
+use crate::flowgger::daemon;
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
-#[cfg(feature = "capnp-recompile")]
+use crate::flowgger::input::connection_events::ConnectionEvent;
+use crate::flowgger::input::listen::{self, Listen};
+use crate::flowgger::input::INPUT_BUFFER_SIZE;
+use crate::flowgger::record_queue::RecordSender;
+#[cfg(feature = "capnp")]
 use crate::flowgger::splitter::CapnpSplitter;
-use crate::flowgger::splitter::{LineSplitter, NulSplitter, Splitter, SyslenSplitter};
+use crate::flowgger::splitter::{
+    AutoSplitter, DelimiterSplitter, JsonSeqSplitter, LineSplitter, NulSplitter, Splitter,
+    SyslenSplitter,
+};
+use crate::flowgger::utils::threads::{self, CpuAffinity};
 use std::io::{stderr, BufReader, Write};
-use std::net::{TcpListener, TcpStream};
-use std::sync::mpsc::SyncSender;
-use std::thread;
+use std::net::TcpStream;
 use std::time::Duration;
 
 pub struct TlsInput {
-    listen: String,
+    listen: Listen,
     timeout: Option<Duration>,
+    affinity: CpuAffinity,
     tls_config: TlsConfig,
 }
 
@@ -24,6 +32,7 @@ impl TlsInput {
             listen,
             tls_config,
             timeout: Some(Duration::from_secs(timeout)),
+            affinity: CpuAffinity::new(config, "input"),
         }
     }
 }
@@ -31,26 +40,29 @@ impl TlsInput {
 impl Input for TlsInput {
     fn accept(
         &self,
-        tx: SyncSender<Vec<u8>>,
+        tx: RecordSender,
         decoder: Box<dyn Decoder + Send>,
         encoder: Box<dyn Encoder + Send>,
     ) {
-        let listener = TcpListener::bind(&self.listen as &str).unwrap();
-        for client in listener.incoming() {
-            if let Ok(client) = client {
-                let _ = client.set_read_timeout(self.timeout);
-                let tx = tx.clone();
-                let (decoder, encoder) = (decoder.clone_boxed(), encoder.clone_boxed());
-                let tls_config = self.tls_config.clone();
-                thread::spawn(move || {
-                    handle_client(client, tx, decoder, encoder, tls_config);
-                });
-            }
+        let listeners = self.listen.tcp_listeners();
+        daemon::listening();
+        for (i, client) in listen::incoming(listeners).iter().enumerate() {
+            let _ = client.set_read_timeout(self.timeout);
+            let tx = tx.clone();
+            let peer = client.peer_addr().ok().map(|addr| addr.ip());
+            let decoder = self.tls_config.peer_stats.wrap(peer, decoder.clone_boxed());
+            let decoder = self.tls_config.dedup.wrap(peer, decoder);
+            let encoder = encoder.clone_boxed();
+            let tls_config = self.tls_config.clone();
+            let name = format!("flowgger-input-tls-{}", i);
+            threads::spawn(name, self.affinity.cpu(i), move || {
+                handle_client(client, tx, decoder, encoder, tls_config);
+            });
         }
     }
 }
 
-#[cfg(feature = "capnp-recompile")]
+#[cfg(feature = "capnp")]
 pub fn get_capnp_splitter<T>() -> Box<dyn Splitter<T>>
 where
     T: std::io::Read,
@@ -58,34 +70,71 @@ where
     Box::new(CapnpSplitter) as Box<dyn Splitter<_>>
 }
 
-#[cfg(not(feature = "capnp-recompile"))]
+#[cfg(not(feature = "capnp"))]
 pub fn get_capnp_splitter() -> ! {
     panic!("Support for CapNProto is not compiled in")
 }
 
 fn handle_client(
     client: TcpStream,
-    tx: SyncSender<Vec<u8>>,
+    tx: RecordSender,
     decoder: Box<dyn Decoder>,
     encoder: Box<dyn Encoder>,
     tls_config: TlsConfig,
 ) {
-    if let Ok(peer_addr) = client.peer_addr() {
+    let peer_addr = client.peer_addr().ok();
+    if let Some(peer_addr) = peer_addr {
         println!("Connection over TLS from [{}]", peer_addr);
     }
+    let events = tls_config
+        .events
+        .clone()
+        .map(|events| (events, tx.clone(), encoder.clone_boxed()));
     let sslclient = match tls_config.acceptor.accept(client) {
-        Err(_) => {
+        Err(e) => {
             let _ = writeln!(stderr(), "SSL handshake aborted by the client");
+            if let Some((events, tx, encoder)) = events {
+                let reason = ConnectionEvent::HandshakeFailed(e.to_string());
+                events.emit(reason, peer_addr, &tx, &*encoder);
+            }
             return;
         }
         Ok(sslclient) => sslclient,
     };
-    let reader = BufReader::new(sslclient);
+    if let Some((ref events, ref tx, ref encoder)) = events {
+        events.emit(ConnectionEvent::Connected, peer_addr, tx, &**encoder);
+    }
+    read_client(sslclient, tx, decoder, encoder, tls_config);
+    if let Some((events, tx, encoder)) = events {
+        events.emit(ConnectionEvent::Disconnected, peer_addr, &tx, &*encoder);
+    }
+}
+
+fn read_client(
+    sslclient: SslStream<TcpStream>,
+    tx: RecordSender,
+    decoder: Box<dyn Decoder>,
+    encoder: Box<dyn Encoder>,
+    tls_config: TlsConfig,
+) {
+    let stream = match tls_config.decompression.reader(sslclient) {
+        Ok(stream) => stream,
+        Err(e) => {
+            let _ = writeln!(stderr(), "Unable to decompress the stream: {}", e);
+            return;
+        }
+    };
+    let reader = BufReader::with_capacity(INPUT_BUFFER_SIZE, stream);
     let splitter = match &tls_config.framing as &str {
         "capnp" => get_capnp_splitter(),
+        "auto" => Box::new(AutoSplitter) as Box<dyn Splitter<_>>,
         "line" => Box::new(LineSplitter) as Box<dyn Splitter<_>>,
         "syslen" => Box::new(SyslenSplitter) as Box<dyn Splitter<_>>,
         "nul" => Box::new(NulSplitter) as Box<dyn Splitter<_>>,
+        "json-seq" => Box::new(JsonSeqSplitter) as Box<dyn Splitter<_>>,
+        "delimiter" => {
+            Box::new(DelimiterSplitter::new(tls_config.framing_delimiter)) as Box<dyn Splitter<_>>
+        }
         _ => panic!("Unsupported framing scheme"),
     };
     splitter.run(reader, tx, decoder, encoder);
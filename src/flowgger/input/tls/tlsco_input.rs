@@ -1,23 +1,26 @@
 use super::*;
 use crate::flowgger::config::Config;
+use crate::flowgger::daemon;
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
+use crate::flowgger::input::listen::Listen;
+use crate::flowgger::record_queue::RecordSender;
 use crate::flowgger::splitter::{
-    CapnpSplitter, LineSplitter, NulSplitter, Splitter, SyslenSplitter,
+    AutoSplitter, CapnpSplitter, DelimiterSplitter, JsonSeqSplitter, LineSplitter, NulSplitter,
+    Splitter, SyslenSplitter,
 };
 use may::net::{TcpListener, TcpStream};
 use std::io::{stderr, BufReader, Write};
 use std::net::SocketAddr;
-use std::sync::mpsc::SyncSender;
 
 pub struct TlsCoInput {
-    listen: String,
+    listen: Listen,
     tls_config: TlsConfig,
 }
 
 impl TlsCoInput {
     pub fn new(config: &Config) -> TlsCoInput {
-        let (tls_config, listen, _timeout) = config_parse(&config);
+        let (tls_config, listen, _timeout) = config_parse(config);
         TlsCoInput { listen, tls_config }
     }
 }
@@ -25,19 +28,23 @@ impl TlsCoInput {
 impl Input for TlsCoInput {
     fn accept(
         &self,
-        tx: SyncSender<Vec<u8>>,
-        decoder: Box<Decoder + Send>,
-        encoder: Box<Encoder + Send>,
+        tx: RecordSender,
+        decoder: Box<dyn Decoder + Send>,
+        encoder: Box<dyn Encoder + Send>,
     ) {
         let tls_config = self.tls_config.clone();
-        may::config().set_io_workers(tls_config.threads);
+        may::config().set_workers(tls_config.threads);
 
-        let listen: SocketAddr = self.listen.parse().unwrap();
-        let listener = TcpListener::bind(&listen).unwrap();
+        let listen: SocketAddr = self.listen.single();
+        let listener = TcpListener::bind(listen).unwrap();
+        daemon::listening();
 
-        while let Ok((socket, _)) = listener.accept() {
+        while let Ok((socket, peer)) = listener.accept() {
             let tx = tx.clone();
-            let (decoder, encoder) = (decoder.clone_boxed(), encoder.clone_boxed());
+            let peer = Some(peer.ip());
+            let decoder = tls_config.peer_stats.wrap(peer, decoder.clone_boxed());
+            let decoder = tls_config.dedup.wrap(peer, decoder);
+            let encoder = encoder.clone_boxed();
             let tls_config = tls_config.clone();
             go!(move || {
                 handle_client(socket, tx, decoder, encoder, tls_config);
@@ -48,9 +55,9 @@ impl Input for TlsCoInput {
 
 fn handle_client(
     client: TcpStream,
-    tx: SyncSender<Vec<u8>>,
-    decoder: Box<Decoder>,
-    encoder: Box<Encoder>,
+    tx: RecordSender,
+    decoder: Box<dyn Decoder>,
+    encoder: Box<dyn Encoder>,
     tls_config: TlsConfig,
 ) {
     if let Ok(peer_addr) = client.peer_addr() {
@@ -63,12 +70,24 @@ fn handle_client(
         }
         Ok(sslclient) => sslclient,
     };
-    let reader = BufReader::new(sslclient);
+    let stream = match tls_config.decompression.reader(sslclient) {
+        Ok(stream) => stream,
+        Err(e) => {
+            let _ = writeln!(stderr(), "Unable to decompress the stream: {}", e);
+            return;
+        }
+    };
+    let reader = BufReader::new(stream);
     let splitter = match &tls_config.framing as &str {
-        "capnp" => Box::new(CapnpSplitter) as Box<Splitter<_>>,
-        "line" => Box::new(LineSplitter) as Box<Splitter<_>>,
-        "syslen" => Box::new(SyslenSplitter) as Box<Splitter<_>>,
-        "nul" => Box::new(NulSplitter) as Box<Splitter<_>>,
+        "capnp" => Box::new(CapnpSplitter) as Box<dyn Splitter<_>>,
+        "auto" => Box::new(AutoSplitter) as Box<dyn Splitter<_>>,
+        "line" => Box::new(LineSplitter) as Box<dyn Splitter<_>>,
+        "syslen" => Box::new(SyslenSplitter) as Box<dyn Splitter<_>>,
+        "nul" => Box::new(NulSplitter) as Box<dyn Splitter<_>>,
+        "json-seq" => Box::new(JsonSeqSplitter) as Box<dyn Splitter<_>>,
+        "delimiter" => {
+            Box::new(DelimiterSplitter::new(tls_config.framing_delimiter)) as Box<dyn Splitter<_>>
+        }
         _ => panic!("Unsupported framing scheme"),
     };
     splitter.run(reader, tx, decoder, encoder);
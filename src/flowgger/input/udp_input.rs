@@ -1,17 +1,27 @@
 use super::Input;
 use crate::flowgger::config::Config;
-use crate::flowgger::decoder::Decoder;
+use crate::flowgger::daemon;
+use crate::flowgger::decoder::{Decoder, DROPPED};
 use crate::flowgger::encoder::Encoder;
+use crate::flowgger::input::listen::Listen;
+use crate::flowgger::record_queue::RecordSender;
+use crate::flowgger::utils::threads;
 use flate2::read::{GzDecoder, ZlibDecoder};
-use std::io::{stderr, Read, Write};
-use std::net::SocketAddr;
+use std::cell::RefCell;
+use std::io::{self, stderr, Read, Write};
 use std::net::UdpSocket;
 use std::str;
-use std::sync::mpsc::SyncSender;
 
 const DEFAULT_LISTEN: &str = "0.0.0.0:514";
 const MAX_UDP_PACKET_SIZE: usize = 65_527;
 const MAX_COMPRESSION_RATIO: usize = 5;
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const DECOMPRESSION_BUFFER_SIZE: usize = MAX_UDP_PACKET_SIZE * MAX_COMPRESSION_RATIO;
+
+thread_local! {
+    /// Buffer the datagrams received by a thread are decompressed into, reused from one to the next
+    static DECOMPRESSED: RefCell<Vec<u8>> = RefCell::new(Vec::with_capacity(DECOMPRESSION_BUFFER_SIZE));
+}
 
 /// UDP input structure for flowgger
 /// It will receive messages from the network, decode them and reencoded them as configured
@@ -19,39 +29,31 @@ const MAX_COMPRESSION_RATIO: usize = 5;
 ///
 /// [`Config`]: ../config/struct.Config.html
 pub struct UdpInput {
-    listen: SocketAddr,
+    listen: Listen,
 }
 
 impl UdpInput {
     /// Attemps to create a new UdpInput instance by parsing the a Config object in the toml format
     /// the only field needed for this to work in input.listen, if input.listen is missing it will
-    /// bind itself to a default ip:port address `0.0.0.0:514`
+    /// bind itself to a default ip:port address `0.0.0.0:514`. input.listen can also be a list of
+    /// addresses, i.e. `["0.0.0.0:514", "[::]:514"]`, each with its own socket
     ///
     /// # Parameters
     /// `config`: Configuration object in toml format
     ///
     /// # Panic
-    /// `input.listen must be an ip:port string`:  input.listen is not parsable as a string
+    /// `input.listen must be an ip:port string or a list of them`:  input.listen is not parsable as a string
     /// `Unable to parse ip:port string from input.listen` input.listen is not a valid ip:port
     pub fn new(config: &Config) -> UdpInput {
-        let listen = config
-            .lookup("input.listen")
-            .map_or(DEFAULT_LISTEN, |x| {
-                x.as_str().expect("input.listen must be an ip:port string")
-            })
-            .to_owned();
-        let bind_address: SocketAddr = listen
-            .parse()
-            .expect("unable to parse ip:port string from input.listen");
         UdpInput {
-            listen: bind_address,
+            listen: Listen::new(config, DEFAULT_LISTEN),
         }
     }
 }
 
 impl Input for UdpInput {
-    /// Bind a [`UdpSocket`][] to the configured listen address and starts a loop for accepting
-    /// incoming upd packets
+    /// Bind a [`UdpSocket`][] to every configured listen address and starts a loop for accepting
+    /// incoming upd packets on each of them
     ///
     /// [`UdpSocket`]: https://doc.rust-lang.org/std/net/struct.UdpSocket.html
     ///
@@ -65,92 +67,163 @@ impl Input for UdpInput {
     /// permissions are insufficent to open the specified socket
     fn accept(
         &self,
-        tx: SyncSender<Vec<u8>>,
+        tx: RecordSender,
         decoder: Box<dyn Decoder + Send>,
         encoder: Box<dyn Encoder + Send>,
     ) {
-        let socket = UdpSocket::bind(&self.listen)
-            .unwrap_or_else(|_| panic!("Unable to listen to {}", self.listen));
-        let tx = tx.clone();
-        let (decoder, encoder): (Box<dyn Decoder>, Box<dyn Encoder>) =
-            (decoder.clone_boxed(), encoder.clone_boxed());
-        let mut buf = [0; MAX_UDP_PACKET_SIZE];
-        loop {
-            let (length, _src) = match socket.recv_from(&mut buf) {
-                Ok(res) => res,
-                Err(_) => continue,
-            };
-            let line = &buf[..length];
-            if let Err(e) = handle_record_maybe_compressed(line, &tx, &decoder, &encoder) {
+        let mut sockets = self.listen.udp_sockets();
+        daemon::listening();
+        let last = sockets.pop().expect("input.listen cannot be an empty list");
+        for (i, socket) in sockets.into_iter().enumerate() {
+            let tx = tx.clone();
+            let (decoder, encoder) = (decoder.clone_boxed(), encoder.clone_boxed());
+            threads::spawn(format!("flowgger-input-udp-{}", i), None, move || {
+                receive(socket, tx, decoder, encoder)
+            });
+        }
+        receive(last, tx, decoder, encoder);
+    }
+}
+
+fn receive(
+    socket: UdpSocket,
+    tx: RecordSender,
+    decoder: Box<dyn Decoder>,
+    encoder: Box<dyn Encoder>,
+) {
+    let mut buf = [0; MAX_UDP_PACKET_SIZE];
+    loop {
+        let (length, _src) = match socket.recv_from(&mut buf) {
+            Ok(res) => res,
+            Err(_) => continue,
+        };
+        let line = &buf[..length];
+        match handle_record_maybe_compressed(line, &tx, &*decoder, &*encoder) {
+            Err(e) if e != DROPPED => {
                 let _ = writeln!(stderr(), "{}", e);
             }
+            _ => {}
         }
     }
 }
 
-/// Handle a line that could be compressed in the Zlib or Gz format, uncompress it if compressed
+/// Handle a line that could be compressed in the Zlib, Gz or Zstd (with the "zstd" feature) format, uncompress it if compressed
 /// with a known algoritm and passed it to handle_record to decoded it from the input format to the
 /// output one and send it over for being sent in output
 ///
 /// # Errors
-/// `Corrupted compressed (gzip/zlib) record`: The record has been identified as a compressed record in a known format
+/// `Corrupted compressed (gzip/zlib/zstd) record`: The record has been identified as a compressed record in a known format
 /// but could not be handled
 /// `Invalid UTF-8 input`: Bubble up from handle_record, the record is not in a valid utf-8 format, it could be a non
 /// supported compression format
 pub fn handle_record_maybe_compressed(
     line: &[u8],
-    tx: &SyncSender<Vec<u8>>,
-    decoder: &Box<dyn Decoder>,
-    encoder: &Box<dyn Encoder>,
+    tx: &RecordSender,
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
 ) -> Result<(), &'static str> {
     if line.len() >= 8
         && (line[0] == 0x78 && (line[1] == 0x01 || line[1] == 0x9c || line[1] == 0xda))
     {
-        let mut decompressed = Vec::with_capacity(MAX_UDP_PACKET_SIZE * MAX_COMPRESSION_RATIO);
-        match ZlibDecoder::new(line).read_to_end(&mut decompressed) {
-            Ok(_) => handle_record(&decompressed, tx, decoder, encoder),
-            Err(_) => Err("Corrupted compressed (zlib) record"),
-        }
+        handle_decompressed(
+            |decompressed| ZlibDecoder::new(line).read_to_end(decompressed),
+            "Corrupted compressed (zlib) record",
+            tx,
+            decoder,
+            encoder,
+        )
     } else if line.len() >= 24 && (line[0] == 0x1f && line[1] == 0x8b && line[2] == 0x08) {
-        let mut decompressed = Vec::with_capacity(MAX_UDP_PACKET_SIZE * MAX_COMPRESSION_RATIO);
-        match GzDecoder::new(line).read_to_end(&mut decompressed) {
-            Ok(_) => handle_record(&decompressed, tx, decoder, encoder),
-            Err(_) => Err("Corrupted compressed (gzip) record"),
-        }
+        handle_decompressed(
+            |decompressed| GzDecoder::new(line).read_to_end(decompressed),
+            "Corrupted compressed (gzip) record",
+            tx,
+            decoder,
+            encoder,
+        )
+    } else if cfg!(feature = "zstd") && line.len() >= 9 && line[..4] == ZSTD_MAGIC {
+        handle_decompressed(
+            |decompressed| zstd_decompress(line, decompressed),
+            "Corrupted compressed (zstd) record",
+            tx,
+            decoder,
+            encoder,
+        )
     } else {
         handle_record(line, tx, decoder, encoder)
     }
 }
 
+/// Decompress a record into the buffer of the thread, and handle it
+///
+/// The buffer only keeps the size needed for a datagram at the maximum compression ratio, so that an
+/// unusually large record doesn't keep its memory allocated.
+fn handle_decompressed<F>(
+    decompress: F,
+    corrupted: &'static str,
+    tx: &RecordSender,
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
+) -> Result<(), &'static str>
+where
+    F: FnOnce(&mut Vec<u8>) -> io::Result<usize>,
+{
+    DECOMPRESSED.with(|decompressed| {
+        let mut decompressed = decompressed.borrow_mut();
+        let res = match decompress(&mut decompressed) {
+            Ok(_) => handle_record(&decompressed, tx, decoder, encoder),
+            Err(_) => Err(corrupted),
+        };
+        decompressed.clear();
+        decompressed.shrink_to(DECOMPRESSION_BUFFER_SIZE);
+        res
+    })
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_decompress(line: &[u8], decompressed: &mut Vec<u8>) -> io::Result<usize> {
+    zstd::stream::read::Decoder::new(line)?.read_to_end(decompressed)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_decompress(_line: &[u8], _decompressed: &mut Vec<u8>) -> io::Result<usize> {
+    Err(io::Error::other("Support for zstd is not compiled in"))
+}
+
 /// Decode a byte line in a valid utf-8 format, encodes it and sends it over throught a channel
 ///
 /// # Errors
 /// `Invalid UTF-8 input`: The record is not in a valid utf-8 format, it could be a non supported compression format
 fn handle_record(
     line: &[u8],
-    tx: &SyncSender<Vec<u8>>,
-    decoder: &Box<dyn Decoder>,
-    encoder: &Box<dyn Encoder>,
+    tx: &RecordSender,
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
 ) -> Result<(), &'static str> {
-    let line = match str::from_utf8(line) {
-        Err(_) => return Err("Invalid UTF-8 input"),
-        Ok(line) => line,
-    };
-    let decoded = decoder.decode(line)?;
+    let decoded = decoder.decode_bytes(line)?;
     let reencoded = encoder.encode(decoded)?;
     tx.send(reencoded).unwrap();
     Ok(())
 }
 
+/// Settings of the UDP input, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "# Address, or list of addresses, to listen on\n\
+         listen = \"{}\"\n",
+        DEFAULT_LISTEN
+    )
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::flowgger::config::Config;
     use crate::flowgger::get_decoder_rfc3164;
     use crate::flowgger::get_encoder_rfc3164;
+    use crate::flowgger::record_queue::{bounded, RecordReceiver};
     use flate2::write::{GzEncoder, ZlibEncoder};
     use flate2::Compression;
-    use std::sync::mpsc::{sync_channel, Receiver};
+    use std::net::SocketAddr;
 
     const DEFAULT_QUEUE_SIZE: usize = 10_000_000;
 
@@ -161,7 +234,7 @@ mod test {
             Config::from_string(format!("[input]\nlisten = \"{}\"", listen_ip).as_str()).unwrap();
         let input = UdpInput::new(&config);
         let listen_addr: SocketAddr = listen_ip.parse().unwrap();
-        assert_eq!(input.listen, listen_addr);
+        assert_eq!(input.listen.addrs, vec![listen_addr]);
     }
 
     #[test]
@@ -176,18 +249,20 @@ mod test {
         let config = Config::from_string("").unwrap();
         let input = UdpInput::new(&config);
         let default_addr: SocketAddr = DEFAULT_LISTEN.parse().unwrap();
-        assert_eq!(input.listen, default_addr);
+        assert_eq!(input.listen.addrs, vec![default_addr]);
     }
 
-    fn handle_record_set_up() -> (
+    type HandleRecordSetUp = (
         &'static str,
-        SyncSender<Vec<u8>>,
-        Receiver<Vec<u8>>,
+        RecordSender,
+        RecordReceiver,
         Box<dyn Decoder>,
         Box<dyn Encoder>,
-    ) {
+    );
+
+    fn handle_record_set_up() -> HandleRecordSetUp {
         let line = "Aug  6 11:15:24 testhostname appname 69 42 [origin@123 software=\"te\\st sc\"ript\" swVersion=\"0.0.1\"] test message";
-        let (tx, rx): (SyncSender<Vec<u8>>, Receiver<Vec<u8>>) = sync_channel(DEFAULT_QUEUE_SIZE);
+        let (tx, rx): (RecordSender, RecordReceiver) = bounded(DEFAULT_QUEUE_SIZE);
         let config = Config::from_string("").unwrap();
         let encoder = get_encoder_rfc3164(&config);
         let decoder = get_decoder_rfc3164(&config);
@@ -199,7 +274,7 @@ mod test {
     #[test]
     fn test_udp_input_handle_record_uncompressed() {
         let (line, tx, rx, decoder, encoder) = handle_record_set_up();
-        handle_record_maybe_compressed(line.as_bytes(), &tx, &decoder, &encoder).unwrap();
+        handle_record_maybe_compressed(line.as_bytes(), &tx, &*decoder, &*encoder).unwrap();
         let transmitted = rx.recv().unwrap();
         assert_eq!(str::from_utf8(&transmitted).unwrap(), line);
     }
@@ -213,7 +288,7 @@ mod test {
             Err(e) => panic!("Compressing line {}, raised Error {:?}", line, e),
         }
         let compressed_line = compressor.finish().unwrap();
-        handle_record_maybe_compressed(&compressed_line, &tx, &decoder, &encoder).unwrap();
+        handle_record_maybe_compressed(&compressed_line, &tx, &*decoder, &*encoder).unwrap();
         let transmitted = rx.recv().unwrap();
         assert_eq!(str::from_utf8(&transmitted).unwrap(), line);
     }
@@ -227,11 +302,37 @@ mod test {
             Err(e) => panic!("Compressing line {}, raised Error {:?}", line, e),
         }
         let compressed_line = compressor.finish().unwrap();
-        handle_record_maybe_compressed(&compressed_line, &tx, &decoder, &encoder).unwrap();
+        handle_record_maybe_compressed(&compressed_line, &tx, &*decoder, &*encoder).unwrap();
         let transmitted = rx.recv().unwrap();
         assert_eq!(str::from_utf8(&transmitted).unwrap(), line);
     }
 
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_handle_record_compressed_zstd() {
+        let (line, tx, rx, decoder, encoder) = handle_record_set_up();
+        let compressed_line = zstd::stream::encode_all(line.as_bytes(), 0).unwrap();
+        handle_record_maybe_compressed(&compressed_line, &tx, &*decoder, &*encoder).unwrap();
+        let transmitted = rx.recv().unwrap();
+        assert_eq!(str::from_utf8(&transmitted).unwrap(), line);
+    }
+
+    #[test]
+    fn test_handle_record_decompression_buffer() {
+        let (line, tx, rx, decoder, encoder) = handle_record_set_up();
+        let large_line = format!("{} {}", line, "x".repeat(DECOMPRESSION_BUFFER_SIZE));
+        for line in [line.to_owned(), large_line, line.to_owned()] {
+            let mut compressor = ZlibEncoder::new(Vec::new(), Compression::default());
+            compressor.write_all(line.as_bytes()).unwrap();
+            let compressed_line = compressor.finish().unwrap();
+            handle_record_maybe_compressed(&compressed_line, &tx, &*decoder, &*encoder).unwrap();
+            assert_eq!(str::from_utf8(&rx.recv().unwrap()).unwrap(), line);
+            DECOMPRESSED.with(|decompressed| {
+                assert!(decompressed.borrow().capacity() <= DECOMPRESSION_BUFFER_SIZE);
+            });
+        }
+    }
+
     #[test]
     #[should_panic(expected = "Invalid UTF-8 input")]
     fn test_handle_record_bad_record() {
@@ -243,6 +344,6 @@ mod test {
         }
         let mut compressed_line = compressor.finish().unwrap();
         compressed_line.truncate(5);
-        handle_record_maybe_compressed(&compressed_line, &tx, &decoder, &encoder).unwrap();
+        handle_record_maybe_compressed(&compressed_line, &tx, &*decoder, &*encoder).unwrap();
     }
 }
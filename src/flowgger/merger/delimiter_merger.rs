@@ -0,0 +1,73 @@
+use super::Merger;
+use crate::flowgger::config::Config;
+use crate::flowgger::utils::parse_delimiter;
+use serde::Deserialize;
+
+const DEFAULT_FRAMING_DELIMITER: &str = "lf";
+
+/// Settings of the delimiter, in the `[output]` section
+#[derive(Deserialize)]
+pub struct DelimiterSettings {
+    framing_delimiter: Option<String>,
+}
+
+/// Non-transparent framing (RFC6587) with a configurable trailer
+#[derive(Clone)]
+pub struct DelimiterMerger {
+    delimiter: Vec<u8>,
+}
+
+impl DelimiterMerger {
+    pub fn new(config: &Config) -> DelimiterMerger {
+        let settings: DelimiterSettings = config.settings("output");
+        let delimiter = settings
+            .framing_delimiter
+            .as_deref()
+            .unwrap_or(DEFAULT_FRAMING_DELIMITER);
+        let delimiter = parse_delimiter(delimiter)
+            .unwrap_or_else(|e| panic!("Invalid output.framing_delimiter: {}", e));
+        DelimiterMerger { delimiter }
+    }
+}
+
+impl Merger for DelimiterMerger {
+    fn frame(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.delimiter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delimiter_merger_default() {
+        let config = Config::from_string("[output]\nframing = \"delimiter\"\n").unwrap();
+        let mut bytes = b"message".to_vec();
+        DelimiterMerger::new(&config).frame(&mut bytes);
+        assert_eq!(bytes, b"message\n");
+    }
+
+    #[test]
+    fn test_delimiter_merger_crlf() {
+        let config = Config::from_string("[output]\nframing_delimiter = \"CRLF\"\n").unwrap();
+        let mut bytes = b"message".to_vec();
+        DelimiterMerger::new(&config).frame(&mut bytes);
+        assert_eq!(bytes, b"message\r\n");
+    }
+
+    #[test]
+    fn test_delimiter_merger_literal() {
+        let config = Config::from_string("[output]\nframing_delimiter = \"\\r\\n\"\n").unwrap();
+        let mut bytes = b"message".to_vec();
+        DelimiterMerger::new(&config).frame(&mut bytes);
+        assert_eq!(bytes, b"message\r\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid output.framing_delimiter: The delimiter cannot be empty")]
+    fn test_delimiter_merger_empty() {
+        let config = Config::from_string("[output]\nframing_delimiter = \"\"\n").unwrap();
+        let _ = DelimiterMerger::new(&config);
+    }
+}
@@ -0,0 +1,30 @@
+use super::Merger;
+use crate::flowgger::config::Config;
+
+/// RFC7464 record separator
+const RS: u8 = 0x1e;
+
+/// JSON text sequences (RFC7464): every record is preceded with RS and terminated with LF
+#[derive(Clone)]
+pub struct JsonSeqMerger;
+
+impl JsonSeqMerger {
+    pub fn new(_config: &Config) -> JsonSeqMerger {
+        JsonSeqMerger
+    }
+}
+
+impl Merger for JsonSeqMerger {
+    fn frame(&self, bytes: &mut Vec<u8>) {
+        bytes.insert(0, RS);
+        bytes.push(b'\n');
+    }
+}
+
+#[test]
+fn test_json_seq_merger() {
+    let config = Config::from_string("").unwrap();
+    let mut bytes = br#"{"short_message":"test"}"#.to_vec();
+    JsonSeqMerger::new(&config).frame(&mut bytes);
+    assert_eq!(bytes, b"\x1e{\"short_message\":\"test\"}\n");
+}
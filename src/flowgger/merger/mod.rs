@@ -1,7 +1,13 @@
+mod delimiter_merger;
+mod json_seq_merger;
 mod line_merger;
 mod nul_merger;
 mod syslen_merger;
 
+pub use self::delimiter_merger::DelimiterMerger;
+#[cfg(test)]
+pub use self::delimiter_merger::DelimiterSettings;
+pub use self::json_seq_merger::JsonSeqMerger;
 pub use self::line_merger::LineMerger;
 pub use self::nul_merger::NulMerger;
 pub use self::syslen_merger::SyslenMerger;
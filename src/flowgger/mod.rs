@@ -24,81 +24,152 @@ pub mod merger;
 #[cfg(test)]
 pub mod output;
 
+mod admin;
+mod byte_queue;
+mod config_check;
+mod config_init;
+pub mod daemon;
+mod impstats;
+mod one_shot;
+mod queue_monitor;
 mod record;
+mod record_queue;
 mod splitter;
 mod utils;
 
 #[cfg(test)]
 mod test_fuzzer;
+#[cfg(test)]
+mod test_round_trip;
+
+#[cfg(fuzzing)]
+pub mod fuzzing;
+#[cfg(feature = "test-util")]
+pub mod test_util;
 
 use std::io::{stderr, Write};
 
-#[cfg(feature = "capnp-recompile")]
+#[cfg(feature = "capnp")]
 extern crate capnp;
 extern crate clap;
 extern crate flate2;
 #[cfg(feature = "file")]
 extern crate glob;
-#[cfg(feature = "kafka-output")]
-extern crate kafka;
 #[cfg(feature = "file")]
 extern crate notify;
 #[cfg(feature = "tls")]
 extern crate openssl;
 extern crate rand;
+#[cfg(feature = "kafka-output")]
+extern crate rdkafka;
 #[cfg(feature = "redis-input")]
 extern crate redis;
+extern crate serde;
 #[cfg(feature = "gelf")]
 extern crate serde_json;
 extern crate time;
 extern crate toml;
 
-use self::config::Config;
+use self::byte_queue::ByteQueue;
+use self::config::{Config, OneOrMany};
+use self::config_check::check_settings;
+pub use self::config_init::config_init;
+use self::decoder::AutoDecoder;
+#[cfg(feature = "charset")]
+use self::decoder::CharsetDecoder;
+#[cfg(feature = "csv")]
+use self::decoder::CsvDecoder;
 #[cfg(feature = "gelf")]
 use self::decoder::GelfDecoder;
 #[cfg(feature = "ltsv")]
 use self::decoder::LTSVDecoder;
+#[cfg(feature = "logfmt")]
+use self::decoder::LogfmtDecoder;
+#[cfg(feature = "passthrough")]
+use self::decoder::PassthroughDecoder;
 #[cfg(feature = "rfc3164")]
 use self::decoder::RFC3164Decoder;
 #[cfg(feature = "rfc5424")]
 use self::decoder::RFC5424Decoder;
-use self::decoder::{Decoder, InvalidDecoder};
-#[cfg(feature = "capnp-recompile")]
+#[cfg(feature = "script")]
+use self::decoder::ScriptDecoder;
+#[cfg(feature = "statsd")]
+use self::decoder::StatsdDecoder;
+#[cfg(feature = "syslog-sign")]
+use self::decoder::SyslogSignDecoder;
+#[cfg(feature = "trace-context")]
+use self::decoder::TraceContextDecoder;
+#[cfg(feature = "wasm")]
+use self::decoder::WasmDecoder;
+use self::decoder::{
+    DeadLetterDecoder, DeadLetterSink, Decoder, ErrorRateDecoder, FallbackDecoder, InvalidDecoder,
+    InvalidUtf8Decoder, MsgUidDecoder, PauseDecoder, ReceivedTsDecoder, SchemaDecoder,
+    SdLimitDecoder, TapDecoder, TenantDecoder,
+};
+#[cfg(feature = "capnp")]
 use self::encoder::CapnpEncoder;
-use self::encoder::Encoder;
 #[cfg(feature = "gelf")]
 use self::encoder::GelfEncoder;
 #[cfg(feature = "ltsv")]
 use self::encoder::LTSVEncoder;
+#[cfg(feature = "logfmt")]
+use self::encoder::LogfmtEncoder;
 #[cfg(feature = "passthrough")]
 use self::encoder::PassthroughEncoder;
 #[cfg(feature = "rfc3164")]
 use self::encoder::RFC3164Encoder;
 #[cfg(feature = "rfc5424")]
 use self::encoder::RFC5424Encoder;
+#[cfg(feature = "redact")]
+use self::encoder::RedactEncoder;
+#[cfg(feature = "syslog-sign")]
+use self::encoder::SyslogSignEncoder;
+use self::encoder::{
+    AccountingEncoder, Encoder, FieldsEncoder, SanitizeEncoder, SequenceEncoder, TruncateEncoder,
+};
+use self::impstats::StatsRecords;
 #[cfg(feature = "file")]
 use self::input::FileInput;
 #[cfg(feature = "redis-input")]
 use self::input::RedisInput;
+#[cfg(feature = "statsd")]
+use self::input::StatsdInput;
 #[cfg(feature = "tls")]
 use self::input::TlsInput;
-use self::input::{Input, StdinInput};
+use self::input::{GeneratorInput, Input, ReplayInput, StdinInput};
+#[cfg(feature = "syslog")]
+use self::input::{RelpInput, TcpInput, UdpInput};
 #[cfg(feature = "coroutines")]
 use self::input::{TcpCoInput, TlsCoInput};
-#[cfg(feature = "syslog")]
-use self::input::{TcpInput, UdpInput};
-use self::merger::{LineMerger, Merger, NulMerger, SyslenMerger};
+use self::merger::{DelimiterMerger, JsonSeqMerger, LineMerger, Merger, NulMerger, SyslenMerger};
+pub use self::one_shot::{decode, encode};
 #[cfg(feature = "file")]
 use self::output::FileOutput;
 #[cfg(feature = "kafka-output")]
 use self::output::KafkaOutput;
+#[cfg(feature = "mqtt")]
+use self::output::MqttOutput;
+pub use self::output::Notifier;
+#[cfg(feature = "postgres-output")]
+use self::output::PostgresOutput;
+#[cfg(feature = "sqlite-output")]
+use self::output::SqliteOutput;
 #[cfg(feature = "tls")]
 use self::output::TlsOutput;
-use self::output::{DebugOutput, Output};
-use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
-use std::sync::{Arc, Mutex};
+#[cfg(unix)]
+use self::output::UnixOutput;
+use self::output::{BlackholeOutput, CircuitBreaker, DebugOutput, Output, RateLimiter, RelpOutput};
+use self::queue_monitor::{QueueMonitor, QueueStats};
+pub use self::record::{
+    Facility, Record, RecordBuilder, SDValue, Severity, StructuredData, Timestamp,
+};
+use self::record_queue::RecordSender;
+use self::utils::threads::{self, CpuAffinity};
+use serde::Deserialize;
+use std::sync::Arc;
 
 const DEFAULT_INPUT_FORMAT: &str = "rfc5424";
+const DEFAULT_STATSD_INPUT_FORMAT: &str = "statsd";
 const DEFAULT_INPUT_TYPE: &str = "syslog-tls";
 const DEFAULT_OUTPUT_FORMAT: &str = "gelf";
 const DEFAULT_OUTPUT_FRAMING: &str = "noop";
@@ -108,9 +179,27 @@ const DEFAULT_OUTPUT_TYPE: &str = "kafka";
 const DEFAULT_OUTPUT_TYPE: &str = "tls";
 const DEFAULT_QUEUE_SIZE: usize = 10_000_000;
 
+/// Settings of the pipeline, in the `[input]` section
+#[derive(Deserialize)]
+pub struct InputSettings {
+    r#type: Option<String>,
+    format: Option<OneOrMany<String>>,
+    queuesize: Option<usize>,
+    checkpoint: Option<String>,
+}
+
+/// Settings of the pipeline, in the `[output]` section
+#[derive(Deserialize)]
+pub struct OutputSettings {
+    r#type: Option<String>,
+    format: Option<String>,
+    framing: Option<String>,
+    framing_delimiter: Option<String>,
+}
+
 #[cfg(feature = "coroutines")]
 fn get_input_tlsco(config: &Config) -> Box<dyn Input> {
-    Box::new(TlsCoInput::new(&config)) as Box<dyn Input>
+    Box::new(TlsCoInput::new(config)) as Box<dyn Input>
 }
 
 #[cfg(not(feature = "coroutines"))]
@@ -120,7 +209,7 @@ fn get_input_tlsco(_config: &Config) -> ! {
 
 #[cfg(feature = "coroutines")]
 fn get_input_tcpco(config: &Config) -> Box<dyn Input> {
-    Box::new(TcpCoInput::new(&config)) as Box<dyn Input>
+    Box::new(TcpCoInput::new(config)) as Box<dyn Input>
 }
 
 #[cfg(not(feature = "coroutines"))]
@@ -130,7 +219,7 @@ fn get_input_tcpco(_config: &Config) -> ! {
 
 #[cfg(feature = "redis-input")]
 fn get_input_redis(config: &Config) -> Box<dyn Input> {
-    Box::new(RedisInput::new(&config)) as Box<dyn Input>
+    Box::new(RedisInput::new(config)) as Box<dyn Input>
 }
 
 #[cfg(not(feature = "redis-input"))]
@@ -140,7 +229,7 @@ fn get_input_redis(_config: &Config) -> ! {
 
 #[cfg(feature = "tls")]
 fn get_input_tls(config: &Config) -> Box<dyn Input> {
-    Box::new(TlsInput::new(&config)) as Box<dyn Input>
+    Box::new(TlsInput::new(config)) as Box<dyn Input>
 }
 
 #[cfg(not(feature = "tls"))]
@@ -150,7 +239,7 @@ fn get_input_tls(_config: &Config) -> ! {
 
 #[cfg(feature = "syslog")]
 fn get_input_tcp(config: &Config) -> Box<dyn Input> {
-    Box::new(TcpInput::new(&config)) as Box<dyn Input>
+    Box::new(TcpInput::new(config)) as Box<dyn Input>
 }
 
 #[cfg(not(feature = "syslog"))]
@@ -158,9 +247,19 @@ fn get_input_tcp(_config: &Config) -> ! {
     panic!("Support for syslog is not compiled in")
 }
 
+#[cfg(feature = "syslog")]
+fn get_input_relp(config: &Config) -> Box<dyn Input> {
+    Box::new(RelpInput::new(config)) as Box<dyn Input>
+}
+
+#[cfg(not(feature = "syslog"))]
+fn get_input_relp(_config: &Config) -> ! {
+    panic!("Support for syslog is not compiled in")
+}
+
 #[cfg(feature = "syslog")]
 fn get_input_udp(config: &Config) -> Box<dyn Input> {
-    Box::new(UdpInput::new(&config)) as Box<dyn Input>
+    Box::new(UdpInput::new(config)) as Box<dyn Input>
 }
 
 #[cfg(not(feature = "syslog"))]
@@ -168,9 +267,19 @@ fn get_input_udp(_config: &Config) -> ! {
     panic!("Support for syslog is not compiled in")
 }
 
+#[cfg(feature = "statsd")]
+fn get_input_statsd(config: &Config) -> Box<dyn Input> {
+    Box::new(StatsdInput::new(config)) as Box<dyn Input>
+}
+
+#[cfg(not(feature = "statsd"))]
+fn get_input_statsd(_config: &Config) -> ! {
+    panic!("Support for statsd is not compiled in")
+}
+
 #[cfg(feature = "file")]
 fn get_input_file(config: &Config) -> Box<dyn Input> {
-    Box::new(FileInput::new(&config)) as Box<dyn Input>
+    Box::new(FileInput::new(config)) as Box<dyn Input>
 }
 
 #[cfg(not(feature = "file"))]
@@ -180,7 +289,11 @@ fn get_input_file(_config: &Config) -> ! {
 
 fn get_input(input_type: &str, config: &Config) -> Box<dyn Input> {
     match input_type {
+        "generator" => Box::new(GeneratorInput::new(config)) as Box<dyn Input>,
         "redis" => get_input_redis(config),
+        "relp" => get_input_relp(config),
+        "replay" => Box::new(ReplayInput::new(config)) as Box<dyn Input>,
+        "statsd" => get_input_statsd(config),
         "stdin" => Box::new(StdinInput::new(config)) as Box<dyn Input>,
         "tcp" | "syslog-tcp" => get_input_tcp(config),
         "tcp_co" | "tcpco" | "syslog-tcp_co" | "syslog-tcpco" => get_input_tcpco(config),
@@ -202,6 +315,36 @@ fn get_output_kafka(_config: &Config) -> ! {
     panic!("Support for Kafka hasn't been compiled in")
 }
 
+#[cfg(feature = "mqtt")]
+fn get_output_mqtt(config: &Config) -> Box<dyn Output> {
+    Box::new(MqttOutput::new(config)) as Box<dyn Output>
+}
+
+#[cfg(not(feature = "mqtt"))]
+fn get_output_mqtt(_config: &Config) -> ! {
+    panic!("Support for MQTT hasn't been compiled in")
+}
+
+#[cfg(feature = "postgres-output")]
+fn get_output_postgres(config: &Config) -> Box<dyn Output> {
+    Box::new(PostgresOutput::new(config)) as Box<dyn Output>
+}
+
+#[cfg(not(feature = "postgres-output"))]
+fn get_output_postgres(_config: &Config) -> ! {
+    panic!("Support for PostgreSQL hasn't been compiled in")
+}
+
+#[cfg(feature = "sqlite-output")]
+fn get_output_sqlite(config: &Config) -> Box<dyn Output> {
+    Box::new(SqliteOutput::new(config)) as Box<dyn Output>
+}
+
+#[cfg(not(feature = "sqlite-output"))]
+fn get_output_sqlite(_config: &Config) -> ! {
+    panic!("Support for SQLite hasn't been compiled in")
+}
+
 #[cfg(all(feature = "file", not(test)))]
 fn get_output_file(config: &Config) -> Box<dyn Output> {
     Box::new(FileOutput::new(config)) as Box<dyn Output>
@@ -232,22 +375,38 @@ fn get_output_tls(_config: &Config) -> ! {
     panic!("Support for tls hasn't been compiled in")
 }
 
+#[cfg(unix)]
+fn get_output_unix(config: &Config) -> Box<dyn Output> {
+    Box::new(UnixOutput::new(config)) as Box<dyn Output>
+}
+
+#[cfg(not(unix))]
+fn get_output_unix(_config: &Config) -> ! {
+    panic!("Unix sockets are not supported on this platform")
+}
+
 fn get_output(output_type: &str, config: &Config) -> Box<dyn Output> {
     match output_type {
         "stdout" | "debug" => Box::new(DebugOutput::new(config)) as Box<dyn Output>,
+        "blackhole" | "null" => Box::new(BlackholeOutput::new(config)) as Box<dyn Output>,
         "kafka" => get_output_kafka(config),
+        "mqtt" => get_output_mqtt(config),
+        "postgres" | "postgresql" => get_output_postgres(config),
         "tls" | "syslog-tls" => get_output_tls(config),
         "file" => get_output_file(config),
+        "relp" => Box::new(RelpOutput::new(config)) as Box<dyn Output>,
+        "sqlite" => get_output_sqlite(config),
+        "unix" => get_output_unix(config),
         _ => panic!("Invalid output type: {}", output_type),
     }
 }
 
-#[cfg(feature = "capnp-recompile")]
+#[cfg(feature = "capnp")]
 fn get_capnp_encoder(config: &Config) -> Box<dyn Encoder + Send> {
     Box::new(CapnpEncoder::new(config)) as Box<dyn Encoder + Send>
 }
 
-#[cfg(not(feature = "capnp-recompile"))]
+#[cfg(not(feature = "capnp"))]
 fn get_capnp_encoder(_config: &Config) -> ! {
     panic!("Support for CapNProto hasn't been compiled in")
 }
@@ -272,6 +431,59 @@ fn get_gelf_decoder(_config: &Config) -> ! {
     panic!("Support for Gelf hasn't been compiled in")
 }
 
+#[cfg(feature = "wasm")]
+fn get_wasm_decoder(config: &Config) -> Box<dyn Decoder + Send> {
+    Box::new(WasmDecoder::new(config)) as Box<dyn Decoder + Send>
+}
+
+#[cfg(not(feature = "wasm"))]
+fn get_wasm_decoder(_config: &Config) -> ! {
+    panic!("Support for WebAssembly plugins hasn't been compiled in")
+}
+
+#[cfg(feature = "csv")]
+fn get_csv_decoder(config: &Config, input_format: &str) -> Box<dyn Decoder + Send> {
+    match input_format {
+        "w3c" => Box::new(CsvDecoder::w3c(config)) as Box<dyn Decoder + Send>,
+        _ => Box::new(CsvDecoder::new(config)) as Box<dyn Decoder + Send>,
+    }
+}
+
+#[cfg(not(feature = "csv"))]
+fn get_csv_decoder(_config: &Config, _input_format: &str) -> ! {
+    panic!("Support for CSV hasn't been compiled in")
+}
+
+#[cfg(feature = "logfmt")]
+fn get_logfmt_encoder(config: &Config) -> Box<dyn Encoder + Send> {
+    Box::new(LogfmtEncoder::new(config)) as Box<dyn Encoder + Send>
+}
+
+#[cfg(not(feature = "logfmt"))]
+fn get_logfmt_encoder(_config: &Config) -> ! {
+    panic!("Support for logfmt hasn't been compiled in")
+}
+
+#[cfg(feature = "statsd")]
+fn get_statsd_decoder(config: &Config) -> Box<dyn Decoder + Send> {
+    Box::new(StatsdDecoder::new(config)) as Box<dyn Decoder + Send>
+}
+
+#[cfg(not(feature = "statsd"))]
+fn get_statsd_decoder(_config: &Config) -> ! {
+    panic!("Support for statsd hasn't been compiled in")
+}
+
+#[cfg(feature = "logfmt")]
+fn get_logfmt_decoder(config: &Config) -> Box<dyn Decoder + Send> {
+    Box::new(LogfmtDecoder::new(config)) as Box<dyn Decoder + Send>
+}
+
+#[cfg(not(feature = "logfmt"))]
+fn get_logfmt_decoder(_config: &Config) -> ! {
+    panic!("Support for logfmt hasn't been compiled in")
+}
+
 #[cfg(feature = "ltsv")]
 fn get_ltvs_encoder(config: &Config) -> Box<dyn Encoder + Send> {
     Box::new(LTSVEncoder::new(config)) as Box<dyn Encoder + Send>
@@ -302,6 +514,11 @@ fn get_encoder_rfc5424(config: &Config) -> Box<dyn Encoder + Send> {
     Box::new(RFC5424Encoder::new(config)) as Box<dyn Encoder + Send>
 }
 
+#[cfg(feature = "passthrough")]
+fn get_decoder_passthrough(config: &Config) -> Box<dyn Decoder + Send> {
+    Box::new(PassthroughDecoder::new(config)) as Box<dyn Decoder + Send>
+}
+
 #[cfg(feature = "passthrough")]
 fn get_encoder_passthrough(config: &Config) -> Box<dyn Encoder + Send> {
     Box::new(PassthroughEncoder::new(config)) as Box<dyn Encoder + Send>
@@ -345,6 +562,11 @@ fn get_encoder_rfc5424(_config: &Config) -> ! {
     panic!("Support for rfc3164 hasn't been compiled in")
 }
 
+#[cfg(not(feature = "passthrough"))]
+fn get_decoder_passthrough(_config: &Config) -> ! {
+    panic!("Support for passthrough hasn't been compiled in")
+}
+
 #[cfg(not(feature = "passthrough"))]
 fn get_encoder_passthrough(_config: &Config) -> ! {
     panic!("Support for passthrough hasn't been compiled in")
@@ -392,88 +614,544 @@ pub fn validate_time_format_input(
     }
 }
 
-pub fn start(config_file: &str) {
-    let config = match Config::from_path(config_file) {
-        Ok(config) => config,
-        Err(e) => panic!(
-            "Unable to read the config file [{}]: {}",
-            config_file,
-            e.to_string()
-        ),
-    };
-    let input_format = config
-        .lookup("input.format")
-        .map_or(DEFAULT_INPUT_FORMAT, |x| {
-            x.as_str().expect("input.format must be a string")
-        });
-    let input_type = config.lookup("input.type").map_or(DEFAULT_INPUT_TYPE, |x| {
-        x.as_str().expect("input.type must be a string")
-    });
-    let input = get_input(input_type, &config);
-    let decoder = match input_format {
+fn get_format_encoder(output_format: &str, config: &Config) -> Box<dyn Encoder + Send> {
+    match output_format {
+        "capnp" => get_capnp_encoder(config),
+        "gelf" | "json" => get_gelf_encoder(config),
+        "logfmt" => get_logfmt_encoder(config),
+        "ltsv" => get_ltvs_encoder(config),
+        "rfc3164" => get_encoder_rfc3164(config),
+        "rfc5424" => get_encoder_rfc5424(config),
+        "passthrough" => get_encoder_passthrough(config),
+        _ => panic!("Unknown output format: {}", output_format),
+    }
+}
+
+fn get_format_decoder(input_format: &str, config: &Config) -> Box<dyn Decoder + Send> {
+    match input_format {
         _ if input_format == "capnp" => {
-            Box::new(InvalidDecoder::new(&config)) as Box<dyn Decoder + Send>
+            Box::new(InvalidDecoder::new(config)) as Box<dyn Decoder + Send>
         }
-        "gelf" => get_gelf_decoder(&config),
-        "ltsv" => get_ltvs_decoder(&config),
-        "rfc5424" => get_decoder_rfc5424(&config),
-        "rfc3164" => get_decoder_rfc3164(&config),
+        "auto" => Box::new(AutoDecoder::new(config)) as Box<dyn Decoder + Send>,
+        "csv" | "w3c" => get_csv_decoder(config, input_format),
+        "gelf" => get_gelf_decoder(config),
+        "logfmt" => get_logfmt_decoder(config),
+        "ltsv" => get_ltvs_decoder(config),
+        "passthrough" => get_decoder_passthrough(config),
+        "rfc5424" => get_decoder_rfc5424(config),
+        "rfc3164" => get_decoder_rfc3164(config),
+        "statsd" => get_statsd_decoder(config),
+        "wasm" => get_wasm_decoder(config),
         _ => panic!("Unknown input format: {}", input_format),
+    }
+}
+
+#[cfg(feature = "charset")]
+fn wrap_charset_decoder(
+    config: &Config,
+    decoder: Box<dyn Decoder + Send>,
+) -> Box<dyn Decoder + Send> {
+    CharsetDecoder::wrap(config, decoder)
+}
+
+#[cfg(not(feature = "charset"))]
+fn wrap_charset_decoder(
+    config: &Config,
+    decoder: Box<dyn Decoder + Send>,
+) -> Box<dyn Decoder + Send> {
+    if config.lookup("input.charset").is_some() {
+        panic!("Support for charset hasn't been compiled in");
+    }
+    decoder
+}
+
+#[cfg(feature = "script")]
+fn wrap_script_decoder(
+    config: &Config,
+    decoder: Box<dyn Decoder + Send>,
+) -> Box<dyn Decoder + Send> {
+    ScriptDecoder::wrap(config, decoder)
+}
+
+#[cfg(not(feature = "script"))]
+fn wrap_script_decoder(
+    config: &Config,
+    decoder: Box<dyn Decoder + Send>,
+) -> Box<dyn Decoder + Send> {
+    if config.lookup("input.script").is_some() {
+        panic!("Support for scripts hasn't been compiled in");
+    }
+    decoder
+}
+
+#[cfg(feature = "syslog-sign")]
+fn wrap_syslog_sign_decoder(
+    config: &Config,
+    decoder: Box<dyn Decoder + Send>,
+) -> Box<dyn Decoder + Send> {
+    SyslogSignDecoder::wrap(config, decoder)
+}
+
+#[cfg(not(feature = "syslog-sign"))]
+fn wrap_syslog_sign_decoder(
+    config: &Config,
+    decoder: Box<dyn Decoder + Send>,
+) -> Box<dyn Decoder + Send> {
+    if config.lookup("input.syslog_sign_key").is_some() {
+        panic!("Support for syslog signing hasn't been compiled in");
+    }
+    decoder
+}
+
+#[cfg(feature = "trace-context")]
+fn wrap_trace_context_decoder(
+    config: &Config,
+    decoder: Box<dyn Decoder + Send>,
+) -> Box<dyn Decoder + Send> {
+    TraceContextDecoder::wrap(config, decoder)
+}
+
+#[cfg(not(feature = "trace-context"))]
+fn wrap_trace_context_decoder(
+    config: &Config,
+    decoder: Box<dyn Decoder + Send>,
+) -> Box<dyn Decoder + Send> {
+    if config.lookup("input.trace_context").is_some() {
+        panic!("Support for trace context extraction hasn't been compiled in");
+    }
+    decoder
+}
+
+#[cfg(feature = "wasm")]
+fn wrap_wasm_decoder(config: &Config, decoder: Box<dyn Decoder + Send>) -> Box<dyn Decoder + Send> {
+    WasmDecoder::wrap(config, decoder)
+}
+
+#[cfg(not(feature = "wasm"))]
+fn wrap_wasm_decoder(config: &Config, decoder: Box<dyn Decoder + Send>) -> Box<dyn Decoder + Send> {
+    if config.lookup("input.wasm_transform").is_some() {
+        panic!("Support for WebAssembly plugins hasn't been compiled in");
+    }
+    decoder
+}
+
+/// Format of the records when 'input.format' is not set, that depends on the input type
+fn default_input_format(input_type: &str) -> &'static str {
+    match input_type {
+        "statsd" => DEFAULT_STATSD_INPUT_FORMAT,
+        _ => DEFAULT_INPUT_FORMAT,
+    }
+}
+
+/// Build the decoder of an input, as set with 'input.format', along with the transforms of the records.
+/// With a list of formats, they are tried in order, and the first one that succeeds is used.
+fn get_decoder(config: &Config) -> Box<dyn Decoder + Send> {
+    build_decoder(config, true)
+}
+
+/// Build the decoder of an input and the transforms of the records only, without the wrappers that act on
+/// the running process: the dead letter sink, the error rate monitor, the tap and the pause of the inputs
+fn get_record_decoder(config: &Config) -> Box<dyn Decoder + Send> {
+    build_decoder(config, false)
+}
+
+/// # Parameters
+/// - `pipeline`: Whether the decoder is the one of a running pipeline, wrapped by the dead letter sink, the
+///   error rate monitor, the tap and the pause of the inputs
+fn build_decoder(config: &Config, pipeline: bool) -> Box<dyn Decoder + Send> {
+    let settings: InputSettings = config.settings("input");
+    let decoder = match settings.format {
+        Some(OneOrMany::Many(formats)) => {
+            let decoders = formats
+                .into_iter()
+                .map(|format| {
+                    let decoder = get_format_decoder(&format, config);
+                    (format, decoder)
+                })
+                .collect();
+            Box::new(FallbackDecoder::new(decoders)) as Box<dyn Decoder + Send>
+        }
+        Some(OneOrMany::One(format)) => get_format_decoder(&format, config),
+        None => get_format_decoder(
+            default_input_format(settings.r#type.as_deref().unwrap_or(DEFAULT_INPUT_TYPE)),
+            config,
+        ),
     };
+    let decoder = wrap_syslog_sign_decoder(config, decoder);
+    let decoder = InvalidUtf8Decoder::wrap(config, decoder);
+    let decoder = wrap_charset_decoder(config, decoder);
+    let decoder = wrap_script_decoder(config, decoder);
+    let decoder = wrap_wasm_decoder(config, decoder);
+    let decoder = wrap_trace_context_decoder(config, decoder);
+    let decoder = SdLimitDecoder::wrap(config, decoder);
+    let decoder = TenantDecoder::wrap(config, decoder);
+    let decoder = ReceivedTsDecoder::wrap(config, decoder);
+    let decoder = MsgUidDecoder::wrap(config, decoder);
+    let decoder = SchemaDecoder::wrap(config, decoder);
+    if !pipeline {
+        return decoder;
+    }
+    let dead_letter = DeadLetterSink::from_config(config);
+    let decoder = DeadLetterDecoder::wrap(config, dead_letter.clone(), decoder);
+    let decoder = ErrorRateDecoder::wrap(config, dead_letter, decoder);
+    let decoder = TapDecoder::wrap(config, decoder);
+    PauseDecoder::wrap(decoder)
+}
 
-    let output_format = config
-        .lookup("output.format")
-        .map_or(DEFAULT_OUTPUT_FORMAT, |x| {
-            x.as_str().expect("output.format must be a string")
-        });
-    let encoder = match output_format {
-        "capnp" => get_capnp_encoder(&config),
-        "gelf" | "json" => get_gelf_encoder(&config),
-        "ltsv" => get_ltvs_encoder(&config),
-        "rfc3164" => get_encoder_rfc3164(&config),
-        "rfc5424" => get_encoder_rfc5424(&config),
-        "passthrough" => get_encoder_passthrough(&config),
-        _ => panic!("Unknown output format: {}", output_format),
+#[cfg(feature = "redact")]
+fn wrap_redact_encoder(
+    config: &Config,
+    encoder: Box<dyn Encoder + Send>,
+) -> Box<dyn Encoder + Send> {
+    RedactEncoder::wrap(config, encoder)
+}
+
+#[cfg(not(feature = "redact"))]
+fn wrap_redact_encoder(
+    config: &Config,
+    encoder: Box<dyn Encoder + Send>,
+) -> Box<dyn Encoder + Send> {
+    if config.lookup("output.redact").is_some() {
+        panic!("Support for redact hasn't been compiled in");
+    }
+    encoder
+}
+
+/// Wrap the format encoder so that records are sanitized, then redacted, then truncated: escape sequences
+/// can't split the data to redact, and truncation can't cut it before it gets redacted
+fn wrap_record_encoders(
+    config: &Config,
+    encoder: Box<dyn Encoder + Send>,
+) -> Box<dyn Encoder + Send> {
+    let encoder = TruncateEncoder::wrap(config, encoder);
+    let encoder = wrap_redact_encoder(config, encoder);
+    SanitizeEncoder::wrap(config, encoder)
+}
+
+#[cfg(feature = "syslog-sign")]
+fn wrap_syslog_sign_encoder(
+    config: &Config,
+    encoder: Box<dyn Encoder + Send>,
+    tx: &RecordSender,
+    fields_count: usize,
+) -> Box<dyn Encoder + Send> {
+    SyslogSignEncoder::wrap(config, encoder, tx.clone(), fields_count)
+}
+
+#[cfg(not(feature = "syslog-sign"))]
+fn wrap_syslog_sign_encoder(
+    config: &Config,
+    encoder: Box<dyn Encoder + Send>,
+    _tx: &RecordSender,
+    _fields_count: usize,
+) -> Box<dyn Encoder + Send> {
+    if config.lookup("output.syslog_sign_key").is_some() {
+        panic!("Support for syslog signing hasn't been compiled in");
+    }
+    encoder
+}
+
+/// Framing of the output when 'output.framing' is not set
+fn default_output_framing(output_format: &str, output_type: &str) -> &'static str {
+    match (output_format, output_type) {
+        ("capnp", _)
+        | (_, "kafka")
+        | (_, "mqtt")
+        | (_, "postgres")
+        | (_, "postgresql")
+        | (_, "relp")
+        | (_, "sqlite") => "noop",
+        (_, "debug") | ("ltsv", _) | ("logfmt", _) => "line",
+        ("gelf", _) => "nul",
+        _ => DEFAULT_OUTPUT_FRAMING,
+    }
+}
+
+#[cfg(feature = "file")]
+pub fn verify_hash_chain(
+    config_file: &str,
+    paths: &[&str],
+    previous_tag: Option<&str>,
+    allow_continuation: bool,
+) -> Result<u64, String> {
+    let config = Config::from_path(config_file)
+        .map_err(|e| format!("Unable to read the config file [{}]: {}", config_file, e))?;
+    output::verify_hash_chain(&config, paths, previous_tag, allow_continuation)
+}
+
+pub fn start(config_file: &str, notifier: Option<Arc<dyn Notifier>>) {
+    let config = match Config::from_path(config_file) {
+        Ok(config) => config,
+        Err(e) => panic!("Unable to read the config file [{}]: {}", config_file, e),
     };
-    let output_type = config
-        .lookup("output.type")
-        .map_or(DEFAULT_OUTPUT_TYPE, |x| {
-            x.as_str().expect("output.type must be a string")
-        });
-    let output = get_output(output_type, &config);
-    let output_framing = match config.lookup("output.framing") {
-        Some(framing) => framing.as_str().expect("output.framing must be a string"),
-        None => match (output_format, output_type) {
-            ("capnp", _) | (_, "kafka") => "noop",
-            (_, "debug") | ("ltsv", _) => "line",
-            ("gelf", _) => "nul",
-            _ => DEFAULT_OUTPUT_FRAMING,
-        },
+    check_settings(&config);
+    let input_settings: InputSettings = config.settings("input");
+    let input_type = input_settings
+        .r#type
+        .as_deref()
+        .unwrap_or(DEFAULT_INPUT_TYPE);
+    let input = get_input(input_type, &config);
+    let output_settings: OutputSettings = config.settings("output");
+    let output_type = output_settings
+        .r#type
+        .as_deref()
+        .unwrap_or(DEFAULT_OUTPUT_TYPE);
+    let output: Arc<dyn Output> = Arc::from(get_output(output_type, &config));
+    #[cfg(any(unix, windows))]
+    {
+        let output = Arc::clone(&output);
+        daemon::on_shutdown(move || output.stop());
+    }
+    run(config, input, output, notifier, true);
+    daemon::run_exit_hooks();
+}
+
+/// Run the pipeline configured by `config` between `input` and `output`, until the input stops
+///
+/// # Parameters
+/// - `standalone`: Whether the pipeline is the one of the flowgger process, that also starts the inputs of
+///   'input.listeners', the admin socket and the statistics records, and drops its privileges once
+///   listening. Otherwise only `input` feeds the pipeline, that stops once it is drained.
+fn run(
+    config: Config,
+    input: Box<dyn Input>,
+    output: Arc<dyn Output>,
+    notifier: Option<Arc<dyn Notifier>>,
+    standalone: bool,
+) {
+    let decoder = get_decoder(&config);
+    let listeners = if standalone {
+        config.input_listeners()
+    } else {
+        Vec::new()
+    };
+    let input_settings: InputSettings = config.settings("input");
+    if !listeners.is_empty() && input_settings.checkpoint.is_some() {
+        panic!("input.checkpoint cannot be used along with input.listeners");
+    }
+
+    let output_settings: OutputSettings = config.settings("output");
+    let output_format = output_settings
+        .format
+        .as_deref()
+        .unwrap_or(DEFAULT_OUTPUT_FORMAT);
+    let encoder = get_format_encoder(output_format, &config);
+    let output_type = output_settings
+        .r#type
+        .as_deref()
+        .unwrap_or(DEFAULT_OUTPUT_TYPE);
+    let encoder = wrap_record_encoders(&config, encoder);
+    let encoder = AccountingEncoder::wrap(&config, encoder);
+    let encoder = SequenceEncoder::wrap(&config, encoder);
+    let output_framing = match output_settings.framing.as_deref() {
+        Some(framing) => framing,
+        None if output_settings.framing_delimiter.is_some() => "delimiter",
+        None => default_output_framing(output_format, output_type),
     };
     let merger: Option<Box<dyn Merger>> = match output_framing {
         "noop" | "nop" | "none" => None,
         "capnp" => None,
         "line" => Some(Box::new(LineMerger::new(&config)) as Box<dyn Merger>),
         "nul" => Some(Box::new(NulMerger::new(&config)) as Box<dyn Merger>),
+        "delimiter" => Some(Box::new(DelimiterMerger::new(&config)) as Box<dyn Merger>),
+        "json-seq" => Some(Box::new(JsonSeqMerger::new(&config)) as Box<dyn Merger>),
         "syslen" => Some(Box::new(SyslenMerger::new(&config)) as Box<dyn Merger>),
         _ => panic!("Invalid framing type: {}", output_framing),
     };
-    let queue_size = config
-        .lookup("input.queuesize")
-        .map_or(DEFAULT_QUEUE_SIZE, |x| {
-            x.as_integer()
-                .expect("input.queuesize must be a size integer") as usize
-        });
-    let (tx, rx): (SyncSender<Vec<u8>>, Receiver<Vec<u8>>) = sync_channel(queue_size);
-    let arx = Arc::new(Mutex::new(rx));
+    let queue_size = input_settings.queuesize.unwrap_or(DEFAULT_QUEUE_SIZE);
+    let (tx, rx, queue_stats) = match ByteQueue::from_config(&config) {
+        Some(byte_queue) => byte_queue.start(queue_size),
+        None => {
+            let (tx, rx) = record_queue::bounded(queue_size);
+            let queue_stats = QueueStats::new(rx.occupancy());
+            (tx, rx, queue_stats)
+        }
+    };
+    let record_fields = output.record_fields();
+    let encoder = wrap_syslog_sign_encoder(&config, encoder, &tx, record_fields.len());
+    let encoder = FieldsEncoder::wrap(encoder, record_fields);
+    let queue_stats = Arc::new(queue_stats);
+    if let Some(queue_monitor) = QueueMonitor::new(&config) {
+        queue_monitor.start(Arc::clone(&queue_stats));
+    }
+    if standalone {
+        admin::start(&config, queue_stats);
+        if let Some(stats_records) = StatsRecords::new(&config) {
+            stats_records.start(tx.clone(), encoder.clone_boxed());
+        }
+    }
+    let rx = match RateLimiter::from_config(&config) {
+        Some(rate_limiter) => rate_limiter.start(rx),
+        None => rx,
+    };
+    let rx = match CircuitBreaker::from_config(&config) {
+        Some(circuit_breaker) => circuit_breaker.start(rx),
+        None => rx,
+    };
 
-    output.start(arx, merger);
+    let notifier = match (notifier, input.notifier()) {
+        (Some(notifier), Some(input_notifier)) => {
+            Some(Arc::new(vec![notifier, input_notifier]) as Arc<dyn Notifier>)
+        }
+        (notifier, input_notifier) => notifier.or(input_notifier),
+    };
+    output.start(rx, merger, notifier);
+    if standalone {
+        daemon::init(&config, listeners.len() + 1);
+    }
+    for listener in listeners {
+        let (tx, encoder) = (tx.clone(), encoder.clone_boxed());
+        let input_settings: InputSettings = listener.settings("input");
+        let input_type = input_settings
+            .r#type
+            .unwrap_or_else(|| DEFAULT_INPUT_TYPE.to_owned());
+        let name = format!("flowgger-input-{}", input_type);
+        let cpu = CpuAffinity::new(&listener, "input").cpu(0);
+        threads::spawn(name, cpu, move || {
+            let input = get_input(&input_type, &listener);
+            input.accept(tx, get_decoder(&listener), encoder);
+        });
+    }
+    if let Some(cpu) = CpuAffinity::new(&config, "input").cpu(0) {
+        threads::pin_current(cpu);
+    }
     input.accept(tx, decoder, encoder);
+    output.stop();
 }
 
 #[cfg(test)]
 mod tests {
+    #[cfg(feature = "rfc5424")]
+    use super::get_decoder;
     use super::validate_time_format_input;
+    #[cfg(any(feature = "redact", feature = "rfc5424"))]
+    use super::Config;
+    #[cfg(feature = "redact")]
+    use super::{wrap_record_encoders, Record};
+    #[cfg(feature = "redact")]
+    use crate::flowgger::utils::test_utils::record_test_utils::MsgEncoder;
+    #[cfg(feature = "rfc5424")]
+    use std::fs;
+    #[cfg(feature = "rfc5424")]
+    use tempdir::TempDir;
+    #[cfg(feature = "rfc5424")]
+    use toml::Value;
+
+    #[cfg(feature = "redact")]
+    #[test]
+    fn test_record_encoders_order() {
+        let config = Config::from_string(
+            r#"[output]
+sanitize = true
+max_msg_length = 16
+redact_report_interval = 0
+[[output.redact]]
+pattern = '\d{4}-\d{4}-\d{4}-\d{4}'
+"#,
+        )
+        .unwrap();
+        let encoder = wrap_record_encoders(&config, Box::new(MsgEncoder));
+        // The card number straddles the truncation limit, and is split by an escape sequence
+        let record = Record::builder()
+            .hostname("example.org")
+            .msg("Paid with 4111-\x1b[0m1111-1111-1234")
+            .build();
+        assert_eq!(
+            String::from_utf8(encoder.encode(record).unwrap()).unwrap(),
+            "Paid with [REDAC[...]"
+        );
+    }
+
+    #[cfg(feature = "rfc5424")]
+    #[test]
+    fn test_decoder_order() {
+        let temp_dir = TempDir::new("test_decoder_order").unwrap();
+        let path = temp_dir.path().join("dead_letter.log");
+        let mut config = Config::from_string(
+            r#"[input]
+format = "rfc5424"
+tenant = "acme"
+schema_required = [ "tenant" ]
+dead_letter = "file"
+[input.schema_max_lengths]
+msg = 5
+"#,
+        )
+        .unwrap();
+        config.set(
+            "input.dead_letter_path",
+            Value::String(path.to_str().unwrap().to_owned()),
+        );
+        let decoder = get_decoder(&config);
+        // The schema sees the records once the tenant has been added
+        assert!(decoder
+            .decode("<13>1 2015-08-05T15:53:45Z example.org app - - - hello")
+            .is_ok());
+        assert!(decoder
+            .decode("<13>1 2015-08-05T15:53:45Z example.org app - - - too long")
+            .is_err());
+        assert!(decoder.decode_bytes(b"<13>1 \xff").is_err());
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let errors: Vec<_> = contents
+            .lines()
+            .map(|line| line.split(r#""error":""#).nth(1).unwrap())
+            .collect();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("Schema violation; a field exceeds its maximum length"));
+        assert!(errors[1].starts_with("Invalid UTF-8 input\",\"raw\":\"<13>1 \u{fffd}"));
+    }
+
+    #[cfg(feature = "rfc5424")]
+    #[test]
+    fn test_dead_letter_transform_failures() {
+        let temp_dir = TempDir::new("test_dead_letter_transform_failures").unwrap();
+        let path = temp_dir.path().join("dead_letter.log");
+        let mut config = Config::from_string(
+            "[input]\nformat = \"rfc5424\"\nsd_max_pairs = 1\nsd_limit_policy = \"drop\"\ndead_letter = \"file\"\n",
+        )
+        .unwrap();
+        config.set(
+            "input.dead_letter_path",
+            Value::String(path.to_str().unwrap().to_owned()),
+        );
+        #[cfg(feature = "script")]
+        {
+            let script = temp_dir.path().join("reject.rhai");
+            fs::write(
+                &script,
+                r#"if record.appname == "bad" { throw "rejected"; }"#,
+            )
+            .unwrap();
+            config.set(
+                "input.script",
+                Value::String(script.to_str().unwrap().to_owned()),
+            );
+        }
+        let decoder = get_decoder(&config);
+        assert!(decoder
+            .decode(r#"<13>1 2015-08-05T15:53:45Z example.org app - - [id a="1" b="2"] hello"#)
+            .is_err());
+        #[cfg(feature = "script")]
+        assert!(decoder
+            .decode("<13>1 2015-08-05T15:53:45Z example.org bad - - - hello")
+            .is_err());
+        let expected: &[&str] = if cfg!(feature = "script") {
+            &["Too many structured data pairs", "The script failed"]
+        } else {
+            &["Too many structured data pairs"]
+        };
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let errors: Vec<_> = contents
+            .lines()
+            .map(|line| line.split(r#""error":""#).nth(1).unwrap())
+            .collect();
+        assert_eq!(errors.len(), expected.len());
+        for (error, expected) in errors.iter().zip(expected) {
+            assert!(error.starts_with(expected), "{}", error);
+        }
+    }
 
     #[test]
     fn test_invalid_time_format() {
@@ -0,0 +1,161 @@
+use super::config::Config;
+use super::decoder::{log_rejected, DROPPED};
+use super::{
+    get_format_encoder, get_record_decoder, wrap_record_encoders, OutputSettings,
+    DEFAULT_OUTPUT_FORMAT,
+};
+use std::io::{stdin, stdout, BufRead, Write};
+use toml::Value;
+
+/// Decode records with the input format and print them, to debug the decoding of a device format without
+/// a whole pipeline. The records go through the transforms of the configuration, but neither through the
+/// dead letter sink nor the error rate monitor.
+///
+/// # Parameters
+/// - `config_file`: Optional. Configuration of the decoder, i.e. the CSV fields or a schema.
+/// - `format`: Optional. Input format, overriding 'input.format'.
+/// - `record`: Optional. Record to decode. Every line of stdin is decoded if it is not set.
+///
+/// # Returns
+/// The number of records that could not be decoded
+pub fn decode(config_file: Option<&str>, format: Option<&str>, record: Option<&str>) -> usize {
+    let config = load_config(config_file, &[("input.format", format)]);
+    let decoder = get_record_decoder(&config);
+    run(record, |line| {
+        decoder
+            .decode_bytes(line)
+            .map(|record| format!("{:#?}\n", record).into_bytes())
+    })
+}
+
+/// Decode records with the input format, encode them with the output format and print them.
+///
+/// # Parameters
+/// - `config_file`: Optional. Configuration of the decoder and of the encoder.
+/// - `input_format`: Optional. Input format, overriding 'input.format'.
+/// - `format`: Optional. Output format, overriding 'output.format'.
+/// - `record`: Optional. Record to encode. Every line of stdin is encoded if it is not set.
+///
+/// # Returns
+/// The number of records that could not be decoded or encoded
+pub fn encode(
+    config_file: Option<&str>,
+    input_format: Option<&str>,
+    format: Option<&str>,
+    record: Option<&str>,
+) -> usize {
+    let config = load_config(
+        config_file,
+        &[("input.format", input_format), ("output.format", format)],
+    );
+    let decoder = get_record_decoder(&config);
+    let settings: OutputSettings = config.settings("output");
+    let output_format = settings.format.as_deref().unwrap_or(DEFAULT_OUTPUT_FORMAT);
+    let encoder = get_format_encoder(output_format, &config);
+    let encoder = wrap_record_encoders(&config, encoder);
+    run(record, |line| {
+        let mut bytes = encoder.encode(decoder.decode_bytes(line)?)?;
+        bytes.push(b'\n');
+        Ok(bytes)
+    })
+}
+
+/// The configuration file, or an empty configuration, with the settings given on the command line
+fn load_config(config_file: Option<&str>, settings: &[(&str, Option<&str>)]) -> Config {
+    let mut config = match config_file {
+        Some(config_file) => Config::from_path(config_file)
+            .unwrap_or_else(|e| panic!("Unable to read the config file [{}]: {}", config_file, e)),
+        None => Config::from_string("").unwrap(),
+    };
+    for (path, value) in settings {
+        if let Some(value) = value {
+            config.set(path, Value::String(value.to_string()));
+        }
+    }
+    config
+}
+
+fn run<F>(record: Option<&str>, handle: F) -> usize
+where
+    F: FnMut(&[u8]) -> Result<Vec<u8>, &'static str>,
+{
+    let mut stdout = stdout().lock();
+    match record {
+        Some(record) => process(record.as_bytes(), &mut stdout, handle),
+        None => process(stdin().lock(), &mut stdout, handle),
+    }
+}
+
+/// Handle every line of the reader, writing the results, and logging the records that were rejected
+fn process<R, W, F>(reader: R, writer: &mut W, mut handle: F) -> usize
+where
+    R: BufRead,
+    W: Write,
+    F: FnMut(&[u8]) -> Result<Vec<u8>, &'static str>,
+{
+    let mut failures = 0;
+    for line in reader.split(b'\n') {
+        let line = line.expect("Unable to read the records");
+        let line = line.strip_suffix(b"\r").unwrap_or(&line);
+        if line.is_empty() {
+            continue;
+        }
+        match handle(line) {
+            Ok(bytes) => writer
+                .write_all(&bytes)
+                .and_then(|_| writer.flush())
+                .expect("Unable to write the records"),
+            Err(e) => {
+                if e != DROPPED {
+                    failures += 1;
+                }
+                log_rejected(e, line);
+            }
+        }
+    }
+    failures
+}
+
+#[cfg(all(test, feature = "rfc5424", feature = "ltsv"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_shot_decoder_side_effects() {
+        // The dead letter file would be opened, and would fail, with a running pipeline
+        let mut config = load_config(None, &[("input.format", Some("rfc5424"))]);
+        config.set("input.dead_letter", Value::String("file".to_owned()));
+        config.set(
+            "input.dead_letter_path",
+            Value::String("/nonexistent/dead-letter.log".to_owned()),
+        );
+        let decoder = get_record_decoder(&config);
+        assert!(decoder.decode("not a record").is_err());
+        let record = decoder
+            .decode("<23>1 2015-08-05T15:53:45Z example.org app 69 42 - hello")
+            .unwrap();
+        assert_eq!(record.hostname, "example.org");
+    }
+
+    #[test]
+    fn test_one_shot_encode() {
+        let config = load_config(
+            None,
+            &[("input.format", Some("rfc5424")), ("output.format", None)],
+        );
+        assert_eq!(config.lookup("output.format"), None);
+        let decoder = get_record_decoder(&config);
+        let encoder = get_format_encoder("ltsv", &config);
+        let mut output = Vec::new();
+        let failures = process(
+            &b"<23>1 2015-08-05T15:53:45Z example.org app 69 42 - hello\r\n\nnot a record\n"[..],
+            &mut output,
+            |line| encoder.encode(decoder.decode_bytes(line)?),
+        );
+        assert_eq!(failures, 1);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "host:example.org\ttime:1438790025\tmessage:hello\tfull_message:<23>1 2015-08-05T15:53:45Z example.org app 69 42 - hello\tlevel:7\tfacility:2\tappname:app\tprocid:69\tmsgid:42"
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use crate::flowgger::config::Config;
+use crate::flowgger::decoder::set_paused_by_output;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_PAUSE_INPUTS: bool = false;
+
+/// Settings of the backpressure, in the `[output]` section
+#[derive(Deserialize)]
+pub struct BackpressureSettings {
+    pause_inputs: Option<bool>,
+}
+
+/// Pauses the inputs while an output has no connection to any of its servers, so that TCP senders are
+/// slowed down by the flow control as soon as records cannot be delivered, rather than once the queue is
+/// full.
+///
+/// Shared by the workers of an output: the inputs are paused when a connection attempt fails while no
+/// other worker is connected, and resumed as soon as one of them connects again. The inputs are paused and
+/// resumed while holding the lock of the number of connections, so that a worker connecting at the same time
+/// as another one fails can't leave them paused.
+#[derive(Clone)]
+pub struct Backpressure {
+    pause_inputs: bool,
+    connections: Arc<Mutex<usize>>,
+    set_paused: Arc<dyn Fn(bool) + Send + Sync>,
+}
+
+/// An established connection, counted until it is dropped
+pub struct Connection {
+    connections: Arc<Mutex<usize>>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        *self.connections.lock().unwrap() -= 1;
+    }
+}
+
+impl Backpressure {
+    /// # Parameters
+    /// - 'output.pause_inputs': Optional. Pause the inputs while the output is disconnected from all its
+    ///   servers. Default is false: the inputs only block once the queue is full.
+    pub fn from_config(config: &Config) -> Backpressure {
+        let settings: BackpressureSettings = config.settings("output");
+        let pause_inputs = settings.pause_inputs.unwrap_or(DEFAULT_PAUSE_INPUTS);
+        Backpressure::new(pause_inputs, Arc::new(set_paused_by_output))
+    }
+
+    /// # Parameters
+    /// - 'set_paused': Pauses or resumes the inputs
+    fn new(pause_inputs: bool, set_paused: Arc<dyn Fn(bool) + Send + Sync>) -> Backpressure {
+        Backpressure {
+            pause_inputs,
+            connections: Arc::new(Mutex::new(0)),
+            set_paused,
+        }
+    }
+
+    /// A connection has been established, and records can be delivered again
+    pub fn connected(&self) -> Connection {
+        let mut connections = self.connections.lock().unwrap();
+        *connections += 1;
+        if self.pause_inputs {
+            (self.set_paused)(false);
+        }
+        Connection {
+            connections: Arc::clone(&self.connections),
+        }
+    }
+
+    /// A connection broke, or couldn't be established
+    pub fn disconnected(&self) {
+        let connections = self.connections.lock().unwrap();
+        if self.pause_inputs && *connections == 0 {
+            (self.set_paused)(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test_backpressure() {
+        let paused = Arc::new(AtomicBool::new(false));
+        let is_paused_by_output = || paused.load(Ordering::Acquire);
+        let set_paused = {
+            let paused = Arc::clone(&paused);
+            Arc::new(move |x| paused.store(x, Ordering::Release))
+        };
+        let backpressure = Backpressure::new(true, set_paused);
+        let other_worker = backpressure.clone();
+
+        let connection = backpressure.connected();
+        let other_connection = other_worker.connected();
+        drop(other_connection);
+        // Another worker is still connected
+        other_worker.disconnected();
+        assert!(!is_paused_by_output());
+
+        drop(connection);
+        backpressure.disconnected();
+        assert!(is_paused_by_output());
+        let _connection = other_worker.connected();
+        assert!(!is_paused_by_output());
+    }
+
+    #[test]
+    fn test_backpressure_config() {
+        let config = Config::from_string("[output]\npause_inputs = true\n").unwrap();
+        assert!(Backpressure::from_config(&config).pause_inputs);
+        let config = Config::from_string("[output]\n").unwrap();
+        assert!(!Backpressure::from_config(&config).pause_inputs);
+    }
+}
@@ -0,0 +1,215 @@
+use super::{notify, recv_batch, Notifier, Output, OUTPUT_BATCH_SIZE};
+use crate::flowgger::admin;
+use crate::flowgger::config::Config;
+use crate::flowgger::merger::Merger;
+use crate::flowgger::record_queue::RecordReceiver;
+use crate::flowgger::utils::threads::{self, CpuAffinity};
+use serde::Deserialize;
+use std::io::{stderr, Write};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_BLACKHOLE_THREADS: usize = 1;
+const DEFAULT_BLACKHOLE_LATENCY: u64 = 0;
+const DEFAULT_BLACKHOLE_REPORT_INTERVAL: u64 = 10;
+
+/// Number of records and bytes discarded since flowgger started
+#[derive(Default)]
+pub struct BlackholeStats {
+    records: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl BlackholeStats {
+    fn add(&self, batch: &[Vec<u8>]) {
+        let bytes: usize = batch.iter().map(Vec::len).sum();
+        self.records
+            .fetch_add(batch.len() as u64, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> (u64, u64) {
+        (
+            self.records.load(Ordering::Relaxed),
+            self.bytes.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Settings of the blackhole output, in the `[output]` section
+#[derive(Deserialize)]
+pub struct BlackholeSettings {
+    blackhole_threads: Option<NonZeroUsize>,
+    blackhole_latency: Option<u64>,
+    blackhole_report_interval: Option<u64>,
+}
+
+/// Output counting and discarding the records, to measure the ingestion performance independently of the
+/// performance of a sink, as the "null" sinks of Vector or syslog-ng.
+pub struct BlackholeOutput {
+    threads: usize,
+    latency: Duration,
+    report_interval: Duration,
+    stats: Arc<BlackholeStats>,
+    affinity: CpuAffinity,
+}
+
+impl BlackholeOutput {
+    /// # Parameters
+    /// - 'output.blackhole_threads':         Optional. Number of threads taking the records from the queue.
+    ///   Default is 1.
+    /// - 'output.blackhole_latency':         Optional. Milliseconds every batch of records is held for before
+    ///   being discarded, to simulate a sink with that latency. Default is 0.
+    /// - 'output.blackhole_report_interval': Optional. Seconds between the throughput reports written to
+    ///   stderr, 0 not to report. Default is 10.
+    ///
+    /// The counters are also reported by the "stats" command of the admin socket.
+    pub fn new(config: &Config) -> BlackholeOutput {
+        let settings: BlackholeSettings = config.settings("output");
+        let threads = settings
+            .blackhole_threads
+            .map_or(DEFAULT_BLACKHOLE_THREADS, NonZeroUsize::get);
+        let latency = settings
+            .blackhole_latency
+            .unwrap_or(DEFAULT_BLACKHOLE_LATENCY);
+        let report_interval = settings
+            .blackhole_report_interval
+            .unwrap_or(DEFAULT_BLACKHOLE_REPORT_INTERVAL);
+        BlackholeOutput {
+            threads,
+            latency: Duration::from_millis(latency),
+            report_interval: Duration::from_secs(report_interval),
+            stats: Arc::new(BlackholeStats::default()),
+            affinity: CpuAffinity::new(config, "output"),
+        }
+    }
+}
+
+impl Output for BlackholeOutput {
+    fn start(
+        &self,
+        rx: RecordReceiver,
+        merger: Option<Box<dyn Merger>>,
+        notifier: Option<Arc<dyn Notifier>>,
+    ) {
+        let admin_stats = Arc::clone(&self.stats);
+        admin::register_stats(move || {
+            let (records, bytes) = admin_stats.snapshot();
+            vec![
+                ("blackhole.records".to_owned(), records),
+                ("blackhole.bytes".to_owned(), bytes),
+            ]
+        });
+        if !self.report_interval.is_zero() {
+            report(self.report_interval, Arc::clone(&self.stats));
+        }
+        for i in 0..self.threads {
+            let rx = rx.clone();
+            let notifier = notifier.clone();
+            let stats = Arc::clone(&self.stats);
+            let latency = self.latency;
+            let merger = merger.as_ref().map(|merger| merger.clone_boxed());
+            let name = format!("flowgger-output-blackhole-{}", i);
+            threads::spawn(name, self.affinity.cpu(i), move || {
+                let mut batch = Vec::with_capacity(OUTPUT_BATCH_SIZE);
+                while recv_batch(&rx, &mut batch) {
+                    // Framed as for a real sink, so that the framing is measured, and counted in the bytes
+                    if let Some(ref merger) = merger {
+                        for bytes in batch.iter_mut() {
+                            merger.frame(bytes);
+                        }
+                    }
+                    if !latency.is_zero() {
+                        thread::sleep(latency);
+                    }
+                    stats.add(&batch);
+                    notify(&notifier, &batch, Ok(()));
+                    batch.clear();
+                }
+            });
+        }
+    }
+}
+
+/// Write the number of records and bytes discarded, and the rates since the previous report, every `interval`
+fn report(interval: Duration, stats: Arc<BlackholeStats>) {
+    threads::spawn("flowgger-blackhole-report".to_owned(), None, move || {
+        let (mut last_records, mut last_bytes) = stats.snapshot();
+        let mut last_time = Instant::now();
+        loop {
+            thread::sleep(interval);
+            let (records, bytes) = stats.snapshot();
+            let now = Instant::now();
+            let elapsed = now.duration_since(last_time).as_secs_f64();
+            let _ = writeln!(
+                stderr(),
+                "Blackhole: {} records, {} bytes discarded ({:.0} records/s, {:.0} bytes/s)",
+                records,
+                bytes,
+                (records - last_records) as f64 / elapsed,
+                (bytes - last_bytes) as f64 / elapsed
+            );
+            (last_records, last_bytes, last_time) = (records, bytes, now);
+        }
+    });
+}
+
+/// Settings of the blackhole output, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "# blackhole_threads = {}\n\
+         # Milliseconds every batch of records is held for, to simulate the latency of a sink\n\
+         # blackhole_latency = {}\n\
+         # Seconds between the throughput reports written to stderr, 0 not to report\n\
+         # blackhole_report_interval = {}\n",
+        DEFAULT_BLACKHOLE_THREADS, DEFAULT_BLACKHOLE_LATENCY, DEFAULT_BLACKHOLE_REPORT_INTERVAL
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::record_queue::unbounded;
+    use std::sync::Mutex;
+
+    struct TestNotifier(Mutex<usize>);
+
+    impl Notifier for TestNotifier {
+        fn notify(&self, records: &[Vec<u8>], result: Result<(), &str>) {
+            assert!(result.is_ok());
+            *self.0.lock().unwrap() += records.len();
+        }
+    }
+
+    #[test]
+    fn test_blackhole_output() {
+        let config = Config::from_string(
+            "[output]\nblackhole_threads = 2\nblackhole_latency = 1\nblackhole_report_interval = 0\n",
+        )
+        .unwrap();
+        let output = BlackholeOutput::new(&config);
+        let notifier = Arc::new(TestNotifier(Mutex::new(0)));
+        let (tx, rx) = unbounded();
+        for _ in 0..1000 {
+            tx.send(b"record".to_vec()).unwrap();
+        }
+        drop(tx);
+        output.start(rx, None, Some(notifier.clone()));
+        let start = Instant::now();
+        while *notifier.0.lock().unwrap() < 1000 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(output.stats.snapshot(), (1000, 6000));
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a nonzero usize for key `output.blackhole_threads`")]
+    fn test_blackhole_output_invalid_threads() {
+        let config = Config::from_string("[output]\nblackhole_threads = 0\n").unwrap();
+        BlackholeOutput::new(&config);
+    }
+}
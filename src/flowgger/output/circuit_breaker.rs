@@ -0,0 +1,431 @@
+use super::OUTPUT_BATCH_SIZE;
+use crate::flowgger::admin;
+use crate::flowgger::config::Config;
+use crate::flowgger::record_queue::{self, RecordReceiver, RecordSender};
+use crate::flowgger::utils::threads;
+#[cfg(test)]
+use crossbeam_channel::Sender;
+use crossbeam_channel::{RecvTimeoutError, SendTimeoutError, TrySendError};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, stderr, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::num::NonZeroU64;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_SPILL_THRESHOLD: u64 = 30;
+const DEFAULT_SPILL_MAX_SIZE: u64 = 1024 * 1024 * 1024;
+/// Size of the files the records are spilled to, deleted once they have been replayed
+const SPILL_SEGMENT_SIZE: u64 = 16 * 1024 * 1024;
+const SPILL_SEGMENT_EXTENSION: &str = "spill";
+/// Delay between two attempts to replay the spilled records while the output is down
+const SPILL_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Settings of the circuit breaker, in the `[output]` section
+#[derive(Deserialize)]
+pub struct SpillSettings {
+    spill_dir: Option<String>,
+    spill_threshold: Option<NonZeroU64>,
+    spill_max_size: Option<NonZeroU64>,
+}
+
+/// Circuit breaker between the queue and the output: when the output hasn't taken any record for longer than
+/// a threshold, i.e. because its servers are down, the records are spilled to disk instead of piling up in
+/// the queue until the inputs block. They are replayed in order as soon as the output takes records again,
+/// and the records received meanwhile are spilled after them.
+///
+/// Records are synced to disk after every batch spilled, and when the breaker stops, so that the records left
+/// on disk when flowgger stops are replayed when it starts again. Once the disk buffer is full, records wait
+/// in the queue, so that the inputs slow down as they would without it.
+pub struct CircuitBreaker {
+    threshold: Duration,
+    spill: SpillQueue,
+    /// Whether a record received from the queue, or spilled, hasn't been handed to the output yet
+    held: Arc<AtomicBool>,
+    /// Told the number of records of every batch spilled, for the tests to know when they are on disk
+    #[cfg(test)]
+    spilled: Option<Sender<usize>>,
+}
+
+/// Records spilled to disk, as files of records prefixed with their length
+struct SpillQueue {
+    dir: PathBuf,
+    max_size: u64,
+    segment_size: u64,
+    /// Bytes of the records that have not been replayed yet
+    size: u64,
+    /// Numbers of the files, oldest first
+    segments: VecDeque<u64>,
+    /// File being written, the last one, and its size
+    writer: Option<(BufWriter<File>, u64)>,
+    /// File being replayed, the first one
+    reader: Option<BufReader<File>>,
+}
+
+impl CircuitBreaker {
+    /// # Parameters
+    /// - 'output.spill_dir':       Optional. Directory the records are spilled to while the output is down.
+    ///   Records are never spilled when this is not set.
+    /// - 'output.spill_threshold': Optional. Number of seconds the output doesn't take any record before
+    ///   they are spilled. Default is 30.
+    /// - 'output.spill_max_size':  Optional. Maximum number of bytes of records spilled to disk. Default is
+    ///   1 GiB.
+    pub fn from_config(config: &Config) -> Option<CircuitBreaker> {
+        let settings: SpillSettings = config.settings("output");
+        let dir = settings.spill_dir?;
+        let threshold = settings
+            .spill_threshold
+            .map_or(DEFAULT_SPILL_THRESHOLD, NonZeroU64::get);
+        let max_size = settings
+            .spill_max_size
+            .map_or(DEFAULT_SPILL_MAX_SIZE, NonZeroU64::get);
+        let spill = SpillQueue::open(Path::new(&dir), max_size, SPILL_SEGMENT_SIZE)
+            .unwrap_or_else(|e| panic!("Unable to open output.spill_dir [{}]: {}", dir, e));
+        Some(CircuitBreaker {
+            threshold: Duration::from_secs(threshold),
+            spill,
+            held: Arc::new(AtomicBool::new(false)),
+            #[cfg(test)]
+            spilled: None,
+        })
+    }
+
+    /// Forward the records of `rx` to the returned receiver, through the disk while the output is down
+    pub fn start(mut self, rx: RecordReceiver) -> RecordReceiver {
+        let (tx, breaker_rx) = record_queue::bounded(OUTPUT_BATCH_SIZE);
+        let (held, output_rx) = (Arc::clone(&self.held), breaker_rx.occupancy());
+        admin::register_pending(move || held.load(Ordering::Acquire) || !output_rx.is_empty());
+        threads::spawn(
+            "flowgger-output-circuit-breaker".to_owned(),
+            None,
+            move || {
+                self.run(&rx, &tx);
+                if let Err(e) = self.spill.sync() {
+                    let _ = writeln!(stderr(), "Unable to sync the spilled records: {}", e);
+                }
+            },
+        );
+        breaker_rx
+    }
+
+    fn run(&mut self, rx: &RecordReceiver, tx: &RecordSender) {
+        // Record read from the disk, that the output couldn't take yet
+        let mut pending = None;
+        let mut replayed = 0;
+        loop {
+            self.held.store(
+                pending.is_some() || !self.spill.is_empty(),
+                Ordering::Release,
+            );
+            if pending.is_none() && self.spill.is_empty() {
+                let bytes = match rx.recv() {
+                    Ok(bytes) => bytes,
+                    Err(_) => return,
+                };
+                self.held.store(true, Ordering::Release);
+                match tx.send_timeout(bytes, self.threshold) {
+                    Ok(()) => {}
+                    Err(SendTimeoutError::Timeout(bytes)) => {
+                        let _ = writeln!(
+                            stderr(),
+                            "Output unavailable for {} seconds, spilling the records to {}",
+                            self.threshold.as_secs(),
+                            self.spill.dir.display()
+                        );
+                        self.spill_batch(bytes, rx, tx);
+                    }
+                    Err(SendTimeoutError::Disconnected(_)) => return,
+                }
+                continue;
+            }
+            loop {
+                let bytes = match pending.take() {
+                    Some(bytes) => bytes,
+                    None => match self.spill.pop() {
+                        Ok(Some(bytes)) => bytes,
+                        Ok(None) => break,
+                        Err(e) => {
+                            let _ =
+                                writeln!(stderr(), "Unable to replay the spilled records: {}", e);
+                            break;
+                        }
+                    },
+                };
+                match tx.try_send(bytes) {
+                    Ok(()) => replayed += 1,
+                    Err(TrySendError::Full(bytes)) => {
+                        pending = Some(bytes);
+                        break;
+                    }
+                    Err(TrySendError::Disconnected(_)) => return,
+                }
+            }
+            if pending.is_none() && self.spill.is_empty() {
+                let _ = writeln!(
+                    stderr(),
+                    "Output available again, {} spilled records replayed",
+                    replayed
+                );
+                replayed = 0;
+                continue;
+            }
+            if self.spill.is_full() {
+                thread::sleep(SPILL_RETRY_INTERVAL);
+                continue;
+            }
+            match rx.recv_timeout(SPILL_RETRY_INTERVAL) {
+                Ok(bytes) => self.spill_batch(bytes, rx, tx),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(SPILL_RETRY_INTERVAL),
+            }
+        }
+    }
+
+    /// Spill a record along with the records received meanwhile, up to a batch, and sync them to disk
+    fn spill_batch(&mut self, bytes: Vec<u8>, rx: &RecordReceiver, tx: &RecordSender) {
+        self.spill(bytes, tx);
+        let mut count = 1;
+        while count < OUTPUT_BATCH_SIZE && !self.spill.is_full() {
+            match rx.try_recv() {
+                Ok(bytes) => self.spill(bytes, tx),
+                Err(_) => break,
+            }
+            count += 1;
+        }
+        if let Err(e) = self.spill.sync() {
+            let _ = writeln!(stderr(), "Unable to sync the spilled records: {}", e);
+        }
+        #[cfg(test)]
+        if let Some(ref spilled) = self.spilled {
+            let _ = spilled.send(count);
+        }
+    }
+
+    /// Spill a record, or wait for the output to take it if it can't be written to disk
+    fn spill(&mut self, bytes: Vec<u8>, tx: &RecordSender) {
+        if let Err(e) = self.spill.push(&bytes) {
+            let _ = writeln!(
+                stderr(),
+                "Unable to spill a record, waiting for the output: {}",
+                e
+            );
+            let _ = tx.send(bytes);
+        }
+    }
+}
+
+impl SpillQueue {
+    /// Open the directory, along with the records that were spilled before a restart
+    fn open(dir: &Path, max_size: u64, segment_size: u64) -> io::Result<SpillQueue> {
+        fs::create_dir_all(dir)?;
+        let mut segments = Vec::new();
+        let mut size = 0;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|x| x.to_str()) != Some(SPILL_SEGMENT_EXTENSION) {
+                continue;
+            }
+            if let Some(segment) = path
+                .file_stem()
+                .and_then(|x| x.to_str())
+                .and_then(|x| x.parse::<u64>().ok())
+            {
+                size += fs::metadata(&path)?.len();
+                segments.push(segment);
+            }
+        }
+        segments.sort_unstable();
+        Ok(SpillQueue {
+            dir: dir.to_owned(),
+            max_size,
+            segment_size,
+            size,
+            segments: segments.into(),
+            writer: None,
+            reader: None,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.size >= self.max_size
+    }
+
+    fn segment_path(&self, segment: u64) -> PathBuf {
+        self.dir
+            .join(format!("{:020}.{}", segment, SPILL_SEGMENT_EXTENSION))
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self
+            .writer
+            .as_ref()
+            .is_none_or(|(_, len)| *len >= self.segment_size)
+        {
+            self.flush()?;
+            let segment = self.segments.back().map_or(0, |segment| segment + 1);
+            let file = OpenOptions::new()
+                .create_new(true)
+                .append(true)
+                .open(self.segment_path(segment))?;
+            self.segments.push_back(segment);
+            self.writer = Some((BufWriter::new(file), 0));
+        }
+        let (writer, len) = self.writer.as_mut().unwrap();
+        writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        writer.write_all(bytes)?;
+        *len += 4 + bytes.len() as u64;
+        self.size += 4 + bytes.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.writer {
+            Some((ref mut writer, _)) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Flush the records written, and wait for them to be on disk
+    fn sync(&mut self) -> io::Result<()> {
+        self.flush()?;
+        match self.writer {
+            Some((ref writer, _)) => writer.get_ref().sync_data(),
+            None => Ok(()),
+        }
+    }
+
+    /// The oldest record, removing the files that have been replayed
+    fn pop(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            let segment = match self.segments.front() {
+                Some(&segment) => segment,
+                None => {
+                    self.size = 0;
+                    return Ok(None);
+                }
+            };
+            let is_written = self.writer.is_some() && self.segments.len() == 1;
+            if is_written {
+                self.flush()?;
+            }
+            if self.reader.is_none() {
+                self.reader = Some(BufReader::new(File::open(self.segment_path(segment))?));
+            }
+            let reader = self.reader.as_mut().unwrap();
+            match read_record(reader) {
+                Ok(Some(bytes)) => {
+                    self.size = self.size.saturating_sub(4 + bytes.len() as u64);
+                    return Ok(Some(bytes));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    let _ = writeln!(
+                        stderr(),
+                        "Truncated spill file, skipping the rest of it: {}",
+                        e
+                    );
+                }
+            }
+            // The file has been replayed. The last one can only be removed once the buffer of the writer has
+            // been flushed to it, so that it is empty then.
+            self.reader = None;
+            if is_written {
+                self.writer = None;
+            }
+            self.segments.pop_front();
+            fs::remove_file(self.segment_path(segment))?;
+        }
+    }
+}
+
+/// Read a record prefixed with its length, or `None` at the end of the file
+fn read_record(reader: &mut BufReader<File>) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut bytes = vec![0; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(Some(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_spill_queue() {
+        let temp_dir = TempDir::new("test_spill_queue").unwrap();
+        let mut spill = SpillQueue::open(temp_dir.path(), 30, 20).unwrap();
+        assert!(spill.is_empty());
+        for record in ["one", "two", "three", "four"] {
+            spill.push(record.as_bytes()).unwrap();
+        }
+        assert_eq!(spill.segments.len(), 2);
+        assert!(spill.is_full());
+        assert_eq!(spill.pop().unwrap().unwrap(), b"one");
+        assert_eq!(spill.pop().unwrap().unwrap(), b"two");
+        spill.push(b"five").unwrap();
+        spill.flush().unwrap();
+
+        // After a restart, the files that were not entirely replayed are replayed again
+        let mut spill = SpillQueue::open(temp_dir.path(), 30, 20).unwrap();
+        let mut records = Vec::new();
+        while let Some(bytes) = spill.pop().unwrap() {
+            records.push(String::from_utf8(bytes).unwrap());
+        }
+        assert_eq!(records, ["one", "two", "three", "four", "five"]);
+        assert!(spill.is_empty());
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let temp_dir = TempDir::new("test_circuit_breaker").unwrap();
+        let (spilled_tx, spilled_rx) = crossbeam_channel::bounded(10);
+        let mut breaker = CircuitBreaker {
+            threshold: Duration::from_millis(10),
+            spill: SpillQueue::open(temp_dir.path(), 1024, 1024).unwrap(),
+            held: Arc::new(AtomicBool::new(false)),
+            spilled: Some(spilled_tx),
+        };
+        let (tx, rx) = record_queue::bounded(10);
+        // The output is down, its queue being full, until the test reads it
+        let (output_tx, output_rx) = record_queue::bounded(1);
+        output_tx.send(b"zero".to_vec()).unwrap();
+        for record in ["one", "two", "three"] {
+            tx.send(record.as_bytes().to_vec()).unwrap();
+        }
+        drop(tx);
+        let breaker = thread::spawn(move || {
+            breaker.run(&rx, &output_tx);
+            breaker
+        });
+
+        let mut spilled = 0;
+        while spilled < 3 {
+            spilled += spilled_rx.recv().unwrap();
+        }
+        // The spilled records are on disk, and not in the buffer of the writer
+        let spill = SpillQueue::open(temp_dir.path(), 1024, 1024).unwrap();
+        assert_eq!(spill.size, 3 * 4 + 11);
+
+        let records: Vec<_> = output_rx
+            .iter()
+            .map(|bytes| String::from_utf8(bytes).unwrap())
+            .collect();
+        assert_eq!(records, ["zero", "one", "two", "three"]);
+        assert!(breaker.join().unwrap().spill.is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+use crate::flowgger::config::Config;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use serde::Deserialize;
+use std::io::{self, Write};
+
+/// Settings of the compression, in the `[output]` section
+#[derive(Deserialize)]
+pub struct CompressSettings {
+    compress: Option<String>,
+    compress_level: Option<i64>,
+}
+
+/// Compression of the streams sent by the TLS output, for the "input.decompress" setting of the receiving
+/// flowgger
+///
+/// The compressor is flushed (`Z_SYNC_FLUSH`, or the end of a zstd block) along with the connection, so
+/// that the receiver can decompress the records without waiting for the end of the stream.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip(u32),
+    Zlib(u32),
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl Compression {
+    /// # Parameters
+    /// - 'output.compress':       Optional. "gzip", "zlib" or "zstd" (requires the "zstd" feature).
+    ///   Default is no compression.
+    /// - 'output.compress_level': Optional. Compression level, 0 to 9 for gzip and zlib, 1 to 22 for zstd.
+    ///   Default is 6 for gzip and zlib, and 3 for zstd.
+    pub fn from_config(config: &Config) -> Compression {
+        let settings: CompressSettings = config.settings("output");
+        let compress = match settings.compress {
+            None => return Compression::None,
+            Some(compress) => compress,
+        };
+        let level = settings.compress_level;
+        let flate2_level = || match level {
+            None => flate2::Compression::default().level(),
+            Some(level @ 0..=9) => level as u32,
+            Some(_) => panic!("output.compress_level must be between 0 and 9 with gzip and zlib"),
+        };
+        match compress.as_str() {
+            "none" => Compression::None,
+            "gzip" => Compression::Gzip(flate2_level()),
+            "zlib" => Compression::Zlib(flate2_level()),
+            #[cfg(feature = "zstd")]
+            "zstd" => Compression::Zstd(match level {
+                None => zstd::DEFAULT_COMPRESSION_LEVEL,
+                Some(level @ 1..=22) => level as i32,
+                Some(_) => panic!("output.compress_level must be between 1 and 22 with zstd"),
+            }),
+            #[cfg(not(feature = "zstd"))]
+            "zstd" => panic!("Support for zstd is not compiled in"),
+            _ => panic!(r#"output.compress must be a string set to "gzip", "zlib" or "zstd""#),
+        }
+    }
+
+    /// Wrap a stream so that what is written to it gets compressed
+    pub fn writer<'a, W: Write + 'a>(self, stream: W) -> io::Result<Box<dyn Write + 'a>> {
+        Ok(match self {
+            Compression::None => Box::new(stream),
+            Compression::Gzip(level) => {
+                Box::new(GzEncoder::new(stream, flate2::Compression::new(level)))
+            }
+            Compression::Zlib(level) => {
+                Box::new(ZlibEncoder::new(stream, flate2::Compression::new(level)))
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => Box::new(zstd::stream::write::Encoder::new(stream, level)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::{MultiGzDecoder, ZlibDecoder};
+    use std::io::Read;
+
+    /// What has been sent after writing a record and flushing, the connection staying open
+    fn flushed(config: &str) -> Vec<u8> {
+        let config = Config::from_string(config).unwrap();
+        let mut compressed = Vec::new();
+        let mut writer = Compression::from_config(&config)
+            .writer(&mut compressed)
+            .unwrap();
+        writer.write_all(b"line1\n").unwrap();
+        writer.flush().unwrap();
+        // Not finishing the stream, as a connection that is still open
+        std::mem::forget(writer);
+        compressed
+    }
+
+    #[test]
+    fn test_compress_config() {
+        let config = Config::from_string("[output]\n").unwrap();
+        assert_eq!(Compression::from_config(&config), Compression::None);
+        let config =
+            Config::from_string("[output]\ncompress = \"zlib\"\ncompress_level = 9\n").unwrap();
+        assert_eq!(Compression::from_config(&config), Compression::Zlib(9));
+    }
+
+    #[test]
+    #[should_panic(expected = "output.compress_level must be between 0 and 9 with gzip and zlib")]
+    fn test_compress_config_invalid_level() {
+        let config =
+            Config::from_string("[output]\ncompress = \"gzip\"\ncompress_level = 12\n").unwrap();
+        Compression::from_config(&config);
+    }
+
+    #[test]
+    fn test_compress_flush() {
+        let mut decompressed = [0; 6];
+        let compressed = flushed("[output]\ncompress = \"gzip\"\n");
+        MultiGzDecoder::new(&compressed[..])
+            .read_exact(&mut decompressed)
+            .unwrap();
+        assert_eq!(&decompressed, b"line1\n");
+
+        let compressed = flushed("[output]\ncompress = \"zlib\"\n");
+        ZlibDecoder::new(&compressed[..])
+            .read_exact(&mut decompressed)
+            .unwrap();
+        assert_eq!(&decompressed, b"line1\n");
+
+        assert_eq!(flushed("[output]\n"), b"line1\n");
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_compress_flush_zstd() {
+        let mut decompressed = [0; 6];
+        let compressed = flushed("[output]\ncompress = \"zstd\"\n");
+        zstd::stream::read::Decoder::new(&compressed[..])
+            .unwrap()
+            .read_exact(&mut decompressed)
+            .unwrap();
+        assert_eq!(&decompressed, b"line1\n");
+    }
+}
@@ -1,36 +1,53 @@
-use super::Output;
+use super::{notify, recv_batch, Notifier, Output, OUTPUT_BATCH_SIZE};
 use crate::flowgger::config::Config;
 use crate::flowgger::merger::Merger;
+use crate::flowgger::record_queue::RecordReceiver;
+use crate::flowgger::utils::threads::{self, CpuAffinity};
 use std::io::{stdout, Write};
-use std::sync::mpsc::Receiver;
-use std::sync::{Arc, Mutex};
-use std::thread;
+use std::sync::Arc;
 
-pub struct DebugOutput;
+pub struct DebugOutput {
+    affinity: CpuAffinity,
+}
 
 impl DebugOutput {
-    pub fn new(_config: &Config) -> DebugOutput {
-        DebugOutput
+    pub fn new(config: &Config) -> DebugOutput {
+        DebugOutput {
+            affinity: CpuAffinity::new(config, "output"),
+        }
     }
 }
 
 impl Output for DebugOutput {
-    fn start(&self, arx: Arc<Mutex<Receiver<Vec<u8>>>>, merger: Option<Box<dyn Merger>>) {
-        let merger = match merger {
-            Some(merger) => Some(merger.clone_boxed()),
-            None => None,
-        };
-        thread::spawn(move || loop {
-            let mut bytes = match { arx.lock().unwrap().recv() } {
-                Ok(line) => line,
-                Err(_) => return,
-            };
-            if let Some(ref merger) = merger {
-                merger.frame(&mut bytes);
+    fn start(
+        &self,
+        rx: RecordReceiver,
+        merger: Option<Box<dyn Merger>>,
+        notifier: Option<Arc<dyn Notifier>>,
+    ) {
+        let merger = merger.map(|merger| merger.clone_boxed());
+        let name = "flowgger-output-debug".to_owned();
+        threads::spawn(name, self.affinity.cpu(0), move || {
+            let mut batch = Vec::with_capacity(OUTPUT_BATCH_SIZE);
+            while recv_batch(&rx, &mut batch) {
+                for bytes in batch.iter_mut() {
+                    if let Some(ref merger) = merger {
+                        merger.frame(bytes);
+                    }
+                    let out = String::from_utf8_lossy(bytes);
+                    print!("{}", out);
+                }
+                match stdout().flush() {
+                    Ok(_) => notify(&notifier, &batch, Ok(())),
+                    Err(_) => notify(&notifier, &batch, Err("Cannot flush stdout")),
+                }
+                batch.clear();
             }
-            let out = String::from_utf8_lossy(&bytes);
-            print!("{}", out);
-            let _ = stdout().flush();
         });
     }
 }
+
+/// Settings of the debug output, for `flowgger config init`
+pub fn sample_config() -> String {
+    "# Records are written to stdout\n".to_owned()
+}
@@ -1,12 +1,25 @@
-use super::Output;
+use super::hash_chain::{hash_chain_key, HashChain};
+use super::{notify, recv_batch, Notifier, Output, OUTPUT_BATCH_SIZE};
 use crate::flowgger::config::Config;
+#[cfg(unix)]
+use crate::flowgger::daemon;
+use crate::flowgger::encoder::split_fields;
 use crate::flowgger::merger::Merger;
-use crate::flowgger::utils::rotating_file::RotatingFile;
+use crate::flowgger::record_queue::RecordReceiver;
+#[cfg(unix)]
+use crate::flowgger::utils::rotating_file::request_rotation;
+use crate::flowgger::utils::rotating_file::{FileCompression, RotatingFile, RotationCalendar};
+use crate::flowgger::utils::threads::{self, CpuAffinity};
 use crate::flowgger::validate_time_format_input;
-use std::io::{BufWriter, Write};
-use std::sync::mpsc::Receiver;
-use std::sync::{Arc, Mutex};
-use std::thread;
+use serde::Deserialize;
+use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
+#[cfg(unix)]
+use std::sync::Once;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use time_tz::timezones::get_by_name;
+use time_tz::Tz;
 
 use std::io::stderr;
 const FILE_DEFAULT_BUFFER_SIZE: usize = 0;
@@ -14,91 +27,132 @@ const FILE_DEFAULT_TIME_FORMAT: &str = "[year][month][day]T[hour][minute][second
 const FILE_DEFAULT_ROTATION_SIZE: usize = 0;
 const FILE_DEFAULT_ROTATION_TIME: u32 = 0;
 const FILE_DEFAULT_ROTATION_MAXFILES: i32 = 50;
+/// Records with a severity up to this one (error) go to the errors file when splitting by severity
+const FILE_SPLIT_MAX_ERROR_SEVERITY: u8 = 3;
 
-/// Output of type file, to store the data to a file
+#[cfg(unix)]
+static ROTATION_SIGNAL: Once = Once::new();
+
+#[cfg(unix)]
+extern "C" fn rotate_on_signal(_signal: libc::c_int) {
+    request_rotation();
+}
+
+/// Settings of the file output, in the `[output]` section
+#[derive(Deserialize)]
+pub struct FileSettings {
+    file_path: Option<String>,
+    file_path_template: Option<String>,
+    file_buffer_size: Option<usize>,
+    file_rotation_size: Option<usize>,
+    file_rotation_time: Option<u32>,
+    file_rotation_maxfiles: Option<i32>,
+    file_rotation_timeformat: Option<String>,
+    file_compression: Option<String>,
+    file_rotation_manifest: Option<bool>,
+    file_rotation_calendar: Option<String>,
+    file_rotation_timezone: Option<String>,
+    file_split_by: Option<String>,
+    file_errors_path: Option<String>,
+    framing: Option<String>,
+}
+
+/// Output of type file, to store the data to a file.
+/// On Unix, sending SIGUSR1 to the process rotates the file before the next write, or closes and opens it again
+/// when no rotation trigger is configured, so that logrotate can move it away and signal flowgger.
+/// With 'output.file_split_by = "severity"', errors go to a second file, rotated the same way.
+/// With 'output.file_hash_chain_key', every line ends with a tag chaining it to the previous line, that
+/// `flowgger verify-chain` checks.
 pub struct FileOutput {
     path: String,
+    errors_path: Option<String>,
     buffer_size: usize,
     rotation_size: usize,
     rotation_time: u32,
     rotation_maxfiles: i32,
     time_format: String,
+    compression: FileCompression,
+    manifest: bool,
+    calendar: Option<RotationCalendar>,
+    timezone: Option<&'static Tz>,
+    name_template: bool,
+    hash_chain_key: Option<Vec<u8>>,
+    affinity: CpuAffinity,
 }
 
 impl FileOutput {
     /// Create a new file output, using the configuration in the Config object
     /// Required elements:
-    /// - 'output.file_path':               Must be a string. Path of the output file.
+    /// - 'output.file_path':               Must be a string. Path of the output file. Not required if
+    ///   'output.file_path_template' is set.
     ///
     /// Optional:
     /// - 'output.file_buffer_size':        Must be an integer. Default is 0. If not 0, enables file buffering.
-    ///                                     Data are only flushed to the file once the buffer isize is reached
+    ///   Data are only flushed to the file once the buffer isize is reached
     /// - 'output.file_rotation_size':      Must be an integer. Default is 0. If not 0, enables file rotation.
-    ///                                     Files are rotated when this size is reached.
+    ///   Files are rotated when this size is reached.
     /// - 'output.file_rotation_time':      Must be an integer. Default is 0. If not 0, enables file rotation.
-    ///                                     Files are rotated after that period of time elapsed between writes.
+    ///   Files are rotated after that period of time elapsed between writes.
     /// - 'output.file_rotation_maxfiles':  Must be an integer. Default is 2. Specifies count rotated files.
-    ///                                     Unused if rotation is not enabled.
+    ///   Unused if rotation is not enabled.
     /// - 'output.file_rotation_maxfiles':  Must be an integer. Default is 2. Specifies count rotated files.
-    ///                                     Unused if rotation is not enabled.
+    ///   Unused if rotation is not enabled.
     /// - 'output.file_rotation_timeformat':Must be a String. Default is set to "[year][month][day]T[hour][minute][second]Z".
-    ///                                     When time rotation is enabled, format of the timestamp added to the
-    ///                                     https://docs.rs/time/0.3.7/time/format_description/index.html
+    ///   When time rotation is enabled, format of the timestamp added to the
+    ///   https://docs.rs/time/0.3.7/time/format_description/index.html
+    /// - 'output.file_compression':        Must be a string. Default is "none". "gzip" or "zstd" (requires the
+    ///   "zstd" feature) to compress the files as they are written. The file path should end with the matching
+    ///   extension, that rotated files keep.
+    /// - 'output.file_rotation_manifest':  Must be a boolean. Default is false. Write a manifest next to every
+    ///   rotated file, with its name, the timestamps of its oldest and newest records, its record count and its
+    ///   SHA-256.
+    ///   Unused if rotation is not enabled, and incompatible with 'output.file_buffer_size'.
+    /// - 'output.file_rotation_calendar':  Must be a string. "hourly" or "daily" to rotate at every hour or at
+    ///   midnight, instead of every 'output.file_rotation_time' minutes. Files are named after the start of
+    ///   their period.
+    /// - 'output.file_rotation_timezone':  Must be a string. Default is "UTC". Time zone of the calendar
+    ///   boundaries and of the timestamps in the file names, i.e. "Europe/Paris".
+    /// - 'output.file_path_template':      Must be a string. Template of the whole path, used instead of
+    ///   'output.file_path', i.e. "logs/app-%Y/%m/%d/%H.log". %Y, %m, %d, %H, %M, %S and %j are replaced with
+    ///   the start of the period of the file, and missing directories are created. Requires a time or calendar
+    ///   rotation if 'output.file_rotation_size' is set.
+    /// - 'output.file_split_by':           Must be a string. "severity" to write the records with a severity
+    ///   of 3 (error) or lower to a separate file, and every other record, including the ones without a
+    ///   severity, to 'output.file_path'.
+    /// - 'output.file_errors_path':        Must be a string. Path of the errors file when splitting by severity.
+    ///   Default is the file path with ".errors" inserted before its extension, i.e. "output.errors.log".
+    /// - 'output.file_hash_chain_key':     Must be a string. Key of the HMAC-SHA256 appended to every line as
+    ///   " chain=<hex>", computed over the tag of the previous line and the line itself. The chain continues
+    ///   from the last line of the file when it is opened again, unless it is compressed or named after a
+    ///   template. Requires 'output.framing = "line"'.
     /// # Parameters
     /// - 'Config':  Configuration parameters
     ///
     pub fn new(config: &Config) -> FileOutput {
-        let path = config
-            .lookup("output.file_path")
-            .expect("output.file_path is missing")
-            .as_str()
-            .expect("output.file_path must be a string")
-            .to_string();
-        let buffer_size =
-            config
-                .lookup("output.file_buffer_size")
-                .map_or(FILE_DEFAULT_BUFFER_SIZE, |bs| {
-                    bs.as_integer()
-                        .expect("output.file_buffer_size should be an integer")
-                        as usize
-                });
+        let settings: FileSettings = config.settings("output");
+        let name_template = settings.file_path_template.is_some();
+        let path = settings
+            .file_path_template
+            .or(settings.file_path)
+            .expect("output.file_path is missing");
+        let buffer_size = settings
+            .file_buffer_size
+            .unwrap_or(FILE_DEFAULT_BUFFER_SIZE);
         // Get the optional file rotation size. if none, set it to 0 to disable the feature
-        let rotation_size = config.lookup("output.file_rotation_size").map_or(
-            FILE_DEFAULT_ROTATION_SIZE,
-            |rot_size| {
-                rot_size
-                    .as_integer()
-                    .expect("output.file_rotation_size should be an integer")
-                    as usize
-            },
-        );
+        let rotation_size = settings
+            .file_rotation_size
+            .unwrap_or(FILE_DEFAULT_ROTATION_SIZE);
         // Get the optional file rotation time. if none, set it to 0 to disable the feature
-        let rotation_time = config.lookup("output.file_rotation_time").map_or(
-            FILE_DEFAULT_ROTATION_TIME,
-            |rot_time| {
-                rot_time
-                    .as_integer()
-                    .expect("output.file_rotation_time should be an integer") as u32
-            },
-        );
+        let rotation_time = settings
+            .file_rotation_time
+            .unwrap_or(FILE_DEFAULT_ROTATION_TIME);
         // Get the optional file rotation max files. Default is 2
-        let rotation_maxfiles = config.lookup("output.file_rotation_maxfiles").map_or(
-            FILE_DEFAULT_ROTATION_MAXFILES,
-            |rot_size| {
-                rot_size
-                    .as_integer()
-                    .expect("output.file_rotation_maxfiles should be an integer")
-                    as i32
-            },
-        );
-        let time_format = config.lookup("output.file_rotation_timeformat").map_or(
-            FILE_DEFAULT_TIME_FORMAT.to_string(),
-            |bs| {
-                bs.as_str()
-                    .expect("output.file_rotation_timeformat should be a string")
-                    .to_string()
-            },
-        );
+        let rotation_maxfiles = settings
+            .file_rotation_maxfiles
+            .unwrap_or(FILE_DEFAULT_ROTATION_MAXFILES);
+        let time_format = settings
+            .file_rotation_timeformat
+            .unwrap_or_else(|| FILE_DEFAULT_TIME_FORMAT.to_string());
 
         let time_format = validate_time_format_input(
             "file_rotation_timeformat",
@@ -106,13 +160,67 @@ impl FileOutput {
             FILE_DEFAULT_TIME_FORMAT.to_string(),
         );
 
+        let compression = match settings.file_compression.as_deref().unwrap_or("none") {
+            "none" => FileCompression::None,
+            "gzip" => FileCompression::Gzip,
+            #[cfg(feature = "zstd")]
+            "zstd" => FileCompression::Zstd,
+            #[cfg(not(feature = "zstd"))]
+            "zstd" => panic!("Support for zstd is not compiled in"),
+            _ => panic!(r#"output.file_compression must be "none", "gzip" or "zstd""#),
+        };
+
+        let manifest = settings.file_rotation_manifest.unwrap_or(false);
+        if manifest && buffer_size > 0 {
+            panic!("output.file_rotation_manifest can't be used with output.file_buffer_size, records would not be counted");
+        }
+
+        let calendar = settings
+            .file_rotation_calendar
+            .map(|calendar| match calendar.as_str() {
+                "hourly" => RotationCalendar::Hourly,
+                "daily" => RotationCalendar::Daily,
+                _ => panic!(r#"output.file_rotation_calendar must be "hourly" or "daily""#),
+            });
+        let timezone = settings.file_rotation_timezone.map(|name| {
+            get_by_name(&name)
+                .unwrap_or_else(|| panic!("Unknown output.file_rotation_timezone: {}", name))
+        });
+
+        let errors_path = match settings.file_split_by.as_deref() {
+            None => None,
+            Some("severity") => Some(
+                settings
+                    .file_errors_path
+                    .unwrap_or_else(|| errors_path(&path)),
+            ),
+            Some(_) => panic!(r#"output.file_split_by must be "severity""#),
+        };
+
+        if name_template && rotation_size > 0 && rotation_time == 0 && calendar.is_none() {
+            panic!("output.file_path_template requires output.file_rotation_time or output.file_rotation_calendar when output.file_rotation_size is set");
+        }
+
+        let hash_chain_key = hash_chain_key(config);
+        if hash_chain_key.is_some() && settings.framing.as_deref() != Some("line") {
+            panic!(r#"output.file_hash_chain_key requires output.framing = "line""#);
+        }
+
         FileOutput {
             path,
+            errors_path,
             buffer_size,
             rotation_size,
             rotation_time,
             rotation_maxfiles,
             time_format,
+            compression,
+            manifest,
+            calendar,
+            timezone,
+            name_template,
+            hash_chain_key,
+            affinity: CpuAffinity::new(config, "output"),
         }
     }
 
@@ -131,66 +239,141 @@ impl FileOutput {
     /// # Errors
     /// Explain when an error value is returned (see also “Returns” in the next section)
     ///
-    fn open_writer(&self) -> Option<Box<dyn Write + Send>> {
-        let file_writer: Option<Box<dyn Write + Send>>;
+    fn open_writer(&self) -> Option<Box<dyn FileWrite>> {
+        self.open_path_writer(&self.path)
+    }
 
-        // Rotation option is enabled, open a rotating file writer
+    fn open_path_writer(&self, path: &str) -> Option<Box<dyn FileWrite>> {
+        // Files are always opened as rotating files, so that they can be rotated or opened again on request
         let mut rotating_file = RotatingFile::new(
-            &self.path,
+            path,
             self.rotation_size,
             self.rotation_time,
             self.rotation_maxfiles,
             &self.time_format,
-        );
+        )
+        .with_compression(self.compression)
+        .with_calendar(self.calendar, self.timezone)
+        .with_name_template(self.name_template);
         if rotating_file.is_enabled() {
-            file_writer = match rotating_file.open() {
-                Ok(_) => Some(Box::new(rotating_file)),
-                Err(e) => {
-                    let _ = writeln!(
-                        stderr(),
-                        "Unable to open rotating file {}: {}",
-                        &self.path,
-                        e
-                    );
-                    None
-                }
-            }
+            rotating_file = rotating_file.with_manifest(self.manifest);
         }
-        // Open a standard file writer
-        else {
-            file_writer = match RotatingFile::open_file(&self.path) {
-                Ok(file) => Some(Box::new(file)),
-                Err(e) => {
-                    let _ = writeln!(stderr(), "Unable to open file {}: {}", &self.path, e);
-                    None
-                }
-            }
+        if let Err(e) = rotating_file.open() {
+            let _ = writeln!(stderr(), "Unable to open file {}: {}", path, e);
+            return None;
         }
         // Return bufferized output if option is enabled
-        if (file_writer.is_some()) && (self.buffer_size > 0) {
+        if self.buffer_size > 0 {
             Some(Box::new(BufWriter::with_capacity(
                 self.buffer_size,
-                file_writer.unwrap(),
+                rotating_file,
             )))
         } else {
-            file_writer
+            Some(Box::new(rotating_file))
+        }
+    }
+
+    /// Hash chain of a file, continuing from its last line if it can be read back
+    fn open_hash_chain(&self, key: &[u8], path: &str) -> HashChain {
+        if self.compression == FileCompression::None && !self.name_template {
+            HashChain::resume(key, path)
+        } else {
+            HashChain::new(key)
         }
     }
 }
 
+/// Default path of the errors file: ".errors" inserted before the extensions of the file name
+fn errors_path(path: &str) -> String {
+    let name_start = path.rfind('/').map_or(0, |i| i + 1);
+    match path[name_start..].find('.').filter(|&i| i > 0) {
+        Some(i) => format!(
+            "{}.errors{}",
+            &path[..name_start + i],
+            &path[name_start + i..]
+        ),
+        None => format!("{}.errors", path),
+    }
+}
+
+/// Writer of an output file, that can wait until the data written to it have been stored on disk
+trait FileWrite: Write + Send {
+    fn sync_data(&mut self) -> io::Result<()>;
+
+    /// Set the timestamp of the record that the next write contains, for the manifest
+    fn set_record_timestamp(&mut self, ts: Option<OffsetDateTime>);
+}
+
+impl FileWrite for RotatingFile {
+    fn sync_data(&mut self) -> io::Result<()> {
+        RotatingFile::sync_data(self)
+    }
+
+    fn set_record_timestamp(&mut self, ts: Option<OffsetDateTime>) {
+        RotatingFile::set_record_timestamp(self, ts)
+    }
+}
+
+impl<W: FileWrite> FileWrite for BufWriter<W> {
+    fn sync_data(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.get_mut().sync_data()
+    }
+
+    fn set_record_timestamp(&mut self, ts: Option<OffsetDateTime>) {
+        self.get_mut().set_record_timestamp(ts)
+    }
+}
+
+/// Remove the fields prepended by `FieldsEncoder`, and return whether the record is an error if `split_errors`
+/// is set, and its timestamp if `manifest` is set
+fn take_fields(
+    bytes: &mut Vec<u8>,
+    split_errors: bool,
+    manifest: bool,
+) -> (bool, Option<OffsetDateTime>) {
+    let count = split_errors as usize + manifest as usize;
+    if count == 0 {
+        return (false, None);
+    }
+    let (is_error, ts, fields_len) = match split_fields(bytes, count) {
+        Ok((values, payload)) => {
+            let mut values = values
+                .into_iter()
+                .map(|value| value.and_then(|value| std::str::from_utf8(value).ok()));
+            let is_error = split_errors
+                && values
+                    .next()
+                    .flatten()
+                    .and_then(|severity| severity.parse::<u8>().ok())
+                    .is_some_and(|severity| severity <= FILE_SPLIT_MAX_ERROR_SEVERITY);
+            let ts = values
+                .next()
+                .flatten()
+                .and_then(|ts| OffsetDateTime::parse(ts, &Rfc3339).ok());
+            (is_error, ts, bytes.len() - payload.len())
+        }
+        Err(_) => return (false, None),
+    };
+    bytes.drain(..fields_len);
+    (is_error, ts)
+}
+
 /// Implements the Output traits (flowgger::Output) to allow FileOutput to be used as a flowgger data output
 impl Output for FileOutput {
     /// Start a thread listening to the specified synchronized input and writing data to a file once received.
     /// See flowgger::Output trait for arguments description
     ///
-    fn start(&self, arx: Arc<Mutex<Receiver<Vec<u8>>>>, merger: Option<Box<dyn Merger>>) {
-        let merger = match merger {
-            Some(merger) => Some(merger.clone_boxed()),
-            None => None,
-        };
+    fn start(
+        &self,
+        rx: RecordReceiver,
+        merger: Option<Box<dyn Merger>>,
+        notifier: Option<Arc<dyn Notifier>>,
+    ) {
+        let merger = merger.map(|merger| merger.clone_boxed());
 
         // Try to get an output writer, or panic: if we can't output data we're useless
-        let mut writer: Box<dyn Write + Send>;
+        let mut writer: Box<dyn FileWrite>;
         match self.open_writer() {
             Some(file) => {
                 writer = file;
@@ -200,21 +383,114 @@ impl Output for FileOutput {
             }
         }
 
-        thread::spawn(move || loop {
-            let mut bytes = match { arx.lock().unwrap().recv() } {
-                Ok(line) => line,
-                Err(_) => return,
-            };
+        let mut errors_writer = self.errors_path.as_ref().map(|path| {
+            self.open_path_writer(path)
+                .unwrap_or_else(|| panic!("Cannot open file to {}", path))
+        });
 
-            if let Some(ref merger) = merger {
-                merger.frame(&mut bytes);
+        let mut chain = self
+            .hash_chain_key
+            .as_ref()
+            .map(|key| self.open_hash_chain(key, &self.path));
+        let mut errors_chain = self
+            .hash_chain_key
+            .as_ref()
+            .zip(self.errors_path.as_ref())
+            .map(|(key, path)| self.open_hash_chain(key, path));
+
+        let split_errors = self.errors_path.is_some();
+        let manifest = self.manifest;
+
+        #[cfg(unix)]
+        ROTATION_SIGNAL.call_once(|| daemon::on_signal(libc::SIGUSR1, rotate_on_signal));
+
+        let name = "flowgger-output-file".to_owned();
+        threads::spawn(name, self.affinity.cpu(0), move || {
+            let mut batch = Vec::with_capacity(OUTPUT_BATCH_SIZE);
+            let mut fields = Vec::with_capacity(OUTPUT_BATCH_SIZE);
+            while recv_batch(&rx, &mut batch) {
+                fields.clear();
+                fields.extend(
+                    batch
+                        .iter_mut()
+                        .map(|bytes| take_fields(bytes, split_errors, manifest)),
+                );
+                if chain.is_some() {
+                    for (i, bytes) in batch.iter_mut().enumerate() {
+                        let chain = match errors_chain.as_mut() {
+                            Some(errors_chain) if fields[i].0 => Some(errors_chain),
+                            _ => chain.as_mut(),
+                        };
+                        if let Some(chain) = chain {
+                            chain.seal(bytes);
+                        }
+                    }
+                }
+                if let Some(ref merger) = merger {
+                    for bytes in batch.iter_mut() {
+                        merger.frame(bytes);
+                    }
+                }
+                for (i, bytes) in batch.iter().enumerate() {
+                    let (is_error, ts) = fields[i];
+                    let writer = match errors_writer.as_mut() {
+                        Some(errors_writer) if is_error => errors_writer,
+                        _ => &mut writer,
+                    };
+                    if manifest {
+                        writer.set_record_timestamp(ts);
+                    }
+                    if writer.write_all(bytes).is_err() {
+                        notify(&notifier, &batch, Err("Cannot write bytes to output file"));
+                        panic!("Cannot write bytes to output file");
+                    }
+                }
+                // Only sync for the notifier, buffering is otherwise left to the writer
+                if notifier.is_some() {
+                    let synced = writer.sync_data().and_then(|_| {
+                        errors_writer
+                            .as_mut()
+                            .map_or(Ok(()), |errors_writer| errors_writer.sync_data())
+                    });
+                    match synced {
+                        Ok(_) => notify(&notifier, &batch, Ok(())),
+                        Err(_) => notify(&notifier, &batch, Err("Cannot sync the output file")),
+                    }
+                }
+                batch.clear();
             }
-
-            writer
-                .write_all(&bytes)
-                .expect("Cannot write bytes to output file");
         });
     }
+
+    fn record_fields(&self) -> Vec<String> {
+        let mut fields = Vec::new();
+        if self.errors_path.is_some() {
+            fields.push("severity".to_owned());
+        }
+        if self.manifest {
+            fields.push("ts".to_owned());
+        }
+        fields
+    }
+}
+
+/// Settings of the file output, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "file_path = \"/var/log/flowgger/output.log\"\n\
+         # Bytes buffered before they are written, 0 not to buffer\n\
+         # file_buffer_size = {}\n\
+         # Rotate the file when it reaches this size, or after this number of minutes, 0 not to rotate\n\
+         # file_rotation_size = {}\n\
+         # file_rotation_time = {}\n\
+         # file_rotation_maxfiles = {}\n\
+         # file_rotation_timeformat = \"{}\"\n",
+        FILE_DEFAULT_BUFFER_SIZE,
+        FILE_DEFAULT_ROTATION_SIZE,
+        FILE_DEFAULT_ROTATION_TIME,
+        FILE_DEFAULT_ROTATION_MAXFILES,
+        FILE_DEFAULT_TIME_FORMAT
+    )
 }
 
 #[cfg(test)]
@@ -223,9 +499,9 @@ mod tests {
     /// Note: Tests checking real files must use test unique filenames as tests are ran in parallel
     use super::*;
     use crate::flowgger::merger::LineMerger;
+    use crate::flowgger::record_queue::{bounded, RecordReceiver, RecordSender};
     use std::fs;
-    use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
-    use std::sync::{Arc, Mutex};
+    use std::sync::Mutex;
     use std::{thread, time};
     extern crate tempdir;
     use std::io::Result;
@@ -278,17 +554,12 @@ mod tests {
             assert!(writer_result.is_none());
         }
 
-        fn setup_start_thread(
-            &self,
-            cfg: Config,
-            merger: Option<Box<dyn Merger>>,
-        ) -> SyncSender<Vec<u8>> {
+        fn setup_start_thread(&self, cfg: Config, merger: Option<Box<dyn Merger>>) -> RecordSender {
             let fp = FileOutput::new(&cfg);
 
             // Create a sync data sender and start the file output task
-            let (tx, rx): (SyncSender<Vec<u8>>, Receiver<Vec<u8>>) = sync_channel(128);
-            let arx = Arc::new(Mutex::new(rx));
-            fp.start(arx, merger);
+            let (tx, rx): (RecordSender, RecordReceiver) = bounded(128);
+            fp.start(rx, merger, None);
             tx
         }
 
@@ -298,58 +569,90 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "output.file_path must be a string")]
+    #[should_panic(expected = "expected a string for key `output.file_path`")]
     fn test_invalid_file_path() {
-        let cfg = Config::from_string(&format!("[output]\nfile_path = 123\n")).unwrap();
+        let cfg = Config::from_string("[output]\nfile_path = 123\n").unwrap();
         let _ = FileOutput::new(&cfg);
     }
 
     #[test]
-    #[should_panic(expected = "output.file_rotation_timeformat should be a string")]
+    #[should_panic(expected = "expected a string for key `output.file_rotation_timeformat`")]
     fn test_invalid_time_format() {
-        let cfg = Config::from_string(&format!(
-            "[output]\nfile_path = \"output_file\"\nfile_rotation_timeformat = 123\n"
-        ))
+        let cfg = Config::from_string(
+            "[output]\nfile_path = \"output_file\"\nfile_rotation_timeformat = 123\n",
+        )
         .unwrap();
         let _ = FileOutput::new(&cfg);
     }
 
     #[test]
-    #[should_panic(expected = "output.file_rotation_size should be an integer")]
+    #[should_panic(expected = "expected usize for key `output.file_rotation_size`")]
     fn test_invalid_rotation_size() {
-        let cfg = Config::from_string(&format!(
-            "[output]\nfile_path = \"output_file\"\nfile_rotation_size= \"15s\"\n"
-        ))
+        let cfg = Config::from_string(
+            "[output]\nfile_path = \"output_file\"\nfile_rotation_size= \"15s\"\n",
+        )
         .unwrap();
         let _ = FileOutput::new(&cfg);
     }
 
     #[test]
-    #[should_panic(expected = "output.file_buffer_size should be an integer")]
+    #[should_panic(expected = "expected usize for key `output.file_buffer_size`")]
     fn test_invalid_buffer_size() {
-        let cfg = Config::from_string(&format!(
-            "[output]\nfile_path = \"output_file\"\nfile_buffer_size= \"15s\"\n"
-        ))
+        let cfg = Config::from_string(
+            "[output]\nfile_path = \"output_file\"\nfile_buffer_size= \"15s\"\n",
+        )
         .unwrap();
         let _ = FileOutput::new(&cfg);
     }
 
     #[test]
-    #[should_panic(expected = "output.file_rotation_maxfiles should be an integer")]
+    #[should_panic(expected = "expected i32 for key `output.file_rotation_maxfiles`")]
     fn test_invalid_rotation_maxfiles() {
-        let cfg = Config::from_string(&format!(
-            "[output]\nfile_path = \"output_file\"\nfile_rotation_maxfiles= \"15s\"\n"
-        ))
+        let cfg = Config::from_string(
+            "[output]\nfile_path = \"output_file\"\nfile_rotation_maxfiles= \"15s\"\n",
+        )
         .unwrap();
         let _ = FileOutput::new(&cfg);
     }
 
     #[test]
-    #[should_panic(expected = "output.file_rotation_time should be an integer")]
+    #[should_panic(expected = "expected u32 for key `output.file_rotation_time`")]
     fn test_invalid_rotation_time() {
-        let cfg = Config::from_string(&format!(
-            "[output]\nfile_path = \"output_file\"\nfile_rotation_time= \"15s\"\n"
-        ))
+        let cfg = Config::from_string(
+            "[output]\nfile_path = \"output_file\"\nfile_rotation_time= \"15s\"\n",
+        )
+        .unwrap();
+        let _ = FileOutput::new(&cfg);
+    }
+
+    #[test]
+    #[should_panic(expected = "output.file_path_template requires output.file_rotation_time")]
+    fn test_path_template_size_rotation() {
+        let cfg = Config::from_string(
+            "[output]\nfile_path_template = \"app-%Y/%m/%d.log\"\nfile_rotation_size = 15\n",
+        )
+        .unwrap();
+        let _ = FileOutput::new(&cfg);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown output.file_rotation_timezone: Mars/Olympus_Mons")]
+    fn test_invalid_rotation_timezone() {
+        let cfg = Config::from_string(
+            "[output]\nfile_path = \"output_file\"\nfile_rotation_calendar = \"daily\"\nfile_rotation_timezone = \"Mars/Olympus_Mons\"\n",
+        )
+        .unwrap();
+        let _ = FileOutput::new(&cfg);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "output.file_rotation_manifest can't be used with output.file_buffer_size"
+    )]
+    fn test_rotation_manifest_buffered() {
+        let cfg = Config::from_string(
+            "[output]\nfile_path = \"output_file\"\nfile_buffer_size = 100\nfile_rotation_manifest = true\n",
+        )
         .unwrap();
         let _ = FileOutput::new(&cfg);
     }
@@ -393,6 +696,120 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_start_split_by_severity() -> Result<()> {
+        let file_base = "/tmp/test_start_split_by_severity.log";
+        let errors_file = "/tmp/test_start_split_by_severity.errors.log";
+        let _ = fs::remove_file(file_base);
+        let _ = fs::remove_file(errors_file);
+        let cfg = Config::from_string(&format!(
+            "[output]\nfile_path = \"{}\"\nfile_split_by = \"severity\"\n",
+            file_base
+        ))
+        .unwrap();
+        let fp = FileOutput::new(&cfg);
+        assert_eq!(fp.record_fields(), vec!["severity"]);
+        let (tx, rx) = bounded(128);
+        fp.start(rx, Some(Box::new(LineMerger::new(&cfg))), None);
+
+        let record = |severity: Option<&str>, msg: &str| {
+            let mut bytes = match severity {
+                Some(severity) => (severity.len() as u32).to_le_bytes().to_vec(),
+                None => u32::MAX.to_le_bytes().to_vec(),
+            };
+            bytes.extend_from_slice(severity.unwrap_or_default().as_bytes());
+            bytes.extend_from_slice(msg.as_bytes());
+            bytes
+        };
+        let _ = tx.send(record(Some("2"), "critical"));
+        let _ = tx.send(record(Some("6"), "info"));
+        let _ = tx.send(record(Some("3"), "error"));
+        let _ = tx.send(record(None, "unknown"));
+        thread::sleep(time::Duration::from_millis(100));
+        assert_eq!(fs::read_to_string(file_base)?, "info\nunknown\n");
+        assert_eq!(fs::read_to_string(errors_file)?, "critical\nerror\n");
+        let _ = fs::remove_file(file_base);
+        let _ = fs::remove_file(errors_file);
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors_path() {
+        assert_eq!(errors_path("output.log"), "output.errors.log");
+        assert_eq!(
+            errors_path("/var/log/app.log.gz"),
+            "/var/log/app.errors.log.gz"
+        );
+        assert_eq!(errors_path("/var/log.d/.app"), "/var/log.d/.app.errors");
+    }
+
+    #[test]
+    fn test_take_fields() {
+        let field = |value: &str| {
+            let mut bytes = (value.len() as u32).to_le_bytes().to_vec();
+            bytes.extend_from_slice(value.as_bytes());
+            bytes
+        };
+        let mut bytes = [field("3"), field("2015-08-06T11:15:24Z"), b"msg".to_vec()].concat();
+        let (is_error, ts) = take_fields(&mut bytes, true, true);
+        assert!(is_error);
+        assert_eq!(
+            ts,
+            Some(OffsetDateTime::parse("2015-08-06T11:15:24Z", &Rfc3339).unwrap())
+        );
+        assert_eq!(bytes, b"msg");
+
+        let mut bytes = [u32::MAX.to_le_bytes().to_vec(), b"msg".to_vec()].concat();
+        assert_eq!(take_fields(&mut bytes, false, true), (false, None));
+        assert_eq!(bytes, b"msg");
+
+        let mut bytes = b"msg".to_vec();
+        assert_eq!(take_fields(&mut bytes, false, false), (false, None));
+        assert_eq!(bytes, b"msg");
+    }
+
+    struct TestNotifier {
+        delivered: Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl Notifier for TestNotifier {
+        fn notify(&self, records: &[Vec<u8>], result: std::result::Result<(), &str>) {
+            assert!(result.is_ok());
+            self.delivered.lock().unwrap().extend_from_slice(records);
+        }
+    }
+
+    #[test]
+    fn test_start_with_notifier() -> Result<()> {
+        let file_base = "/tmp/test_start_with_notifier";
+        let _ = fs::remove_file(file_base);
+        let test_object = WriterTest::new(file_base)?;
+        let cfg = Config::from_string(&format!(
+            "[output]\nfile_path = \"{}\"\nfile_buffer_size = 4096\n",
+            file_base
+        ))
+        .unwrap();
+        let merger = Some(Box::new(LineMerger::new(&cfg)) as Box<dyn Merger>);
+        let notifier = Arc::new(TestNotifier {
+            delivered: Mutex::new(Vec::new()),
+        });
+        let fp = FileOutput::new(&cfg);
+        let (tx, rx) = bounded(128);
+        fp.start(rx, merger, Some(notifier.clone() as Arc<dyn Notifier>));
+
+        // The notification comes after the buffered writer has been flushed, and the file synced
+        let _ = tx.send(test_object.test_patterns[0].as_bytes().to_vec());
+        thread::sleep(time::Duration::from_millis(100));
+        let expected = format!("{}\n", test_object.test_patterns[0]);
+        assert_eq!(
+            *notifier.delivered.lock().unwrap(),
+            vec![expected.as_bytes().to_vec()]
+        );
+        assert_eq!(fs::read_to_string(file_base).unwrap(), expected);
+        let _ = fs::remove_file(file_base);
+        Ok(())
+    }
+
     #[test]
     #[should_panic(expected = "Cannot open file to /wrong/path/test_start_nofile")]
     fn test_start_nofile() {
@@ -0,0 +1,276 @@
+use crate::flowgger::config::Config;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::fs::File;
+use std::io::{stderr, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Separator between a line and its tag
+const TAG_PREFIX: &[u8] = b" chain=";
+const TAG_LEN: usize = 32;
+/// Tag the first line of a chain is computed from
+const CHAIN_START: [u8; TAG_LEN] = [0; TAG_LEN];
+
+/// Settings of the hash chain, in the `[output]` section
+#[derive(Deserialize)]
+pub struct HashChainSettings {
+    file_hash_chain_key: Option<String>,
+}
+
+/// Hash chain of the lines written to a file, for tamper evidence of the local logs.
+///
+/// Every line gets a ` chain=<hex>` suffix, the HMAC-SHA256 of the tag of the previous line followed by the
+/// line itself, so that lines can't be altered, inserted, removed or reordered without the key, unnoticed.
+pub struct HashChain {
+    mac: HmacSha256,
+    last: [u8; TAG_LEN],
+}
+
+impl HashChain {
+    pub fn new(key: &[u8]) -> HashChain {
+        HashChain {
+            mac: new_mac(key),
+            last: CHAIN_START,
+        }
+    }
+
+    /// Chain continuing from the last line of the file if it has a tag, a new chain otherwise
+    pub fn resume(key: &[u8], path: &str) -> HashChain {
+        let mut chain = HashChain::new(key);
+        if let Some(last) = last_tag(path) {
+            chain.last = last;
+        }
+        chain
+    }
+
+    /// Append the tag of a record, that must not be framed yet
+    pub fn seal(&mut self, bytes: &mut Vec<u8>) {
+        self.last = tag(&self.mac, &self.last, bytes);
+        bytes.extend_from_slice(TAG_PREFIX);
+        for c in self.last {
+            bytes.extend_from_slice(format!("{:02x}", c).as_bytes());
+        }
+    }
+}
+
+/// The key of the hash chain, if 'output.file_hash_chain_key' is set
+pub fn hash_chain_key(config: &Config) -> Option<Vec<u8>> {
+    let settings: HashChainSettings = config.settings("output");
+    settings.file_hash_chain_key.map(|key| {
+        if key.is_empty() {
+            panic!("output.file_hash_chain_key must be a non-empty string");
+        }
+        key.into_bytes()
+    })
+}
+
+fn new_mac(key: &[u8]) -> HmacSha256 {
+    HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size")
+}
+
+fn tag(mac: &HmacSha256, last: &[u8; TAG_LEN], line: &[u8]) -> [u8; TAG_LEN] {
+    mac.clone()
+        .chain_update(last)
+        .chain_update(line)
+        .finalize()
+        .into_bytes()
+        .into()
+}
+
+/// Split a line into the record and its tag
+fn split_tag(line: &[u8]) -> Option<(&[u8], [u8; TAG_LEN])> {
+    let tag_start = line.len().checked_sub(TAG_PREFIX.len() + TAG_LEN * 2)?;
+    let (record, suffix) = line.split_at(tag_start);
+    let tag = parse_tag(suffix.strip_prefix(TAG_PREFIX)?)?;
+    Some((record, tag))
+}
+
+/// Tag from its hex representation
+fn parse_tag(hex: &[u8]) -> Option<[u8; TAG_LEN]> {
+    if hex.len() != TAG_LEN * 2 {
+        return None;
+    }
+    let mut tag = [0; TAG_LEN];
+    for (i, pair) in hex.chunks(2).enumerate() {
+        let pair = std::str::from_utf8(pair).ok()?;
+        tag[i] = u8::from_str_radix(pair, 16).ok()?;
+    }
+    Some(tag)
+}
+
+/// Tag of the last line of a file, read from its end
+fn last_tag(path: &str) -> Option<[u8; TAG_LEN]> {
+    let mut file = File::open(path).ok()?;
+    let tail_len = (TAG_PREFIX.len() + TAG_LEN * 2 + 2) as u64;
+    let len = file.metadata().ok()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(tail_len)))
+        .ok()?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).ok()?;
+    split_tag(trim_line_end(&tail)).map(|(_, tag)| tag)
+}
+
+fn trim_line_end(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Verify the hash chain of files written with 'output.file_hash_chain_key', oldest first.
+///
+/// The first line of the first file must start a new chain, or continue from `previous_tag`, the tag of the
+/// last line of the previous file, so that removing the first lines of a file is noticed. With
+/// `allow_continuation`, a first line continuing a chain that started in a file that is not listed is accepted
+/// without being verified. Lines starting a new chain, when flowgger was started again with a new file, are
+/// reported on stderr.
+///
+/// # Returns
+/// The number of lines verified, or the location of the first line breaking the chain
+pub fn verify_hash_chain(
+    config: &Config,
+    paths: &[&str],
+    previous_tag: Option<&str>,
+    allow_continuation: bool,
+) -> Result<u64, String> {
+    let key = hash_chain_key(config).ok_or("output.file_hash_chain_key is missing")?;
+    let mac = new_mac(&key);
+    let mut last = match previous_tag {
+        None => None,
+        Some(previous_tag) => Some(
+            parse_tag(previous_tag.as_bytes())
+                .ok_or("The previous tag must be 64 hexadecimal digits")?,
+        ),
+    };
+    let mut count = 0;
+    for path in paths {
+        let file = File::open(path).map_err(|e| format!("Unable to open {}: {}", path, e))?;
+        for (i, line) in BufReader::new(file).split(b'\n').enumerate() {
+            let line = line.map_err(|e| format!("Unable to read {}: {}", path, e))?;
+            let (record, line_tag) = split_tag(trim_line_end(&line))
+                .ok_or_else(|| format!("{}:{}: missing hash chain tag", path, i + 1))?;
+            let from_last = last.is_some_and(|last| tag(&mac, &last, record) == line_tag);
+            if !from_last {
+                if tag(&mac, &CHAIN_START, record) == line_tag {
+                    if last.is_some() {
+                        let _ = writeln!(stderr(), "{}:{}: new hash chain", path, i + 1);
+                    }
+                } else if last.is_none() && allow_continuation {
+                    let _ = writeln!(
+                        stderr(),
+                        "{}:{}: the hash chain starts in a previous file, this line can't be verified",
+                        path,
+                        i + 1
+                    );
+                } else if last.is_none() {
+                    return Err(format!(
+                        "{}:{}: the hash chain starts in a previous file, pass the tag of its last line",
+                        path,
+                        i + 1
+                    ));
+                } else {
+                    return Err(format!("{}:{}: broken hash chain", path, i + 1));
+                }
+            }
+            last = Some(line_tag);
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    fn write_chain(path: &str, records: &[&str]) {
+        let mut chain = HashChain::resume(b"secret", path);
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        for record in records {
+            let mut bytes = record.as_bytes().to_vec();
+            chain.seal(&mut bytes);
+            bytes.push(b'\n');
+            file.write_all(&bytes).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_hash_chain_verify() {
+        let config = Config::from_string("[output]\nfile_hash_chain_key = \"secret\"\n").unwrap();
+        let tmp_dir = TempDir::new("test_hash_chain_verify").unwrap();
+        let path = tmp_dir.path().join("audit.log");
+        let path = path.to_str().unwrap();
+        write_chain(path, &["first", "second"]);
+        // The chain continues from the last line of the existing file
+        write_chain(path, &["third"]);
+        assert_eq!(verify_hash_chain(&config, &[path], None, false), Ok(3));
+
+        let lines = fs::read_to_string(path).unwrap();
+        fs::write(path, lines.replacen("second", "secont", 1)).unwrap();
+        assert_eq!(
+            verify_hash_chain(&config, &[path], None, false),
+            Err(format!("{}:2: broken hash chain", path))
+        );
+
+        let mut lines: Vec<_> = lines.lines().collect();
+        lines.remove(1);
+        fs::write(path, lines.join("\n")).unwrap();
+        assert_eq!(
+            verify_hash_chain(&config, &[path], None, false),
+            Err(format!("{}:2: broken hash chain", path))
+        );
+
+        let config = Config::from_string("[output]\nfile_hash_chain_key = \"other\"\n").unwrap();
+        fs::write(path, "").unwrap();
+        write_chain(path, &["first", "second"]);
+        assert_eq!(
+            verify_hash_chain(&config, &[path], None, false),
+            Err(format!(
+                "{}:1: the hash chain starts in a previous file, pass the tag of its last line",
+                path
+            ))
+        );
+        assert_eq!(
+            verify_hash_chain(&config, &[path], None, true),
+            Err(format!("{}:2: broken hash chain", path))
+        );
+    }
+
+    #[test]
+    fn test_hash_chain_verify_continuation() {
+        let config = Config::from_string("[output]\nfile_hash_chain_key = \"secret\"\n").unwrap();
+        let tmp_dir = TempDir::new("test_hash_chain_verify_continuation").unwrap();
+        let path = tmp_dir.path().join("audit.log");
+        let path = path.to_str().unwrap();
+        write_chain(path, &["first", "second", "third"]);
+        let lines = fs::read_to_string(path).unwrap();
+        let previous_tag = lines.lines().next().unwrap().rsplit('=').next().unwrap();
+        let truncated = lines.lines().skip(1).collect::<Vec<_>>().join("\n");
+        fs::write(path, truncated).unwrap();
+
+        // A file missing the first lines of its chain is not accepted as is
+        assert_eq!(
+            verify_hash_chain(&config, &[path], None, false),
+            Err(format!(
+                "{}:1: the hash chain starts in a previous file, pass the tag of its last line",
+                path
+            ))
+        );
+        assert_eq!(verify_hash_chain(&config, &[path], None, true), Ok(2));
+        assert_eq!(
+            verify_hash_chain(&config, &[path], Some(previous_tag), false),
+            Ok(2)
+        );
+        assert_eq!(
+            verify_hash_chain(&config, &[path], Some(&"0".repeat(64)), false),
+            Err(format!("{}:1: broken hash chain", path))
+        );
+        assert!(verify_hash_chain(&config, &[path], Some("00"), false).is_err());
+    }
+}
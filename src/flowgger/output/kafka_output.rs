@@ -1,23 +1,84 @@
-use super::Output;
+use super::{notify, notify_outcomes, recv_batch_timeout, Notifier, Output, OUTPUT_BATCH_SIZE};
 use crate::flowgger::config::Config;
+use crate::flowgger::daemon;
+use crate::flowgger::encoder::split_fields;
 use crate::flowgger::merger::Merger;
-use kafka::producer::{Compression, Producer, Record, RequiredAcks};
+use crate::flowgger::record_queue::RecordReceiver;
+use crate::flowgger::utils::threads::{self, CpuAffinity};
+use crossbeam_channel::RecvTimeoutError;
+use rdkafka::config::ClientConfig;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{BaseProducer, BaseRecord, DeliveryResult, Producer, ProducerContext};
+use rdkafka::ClientContext;
+use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::io::{stderr, Write};
 use std::process::exit;
-use std::sync::mpsc::Receiver;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 const KAFKA_DEFAULT_ACKS: i16 = 0;
 const KAFKA_DEFAULT_COALESCE: usize = 1;
 const KAFKA_DEFAULT_COMPRESSION: &str = "none";
+const KAFKA_DEFAULT_LINGER_MS: u64 = 1000;
+const KAFKA_DEFAULT_STARTUP_CHECK: &str = "fail";
+const KAFKA_DEFAULT_STARTUP_RETRY_DELAY: u64 = 5000;
 const KAFKA_DEFAULT_THREADS: u32 = 1;
 const KAFKA_DEFAULT_TIMEOUT: u64 = 60_000;
+const KAFKA_MAX_TOPIC_LEN: usize = 249;
+/// How often the workers check whether they have to stop
+const KAFKA_STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
+/// Settings of the Kafka output, in the `[output]` section
+#[derive(Deserialize)]
+pub struct KafkaSettings {
+    kafka_acks: Option<i16>,
+    kafka_brokers: Vec<String>,
+    kafka_topic: String,
+    kafka_topic_field: Option<String>,
+    kafka_timeout: Option<u64>,
+    kafka_threads: Option<u32>,
+    kafka_coalesce: Option<usize>,
+    kafka_linger_ms: Option<u64>,
+    kafka_startup_check: Option<String>,
+    kafka_startup_retry_delay: Option<u64>,
+    kafka_compression: Option<String>,
+    #[serde(default)]
+    kafka_headers: BTreeMap<String, String>,
+    #[serde(default)]
+    kafka_header_fields: Vec<String>,
+}
+
+/// Output to Kafka. Records are coalesced and sent by batches of 'output.kafka_coalesce' records, or once the
+/// oldest one waited for 'output.kafka_linger_ms'. The last batch is sent before the process exits, when the
+/// input stops or, on Unix, when the process receives SIGTERM or SIGINT.
+///
+/// # Parameters
+/// - 'output.kafka_startup_check': Optional. What to do at startup if the brokers can't be reached or if the
+///   topic doesn't exist: "fail" to exit, "retry" to wait until they are available, or "none" to start
+///   without checking them. Default is "fail".
+/// - 'output.kafka_startup_retry_delay': Optional. Milliseconds between two checks with "retry". Default is
+///   5000.
 pub struct KafkaOutput {
     config: KafkaConfig,
+    startup_check: StartupCheck,
+    startup_retry_delay: Duration,
     threads: u32,
+    affinity: CpuAffinity,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+    /// Set to make the workers send the records they coalesce and return
+    stop: Arc<AtomicBool>,
+}
+
+/// What to do at startup if the brokers or the topic are not available
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum StartupCheck {
+    Fail,
+    Retry,
+    None,
 }
 
 #[derive(Clone)]
@@ -25,188 +86,628 @@ struct KafkaConfig {
     acks: i16,
     brokers: Vec<String>,
     topic: String,
+    topic_field: Option<String>,
     timeout: Duration,
     coalesce: usize,
-    compression: Compression,
+    linger: Duration,
+    compression: String,
+    headers: Vec<(String, String)>,
+    header_fields: Vec<String>,
+}
+
+/// Keeps the first delivery error reported since the last flush, or a timeout if there was one, along with
+/// the positions in the queue of the records that were not delivered
+#[derive(Default)]
+struct DeliveryContext {
+    error: Mutex<Option<KafkaError>>,
+    failed: Mutex<Vec<usize>>,
+}
+
+impl ClientContext for DeliveryContext {}
+
+impl ProducerContext for DeliveryContext {
+    type DeliveryOpaque = usize;
+
+    fn delivery(&self, delivery_result: &DeliveryResult<'_>, index: Self::DeliveryOpaque) {
+        if let Err((e, _)) = delivery_result {
+            let mut error = self.error.lock().unwrap();
+            if error.is_none()
+                || *e == KafkaError::MessageProduction(RDKafkaErrorCode::MessageTimedOut)
+            {
+                *error = Some(e.clone());
+            }
+            self.failed.lock().unwrap().push(index);
+        }
+    }
 }
 
-struct KafkaWorker<'a> {
-    arx: Arc<Mutex<Receiver<Vec<u8>>>>,
-    producer: Producer,
+struct KafkaWorker {
+    rx: RecordReceiver,
+    producer: BaseProducer<DeliveryContext>,
     config: KafkaConfig,
-    queue: Vec<Record<'a, (), Vec<u8>>>,
+    queue: Vec<Vec<u8>>,
+    notifier: Option<Arc<dyn Notifier>>,
+    stop: Arc<AtomicBool>,
 }
 
-impl<'a> KafkaWorker<'a> {
-    fn new(arx: Arc<Mutex<Receiver<Vec<u8>>>>, config: KafkaConfig) -> KafkaWorker<'a> {
-        let acks = match config.acks {
-            -1 => RequiredAcks::All,
-            0 => RequiredAcks::None,
-            1 => RequiredAcks::One,
-            _ => panic!("Unsupported value for kafka_acks"),
-        };
-        let producer = Producer::from_hosts(config.brokers.clone())
-            .with_required_acks(acks)
-            .with_ack_timeout(config.timeout)
-            .with_compression(config.compression);
-        let producer = match producer.create() {
+impl KafkaWorker {
+    fn new(
+        rx: RecordReceiver,
+        config: KafkaConfig,
+        notifier: Option<Arc<dyn Notifier>>,
+        stop: Arc<AtomicBool>,
+    ) -> KafkaWorker {
+        let producer = match config.producer() {
             Ok(producer) => producer,
             Err(e) => {
-                println!("Unable to connect to Kafka: [{}]", e);
-                exit(1);
+                let _ = writeln!(stderr(), "Unable to create the Kafka producer: [{}]", e);
+                exit(daemon::EXIT_UNAVAILABLE);
             }
         };
         let queue = Vec::with_capacity(config.coalesce);
         KafkaWorker {
-            arx,
+            rx,
             producer,
             config,
             queue,
+            notifier,
+            stop,
         }
     }
 
-    fn run_nocoalesce(&'a mut self) {
+    fn run(&mut self) {
+        let mut batch = Vec::with_capacity(OUTPUT_BATCH_SIZE);
+        // When the oldest coalesced record has to be sent
+        let mut deadline = None;
         loop {
-            let bytes = match { self.arx.lock().unwrap().recv() } {
-                Ok(line) => line,
-                Err(_) => return,
-            };
-            match self
-                .producer
-                .send(&Record::from_value(&self.config.topic, bytes))
-            {
-                Ok(_) => {}
-                Err(e) => {
-                    println!("Kafka not responsive: [{}]", e);
-                    exit(1);
+            if self.stop.load(Ordering::Acquire) {
+                self.queue.extend(self.rx.try_iter());
+                self.send_queue();
+                return;
+            }
+            let timeout = deadline.map_or(KAFKA_STOP_POLL_INTERVAL, |deadline: Instant| {
+                deadline
+                    .saturating_duration_since(Instant::now())
+                    .min(KAFKA_STOP_POLL_INTERVAL)
+            });
+            match recv_batch_timeout(&self.rx, &mut batch, timeout) {
+                Ok(()) => {
+                    for bytes in batch.drain(..) {
+                        self.queue.push(bytes);
+                        if self.queue.len() >= self.config.coalesce {
+                            self.send_queue();
+                            deadline = None;
+                        }
+                    }
+                    if !self.queue.is_empty() && deadline.is_none() {
+                        deadline = Some(Instant::now() + self.config.linger);
+                    }
                 }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.send_queue();
+                    return;
+                }
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                self.send_queue();
+                deadline = None;
             }
         }
     }
 
-    fn run_coalesce(&'a mut self) {
-        loop {
-            let bytes = match { self.arx.lock().unwrap().recv() } {
-                Ok(line) => line,
-                Err(_) => return,
-            };
-            let message = Record {
-                key: (),
-                partition: -1,
-                topic: &self.config.topic,
-                value: bytes,
-            };
-            let queue = &mut self.queue;
-            queue.push(message);
-            if queue.len() >= self.config.coalesce {
-                match self.producer.send_all(queue) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        println!("Kafka not responsive: [{}]", e);
-                        exit(1);
+    /// Produce the queued records, and wait for the brokers to acknowledge them. Records that could not be
+    /// produced or that the brokers rejected are notified with an error, the others as delivered.
+    fn send_queue(&mut self) {
+        if self.queue.is_empty() {
+            return;
+        }
+        let header_count = self.config.header_fields.len();
+        let fields_count = header_count + self.config.topic_field.iter().count();
+        let mut delivered = vec![true; self.queue.len()];
+        for (index, bytes) in self.queue.iter_mut().enumerate() {
+            let fields_len = match split_fields(bytes, fields_count) {
+                Ok((values, payload)) => {
+                    let topic = self
+                        .config
+                        .topic(values.get(header_count).copied().flatten());
+                    let mut record =
+                        BaseRecord::<(), _, _>::with_opaque_to(topic, index).payload(payload);
+                    if let Some(headers) = self.config.headers(&values[..header_count]) {
+                        record = record.headers(headers);
+                    }
+                    while let Err((e, unsent)) = self.producer.send(record) {
+                        match e {
+                            // The producer buffer is full, wait for some deliveries
+                            KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull) => {
+                                self.producer.poll(Duration::from_millis(100));
+                                record = unsent;
+                            }
+                            e => {
+                                let _ =
+                                    writeln!(stderr(), "Unable to send a record to Kafka: [{}]", e);
+                                delivered[index] = false;
+                                break;
+                            }
+                        }
                     }
+                    bytes.len() - payload.len()
+                }
+                Err(e) => {
+                    let _ = writeln!(stderr(), "{}", e);
+                    delivered[index] = false;
+                    0
                 }
-                queue.clear();
+            };
+            // Records are notified without the fields used for the headers and the topic
+            bytes.drain(..fields_len);
+        }
+        let flushed = self.producer.flush(self.config.timeout);
+        let context = self.producer.context();
+        let error = context.error.lock().unwrap().take();
+        for index in context.failed.lock().unwrap().drain(..) {
+            delivered[index] = false;
+        }
+        match (flushed, error) {
+            (Err(e), _)
+            | (_, Some(e @ KafkaError::MessageProduction(RDKafkaErrorCode::MessageTimedOut))) => {
+                notify(&self.notifier, &self.queue, Err("Kafka not responsive"));
+                let _ = writeln!(stderr(), "Kafka not responsive: [{}]", e);
+                exit(daemon::EXIT_UNAVAILABLE);
             }
+            (Ok(()), Some(e)) => {
+                let _ = writeln!(stderr(), "Kafka rejected records: [{}]", e);
+            }
+            (Ok(()), None) => {}
         }
+        notify_outcomes(
+            &self.notifier,
+            &self.queue,
+            &delivered,
+            "Kafka rejected records",
+        );
+        self.queue.clear();
     }
+}
 
-    fn run(&'a mut self) {
-        if self.config.coalesce <= 1 {
-            self.run_nocoalesce()
-        } else {
-            self.run_coalesce()
+impl KafkaConfig {
+    fn producer(&self) -> Result<BaseProducer<DeliveryContext>, KafkaError> {
+        let acks = match self.acks {
+            -1 => "all",
+            0 => "0",
+            1 => "1",
+            _ => panic!("Unsupported value for kafka_acks"),
+        };
+        let timeout_ms = self.timeout.as_millis().to_string();
+        ClientConfig::new()
+            .set("bootstrap.servers", self.brokers.join(","))
+            .set("acks", acks)
+            .set("request.timeout.ms", &timeout_ms)
+            .set("message.timeout.ms", &timeout_ms)
+            .set("compression.codec", &self.compression)
+            .create_with_context(DeliveryContext::default())
+    }
+
+    /// Check that the brokers can be reached and that the default topic exists. Brokers creating topics
+    /// automatically create it, and report it as unavailable until it has a leader.
+    fn check(&self) -> Result<(), String> {
+        let producer = self
+            .producer()
+            .map_err(|e| format!("Unable to create the Kafka producer: [{}]", e))?;
+        let metadata = producer
+            .client()
+            .fetch_metadata(Some(&self.topic), self.timeout)
+            .map_err(|e| format!("Unable to connect to Kafka: [{}]", e))?;
+        let topic = metadata
+            .topics()
+            .iter()
+            .find(|topic| topic.name() == self.topic)
+            .ok_or_else(|| format!("Kafka topic [{}] not found", self.topic))?;
+        match topic.error() {
+            Some(e) => Err(format!(
+                "Kafka topic [{}] is not available: [{}]",
+                self.topic,
+                RDKafkaErrorCode::from(e)
+            )),
+            None if topic.partitions().is_empty() => {
+                Err(format!("Kafka topic [{}] has no partitions", self.topic))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Topic named after the value of the topic field, or the default topic if the record doesn't have this
+    /// field, or if its value is not a valid topic name
+    fn topic<'a>(&'a self, value: Option<&'a [u8]>) -> &'a str {
+        match value.and_then(|value| std::str::from_utf8(value).ok()) {
+            Some(topic)
+                if !topic.is_empty()
+                    && topic.len() <= KAFKA_MAX_TOPIC_LEN
+                    && topic != "."
+                    && topic != ".."
+                    && topic.bytes().all(|c| {
+                        c.is_ascii_alphanumeric() || c == b'.' || c == b'_' || c == b'-'
+                    }) =>
+            {
+                topic
+            }
+            _ => &self.topic,
+        }
+    }
+
+    /// Static headers, followed by the headers set from the record fields
+    fn headers(&self, values: &[Option<&[u8]>]) -> Option<OwnedHeaders> {
+        if self.headers.is_empty() && self.header_fields.is_empty() {
+            return None;
+        }
+        let mut headers = OwnedHeaders::new_with_capacity(self.headers.len() + values.len());
+        for (key, value) in &self.headers {
+            headers = headers.insert(Header {
+                key,
+                value: Some(value),
+            });
+        }
+        for (key, value) in self.header_fields.iter().zip(values) {
+            if let Some(value) = value {
+                headers = headers.insert(Header {
+                    key,
+                    value: Some(*value),
+                });
+            }
         }
+        Some(headers)
     }
 }
 
 impl KafkaOutput {
     pub fn new(config: &Config) -> KafkaOutput {
-        let acks = config
-            .lookup("output.kafka_acks")
-            .map_or(KAFKA_DEFAULT_ACKS, |x| {
-                x.as_integer()
-                    .expect("output.kafka_acks must be a 16-bit integer") as i16
-            });
-        let brokers = config
-            .lookup("output.kafka_brokers")
-            .expect("output.kafka_brokers is required")
-            .as_array()
-            .expect("Invalid list of Kafka brokers");
-        let brokers = brokers
-            .iter()
-            .map(|x| {
-                x.as_str()
-                    .expect("output.kafka_brokers must be a list of strings")
-                    .to_owned()
-            })
-            .collect();
-        let topic = config
-            .lookup("output.kafka_topic")
-            .expect("output.kafka_topic must be a string")
-            .as_str()
-            .expect("output.kafka_topic must be a string")
-            .to_owned();
-        let timeout = Duration::from_millis(config.lookup("output.kafka_timeout").map_or(
-            KAFKA_DEFAULT_TIMEOUT,
-            |x| {
-                x.as_integer()
-                    .expect("output.kafka_timeout must be a 64-bit integer") as u64
-            },
-        ));
-        let threads = config
-            .lookup("output.kafka_threads")
-            .map_or(KAFKA_DEFAULT_THREADS, |x| {
-                x.as_integer()
-                    .expect("output.kafka_threads must be a 32-bit integer") as u32
-            });
-        let coalesce = config
-            .lookup("output.kafka_coalesce")
-            .map_or(KAFKA_DEFAULT_COALESCE, |x| {
-                x.as_integer()
-                    .expect("output.kafka_coalesce must be a size integer") as usize
-            });
-        let compression = match config
-            .lookup("output.kafka_compression")
-            .map_or(KAFKA_DEFAULT_COMPRESSION, |x| {
-                x.as_str()
-                    .expect("output.kafka_compresion must be a string")
-            })
-            .to_lowercase()
-            .as_ref()
-        {
-            "none" => Compression::NONE,
-            "gzip" => Compression::GZIP,
-            "snappy" => Compression::SNAPPY,
+        let settings: KafkaSettings = config.settings("output");
+        let compression = settings
+            .kafka_compression
+            .as_deref()
+            .unwrap_or(KAFKA_DEFAULT_COMPRESSION)
+            .to_lowercase();
+        match compression.as_ref() {
+            "none" | "gzip" | "snappy" => {}
             _ => panic!("Unsupported compression method"),
         };
+        let startup_check = match settings
+            .kafka_startup_check
+            .as_deref()
+            .unwrap_or(KAFKA_DEFAULT_STARTUP_CHECK)
+        {
+            "fail" => StartupCheck::Fail,
+            "retry" => StartupCheck::Retry,
+            "none" => StartupCheck::None,
+            _ => panic!(r#"output.kafka_startup_check must be "fail", "retry" or "none""#),
+        };
         let kafka_config = KafkaConfig {
-            acks,
-            brokers,
-            topic,
-            timeout,
-            coalesce,
+            acks: settings.kafka_acks.unwrap_or(KAFKA_DEFAULT_ACKS),
+            brokers: settings.kafka_brokers,
+            topic: settings.kafka_topic,
+            topic_field: settings.kafka_topic_field,
+            timeout: Duration::from_millis(settings.kafka_timeout.unwrap_or(KAFKA_DEFAULT_TIMEOUT)),
+            coalesce: settings
+                .kafka_coalesce
+                .unwrap_or(KAFKA_DEFAULT_COALESCE)
+                .max(1),
+            linger: Duration::from_millis(
+                settings.kafka_linger_ms.unwrap_or(KAFKA_DEFAULT_LINGER_MS),
+            ),
             compression,
+            headers: settings.kafka_headers.into_iter().collect(),
+            header_fields: settings.kafka_header_fields,
         };
         KafkaOutput {
             config: kafka_config,
-            threads,
+            startup_check,
+            startup_retry_delay: Duration::from_millis(
+                settings
+                    .kafka_startup_retry_delay
+                    .unwrap_or(KAFKA_DEFAULT_STARTUP_RETRY_DELAY),
+            ),
+            threads: settings.kafka_threads.unwrap_or(KAFKA_DEFAULT_THREADS),
+            affinity: CpuAffinity::new(config, "output"),
+            workers: Mutex::new(Vec::new()),
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Check the brokers and the topic before the workers start, so that an unavailable cluster stops the
+    /// startup instead of the first send
+    fn check(&self) {
+        if self.startup_check == StartupCheck::None {
+            return;
+        }
+        loop {
+            let e = match self.config.check() {
+                Ok(()) => return,
+                Err(e) => e,
+            };
+            if self.startup_check == StartupCheck::Fail {
+                daemon::fail(daemon::EXIT_UNAVAILABLE, &e);
+            }
+            let _ = writeln!(
+                stderr(),
+                "{}, retrying in {} ms",
+                e,
+                self.startup_retry_delay.as_millis()
+            );
+            thread::sleep(self.startup_retry_delay);
         }
     }
 }
 
 impl Output for KafkaOutput {
-    fn start(&self, arx: Arc<Mutex<Receiver<Vec<u8>>>>, merger: Option<Box<dyn Merger>>) {
+    fn start(
+        &self,
+        rx: RecordReceiver,
+        merger: Option<Box<dyn Merger>>,
+        notifier: Option<Arc<dyn Notifier>>,
+    ) {
         if merger.is_some() {
             let _ = writeln!(stderr(), "Output framing is ignored with the Kafka output");
         }
-        for _ in 0..self.threads {
-            let arx = Arc::clone(&arx);
+        self.check();
+        let mut workers = self.workers.lock().unwrap();
+        for i in 0..self.threads as usize {
+            let rx = rx.clone();
             let config = self.config.clone();
-            thread::spawn(move || {
-                let mut worker = KafkaWorker::new(arx, config);
+            let notifier = notifier.clone();
+            let stop = Arc::clone(&self.stop);
+            let name = format!("flowgger-output-kafka-{}", i);
+            workers.push(threads::spawn(name, self.affinity.cpu(i), move || {
+                let mut worker = KafkaWorker::new(rx, config, notifier, stop);
                 worker.run();
-            });
+            }));
         }
     }
+
+    /// Make the workers send the records they coalesce, and wait for them to return
+    fn stop(&self) {
+        self.stop.store(true, Ordering::Release);
+        let workers = std::mem::take(&mut *self.workers.lock().unwrap());
+        for worker in workers {
+            let _ = worker.join();
+        }
+    }
+
+    /// Fields of the headers, followed by the topic field
+    fn record_fields(&self) -> Vec<String> {
+        let mut fields = self.config.header_fields.clone();
+        fields.extend(self.config.topic_field.iter().cloned());
+        fields
+    }
+}
+
+/// Settings of the Kafka output, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "kafka_brokers = [ \"127.0.0.1:9092\" ]\n\
+         kafka_topic = \"logs\"\n\
+         # kafka_threads = {}\n\
+         # Number of records sent at once\n\
+         # kafka_coalesce = {}\n\
+         # Milliseconds after which fewer records are sent\n\
+         # kafka_linger_ms = {}\n\
+         # Milliseconds before a send is given up on\n\
+         # kafka_timeout = {}\n\
+         # kafka_acks = {}\n\
+         # \"none\", \"gzip\" or \"snappy\"\n\
+         # kafka_compression = \"{}\"\n\
+         # When the brokers or the topic are not available at startup: \"fail\", \"retry\" or \"none\"\n\
+         # kafka_startup_check = \"{}\"\n",
+        KAFKA_DEFAULT_THREADS,
+        KAFKA_DEFAULT_COALESCE,
+        KAFKA_DEFAULT_LINGER_MS,
+        KAFKA_DEFAULT_TIMEOUT,
+        KAFKA_DEFAULT_ACKS,
+        KAFKA_DEFAULT_COMPRESSION,
+        KAFKA_DEFAULT_STARTUP_CHECK
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::record_queue::unbounded;
+    use rdkafka::message::Headers;
+    use rdkafka::mocking::MockCluster;
+    use rdkafka::producer::DefaultProducerContext;
+
+    /// Notifier keeping the records that were delivered
+    struct TestNotifier(Mutex<Vec<Vec<u8>>>);
+
+    impl Notifier for TestNotifier {
+        fn notify(&self, records: &[Vec<u8>], result: Result<(), &str>) {
+            assert_eq!(result, Ok(()));
+            self.0.lock().unwrap().extend_from_slice(records);
+        }
+    }
+
+    /// Output to a mock cluster, with the "logs" topic
+    fn mock_output(
+        cluster: &MockCluster<'static, DefaultProducerContext>,
+        settings: &str,
+    ) -> KafkaOutput {
+        cluster.create_topic("logs", 1, 1).unwrap();
+        let config = Config::from_string(&format!(
+            "[output]\nkafka_brokers = [{:?}]\nkafka_topic = \"logs\"\nkafka_acks = 1\n{}",
+            cluster.bootstrap_servers(),
+            settings
+        ))
+        .unwrap();
+        KafkaOutput::new(&config)
+    }
+
+    #[test]
+    fn test_kafka_linger_flush() {
+        let cluster = MockCluster::new(1).unwrap();
+        let output = mock_output(&cluster, "kafka_coalesce = 100\nkafka_linger_ms = 100\n");
+        let notifier = Arc::new(TestNotifier(Mutex::new(Vec::new())));
+        let (tx, rx) = unbounded();
+        output.start(rx, None, Some(notifier.clone()));
+        tx.send(b"first".to_vec()).unwrap();
+        tx.send(b"second".to_vec()).unwrap();
+
+        // Fewer records than the batch size are sent once the oldest one lingered, while the input still runs
+        let start = Instant::now();
+        while notifier.0.lock().unwrap().len() < 2 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            *notifier.0.lock().unwrap(),
+            vec![b"first".to_vec(), b"second".to_vec()]
+        );
+        output.stop();
+        drop(tx);
+    }
+
+    #[test]
+    fn test_kafka_stop() {
+        let cluster = MockCluster::new(1).unwrap();
+        let output = mock_output(&cluster, "kafka_coalesce = 100\nkafka_linger_ms = 60000\n");
+        let notifier = Arc::new(TestNotifier(Mutex::new(Vec::new())));
+        let (tx, rx) = unbounded();
+        output.start(rx, None, Some(notifier.clone()));
+        tx.send(b"first".to_vec()).unwrap();
+        tx.send(b"second".to_vec()).unwrap();
+
+        // The coalesced records are delivered by the time stop() returns, without waiting for them to linger
+        output.stop();
+        assert_eq!(
+            *notifier.0.lock().unwrap(),
+            vec![b"first".to_vec(), b"second".to_vec()]
+        );
+        assert!(output.workers.lock().unwrap().is_empty());
+        drop(tx);
+    }
+
+    #[test]
+    fn test_kafka_headers_config() {
+        let config = Config::from_string(
+            r#"[output]
+kafka_brokers = ["localhost:9092"]
+kafka_topic = "test"
+kafka_header_fields = ["hostname", "appname"]
+[output.kafka_headers]
+env = "prod"
+"#,
+        )
+        .unwrap();
+        let output = KafkaOutput::new(&config);
+        assert_eq!(output.record_fields(), vec!["hostname", "appname"]);
+
+        let headers = output
+            .config
+            .headers(&[Some(&b"example.org"[..]), None])
+            .unwrap();
+        let headers: Vec<_> = headers
+            .iter()
+            .map(|header| (header.key, header.value.unwrap()))
+            .collect();
+        assert_eq!(
+            headers,
+            vec![("env", &b"prod"[..]), ("hostname", &b"example.org"[..])]
+        );
+    }
+
+    #[test]
+    fn test_kafka_linger_config() {
+        let config = Config::from_string(
+            r#"[output]
+kafka_brokers = ["localhost:9092"]
+kafka_topic = "test"
+"#,
+        )
+        .unwrap();
+        let output = KafkaOutput::new(&config);
+        assert_eq!(
+            output.config.linger,
+            Duration::from_millis(KAFKA_DEFAULT_LINGER_MS)
+        );
+
+        let config = Config::from_string(
+            r#"[output]
+kafka_brokers = ["localhost:9092"]
+kafka_topic = "test"
+kafka_linger_ms = 200
+"#,
+        )
+        .unwrap();
+        let output = KafkaOutput::new(&config);
+        assert_eq!(output.config.linger, Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_kafka_startup_check_config() {
+        let config = Config::from_string(
+            r#"[output]
+kafka_brokers = ["localhost:9092"]
+kafka_topic = "test"
+"#,
+        )
+        .unwrap();
+        assert_eq!(KafkaOutput::new(&config).startup_check, StartupCheck::Fail);
+
+        let config = Config::from_string(
+            r#"[output]
+kafka_brokers = ["localhost:9092"]
+kafka_topic = "test"
+kafka_startup_check = "retry"
+kafka_startup_retry_delay = 1000
+"#,
+        )
+        .unwrap();
+        let output = KafkaOutput::new(&config);
+        assert_eq!(output.startup_check, StartupCheck::Retry);
+        assert_eq!(output.startup_retry_delay, Duration::from_secs(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "output.kafka_startup_check must be")]
+    fn test_kafka_startup_check_invalid() {
+        let config = Config::from_string(
+            r#"[output]
+kafka_brokers = ["localhost:9092"]
+kafka_topic = "test"
+kafka_startup_check = "maybe"
+"#,
+        )
+        .unwrap();
+        KafkaOutput::new(&config);
+    }
+
+    #[test]
+    fn test_kafka_topic_field() {
+        let config = Config::from_string(
+            r#"[output]
+kafka_brokers = ["localhost:9092"]
+kafka_topic = "logs"
+kafka_topic_field = "appname"
+kafka_header_fields = ["hostname"]
+"#,
+        )
+        .unwrap();
+        let output = KafkaOutput::new(&config);
+        assert_eq!(output.record_fields(), vec!["hostname", "appname"]);
+        assert_eq!(output.config.topic(Some(b"billing-api")), "billing-api");
+        assert_eq!(output.config.topic(None), "logs");
+        assert_eq!(output.config.topic(Some(b"")), "logs");
+        assert_eq!(output.config.topic(Some(b"..")), "logs");
+        assert_eq!(output.config.topic(Some(b"not/a topic")), "logs");
+        assert_eq!(output.config.topic(Some(&[b'a'; 250])), "logs");
+    }
+
+    #[test]
+    fn test_kafka_no_headers() {
+        let config = Config::from_string(
+            "[output]\nkafka_brokers = [\"localhost:9092\"]\nkafka_topic = \"test\"\n",
+        )
+        .unwrap();
+        let output = KafkaOutput::new(&config);
+        assert!(output.record_fields().is_empty());
+        assert!(output.config.headers(&[]).is_none());
+    }
 }
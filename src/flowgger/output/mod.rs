@@ -1,30 +1,292 @@
+mod backpressure;
+mod blackhole_output;
+mod circuit_breaker;
+#[cfg(feature = "tls")]
+mod compress;
 mod debug_output;
 #[cfg(feature = "file")]
 mod file_output;
+#[cfg(feature = "file")]
+mod hash_chain;
 #[cfg(feature = "kafka-output")]
 mod kafka_output;
+#[cfg(feature = "mqtt")]
+mod mqtt_output;
+#[cfg(feature = "postgres-output")]
+mod postgres_output;
+mod rate_limiter;
+mod relp_output;
+#[cfg(feature = "sqlite-output")]
+mod sqlite_output;
 #[cfg(feature = "tls")]
 mod tls_output;
+#[cfg(unix)]
+mod unix_output;
 
+pub use self::blackhole_output::BlackholeOutput;
+pub use self::circuit_breaker::CircuitBreaker;
 pub use self::debug_output::DebugOutput;
 #[cfg(feature = "file")]
 pub use self::file_output::FileOutput;
+#[cfg(feature = "file")]
+pub use self::hash_chain::verify_hash_chain;
 #[cfg(feature = "kafka-output")]
 pub use self::kafka_output::KafkaOutput;
+#[cfg(feature = "mqtt")]
+pub use self::mqtt_output::MqttOutput;
+#[cfg(feature = "postgres-output")]
+pub use self::postgres_output::PostgresOutput;
+pub use self::rate_limiter::RateLimiter;
+pub use self::relp_output::RelpOutput;
+#[cfg(feature = "sqlite-output")]
+pub use self::sqlite_output::SqliteOutput;
 #[cfg(feature = "tls")]
 pub use self::tls_output::TlsOutput;
+#[cfg(unix)]
+pub use self::unix_output::UnixOutput;
 
 use crate::flowgger::merger::Merger;
-use std::sync::mpsc::Receiver;
-use std::sync::{Arc, Mutex};
+use crate::flowgger::record_queue::RecordReceiver;
+#[cfg(any(feature = "tls", feature = "kafka-output"))]
+use crossbeam_channel::RecvTimeoutError;
+use std::sync::Arc;
+#[cfg(any(feature = "tls", feature = "kafka-output"))]
+use std::time::Duration;
+
+/// Names of the settings the outputs deserialize with `Config::settings`
+#[cfg(test)]
+pub fn typed_settings() -> Vec<String> {
+    use crate::flowgger::config::setting_names;
+
+    let mut settings = setting_names::<backpressure::BackpressureSettings>("output");
+    settings.extend(setting_names::<blackhole_output::BlackholeSettings>(
+        "output",
+    ));
+    settings.extend(setting_names::<circuit_breaker::SpillSettings>("output"));
+    settings.extend(setting_names::<rate_limiter::RateLimitSettings>("output"));
+    settings.extend(setting_names::<relp_output::RelpSettings>("output"));
+    #[cfg(feature = "tls")]
+    {
+        settings.extend(setting_names::<compress::CompressSettings>("output"));
+        settings.extend(setting_names::<tls_output::TlsSettings>("output"));
+    }
+    #[cfg(feature = "file")]
+    {
+        settings.extend(setting_names::<file_output::FileSettings>("output"));
+        settings.extend(setting_names::<hash_chain::HashChainSettings>("output"));
+    }
+    #[cfg(feature = "kafka-output")]
+    settings.extend(setting_names::<kafka_output::KafkaSettings>("output"));
+    #[cfg(feature = "mqtt")]
+    settings.extend(setting_names::<mqtt_output::MqttSettings>("output"));
+    #[cfg(feature = "postgres-output")]
+    settings.extend(setting_names::<postgres_output::PostgresSettings>("output"));
+    #[cfg(feature = "sqlite-output")]
+    settings.extend(setting_names::<sqlite_output::SqliteSettings>("output"));
+    #[cfg(unix)]
+    settings.extend(setting_names::<unix_output::UnixSettings>("output"));
+    settings
+}
+
+/// Commented settings of an output type, for `flowgger config init`
+///
+/// # Returns
+/// `None` if the output type doesn't exist, or if its support hasn't been compiled in
+pub fn sample_config(output_type: &str) -> Option<String> {
+    match output_type {
+        "stdout" | "debug" => Some(debug_output::sample_config()),
+        "blackhole" | "null" => Some(blackhole_output::sample_config()),
+        #[cfg(feature = "kafka-output")]
+        "kafka" => Some(kafka_output::sample_config()),
+        #[cfg(feature = "mqtt")]
+        "mqtt" => Some(mqtt_output::sample_config()),
+        #[cfg(feature = "postgres-output")]
+        "postgres" | "postgresql" => Some(postgres_output::sample_config()),
+        #[cfg(feature = "tls")]
+        "tls" | "syslog-tls" => Some(tls_output::sample_config()),
+        #[cfg(feature = "file")]
+        "file" => Some(file_output::sample_config()),
+        "relp" => Some(relp_output::sample_config()),
+        #[cfg(feature = "sqlite-output")]
+        "sqlite" => Some(sqlite_output::sample_config()),
+        #[cfg(unix)]
+        "unix" => Some(unix_output::sample_config()),
+        _ => None,
+    }
+}
 
-pub trait Output {
+/// Maximum number of records an output thread takes from the queue in one go
+pub const OUTPUT_BATCH_SIZE: usize = 512;
+
+pub trait Output: Send + Sync {
     /// Start the output processor
     ///
     /// # Parameters
-    /// - 'arx':    Synchronized data receiver
+    /// - 'rx':     Data receiver, can be cloned and shared by several output threads
     /// - 'merger': Optional merger, specifying how to frame the data.
-    ///             i.e. adding an EOL or split after specified size
+    ///   i.e. adding an EOL or split after specified size
+    /// - 'notifier': Optional notifier, told about every delivery made by the output
     ///
-    fn start(&self, arx: Arc<Mutex<Receiver<Vec<u8>>>>, merger: Option<Box<dyn Merger>>);
+    fn start(
+        &self,
+        rx: RecordReceiver,
+        merger: Option<Box<dyn Merger>>,
+        notifier: Option<Arc<dyn Notifier>>,
+    );
+
+    /// Fields of the records the output needs besides the encoded records, that are then prepended to them
+    /// by `FieldsEncoder`
+    fn record_fields(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Deliver the records the output holds back, and wait for them to be delivered, once the input
+    /// stopped or, on Unix, once the process received SIGTERM or SIGINT, and before the process exits
+    fn stop(&self) {}
+}
+
+/// Delivery callback, for applications embedding flowgger that need to know when records have been delivered,
+/// i.e. to implement their own retries or checkpoints.
+///
+/// Outputs call it once per batch, from their own threads:
+/// - blackhole: after the records have been counted and discarded, once the simulated latency elapsed
+/// - debug: after the records have been written to stdout and flushed
+/// - file: after the records have been written to the file, and the file synced to disk
+/// - kafka: once the brokers acknowledged the records, as configured with 'output.kafka_acks'
+/// - mqtt: once written to the connection with QoS 0, or acknowledged by the broker with QoS 1
+/// - postgres: once the COPY of the records has been committed, or with an error for the records the server
+///   rejected
+/// - relp: once acknowledged by the RELP server, or with an error for the records it rejected
+/// - sqlite: once the transaction inserting the records has been committed
+/// - tls: after the records have been written to the connection and flushed
+/// - unix: after the records have been written to the socket
+pub trait Notifier: Send + Sync {
+    /// # Parameters
+    /// - 'records': The records of the batch, as sent by the output (framing included)
+    /// - 'result':  `Ok` if the records have been delivered, or the reason why they could not be.
+    ///   Every record is notified once, with its final outcome: records the output retries are only
+    ///   notified once they have been delivered, or given up on.
+    fn notify(&self, records: &[Vec<u8>], result: Result<(), &str>);
+}
+
+/// Several notifiers, all told about every delivery
+impl Notifier for Vec<Arc<dyn Notifier>> {
+    fn notify(&self, records: &[Vec<u8>], result: Result<(), &str>) {
+        for notifier in self {
+            notifier.notify(records, result);
+        }
+    }
+}
+
+/// Report the outcome of a delivery to the notifier, if there is one
+pub fn notify(notifier: &Option<Arc<dyn Notifier>>, records: &[Vec<u8>], result: Result<(), &str>) {
+    if let Some(notifier) = notifier {
+        notifier.notify(records, result);
+    }
+}
+
+/// Report the outcome of the delivery of every record, in order, with a notification per run of records
+/// with the same outcome. Records that were not delivered are notified with `error`.
+#[cfg(any(feature = "kafka-output", feature = "postgres-output"))]
+pub fn notify_outcomes(
+    notifier: &Option<Arc<dyn Notifier>>,
+    records: &[Vec<u8>],
+    delivered: &[bool],
+    error: &str,
+) {
+    let mut start = 0;
+    while start < records.len() {
+        let run_delivered = delivered[start];
+        let end = delivered[start..]
+            .iter()
+            .position(|&x| x != run_delivered)
+            .map_or(records.len(), |len| start + len);
+        let result = if run_delivered { Ok(()) } else { Err(error) };
+        notify(notifier, &records[start..end], result);
+        start = end;
+    }
+}
+
+/// Wait for the next record, then move it to `batch` along with the records already queued behind it,
+/// up to `OUTPUT_BATCH_SIZE` records.
+///
+/// # Returns
+/// `false` if the queue has been closed and there is nothing left to read
+pub fn recv_batch(rx: &RecordReceiver, batch: &mut Vec<Vec<u8>>) -> bool {
+    rx.recv_batch(batch, OUTPUT_BATCH_SIZE).is_ok()
+}
+
+/// Like `recv_batch`, but only waits up to `timeout` for the next record
+#[cfg(any(feature = "tls", feature = "kafka-output"))]
+pub fn recv_batch_timeout(
+    rx: &RecordReceiver,
+    batch: &mut Vec<Vec<u8>>,
+    timeout: Duration,
+) -> Result<(), RecvTimeoutError> {
+    rx.recv_batch_timeout(batch, OUTPUT_BATCH_SIZE, timeout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::record_queue::bounded;
+
+    #[test]
+    fn test_recv_batch() {
+        let (tx, rx) = bounded(OUTPUT_BATCH_SIZE * 2);
+        for i in 0..OUTPUT_BATCH_SIZE + 3 {
+            tx.send(vec![i as u8]).unwrap();
+        }
+        drop(tx);
+
+        let mut batch = Vec::new();
+        assert!(recv_batch(&rx, &mut batch));
+        assert_eq!(batch.len(), OUTPUT_BATCH_SIZE);
+        assert_eq!(batch[0], vec![0]);
+        batch.clear();
+        assert!(recv_batch(&rx, &mut batch));
+        assert_eq!(batch.len(), 3);
+        batch.clear();
+        assert!(!recv_batch(&rx, &mut batch));
+        assert!(batch.is_empty());
+    }
+
+    #[cfg(any(feature = "kafka-output", feature = "postgres-output"))]
+    struct TestNotifier {
+        notified: std::sync::Mutex<Vec<(Vec<Vec<u8>>, bool)>>,
+    }
+
+    #[cfg(any(feature = "kafka-output", feature = "postgres-output"))]
+    impl Notifier for TestNotifier {
+        fn notify(&self, records: &[Vec<u8>], result: Result<(), &str>) {
+            self.notified
+                .lock()
+                .unwrap()
+                .push((records.to_vec(), result.is_ok()));
+        }
+    }
+
+    #[cfg(any(feature = "kafka-output", feature = "postgres-output"))]
+    #[test]
+    fn test_notify_outcomes() {
+        let notifier = Arc::new(TestNotifier {
+            notified: std::sync::Mutex::new(Vec::new()),
+        });
+        let records: Vec<Vec<u8>> = (b'a'..=b'e').map(|c| vec![c]).collect();
+        notify_outcomes(
+            &Some(notifier.clone() as Arc<dyn Notifier>),
+            &records,
+            &[true, true, false, true, false],
+            "Not delivered",
+        );
+        assert_eq!(
+            *notifier.notified.lock().unwrap(),
+            vec![
+                (vec![b"a".to_vec(), b"b".to_vec()], true),
+                (vec![b"c".to_vec()], false),
+                (vec![b"d".to_vec()], true),
+                (vec![b"e".to_vec()], false),
+            ]
+        );
+    }
 }
@@ -0,0 +1,418 @@
+use super::{notify, recv_batch, Notifier, Output, OUTPUT_BATCH_SIZE};
+use crate::flowgger::config::Config;
+use crate::flowgger::daemon;
+use crate::flowgger::encoder::split_fields;
+use crate::flowgger::merger::Merger;
+use crate::flowgger::record_queue::RecordReceiver;
+use crate::flowgger::utils::threads::{self, CpuAffinity};
+use crossbeam_channel::{unbounded, Receiver};
+use native_tls::{Certificate, Identity, TlsConnector};
+use rumqttc::{
+    Client, Connection, Event, MqttOptions, Outgoing, Packet, QoS, TlsConfiguration, Transport,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{stderr, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::process::exit;
+use std::slice;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const MQTT_DEFAULT_PORT: u16 = 1883;
+const MQTT_DEFAULT_TLS_PORT: u16 = 8883;
+const MQTT_DEFAULT_QOS: i64 = 0;
+const MQTT_DEFAULT_KEEP_ALIVE: u64 = 60;
+const MQTT_DEFAULT_TLS: bool = false;
+const MQTT_MAX_PACKET_SIZE: usize = 1024 * 1024;
+const MQTT_QUEUE_CAPACITY: usize = 1000;
+const MQTT_RECONNECT_DELAY: u64 = 1000;
+
+/// Settings of the MQTT output, in the `[output]` section
+#[derive(Deserialize)]
+pub struct MqttSettings {
+    mqtt_broker: String,
+    mqtt_topic: String,
+    mqtt_qos: Option<i64>,
+    mqtt_client_id: Option<String>,
+    mqtt_keep_alive: Option<u64>,
+    mqtt_username: Option<String>,
+    mqtt_password: Option<String>,
+    mqtt_tls: Option<bool>,
+    mqtt_tls_ca_file: Option<String>,
+    mqtt_tls_cert: Option<String>,
+    mqtt_tls_key: Option<String>,
+}
+
+pub struct MqttOutput {
+    options: MqttOptions,
+    qos: QoS,
+    topic: TopicTemplate,
+    affinity: CpuAffinity,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TopicPart {
+    Literal(String),
+    /// Index of the field in the fields prepended to the records
+    Field(usize),
+}
+
+/// Topic with `{field}` placeholders, replaced with the fields of every record
+#[derive(Clone, Debug, PartialEq)]
+struct TopicTemplate {
+    parts: Vec<TopicPart>,
+    fields: Vec<String>,
+}
+
+impl TopicTemplate {
+    fn parse(template: &str) -> Result<TopicTemplate, &'static str> {
+        if template.is_empty() {
+            return Err("output.mqtt_topic cannot be empty");
+        }
+        if template.contains(['+', '#']) {
+            return Err("output.mqtt_topic cannot contain wildcards");
+        }
+        let mut parts = Vec::new();
+        let mut fields: Vec<String> = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(TopicPart::Literal(rest[..start].to_owned()));
+            }
+            let len = rest[start..]
+                .find('}')
+                .ok_or("Unterminated field name in output.mqtt_topic")?;
+            let name = &rest[start + 1..start + len];
+            if name.is_empty() {
+                return Err("Empty field name in output.mqtt_topic");
+            }
+            let idx = match fields.iter().position(|field| field == name) {
+                Some(idx) => idx,
+                None => {
+                    fields.push(name.to_owned());
+                    fields.len() - 1
+                }
+            };
+            parts.push(TopicPart::Field(idx));
+            rest = &rest[start + len + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(TopicPart::Literal(rest.to_owned()));
+        }
+        Ok(TopicTemplate { parts, fields })
+    }
+
+    /// Build the topic of a record. Missing fields are replaced with "-", and characters that have a
+    /// meaning in topics are replaced with "_", so that a field always fills a single topic level.
+    fn render(&self, values: &[Option<&[u8]>]) -> String {
+        let mut topic = String::new();
+        for part in &self.parts {
+            match part {
+                TopicPart::Literal(literal) => topic.push_str(literal),
+                TopicPart::Field(idx) => match values.get(*idx).copied().flatten() {
+                    None => topic.push('-'),
+                    Some(value) => {
+                        topic.extend(String::from_utf8_lossy(value).chars().map(|c| match c {
+                            '/' | '+' | '#' | '\0' => '_',
+                            c => c,
+                        }))
+                    }
+                },
+            }
+        }
+        topic
+    }
+}
+
+fn read_file(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|e| panic!("Unable to read {}: {}", path, e))
+}
+
+fn tls_connector(settings: &MqttSettings) -> TlsConnector {
+    let mut builder = TlsConnector::builder();
+    if let Some(ca_file) = &settings.mqtt_tls_ca_file {
+        let ca = Certificate::from_pem(&read_file(ca_file))
+            .expect("output.mqtt_tls_ca_file must contain a PEM certificate");
+        builder.add_root_certificate(ca);
+    }
+    match (&settings.mqtt_tls_cert, &settings.mqtt_tls_key) {
+        (Some(cert), Some(key)) => {
+            let identity = Identity::from_pkcs8(&read_file(cert), &read_file(key))
+                .expect("Invalid client certificate or key for the MQTT output");
+            builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => panic!("output.mqtt_tls_cert and output.mqtt_tls_key must be set together"),
+    }
+    builder
+        .build()
+        .expect("Unable to set up TLS for the MQTT output")
+}
+
+impl MqttOutput {
+    pub fn new(config: &Config) -> MqttOutput {
+        let settings: MqttSettings = config.settings("output");
+        let tls = settings.mqtt_tls.unwrap_or(MQTT_DEFAULT_TLS);
+        let broker = &settings.mqtt_broker;
+        let default_port = if tls {
+            MQTT_DEFAULT_TLS_PORT
+        } else {
+            MQTT_DEFAULT_PORT
+        };
+        let (host, port) = broker_address(broker, default_port)
+            .expect("output.mqtt_broker must be a host:port string");
+        let topic = TopicTemplate::parse(&settings.mqtt_topic).unwrap_or_else(|e| panic!("{}", e));
+        let qos = match settings.mqtt_qos.unwrap_or(MQTT_DEFAULT_QOS) {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            _ => panic!("output.mqtt_qos must be 0 or 1"),
+        };
+        let client_id = settings
+            .mqtt_client_id
+            .clone()
+            .unwrap_or_else(|| format!("flowgger-{}", std::process::id()));
+        let keep_alive = settings.mqtt_keep_alive.unwrap_or(MQTT_DEFAULT_KEEP_ALIVE);
+
+        let mut options = MqttOptions::new(client_id, host, port);
+        options
+            .set_keep_alive(Duration::from_secs(keep_alive))
+            .set_max_packet_size(MQTT_MAX_PACKET_SIZE, MQTT_MAX_PACKET_SIZE);
+        if let Some(username) = &settings.mqtt_username {
+            let password = settings.mqtt_password.as_deref().unwrap_or("");
+            options.set_credentials(username, password);
+        }
+        if tls {
+            options.set_transport(Transport::tls_with_config(
+                TlsConfiguration::NativeConnector(tls_connector(&settings)),
+            ));
+        }
+        MqttOutput {
+            options,
+            qos,
+            topic,
+            affinity: CpuAffinity::new(config, "output"),
+        }
+    }
+}
+
+/// Host and port of the broker, from "host", "host:port", "ipv6" or "[ipv6]:port"
+fn broker_address(broker: &str, default_port: u16) -> Option<(String, u16)> {
+    if let Ok(addr) = broker.parse::<SocketAddr>() {
+        return Some((addr.ip().to_string(), addr.port()));
+    }
+    let ip = broker
+        .strip_prefix('[')
+        .and_then(|ip| ip.strip_suffix(']'))
+        .unwrap_or(broker);
+    if let Ok(ip) = ip.parse::<IpAddr>() {
+        return Some((ip.to_string(), default_port));
+    }
+    let (host, port) = match broker.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (broker, default_port),
+    };
+    if host.is_empty() {
+        return None;
+    }
+    Some((host.to_owned(), port))
+}
+
+/// Publish the records received from the queue
+fn run_publisher(
+    rx: RecordReceiver,
+    client: Client,
+    qos: QoS,
+    topic: TopicTemplate,
+    sent_tx: crossbeam_channel::Sender<Vec<u8>>,
+) {
+    let mut batch = Vec::with_capacity(OUTPUT_BATCH_SIZE);
+    while recv_batch(&rx, &mut batch) {
+        for mut bytes in batch.drain(..) {
+            let fields_len = match split_fields(&bytes, topic.fields.len()) {
+                Ok((values, payload)) => {
+                    if let Err(e) = client.publish(topic.render(&values), qos, false, payload) {
+                        let _ = writeln!(stderr(), "MQTT client stopped: [{}]", e);
+                        exit(daemon::EXIT_UNAVAILABLE);
+                    }
+                    bytes.len() - payload.len()
+                }
+                Err(e) => {
+                    let _ = writeln!(stderr(), "{}", e);
+                    continue;
+                }
+            };
+            // Records are notified without the fields used for the topic
+            bytes.drain(..fields_len);
+            let _ = sent_tx.send(bytes);
+        }
+    }
+}
+
+/// Drive the connection to the broker, and report deliveries: QoS 0 records once they have been written
+/// to the connection, QoS 1 records once the broker acknowledged them.
+fn run_connection(
+    mut connection: Connection,
+    qos: QoS,
+    sent_rx: Receiver<Vec<u8>>,
+    notifier: Option<Arc<dyn Notifier>>,
+) {
+    let mut inflight: HashMap<u16, Vec<u8>> = HashMap::new();
+    for event in connection.iter() {
+        match event {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                let _ = writeln!(stderr(), "Connected to the MQTT broker");
+            }
+            // Unacknowledged records are published again after a reconnection, with the same id
+            Ok(Event::Outgoing(Outgoing::Publish(pkid))) if !inflight.contains_key(&pkid) => {
+                let bytes = match sent_rx.recv() {
+                    Ok(bytes) => bytes,
+                    Err(_) => return,
+                };
+                if qos == QoS::AtMostOnce {
+                    notify(&notifier, slice::from_ref(&bytes), Ok(()));
+                } else {
+                    inflight.insert(pkid, bytes);
+                }
+            }
+            Ok(Event::Incoming(Packet::PubAck(ack))) => {
+                if let Some(bytes) = inflight.remove(&ack.pkid) {
+                    notify(&notifier, slice::from_ref(&bytes), Ok(()));
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                let _ = writeln!(stderr(), "MQTT connection error: [{}]", e);
+                thread::sleep(Duration::from_millis(MQTT_RECONNECT_DELAY));
+            }
+        }
+    }
+}
+
+impl Output for MqttOutput {
+    fn start(
+        &self,
+        rx: RecordReceiver,
+        merger: Option<Box<dyn Merger>>,
+        notifier: Option<Arc<dyn Notifier>>,
+    ) {
+        if merger.is_some() {
+            let _ = writeln!(stderr(), "Output framing is ignored with the MQTT output");
+        }
+        let (client, connection) = Client::new(self.options.clone(), MQTT_QUEUE_CAPACITY);
+        let (sent_tx, sent_rx) = unbounded();
+        let (qos, topic) = (self.qos, self.topic.clone());
+        let affinity = &self.affinity;
+        threads::spawn(
+            "flowgger-output-mqtt-publisher".to_owned(),
+            affinity.cpu(0),
+            move || run_publisher(rx, client, qos, topic, sent_tx),
+        );
+        threads::spawn(
+            "flowgger-output-mqtt-connection".to_owned(),
+            affinity.cpu(1),
+            move || run_connection(connection, qos, sent_rx, notifier),
+        );
+    }
+
+    fn record_fields(&self) -> Vec<String> {
+        self.topic.fields.clone()
+    }
+}
+
+/// Settings of the MQTT output, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "# Broker, as host:port or [ipv6]:port, the port being {} by default, {} with TLS\n\
+         mqtt_broker = \"127.0.0.1:{}\"\n\
+         # Topic, where {{field}} is replaced with the value of a field of the record\n\
+         mqtt_topic = \"logs/{{hostname}}\"\n\
+         # mqtt_qos = {}\n\
+         # mqtt_keep_alive = {}\n\
+         # mqtt_client_id = \"flowgger\"\n\
+         # mqtt_username = \"flowgger\"\n\
+         # mqtt_password = \"secret\"\n\
+         # mqtt_tls = {}\n\
+         # mqtt_tls_ca_file = \"ca.pem\"\n",
+        MQTT_DEFAULT_PORT,
+        MQTT_DEFAULT_TLS_PORT,
+        MQTT_DEFAULT_PORT,
+        MQTT_DEFAULT_QOS,
+        MQTT_DEFAULT_KEEP_ALIVE,
+        MQTT_DEFAULT_TLS
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic_template() {
+        let topic = TopicTemplate::parse("logs/{hostname}/{appname}/{hostname}").unwrap();
+        assert_eq!(topic.fields, vec!["hostname", "appname"]);
+        assert_eq!(
+            topic.render(&[Some(&b"example.org"[..]), None]),
+            "logs/example.org/-/example.org"
+        );
+        assert_eq!(
+            topic.render(&[Some(&b"a/b+#"[..]), Some(&b"app"[..])]),
+            "logs/a_b__/app/a_b__"
+        );
+        assert_eq!(TopicTemplate::parse("logs").unwrap().render(&[]), "logs");
+    }
+
+    #[test]
+    fn test_topic_template_invalid() {
+        assert!(TopicTemplate::parse("").is_err());
+        assert!(TopicTemplate::parse("logs/#").is_err());
+        assert!(TopicTemplate::parse("logs/{hostname").is_err());
+        assert!(TopicTemplate::parse("logs/{}").is_err());
+    }
+
+    #[test]
+    fn test_mqtt_config() {
+        let config = Config::from_string(
+            "[output]\nmqtt_broker = \"localhost\"\nmqtt_topic = \"logs/{appname}\"\nmqtt_qos = 1\n",
+        )
+        .unwrap();
+        let output = MqttOutput::new(&config);
+        assert_eq!(
+            output.options.broker_address(),
+            ("localhost".to_owned(), 1883)
+        );
+        assert_eq!(output.qos, QoS::AtLeastOnce);
+        assert_eq!(output.record_fields(), vec!["appname"]);
+    }
+
+    #[test]
+    fn test_mqtt_broker_address() {
+        let address = |broker| broker_address(broker, 1883);
+        assert_eq!(address("localhost"), Some(("localhost".to_owned(), 1883)));
+        assert_eq!(
+            address("mqtt.local:8883"),
+            Some(("mqtt.local".to_owned(), 8883))
+        );
+        assert_eq!(
+            address("10.0.0.1:1884"),
+            Some(("10.0.0.1".to_owned(), 1884))
+        );
+        assert_eq!(address("[::1]:1884"), Some(("::1".to_owned(), 1884)));
+        assert_eq!(address("[fe80::1]"), Some(("fe80::1".to_owned(), 1883)));
+        assert_eq!(address("fe80::1"), Some(("fe80::1".to_owned(), 1883)));
+        assert_eq!(address("localhost:port"), None);
+        assert_eq!(address(":1883"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "output.mqtt_qos must be 0 or 1")]
+    fn test_mqtt_config_qos2() {
+        let config = Config::from_string(
+            "[output]\nmqtt_broker = \"localhost\"\nmqtt_topic = \"logs\"\nmqtt_qos = 2\n",
+        )
+        .unwrap();
+        let _ = MqttOutput::new(&config);
+    }
+}
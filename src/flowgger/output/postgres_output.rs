@@ -0,0 +1,409 @@
+use super::backpressure::Backpressure;
+use super::{notify_outcomes, recv_batch, Notifier, Output, OUTPUT_BATCH_SIZE};
+use crate::flowgger::config::Config;
+use crate::flowgger::encoder::split_fields;
+use crate::flowgger::merger::Merger;
+use crate::flowgger::record_queue::RecordReceiver;
+use crate::flowgger::utils::threads::{self, CpuAffinity};
+use postgres::config::SslMode;
+use postgres::{Client, NoTls};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::{stderr, Write};
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_POSTGRES_CONNECTIONS: u32 = 1;
+const RECOVERY_DELAY_INIT: Duration = Duration::from_secs(1);
+const RECOVERY_DELAY_MAX: Duration = Duration::from_secs(30);
+
+/// PostgreSQL (and TimescaleDB) output, writing records as rows of a table, for deployments that want logs
+/// they can query with SQL without running a log store.
+///
+/// Records are written in batches with `COPY ... FROM STDIN`, the values being converted to the types of the
+/// columns by the server. A batch the server rejects is split in halves that are written again, until only
+/// the invalid records are left out.
+///
+/// TLS is not supported: connections are not encrypted, so the server should be local, or reached through a
+/// tunnel. Connection strings that require TLS (`sslmode=require`) are refused.
+/// Settings of the PostgreSQL output, in the `[output]` section
+#[derive(Deserialize)]
+pub struct PostgresSettings {
+    postgres_url: String,
+    postgres_table: String,
+    #[serde(default)]
+    postgres_columns: BTreeMap<String, String>,
+    postgres_record_column: Option<String>,
+    postgres_connections: Option<NonZeroU32>,
+}
+
+pub struct PostgresOutput {
+    config: postgres::Config,
+    copy: Arc<str>,
+    fields: Vec<String>,
+    record_column: bool,
+    connections: u32,
+    backpressure: Backpressure,
+    affinity: CpuAffinity,
+}
+
+/// Quote a possibly schema-qualified identifier, that is then case-sensitive
+fn quote_identifier(name: &str) -> String {
+    name.split('.')
+        .map(|part| format!("\"{}\"", part.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+impl PostgresOutput {
+    /// # Parameters
+    /// - 'output.postgres_url':           Connection string, i.e. "postgresql://flowgger@localhost/logs" or
+    ///   "host=localhost user=flowgger dbname=logs".
+    /// - 'output.postgres_table':         Table the records are written to.
+    /// - 'output.postgres_columns':       Optional. Table of columns and the record fields they are set to:
+    ///   "ts" (RFC3339 timestamp), "hostname", "appname", "msg", or any structured data pair.
+    /// - 'output.postgres_record_column': Optional. Column set to the record, as encoded with 'output.format',
+    ///   i.e. a `jsonb` column with the "gelf" format.
+    /// - 'output.postgres_connections':   Optional. Number of connections writing in parallel. Default is 1.
+    /// - 'output.pause_inputs':           Optional. Pause the inputs while no connection is open. Default is
+    ///   false.
+    pub fn new(config: &Config) -> PostgresOutput {
+        let settings: PostgresSettings = config.settings("output");
+        let pg_config = settings
+            .postgres_url
+            .parse::<postgres::Config>()
+            .unwrap_or_else(|e| panic!("Invalid output.postgres_url: {}", e));
+        if pg_config.get_ssl_mode() == SslMode::Require {
+            panic!(
+                "output.postgres_url can't require TLS, that the PostgreSQL output doesn't support"
+            );
+        }
+        let table = &settings.postgres_table;
+        let mut columns = Vec::new();
+        let mut fields = Vec::new();
+        for (column, field) in settings.postgres_columns {
+            columns.push(quote_identifier(&column));
+            fields.push(field);
+        }
+        if let Some(record_column) = &settings.postgres_record_column {
+            columns.push(quote_identifier(record_column));
+        }
+        if columns.is_empty() {
+            panic!("output.postgres_columns or output.postgres_record_column must be set");
+        }
+        let connections = settings
+            .postgres_connections
+            .map_or(DEFAULT_POSTGRES_CONNECTIONS, NonZeroU32::get);
+        let copy = format!(
+            "COPY {} ({}) FROM STDIN",
+            quote_identifier(table),
+            columns.join(", ")
+        );
+        PostgresOutput {
+            config: pg_config,
+            copy: copy.into(),
+            fields,
+            record_column: settings.postgres_record_column.is_some(),
+            connections,
+            backpressure: Backpressure::from_config(config),
+            affinity: CpuAffinity::new(config, "output"),
+        }
+    }
+}
+
+impl Output for PostgresOutput {
+    fn start(
+        &self,
+        rx: RecordReceiver,
+        merger: Option<Box<dyn Merger>>,
+        notifier: Option<Arc<dyn Notifier>>,
+    ) {
+        if merger.is_some() {
+            let _ = writeln!(
+                stderr(),
+                "Output framing is ignored with the PostgreSQL output"
+            );
+        }
+        for i in 0..self.connections {
+            let worker = PostgresWorker {
+                rx: rx.clone(),
+                notifier: notifier.clone(),
+                config: self.config.clone(),
+                copy: Arc::clone(&self.copy),
+                fields_count: self.fields.len(),
+                record_column: self.record_column,
+                backpressure: self.backpressure.clone(),
+            };
+            threads::spawn(
+                format!("flowgger-output-postgres-{}", i),
+                self.affinity.cpu(i as usize),
+                move || worker.run(),
+            );
+        }
+    }
+
+    fn record_fields(&self) -> Vec<String> {
+        self.fields.clone()
+    }
+}
+
+struct PostgresWorker {
+    rx: RecordReceiver,
+    notifier: Option<Arc<dyn Notifier>>,
+    config: postgres::Config,
+    copy: Arc<str>,
+    fields_count: usize,
+    record_column: bool,
+    backpressure: Backpressure,
+}
+
+impl PostgresWorker {
+    fn run(self) {
+        let mut batch = Vec::with_capacity(OUTPUT_BATCH_SIZE);
+        let mut recovery_delay = RECOVERY_DELAY_INIT;
+        loop {
+            match self.handle_connection(&mut batch, &mut recovery_delay) {
+                Ok(()) => return,
+                Err(e) => {
+                    let _ = writeln!(
+                        stderr(),
+                        "Error while writing to PostgreSQL - {}",
+                        describe(&e)
+                    );
+                    self.backpressure.disconnected();
+                }
+            }
+            thread::sleep(recovery_delay);
+            recovery_delay = (recovery_delay * 2).min(RECOVERY_DELAY_MAX);
+            let _ = writeln!(stderr(), "Attempting to reconnect");
+        }
+    }
+
+    /// Write the records over a new connection until it breaks. The records that have not been written yet
+    /// are left in `batch`, and only notified once written, or rejected by the server.
+    ///
+    /// # Returns
+    /// `Ok` once the queue has been closed and all the records written
+    fn handle_connection(
+        &self,
+        batch: &mut Vec<Vec<u8>>,
+        recovery_delay: &mut Duration,
+    ) -> Result<(), postgres::Error> {
+        let mut client = self.config.connect(NoTls)?;
+        let _ = writeln!(stderr(), "Connected to PostgreSQL");
+        let _connection = self.backpressure.connected();
+        *recovery_delay = RECOVERY_DELAY_INIT;
+        loop {
+            if batch.is_empty() && !recv_batch(&self.rx, batch) {
+                return Ok(());
+            }
+            let rows = self.rows(batch);
+            let mut written = Vec::with_capacity(batch.len());
+            let res = write_rows(
+                &rows,
+                &mut written,
+                &mut |data| match self.copy(&mut client, data) {
+                    Ok(()) => Ok(Ok(())),
+                    Err(e) if client.is_closed() => Err(e),
+                    Err(e) => Ok(Err(describe(&e))),
+                },
+            );
+            notify_outcomes(
+                &self.notifier,
+                &batch[..written.len()],
+                &written,
+                "Rejected by PostgreSQL",
+            );
+            batch.drain(..written.len());
+            res?;
+        }
+    }
+
+    /// The row of every record, or `None` for the records whose fields can't be read
+    fn rows(&self, records: &[Vec<u8>]) -> Vec<Option<Vec<u8>>> {
+        records
+            .iter()
+            .map(|record| match split_fields(record, self.fields_count) {
+                Ok((values, payload)) => {
+                    let mut row = Vec::new();
+                    push_row(&mut row, &values, self.record_column.then_some(payload));
+                    Some(row)
+                }
+                Err(e) => {
+                    let _ = writeln!(stderr(), "{}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn copy(&self, client: &mut Client, rows: &[u8]) -> Result<(), postgres::Error> {
+        let mut writer = client.copy_in(&*self.copy)?;
+        // Errors are reported by `finish()`
+        let _ = writer.write_all(rows);
+        writer.finish().map(|_| ())
+    }
+}
+
+/// Outcome of a COPY: `Ok(Err(reason))` if the server rejected the rows, or `Err` if the connection broke
+type CopyResult<E> = Result<Result<(), String>, E>;
+
+/// Write `rows` with `copy`, splitting the batches the server rejects in halves until the rejected rows are
+/// isolated. Rows are written in order, and `None` rows are not written. `written` gets whether each row
+/// has been written, up to the last one written or rejected before `copy` failed.
+fn write_rows<E>(
+    rows: &[Option<Vec<u8>>],
+    written: &mut Vec<bool>,
+    copy: &mut dyn FnMut(&[u8]) -> CopyResult<E>,
+) -> Result<(), E> {
+    let data: Vec<u8> = rows.iter().flatten().flatten().copied().collect();
+    if data.is_empty() {
+        written.extend(rows.iter().map(|_| false));
+        return Ok(());
+    }
+    match copy(&data)? {
+        Ok(()) => written.extend(rows.iter().map(Option::is_some)),
+        Err(reason) if rows.len() == 1 => {
+            let _ = writeln!(stderr(), "PostgreSQL rejected a record - {}", reason);
+            written.push(false);
+        }
+        Err(_) => {
+            let (first, second) = rows.split_at(rows.len() / 2);
+            write_rows(first, written, copy)?;
+            write_rows(second, written, copy)?;
+        }
+    }
+    Ok(())
+}
+
+/// The message of the server for database errors, that are otherwise only described as "db error"
+fn describe(e: &postgres::Error) -> String {
+    e.as_db_error()
+        .map_or_else(|| e.to_string(), |db_error| db_error.to_string())
+}
+
+/// Append a row in the text format of COPY
+fn push_row(rows: &mut Vec<u8>, values: &[Option<&[u8]>], record: Option<&[u8]>) {
+    for (i, value) in values.iter().copied().chain(record.map(Some)).enumerate() {
+        if i > 0 {
+            rows.push(b'\t');
+        }
+        match value {
+            None => rows.extend_from_slice(b"\\N"),
+            Some(value) => {
+                for &c in value {
+                    match c {
+                        b'\\' => rows.extend_from_slice(b"\\\\"),
+                        b'\n' => rows.extend_from_slice(b"\\n"),
+                        b'\r' => rows.extend_from_slice(b"\\r"),
+                        b'\t' => rows.extend_from_slice(b"\\t"),
+                        c => rows.push(c),
+                    }
+                }
+            }
+        }
+    }
+    rows.push(b'\n');
+}
+
+/// Settings of the PostgreSQL output, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "postgres_url = \"postgresql://flowgger@localhost/logs\"\n\
+         postgres_table = \"logs\"\n\
+         # postgres_connections = {}\n",
+        DEFAULT_POSTGRES_CONNECTIONS
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_postgres_copy_statement() {
+        let config = Config::from_string(
+            r#"
+[output]
+postgres_url = "postgresql://flowgger@localhost/logs"
+postgres_table = "public.logs"
+postgres_record_column = "record"
+[output.postgres_columns]
+ts = "ts"
+host = "hostname"
+"#,
+        )
+        .unwrap();
+        let output = PostgresOutput::new(&config);
+        assert_eq!(
+            &*output.copy,
+            r#"COPY "public"."logs" ("host", "ts", "record") FROM STDIN"#
+        );
+        assert_eq!(output.record_fields(), vec!["hostname", "ts"]);
+        assert_eq!(quote_identifier(r#"we"ird"#), r#""we""ird""#);
+    }
+
+    #[test]
+    #[should_panic(expected = "output.postgres_url can't require TLS")]
+    fn test_postgres_tls_required() {
+        let config = Config::from_string(
+            r#"
+[output]
+postgres_url = "postgresql://flowgger@localhost/logs?sslmode=require"
+postgres_table = "logs"
+postgres_record_column = "record"
+"#,
+        )
+        .unwrap();
+        PostgresOutput::new(&config);
+    }
+
+    #[test]
+    fn test_postgres_write_rows() {
+        let row = |row: &str| Some(row.as_bytes().to_vec());
+        let rows = vec![row("a\n"), row("bad\n"), None, row("c\n"), row("d\n")];
+        // The server rejects the batches with "bad" rows
+        let mut copies = Vec::new();
+        let mut copy = |data: &[u8]| -> CopyResult<()> {
+            let data = String::from_utf8(data.to_vec()).unwrap();
+            copies.push(data.clone());
+            Ok(if data.contains("bad") {
+                Err("invalid row".to_owned())
+            } else {
+                Ok(())
+            })
+        };
+        let mut written = Vec::new();
+        write_rows(&rows, &mut written, &mut copy).unwrap();
+        assert_eq!(written, vec![true, false, false, true, true]);
+        assert_eq!(
+            copies,
+            vec!["a\nbad\nc\nd\n", "a\nbad\n", "a\n", "bad\n", "c\nd\n"]
+        );
+
+        // The connection breaks while writing the second half
+        let mut copy = |data: &[u8]| -> CopyResult<()> {
+            match data {
+                b"a\nbad\n" | b"a\n" | b"bad\n" => Ok(Err("invalid row".to_owned())),
+                b"c\nd\n" => Err(()),
+                _ => Ok(Err("invalid row".to_owned())),
+            }
+        };
+        let mut written = Vec::new();
+        assert!(write_rows(&rows, &mut written, &mut copy).is_err());
+        assert_eq!(written, vec![false, false]);
+    }
+
+    #[test]
+    fn test_postgres_row() {
+        let mut rows = Vec::new();
+        push_row(
+            &mut rows,
+            &[Some(&b"a\tb\\c"[..]), None],
+            Some(&b"{\"msg\":\"x\ny\"}"[..]),
+        );
+        assert_eq!(rows, b"a\\tb\\\\c\t\\N\t{\"msg\":\"x\\ny\"}\n");
+    }
+}
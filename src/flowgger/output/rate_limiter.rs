@@ -0,0 +1,125 @@
+use super::OUTPUT_BATCH_SIZE;
+use crate::flowgger::admin;
+use crate::flowgger::config::Config;
+use crate::flowgger::record_queue::{self, RecordReceiver};
+use crate::flowgger::utils::threads;
+use serde::Deserialize;
+use std::num::NonZeroU64;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Settings of the rate limit, in the `[output]` section
+#[derive(Deserialize)]
+pub struct RateLimitSettings {
+    rate_limit: Option<NonZeroU64>,
+    rate_limit_burst: Option<NonZeroU64>,
+}
+
+/// Egress rate limit of the output, as a token bucket: records are let through as long as the bucket holds
+/// enough bytes, refilled at `rate` bytes per second up to `burst` bytes.
+/// Records that don't fit wait in the queue, so that the inputs slow down as they would with a slow network.
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// # Parameters
+    /// - 'output.rate_limit':       Optional. Maximum throughput in bytes per second. The output isn't
+    ///   rate limited when this is not set.
+    /// - 'output.rate_limit_burst': Optional. Number of bytes that can be sent at once after the output has
+    ///   been idle. Default is one second worth of 'output.rate_limit'.
+    pub fn from_config(config: &Config) -> Option<RateLimiter> {
+        let settings: RateLimitSettings = config.settings("output");
+        let rate = settings.rate_limit?.get();
+        let burst = settings.rate_limit_burst.map_or(rate, NonZeroU64::get);
+        Some(RateLimiter::new(rate as f64, burst as f64, Instant::now()))
+    }
+
+    fn new(rate: f64, burst: f64, now: Instant) -> RateLimiter {
+        RateLimiter {
+            rate,
+            burst,
+            tokens: burst,
+            last_refill: now,
+        }
+    }
+
+    /// Forward the records of `rx` to the returned receiver, no faster than the rate limit
+    pub fn start(mut self, rx: RecordReceiver) -> RecordReceiver {
+        let (tx, limited_rx) = record_queue::bounded(OUTPUT_BATCH_SIZE);
+        // Whether a record received from the queue is waiting for the bucket to refill
+        let held = Arc::new(AtomicBool::new(false));
+        let (pending, output_rx) = (Arc::clone(&held), limited_rx.occupancy());
+        admin::register_pending(move || pending.load(Ordering::Acquire) || !output_rx.is_empty());
+        threads::spawn("flowgger-output-rate-limiter".to_owned(), None, move || {
+            for bytes in rx.iter() {
+                held.store(true, Ordering::Release);
+                let delay = self.reserve(bytes.len(), Instant::now());
+                if !delay.is_zero() {
+                    thread::sleep(delay);
+                }
+                if tx.send(bytes).is_err() {
+                    return;
+                }
+                held.store(false, Ordering::Release);
+            }
+        });
+        limited_rx
+    }
+
+    /// Take `len` bytes from the bucket, and return how long to wait before sending them.
+    /// Records larger than the bucket are not rejected: they leave it in debt, and the records after
+    /// them wait until it has been paid back.
+    fn reserve(&mut self, len: usize, now: Instant) -> Duration {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last_refill = now;
+        self.tokens -= len as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_reserve() {
+        let start = Instant::now();
+        let mut rate_limiter = RateLimiter::new(1000.0, 2000.0, start);
+        assert_eq!(rate_limiter.reserve(1500, start), Duration::ZERO);
+        assert_eq!(rate_limiter.reserve(500, start), Duration::ZERO);
+        assert_eq!(rate_limiter.reserve(500, start), Duration::from_millis(500));
+        let later = start + Duration::from_secs(10);
+        assert_eq!(rate_limiter.reserve(2000, later), Duration::ZERO);
+        assert_eq!(rate_limiter.reserve(3000, later), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_rate_limiter_config() {
+        let config = Config::from_string("[output]\nrate_limit = 4096\n").unwrap();
+        let rate_limiter = RateLimiter::from_config(&config).unwrap();
+        assert_eq!(rate_limiter.rate, 4096.0);
+        assert_eq!(rate_limiter.burst, 4096.0);
+        let config = Config::from_string("[output]\n").unwrap();
+        assert!(RateLimiter::from_config(&config).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a nonzero u64 for key `output.rate_limit`")]
+    fn test_rate_limiter_config_invalid() {
+        let config = Config::from_string("[output]\nrate_limit = 0\n").unwrap();
+        let _ = RateLimiter::from_config(&config);
+    }
+}
@@ -0,0 +1,412 @@
+use super::backpressure::Backpressure;
+use super::{notify, recv_batch, Notifier, Output, OUTPUT_BATCH_SIZE};
+use crate::flowgger::config::Config;
+use crate::flowgger::merger::Merger;
+use crate::flowgger::record_queue::RecordReceiver;
+use crate::flowgger::utils::relp::{self, Frame, RELP_MAX_TXNR, RELP_SOFTWARE};
+use crate::flowgger::utils::threads::{self, CpuAffinity};
+use serde::Deserialize;
+use std::io::{self, stderr, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_RELP_WINDOW: usize = 128;
+const DEFAULT_TIMEOUT: u64 = 90;
+const RECOVERY_DELAY_INIT: Duration = Duration::from_secs(1);
+const RECOVERY_DELAY_MAX: Duration = Duration::from_secs(30);
+/// Maximum size of the responses of the server, offers included
+const RELP_MAX_RESPONSE_SIZE: usize = 64 * 1024;
+
+/// Settings of the RELP output, in the `[output]` section
+#[derive(Deserialize)]
+pub struct RelpSettings {
+    connect: Vec<String>,
+    relp_window: Option<NonZeroUsize>,
+    timeout: Option<u64>,
+}
+
+/// RELP (Reliable Event Logging Protocol) output, to forward records to rsyslog (imrelp) or to any other RELP
+/// server that requires reliable delivery.
+///
+/// Records are only considered delivered once the server acknowledged them, in any order. The ones that were not
+/// when a connection broke are sent again, possibly twice, to the next server, while the others are not.
+pub struct RelpOutput {
+    connect: Vec<String>,
+    window: usize,
+    timeout: Option<Duration>,
+    backpressure: Backpressure,
+    affinity: CpuAffinity,
+}
+
+impl RelpOutput {
+    /// # Parameters
+    /// - 'output.connect':     List of "host:port" RELP servers, tried in turn when a connection fails.
+    /// - 'output.relp_window': Optional. Maximum number of records sent without having been acknowledged yet.
+    ///   Default is 128, as with rsyslog.
+    /// - 'output.timeout':     Optional. Seconds to wait for the server to accept records or to acknowledge them
+    ///   before reconnecting. Default is 90.
+    /// - 'output.pause_inputs': Optional. Pause the inputs while no RELP session is open. Default is false.
+    pub fn new(config: &Config) -> RelpOutput {
+        let settings: RelpSettings = config.settings("output");
+        let connect = settings.connect;
+        if connect.is_empty() {
+            panic!("output.connect must list at least one server");
+        }
+        let window = settings
+            .relp_window
+            .map_or(DEFAULT_RELP_WINDOW, NonZeroUsize::get);
+        let timeout = settings.timeout.unwrap_or(DEFAULT_TIMEOUT);
+        RelpOutput {
+            connect,
+            window,
+            timeout: Some(Duration::from_secs(timeout)),
+            backpressure: Backpressure::from_config(config),
+            affinity: CpuAffinity::new(config, "output"),
+        }
+    }
+}
+
+impl Output for RelpOutput {
+    fn start(
+        &self,
+        rx: RecordReceiver,
+        merger: Option<Box<dyn Merger>>,
+        notifier: Option<Arc<dyn Notifier>>,
+    ) {
+        let worker = RelpWorker {
+            rx,
+            merger: merger.map(|merger| merger.clone_boxed()),
+            notifier,
+            connect: self.connect.clone(),
+            window: self.window,
+            timeout: self.timeout,
+            backpressure: self.backpressure.clone(),
+        };
+        threads::spawn(
+            "flowgger-output-relp".to_owned(),
+            self.affinity.cpu(0),
+            move || worker.run(),
+        );
+    }
+}
+
+struct RelpWorker {
+    rx: RecordReceiver,
+    merger: Option<Box<dyn Merger + Send>>,
+    notifier: Option<Arc<dyn Notifier>>,
+    connect: Vec<String>,
+    window: usize,
+    timeout: Option<Duration>,
+    backpressure: Backpressure,
+}
+
+impl RelpWorker {
+    fn run(self) {
+        let mut batch = Vec::with_capacity(OUTPUT_BATCH_SIZE);
+        let mut acked = Vec::with_capacity(OUTPUT_BATCH_SIZE);
+        let mut recovery_delay = RECOVERY_DELAY_INIT;
+        for connect in self.connect.iter().cycle() {
+            match self.handle_connection(connect, &mut batch, &mut acked, &mut recovery_delay) {
+                Ok(()) => return,
+                Err(e) => {
+                    let _ = writeln!(
+                        stderr(),
+                        "Error while communicating with RELP server {} - {}",
+                        connect,
+                        e
+                    );
+                    self.backpressure.disconnected();
+                }
+            }
+            thread::sleep(recovery_delay);
+            recovery_delay = (recovery_delay * 2).min(RECOVERY_DELAY_MAX);
+            let _ = writeln!(stderr(), "Attempting to reconnect");
+        }
+    }
+
+    /// Send the records over a new session until an error occurs. The records that can't be notified yet are
+    /// left in `batch`, with whether they have been acknowledged in `acked`.
+    ///
+    /// # Returns
+    /// `Ok` once the queue has been closed and all the records acknowledged
+    fn handle_connection(
+        &self,
+        connect: &str,
+        batch: &mut Vec<Vec<u8>>,
+        acked: &mut Vec<Option<bool>>,
+        recovery_delay: &mut Duration,
+    ) -> io::Result<()> {
+        let stream = TcpStream::connect(connect)?;
+        stream.set_read_timeout(self.timeout)?;
+        stream.set_write_timeout(self.timeout)?;
+        let mut client = RelpClient::open(stream.try_clone()?, stream)?;
+        let _ = writeln!(stderr(), "Opened a RELP session with {}", connect);
+        let _connection = self.backpressure.connected();
+        *recovery_delay = RECOVERY_DELAY_INIT;
+        loop {
+            if batch.is_empty() {
+                if !recv_batch(&self.rx, batch) {
+                    client.close();
+                    return Ok(());
+                }
+                if let Some(ref merger) = self.merger {
+                    for bytes in batch.iter_mut() {
+                        merger.frame(bytes);
+                    }
+                }
+            }
+            acked.resize(batch.len(), None);
+            // The records that were not acknowledged are kept, and sent again once reconnected
+            let res = client.send_records(batch, self.window, acked);
+            self.notify_acked(batch, acked);
+            res?;
+        }
+    }
+
+    /// Notify the records acknowledged before the first one that was not, in order, and remove them from
+    /// `batch` and `acked`
+    fn notify_acked(&self, batch: &mut Vec<Vec<u8>>, acked: &mut Vec<Option<bool>>) {
+        let len = acked
+            .iter()
+            .position(Option::is_none)
+            .unwrap_or(acked.len());
+        let mut start = 0;
+        while start < len {
+            let accepted = acked[start];
+            let end = acked[start..len]
+                .iter()
+                .position(|&x| x != accepted)
+                .map_or(len, |run| start + run);
+            let result = if accepted == Some(true) {
+                Ok(())
+            } else {
+                Err("Rejected by the RELP server")
+            };
+            notify(&self.notifier, &batch[start..end], result);
+            start = end;
+        }
+        batch.drain(..len);
+        acked.drain(..len);
+    }
+}
+
+/// Client side of a RELP session
+struct RelpClient<R: Read, W: Write> {
+    reader: BufReader<R>,
+    writer: BufWriter<W>,
+    txnr: u32,
+}
+
+impl<R: Read, W: Write> RelpClient<R, W> {
+    /// Open a session, and check that the server supports the syslog command
+    fn open(reader: R, writer: W) -> io::Result<RelpClient<R, W>> {
+        let mut client = RelpClient {
+            reader: BufReader::new(reader),
+            writer: BufWriter::new(writer),
+            txnr: 0,
+        };
+        let offers = format!(
+            "relp_version=0\nrelp_software={}\ncommands=syslog",
+            RELP_SOFTWARE
+        );
+        client.send("open", offers.as_bytes())?;
+        client.writer.flush()?;
+        let response = client.read_response()?;
+        let response = String::from_utf8_lossy(&response.data);
+        let mut lines = response.lines();
+        if !lines.next().is_some_and(|status| status.starts_with("200")) {
+            return Err(io::Error::new(
+                ErrorKind::ConnectionRefused,
+                format!("RELP session refused: {}", response),
+            ));
+        }
+        let syslog_supported = lines
+            .find_map(|offer| offer.strip_prefix("commands="))
+            .is_none_or(|commands| commands.split(',').any(|command| command == "syslog"));
+        if !syslog_supported {
+            return Err(io::Error::other(
+                "The RELP server doesn't support the syslog command",
+            ));
+        }
+        Ok(client)
+    }
+
+    /// Send the records of `batch` that have not been acknowledged yet, with up to `window` records waiting for
+    /// their acknowledgement, until all of them have been. The server can acknowledge them in any order.
+    /// `acked` has an entry per record, `None` until the record has been acknowledged, and then whether the
+    /// server accepted it. Records the server answered with an error are not sent again.
+    fn send_records(
+        &mut self,
+        batch: &[Vec<u8>],
+        window: usize,
+        acked: &mut [Option<bool>],
+    ) -> io::Result<()> {
+        let unacked: Vec<usize> = (0..batch.len()).filter(|&i| acked[i].is_none()).collect();
+        let mut unacked = unacked.into_iter();
+        // Transaction numbers of the records waiting for their acknowledgement, with their indexes
+        let mut pending: Vec<(u32, usize)> = Vec::with_capacity(window);
+        loop {
+            while pending.len() < window {
+                match unacked.next() {
+                    Some(i) => pending.push((self.send("syslog", &batch[i])?, i)),
+                    None => break,
+                }
+            }
+            if pending.is_empty() {
+                return Ok(());
+            }
+            self.writer.flush()?;
+            let response = self.read_response()?;
+            let position = pending
+                .iter()
+                .position(|&(txnr, _)| txnr == response.txnr)
+                .ok_or_else(|| {
+                    io::Error::new(ErrorKind::InvalidData, "Unexpected RELP transaction number")
+                })?;
+            let (_, i) = pending.swap_remove(position);
+            let accepted = response.data.starts_with(b"200");
+            if !accepted {
+                let _ = writeln!(
+                    stderr(),
+                    "Record rejected by the RELP server: {}",
+                    String::from_utf8_lossy(&response.data)
+                );
+            }
+            acked[i] = Some(accepted);
+        }
+    }
+
+    /// Close the session, once every record has been acknowledged
+    fn close(&mut self) {
+        let _ = self
+            .send("close", b"")
+            .and_then(|_| self.writer.flush())
+            .and_then(|_| self.read_response());
+    }
+
+    /// # Returns
+    /// The transaction number of the command
+    fn send(&mut self, command: &str, data: &[u8]) -> io::Result<u32> {
+        self.txnr = if self.txnr >= RELP_MAX_TXNR {
+            1
+        } else {
+            self.txnr + 1
+        };
+        self.writer
+            .write_all(&relp::encode_frame(self.txnr, command, data))?;
+        Ok(self.txnr)
+    }
+
+    fn read_response(&mut self) -> io::Result<Frame> {
+        let frame = relp::read_frame(&mut self.reader, RELP_MAX_RESPONSE_SIZE)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?
+            .ok_or_else(|| io::Error::from(ErrorKind::ConnectionReset))?;
+        match frame.command.as_str() {
+            "rsp" => Ok(frame),
+            "serverclose" => Err(io::Error::new(
+                ErrorKind::ConnectionAborted,
+                "Session closed by the RELP server",
+            )),
+            _ => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "Unexpected RELP command",
+            )),
+        }
+    }
+}
+
+/// Settings of the RELP output, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "# Servers to connect to\n\
+         connect = [ \"127.0.0.1:2514\" ]\n\
+         # Records sent and not acknowledged yet\n\
+         # relp_window = {}\n\
+         # Seconds to wait for the server to accept records or to acknowledge them\n\
+         # timeout = {}\n",
+        DEFAULT_RELP_WINDOW, DEFAULT_TIMEOUT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Responses of a server to the open command and to the records sent by `send_records`
+    fn test_client(responses: &str) -> RelpClient<&[u8], Vec<u8>> {
+        RelpClient {
+            reader: BufReader::new(responses.as_bytes()),
+            writer: BufWriter::new(Vec::new()),
+            txnr: 0,
+        }
+    }
+
+    #[test]
+    fn test_relp_client_open() {
+        let responses = "1 rsp 46 200 OK\nrelp_version=0\ncommands=syslog,starttls\n";
+        let client = RelpClient::open(responses.as_bytes(), Vec::new()).unwrap();
+        let sent = String::from_utf8(client.writer.into_inner().unwrap()).unwrap();
+        assert!(sent.starts_with("1 open "));
+        assert!(sent.ends_with("\ncommands=syslog\n"));
+
+        let responses = "1 rsp 36 200 OK\nrelp_version=0\ncommands=other\n";
+        assert!(RelpClient::open(responses.as_bytes(), Vec::new()).is_err());
+        let responses = "1 rsp 9 500 error\n";
+        assert!(RelpClient::open(responses.as_bytes(), Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_relp_client_send_records() {
+        let batch = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        let mut client = test_client("1 rsp 6 200 OK\n2 rsp 9 500 error\n3 rsp 6 200 OK\n");
+        let mut acked = vec![None; 3];
+        client.send_records(&batch, 2, &mut acked).unwrap();
+        assert_eq!(acked, vec![Some(true), Some(false), Some(true)]);
+        assert_eq!(
+            client.writer.into_inner().unwrap(),
+            b"1 syslog 1 a\n2 syslog 1 b\n3 syslog 1 c\n"
+        );
+
+        // The connection breaks after the first acknowledgement
+        let mut client = test_client("1 rsp 6 200 OK\n");
+        let mut acked = vec![None; 3];
+        assert!(client.send_records(&batch, 2, &mut acked).is_err());
+        assert_eq!(acked, vec![Some(true), None, None]);
+
+        let mut client = test_client("1 rsp 6 200 OK\n0 serverclose 0\n");
+        let mut acked = vec![None; 3];
+        let e = client.send_records(&batch, 2, &mut acked).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::ConnectionAborted);
+        assert_eq!(acked, vec![Some(true), None, None]);
+    }
+
+    #[test]
+    fn test_relp_client_resend_unacked() {
+        let batch = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        // The second record is acknowledged first, then the connection breaks
+        let mut client = test_client("2 rsp 6 200 OK\n");
+        let mut acked = vec![None; 3];
+        assert!(client.send_records(&batch, 2, &mut acked).is_err());
+        assert_eq!(acked, vec![None, Some(true), None]);
+
+        // Only the records that were not acknowledged are sent again
+        let mut client = test_client("2 rsp 6 200 OK\n1 rsp 6 200 OK\n");
+        client.send_records(&batch, 2, &mut acked).unwrap();
+        assert_eq!(acked, vec![Some(true); 3]);
+        assert_eq!(
+            client.writer.into_inner().unwrap(),
+            b"1 syslog 1 a\n2 syslog 1 c\n"
+        );
+    }
+
+    #[test]
+    fn test_relp_client_txnr_wraps() {
+        let mut client = test_client("");
+        client.txnr = RELP_MAX_TXNR - 1;
+        assert_eq!(client.send("syslog", b"a").unwrap(), RELP_MAX_TXNR);
+        assert_eq!(client.send("syslog", b"b").unwrap(), 1);
+    }
+}
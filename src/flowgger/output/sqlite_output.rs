@@ -0,0 +1,360 @@
+use super::{notify, recv_batch, Notifier, Output, OUTPUT_BATCH_SIZE};
+use crate::flowgger::config::Config;
+use crate::flowgger::encoder::split_fields;
+use crate::flowgger::merger::Merger;
+use crate::flowgger::record_queue::RecordReceiver;
+use crate::flowgger::utils::threads::{self, CpuAffinity};
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+use serde::Deserialize;
+use std::io::{stderr, Write};
+use std::num::NonZeroU64;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_SQLITE_TABLE: &str = "logs";
+const DEFAULT_SQLITE_COLUMNS: [&str; 5] = ["ts", "hostname", "appname", "severity", "msg"];
+const DEFAULT_SQLITE_MAX_RECORDS: i64 = 1_000_000;
+const SQLITE_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Share of the records removed when the database is over its maximum size
+const SQLITE_PRUNE_RATIO: i64 = 10;
+/// Columns that are not record fields
+const SQLITE_RESERVED_COLUMNS: [&str; 3] = ["id", "inserted_at", "record"];
+
+/// SQLite output, keeping the most recent records in a local database, so that they can be queried on the
+/// device itself, i.e. with the `sqlite3` shell, even when it can't reach the log servers.
+///
+/// The table has an `id`, the `inserted_at` time (in seconds since the Unix epoch), a column per record field,
+/// and the `record` as encoded with 'output.format'. It works as a ring buffer: the oldest records are removed
+/// once the table has too many of them, they are too old, or the database is too large. Records that can't be
+/// written are dropped.
+/// Settings of the SQLite output, in the `[output]` section
+#[derive(Deserialize)]
+pub struct SqliteSettings {
+    sqlite_path: String,
+    sqlite_table: Option<String>,
+    sqlite_columns: Option<Vec<String>>,
+    sqlite_max_records: Option<NonZeroU64>,
+    sqlite_max_age: Option<NonZeroU64>,
+    sqlite_max_size: Option<NonZeroU64>,
+}
+
+pub struct SqliteOutput {
+    writer: Mutex<Option<SqliteWriter>>,
+    fields: Vec<String>,
+    affinity: CpuAffinity,
+}
+
+struct SqliteWriter {
+    connection: Connection,
+    table: String,
+    insert: String,
+    fields_count: usize,
+    max_records: Option<i64>,
+    max_age: Option<u64>,
+    max_size: Option<i64>,
+}
+
+/// Quote an identifier, that is then case-sensitive
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+impl SqliteOutput {
+    /// # Parameters
+    /// - 'output.sqlite_path':        Path of the database, created if it doesn't exist.
+    /// - 'output.sqlite_table':       Optional. Table of the records, created if it doesn't exist. Default is
+    ///   "logs".
+    /// - 'output.sqlite_columns':     Optional. Record fields stored in columns of their own, named after them:
+    ///   "ts" (RFC3339 timestamp), "hostname", "appname", "severity", "msg", or any structured data pair.
+    ///   Default is [ "ts", "hostname", "appname", "severity", "msg" ].
+    /// - 'output.sqlite_max_records': Optional. Maximum number of records kept. Default is 1000000.
+    /// - 'output.sqlite_max_age':     Optional. Maximum age of the records kept, in seconds. Not limited when
+    ///   this is not set.
+    /// - 'output.sqlite_max_size':    Optional. Maximum size of the records, in megabytes, the oldest tenth of
+    ///   them being removed when it is exceeded. Not limited when this is not set.
+    ///
+    /// # Panics
+    /// `Unable to open the SQLite database`: the database can't be opened, or the table created
+    pub fn new(config: &Config) -> SqliteOutput {
+        let settings: SqliteSettings = config.settings("output");
+        let path = &settings.sqlite_path;
+        let table = settings
+            .sqlite_table
+            .as_deref()
+            .unwrap_or(DEFAULT_SQLITE_TABLE);
+        let fields = settings.sqlite_columns.unwrap_or_else(|| {
+            DEFAULT_SQLITE_COLUMNS
+                .iter()
+                .map(|&field| field.to_owned())
+                .collect()
+        });
+        if let Some(field) = fields
+            .iter()
+            .find(|field| SQLITE_RESERVED_COLUMNS.contains(&field.as_str()))
+        {
+            panic!("output.sqlite_columns cannot include [{}]", field);
+        }
+        let max_records = Some(
+            settings
+                .sqlite_max_records
+                .map_or(DEFAULT_SQLITE_MAX_RECORDS, |x| x.get() as i64),
+        );
+        let max_age = settings.sqlite_max_age.map(NonZeroU64::get);
+        let max_size = settings
+            .sqlite_max_size
+            .map(|max_size| max_size.get() as i64 * 1024 * 1024);
+        let writer = SqliteWriter::open(path, table, &fields, max_records, max_age, max_size)
+            .unwrap_or_else(|e| panic!("Unable to open the SQLite database [{}]: {}", path, e));
+        SqliteOutput {
+            writer: Mutex::new(Some(writer)),
+            fields,
+            affinity: CpuAffinity::new(config, "output"),
+        }
+    }
+}
+
+impl Output for SqliteOutput {
+    fn start(
+        &self,
+        rx: RecordReceiver,
+        merger: Option<Box<dyn Merger>>,
+        notifier: Option<Arc<dyn Notifier>>,
+    ) {
+        if merger.is_some() {
+            let _ = writeln!(stderr(), "Output framing is ignored with the SQLite output");
+        }
+        let writer = self
+            .writer
+            .lock()
+            .unwrap()
+            .take()
+            .expect("The SQLite output can only be started once");
+        threads::spawn(
+            "flowgger-output-sqlite".to_owned(),
+            self.affinity.cpu(0),
+            move || {
+                let mut batch = Vec::with_capacity(OUTPUT_BATCH_SIZE);
+                while recv_batch(&rx, &mut batch) {
+                    match writer.write(&batch) {
+                        Ok(()) => notify(&notifier, &batch, Ok(())),
+                        Err(e) => {
+                            let _ =
+                                writeln!(stderr(), "Unable to write to the SQLite database: {}", e);
+                            notify(
+                                &notifier,
+                                &batch,
+                                Err("Cannot write to the SQLite database"),
+                            );
+                        }
+                    }
+                    batch.clear();
+                }
+            },
+        );
+    }
+
+    fn record_fields(&self) -> Vec<String> {
+        self.fields.clone()
+    }
+}
+
+impl SqliteWriter {
+    fn open(
+        path: &str,
+        table: &str,
+        fields: &[String],
+        max_records: Option<i64>,
+        max_age: Option<u64>,
+        max_size: Option<i64>,
+    ) -> rusqlite::Result<SqliteWriter> {
+        let connection = Connection::open(path)?;
+        connection.busy_timeout(SQLITE_BUSY_TIMEOUT)?;
+        // Readers don't block the writer, nor the other way around
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        let columns: Vec<String> = fields
+            .iter()
+            .map(|field| {
+                let affinity = match field.as_str() {
+                    "facility" | "severity" => "INTEGER",
+                    _ => "TEXT",
+                };
+                format!("{} {}", quote_identifier(field), affinity)
+            })
+            .collect();
+        let quoted_table = quote_identifier(table);
+        let mut schema = format!(
+            "CREATE TABLE IF NOT EXISTS {} (id INTEGER PRIMARY KEY, inserted_at INTEGER NOT NULL, {}{}record TEXT);
+             CREATE INDEX IF NOT EXISTS {} ON {} (inserted_at);",
+            quoted_table,
+            columns.join(", "),
+            if columns.is_empty() { "" } else { ", " },
+            quote_identifier(&format!("{}_inserted_at", table)),
+            quoted_table
+        );
+        if fields.iter().any(|field| field == "ts") {
+            schema.push_str(&format!(
+                "CREATE INDEX IF NOT EXISTS {} ON {} (\"ts\");",
+                quote_identifier(&format!("{}_ts", table)),
+                quoted_table
+            ));
+        }
+        connection.execute_batch(&schema)?;
+        let insert = format!(
+            "INSERT INTO {} (inserted_at, {}{}record) VALUES ({})",
+            quoted_table,
+            fields
+                .iter()
+                .map(|field| quote_identifier(field))
+                .collect::<Vec<_>>()
+                .join(", "),
+            if fields.is_empty() { "" } else { ", " },
+            vec!["?"; fields.len() + 2].join(", ")
+        );
+        Ok(SqliteWriter {
+            connection,
+            table: quoted_table,
+            insert,
+            fields_count: fields.len(),
+            max_records,
+            max_age,
+            max_size,
+        })
+    }
+
+    /// Insert a batch of records, and remove the oldest ones if the table is over its limits
+    fn write(&self, batch: &[Vec<u8>]) -> rusqlite::Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+        let transaction = self.connection.unchecked_transaction()?;
+        {
+            let mut insert = transaction.prepare_cached(&self.insert)?;
+            for bytes in batch {
+                let (values, record) = match split_fields(bytes, self.fields_count) {
+                    Ok(split) => split,
+                    Err(e) => {
+                        let _ = writeln!(stderr(), "{}", e);
+                        continue;
+                    }
+                };
+                let text = |value: &[u8]| Value::Text(String::from_utf8_lossy(value).into_owned());
+                let params = Some(Value::Integer(now as i64))
+                    .into_iter()
+                    .chain(
+                        values
+                            .into_iter()
+                            .map(|value| value.map_or(Value::Null, text)),
+                    )
+                    .chain(Some(text(record)));
+                insert.execute(params_from_iter(params))?;
+            }
+        }
+        self.prune(&transaction, now)?;
+        transaction.commit()
+    }
+
+    fn prune(&self, connection: &Connection, now: u64) -> rusqlite::Result<()> {
+        let table = &self.table;
+        if let Some(max_records) = self.max_records {
+            connection.execute(
+                &format!(
+                    "DELETE FROM {} WHERE id <= (SELECT MAX(id) FROM {}) - ?",
+                    table, table
+                ),
+                [max_records],
+            )?;
+        }
+        if let Some(max_age) = self.max_age {
+            connection.execute(
+                &format!("DELETE FROM {} WHERE inserted_at < ?", table),
+                [now.saturating_sub(max_age) as i64],
+            )?;
+        }
+        if let Some(max_size) = self.max_size {
+            // Pages freed by deleted records are reused, so the size of the file itself stops growing
+            let used: i64 = connection.query_row(
+                "SELECT (page_count - freelist_count) * page_size \
+                 FROM pragma_page_count(), pragma_freelist_count(), pragma_page_size()",
+                [],
+                |row| row.get(0),
+            )?;
+            if used > max_size {
+                connection.execute(
+                    &format!(
+                        "DELETE FROM {} WHERE id <= (SELECT MIN(id) + (MAX(id) - MIN(id)) / ? FROM {})",
+                        table, table
+                    ),
+                    [SQLITE_PRUNE_RATIO],
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Settings of the SQLite output, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "sqlite_path = \"/var/lib/flowgger/logs.sqlite\"\n\
+         # sqlite_table = \"{}\"\n\
+         # sqlite_columns = {:?}\n\
+         # Oldest records are deleted beyond this number\n\
+         # sqlite_max_records = {}\n",
+        DEFAULT_SQLITE_TABLE, DEFAULT_SQLITE_COLUMNS, DEFAULT_SQLITE_MAX_RECORDS
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::encoder::FieldsEncoder;
+    use crate::flowgger::record::{Record, Severity, Timestamp};
+    use crate::flowgger::utils::test_utils::record_test_utils::MsgEncoder;
+    use tempdir::TempDir;
+
+    fn record(i: usize) -> Record<'static> {
+        Record::builder()
+            .ts(Timestamp::from_unix_secs(i as i64))
+            .hostname("example.org")
+            .severity(Severity::Error)
+            .msg(format!("message {}", i))
+            .build()
+    }
+
+    #[test]
+    fn test_sqlite_ring_buffer() {
+        let temp_dir = TempDir::new("test_sqlite_output").unwrap();
+        let path = temp_dir.path().join("logs.db");
+        let fields: Vec<String> = DEFAULT_SQLITE_COLUMNS
+            .iter()
+            .map(|&field| field.to_owned())
+            .collect();
+        let writer =
+            SqliteWriter::open(path.to_str().unwrap(), "logs", &fields, Some(5), None, None)
+                .unwrap();
+        let encoder = FieldsEncoder::wrap(Box::new(MsgEncoder), fields);
+        let batch: Vec<Vec<u8>> = (0..8).map(|i| encoder.encode(record(i)).unwrap()).collect();
+        writer.write(&batch).unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        let (count, oldest): (i64, String) = connection
+            .query_row("SELECT COUNT(*), MIN(msg) FROM logs", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(count, 5);
+        assert_eq!(oldest, "message 3");
+        let (ts, severity, appname, record): (String, i64, Option<String>, String) = connection
+            .query_row(
+                "SELECT ts, severity, appname, record FROM logs ORDER BY id DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(ts, "1970-01-01T00:00:07Z");
+        assert_eq!(severity, 3);
+        assert_eq!(appname, None);
+        assert_eq!(record, "message 7");
+    }
+}
@@ -1,22 +1,33 @@
+use super::backpressure::Backpressure;
+use super::compress::Compression;
 use crate::flowgger::config::Config;
 use crate::flowgger::merger::Merger;
+use crate::flowgger::record_queue::RecordReceiver;
+use crate::flowgger::utils::proxy::Proxy;
+use crate::flowgger::utils::resolver::Resolver;
+use crate::flowgger::utils::threads::{self, CpuAffinity};
+use crate::flowgger::utils::tls::{self, set_protocol_options, SessionCache};
 use openssl::bn::BigNum;
 use openssl::dh::Dh;
 use openssl::ssl::*;
 use rand;
 use rand::prelude::SliceRandom;
 use rand::Rng;
+use serde::Deserialize;
 use time;
 
-use super::Output;
+use super::{notify, recv_batch, recv_batch_timeout, Notifier, Output, OUTPUT_BATCH_SIZE};
+use crossbeam_channel::{unbounded, RecvTimeoutError};
+use std::collections::VecDeque;
+use std::fmt;
 use std::io;
 use std::io::{stderr, BufWriter, ErrorKind, Write};
-use std::net::TcpStream;
+use std::net::{SocketAddr, TcpStream};
+use std::num::{NonZeroU64, NonZeroUsize};
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::Receiver;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 const DEFAULT_CIPHERS: &str =
     "ECDHE-ECDSA-AES128-GCM-SHA256:ECDHE-RSA-AES128-GCM-SHA256:ECDHE-ECDSA-CHACHA20-POLY1305:\
@@ -34,57 +45,244 @@ const DEFAULT_RECOVERY_PROBE_TIME: u32 = 30_000;
 const DEFAULT_ASYNC: bool = false;
 const DEFAULT_TIMEOUT: u64 = 3600;
 const DEFAULT_VERIFY_PEER: bool = false;
+const DEFAULT_FLUSH_BYTES: usize = 64 * 1024;
+const DEFAULT_FLUSH_INTERVAL: u64 = 100;
+const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+const DEFAULT_CONNECT_ATTEMPT_DELAY: u64 = 250;
 const TLS_VERIFY_DEPTH: u32 = 6;
 const TLS_DEFAULT_THREADS: u32 = 1;
 
+/// Settings of the TLS output, in the `[output]` section
+#[derive(Deserialize)]
+pub struct TlsSettings {
+    connect: Vec<String>,
+    tls_threads: Option<u32>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    tls_ciphers: Option<String>,
+    tls_verify_peer: Option<bool>,
+    tls_ca_file: Option<PathBuf>,
+    tls_compression: Option<bool>,
+    timeout: Option<u64>,
+    tls_async: Option<bool>,
+    connect_timeout: Option<NonZeroU64>,
+    connect_attempt_delay: Option<NonZeroU64>,
+    tls_flush_bytes: Option<NonZeroUsize>,
+    tls_flush_interval: Option<u64>,
+    tls_recovery_delay_init: Option<u32>,
+    tls_recovery_delay_max: Option<u32>,
+    tls_recovery_probe_time: Option<u32>,
+}
+
 pub struct TlsOutput {
     config: TlsConfig,
     threads: u32,
+    affinity: CpuAffinity,
+}
+
+/// A server to connect to: an address of one of the hostnames of output.connect, or the hostname itself when
+/// connecting through a proxy, that resolves it
+#[derive(Clone, Debug, PartialEq)]
+struct Member {
+    connect: String,
+    addr: Option<SocketAddr>,
+}
+
+impl fmt::Display for Member {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.addr {
+            Some(addr) if addr.to_string() != self.connect => {
+                write!(f, "{} ({})", self.connect, addr)
+            }
+            _ => write!(f, "{}", self.connect),
+        }
+    }
 }
 
+/// The servers of output.connect, every address of a hostname being a server of its own
 struct Cluster {
     connect: Vec<String>,
+    members: Vec<Member>,
     idx: usize,
 }
 
+impl Cluster {
+    /// # Returns
+    /// The next server to connect to, or `None` once every server of the round has been tried
+    fn next(&mut self) -> Option<Member> {
+        self.idx += 1;
+        self.members.get(self.idx).cloned()
+    }
+
+    /// Start a new round with `members`, in a random order
+    ///
+    /// # Returns
+    /// The first server to connect to, or `None` if there are none
+    fn new_round<R: Rng>(&mut self, mut members: Vec<Member>, rng: &mut R) -> Option<Member> {
+        members.shuffle(rng);
+        self.members = members;
+        self.idx = 0;
+        self.members.first().cloned()
+    }
+
+    /// # Returns
+    /// `first`, followed by the other servers of the round to race it with when connecting, alternating
+    /// address families as with Happy Eyeballs (RFC 8305)
+    fn race_order(&self, first: &Member) -> Vec<Member> {
+        let family = |member: &Member| member.addr.map(|addr| addr.is_ipv4());
+        if family(first).is_none() {
+            return vec![first.clone()];
+        }
+        let count = self.members.len();
+        let (mut same, mut other): (VecDeque<&Member>, VecDeque<&Member>) = (1..=count)
+            .map(|i| &self.members[(self.idx + i) % count])
+            .filter(|member| *member != first && family(member).is_some())
+            .partition(|member| family(member) == family(first));
+        let mut order = vec![first.clone()];
+        while let Some(member) = other.pop_front().or_else(|| same.pop_front()) {
+            order.push(member.clone());
+            std::mem::swap(&mut same, &mut other);
+        }
+        order
+    }
+}
+
+/// The servers of `connect`: every address of their hostnames, or the hostnames themselves without `resolver`
+fn resolve_members(connect: &[String], resolver: Option<&Resolver>, now: Instant) -> Vec<Member> {
+    let resolver = match resolver {
+        None => {
+            return connect
+                .iter()
+                .map(|connect| Member {
+                    connect: connect.clone(),
+                    addr: None,
+                })
+                .collect()
+        }
+        Some(resolver) => resolver,
+    };
+    let mut members = Vec::new();
+    for connect in connect {
+        for addr in resolver.resolve(connect, now) {
+            members.push(Member {
+                connect: connect.clone(),
+                addr: Some(addr),
+            });
+        }
+    }
+    members
+}
+
 #[derive(Clone)]
 struct TlsConfig {
     timeout: Option<Duration>,
     mx_cluster: Arc<Mutex<Cluster>>,
+    resolver: Option<Arc<Resolver>>,
     connector: SslConnector,
+    sessions: Option<SessionCache>,
     async_: bool,
+    compress: Compression,
+    connect_timeout: Duration,
+    connect_attempt_delay: Duration,
+    backpressure: Backpressure,
+    flush_bytes: usize,
+    flush_interval: Duration,
     recovery_delay_init: u32,
     recovery_delay_max: u32,
     recovery_probe_time: u32,
+    proxy: Option<Proxy>,
+}
+
+impl TlsConfig {
+    /// # Returns
+    /// The next server to connect to, the hostnames being resolved again after every round, or `None` if none
+    /// of them resolves. Hostnames are resolved without holding the cluster lock.
+    fn next_member<R: Rng>(&self, rng: &mut R) -> Option<Member> {
+        let connect = {
+            let mut cluster = self.mx_cluster.lock().unwrap();
+            if let Some(member) = cluster.next() {
+                return Some(member);
+            }
+            cluster.connect.clone()
+        };
+        let members = resolve_members(&connect, self.resolver.as_deref(), Instant::now());
+        self.mx_cluster.lock().unwrap().new_round(members, rng)
+    }
+
+    /// # Returns
+    /// `false` if the hostname of `member` doesn't resolve to its address any more
+    fn is_current(&self, member: &Member, now: Instant) -> bool {
+        match (&self.resolver, member.addr) {
+            (Some(resolver), Some(addr)) => resolver.resolve(&member.connect, now).contains(&addr),
+            _ => true,
+        }
+    }
 }
 
 struct TlsWorker {
-    arx: Arc<Mutex<Receiver<Vec<u8>>>>,
+    rx: RecordReceiver,
     merger: Option<Box<dyn Merger + Send>>,
+    notifier: Option<Arc<dyn Notifier>>,
     tls_config: TlsConfig,
 }
 
 impl TlsWorker {
     fn new(
-        arx: Arc<Mutex<Receiver<Vec<u8>>>>,
+        rx: RecordReceiver,
         merger: Option<Box<dyn Merger + Send>>,
+        notifier: Option<Arc<dyn Notifier>>,
         tls_config: TlsConfig,
     ) -> TlsWorker {
         TlsWorker {
-            arx,
+            rx,
             merger,
+            notifier,
             tls_config,
         }
     }
 
-    fn handle_connection(&self, connect_chosen: &str) -> io::Result<()> {
-        let client = new_tcp(connect_chosen)?;
+    /// Connect to `member`, or to the first of the other servers raced with it to accept the connection,
+    /// that `member` is then replaced with, and write the records of the queue until the connection breaks
+    fn handle_connection(&self, member: &mut Member, batch: &mut Vec<Vec<u8>>) -> io::Result<()> {
+        let client = match &self.tls_config.proxy {
+            None => {
+                let members = self
+                    .tls_config
+                    .mx_cluster
+                    .lock()
+                    .unwrap()
+                    .race_order(member);
+                let (winner, client) = race_connect(
+                    &members,
+                    self.tls_config.connect_attempt_delay,
+                    self.tls_config.connect_timeout,
+                )?;
+                *member = members[winner].clone();
+                client
+            }
+            Some(proxy) => proxy.connect(
+                &member.connect,
+                self.tls_config.connect_timeout,
+                self.tls_config.timeout,
+            )?,
+        };
+        let member = &*member;
+        let connect_chosen = &member.connect;
+        client.set_write_timeout(self.tls_config.timeout)?;
         let hostname = connect_chosen
             .split(':')
             .next()
             .unwrap_or_else(|| panic!("Invalid connection string: {}", connect_chosen));
-        let _ = writeln!(stderr(), "Connected to {}", connect_chosen);
-        let sslclient = match self.tls_config.connector.connect(hostname, client) {
+        let _ = writeln!(stderr(), "Connected to {}", member);
+        let mut ssl_config = self
+            .tls_config
+            .connector
+            .configure()
+            .map_err(io::Error::other)?;
+        if let Some(ref sessions) = self.tls_config.sessions {
+            sessions.resume(&mut ssl_config, connect_chosen);
+        }
+        let mut sslclient = match ssl_config.connect(hostname, client) {
             Err(_) => {
                 return Err(io::Error::new(
                     io::ErrorKind::ConnectionAborted,
@@ -93,31 +291,102 @@ impl TlsWorker {
             }
             Ok(sslclient) => sslclient,
         };
-        let _ = writeln!(stderr(), "Completed SSL handshake with {}", connect_chosen);
-        let mut writer = BufWriter::new(sslclient);
-        let merger = &self.merger;
+        let resumed = if sslclient.ssl().session_reused() {
+            " (resumed session)"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            stderr(),
+            "Completed SSL handshake with {}{}",
+            connect_chosen,
+            resumed
+        );
+        if let Some(ref sessions) = self.tls_config.sessions {
+            sessions.receive_tickets(&mut sslclient)?;
+        }
+        let _connection = self.tls_config.backpressure.connected();
+        let stream = self.tls_config.compress.writer(sslclient)?;
+        let mut writer = BufWriter::with_capacity(self.tls_config.flush_bytes, stream);
+        let mut corked = Vec::new();
+        let res = self.write_batches(&mut writer, member, batch, &mut corked);
+        // The records that have not been flushed are sent again once reconnected, before the batch
+        batch.splice(0..0, corked);
+        res
+    }
+
+    /// Write the records of the queue until the connection breaks.
+    ///
+    /// Unless the output is asynchronous, records are corked in the buffer while more are queued, and
+    /// only flushed once the queue is empty, `flush_bytes` have been written, or after `flush_interval`.
+    /// They are moved from `batch` to `corked` until they have been flushed.
+    fn write_batches<W: Write>(
+        &self,
+        writer: &mut BufWriter<W>,
+        member: &Member,
+        batch: &mut Vec<Vec<u8>>,
+        corked: &mut Vec<Vec<u8>>,
+    ) -> io::Result<()> {
+        let fire_and_forget = self.tls_config.async_ && self.notifier.is_none();
+        let mut corked_bytes = 0;
+        let mut last_flush = Instant::now();
         loop {
-            let mut bytes = match { self.arx.lock().unwrap().recv() } {
-                Ok(line) => line,
-                Err(_) => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        "Cannot read the message queue any more",
-                    ))
+            if batch.is_empty() {
+                let received = if corked.is_empty() {
+                    if recv_batch(&self.rx, batch) {
+                        Ok(())
+                    } else {
+                        Err(RecvTimeoutError::Disconnected)
+                    }
+                } else {
+                    recv_batch_timeout(&self.rx, batch, self.tls_config.flush_interval)
+                };
+                match received {
+                    Ok(()) => {}
+                    Err(RecvTimeoutError::Timeout) => {
+                        writer.flush()?;
+                        corked.clear();
+                        corked_bytes = 0;
+                        last_flush = Instant::now();
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        writer.flush()?;
+                        corked.clear();
+                        return Err(io::Error::other("Cannot read the message queue any more"));
+                    }
+                }
+                if let Some(ref merger) = self.merger {
+                    for bytes in batch.iter_mut() {
+                        merger.frame(bytes);
+                    }
                 }
-            };
-            if let Some(ref merger) = *merger {
-                merger.frame(&mut bytes);
             }
-            match writer.write_all(&bytes) {
-                Ok(_) => {}
-                Err(e) => match e.kind() {
-                    ErrorKind::Interrupted => continue,
-                    _ => return Err(e),
-                },
-            };
-            if !self.tls_config.async_ {
-                writer.flush()?;
+            // The batch is kept, and sent again once reconnected
+            batch.iter().try_for_each(|bytes| writer.write_all(bytes))?;
+            if fire_and_forget {
+                batch.clear();
+            } else {
+                corked_bytes += batch.iter().map(|bytes| bytes.len()).sum::<usize>();
+                corked.append(batch);
+                if self.notifier.is_some()
+                    || self.rx.is_empty()
+                    || corked_bytes >= self.tls_config.flush_bytes
+                    || last_flush.elapsed() >= self.tls_config.flush_interval
+                {
+                    // Corked records are sent again once reconnected if the flush fails
+                    writer.flush()?;
+                    notify(&self.notifier, corked, Ok(()));
+                    corked.clear();
+                    corked_bytes = 0;
+                    last_flush = Instant::now();
+                }
+            }
+            if corked.is_empty() && !self.tls_config.is_current(member, Instant::now()) {
+                return Err(io::Error::new(
+                    ErrorKind::AddrNotAvailable,
+                    "The address is not one of the server any more",
+                ));
             }
         }
     }
@@ -127,22 +396,34 @@ impl TlsWorker {
         let mut rng = rand::thread_rng();
         let mut recovery_delay = f64::from(tls_config.recovery_delay_init);
         let mut last_recovery;
+        let mut batch = Vec::with_capacity(OUTPUT_BATCH_SIZE);
         loop {
             last_recovery = time::OffsetDateTime::now_utc();
-            let connect_chosen = {
-                let mut cluster = tls_config.mx_cluster.lock().unwrap();
-                cluster.idx += 1;
-                if cluster.idx >= cluster.connect.len() {
-                    cluster.connect.shuffle(&mut rng);
-                    cluster.idx = 0;
-                }
-                cluster.connect[cluster.idx].clone()
+            let mut member = tls_config.next_member(&mut rng);
+            let res = match member {
+                Some(ref mut member) => self.handle_connection(member, &mut batch),
+                None => Err(io::Error::new(
+                    ErrorKind::NotFound,
+                    "None of the servers resolves",
+                )),
             };
-            if let Err(e) = self.handle_connection(&connect_chosen) {
+            let connect_chosen = member.map_or_else(
+                || tls_config.mx_cluster.lock().unwrap().connect.join(", "),
+                |member| member.to_string(),
+            );
+            if let Err(e) = res {
+                tls_config.backpressure.disconnected();
                 match e.kind() {
                     ErrorKind::ConnectionRefused => {
                         let _ = writeln!(stderr(), "Connection to {} refused", connect_chosen);
                     }
+                    ErrorKind::AddrNotAvailable => {
+                        let _ = writeln!(
+                            stderr(),
+                            "{} does not resolve to the address of the connection any more",
+                            connect_chosen
+                        );
+                    }
                     ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset => {
                         let _ = writeln!(
                             stderr(),
@@ -175,11 +456,60 @@ impl TlsWorker {
     }
 }
 
-fn new_tcp(connect_chosen: &str) -> Result<TcpStream, io::Error> {
-    match TcpStream::connect(connect_chosen) {
-        Ok(stream) => Ok(stream),
-        Err(e) => Err(e),
+fn new_tcp(member: &Member, timeout: Duration) -> Result<TcpStream, io::Error> {
+    match member.addr {
+        Some(addr) => TcpStream::connect_timeout(&addr, timeout),
+        None => TcpStream::connect(&member.connect),
+    }
+}
+
+/// Connect to the first of `members` that accepts the connection.
+///
+/// As with Happy Eyeballs (RFC 8305), a connection attempt is started every `attempt_delay`, or as soon as
+/// the previous one fails, without waiting for the ones in progress. The other connections are closed once
+/// one has been established, so that recovering from the outage of a server doesn't take the connection
+/// timeout of every unreachable one.
+///
+/// # Returns
+/// The index of the member the connection was made to, and the connection, or the error of the last attempt
+fn race_connect(
+    members: &[Member],
+    attempt_delay: Duration,
+    timeout: Duration,
+) -> io::Result<(usize, TcpStream)> {
+    let (tx, rx) = unbounded();
+    let mut pending = 0;
+    let mut last_error = None;
+    for (i, member) in members.iter().enumerate() {
+        let (tx, member) = (tx.clone(), member.clone());
+        threads::spawn(format!("flowgger-connect-{}", member), None, move || {
+            let _ = tx.send((i, new_tcp(&member, timeout)));
+        });
+        pending += 1;
+        let last = i + 1 == members.len();
+        let deadline = Instant::now() + attempt_delay;
+        while pending > 0 {
+            let res = if last {
+                rx.recv().ok()
+            } else {
+                rx.recv_deadline(deadline).ok()
+            };
+            match res {
+                Some((winner, Ok(client))) => return Ok((winner, client)),
+                Some((_, Err(e))) => {
+                    pending -= 1;
+                    last_error = Some(e);
+                    if !last {
+                        break;
+                    }
+                }
+                // Time for the next attempt
+                None => break,
+            }
+        }
     }
+    Err(last_error
+        .unwrap_or_else(|| io::Error::new(ErrorKind::NotFound, "No server to connect to")))
 }
 
 impl TlsOutput {
@@ -188,21 +518,29 @@ impl TlsOutput {
         TlsOutput {
             config: tls_config,
             threads,
+            affinity: CpuAffinity::new(config, "output"),
         }
     }
 }
 
 impl Output for TlsOutput {
-    fn start(&self, arx: Arc<Mutex<Receiver<Vec<u8>>>>, merger: Option<Box<dyn Merger>>) {
-        for _ in 0..self.threads {
-            let arx = Arc::clone(&arx);
+    fn start(
+        &self,
+        rx: RecordReceiver,
+        merger: Option<Box<dyn Merger>>,
+        notifier: Option<Arc<dyn Notifier>>,
+    ) {
+        for i in 0..self.threads as usize {
+            let rx = rx.clone();
+            let notifier = notifier.clone();
             let config = self.config.clone();
             let merger = match merger {
                 Some(ref merger) => Some(merger.clone_boxed()) as Option<Box<dyn Merger + Send>>,
                 None => None,
             };
-            thread::spawn(move || {
-                let worker = TlsWorker::new(arx, merger, config);
+            let name = format!("flowgger-output-tls-{}", i);
+            threads::spawn(name, self.affinity.cpu(i), move || {
+                let worker = TlsWorker::new(rx, merger, notifier, config);
                 worker.run();
             });
         }
@@ -220,102 +558,49 @@ fn set_fs(ctx: &mut SslContextBuilder) {
 }
 
 fn config_parse(config: &Config) -> (TlsConfig, u32) {
-    let threads = config
-        .lookup("output.tls_threads")
-        .map_or(TLS_DEFAULT_THREADS, |x| {
-            x.as_integer()
-                .expect("output.tls_threads must be a 32-bit integer") as u32
-        });
-    let connect = config
-        .lookup("output.connect")
-        .expect("output.connect is required")
-        .as_array()
-        .expect("output.connect must be a list");
-    let mut connect: Vec<String> = connect
-        .iter()
-        .map(|x| {
-            x.as_str()
-                .expect("output.connect must be a list of strings")
-                .to_owned()
-        })
-        .collect();
-    let cert: Option<PathBuf> = config.lookup("output.tls_cert").and_then(|x| {
-        Some(PathBuf::from(
-            x.as_str()
-                .expect("output.tls_cert must be a path to a .pem file"),
-        ))
-    });
-    let key: Option<PathBuf> = config.lookup("output.tls_key").and_then(|x| {
-        Some(PathBuf::from(
-            x.as_str()
-                .expect("output.tls_key must be a path to a .pem file"),
-        ))
-    });
-    let ciphers = config
-        .lookup("output.tls_ciphers")
-        .map_or(DEFAULT_CIPHERS, |x| {
-            x.as_str()
-                .expect("output.tls_ciphers must be a string with a cipher suite")
-        })
-        .to_owned();
-    let verify_peer = config
-        .lookup("output.tls_verify_peer")
-        .map_or(DEFAULT_VERIFY_PEER, |x| {
-            x.as_bool()
-                .expect("output.tls_verify_peer must be a boolean")
-        });
-    let ca_file: Option<PathBuf> = config.lookup("output.tls_ca_file").and_then(|x| {
-        Some(PathBuf::from(
-            x.as_str()
-                .expect("output.tls_ca_file must be a path to a file"),
-        ))
-    });
-    let compression = config
-        .lookup("output.tls_compression")
-        .map_or(DEFAULT_COMPRESSION, |x| {
-            x.as_bool()
-                .expect("output.tls_compression must be a boolean")
-        });
-    let timeout = config
-        .lookup("output.timeout")
-        .map_or(DEFAULT_TIMEOUT, |x| {
-            x.as_integer().expect("output.timeout must be an integer") as u64
-        });
-    let async_ = config
-        .lookup("output.tls_async")
-        .map_or(DEFAULT_ASYNC, |x| {
-            x.as_bool().expect("output.tls_async must be a boolean")
-        });
-    let recovery_delay_init =
-        config
-            .lookup("output.tls_recovery_delay_init")
-            .map_or(DEFAULT_RECOVERY_DELAY_INIT, |x| {
-                x.as_integer()
-                    .expect("output.tls_recovery_delay_init must be an integer")
-                    as u32
-            });
-    let recovery_delay_max =
-        config
-            .lookup("output.tls_recovery_delay_max")
-            .map_or(DEFAULT_RECOVERY_DELAY_MAX, |x| {
-                x.as_integer()
-                    .expect("output.tls_recovery_delay_max must be an integer")
-                    as u32
-            });
-    let recovery_probe_time =
-        config
-            .lookup("output.tls_recovery_probe_time")
-            .map_or(DEFAULT_RECOVERY_PROBE_TIME, |x| {
-                x.as_integer()
-                    .expect("output.tls_recovery_probe_time must be an integer")
-                    as u32
-            });
+    let settings: TlsSettings = config.settings("output");
+    let threads = settings.tls_threads.unwrap_or(TLS_DEFAULT_THREADS);
+    let mut connect = settings.connect;
+    let cert = settings.tls_cert;
+    let key = settings.tls_key;
+    let ciphers = settings
+        .tls_ciphers
+        .unwrap_or_else(|| DEFAULT_CIPHERS.to_owned());
+    let verify_peer = settings.tls_verify_peer.unwrap_or(DEFAULT_VERIFY_PEER);
+    let ca_file = settings.tls_ca_file;
+    let compression = settings.tls_compression.unwrap_or(DEFAULT_COMPRESSION);
+    let timeout = settings.timeout.unwrap_or(DEFAULT_TIMEOUT);
+    let async_ = settings.tls_async.unwrap_or(DEFAULT_ASYNC);
+    let compress = Compression::from_config(config);
+    let connect_timeout = settings
+        .connect_timeout
+        .map_or(DEFAULT_CONNECT_TIMEOUT, NonZeroU64::get);
+    let connect_attempt_delay = settings
+        .connect_attempt_delay
+        .map_or(DEFAULT_CONNECT_ATTEMPT_DELAY, NonZeroU64::get);
+    let flush_bytes = settings
+        .tls_flush_bytes
+        .map_or(DEFAULT_FLUSH_BYTES, NonZeroUsize::get);
+    let flush_interval = settings
+        .tls_flush_interval
+        .unwrap_or(DEFAULT_FLUSH_INTERVAL);
+    let recovery_delay_init = settings
+        .tls_recovery_delay_init
+        .unwrap_or(DEFAULT_RECOVERY_DELAY_INIT);
+    let recovery_delay_max = settings
+        .tls_recovery_delay_max
+        .unwrap_or(DEFAULT_RECOVERY_DELAY_MAX);
+    let recovery_probe_time = settings
+        .tls_recovery_probe_time
+        .unwrap_or(DEFAULT_RECOVERY_PROBE_TIME);
     if recovery_delay_max < recovery_delay_init {
         panic!("output.tls_recovery_delay_max cannot be less than output.tls_recovery_delay_init");
     }
+    let session_resumption = tls::session_resumption(config, "output");
+    let mut sessions = None;
     let mut connector_builder = SslConnector::builder(SslMethod::tls()).unwrap();
     {
-        let mut ctx = &mut connector_builder;
+        let ctx = &mut connector_builder;
         if !verify_peer {
             ctx.set_verify(SslVerifyMode::NONE);
         } else {
@@ -332,30 +617,224 @@ fn config_parse(config: &Config) -> (TlsConfig, u32) {
             opts |= SslOptions::NO_COMPRESSION;
         }
         ctx.set_options(opts);
-        set_fs(&mut ctx);
+        set_fs(ctx);
         if let Some(cert) = cert {
-            ctx.set_certificate_file(&Path::new(&cert), SslFiletype::PEM)
+            ctx.set_certificate_file(Path::new(&cert), SslFiletype::PEM)
                 .expect("Unable to read the TLS certificate");
         }
         if let Some(key) = key {
-            ctx.set_private_key_file(&Path::new(&key), SslFiletype::PEM)
+            ctx.set_private_key_file(Path::new(&key), SslFiletype::PEM)
                 .expect("Unable to read the TLS key");
         }
         ctx.set_cipher_list(&ciphers)
             .expect("Unsupported cipher suite");
+        set_protocol_options(ctx, config, "output");
+        if session_resumption {
+            sessions = Some(SessionCache::new(ctx));
+        }
     }
     let connector = connector_builder.build();
+    let proxy = Proxy::from_config(config);
     connect.shuffle(&mut rand::thread_rng());
-    let cluster = Cluster { connect, idx: 0 };
+    let resolver = match proxy {
+        None => Some(Arc::new(Resolver::new(config))),
+        Some(_) => None,
+    };
+    let cluster = Cluster {
+        connect,
+        members: Vec::new(),
+        idx: 0,
+    };
     let mx_cluster = Arc::new(Mutex::new(cluster));
     let tls_config = TlsConfig {
         mx_cluster,
+        resolver,
         timeout: Some(Duration::from_secs(timeout)),
         connector,
+        sessions,
         async_,
+        compress,
+        connect_timeout: Duration::from_secs(connect_timeout),
+        connect_attempt_delay: Duration::from_millis(connect_attempt_delay),
+        backpressure: Backpressure::from_config(config),
+        flush_bytes,
+        flush_interval: Duration::from_millis(flush_interval),
         recovery_delay_init,
         recovery_delay_max,
         recovery_probe_time,
+        proxy,
     };
     (tls_config, threads)
 }
+
+/// Settings of the TLS output, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "# Servers to connect to\n\
+         connect = [ \"127.0.0.1:6514\" ]\n\
+         # tls_threads = {}\n\
+         # Client certificate and key, in the PEM format\n\
+         # tls_cert = \"flowgger.pem\"\n\
+         # tls_key = \"flowgger.pem\"\n\
+         # Verify the certificate of the servers with this CA\n\
+         # tls_verify_peer = {}\n\
+         # tls_ca_file = \"ca.pem\"\n\
+         # tls_compression = {}\n\
+         # tls_ciphers = \"{}\"\n\
+         # Seconds after which idle connections are closed\n\
+         # timeout = {}\n\
+         # connect_timeout = {}\n\
+         # connect_attempt_delay = {}\n\
+         # tls_async = {}\n\
+         # tls_flush_bytes = {}\n\
+         # tls_flush_interval = {}\n\
+         # tls_recovery_delay_init = {}\n\
+         # tls_recovery_delay_max = {}\n\
+         # tls_recovery_probe_time = {}\n",
+        TLS_DEFAULT_THREADS,
+        DEFAULT_VERIFY_PEER,
+        DEFAULT_COMPRESSION,
+        DEFAULT_CIPHERS,
+        DEFAULT_TIMEOUT,
+        DEFAULT_CONNECT_TIMEOUT,
+        DEFAULT_CONNECT_ATTEMPT_DELAY,
+        DEFAULT_ASYNC,
+        DEFAULT_FLUSH_BYTES,
+        DEFAULT_FLUSH_INTERVAL,
+        DEFAULT_RECOVERY_DELAY_INIT,
+        DEFAULT_RECOVERY_DELAY_MAX,
+        DEFAULT_RECOVERY_PROBE_TIME
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::record_queue::unbounded;
+    use std::net::TcpListener;
+
+    /// Accepts writes, but fails once flushed if `broken` is set
+    struct TestWriter {
+        written: Vec<u8>,
+        broken: bool,
+    }
+
+    impl Write for TestWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.broken {
+                return Err(io::Error::from(ErrorKind::ConnectionReset));
+            }
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn test_worker(rx: RecordReceiver) -> (TlsWorker, Member) {
+        let config = Config::from_string(
+            "[output]\nconnect = [\"192.0.2.1:6514\"]\nproxy_url = \"socks5://192.0.2.2:1080\"\n",
+        )
+        .unwrap();
+        let (tls_config, _) = config_parse(&config);
+        let member = Member {
+            connect: "192.0.2.1:6514".to_owned(),
+            addr: None,
+        };
+        (TlsWorker::new(rx, None, None, tls_config), member)
+    }
+
+    #[test]
+    fn test_tls_worker_write_batches() {
+        let (tx, rx) = unbounded();
+        let (worker, member) = test_worker(rx);
+        for record in ["a\n", "b\n", "c\n"] {
+            tx.send(record.as_bytes().to_vec()).unwrap();
+        }
+        drop(tx);
+        let mut writer = BufWriter::new(TestWriter {
+            written: Vec::new(),
+            broken: false,
+        });
+        let (mut batch, mut corked) = (Vec::new(), Vec::new());
+        let res = worker.write_batches(&mut writer, &member, &mut batch, &mut corked);
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::Other);
+        assert_eq!(writer.get_ref().written, b"a\nb\nc\n");
+        assert!(batch.is_empty() && corked.is_empty());
+    }
+
+    #[test]
+    fn test_tls_worker_write_batches_broken() {
+        let (tx, rx) = unbounded();
+        let (worker, member) = test_worker(rx);
+        tx.send(b"a\n".to_vec()).unwrap();
+        tx.send(b"b\n".to_vec()).unwrap();
+        let mut writer = BufWriter::new(TestWriter {
+            written: Vec::new(),
+            broken: true,
+        });
+        let (mut batch, mut corked) = (Vec::new(), Vec::new());
+        let res = worker.write_batches(&mut writer, &member, &mut batch, &mut corked);
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::ConnectionReset);
+        // The records that could not be flushed are kept, to be sent again
+        assert_eq!(corked, vec![b"a\n".to_vec(), b"b\n".to_vec()]);
+    }
+
+    fn member(addr: &str) -> Member {
+        Member {
+            connect: "collector:6514".to_owned(),
+            addr: Some(addr.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_cluster_race_order() {
+        let members = vec![
+            member("192.0.2.1:6514"),
+            member("192.0.2.2:6514"),
+            member("[2001:db8::1]:6514"),
+            member("192.0.2.3:6514"),
+            member("[2001:db8::2]:6514"),
+        ];
+        let cluster = Cluster {
+            connect: vec!["collector:6514".to_owned()],
+            members: members.clone(),
+            idx: 0,
+        };
+        let order: Vec<String> = cluster
+            .race_order(&members[0])
+            .iter()
+            .map(|member| member.addr.unwrap().to_string())
+            .collect();
+        assert_eq!(
+            order,
+            [
+                "192.0.2.1:6514",
+                "[2001:db8::1]:6514",
+                "192.0.2.2:6514",
+                "[2001:db8::2]:6514",
+                "192.0.2.3:6514"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_race_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let refused = TcpListener::bind("127.0.0.1:0").unwrap();
+        let refused_addr = refused.local_addr().unwrap().to_string();
+        drop(refused);
+        let members = vec![
+            member(&refused_addr),
+            member(&listener.local_addr().unwrap().to_string()),
+        ];
+        let delay = Duration::from_secs(60);
+        // The refused connection doesn't wait for the attempt delay
+        let (winner, _) = race_connect(&members, delay, delay).unwrap();
+        assert_eq!(winner, 1);
+        let err = race_connect(&members[..1], delay, delay).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    }
+}
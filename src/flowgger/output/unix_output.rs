@@ -0,0 +1,299 @@
+use super::{notify, recv_batch, Notifier, Output, OUTPUT_BATCH_SIZE};
+use crate::flowgger::config::Config;
+use crate::flowgger::merger::Merger;
+use crate::flowgger::record_queue::RecordReceiver;
+use crate::flowgger::utils::threads::{self, CpuAffinity};
+use serde::Deserialize;
+use std::io::{self, stderr, Write};
+use std::os::unix::net::{UnixDatagram, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const UNIX_DEFAULT_PATH: &str = "/dev/log";
+const UNIX_DEFAULT_SOCKET_TYPE: SocketType = SocketType::Datagram;
+const UNIX_DEFAULT_RECONNECT_DELAY: u64 = 1000;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum SocketType {
+    #[serde(alias = "dgram")]
+    Datagram,
+    Stream,
+}
+
+impl SocketType {
+    fn as_str(self) -> &'static str {
+        match self {
+            SocketType::Datagram => "datagram",
+            SocketType::Stream => "stream",
+        }
+    }
+}
+
+/// Settings of the unix output, in the `[output]` section
+#[derive(Deserialize)]
+pub struct UnixSettings {
+    unix_path: Option<PathBuf>,
+    unix_socket_type: Option<SocketType>,
+    unix_reconnect_delay: Option<u64>,
+}
+
+/// Output to a local unix socket, i.e. to hand records over to the system syslog daemon
+pub struct UnixOutput {
+    path: PathBuf,
+    socket_type: SocketType,
+    reconnect_delay: Duration,
+    affinity: CpuAffinity,
+}
+
+enum UnixSocket {
+    Datagram(UnixDatagram),
+    Stream(UnixStream),
+}
+
+impl UnixSocket {
+    fn connect(path: &Path, socket_type: SocketType) -> io::Result<UnixSocket> {
+        match socket_type {
+            SocketType::Datagram => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(path)?;
+                Ok(UnixSocket::Datagram(socket))
+            }
+            SocketType::Stream => Ok(UnixSocket::Stream(UnixStream::connect(path)?)),
+        }
+    }
+
+    /// Send a record, as a single datagram with datagram sockets
+    fn send(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match self {
+            UnixSocket::Datagram(socket) => socket.send(bytes).map(|_| ()),
+            UnixSocket::Stream(stream) => stream.write_all(bytes),
+        }
+    }
+}
+
+impl UnixOutput {
+    /// # Parameters
+    /// - 'output.unix_path': Optional. Path to the socket, "/dev/log" by default.
+    /// - 'output.unix_socket_type': Optional. "datagram" (default) or "stream".
+    /// - 'output.unix_reconnect_delay': Optional. Delay in milliseconds before connecting again after an error.
+    pub fn new(config: &Config) -> UnixOutput {
+        let settings: UnixSettings = config.settings("output");
+        let path = settings
+            .unix_path
+            .unwrap_or_else(|| PathBuf::from(UNIX_DEFAULT_PATH));
+        let socket_type = settings
+            .unix_socket_type
+            .unwrap_or(UNIX_DEFAULT_SOCKET_TYPE);
+        let reconnect_delay = Duration::from_millis(
+            settings
+                .unix_reconnect_delay
+                .unwrap_or(UNIX_DEFAULT_RECONNECT_DELAY),
+        );
+        UnixOutput {
+            path,
+            socket_type,
+            reconnect_delay,
+            affinity: CpuAffinity::new(config, "output"),
+        }
+    }
+}
+
+struct UnixWorker {
+    rx: RecordReceiver,
+    merger: Option<Box<dyn Merger + Send>>,
+    notifier: Option<Arc<dyn Notifier>>,
+    path: PathBuf,
+    socket_type: SocketType,
+}
+
+impl UnixWorker {
+    /// Send records until the queue is closed, or an error occurs. The records of the batch that were not
+    /// sent are kept in `batch`, to be sent again once reconnected, and only notified then.
+    fn handle_connection(&self, batch: &mut Vec<Vec<u8>>) -> io::Result<()> {
+        let mut socket = UnixSocket::connect(&self.path, self.socket_type)?;
+        loop {
+            if batch.is_empty() {
+                if !recv_batch(&self.rx, batch) {
+                    return Ok(());
+                }
+                if let Some(ref merger) = self.merger {
+                    for bytes in batch.iter_mut() {
+                        merger.frame(bytes);
+                    }
+                }
+            }
+            let mut sent = 0;
+            let result: io::Result<()> = batch.iter().try_for_each(|bytes| {
+                socket.send(bytes)?;
+                sent += 1;
+                Ok(())
+            });
+            if sent > 0 {
+                notify(&self.notifier, &batch[..sent], Ok(()));
+                batch.drain(..sent);
+            }
+            result?;
+        }
+    }
+
+    fn run(self, reconnect_delay: Duration) {
+        let mut batch = Vec::with_capacity(OUTPUT_BATCH_SIZE);
+        loop {
+            match self.handle_connection(&mut batch) {
+                Ok(()) => return,
+                Err(e) => {
+                    let _ = writeln!(
+                        stderr(),
+                        "Error while writing to {} - {}",
+                        self.path.display(),
+                        e
+                    );
+                }
+            }
+            thread::sleep(reconnect_delay);
+        }
+    }
+}
+
+impl Output for UnixOutput {
+    fn start(
+        &self,
+        rx: RecordReceiver,
+        merger: Option<Box<dyn Merger>>,
+        notifier: Option<Arc<dyn Notifier>>,
+    ) {
+        let worker = UnixWorker {
+            rx,
+            merger: merger.map(|merger| merger.clone_boxed()),
+            notifier,
+            path: self.path.clone(),
+            socket_type: self.socket_type,
+        };
+        let reconnect_delay = self.reconnect_delay;
+        let name = "flowgger-output-unix".to_owned();
+        threads::spawn(name, self.affinity.cpu(0), move || {
+            worker.run(reconnect_delay)
+        });
+    }
+}
+
+/// Settings of the Unix socket output, for `flowgger config init`
+pub fn sample_config() -> String {
+    format!(
+        "unix_path = \"{}\"\n\
+         # \"datagram\" or \"stream\"\n\
+         # unix_socket_type = \"{}\"\n\
+         # unix_reconnect_delay = {}\n",
+        UNIX_DEFAULT_PATH,
+        UNIX_DEFAULT_SOCKET_TYPE.as_str(),
+        UNIX_DEFAULT_RECONNECT_DELAY
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::merger::LineMerger;
+    use crate::flowgger::record_queue::unbounded;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixListener;
+    use std::sync::Mutex;
+    use tempdir::TempDir;
+
+    fn config(path: &Path, socket_type: &str) -> Config {
+        Config::from_string(&format!(
+            "[output]\nunix_path = {:?}\nunix_socket_type = \"{}\"\n",
+            path.to_str().unwrap(),
+            socket_type
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_unix_output_datagram() {
+        let temp_dir = TempDir::new("test_unix_output").unwrap();
+        let path = temp_dir.path().join("log.sock");
+        let socket = UnixDatagram::bind(&path).unwrap();
+        let (tx, rx) = unbounded();
+        UnixOutput::new(&config(&path, "datagram")).start(rx, None, None);
+        tx.send(b"<13>1 - - - - - - first".to_vec()).unwrap();
+        tx.send(b"<13>1 - - - - - - second".to_vec()).unwrap();
+
+        let mut buf = [0u8; 128];
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"<13>1 - - - - - - first");
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"<13>1 - - - - - - second");
+    }
+
+    #[test]
+    fn test_unix_output_stream() {
+        let temp_dir = TempDir::new("test_unix_output").unwrap();
+        let path = temp_dir.path().join("log.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let config = config(&path, "stream");
+        let (tx, rx) = unbounded();
+        let merger = Box::new(LineMerger::new(&config)) as Box<dyn Merger>;
+        UnixOutput::new(&config).start(rx, Some(merger), None);
+        tx.send(b"first".to_vec()).unwrap();
+        tx.send(b"second".to_vec()).unwrap();
+
+        let (stream, _) = listener.accept().unwrap();
+        let lines: Vec<_> = BufReader::new(stream)
+            .lines()
+            .take(2)
+            .map(|line| line.unwrap())
+            .collect();
+        assert_eq!(lines, vec!["first", "second"]);
+    }
+
+    struct TestNotifier {
+        delivered: Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl Notifier for TestNotifier {
+        fn notify(&self, records: &[Vec<u8>], result: Result<(), &str>) {
+            assert!(result.is_ok());
+            self.delivered.lock().unwrap().extend_from_slice(records);
+        }
+    }
+
+    #[test]
+    fn test_unix_output_retry_unsent() {
+        let temp_dir = TempDir::new("test_unix_output").unwrap();
+        let path = temp_dir.path().join("log.sock");
+        let socket = UnixDatagram::bind(&path).unwrap();
+        let notifier = Arc::new(TestNotifier {
+            delivered: Mutex::new(Vec::new()),
+        });
+        let (_tx, rx) = unbounded();
+        let worker = UnixWorker {
+            rx,
+            merger: None,
+            notifier: Some(notifier.clone() as Arc<dyn Notifier>),
+            path: path.clone(),
+            socket_type: SocketType::Datagram,
+        };
+        // Too large for a datagram, so that the batch fails in the middle
+        let oversized = vec![b'x'; 4 << 20];
+        let mut batch = vec![b"first".to_vec(), oversized.clone(), b"last".to_vec()];
+        assert!(worker.handle_connection(&mut batch).is_err());
+        assert_eq!(batch, vec![oversized, b"last".to_vec()]);
+        assert_eq!(*notifier.delivered.lock().unwrap(), vec![b"first".to_vec()]);
+
+        let mut buf = [0u8; 128];
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"first");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "unknown variant `seqpacket`, expected one of `datagram`, `dgram`, `stream` for key `output.unix_socket_type`"
+    )]
+    fn test_unix_output_invalid_socket_type() {
+        let _ = UnixOutput::new(&config(Path::new("/dev/log"), "seqpacket"));
+    }
+}
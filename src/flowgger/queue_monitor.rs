@@ -0,0 +1,229 @@
+use crate::flowgger::byte_queue::ByteBudget;
+use crate::flowgger::config::Config;
+use crate::flowgger::record_queue::Occupancy;
+use crate::flowgger::utils::threads;
+use serde::Deserialize;
+use std::io::{stderr, Write};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const QUEUE_MONITOR_INTERVAL_MS: u64 = 1000;
+
+/// Occupancy of the queue between the inputs and the outputs
+pub struct QueueStats {
+    occupancy: Occupancy,
+    peak: AtomicUsize,
+    warnings: AtomicU64,
+}
+
+impl QueueStats {
+    pub fn new(occupancy: Occupancy) -> QueueStats {
+        QueueStats {
+            occupancy,
+            peak: AtomicUsize::new(0),
+            warnings: AtomicU64::new(0),
+        }
+    }
+
+    /// Bytes of the queued records, if the queue is bounded by size
+    pub fn budget(&self) -> Option<&ByteBudget> {
+        self.occupancy.budget()
+    }
+
+    /// Number of records currently waiting in the queue
+    pub fn occupancy(&self) -> usize {
+        self.occupancy.len()
+    }
+
+    /// Maximum number of records the queue can hold
+    pub fn capacity(&self) -> usize {
+        self.occupancy.capacity()
+    }
+
+    /// Highest occupancy observed by the monitor
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    /// Number of times the queue went above the warning threshold
+    pub fn warnings(&self) -> u64 {
+        self.warnings.load(Ordering::Relaxed)
+    }
+
+    fn sample(&self) -> usize {
+        let occupancy = self.occupancy();
+        self.peak.fetch_max(occupancy, Ordering::Relaxed);
+        occupancy
+    }
+}
+
+/// Settings of the queue monitor, in the `[input]` section
+#[derive(Deserialize)]
+pub struct QueueMonitorSettings {
+    queue_warn_percent: Option<u64>,
+    queue_low_percent: Option<u64>,
+}
+
+/// Periodically checks the queue occupancy, and warns when it crosses the high watermark.
+/// Once the warning has been emitted, it is not repeated until the occupancy goes back under
+/// the low watermark.
+pub struct QueueMonitor {
+    high_percent: u64,
+    low_percent: u64,
+}
+
+impl QueueMonitor {
+    /// Build a queue monitor from the configuration
+    ///
+    /// # Parameters
+    /// - 'input.queue_warn_percent': Optional. Must be an integer between 1 and 100. The monitor is only
+    ///   enabled when this is set.
+    /// - 'input.queue_low_percent':  Optional. Must be an integer lower than 'input.queue_warn_percent'.
+    ///   Default is half of 'input.queue_warn_percent'.
+    pub fn new(config: &Config) -> Option<QueueMonitor> {
+        let settings: QueueMonitorSettings = config.settings("input");
+        let high_percent = settings.queue_warn_percent?;
+        if !(1..=100).contains(&high_percent) {
+            panic!("input.queue_warn_percent must be between 1 and 100");
+        }
+        let low_percent = settings.queue_low_percent.unwrap_or(high_percent / 2);
+        if low_percent >= high_percent {
+            panic!("input.queue_low_percent must be lower than input.queue_warn_percent");
+        }
+        Some(QueueMonitor {
+            high_percent,
+            low_percent,
+        })
+    }
+
+    pub fn start(self, stats: Arc<QueueStats>) {
+        threads::spawn("flowgger-queue-monitor".to_owned(), None, move || {
+            let mut above = false;
+            loop {
+                thread::sleep(Duration::from_millis(QUEUE_MONITOR_INTERVAL_MS));
+                above = self.check(&stats, above);
+            }
+        });
+    }
+
+    /// Sample the queue, and return whether it is above the high watermark, in records, or in bytes if
+    /// this is what fills up first
+    fn check(&self, stats: &QueueStats, above: bool) -> bool {
+        let occupancy = stats.sample();
+        let capacity = stats.capacity();
+        let percent = (occupancy as u128 * 100 / capacity.max(1) as u128) as u64;
+        let (occupancy, capacity, percent, unit) = match stats.budget() {
+            Some(budget) => {
+                let used = budget.used();
+                let bytes_percent = (used as u128 * 100 / budget.max_bytes().max(1) as u128) as u64;
+                if bytes_percent > percent {
+                    (used, budget.max_bytes(), bytes_percent, "bytes")
+                } else {
+                    (occupancy, capacity, percent, "records")
+                }
+            }
+            None => (occupancy, capacity, percent, "records"),
+        };
+        if !above && percent >= self.high_percent {
+            stats.warnings.fetch_add(1, Ordering::Relaxed);
+            let _ = writeln!(
+                stderr(),
+                "WARNING: the queue is {}% full ({}/{} {}), inputs will block or drop records once it is full",
+                percent,
+                occupancy,
+                capacity,
+                unit
+            );
+            true
+        } else if above && percent <= self.low_percent {
+            let _ = writeln!(
+                stderr(),
+                "The queue is back to {}% ({}/{} {}, peak: {} records, warnings: {})",
+                percent,
+                occupancy,
+                capacity,
+                unit,
+                stats.peak(),
+                stats.warnings()
+            );
+            false
+        } else {
+            above
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::record_queue::bounded;
+
+    #[test]
+    fn test_queue_monitor_disabled() {
+        let config = Config::from_string("[input]\n").unwrap();
+        assert!(QueueMonitor::new(&config).is_none());
+    }
+
+    #[test]
+    fn test_queue_monitor_config() {
+        let config = Config::from_string("[input]\nqueue_warn_percent = 80\n").unwrap();
+        let monitor = QueueMonitor::new(&config).unwrap();
+        assert_eq!(monitor.high_percent, 80);
+        assert_eq!(monitor.low_percent, 40);
+
+        let config =
+            Config::from_string("[input]\nqueue_warn_percent = 80\nqueue_low_percent = 70\n")
+                .unwrap();
+        let monitor = QueueMonitor::new(&config).unwrap();
+        assert_eq!(monitor.low_percent, 70);
+    }
+
+    #[test]
+    #[should_panic(expected = "input.queue_warn_percent must be between 1 and 100")]
+    fn test_queue_monitor_invalid_warn_percent() {
+        let config = Config::from_string("[input]\nqueue_warn_percent = 120\n").unwrap();
+        let _ = QueueMonitor::new(&config);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "input.queue_low_percent must be lower than input.queue_warn_percent"
+    )]
+    fn test_queue_monitor_invalid_low_percent() {
+        let config =
+            Config::from_string("[input]\nqueue_warn_percent = 50\nqueue_low_percent = 50\n")
+                .unwrap();
+        let _ = QueueMonitor::new(&config);
+    }
+
+    #[test]
+    fn test_queue_monitor_watermarks() {
+        let config =
+            Config::from_string("[input]\nqueue_warn_percent = 80\nqueue_low_percent = 20\n")
+                .unwrap();
+        let monitor = QueueMonitor::new(&config).unwrap();
+        let (tx, rx) = bounded(10);
+        let stats = QueueStats::new(rx.occupancy());
+
+        for _ in 0..7 {
+            tx.send(vec![]).unwrap();
+        }
+        assert!(!monitor.check(&stats, false));
+        tx.send(vec![]).unwrap();
+        assert!(monitor.check(&stats, false));
+        assert_eq!(stats.warnings(), 1);
+
+        // Stays above until the low watermark is reached
+        for _ in 0..5 {
+            rx.recv().unwrap();
+        }
+        assert!(monitor.check(&stats, true));
+        rx.recv().unwrap();
+        assert!(!monitor.check(&stats, true));
+        assert_eq!(stats.occupancy(), 2);
+        assert_eq!(stats.peak(), 8);
+        assert_eq!(stats.warnings(), 1);
+    }
+}
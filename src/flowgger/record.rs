@@ -1,8 +1,17 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
 use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
 
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+/// Value of a structured data pair. New types of values may be added, matches must have a wildcard arm.
 #[derive(Debug, Clone)]
-pub enum SDValue {
-    String(String),
+#[non_exhaustive]
+pub enum SDValue<'a> {
+    String(Cow<'a, str>),
     Bool(bool),
     F64(f64),
     I64(i64),
@@ -10,7 +19,20 @@ pub enum SDValue {
     Null,
 }
 
-#[cfg(feature = "ltsv")]
+impl SDValue<'_> {
+    /// The value, no longer borrowing the record it was decoded from
+    pub fn into_owned(self) -> SDValue<'static> {
+        match self {
+            SDValue::String(value) => SDValue::String(Cow::Owned(value.into_owned())),
+            SDValue::Bool(value) => SDValue::Bool(value),
+            SDValue::F64(value) => SDValue::F64(value),
+            SDValue::I64(value) => SDValue::I64(value),
+            SDValue::U64(value) => SDValue::U64(value),
+            SDValue::Null => SDValue::Null,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum SDValueType {
     String,
@@ -20,32 +42,47 @@ pub enum SDValueType {
     U64,
 }
 
+/// Structured data element, as in RFC5424. Also holds the additional fields of the other formats.
+/// New fields may be added, elements are created with `StructuredData::new`.
 #[derive(Debug)]
-pub struct StructuredData {
-    pub sd_id: Option<String>,
-    pub pairs: Vec<(String, SDValue)>,
+#[non_exhaustive]
+pub struct StructuredData<'a> {
+    /// SD-ID of the element, if the format has one
+    pub sd_id: Option<Cow<'a, str>>,
+    /// Names and values of the pairs, in the order they were decoded. Names starting with '_' are additional
+    /// fields, as in GELF.
+    pub pairs: Vec<(Cow<'a, str>, SDValue<'a>)>,
 }
 
-impl StructuredData {
-    pub fn new(sd_id: Option<&str>) -> StructuredData {
+impl<'a> StructuredData<'a> {
+    pub fn new(sd_id: Option<&'a str>) -> StructuredData<'a> {
         StructuredData {
-            sd_id: match sd_id {
-                Some(sd_id) => Some(sd_id.to_owned()),
-                None => None,
-            },
+            sd_id: sd_id.map(Cow::Borrowed),
             pairs: Vec::new(),
         }
     }
+
+    /// The element, no longer borrowing the record it was decoded from
+    pub fn into_owned(self) -> StructuredData<'static> {
+        StructuredData {
+            sd_id: self.sd_id.map(|sd_id| Cow::Owned(sd_id.into_owned())),
+            pairs: self
+                .pairs
+                .into_iter()
+                .map(|(name, value)| (Cow::Owned(name.into_owned()), value.into_owned()))
+                .collect(),
+        }
+    }
 }
 
 /// Implement the structured data display also provides to_string() for free
-impl fmt::Display for StructuredData {
+impl fmt::Display for StructuredData<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_str("[")?;
         if let Some(sd_id) = &self.sd_id {
-            f.write_str(&sd_id)?;
+            f.write_str(sd_id)?;
         }
-        for &(ref name, ref value) in &self.pairs {
+        for (name, value) in &self.pairs {
             // Remove trailing '_' if exists
             let name = if (*name).starts_with('_') {
                 &name[1..] as &str
@@ -54,7 +91,17 @@ impl fmt::Display for StructuredData {
             };
 
             match *value {
-                SDValue::String(ref value) => write!(f, " {}=\"{}\"", name, value)?,
+                SDValue::String(ref value) => {
+                    write!(f, " {}=\"", name)?;
+                    // '"', '\\' and ']' must be escaped in parameter values
+                    for c in value.chars() {
+                        if c == '"' || c == '\\' || c == ']' {
+                            f.write_str("\\")?;
+                        }
+                        write!(f, "{}", c)?;
+                    }
+                    f.write_str("\"")?
+                }
                 SDValue::Bool(ref value) => write!(f, " {}=\"{}\"", name, value)?,
                 SDValue::F64(ref value) => write!(f, " {}=\"{}\"", name, value)?,
                 SDValue::I64(ref value) => write!(f, " {}=\"{}\"", name, value)?,
@@ -67,42 +114,517 @@ impl fmt::Display for StructuredData {
     }
 }
 
+/// Timestamp of a record, in nanoseconds since the Unix epoch.
+///
+/// Formats carrying timestamps as floating point seconds (Cap'n Proto, GELF, scripts) convert them with
+/// `from_secs_f64()` and `as_secs_f64()`, which keep a microsecond precision. Timestamps are displayed as
+/// seconds, with as many decimals as required.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(i128);
+
+impl Timestamp {
+    pub fn now() -> Timestamp {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        Timestamp(now.as_nanos() as i128)
+    }
+
+    pub fn from_unix_nanos(nanos: i128) -> Timestamp {
+        Timestamp(nanos)
+    }
+
+    pub fn from_unix_secs(secs: i64) -> Timestamp {
+        Timestamp(i128::from(secs) * NANOS_PER_SEC)
+    }
+
+    /// Timestamp from seconds, rounded to the microsecond, the precision of a `f64` for current dates
+    pub fn from_secs_f64(secs: f64) -> Timestamp {
+        Timestamp((secs * 1e6).round() as i128 * 1000)
+    }
+
+    pub fn from_offset_datetime(tsd: OffsetDateTime) -> Timestamp {
+        Timestamp(tsd.unix_timestamp_nanos())
+    }
+
+    pub fn from_primitive_datetime(tsd: PrimitiveDateTime) -> Timestamp {
+        Timestamp::from_offset_datetime(tsd.assume_utc())
+    }
+
+    pub fn unix_nanos(self) -> i128 {
+        self.0
+    }
+
+    /// Whole seconds since the Unix epoch, rounded down
+    pub fn unix_secs(self) -> i64 {
+        self.0.div_euclid(NANOS_PER_SEC) as i64
+    }
+
+    pub fn as_secs_f64(self) -> f64 {
+        self.unix_secs() as f64 + self.0.rem_euclid(NANOS_PER_SEC) as f64 / 1e9
+    }
+
+    pub fn to_offset_datetime(self) -> Result<OffsetDateTime, &'static str> {
+        OffsetDateTime::from_unix_timestamp_nanos(self.0).map_err(|_| "Timestamp out of range")
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let nanos = self.0.unsigned_abs();
+        let (secs, subsec) = (nanos / 1_000_000_000, nanos % 1_000_000_000);
+        if subsec == 0 {
+            return write!(f, "{}{}", sign, secs);
+        }
+        let subsec = format!("{:09}", subsec);
+        write!(f, "{}{}.{}", sign, secs, subsec.trim_end_matches('0'))
+    }
+}
+
+/// Seconds since the Unix epoch, with up to 9 decimals kept as is, or any other floating point number
+impl FromStr for Timestamp {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Timestamp, &'static str> {
+        let (negative, unsigned) = match s.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, s),
+        };
+        let (secs, subsec) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        let is_decimal = |x: &str| x.bytes().all(|c| c.is_ascii_digit());
+        if secs.is_empty() || !is_decimal(secs) || !is_decimal(subsec) || subsec.len() > 9 {
+            return match s.parse::<f64>() {
+                Ok(secs) if secs.is_finite() => Ok(Timestamp::from_secs_f64(secs)),
+                _ => Err("Invalid timestamp"),
+            };
+        }
+        let secs: i128 = secs.parse().or(Err("Invalid timestamp"))?;
+        let subsec: i128 = format!("{:0<9}", subsec).parse().unwrap();
+        let nanos = secs
+            .checked_mul(NANOS_PER_SEC)
+            .and_then(|nanos| nanos.checked_add(subsec))
+            .ok_or("Invalid timestamp")?;
+        Ok(Timestamp(if negative { -nanos } else { nanos }))
+    }
+}
+
+impl fmt::Debug for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Record decoded by the inputs and encoded by the outputs. New fields may be added, records are created
+/// with `Record::builder()`.
+///
+/// Text fields borrow the line the record was decoded from whenever they can be sliced from it as is, so that
+/// decoding doesn't allocate a string per field. `into_owned()` detaches a record from the line.
 #[derive(Debug)]
-pub struct Record {
-    pub ts: f64,
-    pub hostname: String,
-    pub facility: Option<u8>,
-    pub severity: Option<u8>,
-    pub appname: Option<String>,
-    pub procid: Option<String>,
-    pub msgid: Option<String>,
-    pub msg: Option<String>,
-    pub full_msg: Option<String>,
-    pub sd: Option<Vec<StructuredData>>,
-}
-
-#[cfg(feature = "capnp-recompile")]
-pub const FACILITY_MAX: u8 = 0xff >> 3;
-#[cfg(feature = "capnp-recompile")]
+#[non_exhaustive]
+pub struct Record<'a> {
+    pub ts: Timestamp,
+    /// UTC offset the timestamp was sent with, for formats carrying it
+    pub utc_offset: Option<UtcOffset>,
+    pub hostname: Cow<'a, str>,
+    pub facility: Option<Facility>,
+    pub severity: Option<Severity>,
+    pub appname: Option<Cow<'a, str>>,
+    pub procid: Option<Cow<'a, str>>,
+    pub msgid: Option<Cow<'a, str>>,
+    pub msg: Option<Cow<'a, str>>,
+    /// Full message, e.g. with a backtrace, as in GELF
+    pub full_msg: Option<Cow<'a, str>>,
+    pub sd: Option<Vec<StructuredData<'a>>>,
+}
+
+/// Builder of a record, with the current time as the timestamp and no other field set
+pub struct RecordBuilder<'a> {
+    record: Record<'a>,
+}
+
+impl<'a> RecordBuilder<'a> {
+    pub fn ts(mut self, ts: Timestamp) -> RecordBuilder<'a> {
+        self.record.ts = ts;
+        self
+    }
+
+    pub fn utc_offset(mut self, utc_offset: UtcOffset) -> RecordBuilder<'a> {
+        self.record.utc_offset = Some(utc_offset);
+        self
+    }
+
+    pub fn hostname(mut self, hostname: impl Into<Cow<'a, str>>) -> RecordBuilder<'a> {
+        self.record.hostname = hostname.into();
+        self
+    }
+
+    pub fn facility(mut self, facility: Facility) -> RecordBuilder<'a> {
+        self.record.facility = Some(facility);
+        self
+    }
+
+    pub fn severity(mut self, severity: Severity) -> RecordBuilder<'a> {
+        self.record.severity = Some(severity);
+        self
+    }
+
+    pub fn appname(mut self, appname: impl Into<Cow<'a, str>>) -> RecordBuilder<'a> {
+        self.record.appname = Some(appname.into());
+        self
+    }
+
+    pub fn procid(mut self, procid: impl Into<Cow<'a, str>>) -> RecordBuilder<'a> {
+        self.record.procid = Some(procid.into());
+        self
+    }
+
+    pub fn msgid(mut self, msgid: impl Into<Cow<'a, str>>) -> RecordBuilder<'a> {
+        self.record.msgid = Some(msgid.into());
+        self
+    }
+
+    pub fn msg(mut self, msg: impl Into<Cow<'a, str>>) -> RecordBuilder<'a> {
+        self.record.msg = Some(msg.into());
+        self
+    }
+
+    pub fn full_msg(mut self, full_msg: impl Into<Cow<'a, str>>) -> RecordBuilder<'a> {
+        self.record.full_msg = Some(full_msg.into());
+        self
+    }
+
+    /// Add a structured data element
+    pub fn sd(mut self, sd: StructuredData<'a>) -> RecordBuilder<'a> {
+        self.record.sd.get_or_insert_with(Vec::new).push(sd);
+        self
+    }
+
+    /// Add a pair to the first structured data element, as `Record::push_sd_pair`
+    pub fn sd_pair(
+        mut self,
+        name: impl Into<Cow<'a, str>>,
+        value: SDValue<'a>,
+    ) -> RecordBuilder<'a> {
+        self.record.push_sd_pair(name, value);
+        self
+    }
+
+    pub fn build(self) -> Record<'a> {
+        self.record
+    }
+}
+
+impl<'a> Record<'a> {
+    pub fn builder() -> RecordBuilder<'a> {
+        RecordBuilder {
+            record: Record {
+                ts: Timestamp::now(),
+                utc_offset: None,
+                hostname: Cow::Borrowed(""),
+                facility: None,
+                severity: None,
+                appname: None,
+                procid: None,
+                msgid: None,
+                msg: None,
+                full_msg: None,
+                sd: None,
+            },
+        }
+    }
+
+    /// Value of a field, by name: either a header field, or a structured data pair
+    /// (the leading '_' of the pair name being optional)
+    pub fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "hostname" => Some(self.hostname.to_string()),
+            "facility" => self.facility.map(|facility| facility.to_string()),
+            "severity" => self.severity.map(|severity| severity.to_string()),
+            "appname" => self.appname.as_deref().map(str::to_owned),
+            "procid" => self.procid.as_deref().map(str::to_owned),
+            "msgid" => self.msgid.as_deref().map(str::to_owned),
+            _ => self
+                .sd
+                .iter()
+                .flatten()
+                .flat_map(|sd| sd.pairs.iter())
+                .find(|(key, _)| key == name || key.strip_prefix('_') == Some(name))
+                .and_then(|(_, value)| match value {
+                    SDValue::String(value) => Some(value.to_string()),
+                    SDValue::Bool(value) => Some(value.to_string()),
+                    SDValue::F64(value) => Some(value.to_string()),
+                    SDValue::I64(value) => Some(value.to_string()),
+                    SDValue::U64(value) => Some(value.to_string()),
+                    SDValue::Null => None,
+                }),
+        }
+    }
+
+    /// Add a pair to the first structured data element, that is created with the `FLOWGGER_SD_ID` SD-ID if the
+    /// record doesn't have any
+    pub fn push_sd_pair(&mut self, name: impl Into<Cow<'a, str>>, value: SDValue<'a>) {
+        let sd = self.sd.get_or_insert_with(Vec::new);
+        if sd.is_empty() {
+            sd.push(StructuredData::new(Some(FLOWGGER_SD_ID)));
+        }
+        sd[0].pairs.push((name.into(), value));
+    }
+
+    /// The record, no longer borrowing the line it was decoded from, i.e. to decode a line transcoded to
+    /// a temporary buffer
+    pub fn into_owned(self) -> Record<'static> {
+        let owned = |text: Cow<'_, str>| Cow::Owned(text.into_owned());
+        Record {
+            ts: self.ts,
+            utc_offset: self.utc_offset,
+            hostname: owned(self.hostname),
+            facility: self.facility,
+            severity: self.severity,
+            appname: self.appname.map(owned),
+            procid: self.procid.map(owned),
+            msgid: self.msgid.map(owned),
+            msg: self.msg.map(owned),
+            full_msg: self.full_msg.map(owned),
+            sd: self
+                .sd
+                .map(|sd| sd.into_iter().map(StructuredData::into_owned).collect()),
+        }
+    }
+}
+
+#[cfg(feature = "capnp")]
 pub const FACILITY_MISSING: u8 = 0xff;
-#[cfg(any(feature = "capnp-recompile", feature = "gelf"))]
-pub const SEVERITY_MAX: u8 = (1 << 3) - 1;
-#[cfg(feature = "capnp-recompile")]
+#[cfg(feature = "capnp")]
 pub const SEVERITY_MISSING: u8 = 0xff;
+/// Version of the Cap'n Proto record schema written by the encoder
+#[cfg(feature = "capnp")]
+pub const CAPNP_SCHEMA_VERSION: u16 = 1;
+/// SD-ID of the structured data created for the fields flowgger adds to records without structured data, so
+/// that they are valid RFC5424 structured data.
+///
+/// flowgger has no private enterprise number of its own, so the SD-IDs it creates (this one, `gelf@`,
+/// `connection@`, `generator@` and `ssign-verify@`) are placeholders using 32473, the number reserved for
+/// documentation, and don't identify flowgger to receivers. The GELF one can be replaced with `gelf_sd_id`.
+pub const FLOWGGER_SD_ID: &str = "flowgger@32473";
+/// SD-ID of the structured data holding the additional fields of GELF records, so that they are kept when
+/// converting from and to RFC5424
+#[cfg(feature = "gelf")]
+pub const GELF_DEFAULT_SD_ID: &str = "gelf@32473";
+/// Syslog severity keywords, indexed by severity
+const SEVERITY_NAMES: [&str; 8] = [
+    "emerg", "alert", "crit", "error", "warning", "notice", "info", "debug",
+];
+/// Syslog facility keywords, indexed by facility
+const FACILITY_NAMES: [&str; 24] = [
+    "kern",
+    "user",
+    "mail",
+    "daemon",
+    "auth",
+    "syslog",
+    "lpr",
+    "news",
+    "uucp",
+    "cron",
+    "authpriv",
+    "ftp",
+    "ntp",
+    "security",
+    "console",
+    "solaris-cron",
+    "local0",
+    "local1",
+    "local2",
+    "local3",
+    "local4",
+    "local5",
+    "local6",
+    "local7",
+];
+
+/// Syslog severity, as in RFC5424. Converts from and to its code, and displays as its code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum Severity {
+    Emergency = 0,
+    Alert,
+    Critical,
+    Error,
+    Warning,
+    Notice,
+    Informational,
+    Debug,
+}
+
+impl Severity {
+    const ALL: [Severity; 8] = [
+        Severity::Emergency,
+        Severity::Alert,
+        Severity::Critical,
+        Severity::Error,
+        Severity::Warning,
+        Severity::Notice,
+        Severity::Informational,
+        Severity::Debug,
+    ];
+
+    /// Syslog keyword of the severity, e.g. "warning"
+    pub fn name(self) -> &'static str {
+        SEVERITY_NAMES[self as usize]
+    }
+
+    /// Severity from its syslog keyword
+    pub fn from_name(name: &str) -> Option<Severity> {
+        SEVERITY_NAMES
+            .iter()
+            .position(|x| *x == name)
+            .map(|code| Severity::ALL[code])
+    }
+}
+
+impl TryFrom<u8> for Severity {
+    type Error = &'static str;
+
+    fn try_from(code: u8) -> Result<Severity, &'static str> {
+        Severity::ALL
+            .get(code as usize)
+            .copied()
+            .ok_or("Invalid severity level")
+    }
+}
+
+impl From<Severity> for u8 {
+    fn from(severity: Severity) -> u8 {
+        severity as u8
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", *self as u8)
+    }
+}
+
+/// Syslog facility, as in RFC5424. Converts from and to its code, and displays as its code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum Facility {
+    Kern = 0,
+    User,
+    Mail,
+    Daemon,
+    Auth,
+    Syslog,
+    Lpr,
+    News,
+    Uucp,
+    Cron,
+    Authpriv,
+    Ftp,
+    Ntp,
+    Security,
+    Console,
+    SolarisCron,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+impl Facility {
+    const ALL: [Facility; 24] = [
+        Facility::Kern,
+        Facility::User,
+        Facility::Mail,
+        Facility::Daemon,
+        Facility::Auth,
+        Facility::Syslog,
+        Facility::Lpr,
+        Facility::News,
+        Facility::Uucp,
+        Facility::Cron,
+        Facility::Authpriv,
+        Facility::Ftp,
+        Facility::Ntp,
+        Facility::Security,
+        Facility::Console,
+        Facility::SolarisCron,
+        Facility::Local0,
+        Facility::Local1,
+        Facility::Local2,
+        Facility::Local3,
+        Facility::Local4,
+        Facility::Local5,
+        Facility::Local6,
+        Facility::Local7,
+    ];
+
+    /// Syslog keyword of the facility, e.g. "daemon"
+    pub fn name(self) -> &'static str {
+        FACILITY_NAMES[self as usize]
+    }
+
+    /// Facility from its syslog keyword
+    pub fn from_name(name: &str) -> Option<Facility> {
+        FACILITY_NAMES
+            .iter()
+            .position(|x| *x == name)
+            .map(|code| Facility::ALL[code])
+    }
+}
+
+impl TryFrom<u8> for Facility {
+    type Error = &'static str;
+
+    fn try_from(code: u8) -> Result<Facility, &'static str> {
+        Facility::ALL
+            .get(code as usize)
+            .copied()
+            .ok_or("Invalid facility")
+    }
+}
+
+impl From<Facility> for u8 {
+    fn from(facility: Facility) -> u8 {
+        facility as u8
+    }
+}
+
+impl fmt::Display for Facility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", *self as u8)
+    }
+}
+
+/// Facility and severity of a syslog priority, i.e. `facility * 8 + severity`
+pub fn split_pri(pri: u8) -> Result<(Facility, Severity), &'static str> {
+    let facility = Facility::try_from(pri >> 3).or(Err("Invalid priority"))?;
+    let severity = Severity::try_from(pri & 7)?;
+    Ok((facility, severity))
+}
+
+/// Syslog priority of a facility and a severity
+pub fn pri(facility: Facility, severity: Severity) -> u8 {
+    (facility as u8) << 3 | severity as u8
+}
 
 #[test]
 fn test_structured_data_display() {
-    let expected_string = r#"[someid a="a string" b="123456" c="true" d="123.456" e="-123456" f]"#;
-    let expected_debug = r#"StructuredData { sd_id: Some("someid"), pairs: [("a", String("a string")), ("b", U64(123456)), ("c", Bool(true)), ("d", F64(123.456)), ("e", I64(-123456)), ("_f", Null)] }"#;
+    let expected_string = r#"[someid a="a string" b="123456" c="true" d="123.456" e="-123456" f g="te\\st sc\"ript\]"]"#;
+    let expected_debug = r#"StructuredData { sd_id: Some("someid"), pairs: [("a", String("a string")), ("b", U64(123456)), ("c", Bool(true)), ("d", F64(123.456)), ("e", I64(-123456)), ("_f", Null), ("g", String("te\\st sc\"ript]"))] }"#;
     let data = StructuredData {
-        sd_id: Some("someid".to_string()),
+        sd_id: Some("someid".into()),
         pairs: vec![
-            ("a".to_string(), SDValue::String("a string".to_string())),
-            ("b".to_string(), SDValue::U64(123456)),
-            ("c".to_string(), SDValue::Bool(true)),
-            ("d".to_string(), SDValue::F64(123.456)),
-            ("e".to_string(), SDValue::I64(-123456)),
-            ("_f".to_string(), SDValue::Null),
+            ("a".into(), SDValue::String("a string".into())),
+            ("b".into(), SDValue::U64(123456)),
+            ("c".into(), SDValue::Bool(true)),
+            ("d".into(), SDValue::F64(123.456)),
+            ("e".into(), SDValue::I64(-123456)),
+            ("_f".into(), SDValue::Null),
+            ("g".into(), SDValue::String(r#"te\st sc"ript]"#.into())),
         ],
     };
 
@@ -114,19 +636,167 @@ fn test_structured_data_display() {
 
 #[test]
 fn test_record_display() {
-    let expected_debug = r#"Record { ts: 123.456, hostname: "hostname", facility: Some(3), severity: Some(8), appname: Some("app"), procid: Some("123"), msgid: None, msg: Some("msg"), full_msg: None, sd: None }"#;
+    let expected_debug = r#"Record { ts: 123.456, utc_offset: None, hostname: "hostname", facility: Some(Daemon), severity: Some(Debug), appname: Some("app"), procid: Some("123"), msgid: None, msg: Some("msg"), full_msg: None, sd: None }"#;
     let record = Record {
-        ts: 123.456,
-        hostname: "hostname".to_string(),
-        facility: Some(3),
-        severity: Some(8),
-        appname: Some("app".to_string()),
-        procid: Some("123".to_string()),
+        ts: Timestamp::from_unix_nanos(123_456_000_000),
+        utc_offset: None,
+        hostname: "hostname".into(),
+        facility: Some(Facility::Daemon),
+        severity: Some(Severity::Debug),
+        appname: Some("app".into()),
+        procid: Some("123".into()),
         msgid: None,
-        msg: Some("msg".to_string()),
+        msg: Some("msg".into()),
         full_msg: None,
         sd: None,
     };
 
     assert_eq!(format!("{:?}", record), expected_debug);
 }
+
+#[test]
+fn test_severity_facility() {
+    assert_eq!(Severity::try_from(4), Ok(Severity::Warning));
+    assert_eq!(Severity::try_from(8), Err("Invalid severity level"));
+    assert_eq!(u8::from(Severity::Debug), 7);
+    assert_eq!(Severity::Warning.name(), "warning");
+    assert_eq!(Severity::from_name("crit"), Some(Severity::Critical));
+    assert_eq!(Severity::from_name("critical"), None);
+    assert_eq!(Severity::Error.to_string(), "3");
+
+    assert_eq!(Facility::try_from(23), Ok(Facility::Local7));
+    assert_eq!(Facility::try_from(24), Err("Invalid facility"));
+    assert_eq!(Facility::SolarisCron.name(), "solaris-cron");
+    assert_eq!(Facility::from_name("local0"), Some(Facility::Local0));
+    assert_eq!(Facility::Daemon.to_string(), "3");
+
+    assert_eq!(split_pri(165), Ok((Facility::Local4, Severity::Notice)));
+    assert_eq!(split_pri(192), Err("Invalid priority"));
+    assert_eq!(pri(Facility::Local4, Severity::Notice), 165);
+}
+
+#[test]
+fn test_timestamp() {
+    let ts = Timestamp::from_unix_nanos(1_438_790_025_637_824_123);
+    assert_eq!(ts.to_string(), "1438790025.637824123");
+    assert_eq!(ts.unix_secs(), 1_438_790_025);
+    assert_eq!(
+        Timestamp::from_unix_secs(1_438_790_025).to_string(),
+        "1438790025"
+    );
+    assert_eq!(
+        Timestamp::from_unix_nanos(-1_500_000_000).to_string(),
+        "-1.5"
+    );
+    assert_eq!(Timestamp::from_unix_nanos(-1_500_000_000).unix_secs(), -2);
+
+    let ts = Timestamp::from_secs_f64(1_438_790_025.637_824);
+    assert_eq!(ts.unix_nanos(), 1_438_790_025_637_824_000);
+    assert_eq!(ts.as_secs_f64(), 1_438_790_025.637_824);
+    assert_eq!(
+        ts.to_offset_datetime().unwrap().unix_timestamp_nanos(),
+        1_438_790_025_637_824_000
+    );
+    assert!(Timestamp::from_unix_nanos(i128::MAX)
+        .to_offset_datetime()
+        .is_err());
+
+    assert_eq!(
+        "1438790025.637824123"
+            .parse::<Timestamp>()
+            .unwrap()
+            .unix_nanos(),
+        1_438_790_025_637_824_123
+    );
+    assert_eq!(
+        "-1.5".parse(),
+        Ok(Timestamp::from_unix_nanos(-1_500_000_000))
+    );
+    assert_eq!(
+        "1438790025".parse(),
+        Ok(Timestamp::from_unix_secs(1_438_790_025))
+    );
+    assert_eq!("1.5e3".parse(), Ok(Timestamp::from_unix_secs(1500)));
+    assert_eq!(
+        "1438790025.".parse::<Timestamp>().unwrap().unix_secs(),
+        1_438_790_025
+    );
+    assert_eq!("now".parse::<Timestamp>(), Err("Invalid timestamp"));
+    assert_eq!("inf".parse::<Timestamp>(), Err("Invalid timestamp"));
+}
+
+#[test]
+fn test_record_builder() {
+    let mut sd = StructuredData::new(Some("someid"));
+    sd.pairs.push(("count".into(), SDValue::U64(3)));
+    let record = Record::builder()
+        .ts(Timestamp::from_unix_nanos(123_456_000_000))
+        .hostname("hostname")
+        .severity(Severity::Informational)
+        .appname("app")
+        .msg("msg")
+        .sd(sd)
+        .sd_pair("_user", SDValue::String("alice".into()))
+        .build();
+
+    assert_eq!(record.ts, Timestamp::from_unix_nanos(123_456_000_000));
+    assert_eq!(record.hostname, "hostname");
+    assert_eq!(record.facility, None);
+    assert_eq!(record.severity, Some(Severity::Informational));
+    assert_eq!(record.msg.as_deref(), Some("msg"));
+    assert_eq!(record.field("count").as_deref(), Some("3"));
+    assert_eq!(record.field("user").as_deref(), Some("alice"));
+    assert!(Record::builder().build().ts > Timestamp::default());
+}
+
+#[test]
+fn test_record_push_sd_pair() {
+    let mut record = Record::builder().build();
+    record.push_sd_pair("_tenant", SDValue::String("acme".into()));
+    record.push_sd_pair("_count", SDValue::U64(3));
+    assert_eq!(
+        record.sd.as_ref().unwrap()[0].to_string(),
+        r#"[flowgger@32473 tenant="acme" count="3"]"#
+    );
+
+    let record = Record::builder()
+        .sd(StructuredData::new(Some("someid")))
+        .sd_pair("_tenant", SDValue::String("acme".into()))
+        .build();
+    assert_eq!(
+        record.sd.unwrap()[0].to_string(),
+        r#"[someid tenant="acme"]"#
+    );
+}
+
+#[test]
+fn test_record_field() {
+    let mut sd = StructuredData::new(Some("someid"));
+    sd.pairs
+        .push(("_user".into(), SDValue::String("alice".into())));
+    sd.pairs.push(("count".into(), SDValue::U64(3)));
+    sd.pairs.push(("flag".into(), SDValue::Null));
+    let record = Record {
+        ts: Timestamp::from_unix_nanos(123_456_000_000),
+        utc_offset: None,
+        hostname: "hostname".into(),
+        facility: Some(Facility::Daemon),
+        severity: None,
+        appname: Some("app".into()),
+        procid: None,
+        msgid: None,
+        msg: Some("msg".into()),
+        full_msg: None,
+        sd: Some(vec![sd]),
+    };
+
+    assert_eq!(record.field("hostname").as_deref(), Some("hostname"));
+    assert_eq!(record.field("facility").as_deref(), Some("3"));
+    assert_eq!(record.field("severity"), None);
+    assert_eq!(record.field("appname").as_deref(), Some("app"));
+    assert_eq!(record.field("user").as_deref(), Some("alice"));
+    assert_eq!(record.field("_user").as_deref(), Some("alice"));
+    assert_eq!(record.field("count").as_deref(), Some("3"));
+    assert_eq!(record.field("flag"), None);
+    assert_eq!(record.field("missing"), None);
+}
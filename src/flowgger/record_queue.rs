@@ -0,0 +1,532 @@
+use crate::flowgger::byte_queue::ByteBudget;
+use crossbeam_channel::{
+    self, RecvError, RecvTimeoutError, SendError, SendTimeoutError, TryRecvError, TrySendError,
+};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Maximum number of records an input sends to the queue at once
+pub const INPUT_BATCH_SIZE: usize = 64;
+
+/// Number of records in the queue, counted from the time they are sent until a receiver hands them out.
+/// Senders take slots without locking, and only fall back to waiting on a condition variable once the
+/// queue is full.
+struct Slots {
+    capacity: usize,
+    used: AtomicUsize,
+    waiters: AtomicUsize,
+    receivers: AtomicUsize,
+    lock: Mutex<()>,
+    released: Condvar,
+}
+
+enum AcquireError {
+    Timeout,
+    Disconnected,
+}
+
+impl Slots {
+    fn new(capacity: usize) -> Slots {
+        Slots {
+            capacity,
+            used: AtomicUsize::new(0),
+            waiters: AtomicUsize::new(0),
+            receivers: AtomicUsize::new(1),
+            lock: Mutex::new(()),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Take `count` slots if they fit. A batch larger than the whole queue is admitted once the queue
+    /// is empty, rather than blocking forever.
+    fn try_acquire(&self, count: usize) -> bool {
+        let mut used = self.used.load(Ordering::SeqCst);
+        loop {
+            if used > 0 && count > self.capacity - used {
+                return false;
+            }
+            match self.used.compare_exchange_weak(
+                used,
+                used + count,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => return true,
+                Err(current) => used = current,
+            }
+        }
+    }
+
+    fn acquire(&self, count: usize, deadline: Option<Instant>) -> Result<(), AcquireError> {
+        if self.try_acquire(count) {
+            return Ok(());
+        }
+        let mut guard = self.lock.lock().unwrap();
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let res = loop {
+            if self.receivers.load(Ordering::SeqCst) == 0 {
+                break Err(AcquireError::Disconnected);
+            }
+            if self.try_acquire(count) {
+                break Ok(());
+            }
+            guard = match deadline {
+                None => self.released.wait(guard).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break Err(AcquireError::Timeout);
+                    }
+                    self.released.wait_timeout(guard, deadline - now).unwrap().0
+                }
+            };
+        };
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        res
+    }
+
+    fn release(&self, count: usize) {
+        self.used.fetch_sub(count, Ordering::SeqCst);
+        self.wake();
+    }
+
+    fn wake(&self) {
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            let _guard = self.lock.lock().unwrap();
+            self.released.notify_all();
+        }
+    }
+}
+
+struct Shared {
+    slots: Slots,
+    budget: Option<Arc<ByteBudget>>,
+}
+
+impl Shared {
+    fn acquire(&self, batch: &[Vec<u8>], deadline: Option<Instant>) -> Result<(), AcquireError> {
+        self.slots.acquire(batch.len(), deadline)?;
+        if let Some(budget) = &self.budget {
+            budget.acquire(batch.iter().map(Vec::len).sum());
+        }
+        Ok(())
+    }
+
+    fn release(&self, records: &[Vec<u8>]) {
+        if records.is_empty() {
+            return;
+        }
+        if let Some(budget) = &self.budget {
+            budget.release(records.iter().map(Vec::len).sum());
+        }
+        self.slots.release(records.len());
+    }
+}
+
+/// Queue of records between two stages of the pipeline, i.e. from the inputs to the outputs.
+///
+/// Records travel in batches, so that an input reading many records at once only wakes up the receivers
+/// once. The queue is still bounded by its number of records, not batches.
+pub fn bounded(capacity: usize) -> (RecordSender, RecordReceiver) {
+    with_shared(Shared {
+        slots: Slots::new(capacity),
+        budget: None,
+    })
+}
+
+/// Queue of records that never blocks the senders
+#[cfg(any(test, fuzzing))]
+pub fn unbounded() -> (RecordSender, RecordReceiver) {
+    bounded(usize::MAX)
+}
+
+/// Queue of records also bounded by the size of the records
+pub fn with_budget(capacity: usize, budget: Arc<ByteBudget>) -> (RecordSender, RecordReceiver) {
+    with_shared(Shared {
+        slots: Slots::new(capacity),
+        budget: Some(budget),
+    })
+}
+
+fn with_shared(shared: Shared) -> (RecordSender, RecordReceiver) {
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let shared = Arc::new(shared);
+    let sender = RecordSender {
+        tx,
+        shared: Arc::clone(&shared),
+    };
+    let receiver = RecordReceiver {
+        rx,
+        shared,
+        pending: RefCell::new(VecDeque::new()),
+    };
+    (sender, receiver)
+}
+
+/// Sending side of a record queue, that can be cloned and shared by several inputs
+#[derive(Clone)]
+pub struct RecordSender {
+    tx: crossbeam_channel::Sender<Vec<Vec<u8>>>,
+    shared: Arc<Shared>,
+}
+
+impl RecordSender {
+    /// Send a record, waiting for room in the queue
+    pub fn send(&self, record: Vec<u8>) -> Result<(), SendError<Vec<u8>>> {
+        self.send_batch(vec![record])
+            .map_err(|SendError(mut batch)| SendError(batch.remove(0)))
+    }
+
+    /// Send several records at once, waiting for room in the queue for all of them
+    pub fn send_batch(&self, batch: Vec<Vec<u8>>) -> Result<(), SendError<Vec<Vec<u8>>>> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        if self.shared.acquire(&batch, None).is_err() {
+            return Err(SendError(batch));
+        }
+        self.tx.send(batch).map_err(|SendError(batch)| {
+            self.shared.release(&batch);
+            SendError(batch)
+        })
+    }
+
+    /// Send a record if there is room in the queue right away
+    pub fn try_send(&self, record: Vec<u8>) -> Result<(), TrySendError<Vec<u8>>> {
+        if self.shared.slots.receivers.load(Ordering::SeqCst) == 0 {
+            return Err(TrySendError::Disconnected(record));
+        }
+        if !self.shared.slots.try_acquire(1) {
+            return Err(TrySendError::Full(record));
+        }
+        if let Some(budget) = &self.shared.budget {
+            budget.acquire(record.len());
+        }
+        self.tx.send(vec![record]).map_err(|SendError(mut batch)| {
+            self.shared.release(&batch);
+            TrySendError::Disconnected(batch.remove(0))
+        })
+    }
+
+    /// Send a record, waiting up to `timeout` for room in the queue
+    pub fn send_timeout(
+        &self,
+        record: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<(), SendTimeoutError<Vec<u8>>> {
+        let batch = vec![record];
+        match self.shared.acquire(&batch, Some(Instant::now() + timeout)) {
+            Ok(()) => {}
+            Err(AcquireError::Timeout) => {
+                return Err(SendTimeoutError::Timeout(batch.into_iter().next().unwrap()))
+            }
+            Err(AcquireError::Disconnected) => {
+                return Err(SendTimeoutError::Disconnected(
+                    batch.into_iter().next().unwrap(),
+                ))
+            }
+        }
+        self.tx.send(batch).map_err(|SendError(mut batch)| {
+            self.shared.release(&batch);
+            SendTimeoutError::Disconnected(batch.remove(0))
+        })
+    }
+}
+
+/// Records read by an input, sent to the queue together once `INPUT_BATCH_SIZE` of them have been
+/// collected, or when flushed. Inputs flush it before waiting for more data, so that records are never
+/// held back. Records still in the batch are sent when it is dropped.
+pub struct BatchSender {
+    tx: RecordSender,
+    batch: Vec<Vec<u8>>,
+}
+
+impl BatchSender {
+    pub fn new(tx: RecordSender) -> BatchSender {
+        BatchSender {
+            tx,
+            batch: Vec::with_capacity(INPUT_BATCH_SIZE),
+        }
+    }
+
+    /// Add a record to the batch, and send the batch if it is full
+    pub fn push(&mut self, record: Vec<u8>) -> Result<(), SendError<Vec<Vec<u8>>>> {
+        self.batch.push(record);
+        if self.batch.len() >= INPUT_BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Send the records of the batch
+    pub fn flush(&mut self) -> Result<(), SendError<Vec<Vec<u8>>>> {
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(INPUT_BATCH_SIZE));
+        self.tx.send_batch(batch)
+    }
+}
+
+impl Drop for BatchSender {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Receiving side of a record queue, that can be cloned and shared by several outputs.
+///
+/// Each receiver takes whole batches from the queue, and hands their records out one by one or in
+/// batches of its own. Records are only counted out of the queue once they have been handed out.
+pub struct RecordReceiver {
+    rx: crossbeam_channel::Receiver<Vec<Vec<u8>>>,
+    shared: Arc<Shared>,
+    pending: RefCell<VecDeque<Vec<u8>>>,
+}
+
+impl Clone for RecordReceiver {
+    fn clone(&self) -> RecordReceiver {
+        self.shared.slots.receivers.fetch_add(1, Ordering::SeqCst);
+        RecordReceiver {
+            rx: self.rx.clone(),
+            shared: Arc::clone(&self.shared),
+            pending: RefCell::new(VecDeque::new()),
+        }
+    }
+}
+
+impl Drop for RecordReceiver {
+    fn drop(&mut self) {
+        let pending: Vec<_> = self.pending.get_mut().drain(..).collect();
+        self.shared.release(&pending);
+        if self.shared.slots.receivers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // Blocked senders give up once nobody is left to read the queue
+            let _guard = self.shared.slots.lock.lock().unwrap();
+            self.shared.slots.released.notify_all();
+        }
+    }
+}
+
+impl RecordReceiver {
+    /// Wait for the next record
+    pub fn recv(&self) -> Result<Vec<u8>, RecvError> {
+        if self.pending.borrow().is_empty() {
+            let batch = self.rx.recv()?;
+            self.pending.borrow_mut().extend(batch);
+        }
+        Ok(self.take_one())
+    }
+
+    /// Wait up to `timeout` for the next record
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Vec<u8>, RecvTimeoutError> {
+        if self.pending.borrow().is_empty() {
+            let batch = self.rx.recv_timeout(timeout)?;
+            self.pending.borrow_mut().extend(batch);
+        }
+        Ok(self.take_one())
+    }
+
+    /// Take the next record if there is one
+    pub fn try_recv(&self) -> Result<Vec<u8>, TryRecvError> {
+        if self.pending.borrow().is_empty() {
+            let batch = self.rx.try_recv()?;
+            self.pending.borrow_mut().extend(batch);
+        }
+        Ok(self.take_one())
+    }
+
+    /// Wait for the next record, then move it to `batch` along with the records already queued behind
+    /// it, up to `max` records in `batch`
+    pub fn recv_batch(&self, batch: &mut Vec<Vec<u8>>, max: usize) -> Result<(), RecvError> {
+        batch.push(self.recv()?);
+        self.fill(batch, max);
+        Ok(())
+    }
+
+    /// Like `recv_batch`, but only waits up to `timeout` for the next record
+    #[cfg(any(feature = "tls", feature = "kafka-output"))]
+    pub fn recv_batch_timeout(
+        &self,
+        batch: &mut Vec<Vec<u8>>,
+        max: usize,
+        timeout: Duration,
+    ) -> Result<(), RecvTimeoutError> {
+        batch.push(self.recv_timeout(timeout)?);
+        self.fill(batch, max);
+        Ok(())
+    }
+
+    /// Iterate over the records, waiting for them, until the queue is closed
+    pub fn iter(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        std::iter::from_fn(move || self.recv().ok())
+    }
+
+    /// Iterate over the records already in the queue
+    #[cfg(any(test, feature = "kafka-output"))]
+    pub fn try_iter(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        std::iter::from_fn(move || self.try_recv().ok())
+    }
+
+    /// Number of records waiting in the queue
+    #[cfg(any(test, feature = "tls"))]
+    pub fn len(&self) -> usize {
+        self.shared.slots.used.load(Ordering::SeqCst)
+    }
+
+    /// `true` if no records are waiting in the queue
+    #[cfg(any(test, feature = "tls"))]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Handle on the occupancy of the queue, that doesn't keep it open
+    pub fn occupancy(&self) -> Occupancy {
+        Occupancy {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    fn take_one(&self) -> Vec<u8> {
+        let record = self.pending.borrow_mut().pop_front().unwrap();
+        self.shared.release(std::slice::from_ref(&record));
+        record
+    }
+
+    fn fill(&self, batch: &mut Vec<Vec<u8>>, max: usize) {
+        let start = batch.len();
+        {
+            let mut pending = self.pending.borrow_mut();
+            while batch.len() < max {
+                if pending.is_empty() {
+                    match self.rx.try_recv() {
+                        Ok(queued) => pending.extend(queued),
+                        Err(_) => break,
+                    }
+                }
+                let count = pending.len().min(max - batch.len());
+                batch.extend(pending.drain(..count));
+            }
+        }
+        self.shared.release(&batch[start..]);
+    }
+}
+
+/// Number of records waiting in a queue, for monitoring
+#[derive(Clone)]
+pub struct Occupancy {
+    shared: Arc<Shared>,
+}
+
+impl Occupancy {
+    /// Number of records waiting in the queue
+    pub fn len(&self) -> usize {
+        self.shared.slots.used.load(Ordering::SeqCst)
+    }
+
+    /// `true` if no records are waiting in the queue
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maximum number of records the queue holds
+    pub fn capacity(&self) -> usize {
+        self.shared.slots.capacity
+    }
+
+    /// Bytes of the queued records, if the queue is bounded by size
+    pub fn budget(&self) -> Option<&ByteBudget> {
+        self.shared.budget.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_record_queue_batches() {
+        let (tx, rx) = bounded(10);
+        tx.send_batch(vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()])
+            .unwrap();
+        tx.send(b"d".to_vec()).unwrap();
+        assert_eq!(rx.len(), 4);
+
+        let mut batch = Vec::new();
+        rx.recv_batch(&mut batch, 2).unwrap();
+        assert_eq!(batch, vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(rx.len(), 2);
+        assert_eq!(rx.recv().unwrap(), b"c");
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![b"d".to_vec()]);
+        assert!(rx.is_empty());
+        assert!(rx.try_recv().is_err());
+
+        drop(tx);
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn test_record_queue_capacity() {
+        let (tx, rx) = bounded(2);
+        tx.send_batch(vec![b"a".to_vec(), b"b".to_vec()]).unwrap();
+        assert!(matches!(
+            tx.try_send(b"c".to_vec()),
+            Err(TrySendError::Full(_))
+        ));
+        assert!(matches!(
+            tx.send_timeout(b"c".to_vec(), Duration::from_millis(10)),
+            Err(SendTimeoutError::Timeout(_))
+        ));
+
+        // The sender waits until a record has been handed out
+        let sender = thread::spawn(move || tx.send(b"c".to_vec()).unwrap());
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(rx.len(), 2);
+        assert_eq!(rx.recv().unwrap(), b"a");
+        sender.join().unwrap();
+        assert_eq!(
+            rx.iter().collect::<Vec<_>>(),
+            vec![b"b".to_vec(), b"c".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_record_queue_large_batch() {
+        let (tx, rx) = bounded(2);
+        tx.send_batch(vec![b"a".to_vec(); 5]).unwrap();
+        assert_eq!(rx.len(), 5);
+        assert_eq!(rx.iter().take(5).count(), 5);
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn test_batch_sender() {
+        let (tx, rx) = unbounded();
+        let mut batch = BatchSender::new(tx);
+        for _ in 0..INPUT_BATCH_SIZE - 1 {
+            batch.push(b"a".to_vec()).unwrap();
+        }
+        assert!(rx.is_empty());
+        batch.push(b"a".to_vec()).unwrap();
+        assert_eq!(rx.len(), INPUT_BATCH_SIZE);
+        batch.push(b"b".to_vec()).unwrap();
+        batch.flush().unwrap();
+        assert_eq!(rx.len(), INPUT_BATCH_SIZE + 1);
+        batch.push(b"c".to_vec()).unwrap();
+        drop(batch);
+        assert_eq!(
+            rx.iter().skip(INPUT_BATCH_SIZE).collect::<Vec<_>>(),
+            vec![b"b".to_vec(), b"c".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_record_queue_receivers_dropped() {
+        let (tx, rx) = bounded(1);
+        tx.send(b"a".to_vec()).unwrap();
+        let sender = thread::spawn(move || tx.send(b"b".to_vec()));
+        thread::sleep(Duration::from_millis(100));
+        drop(rx);
+        assert!(sender.join().unwrap().is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use super::{LineSplitter, NulSplitter, Splitter, SyslenSplitter};
+use crate::flowgger::decoder::Decoder;
+use crate::flowgger::encoder::Encoder;
+use crate::flowgger::record_queue::RecordSender;
+use std::io::{BufRead, BufReader, Read};
+
+/// Detects the framing of every connection from its first byte, for inputs sharing a single port between
+/// devices using octet counting and devices using line breaks (RFC6587).
+///
+/// Connections starting with a digit are split as `syslen` frames, that start with the length of the record,
+/// connections starting with `{` as GELF messages, that are terminated by a NUL byte, and any other
+/// connection is split on line breaks.
+pub struct AutoSplitter;
+
+/// Framing of a connection, detected from its first byte
+#[derive(Debug, PartialEq)]
+enum Framing {
+    Syslen,
+    Nul,
+    Line,
+}
+
+impl<T: Read> Splitter<T> for AutoSplitter {
+    fn run(
+        &self,
+        mut buf_reader: BufReader<T>,
+        tx: RecordSender,
+        decoder: Box<dyn Decoder>,
+        encoder: Box<dyn Encoder>,
+    ) {
+        let framing = match buf_reader.fill_buf() {
+            Ok(buf) => detect_framing(buf),
+            Err(_) => Framing::Line,
+        };
+        match framing {
+            Framing::Syslen => SyslenSplitter.run(buf_reader, tx, decoder, encoder),
+            Framing::Nul => NulSplitter.run(buf_reader, tx, decoder, encoder),
+            Framing::Line => LineSplitter.run(buf_reader, tx, decoder, encoder),
+        }
+    }
+}
+
+/// Framing of a connection from its first bytes, knowing that the length of a frame can't start with a zero
+fn detect_framing(buf: &[u8]) -> Framing {
+    match buf.first() {
+        Some(b'1'..=b'9') => Framing::Syslen,
+        Some(b'{') => Framing::Nul,
+        _ => Framing::Line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_splitter_framing() {
+        assert_eq!(
+            detect_framing(b"56 <23>1 2015-08-05T15:53:45Z"),
+            Framing::Syslen
+        );
+        assert_eq!(detect_framing(b"<23>1 2015-08-05T15:53:45Z"), Framing::Line);
+        assert_eq!(detect_framing(b"{\"version\":\"1.1\"}"), Framing::Nul);
+        assert_eq!(detect_framing(b"0 "), Framing::Line);
+        assert_eq!(detect_framing(b""), Framing::Line);
+    }
+
+    #[cfg(feature = "gelf")]
+    #[test]
+    fn test_auto_splitter_gelf() {
+        use crate::flowgger::config::Config;
+        use crate::flowgger::decoder::GelfDecoder;
+        use crate::flowgger::encoder::GelfEncoder;
+        use crate::flowgger::record_queue::unbounded;
+
+        let input = b"{\"version\":\"1.1\",\"host\":\"example.org\",\"short_message\":\"first\"}\0\
+                      {\"version\":\"1.1\",\"host\":\"example.org\",\"short_message\":\"second\"}\0";
+        let config = Config::from_string("").unwrap();
+        let (tx, rx) = unbounded();
+        AutoSplitter.run(
+            BufReader::new(&input[..]),
+            tx,
+            Box::new(GelfDecoder::new(&config)),
+            Box::new(GelfEncoder::new(&config)),
+        );
+        let records: Vec<String> = rx
+            .try_iter()
+            .map(|record| String::from_utf8(record).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].contains(r#""short_message":"first""#));
+        assert!(records[1].contains(r#""short_message":"second""#));
+    }
+}
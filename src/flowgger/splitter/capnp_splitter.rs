@@ -1,27 +1,38 @@
 use super::Splitter;
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
-use crate::flowgger::record::{Record, SDValue, StructuredData, FACILITY_MAX, SEVERITY_MAX};
+use crate::flowgger::record::{
+    Facility, Record, SDValue, Severity, StructuredData, Timestamp, CAPNP_SCHEMA_VERSION,
+};
+use crate::flowgger::record_queue::{BatchSender, RecordSender};
 use crate::record_capnp;
 use capnp;
 use capnp::message::ReaderOptions;
+use std::borrow::Cow;
+use std::convert::TryFrom;
 use std::io::{stderr, BufReader, Read, Write};
-use std::sync::mpsc::SyncSender;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::time::Duration;
 
 pub struct CapnpSplitter;
 
+static NEWER_VERSION_WARNED: AtomicBool = AtomicBool::new(false);
+
 impl<T: Read> Splitter<T> for CapnpSplitter {
     fn run(
         &self,
         buf_reader: BufReader<T>,
-        tx: SyncSender<Vec<u8>>,
+        tx: RecordSender,
         _decoder: Box<dyn Decoder>,
         encoder: Box<dyn Encoder>,
     ) {
         let mut buf_reader = buf_reader;
+        let mut batch = BatchSender::new(tx);
         loop {
+            if !message_buffered(buf_reader.buffer()) {
+                batch.flush().unwrap();
+            }
             let message_reader =
                 match capnp::serialize::read_message(&mut buf_reader, ReaderOptions::new()) {
                     Err(e) => match e.kind {
@@ -56,39 +67,52 @@ impl<T: Read> Splitter<T> for CapnpSplitter {
                 Err(e) => {
                     let _ = writeln!(stderr(), "{}", e);
                 }
-                Ok(reencoded) => tx.send(reencoded).unwrap(),
+                Ok(reencoded) => batch.push(reencoded).unwrap(),
             };
         }
     }
 }
 
-fn get_pairs(
-    message_pairs: Option<capnp::struct_list::Reader<record_capnp::pair::Owned>>,
-    message_extra: Option<capnp::struct_list::Reader<record_capnp::pair::Owned>>,
-) -> Vec<(String, SDValue)> {
-    let pairs_count = message_pairs
-        .and_then(|x| Some(x.len()))
-        .or(Some(0))
-        .unwrap() as usize
-        + message_extra
-            .and_then(|x| Some(x.len()))
-            .or(Some(0))
-            .unwrap() as usize;
+/// `true` if the buffer holds a whole message, that can be read without waiting for more data
+fn message_buffered(buf: &[u8]) -> bool {
+    let word = |i: usize| {
+        buf.get(i * 4..i * 4 + 4)
+            .map(|x| u32::from_le_bytes(<[u8; 4]>::try_from(x).unwrap()) as usize)
+    };
+    let segments = match word(0) {
+        Some(segments) => segments + 1,
+        None => return false,
+    };
+    // The segment table is padded to a whole number of 8 bytes words
+    let table_len = (4 + segments * 4).next_multiple_of(8);
+    if buf.len() < table_len {
+        return false;
+    }
+    let words: usize = (1..=segments).filter_map(word).sum();
+    buf.len() - table_len >= words * 8
+}
+
+fn get_pairs<'a>(
+    message_pairs: Option<capnp::struct_list::Reader<'a, record_capnp::pair::Owned>>,
+    message_extra: Option<capnp::struct_list::Reader<'a, record_capnp::pair::Owned>>,
+) -> Vec<(Cow<'a, str>, SDValue<'a>)> {
+    let pairs_count = message_pairs.map(|x| x.len()).unwrap_or(0) as usize
+        + message_extra.map(|x| x.len()).unwrap_or(0) as usize;
     let mut pairs = Vec::with_capacity(pairs_count);
     if let Some(message_pairs) = message_pairs {
         for message_pair in message_pairs.iter() {
             let name = match message_pair.get_key() {
                 Ok(name) => {
                     if name.starts_with('_') {
-                        name.to_owned()
+                        Cow::Borrowed(name)
                     } else {
-                        format!("_{}", name)
+                        Cow::Owned(format!("_{}", name))
                     }
                 }
                 _ => continue,
             };
             let value = match message_pair.get_value().which() {
-                Ok(record_capnp::pair::value::String(Ok(x))) => SDValue::String(x.to_owned()),
+                Ok(record_capnp::pair::value::String(Ok(x))) => SDValue::String(Cow::Borrowed(x)),
                 Ok(record_capnp::pair::value::Bool(x)) => SDValue::Bool(x),
                 Ok(record_capnp::pair::value::F64(x)) => SDValue::F64(x),
                 Ok(record_capnp::pair::value::I64(x)) => SDValue::I64(x),
@@ -103,7 +127,7 @@ fn get_pairs(
         for message_pair in message_extra.iter() {
             match (message_pair.get_key(), message_pair.get_value().which()) {
                 (Ok(name), Ok(record_capnp::pair::value::String(Ok(value)))) => {
-                    pairs.push((name.to_owned(), SDValue::String(value.to_owned())))
+                    pairs.push((Cow::Borrowed(name), SDValue::String(Cow::Borrowed(value))))
                 }
                 _ => continue,
             }
@@ -113,11 +137,11 @@ fn get_pairs(
 }
 
 fn get_sd(
-    message: record_capnp::record::Reader,
-) -> Result<Option<Vec<StructuredData>>, &'static str> {
-    let sd_id = message.get_sd_id().and_then(|x| Ok(x.to_owned())).ok();
-    let pairs = message.get_pairs().ok();
-    let extra = message.get_extra().ok();
+    message: record_capnp::record::Reader<'_>,
+) -> Result<Option<Vec<StructuredData<'_>>>, &'static str> {
+    let sd_id = text(message.has_sd_id(), message.get_sd_id());
+    let pairs = message.get_pairs().ok().filter(|_| message.has_pairs());
+    let extra = message.get_extra().ok().filter(|_| message.has_extra());
     let pairs = if pairs.is_none() && extra.is_none() {
         if sd_id.is_none() {
             return Ok(None);
@@ -129,31 +153,44 @@ fn get_sd(
     Ok(Some(vec![StructuredData { sd_id, pairs }]))
 }
 
-fn handle_message(message: record_capnp::record::Reader) -> Result<Record, &'static str> {
+/// Optional text field, `None` when it was not set rather than the empty string capnp reads by default
+fn text(has: bool, value: capnp::Result<capnp::text::Reader<'_>>) -> Option<Cow<'_, str>> {
+    value.ok().filter(|_| has).map(Cow::Borrowed)
+}
+
+/// Decode a record. Records encoded before the schema was versioned (version 0) share the layout of the
+/// first version. Newer schemas only add fields, so records from a newer schema than the one compiled in
+/// are decoded without these fields, with a warning the first time.
+fn handle_message(message: record_capnp::record::Reader<'_>) -> Result<Record<'_>, &'static str> {
+    let version = message.get_version();
+    if version > CAPNP_SCHEMA_VERSION && !NEWER_VERSION_WARNED.swap(true, Ordering::Relaxed) {
+        let _ = writeln!(
+            stderr(),
+            "Cap'n Proto records use schema version {}, newer than version {}: their new fields are ignored",
+            version, CAPNP_SCHEMA_VERSION
+        );
+    }
     let ts = message.get_ts();
     if ts.is_nan() || ts <= 0.0 {
         return Err("Missing timestamp");
     }
+    let ts = Timestamp::from_secs_f64(ts);
     let hostname = message
         .get_hostname()
-        .and_then(|x| Ok(x.to_owned()))
+        .map(Cow::Borrowed)
         .or(Err("Missing host name"))?;
-    let facility = match message.get_facility() {
-        facility if facility <= FACILITY_MAX => Some(facility),
-        _ => None,
-    };
-    let severity = match message.get_severity() {
-        severity if severity <= SEVERITY_MAX => Some(severity),
-        _ => None,
-    };
-    let appname = message.get_appname().and_then(|x| Ok(x.to_owned())).ok();
-    let procid = message.get_procid().and_then(|x| Ok(x.to_owned())).ok();
-    let msgid = message.get_msgid().and_then(|x| Ok(x.to_owned())).ok();
-    let msg = message.get_msg().and_then(|x| Ok(x.to_owned())).ok();
-    let full_msg = message.get_full_msg().and_then(|x| Ok(x.to_owned())).ok();
+    // Facilities 24 to 31 don't exist, such records are kept without a facility
+    let facility = Facility::try_from(message.get_facility()).ok();
+    let severity = Severity::try_from(message.get_severity()).ok();
+    let appname = text(message.has_appname(), message.get_appname());
+    let procid = text(message.has_procid(), message.get_procid());
+    let msgid = text(message.has_msgid(), message.get_msgid());
+    let msg = text(message.has_msg(), message.get_msg());
+    let full_msg = text(message.has_full_msg(), message.get_full_msg());
     let sd = get_sd(message)?;
     Ok(Record {
         ts,
+        utc_offset: None,
         hostname,
         facility,
         severity,
@@ -173,19 +210,20 @@ mod tests {
     #[test]
     fn test_decode_message() {
         let sd = StructuredData {
-            sd_id: Some("someid".to_string()),
-            pairs: vec![("_some_info".to_string(), SDValue::String("foo".to_string()))],
+            sd_id: Some("someid".into()),
+            pairs: vec![("_some_info".into(), SDValue::String("foo".into()))],
         };
         let expected = Record {
-            ts: 1385053862.3072,
-            hostname: "example.org".to_string(),
+            ts: Timestamp::from_secs_f64(1385053862.3072),
+            utc_offset: None,
+            hostname: "example.org".into(),
             facility: None,
-            severity: Some(1),
-            appname: Some("appname".to_string()),
-            procid: Some("44".to_string()),
-            msgid: Some("".to_string()),
-            msg: Some("A short message that helps you identify what is going on".to_string()),
-            full_msg: Some("Backtrace here\n\nmore stuff".to_string()),
+            severity: Some(Severity::Alert),
+            appname: Some("appname".into()),
+            procid: Some("44".into()),
+            msgid: Some("".into()),
+            msg: Some("A short message that helps you identify what is going on".into()),
+            full_msg: Some("Backtrace here\n\nmore stuff".into()),
             sd: Some(vec![sd]),
         };
 
@@ -222,4 +260,54 @@ mod tests {
         assert_eq!(record.full_msg, expected.full_msg);
         assert_eq!(record.sd.unwrap()[0].sd_id, expected.sd.unwrap()[0].sd_id);
     }
+
+    #[test]
+    fn test_decode_message_version() {
+        let mut message = capnp::message::Builder::new_default();
+        let mut root: record_capnp::record::Builder = message.init_root();
+        root.set_ts(1385053862.3072);
+        root.set_hostname("example.org");
+        root.set_version(CAPNP_SCHEMA_VERSION);
+        let record = handle_message(message.get_root_as_reader().unwrap()).unwrap();
+        assert_eq!(record.hostname, "example.org");
+
+        let mut root: record_capnp::record::Builder = message.get_root().unwrap();
+        root.set_version(CAPNP_SCHEMA_VERSION + 1);
+        let record = handle_message(message.get_root_as_reader().unwrap()).unwrap();
+        assert_eq!(record.hostname, "example.org");
+        assert!(NEWER_VERSION_WARNED.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_decode_message_invalid_facility() {
+        let mut message = capnp::message::Builder::new_default();
+        let mut root: record_capnp::record::Builder = message.init_root();
+        root.set_ts(1385053862.3072);
+        root.set_hostname("example.org");
+        root.set_facility(23);
+        root.set_severity(3);
+        let record = handle_message(message.get_root_as_reader().unwrap()).unwrap();
+        assert_eq!(record.facility, Some(Facility::Local7));
+
+        let mut root: record_capnp::record::Builder = message.get_root().unwrap();
+        root.set_facility(24);
+        let record = handle_message(message.get_root_as_reader().unwrap()).unwrap();
+        assert_eq!(record.facility, None);
+        assert_eq!(record.severity, Some(Severity::Error));
+    }
+
+    #[test]
+    fn test_message_buffered() {
+        let mut message = capnp::message::Builder::new_default();
+        let mut root: record_capnp::record::Builder = message.init_root();
+        root.set_hostname("example.org");
+        let mut buf = Vec::new();
+        capnp::serialize::write_message(&mut buf, &message).unwrap();
+        assert!(message_buffered(&buf));
+        assert!(!message_buffered(&buf[..buf.len() - 1]));
+        assert!(!message_buffered(&buf[..6]));
+        assert!(!message_buffered(&[]));
+        buf.extend_from_slice(&[0; 3]);
+        assert!(message_buffered(&buf));
+    }
 }
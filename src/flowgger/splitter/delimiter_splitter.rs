@@ -0,0 +1,164 @@
+use super::{flush_unless_buffered, Splitter};
+use crate::flowgger::config::Config;
+use crate::flowgger::decoder::{log_rejected, Decoder};
+use crate::flowgger::encoder::Encoder;
+use crate::flowgger::record_queue::{BatchSender, RecordSender};
+use crate::flowgger::utils::parse_delimiter;
+use serde::Deserialize;
+use std::io::{stderr, BufRead, BufReader, ErrorKind, Read, Write};
+use std::str;
+
+const DEFAULT_FRAMING_DELIMITER: &str = "lf";
+
+/// Settings of the delimiter, in the `[input]` section
+#[derive(Deserialize)]
+pub struct DelimiterSettings {
+    framing_delimiter: Option<String>,
+}
+
+/// Read 'input.framing_delimiter' from the configuration
+///
+/// # Parameters
+/// - 'input.framing_delimiter': Optional. Either "lf", "cr", "crlf", "nul", "rs", or the literal sequence
+///   of bytes separating records. Default is "lf".
+pub fn framing_delimiter(config: &Config) -> Vec<u8> {
+    let settings: DelimiterSettings = config.settings("input");
+    let delimiter = settings
+        .framing_delimiter
+        .as_deref()
+        .unwrap_or(DEFAULT_FRAMING_DELIMITER);
+    parse_delimiter(delimiter).unwrap_or_else(|e| panic!("Invalid input.framing_delimiter: {}", e))
+}
+
+/// Non-transparent framing (RFC6587) with an arbitrary, possibly multi-byte, delimiter
+pub struct DelimiterSplitter {
+    delimiter: Vec<u8>,
+}
+
+impl DelimiterSplitter {
+    pub fn new(delimiter: Vec<u8>) -> DelimiterSplitter {
+        assert!(!delimiter.is_empty(), "The delimiter cannot be empty");
+        DelimiterSplitter { delimiter }
+    }
+
+    /// Read the next record, without its delimiter. The last record of the stream doesn't have to be terminated.
+    /// The records of the batch are sent before waiting for more data.
+    fn read_record<T: Read>(
+        &self,
+        buf_reader: &mut BufReader<T>,
+        record: &mut Vec<u8>,
+        batch: &mut BatchSender,
+    ) -> Result<bool, std::io::Error> {
+        let last = self.delimiter[self.delimiter.len() - 1];
+        loop {
+            flush_unless_buffered(buf_reader, last, batch);
+            if buf_reader.read_until(last, record)? == 0 {
+                return Ok(!record.is_empty());
+            }
+            if record.ends_with(&self.delimiter) {
+                record.truncate(record.len() - self.delimiter.len());
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl<T: Read> Splitter<T> for DelimiterSplitter {
+    fn run(
+        &self,
+        mut buf_reader: BufReader<T>,
+        tx: RecordSender,
+        decoder: Box<dyn Decoder>,
+        encoder: Box<dyn Encoder>,
+    ) {
+        let mut batch = BatchSender::new(tx);
+        let mut record = Vec::new();
+        loop {
+            record.clear();
+            match self.read_record(&mut buf_reader, &mut record, &mut batch) {
+                Ok(true) => {}
+                Ok(false) => return,
+                Err(e) => match e.kind() {
+                    ErrorKind::Interrupted => continue,
+                    ErrorKind::WouldBlock => {
+                        let _ = writeln!(
+                            stderr(),
+                            "Client hasn't sent any data for a while - Closing \
+                             idle connection"
+                        );
+                        return;
+                    }
+                    _ => return,
+                },
+            }
+            if record.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            if let Err(e) = handle_line(&record, &mut batch, &*decoder, &*encoder) {
+                log_rejected(e, &record);
+            }
+        }
+    }
+}
+
+fn handle_line(
+    line: &[u8],
+    batch: &mut BatchSender,
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
+) -> Result<(), &'static str> {
+    let decoded = decoder.decode_bytes(line)?;
+    let reencoded = encoder.encode(decoded)?;
+    batch.push(reencoded).unwrap();
+    Ok(())
+}
+
+#[cfg(all(test, feature = "rfc5424"))]
+mod tests {
+    use super::*;
+    use crate::flowgger::decoder::RFC5424Decoder;
+    use crate::flowgger::encoder::PassthroughEncoder;
+    use crate::flowgger::record_queue::unbounded;
+
+    fn split(delimiter: &[u8], input: &[u8]) -> Vec<Vec<u8>> {
+        let config = Config::from_string("").unwrap();
+        let (tx, rx) = unbounded();
+        DelimiterSplitter::new(delimiter.to_vec()).run(
+            BufReader::new(input),
+            tx,
+            Box::new(RFC5424Decoder::new(&config)),
+            Box::new(PassthroughEncoder::new(&config)),
+        );
+        rx.try_iter().collect()
+    }
+
+    #[test]
+    fn test_delimiter_splitter_crlf() {
+        let input = b"<23>1 2015-08-05T15:53:45Z testhostname appname 69 42 - first\r\n\
+                      <23>1 2015-08-05T15:53:45Z testhostname appname 69 42 - with \r and \n inside\r\n\
+                      <23>1 2015-08-05T15:53:45Z testhostname appname 69 42 - last";
+        let records = split(b"\r\n", input);
+        assert_eq!(records.len(), 3);
+        assert!(records[0].ends_with(b"- first"));
+        assert!(records[1].ends_with(b"- with \r and \n inside"));
+        assert!(records[2].ends_with(b"- last"));
+    }
+
+    #[test]
+    fn test_delimiter_splitter_record_separator() {
+        let input = b"\x1e<23>1 2015-08-05T15:53:45Z testhostname appname 69 42 - first\n\
+                      \x1e<23>1 2015-08-05T15:53:45Z testhostname appname 69 42 - second\n";
+        let records = split(&[0x1e], input);
+        assert_eq!(records.len(), 2);
+        assert!(records[0].trim_ascii_end().ends_with(b"- first"));
+        assert!(records[1].trim_ascii_end().ends_with(b"- second"));
+    }
+
+    #[test]
+    fn test_framing_delimiter_config() {
+        let config = Config::from_string("[input]\n").unwrap();
+        assert_eq!(framing_delimiter(&config), b"\n");
+        let config = Config::from_string("[input]\nframing_delimiter = \"rs\"\n").unwrap();
+        assert_eq!(framing_delimiter(&config), [0x1e]);
+    }
+}
@@ -0,0 +1,94 @@
+use super::{flush_unless_buffered, Splitter};
+use crate::flowgger::decoder::{log_rejected, Decoder};
+use crate::flowgger::encoder::Encoder;
+use crate::flowgger::record_queue::{BatchSender, RecordSender};
+use std::io::{stderr, BufRead, BufReader, ErrorKind, Read, Write};
+use std::str;
+
+/// RFC7464 record separator
+const RS: u8 = 0x1e;
+
+/// JSON text sequences (RFC7464). Records start with RS and should end with LF; empty
+/// records are ignored, and a record missing its trailing LF is only accepted if it still decodes.
+pub struct JsonSeqSplitter;
+
+impl<T: Read> Splitter<T> for JsonSeqSplitter {
+    fn run(
+        &self,
+        mut buf_reader: BufReader<T>,
+        tx: RecordSender,
+        decoder: Box<dyn Decoder>,
+        encoder: Box<dyn Encoder>,
+    ) {
+        let mut batch = BatchSender::new(tx);
+        let mut record = Vec::new();
+        loop {
+            flush_unless_buffered(&buf_reader, RS, &mut batch);
+            record.clear();
+            match buf_reader.read_until(RS, &mut record) {
+                Ok(0) => return,
+                Ok(_) => {}
+                Err(e) => match e.kind() {
+                    ErrorKind::Interrupted => continue,
+                    ErrorKind::WouldBlock => {
+                        let _ = writeln!(
+                            stderr(),
+                            "Client hasn't sent any data for a while - Closing \
+                             idle connection"
+                        );
+                        return;
+                    }
+                    _ => return,
+                },
+            }
+            let text = record.strip_suffix(&[RS]).unwrap_or(&record).trim_ascii();
+            if text.is_empty() {
+                continue;
+            }
+            if let Err(e) = handle_text(text, &mut batch, &*decoder, &*encoder) {
+                log_rejected(e, text);
+            }
+        }
+    }
+}
+
+fn handle_text(
+    text: &[u8],
+    batch: &mut BatchSender,
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
+) -> Result<(), &'static str> {
+    let decoded = decoder.decode_bytes(text)?;
+    let reencoded = encoder.encode(decoded)?;
+    batch.push(reencoded).unwrap();
+    Ok(())
+}
+
+#[cfg(all(test, feature = "gelf"))]
+#[test]
+fn test_json_seq_splitter() {
+    use crate::flowgger::config::Config;
+    use crate::flowgger::decoder::GelfDecoder;
+    use crate::flowgger::encoder::GelfEncoder;
+    use crate::flowgger::record_queue::unbounded;
+
+    let config = Config::from_string("").unwrap();
+    let input = b"\x1e{\"version\":\"1.1\",\"host\":\"a\",\"short_message\":\"first\"}\n\
+                  \x1e\n\
+                  \x1e{\"version\":\"1.1\",\"host\":\"b\",\"short_message\":\"trunc\n\
+                  \x1e{\"version\":\"1.1\",\"host\":\"c\",\"short_message\":\"last\"}";
+    let (tx, rx) = unbounded();
+    JsonSeqSplitter.run(
+        BufReader::new(&input[..]),
+        tx,
+        Box::new(GelfDecoder::new(&config)),
+        Box::new(GelfEncoder::new(&config)),
+    );
+    let records: Vec<String> = rx
+        .try_iter()
+        .map(|x| String::from_utf8(x).unwrap())
+        .collect();
+    assert_eq!(records.len(), 2);
+    assert!(records[0].contains(r#""short_message":"first""#));
+    assert!(records[1].contains(r#""short_message":"last""#));
+}
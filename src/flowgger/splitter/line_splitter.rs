@@ -1,28 +1,32 @@
 use super::Splitter;
-use crate::flowgger::decoder::Decoder;
+use crate::flowgger::decoder::{log_rejected, Decoder};
 use crate::flowgger::encoder::Encoder;
+use crate::flowgger::record_queue::{BatchSender, RecordSender};
+use memchr::memchr_iter;
 use std::io::{stderr, BufRead, BufReader, ErrorKind, Read, Write};
-use std::sync::mpsc::SyncSender;
 
+/// Splits the stream on '\n', dropping a trailing '\r'.
+///
+/// Lines are looked for with SIMD directly in the buffer of the reader, and handled from there without being
+/// copied. Only the lines straddling two reads are copied, into a buffer that is reused for the next ones.
+/// The records of every read are sent to the queue together.
 pub struct LineSplitter;
 
 impl<T: Read> Splitter<T> for LineSplitter {
     fn run(
         &self,
-        buf_reader: BufReader<T>,
-        tx: SyncSender<Vec<u8>>,
+        mut buf_reader: BufReader<T>,
+        tx: RecordSender,
         decoder: Box<dyn Decoder>,
         encoder: Box<dyn Encoder>,
     ) {
-        for line in buf_reader.lines() {
-            let line = match line {
-                Ok(line) => line,
+        let mut batch = BatchSender::new(tx);
+        let mut partial = Vec::new();
+        loop {
+            let buf = match buf_reader.fill_buf() {
+                Ok(buf) => buf,
                 Err(e) => match e.kind() {
                     ErrorKind::Interrupted => continue,
-                    ErrorKind::InvalidInput | ErrorKind::InvalidData => {
-                        let _ = writeln!(stderr(), "Invalid UTF-8 input");
-                        continue;
-                    }
                     ErrorKind::WouldBlock => {
                         let _ = writeln!(
                             stderr(),
@@ -34,21 +38,127 @@ impl<T: Read> Splitter<T> for LineSplitter {
                     _ => return,
                 },
             };
-            if let Err(e) = handle_line(&line, &tx, &decoder, &encoder) {
-                let _ = writeln!(stderr(), "{}: [{}]", e, line.trim());
+            if buf.is_empty() {
+                if !partial.is_empty() {
+                    split_line(&partial, &mut batch, &*decoder, &*encoder);
+                }
+                return;
+            }
+            let mut start = 0;
+            for end in memchr_iter(b'\n', buf) {
+                if partial.is_empty() {
+                    split_line(&buf[start..end], &mut batch, &*decoder, &*encoder);
+                } else {
+                    partial.extend_from_slice(&buf[start..end]);
+                    split_line(&partial, &mut batch, &*decoder, &*encoder);
+                    partial.clear();
+                }
+                start = end + 1;
             }
+            partial.extend_from_slice(&buf[start..]);
+            let len = buf.len();
+            buf_reader.consume(len);
+            // The records of a read are sent together, before waiting for the next one
+            batch.flush().unwrap();
         }
     }
 }
 
+fn split_line(line: &[u8], batch: &mut BatchSender, decoder: &dyn Decoder, encoder: &dyn Encoder) {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if let Err(e) = handle_line(line, batch, decoder, encoder) {
+        log_rejected(e, line);
+    }
+}
+
 fn handle_line(
-    line: &str,
-    tx: &SyncSender<Vec<u8>>,
-    decoder: &Box<dyn Decoder>,
-    encoder: &Box<dyn Encoder>,
+    line: &[u8],
+    batch: &mut BatchSender,
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
 ) -> Result<(), &'static str> {
-    let decoded = decoder.decode(line)?;
+    let decoded = decoder.decode_bytes(line)?;
     let reencoded = encoder.encode(decoded)?;
-    tx.send(reencoded).unwrap();
+    batch.push(reencoded).unwrap();
     Ok(())
 }
+
+#[cfg(all(test, feature = "rfc5424"))]
+mod tests {
+    use super::*;
+    use crate::flowgger::config::Config;
+    use crate::flowgger::decoder::RFC5424Decoder;
+    use crate::flowgger::encoder::PassthroughEncoder;
+    use crate::flowgger::record_queue::{unbounded, RecordReceiver};
+    use std::io;
+
+    #[test]
+    fn test_line_splitter() {
+        let input = b"<23>1 2015-08-05T15:53:45Z testhostname appname 69 42 - first\r\n\
+                      <23>1 2015-08-05T15:53:45Z testhostname appname 69 42 - second\n\
+                      \n\
+                      <23>1 2015-08-05T15:53:45Z testhostname appname 69 42 - last";
+        // Buffers smaller than the lines, so that most of them straddle two reads
+        for capacity in [1, 7, 64, 8192] {
+            let config = Config::from_string("").unwrap();
+            let (tx, rx) = unbounded();
+            LineSplitter.run(
+                BufReader::with_capacity(capacity, &input[..]),
+                tx,
+                Box::new(RFC5424Decoder::new(&config)),
+                Box::new(PassthroughEncoder::new(&config)),
+            );
+            let records: Vec<Vec<u8>> = rx.try_iter().collect();
+            assert_eq!(records.len(), 3);
+            assert!(records[0].ends_with(b"- first"));
+            assert!(records[1].ends_with(b"- second"));
+            assert!(records[2].ends_with(b"- last"));
+        }
+    }
+
+    /// Reader recording how many records were queued every time it was read
+    struct QueuedReader {
+        chunks: Vec<&'static [u8]>,
+        rx: RecordReceiver,
+        queued: Vec<usize>,
+    }
+
+    impl Read for QueuedReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.queued.push(self.rx.len());
+            if self.chunks.is_empty() {
+                return Ok(0);
+            }
+            let chunk = self.chunks.remove(0);
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn test_line_splitter_batches() {
+        let config = Config::from_string("").unwrap();
+        let (tx, rx) = unbounded();
+        let mut reader = QueuedReader {
+            chunks: vec![
+                b"<23>1 2015-08-05T15:53:45Z testhostname appname 69 42 - first\n\
+                  <23>1 2015-08-05T15:53:45Z testhostname appname 69 42 - second\n\
+                  <23>1 2015-08-05T15:53:45Z testhostname",
+                b" appname 69 42 - third\n",
+            ],
+            rx: rx.clone(),
+            queued: Vec::new(),
+        };
+        LineSplitter.run(
+            BufReader::new(&mut reader),
+            tx,
+            Box::new(RFC5424Decoder::new(&config)),
+            Box::new(PassthroughEncoder::new(&config)),
+        );
+        // The records of every read are queued before the next read, that could block
+        assert_eq!(reader.queued, [0, 2, 3]);
+        let mut batch = Vec::new();
+        rx.recv_batch(&mut batch, 10).unwrap();
+        assert_eq!(batch.len(), 3);
+    }
+}
@@ -1,26 +1,43 @@
-#[cfg(feature = "capnp-recompile")]
+mod auto_splitter;
+#[cfg(feature = "capnp")]
 mod capnp_splitter;
+mod delimiter_splitter;
+mod json_seq_splitter;
 mod line_splitter;
 mod nul_splitter;
 mod syslen_splitter;
 
-#[cfg(feature = "capnp-recompile")]
+pub use self::auto_splitter::AutoSplitter;
+#[cfg(feature = "capnp")]
 pub use self::capnp_splitter::CapnpSplitter;
+#[cfg(test)]
+pub use self::delimiter_splitter::DelimiterSettings;
+pub use self::delimiter_splitter::{framing_delimiter, DelimiterSplitter};
+pub use self::json_seq_splitter::JsonSeqSplitter;
 pub use self::line_splitter::LineSplitter;
 pub use self::nul_splitter::NulSplitter;
 pub use self::syslen_splitter::SyslenSplitter;
 
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
+use crate::flowgger::record_queue::{BatchSender, RecordSender};
+use memchr::memchr;
 use std::io::BufReader;
-use std::sync::mpsc::SyncSender;
 
 pub trait Splitter<T> {
     fn run(
         &self,
         buf_reader: BufReader<T>,
-        tx: SyncSender<Vec<u8>>,
+        tx: RecordSender,
         decoder: Box<dyn Decoder>,
         encoder: Box<dyn Encoder>,
     );
 }
+
+/// Send the records collected so far, unless the end of the next record is already buffered, so that
+/// records are never held back while reading the next one waits for more data
+fn flush_unless_buffered<T>(buf_reader: &BufReader<T>, delimiter: u8, batch: &mut BatchSender) {
+    if memchr(delimiter, buf_reader.buffer()).is_none() {
+        batch.flush().unwrap();
+    }
+}
@@ -1,23 +1,28 @@
-use super::Splitter;
-use crate::flowgger::decoder::Decoder;
+use super::{flush_unless_buffered, Splitter};
+use crate::flowgger::decoder::{log_rejected, Decoder};
 use crate::flowgger::encoder::Encoder;
+use crate::flowgger::record_queue::{BatchSender, RecordSender};
 use std::io::{stderr, BufRead, BufReader, ErrorKind, Read, Write};
 use std::str;
-use std::sync::mpsc::SyncSender;
 
 pub struct NulSplitter;
 
 impl<T: Read> Splitter<T> for NulSplitter {
     fn run(
         &self,
-        buf_reader: BufReader<T>,
-        tx: SyncSender<Vec<u8>>,
+        mut buf_reader: BufReader<T>,
+        tx: RecordSender,
         decoder: Box<dyn Decoder>,
         encoder: Box<dyn Encoder>,
     ) {
-        for line in buf_reader.split(0) {
-            let line = match line {
-                Ok(line) => line,
+        let mut batch = BatchSender::new(tx);
+        let mut line = Vec::new();
+        loop {
+            flush_unless_buffered(&buf_reader, 0, &mut batch);
+            line.clear();
+            match buf_reader.read_until(0, &mut line) {
+                Ok(0) => return,
+                Ok(_) => {}
                 Err(e) => match e.kind() {
                     ErrorKind::Interrupted => continue,
                     ErrorKind::WouldBlock => {
@@ -30,18 +35,13 @@ impl<T: Read> Splitter<T> for NulSplitter {
                     }
                     _ => return,
                 },
-            };
-            let line = match str::from_utf8(&line) {
-                Err(_) => {
-                    let _ = writeln!(stderr(), "Invalid UTF-8 input");
-                    continue;
-                }
-                Ok(line) => line,
-            };
-            if let Err(e) = handle_line(line, &tx, &decoder, &encoder) {
-                let line = line.trim();
-                if !line.is_empty() {
-                    let _ = writeln!(stderr(), "{}: [{}]", e, line.trim());
+            }
+            if line.last() == Some(&0) {
+                line.pop();
+            }
+            if let Err(e) = handle_line(&line, &mut batch, &*decoder, &*encoder) {
+                if !line.iter().all(u8::is_ascii_whitespace) {
+                    log_rejected(e, &line);
                 }
             }
         }
@@ -49,13 +49,13 @@ impl<T: Read> Splitter<T> for NulSplitter {
 }
 
 fn handle_line(
-    line: &str,
-    tx: &SyncSender<Vec<u8>>,
-    decoder: &Box<dyn Decoder>,
-    encoder: &Box<dyn Encoder>,
+    line: &[u8],
+    batch: &mut BatchSender,
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
 ) -> Result<(), &'static str> {
-    let decoded = decoder.decode(line)?;
+    let decoded = decoder.decode_bytes(line)?;
     let reencoded = encoder.encode(decoded)?;
-    tx.send(reencoded).unwrap();
+    batch.push(reencoded).unwrap();
     Ok(())
 }
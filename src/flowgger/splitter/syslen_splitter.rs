@@ -1,9 +1,10 @@
 use super::Splitter;
-use crate::flowgger::decoder::Decoder;
+use crate::flowgger::decoder::{log_rejected, Decoder};
 use crate::flowgger::encoder::Encoder;
+use crate::flowgger::record_queue::{BatchSender, RecordSender};
+use memchr::memchr;
 use std::io::{stderr, BufRead, BufReader, Read, Write};
 use std::str;
-use std::sync::mpsc::SyncSender;
 
 pub struct SyslenSplitter;
 
@@ -11,12 +12,16 @@ impl<T: Read> Splitter<T> for SyslenSplitter {
     fn run(
         &self,
         buf_reader: BufReader<T>,
-        tx: SyncSender<Vec<u8>>,
+        tx: RecordSender,
         decoder: Box<dyn Decoder>,
         encoder: Box<dyn Encoder>,
     ) {
         let mut buf_reader = buf_reader;
+        let mut batch = BatchSender::new(tx);
         loop {
+            if !frame_buffered(buf_reader.buffer()) {
+                batch.flush().unwrap();
+            }
             let size = match read_msglen(&mut buf_reader) {
                 Ok(size) => size,
                 Err(_) => {
@@ -30,15 +35,25 @@ impl<T: Read> Splitter<T> for SyslenSplitter {
                 return;
             }
 
-            let buffer = String::from_utf8(buffer).unwrap();
-
-            if let Err(e) = handle_line(&buffer, &tx, &decoder, &encoder) {
-                let _ = writeln!(stderr(), "{}: [{}]", e, buffer.trim());
+            if let Err(e) = handle_line(&buffer, &mut batch, &*decoder, &*encoder) {
+                log_rejected(e, &buffer);
             }
         }
     }
 }
 
+/// `true` if the buffer holds a whole frame, that can be read without waiting for more data
+fn frame_buffered(buf: &[u8]) -> bool {
+    let space = match memchr(b' ', buf) {
+        Some(space) => space,
+        None => return false,
+    };
+    str::from_utf8(&buf[..space])
+        .ok()
+        .and_then(|nbytes_s| nbytes_s.parse::<usize>().ok())
+        .is_some_and(|nbytes| buf.len() - space > nbytes)
+}
+
 fn read_msglen(reader: &mut dyn BufRead) -> Result<usize, &'static str> {
     let mut nbytes_v = Vec::with_capacity(16);
     let nbytes_vl = match reader.read_until(b' ', &mut nbytes_v) {
@@ -57,13 +72,28 @@ fn read_msglen(reader: &mut dyn BufRead) -> Result<usize, &'static str> {
 }
 
 fn handle_line(
-    line: &str,
-    tx: &SyncSender<Vec<u8>>,
-    decoder: &Box<dyn Decoder>,
-    encoder: &Box<dyn Encoder>,
+    line: &[u8],
+    batch: &mut BatchSender,
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
 ) -> Result<(), &'static str> {
-    let decoded = decoder.decode(line)?;
+    let decoded = decoder.decode_bytes(line)?;
     let reencoded = encoder.encode(decoded)?;
-    tx.send(reencoded).unwrap();
+    batch.push(reencoded).unwrap();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_buffered() {
+        assert!(frame_buffered(b"5 hello"));
+        assert!(frame_buffered(b"5 hello3 abc"));
+        assert!(!frame_buffered(b"5 hell"));
+        assert!(!frame_buffered(b"12"));
+        assert!(!frame_buffered(b"x hello"));
+        assert!(!frame_buffered(b""));
+    }
+}
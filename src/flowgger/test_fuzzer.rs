@@ -24,12 +24,12 @@ mod tests {
     use quickcheck::QuickCheck;
     use tempdir::TempDir;
 
+    use crate::flowgger::record_queue::{bounded, RecordReceiver, RecordSender};
     use std::fs;
     use std::io::{BufRead, BufReader};
     use std::ptr::addr_of_mut;
-    use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+    use std::sync::Mutex;
     use std::sync::Once;
-    use std::sync::{Arc, Mutex};
 
     use flowgger::config::Config;
     use flowgger::decoder::Decoder;
@@ -59,7 +59,7 @@ mod tests {
     struct Context {
         encoder: Box<dyn Encoder>,
         decoder: Box<dyn Decoder>,
-        sync_sender: SyncSender<Vec<u8>>,
+        sync_sender: RecordSender,
     }
 
     #[test]
@@ -71,8 +71,8 @@ mod tests {
                 x.as_str().expect("File output path missing in config")
             });
         let output_dir = get_output_dir();
-        let file_output_path = get_output_file_path(&output_dir, &file_output_name);
-        let (tx, rx): (SyncSender<Vec<u8>>, Receiver<Vec<u8>>) = sync_channel(DEFAULT_QUEUE_SIZE);
+        let file_output_path = get_output_file_path(&output_dir, file_output_name);
+        let (tx, rx): (RecordSender, RecordReceiver) = bounded(DEFAULT_QUEUE_SIZE);
 
         set_output_file_path_in_config(&mut config, &file_output_path);
         start_file_output(&config, rx);
@@ -85,8 +85,7 @@ mod tests {
     }
 
     fn get_output_dir() -> TempDir {
-        let temp_dir = TempDir::new("test_file_output").expect("Couldn't create output directory");
-        return temp_dir;
+        TempDir::new("test_file_output").expect("Couldn't create output directory")
     }
 
     fn get_output_file_path(output_dir: &TempDir, file_output_name: &str) -> String {
@@ -96,17 +95,17 @@ mod tests {
             .to_string_lossy()
             .to_string();
 
-        return file_base;
+        file_base
     }
 
     fn get_global_context() -> *mut Mutex<Option<Context>> {
-        unsafe { addr_of_mut!(GLOBAL_CONTEXT) }
+        addr_of_mut!(GLOBAL_CONTEXT)
     }
 
     // Set the global context for the fuzzer
     // The global context is used to share resources across all test runs
     // CallOnce routine ensures the static variable referencing the struct is only ever set once
-    fn set_global_context(config: &Config, sync_sender: SyncSender<Vec<u8>>) {
+    fn set_global_context(config: &Config, sync_sender: RecordSender) {
         INIT_CONTEXT.call_once(|| unsafe {
             let decoder = get_decoder_rfc3164(config);
             let encoder = get_encoder_rfc3164(config);
@@ -114,12 +113,12 @@ mod tests {
                 (decoder.clone_boxed(), encoder.clone_boxed());
 
             let context = Context {
-                encoder: encoder,
-                decoder: decoder,
-                sync_sender: sync_sender,
+                encoder,
+                decoder,
+                sync_sender,
             };
 
-            let mut guard = GLOBAL_CONTEXT.lock().unwrap();
+            let mut guard = (*get_global_context()).lock().unwrap();
             if guard.is_none() {
                 *guard = Some(context);
             }
@@ -132,13 +131,12 @@ mod tests {
             Ok(config) => config,
             Err(e) => panic!(
                 "Unable to read the config file [{}]: {}",
-                "flowgger.toml",
-                e.to_string()
+                "flowgger.toml", e
             ),
         };
 
         update_file_rotation_defaults_in_config(&mut config);
-        return config;
+        config
     }
 
     /// Update the default file rotation size and time in the config file
@@ -175,14 +173,14 @@ mod tests {
     }
 
     /// Start an input listener which writes data to the output file once received.
-    fn start_file_output(config: &Config, rx: Receiver<Vec<u8>>) {
+    fn start_file_output(config: &Config, rx: RecordReceiver) {
         let output_format = config
             .lookup("output.format")
             .map_or(DEFAULT_OUTPUT_FORMAT, |x| {
                 x.as_str().expect("output.format must be a string")
             });
 
-        let output = get_output_file(&config);
+        let output = get_output_file(config);
         let output_type = config
             .lookup("output.type")
             .map_or(DEFAULT_OUTPUT_TYPE, |x| {
@@ -199,10 +197,9 @@ mod tests {
             },
         };
         let merger: Option<Box<dyn Merger>> =
-            Some(Box::new(LineMerger::new(&config)) as Box<dyn Merger>);
+            Some(Box::new(LineMerger::new(config)) as Box<dyn Merger>);
 
-        let arx = Arc::new(Mutex::new(rx));
-        output.start(arx, merger);
+        output.start(rx, merger, None);
     }
 
     fn fuzz_target_rfc3164(data: String) {
@@ -214,16 +211,20 @@ mod tests {
                 Ok(guard) => guard,
                 Err(_poisoned_error) => {
                     // Handle the poisoned Mutex
-                    let guard = _poisoned_error.into_inner();
-                    guard
+
+                    _poisoned_error.into_inner()
                 }
             };
             let context: &mut Context = guard.as_mut().unwrap();
-            let sync_sender: &mut SyncSender<Vec<u8>> = &mut context.sync_sender;
+            let sync_sender: &mut RecordSender = &mut context.sync_sender;
             let encoder: &mut Box<dyn Encoder> = &mut context.encoder;
             let decoder: &mut Box<dyn Decoder> = &mut context.decoder;
-            let _result =
-                handle_record_maybe_compressed(data.as_bytes(), &sync_sender, &decoder, &encoder);
+            let _result = handle_record_maybe_compressed(
+                data.as_bytes(),
+                sync_sender,
+                &**decoder,
+                &**encoder,
+            );
 
             drop(guard);
         }
@@ -237,12 +238,9 @@ mod tests {
             let global_context = get_global_context().as_ref().unwrap();
             let mut guard = match global_context.lock() {
                 Ok(guard) => guard,
-                Err(_poisoned_error) => {
-                    let guard = _poisoned_error.into_inner();
-                    guard
-                }
+                Err(_poisoned_error) => _poisoned_error.into_inner(),
             };
-            let tx: SyncSender<Vec<u8>> = guard.take().unwrap().sync_sender;
+            let tx: RecordSender = guard.take().unwrap().sync_sender;
             drop(tx);
             drop(guard);
 
@@ -0,0 +1,475 @@
+/// Round-trip tests between the encoders and the decoders of the same format
+///
+/// Random records are encoded, decoded back, and compared to the record the format is expected to preserve.
+/// Formats can't represent every record, so every conversion a format loses is spelled out in its `expected_*`
+/// function: a field dropped or changed by an encoder or a decoder without being listed there is a bug.
+///
+/// # Dependencies
+/// It depends on the external crate [`QuickCheck`][https://docs.rs/quickcheck/latest/quickcheck/] to generate
+/// the records
+#[cfg(test)]
+mod tests {
+    extern crate quickcheck;
+
+    use crate::flowgger::config::Config;
+    use crate::flowgger::decoder::Decoder;
+    use crate::flowgger::encoder::Encoder;
+    use crate::flowgger::record::{Facility, Record, SDValue, Severity, StructuredData, Timestamp};
+    use quickcheck::{Arbitrary, Gen, QuickCheck};
+    use std::borrow::Cow;
+    use std::convert::TryFrom;
+
+    const ROUND_TRIP_COUNT: u64 = 500;
+
+    /// Characters of the generated names: no spaces, and nothing that needs to be escaped
+    const NAME_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789._";
+    /// Characters of the generated values and messages, including the ones the encoders have to escape
+    const TEXT_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789 .,;:=\"'\\[]{}@#%-_/";
+
+    /// Fields of a random record. `Record` is neither `Clone` nor comparable, so records are built from this,
+    /// and compared through their `Debug` representation.
+    #[derive(Clone, Debug)]
+    struct Fields {
+        ts_us: i64,
+        hostname: String,
+        facility: Option<Facility>,
+        severity: Option<Severity>,
+        appname: Option<String>,
+        procid: Option<String>,
+        msgid: Option<String>,
+        msg: Option<String>,
+        full_msg: Option<String>,
+        sd: Option<Vec<(String, Pairs)>>,
+    }
+
+    type Pairs = Vec<(String, Value)>;
+
+    #[derive(Clone, Debug)]
+    enum Value {
+        String(String),
+        Bool(bool),
+        F64(f64),
+        I64(i64),
+        U64(u64),
+        Null,
+    }
+
+    fn name(g: &mut Gen) -> String {
+        let len = 1 + usize::arbitrary(g) % 12;
+        (0..len)
+            .map(|_| *g.choose(NAME_CHARS).unwrap() as char)
+            .collect()
+    }
+
+    /// Text without leading or trailing spaces, that decoders trim
+    fn text(g: &mut Gen) -> String {
+        let len = usize::arbitrary(g) % 40;
+        let text: String = (0..len)
+            .map(|_| *g.choose(TEXT_CHARS).unwrap() as char)
+            .collect();
+        format!("x{}x", text.trim())
+    }
+
+    impl Arbitrary for Value {
+        fn arbitrary(g: &mut Gen) -> Value {
+            match u8::arbitrary(g) % 6 {
+                0 => Value::Bool(bool::arbitrary(g)),
+                1 => Value::F64(f64::from(i32::arbitrary(g)) / 64.0 + 0.5),
+                2 => Value::I64(-1 - i64::from(u32::arbitrary(g))),
+                3 => Value::U64(u64::arbitrary(g)),
+                4 => Value::Null,
+                _ => Value::String(text(g)),
+            }
+        }
+    }
+
+    impl Arbitrary for Fields {
+        fn arbitrary(g: &mut Gen) -> Fields {
+            let sd = (0..u8::arbitrary(g) % 3)
+                .map(|_| {
+                    let sd_id = format!("{}@{}", name(g), u16::arbitrary(g));
+                    // Pair names start with a prefix, so that they don't collide with the header fields
+                    let pairs = (0..u8::arbitrary(g) % 4)
+                        .map(|_| (format!("_k{}", name(g)), Value::arbitrary(g)))
+                        .collect();
+                    (sd_id, pairs)
+                })
+                .collect::<Vec<_>>();
+            Fields {
+                // Microseconds, between 2001 and 2035
+                ts_us: 1_000_000_000_000_000 + i64::from(u32::arbitrary(g)) * 250_001,
+                hostname: name(g),
+                facility: Option::<u8>::arbitrary(g)
+                    .map(|facility| Facility::try_from(facility % 24).unwrap()),
+                severity: Option::<u8>::arbitrary(g)
+                    .map(|severity| Severity::try_from(severity % 8).unwrap()),
+                appname: Option::<bool>::arbitrary(g).map(|_| name(g)),
+                procid: Option::<bool>::arbitrary(g).map(|_| name(g)),
+                msgid: Option::<bool>::arbitrary(g).map(|_| name(g)),
+                msg: Option::<bool>::arbitrary(g).map(|_| text(g)),
+                full_msg: Option::<bool>::arbitrary(g).map(|_| text(g)),
+                sd: if sd.is_empty() { None } else { Some(sd) },
+            }
+        }
+    }
+
+    impl Value {
+        fn sd_value(&self) -> SDValue<'_> {
+            match self {
+                Value::String(value) => SDValue::String(value.as_str().into()),
+                Value::Bool(value) => SDValue::Bool(*value),
+                Value::F64(value) => SDValue::F64(*value),
+                Value::I64(value) => SDValue::I64(*value),
+                Value::U64(value) => SDValue::U64(*value),
+                Value::Null => SDValue::Null,
+            }
+        }
+
+        fn text(&self) -> String {
+            match self {
+                Value::String(value) => value.clone(),
+                Value::Bool(value) => value.to_string(),
+                Value::F64(value) => value.to_string(),
+                Value::I64(value) => value.to_string(),
+                Value::U64(value) => value.to_string(),
+                Value::Null => String::new(),
+            }
+        }
+    }
+
+    impl Fields {
+        fn ts(&self) -> Timestamp {
+            Timestamp::from_unix_nanos(i128::from(self.ts_us) * 1000)
+        }
+
+        fn record(&self) -> Record<'_> {
+            Record {
+                ts: self.ts(),
+                utc_offset: None,
+                hostname: self.hostname.as_str().into(),
+                facility: self.facility,
+                severity: self.severity,
+                appname: self.appname.as_deref().map(Cow::Borrowed),
+                procid: self.procid.as_deref().map(Cow::Borrowed),
+                msgid: self.msgid.as_deref().map(Cow::Borrowed),
+                msg: self.msg.as_deref().map(Cow::Borrowed),
+                full_msg: self.full_msg.as_deref().map(Cow::Borrowed),
+                sd: self.sd.as_ref().map(|sd| {
+                    sd.iter()
+                        .map(|(sd_id, pairs)| StructuredData {
+                            sd_id: Some(sd_id.as_str().into()),
+                            pairs: pairs
+                                .iter()
+                                .map(|(name, value)| (name.as_str().into(), value.sd_value()))
+                                .collect(),
+                        })
+                        .collect()
+                }),
+            }
+        }
+    }
+
+    fn debug(record: &Record) -> String {
+        format!("{:?}", record)
+    }
+
+    fn round_trip(
+        fields: &Fields,
+        encoder: &dyn Encoder,
+        decoder: &dyn Decoder,
+        expected: fn(&Fields) -> Record<'_>,
+        normalize: fn(Record<'_>) -> Record<'_>,
+    ) -> bool {
+        let encoded = encoder.encode(fields.record()).unwrap();
+        let decoded = match decoder.decode_bytes(&encoded) {
+            Ok(decoded) => normalize(decoded),
+            Err(e) => panic!(
+                "Unable to decode {:?}: {}",
+                String::from_utf8_lossy(&encoded),
+                e
+            ),
+        };
+        let (expected, decoded) = (debug(&expected(fields)), debug(&decoded));
+        if expected != decoded {
+            eprintln!(
+                "Round trip mismatch for {:?}\n  expected: {}\n  decoded:  {}",
+                String::from_utf8_lossy(&encoded),
+                expected,
+                decoded
+            );
+        }
+        expected == decoded
+    }
+
+    /// RFC5424:
+    /// - Facilities and severities are only kept if both are set, otherwise the default priority (user.notice)
+    ///   is written
+    /// - Timestamps are written in UTC, so the UTC offset is read as UTC
+    /// - Structured data values are strings
+    /// - The full message is replaced by the raw record
+    /// - Null values are written without a value, and structured data without pairs as `[sd_id]`, that the
+    ///   decoder rejects: these records are not representable
+    #[cfg(feature = "rfc5424")]
+    fn expected_rfc5424(fields: &Fields) -> Record<'_> {
+        let mut record = fields.record();
+        if record.facility.is_none() || record.severity.is_none() {
+            record.facility = Some(Facility::User);
+            record.severity = Some(Severity::Notice);
+        }
+        record.utc_offset = Some(time::UtcOffset::UTC);
+        record.full_msg = None;
+        record.sd = fields.sd.as_ref().map(|sd| {
+            sd.iter()
+                .map(|(sd_id, pairs)| StructuredData {
+                    sd_id: Some(sd_id.as_str().into()),
+                    pairs: pairs
+                        .iter()
+                        .map(|(name, value)| {
+                            (name.as_str().into(), SDValue::String(value.text().into()))
+                        })
+                        .collect(),
+                })
+                .collect()
+        });
+        record
+    }
+
+    #[cfg(feature = "rfc5424")]
+    #[test]
+    fn test_round_trip_rfc5424() {
+        use crate::flowgger::decoder::RFC5424Decoder;
+        use crate::flowgger::encoder::RFC5424Encoder;
+
+        fn prop(mut fields: Fields) -> bool {
+            let config = Config::from_string("").unwrap();
+            if let Some(sd) = fields.sd.as_mut() {
+                for (_, pairs) in sd.iter_mut() {
+                    pairs.retain(|(_, value)| !matches!(value, Value::Null));
+                }
+                sd.retain(|(_, pairs)| !pairs.is_empty());
+            }
+            fields.sd = fields.sd.filter(|sd| !sd.is_empty());
+            round_trip(
+                &fields,
+                &RFC5424Encoder::new(&config),
+                &RFC5424Decoder::new(&config),
+                expected_rfc5424,
+                |mut record| {
+                    record.full_msg = None;
+                    record
+                },
+            )
+        }
+        QuickCheck::new()
+            .tests(ROUND_TRIP_COUNT)
+            .quickcheck(prop as fn(Fields) -> bool);
+    }
+
+    /// GELF:
+    /// - Facilities and message ids are lost
+    /// - The application name and the process id become the `_application_name` and `_process_id` fields
+    /// - A missing short message is written as "-"
+    /// - The pairs of every structured data are merged into additional fields, sorted by name, a later pair
+    ///   replacing an earlier one with the same name. Their SD-IDs are lost, only the last one that isn't the
+    ///   GELF SD-ID is kept, as the `_sd_id` field
+    /// - Negative integers are I64, positive ones U64
+    #[cfg(feature = "gelf")]
+    fn expected_gelf(fields: &Fields) -> Record<'_> {
+        use crate::flowgger::record::GELF_DEFAULT_SD_ID;
+        use std::collections::BTreeMap;
+
+        // Keyed by the name of the GELF field, that the decoder prefixes with '_' if it isn't already
+        let mut pairs = BTreeMap::new();
+        for (sd_id, sd_pairs) in fields.sd.iter().flatten() {
+            if sd_id != GELF_DEFAULT_SD_ID {
+                pairs.insert("sd_id".to_owned(), Value::String(sd_id.clone()));
+            }
+            for (name, value) in sd_pairs {
+                pairs.insert(name.clone(), value.clone());
+            }
+        }
+        if let Some(appname) = &fields.appname {
+            pairs.insert(
+                "application_name".to_owned(),
+                Value::String(appname.clone()),
+            );
+        }
+        if let Some(procid) = &fields.procid {
+            pairs.insert("process_id".to_owned(), Value::String(procid.clone()));
+        }
+        Record {
+            ts: fields.ts(),
+            utc_offset: None,
+            hostname: fields.hostname.as_str().into(),
+            facility: None,
+            severity: fields.severity,
+            appname: None,
+            procid: None,
+            msgid: None,
+            msg: Some(fields.msg.as_deref().unwrap_or("-").into()),
+            full_msg: fields.full_msg.as_deref().map(Cow::Borrowed),
+            sd: if pairs.is_empty() {
+                None
+            } else {
+                Some(vec![StructuredData {
+                    sd_id: Some(GELF_DEFAULT_SD_ID.into()),
+                    pairs: pairs
+                        .into_iter()
+                        .map(|(name, value)| match name.starts_with('_') {
+                            true => (name.into(), value.sd_value().into_owned()),
+                            false => (format!("_{}", name).into(), value.sd_value().into_owned()),
+                        })
+                        .collect(),
+                }])
+            },
+        }
+    }
+
+    #[cfg(feature = "gelf")]
+    #[test]
+    fn test_round_trip_gelf() {
+        use crate::flowgger::decoder::GelfDecoder;
+        use crate::flowgger::encoder::GelfEncoder;
+
+        fn prop(fields: Fields) -> bool {
+            let config = Config::from_string("").unwrap();
+            round_trip(
+                &fields,
+                &GelfEncoder::new(&config),
+                &GelfDecoder::new(&config),
+                expected_gelf,
+                |record| record,
+            )
+        }
+        QuickCheck::new()
+            .tests(ROUND_TRIP_COUNT)
+            .quickcheck(prop as fn(Fields) -> bool);
+    }
+
+    /// LTSV:
+    /// - The pairs of every structured data are merged into untyped fields, without SD-ID, and null values are
+    ///   empty strings
+    /// - Facilities, application names, process ids, message ids and full messages become the `_facility`,
+    ///   `_appname`, `_procid`, `_msgid` and `_full_message` fields, after the structured data
+    /// - The full message is replaced by the raw record
+    #[cfg(feature = "ltsv")]
+    fn expected_ltsv(fields: &Fields) -> Record<'_> {
+        let mut pairs: Vec<_> = fields
+            .sd
+            .iter()
+            .flatten()
+            .flat_map(|(_, pairs)| pairs)
+            .map(|(name, value)| (name.as_str().into(), SDValue::String(value.text().into())))
+            .collect();
+        let headers = [
+            ("_full_message", fields.full_msg.clone()),
+            ("_facility", fields.facility.map(|x| x.to_string())),
+            ("_appname", fields.appname.clone()),
+            ("_procid", fields.procid.clone()),
+            ("_msgid", fields.msgid.clone()),
+        ];
+        for (name, value) in headers {
+            if let Some(value) = value {
+                pairs.push((name.into(), SDValue::String(value.into())));
+            }
+        }
+        Record {
+            ts: fields.ts(),
+            utc_offset: None,
+            hostname: fields.hostname.as_str().into(),
+            facility: None,
+            severity: fields.severity,
+            appname: None,
+            procid: None,
+            msgid: None,
+            msg: fields.msg.as_deref().map(Cow::Borrowed),
+            full_msg: None,
+            sd: if pairs.is_empty() {
+                None
+            } else {
+                Some(vec![StructuredData { sd_id: None, pairs }])
+            },
+        }
+    }
+
+    #[cfg(feature = "ltsv")]
+    #[test]
+    fn test_round_trip_ltsv() {
+        use crate::flowgger::decoder::LTSVDecoder;
+        use crate::flowgger::encoder::LTSVEncoder;
+
+        fn prop(fields: Fields) -> bool {
+            let config = Config::from_string("").unwrap();
+            round_trip(
+                &fields,
+                &LTSVEncoder::new(&config),
+                &LTSVDecoder::new(&config),
+                expected_ltsv,
+                |mut record| {
+                    record.full_msg = None;
+                    record
+                },
+            )
+        }
+        QuickCheck::new()
+            .tests(ROUND_TRIP_COUNT)
+            .quickcheck(prop as fn(Fields) -> bool);
+    }
+
+    /// Cap'n Proto:
+    /// - Only the first structured data is written
+    #[cfg(feature = "capnp")]
+    fn expected_capnp(fields: &Fields) -> Record<'_> {
+        let mut record = fields.record();
+        if let Some(sd) = record.sd.as_mut() {
+            sd.truncate(1);
+        }
+        record
+    }
+
+    /// Encoder writing the `Debug` representation of the records, to get back the records the Cap'n Proto
+    /// splitter decodes
+    #[cfg(feature = "capnp")]
+    #[derive(Clone)]
+    struct DebugEncoder;
+
+    #[cfg(feature = "capnp")]
+    impl Encoder for DebugEncoder {
+        fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
+            Ok(debug(&record).into_bytes())
+        }
+    }
+
+    #[cfg(feature = "capnp")]
+    #[test]
+    fn test_round_trip_capnp() {
+        use crate::flowgger::decoder::InvalidDecoder;
+        use crate::flowgger::encoder::CapnpEncoder;
+        use crate::flowgger::splitter::{CapnpSplitter, Splitter};
+        use std::io::BufReader;
+
+        fn prop(fields: Fields) -> bool {
+            let config = Config::from_string("").unwrap();
+            let encoded = CapnpEncoder::new(&config).encode(fields.record()).unwrap();
+            let (tx, rx) = crate::flowgger::record_queue::unbounded();
+            CapnpSplitter.run(
+                BufReader::new(&encoded[..]),
+                tx,
+                Box::new(InvalidDecoder::new(&config)),
+                Box::new(DebugEncoder),
+            );
+            let decoded = String::from_utf8(rx.recv().unwrap()).unwrap();
+            let expected = debug(&expected_capnp(&fields));
+            if expected != decoded {
+                eprintln!(
+                    "Round trip mismatch\n  expected: {}\n  decoded:  {}",
+                    expected, decoded
+                );
+            }
+            expected == decoded
+        }
+        QuickCheck::new()
+            .tests(ROUND_TRIP_COUNT)
+            .quickcheck(prop as fn(Fields) -> bool);
+    }
+}
@@ -0,0 +1,188 @@
+//! Test doubles for applications embedding flowgger, compiled with the `test-util` feature, to unit test
+//! their configuration without sockets: `MemoryInput` replays frames, `CaptureOutput` keeps the encoded
+//! records, and `run` goes through the pipeline of a configuration between them.
+
+use super::config::Config;
+use super::decoder::{log_rejected, Decoder};
+use super::encoder::Encoder;
+use super::input::Input;
+use super::merger::Merger;
+use super::output::{notify, Notifier, Output};
+use super::utils::threads;
+use super::{check_settings, daemon};
+use crate::flowgger::record_queue::{RecordReceiver, RecordSender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// Input replaying frames, i.e. records already split the way the framing of the input would split them
+pub struct MemoryInput {
+    frames: Vec<Vec<u8>>,
+}
+
+impl MemoryInput {
+    pub fn new<T: Into<Vec<u8>>>(frames: Vec<T>) -> MemoryInput {
+        MemoryInput {
+            frames: frames.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl Input for MemoryInput {
+    fn accept(
+        &self,
+        tx: RecordSender,
+        decoder: Box<dyn Decoder + Send>,
+        encoder: Box<dyn Encoder + Send>,
+    ) {
+        daemon::listening();
+        for frame in &self.frames {
+            let reencoded = decoder
+                .decode_bytes(frame)
+                .and_then(|record| encoder.encode(record));
+            match reencoded {
+                Ok(reencoded) => tx.send(reencoded).unwrap(),
+                Err(e) => log_rejected(e, frame),
+            }
+        }
+    }
+}
+
+/// Output keeping the encoded records, framed with the output framing, and reporting them as delivered.
+/// Clones share the records.
+#[derive(Clone, Default)]
+pub struct CaptureOutput {
+    records: Arc<Mutex<Vec<Vec<u8>>>>,
+    handle: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl CaptureOutput {
+    pub fn new() -> CaptureOutput {
+        CaptureOutput::default()
+    }
+
+    /// The records received so far, in the order they were received
+    pub fn records(&self) -> Vec<Vec<u8>> {
+        self.records.lock().unwrap().clone()
+    }
+}
+
+impl Output for CaptureOutput {
+    fn start(
+        &self,
+        rx: RecordReceiver,
+        merger: Option<Box<dyn Merger>>,
+        notifier: Option<Arc<dyn Notifier>>,
+    ) {
+        let merger = merger.map(|merger| merger.clone_boxed());
+        let records = Arc::clone(&self.records);
+        let name = "flowgger-output-capture".to_owned();
+        let handle = threads::spawn(name, None, move || {
+            for mut bytes in rx.iter() {
+                if let Some(ref merger) = merger {
+                    merger.frame(&mut bytes);
+                }
+                notify(&notifier, std::slice::from_ref(&bytes), Ok(()));
+                records.lock().unwrap().push(bytes);
+            }
+        });
+        *self.handle.lock().unwrap() = Some(handle);
+    }
+
+    /// Wait until every record went through the pipeline, once the input stopped
+    fn stop(&self) {
+        if let Some(handle) = self.handle.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Run the pipeline of a configuration between `input` and `output` instead of the input and the output it
+/// sets, and return once every frame went through it.
+///
+/// Only the pipeline runs: the inputs of 'input.listeners', the admin socket, the statistics records and the
+/// privilege drop of the configuration are ignored.
+///
+/// # Parameters
+/// - `config`: the TOML of a configuration file
+/// - `input`: the frames to replay, decoded with 'input.format'
+/// - `output`: the output receiving the records encoded with 'output.format' and framed with 'output.framing'
+///
+/// # Panics
+/// Same as `flowgger::start`
+pub fn run(config: &str, input: MemoryInput, output: &CaptureOutput) {
+    let config = match Config::from_string(config) {
+        Ok(config) => config,
+        Err(e) => panic!("Unable to read the configuration: {}", e),
+    };
+    check_settings(&config);
+    super::run(
+        config,
+        Box::new(input),
+        Arc::new(output.clone()),
+        None,
+        false,
+    );
+}
+
+#[cfg(all(test, feature = "rfc5424", feature = "gelf"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_input_capture_output() {
+        let output = CaptureOutput::new();
+        run(
+            r#"[input]
+type = "stdin"
+format = "rfc5424"
+[output]
+type = "debug"
+format = "gelf"
+framing = "line"
+"#,
+            MemoryInput::new(vec![
+                "<23>1 2015-08-05T15:53:45Z example.org app 69 42 - first",
+                "not a record",
+                "<23>1 2015-08-05T15:53:46Z example.org app 69 42 - second",
+            ]),
+            &output,
+        );
+        let records = output.records();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|record| record.ends_with(b"}\n")));
+        let record = String::from_utf8_lossy(&records[1]);
+        assert!(record.contains(r#""short_message":"second""#));
+        assert!(record.contains(r#""host":"example.org""#));
+    }
+
+    #[test]
+    fn test_run_pipeline_only() {
+        let temp_dir = tempdir::TempDir::new("test_run_pipeline_only").unwrap();
+        let socket = temp_dir.path().join("admin");
+        let output = CaptureOutput::new();
+        run(
+            &format!(
+                r#"[input]
+type = "stdin"
+format = "rfc5424"
+[output]
+type = "debug"
+format = "gelf"
+[stats]
+interval = 1
+[admin]
+socket = {:?}
+[daemon]
+user = "flowgger-test-unknown-user"
+"#,
+                socket.to_str().unwrap()
+            ),
+            MemoryInput::new(vec![
+                "<23>1 2015-08-05T15:53:45Z example.org app 69 42 - first",
+            ]),
+            &output,
+        );
+        assert_eq!(output.records().len(), 1);
+        assert!(!socket.exists());
+    }
+}
@@ -1,41 +1,45 @@
+#[cfg(feature = "tls")]
+pub mod proxy;
+pub mod relp;
+#[cfg(feature = "tls")]
+pub mod resolver;
 pub mod rotating_file;
 #[cfg(test)]
 pub mod test_utils;
+pub mod threads;
+#[cfg(feature = "tls")]
+pub mod tls;
 
-#[cfg(feature = "gelf")]
-use std::time::{SystemTime, UNIX_EPOCH};
-use time::{OffsetDateTime, PrimitiveDateTime};
-
-pub struct PreciseTimestamp {
-    ts: f64,
-}
-
-impl PreciseTimestamp {
-    #[cfg(feature = "gelf")]
-    #[inline]
-    pub fn now() -> Self {
-        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-        PreciseTimestamp {
-            ts: now.as_secs() as f64 + f64::from(now.subsec_nanos()) / 1e9,
-        }
-    }
-
-    #[inline]
-    pub fn from_offset_datetime(tsd: OffsetDateTime) -> Self {
-        PreciseTimestamp {
-            ts: tsd.unix_timestamp_nanos() as f64 / 1e9,
-        }
+/// Parse a framing delimiter from the configuration: either one of the names "lf", "cr", "crlf", "nul" and "rs"
+/// (case insensitive), or the literal sequence of bytes to use
+pub fn parse_delimiter(value: &str) -> Result<Vec<u8>, &'static str> {
+    let delimiter = match value.to_ascii_lowercase().as_str() {
+        "lf" => b"\n".to_vec(),
+        "cr" => b"\r".to_vec(),
+        "crlf" => b"\r\n".to_vec(),
+        "nul" => vec![0],
+        "rs" => vec![0x1e],
+        _ => value.as_bytes().to_vec(),
+    };
+    if delimiter.is_empty() {
+        return Err("The delimiter cannot be empty");
     }
+    Ok(delimiter)
+}
 
-    #[inline]
-    pub fn from_primitive_datetime(tsd: PrimitiveDateTime) -> Self {
-        PreciseTimestamp {
-            ts: tsd.assume_utc().unix_timestamp_nanos() as f64 / 1e9,
-        }
+/// Name of the host flowgger runs on
+#[cfg(unix)]
+pub fn local_hostname() -> Option<String> {
+    let mut name = [0u8; 256];
+    if unsafe { libc::gethostname(name.as_mut_ptr() as *mut libc::c_char, name.len()) } != 0 {
+        return None;
     }
+    let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+    String::from_utf8(name[..len].to_vec()).ok()
+}
 
-    #[inline]
-    pub fn as_f64(&self) -> f64 {
-        self.ts
-    }
+/// Name of the host flowgger runs on
+#[cfg(not(unix))]
+pub fn local_hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
 }
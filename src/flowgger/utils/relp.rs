@@ -0,0 +1,186 @@
+//! Frames of RELP, the Reliable Event Logging Protocol of rsyslog: `TXNR SP COMMAND SP DATALEN [SP DATA] LF`
+
+use std::io::Read;
+use std::str;
+
+/// Transaction numbers are at most 9 digits long
+const RELP_MAX_TXNR_LENGTH: usize = 9;
+/// Commands are at most 32 characters long
+const RELP_MAX_COMMAND_LENGTH: usize = 32;
+/// Largest transaction number, after which numbering starts over from 1
+pub const RELP_MAX_TXNR: u32 = 999_999_999;
+/// Name, version and URL of flowgger, sent in the offers
+pub const RELP_SOFTWARE: &str = concat!(
+    "flowgger,",
+    env!("CARGO_PKG_VERSION"),
+    ",https://github.com/awslabs/flowgger"
+);
+
+pub struct Frame {
+    pub txnr: u32,
+    pub command: String,
+    pub data: Vec<u8>,
+}
+
+/// Read a frame, with data of up to `max_frame_size` bytes. `reader` should be buffered, the header being
+/// read byte by byte.
+///
+/// # Returns
+/// The next frame, or `None` if the connection was closed between two frames
+pub fn read_frame<R: Read>(
+    reader: &mut R,
+    max_frame_size: usize,
+) -> Result<Option<Frame>, &'static str> {
+    let txnr = match read_token(reader, RELP_MAX_TXNR_LENGTH)? {
+        None => return Ok(None),
+        Some((txnr, b' ')) => parse_number(&txnr).ok_or("Invalid RELP transaction number")?,
+        Some(_) => return Err("Missing RELP command"),
+    } as u32;
+    let command = match read_token(reader, RELP_MAX_COMMAND_LENGTH)? {
+        Some((command, b' ')) => String::from_utf8(command).or(Err("Invalid RELP command"))?,
+        _ => return Err("Missing RELP data length"),
+    };
+    let (datalen, sep) = match read_token(reader, RELP_MAX_TXNR_LENGTH)? {
+        Some((datalen, sep)) => (
+            parse_number(&datalen).ok_or("Invalid RELP data length")?,
+            sep,
+        ),
+        None => return Err("Truncated RELP frame"),
+    };
+    if datalen > max_frame_size {
+        return Err("RELP frame too large");
+    }
+    let mut data = vec![0; datalen];
+    match sep {
+        b'\n' if datalen == 0 => {}
+        b' ' => {
+            reader
+                .read_exact(&mut data)
+                .or(Err("Truncated RELP frame"))?;
+            let mut trailer = [0u8; 1];
+            reader
+                .read_exact(&mut trailer)
+                .or(Err("Truncated RELP frame"))?;
+            if trailer[0] != b'\n' {
+                return Err("Missing RELP frame trailer");
+            }
+        }
+        _ => return Err("Missing RELP data"),
+    }
+    Ok(Some(Frame {
+        txnr,
+        command,
+        data,
+    }))
+}
+
+/// Serialize a frame
+pub fn encode_frame(txnr: u32, command: &str, data: &[u8]) -> Vec<u8> {
+    let mut frame = format!("{} {} {}", txnr, command, data.len()).into_bytes();
+    if !data.is_empty() {
+        frame.push(b' ');
+        frame.extend_from_slice(data);
+    }
+    frame.push(b'\n');
+    frame
+}
+
+/// Read up to `max_len` bytes, until a space or a line feed
+///
+/// # Returns
+/// The token and the separator that ended it, or `None` if the connection was closed before the token
+fn read_token<R: Read>(
+    reader: &mut R,
+    max_len: usize,
+) -> Result<Option<(Vec<u8>, u8)>, &'static str> {
+    let mut token = Vec::with_capacity(max_len);
+    loop {
+        let mut c = [0u8; 1];
+        match reader.read(&mut c) {
+            Ok(0) if token.is_empty() => return Ok(None),
+            Ok(0) => return Err("Truncated RELP frame"),
+            Ok(_) => {}
+            Err(_) => return Err("Connection closed"),
+        }
+        match c[0] {
+            // Line feeds between frames are tolerated, as some peers send them
+            b'\n' if token.is_empty() => continue,
+            sep @ (b' ' | b'\n') => return Ok(Some((token, sep))),
+            _ if token.len() >= max_len => return Err("Invalid RELP frame header"),
+            c => token.push(c),
+        }
+    }
+}
+
+fn parse_number(digits: &[u8]) -> Option<usize> {
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    str::from_utf8(digits).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn read(frames: &[u8]) -> Result<Option<Frame>, &'static str> {
+        read_frame(&mut Cursor::new(frames), 16)
+    }
+
+    #[test]
+    fn test_relp_read_frame() {
+        let mut reader = Cursor::new(&b"1 open 5 hello\n\n2 close 0\n"[..]);
+        let frame = read_frame(&mut reader, 16).unwrap().unwrap();
+        assert_eq!(
+            (frame.txnr, frame.command.as_str(), &frame.data[..]),
+            (1, "open", &b"hello"[..])
+        );
+        let frame = read_frame(&mut reader, 16).unwrap().unwrap();
+        assert_eq!(
+            (frame.txnr, frame.command.as_str(), frame.data.len()),
+            (2, "close", 0)
+        );
+        assert!(read_frame(&mut reader, 16).unwrap().is_none());
+
+        // The data can contain line feeds
+        let frame = read(b"999999999 syslog 3 a\nb\n").unwrap().unwrap();
+        assert_eq!((frame.txnr, &frame.data[..]), (RELP_MAX_TXNR, &b"a\nb"[..]));
+    }
+
+    #[test]
+    fn test_relp_read_frame_invalid() {
+        assert_eq!(
+            read(b"x open 0\n").err(),
+            Some("Invalid RELP transaction number")
+        );
+        assert_eq!(
+            read(b"1234567890 open 0\n").err(),
+            Some("Invalid RELP frame header")
+        );
+        assert_eq!(read(b"1\n").err(), Some("Missing RELP command"));
+        assert_eq!(read(b"1 open\n").err(), Some("Missing RELP data length"));
+        assert_eq!(read(b"1 open -1\n").err(), Some("Invalid RELP data length"));
+        assert_eq!(read(b"1 open 17 ").err(), Some("RELP frame too large"));
+        assert_eq!(read(b"1 open 5\n").err(), Some("Missing RELP data"));
+        assert_eq!(read(b"1 open 5 hel").err(), Some("Truncated RELP frame"));
+        assert_eq!(
+            read(b"1 open 5 hello!").err(),
+            Some("Missing RELP frame trailer")
+        );
+        assert_eq!(read(b"1 op").err(), Some("Truncated RELP frame"));
+    }
+
+    #[test]
+    fn test_relp_encode_frame() {
+        assert_eq!(encode_frame(1, "rsp", b"200 OK"), b"1 rsp 6 200 OK\n");
+        assert_eq!(encode_frame(2, "close", b""), b"2 close 0\n");
+        let frame = read(&encode_frame(3, "syslog", b"record"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (frame.txnr, frame.command.as_str(), &frame.data[..]),
+            (3, "syslog", &b"record"[..])
+        );
+    }
+}
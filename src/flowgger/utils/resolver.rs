@@ -0,0 +1,229 @@
+use crate::flowgger::config::Config;
+use serde::Deserialize;
+use std::cmp;
+use std::collections::HashMap;
+use std::io::{self, stderr, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::num::NonZeroU64;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const DEFAULT_DNS_REFRESH: u64 = 300;
+const DEFAULT_IP_PREFERENCE: &str = "any";
+const DNS_RETRY_DELAY_INIT: Duration = Duration::from_secs(1);
+
+/// Address family to connect to when a hostname has both IPv4 and IPv6 addresses
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IpPreference {
+    Any,
+    Ipv4,
+    Ipv6,
+}
+
+impl IpPreference {
+    /// Keep the addresses of the preferred family, or all of them if there are none
+    fn filter(self, addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        let preferred: Vec<SocketAddr> = addrs
+            .iter()
+            .filter(|addr| match self {
+                IpPreference::Any => true,
+                IpPreference::Ipv4 => addr.is_ipv4(),
+                IpPreference::Ipv6 => addr.is_ipv6(),
+            })
+            .cloned()
+            .collect();
+        if preferred.is_empty() {
+            addrs
+        } else {
+            preferred
+        }
+    }
+}
+
+struct Entry {
+    addrs: Vec<SocketAddr>,
+    expires: Instant,
+    retry_delay: Duration,
+    /// Set while a thread resolves the hostname again, the other ones keep using the current addresses
+    refreshing: bool,
+}
+
+/// Settings of the resolver, in the `[output]` section
+#[derive(Deserialize)]
+pub struct ResolverSettings {
+    dns_refresh: Option<NonZeroU64>,
+    ip_preference: Option<String>,
+}
+
+/// Cache of the addresses of "host:port" strings, resolved again once expired.
+///
+/// The system resolver doesn't tell the TTLs of the records, so addresses are kept for a fixed time instead.
+/// Failed resolutions keep the previous addresses, and are retried after a delay doubling up to that time,
+/// so that a DNS outage neither breaks the connections nor floods the servers.
+///
+/// Lookups run without holding the cache lock, a slow DNS server only delays the thread resolving the
+/// expired hostname.
+pub struct Resolver {
+    refresh: Duration,
+    preference: IpPreference,
+    entries: Mutex<HashMap<String, Entry>>,
+    lookup: fn(&str) -> io::Result<Vec<SocketAddr>>,
+}
+
+impl Resolver {
+    /// # Parameters
+    /// - 'output.dns_refresh':   Optional. Seconds after which hostnames are resolved again. Default is 300.
+    /// - 'output.ip_preference': Optional. "any", "ipv4" or "ipv6". Addresses of the preferred family are
+    ///   used when a hostname has some, the other ones only otherwise. Default is "any".
+    pub fn new(config: &Config) -> Resolver {
+        let settings: ResolverSettings = config.settings("output");
+        let refresh = settings
+            .dns_refresh
+            .map_or(DEFAULT_DNS_REFRESH, NonZeroU64::get);
+        let preference = match settings
+            .ip_preference
+            .as_deref()
+            .unwrap_or(DEFAULT_IP_PREFERENCE)
+        {
+            "any" => IpPreference::Any,
+            "ipv4" => IpPreference::Ipv4,
+            "ipv6" => IpPreference::Ipv6,
+            _ => panic!(r#"output.ip_preference must be "any", "ipv4" or "ipv6""#),
+        };
+        Resolver {
+            refresh: Duration::from_secs(refresh),
+            preference,
+            entries: Mutex::new(HashMap::new()),
+            lookup: system_lookup,
+        }
+    }
+
+    /// # Returns
+    /// The addresses of `connect`, resolved again if they expired, or none if it never resolved
+    pub fn resolve(&self, connect: &str, now: Instant) -> Vec<SocketAddr> {
+        {
+            let mut entries = self.entries.lock().unwrap();
+            match entries.get_mut(connect) {
+                Some(entry) if now < entry.expires || entry.refreshing => {
+                    return entry.addrs.clone()
+                }
+                Some(entry) => entry.refreshing = true,
+                None => {}
+            }
+        }
+        let res = (self.lookup)(connect);
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.entry(connect.to_owned()).or_insert(Entry {
+            addrs: Vec::new(),
+            expires: now,
+            retry_delay: DNS_RETRY_DELAY_INIT,
+            refreshing: false,
+        });
+        entry.refreshing = false;
+        match res {
+            Ok(mut addrs) if !addrs.is_empty() => {
+                addrs.sort();
+                addrs.dedup();
+                entry.addrs = self.preference.filter(addrs);
+                entry.expires = now + self.refresh;
+                entry.retry_delay = DNS_RETRY_DELAY_INIT;
+            }
+            res => {
+                let e = res.err().map_or("no address".to_owned(), |e| e.to_string());
+                let _ = writeln!(stderr(), "Unable to resolve {}: {}", connect, e);
+                entry.expires = now + entry.retry_delay;
+                entry.retry_delay = cmp::min(entry.retry_delay * 2, self.refresh);
+            }
+        }
+        entry.addrs.clone()
+    }
+
+    #[cfg(test)]
+    fn expires(&self, connect: &str) -> Instant {
+        self.entries.lock().unwrap()[connect].expires
+    }
+}
+
+fn system_lookup(connect: &str) -> io::Result<Vec<SocketAddr>> {
+    connect.to_socket_addrs().map(|addrs| addrs.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_lookup(connect: &str) -> io::Result<Vec<SocketAddr>> {
+        match connect {
+            "dual:514" => Ok(vec![
+                "192.0.2.1:514".parse().unwrap(),
+                "[2001:db8::1]:514".parse().unwrap(),
+                "192.0.2.1:514".parse().unwrap(),
+            ]),
+            "v4:514" => Ok(vec!["192.0.2.2:514".parse().unwrap()]),
+            _ => Err(io::Error::other("not found")),
+        }
+    }
+
+    fn test_resolver(config: &str) -> Resolver {
+        let mut resolver = Resolver::new(&Config::from_string(config).unwrap());
+        resolver.lookup = test_lookup;
+        resolver
+    }
+
+    #[test]
+    fn test_resolver_preference() {
+        let now = Instant::now();
+        let resolver = test_resolver("[output]\n");
+        assert_eq!(resolver.resolve("dual:514", now).len(), 2);
+
+        let resolver = test_resolver("[output]\nip_preference = \"ipv6\"\n");
+        assert_eq!(
+            resolver.resolve("dual:514", now),
+            ["[2001:db8::1]:514".parse().unwrap()]
+        );
+        assert_eq!(
+            resolver.resolve("v4:514", now),
+            ["192.0.2.2:514".parse().unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_resolver_expiry() {
+        let now = Instant::now();
+        let mut resolver = test_resolver("[output]\ndns_refresh = 60\n");
+        assert!(resolver.resolve("unknown:514", now).is_empty());
+        assert_eq!(resolver.expires("unknown:514"), now + DNS_RETRY_DELAY_INIT);
+        resolver.resolve("unknown:514", now + Duration::from_secs(1));
+        assert_eq!(
+            resolver.expires("unknown:514"),
+            now + Duration::from_secs(3)
+        );
+
+        // Failures keep the previous addresses
+        assert_eq!(resolver.resolve("v4:514", now).len(), 1);
+        resolver.lookup = |_| Err(io::Error::other("timeout"));
+        assert_eq!(resolver.resolve("v4:514", now).len(), 1);
+        let later = now + Duration::from_secs(60);
+        assert_eq!(resolver.resolve("v4:514", later).len(), 1);
+        assert_eq!(resolver.expires("v4:514"), later + DNS_RETRY_DELAY_INIT);
+    }
+
+    #[test]
+    fn test_resolver_refreshing() {
+        let now = Instant::now();
+        let resolver = test_resolver("[output]\ndns_refresh = 60\n");
+        assert_eq!(resolver.resolve("v4:514", now).len(), 1);
+
+        // While a thread resolves an expired hostname, the other ones get the current addresses
+        let later = now + Duration::from_secs(60);
+        resolver
+            .entries
+            .lock()
+            .unwrap()
+            .get_mut("v4:514")
+            .unwrap()
+            .refreshing = true;
+        assert_eq!(resolver.resolve("v4:514", later).len(), 1);
+        assert_eq!(resolver.expires("v4:514"), now + Duration::from_secs(60));
+    }
+}
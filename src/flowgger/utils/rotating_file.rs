@@ -1,13 +1,216 @@
 extern crate time;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
 use std::ffi::OsStr;
 use std::fs::OpenOptions;
 use std::io::stderr;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::{
     fs::{self, File},
     io::{self, Write},
 };
-use time::{format_description, Duration, OffsetDateTime};
+use time::format_description::well_known::Rfc3339;
+use time::{format_description, Duration, OffsetDateTime, PrimitiveDateTime, Time};
+use time_tz::{Offset, OffsetDateTimeExt, OffsetResult, PrimitiveDateTimeExt, TimeZone, Tz};
+use toml::value::Table;
+use toml::Value;
+
+const MANIFEST_EXTENSION: &str = "manifest";
+
+/// Number of rotations requested from outside of the file output, i.e. with SIGUSR1 or the admin socket
+static ROTATION_REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+/// Ask every rotating file to rotate before their next write. Files without rotation triggers are closed
+/// and opened again, for external tools such as logrotate to move them away.
+#[cfg_attr(not(unix), allow(dead_code))]
+pub fn request_rotation() {
+    ROTATION_REQUESTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Compression of the files written by the file output. Every time a file is opened, a new gzip member or zstd
+/// frame is started, so that appending to an existing file keeps it valid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileCompression {
+    None,
+    Gzip,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl FileCompression {
+    /// Extension of the compressed files, kept last when rotated files are numbered
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            FileCompression::None => None,
+            FileCompression::Gzip => Some("gz"),
+            #[cfg(feature = "zstd")]
+            FileCompression::Zstd => Some("zst"),
+        }
+    }
+
+    /// Wrap a file so that the data written to it gets compressed. Compressed data are written out when the
+    /// writer is flushed, and the compressed stream is terminated when it is dropped.
+    pub fn writer(self, file: File) -> io::Result<Box<dyn Write + Send>> {
+        self.writer_from(Box::new(file))
+    }
+
+    fn writer_from(self, file: Box<dyn Write + Send>) -> io::Result<Box<dyn Write + Send>> {
+        Ok(match self {
+            FileCompression::None => file,
+            FileCompression::Gzip => Box::new(GzEncoder::new(file, flate2::Compression::default())),
+            #[cfg(feature = "zstd")]
+            FileCompression::Zstd => {
+                Box::new(zstd::stream::write::Encoder::new(file, 0)?.auto_finish())
+            }
+        })
+    }
+}
+
+/// Render a file name template, replacing %Y, %m, %d, %H, %M, %S and %j with the fields of `time`, and %% with %
+fn render_template(template: &str, time: OffsetDateTime) -> String {
+    let mut rendered = String::with_capacity(template.len() + 16);
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            rendered.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => rendered.push_str(&format!("{:04}", time.year())),
+            Some('m') => rendered.push_str(&format!("{:02}", time.month() as u8)),
+            Some('d') => rendered.push_str(&format!("{:02}", time.day())),
+            Some('H') => rendered.push_str(&format!("{:02}", time.hour())),
+            Some('M') => rendered.push_str(&format!("{:02}", time.minute())),
+            Some('S') => rendered.push_str(&format!("{:02}", time.second())),
+            Some('j') => rendered.push_str(&format!("{:03}", time.ordinal())),
+            Some('%') => rendered.push('%'),
+            Some(other) => {
+                rendered.push('%');
+                rendered.push(other);
+            }
+            None => rendered.push('%'),
+        }
+    }
+    rendered
+}
+
+/// Wall-clock boundaries the time rotation can be aligned to, instead of a period since the file creation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RotationCalendar {
+    Hourly,
+    Daily,
+}
+
+impl RotationCalendar {
+    /// Start and end of the period `now` belongs to, in the time zone `tz`
+    fn period(
+        self,
+        now: OffsetDateTime,
+        tz: Option<&'static Tz>,
+    ) -> (OffsetDateTime, OffsetDateTime) {
+        let local = tz.map_or(now, |tz| now.to_timezone(tz));
+        let (start, length) = match self {
+            RotationCalendar::Hourly => (
+                PrimitiveDateTime::new(
+                    local.date(),
+                    Time::from_hms(local.hour(), 0, 0).unwrap_or(Time::MIDNIGHT),
+                ),
+                Duration::HOUR,
+            ),
+            RotationCalendar::Daily => (
+                PrimitiveDateTime::new(local.date(), Time::MIDNIGHT),
+                Duration::DAY,
+            ),
+        };
+        let at = |time: PrimitiveDateTime| match tz {
+            Some(tz) => resolve_local(time, tz),
+            None => time.assume_utc(),
+        };
+        (at(start), at(start + length))
+    }
+}
+
+/// First instant at which the wall clock of the time zone `tz` shows `local`.
+/// A time repeated when the clocks go back resolves to its first occurrence, and a time skipped when
+/// they go forward resolves to the instant the clocks jumped, so that periods follow the zone's actual offsets.
+fn resolve_local(local: PrimitiveDateTime, tz: &'static Tz) -> OffsetDateTime {
+    match local.assume_timezone(tz) {
+        OffsetResult::Some(time) => time,
+        OffsetResult::Ambiguous(first, second) => first.min(second),
+        OffsetResult::None => {
+            // With the offset in effect before the jump, `local` is the instant the clocks jumped
+            let before = tz.get_offset_utc(&(local.assume_utc() - Duration::DAY));
+            local.assume_offset(before.to_utc()).to_timezone(tz)
+        }
+    }
+}
+
+/// What has been written to the current file, saved next to it in a manifest once it gets rotated
+#[derive(Default)]
+struct Manifest {
+    first_timestamp: Option<OffsetDateTime>,
+    last_timestamp: Option<OffsetDateTime>,
+    records: u64,
+    hasher: Sha256,
+}
+
+impl Manifest {
+    /// Manifest of the file at `path`, as a TOML table
+    fn to_toml(&self, path: &Path) -> String {
+        let mut table = Table::new();
+        table.insert("file".to_owned(), Value::String(file_name(path).to_owned()));
+        for (key, timestamp) in [
+            ("first_timestamp", self.first_timestamp),
+            ("last_timestamp", self.last_timestamp),
+        ] {
+            if let Some(timestamp) = timestamp.and_then(|ts| ts.format(&Rfc3339).ok()) {
+                table.insert(key.to_owned(), Value::String(timestamp));
+            }
+        }
+        table.insert("records".to_owned(), Value::Integer(self.records as i64));
+        let sha256: String = self
+            .hasher
+            .clone()
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        table.insert("sha256".to_owned(), Value::String(sha256));
+        Value::Table(table).to_string()
+    }
+}
+
+/// File hashing the data as they are written to the disk, after compression
+struct ManifestFile {
+    file: File,
+    manifest: Arc<Mutex<Manifest>>,
+}
+
+impl Write for ManifestFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.manifest.lock().unwrap().hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn file_name(path: &Path) -> &str {
+    path.file_name().and_then(OsStr::to_str).unwrap_or_default()
+}
+
+/// Path of the manifest of a file: 'file.log' -> 'file.log.manifest'
+fn manifest_path(path: &Path) -> PathBuf {
+    let mut manifest_path = path.as_os_str().to_owned();
+    manifest_path.push(".");
+    manifest_path.push(MANIFEST_EXTENSION);
+    PathBuf::from(manifest_path)
+}
 
 /// Writer providing a file rotating feature when a file reaches the configured size
 pub struct RotatingFile {
@@ -16,10 +219,23 @@ pub struct RotatingFile {
     max_time: u32,
     max_files: i32,
     time_format: String,
+    compression: FileCompression,
+    write_manifest: bool,
 
-    current_file: Option<File>,
+    current_file: Option<Box<dyn Write + Send>>,
+    /// Handle on the current file, to sync it once the data written to `current_file` have been flushed
+    current_handle: Option<File>,
+    current_path: PathBuf,
     current_size: usize,
+    current_manifest: Option<Arc<Mutex<Manifest>>>,
+    /// Timestamp of the record contained in the next write, for the manifest
+    record_timestamp: Option<OffsetDateTime>,
     next_rotation_time: Option<OffsetDateTime>,
+    calendar: Option<RotationCalendar>,
+    timezone: Option<&'static Tz>,
+    name_template: bool,
+    rotation_requests: &'static AtomicU64,
+    handled_rotation_requests: u64,
 
     #[cfg(test)]
     now_time_mock: OffsetDateTime,
@@ -37,7 +253,7 @@ impl RotatingFile {
     /// Rotation occurs when a write is requested to an expired file. The file is then closed and a new one is created.
     /// # Notes:
     /// - the max_files has currently no impact on time trigger rotation, leading to an uncontrolled number of files being
-    /// generated if not externally purged.
+    ///   generated if not externally purged.
     /// - files are only being rotated on write operation. Empty files will not be created every x minutes if there was no write requests.
     ///
     /// A size trigger can be configured in addition to the time trigger (max_time >0 and max_size > 0).
@@ -51,7 +267,7 @@ impl RotatingFile {
     /// Rotation occurs when the data to write in the current file is going to reach the specified limit.
     /// During a rotation:
     /// - each existing file is renamed 'basename.{n}' -> 'basename.{n+1}', starting with n = maxfiles -2 up to 0.
-    ///     The oldest 'basename.{maxfiles -1}' is therefore overwritten and the old data are lost
+    ///   The oldest 'basename.{maxfiles -1}' is therefore overwritten and the old data are lost
     /// - the current file is renamed 'basename' -> 'basename.0'
     /// - A new file 'basename' is created
     ///
@@ -60,13 +276,13 @@ impl RotatingFile {
     /// - 'basename': Original file name and path.
     /// - 'max_size': Target size for rotating files. If a write will reach that limit, the file is rotated first.
     /// - 'max_time': Period in minutes for rotating files. If a write is done more than max_time after the file
-    ///             creation, the file is rotated.
+    ///   creation, the file is rotated.
     /// - 'max_files': Count of files that can be created in addition to the original file,
-    ///             named 'basename.N' where'basename' is always the file being currently written
-    ///             - 'basename.0' is always the most recent file that has been rotated
-    ///             - 'basename.N' is always the oldest file
+    ///   named 'basename.N' where'basename' is always the file being currently written
+    ///   - 'basename.0' is always the most recent file that has been rotated
+    ///   - 'basename.N' is always the oldest file
     /// - time_format: Format of the timestamp to use when time rotation is enabled. Must conform to
-    ///             https://docs.rs/time/0.3.7/time/format_description/index.html
+    ///   https://docs.rs/time/0.3.7/time/format_description/index.html
     ///
     /// # Example
     /// From parameters:
@@ -104,15 +320,66 @@ impl RotatingFile {
             max_time,
             max_files,
             time_format: time_format.to_string(),
+            compression: FileCompression::None,
+            write_manifest: false,
             current_file: None,
+            current_handle: None,
+            current_path: PathBuf::new(),
             current_size: 0,
+            current_manifest: None,
+            record_timestamp: None,
             next_rotation_time: None,
+            calendar: None,
+            timezone: None,
+            name_template: false,
+            rotation_requests: &ROTATION_REQUESTS,
+            handled_rotation_requests: ROTATION_REQUESTS.load(Ordering::Relaxed),
 
             #[cfg(test)]
             now_time_mock: OffsetDateTime::now_utc(),
         }
     }
 
+    /// Compress the files. Sizes are counted before compression.
+    pub fn with_compression(mut self, compression: FileCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Write a manifest next to every rotated file, i.e. 'file.log.0.manifest', with the file name, the
+    /// timestamps of the oldest and newest records, the number of records and the SHA-256 of the file.
+    /// Every write is counted as a record, the file must not be buffered. Record timestamps are given with
+    /// `set_record_timestamp()`.
+    pub fn with_manifest(mut self, write_manifest: bool) -> Self {
+        self.write_manifest = write_manifest;
+        self
+    }
+
+    /// Set the timestamp of the record that the next write contains, for the manifest
+    pub fn set_record_timestamp(&mut self, ts: Option<OffsetDateTime>) {
+        self.record_timestamp = ts;
+    }
+
+    /// Rotate the files at every hour or day boundary, instead of every 'max_time' minutes. The files are named
+    /// after the start of their period, in the time zone `timezone`, or UTC if it is not set.
+    pub fn with_calendar(
+        mut self,
+        calendar: Option<RotationCalendar>,
+        timezone: Option<&'static Tz>,
+    ) -> Self {
+        self.calendar = calendar;
+        self.timezone = timezone;
+        self
+    }
+
+    /// Use the base path as a template of the whole file name, i.e. "logs/app-%Y/%m/%d/%H.log", rendered with
+    /// the start of the period of the file instead of appending a timestamp to the stem. Missing directories
+    /// are created. Not compatible with size triggers without a time trigger, that rename files.
+    pub fn with_name_template(mut self, name_template: bool) -> Self {
+        self.name_template = name_template;
+        self
+    }
+
     fn get_current_date_time(&self) -> OffsetDateTime {
         #[cfg(test)]
         return self.now_time_mock;
@@ -124,15 +391,33 @@ impl RotatingFile {
     /// Build an output file name appending the current timestamp, and compute the file expiration time
     fn build_timestamped_filename(&mut self) -> Result<PathBuf, &'static str> {
         let current_time = self.get_current_date_time();
-        self.next_rotation_time = Some(current_time + Duration::minutes(i64::from(self.max_time)));
+        let current_time = match self.calendar {
+            Some(calendar) => {
+                let (start, end) = calendar.period(current_time, self.timezone);
+                self.next_rotation_time = Some(end);
+                start
+            }
+            None => {
+                self.next_rotation_time =
+                    Some(current_time + Duration::minutes(i64::from(self.max_time)));
+                self.timezone
+                    .map_or(current_time, |tz| current_time.to_timezone(tz))
+            }
+        };
 
-        let format_item = format_description::parse(&self.time_format).unwrap();
+        if self.name_template {
+            return Ok(PathBuf::from(render_template(
+                &self.basename.to_string_lossy(),
+                current_time,
+            )));
+        }
+        let format_item = format_description::parse_borrowed::<1>(&self.time_format).unwrap();
         let dt_str = match current_time.format(&format_item) {
             Ok(date) => date,
             Err(_) => return Err("Failed to parse date"),
         };
         let mut new_file = self.basename.clone();
-        new_file.set_file_name(&format!(
+        new_file.set_file_name(format!(
             "{}-{}.{}",
             self.basename
                 .file_stem()
@@ -164,22 +449,95 @@ impl RotatingFile {
         // Either use a timstamped filename or the one provided
         let filepath = if self.is_time_triggered() {
             self.build_timestamped_filename().clone().unwrap()
+        } else if self.name_template {
+            let now = self.get_current_date_time();
+            let now = self.timezone.map_or(now, |tz| now.to_timezone(tz));
+            PathBuf::from(render_template(&self.basename.to_string_lossy(), now))
         } else {
             self.basename.clone()
         };
+        if self.name_template {
+            if let Some(dir) = filepath.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(dir)?;
+            }
+        }
 
-        match RotatingFile::open_file(filepath) {
+        match RotatingFile::open_file(&filepath) {
             Ok(file) => {
                 let metadata = file.metadata()?;
                 self.current_size = metadata.len() as usize;
+                self.current_handle = Some(file.try_clone()?);
 
-                self.current_file = Some(file);
+                self.current_file = Some(if self.write_manifest {
+                    // Data already in the file are part of it, even though their records are not counted
+                    let mut manifest = Manifest::default();
+                    if self.current_size > 0 {
+                        manifest.hasher.update(fs::read(&filepath)?);
+                    }
+                    let manifest = Arc::new(Mutex::new(manifest));
+                    self.current_manifest = Some(manifest.clone());
+                    self.compression
+                        .writer_from(Box::new(ManifestFile { file, manifest }))?
+                } else {
+                    self.compression.writer(file)?
+                });
+                self.current_path = filepath;
                 Ok(())
             }
             Err(e) => Err(e),
         }
     }
 
+    /// Flush the data written so far, and wait until they have been stored on disk
+    pub fn sync_data(&mut self) -> io::Result<()> {
+        self.flush()?;
+        match &self.current_handle {
+            Some(file) => file.sync_data(),
+            None => Ok(()),
+        }
+    }
+
+    /// Close the current file, and write its manifest, now under the name `path`
+    fn close(&mut self, path: &Path) {
+        // Make sure that file is not gonna be used anymore, and that the compressed stream has been terminated
+        let _ = self.current_file.take();
+        let _ = self.current_handle.take();
+        let manifest = match self.current_manifest.take() {
+            None => return,
+            Some(manifest) => manifest,
+        };
+        let manifest = manifest.lock().unwrap().to_toml(path);
+        if let Err(e) = fs::write(manifest_path(path), manifest) {
+            let _ = writeln!(
+                stderr(),
+                "Unable to write the manifest of {}: {}",
+                path.to_string_lossy(),
+                e
+            );
+        }
+    }
+
+    /// Rename the manifest of a file that has been renamed, or remove the manifest of `dest` that doesn't
+    /// describe it any more
+    fn rename_manifest(src: &Path, dest: &Path) {
+        let dest_manifest = manifest_path(dest);
+        let mut manifest = match fs::read_to_string(manifest_path(src))
+            .ok()
+            .and_then(|manifest| manifest.parse::<Value>().ok())
+        {
+            None => {
+                let _ = fs::remove_file(dest_manifest);
+                return;
+            }
+            Some(manifest) => manifest,
+        };
+        if let Some(table) = manifest.as_table_mut() {
+            table.insert("file".to_owned(), Value::String(file_name(dest).to_owned()));
+        }
+        let _ = fs::write(dest_manifest, manifest.to_string());
+        let _ = fs::remove_file(manifest_path(src));
+    }
+
     /// Static method to open a file
     ///
     /// # Parameters
@@ -203,32 +561,33 @@ impl RotatingFile {
     ///
     fn build_file_path(&self, file_num: i32) -> PathBuf {
         if file_num < 0 {
-            self.basename.clone()
-        } else {
-            let mut path = self.basename.clone();
-            path.set_extension(file_num.to_string());
-            path
+            return self.basename.clone();
         }
+        let mut path = self.basename.clone();
+        match self.compression.extension() {
+            // 'file.log.gz' -> 'file.N.gz'
+            Some(ext) if path.extension() == Some(OsStr::new(ext)) => {
+                path.set_extension("");
+                path.set_extension(format!("{}.{}", file_num, ext));
+            }
+            _ => {
+                path.set_extension(file_num.to_string());
+            }
+        }
+        path
     }
 
     /// Execute a log file rotation for size triggers
     /// Starting from the file n=(self.max_files -1):
     /// - each existing file is renamed 'basename.{n}' -> 'basename.{n+1}'
     /// - the current file is renamed 'basename' -> 'basename.0'
-    /// A new file 'basename' is created
+    ///   A new file 'basename' is created
     ///
     /// # Returns
     /// - 'Ok':   when the rotation has been done
     /// - 'Err':  when the new file could not be open
     ///
     fn rotate_size(&mut self) -> io::Result<()> {
-        let _ = writeln!(
-            stderr(),
-            "File {} reached size limit {}, rotating",
-            self.basename.to_string_lossy(),
-            self.max_size
-        );
-
         // Make sure that file is not gonna be used anymore
         let _ = self.current_file.take();
 
@@ -238,8 +597,14 @@ impl RotatingFile {
         for file_num in (0..self.max_files).rev() {
             src_pathbuf = self.build_file_path(file_num - 1);
             let _ = fs::rename(src_pathbuf.as_path(), dest_pathbuf.as_path());
+            if self.write_manifest && file_num > 0 {
+                RotatingFile::rename_manifest(&src_pathbuf, &dest_pathbuf);
+            }
             dest_pathbuf = src_pathbuf;
         }
+        if self.max_files > 0 {
+            self.close(&self.build_file_path(0));
+        }
 
         // Create new logfile, fail if we can't
         self.open()?;
@@ -257,16 +622,8 @@ impl RotatingFile {
     /// - 'Err':  when the new file could not be open
     ///
     fn rotate_time(&mut self) -> io::Result<()> {
-        let _ = writeln!(
-            stderr(),
-            "File {} reached time/size limit {}min/{}bytes, rotating",
-            self.basename.to_string_lossy(),
-            self.max_time,
-            self.max_size
-        );
-
         // Make sure that file is not gonna be used anymore
-        let _ = self.current_file.take();
+        self.close(&self.current_path.clone());
 
         // Create new logfile, fail if we can't
         self.open()?;
@@ -292,7 +649,7 @@ impl RotatingFile {
     /// - false:    The rotation is not configured to be time triggered
     ///
     pub fn is_time_triggered(&self) -> bool {
-        self.max_time > 0
+        self.max_time > 0 || self.calendar.is_some()
     }
 
     /// Indicates if the file rotation is triggered by a size trigger
@@ -302,7 +659,7 @@ impl RotatingFile {
     /// - false:    The rotation is not configured to be size triggered
     ///
     pub fn is_size_triggered(&self) -> bool {
-        (self.max_time == 0) && (self.max_size > 0)
+        !self.is_time_triggered() && (self.max_size > 0)
     }
 
     /// Indicates if the file rotation condition for time trigger are reached:
@@ -328,13 +685,47 @@ impl RotatingFile {
         (self.max_size > 0) && (self.current_size + bytes_to_write > self.max_size)
     }
 
-    /// Verify whether a rotation is needed based on the configured triggers, and rotate the files
+    /// Close the current file and open it again, i.e. after it has been moved away
+    fn reopen(&mut self) -> io::Result<()> {
+        self.close(&self.current_path.clone());
+        self.open()
+    }
+
+    /// Verify whether a rotation is needed based on the configured triggers or on a request, and rotate the files
     fn check_rotation_trigger(&mut self, bytes_to_write: usize) -> io::Result<()> {
-        if self.is_time_triggered() {
+        let rotation_requests = self.rotation_requests.load(Ordering::Relaxed);
+        if rotation_requests != self.handled_rotation_requests {
+            self.handled_rotation_requests = rotation_requests;
+            let _ = writeln!(
+                stderr(),
+                "Rotating {} on request",
+                self.basename.to_string_lossy()
+            );
+            if self.is_time_triggered() {
+                self.rotate_time()?;
+            } else if self.is_size_triggered() {
+                self.rotate_size()?;
+            } else {
+                self.reopen()?;
+            }
+        } else if self.is_time_triggered() {
             if self.is_rotation_time_reached() || self.is_rotation_size_reached(bytes_to_write) {
+                let _ = writeln!(
+                    stderr(),
+                    "File {} reached time/size limit {}min/{}bytes, rotating",
+                    self.basename.to_string_lossy(),
+                    self.max_time,
+                    self.max_size
+                );
                 self.rotate_time()?;
             }
         } else if self.is_size_triggered() && self.is_rotation_size_reached(bytes_to_write) {
+            let _ = writeln!(
+                stderr(),
+                "File {} reached size limit {}, rotating",
+                self.basename.to_string_lossy(),
+                self.max_size
+            );
             self.rotate_size()?;
         }
         Ok(())
@@ -353,9 +744,21 @@ impl Write for RotatingFile {
         // Rotate the file if needed
         self.check_rotation_trigger(written)?;
 
+        let record_timestamp = self.record_timestamp.take();
+        if let Some(manifest) = &self.current_manifest {
+            let mut manifest = manifest.lock().unwrap();
+            if let Some(ts) = record_timestamp {
+                manifest.first_timestamp =
+                    Some(manifest.first_timestamp.map_or(ts, |first| first.min(ts)));
+                manifest.last_timestamp =
+                    Some(manifest.last_timestamp.map_or(ts, |last| last.max(ts)));
+            }
+            manifest.records += 1;
+        }
+
         // Write the whole data block
         self.current_size += written;
-        if let Some(Err(err)) = self.current_file.as_mut().map(|file| file.write(buf)) {
+        if let Some(Err(err)) = self.current_file.as_mut().map(|file| file.write_all(buf)) {
             return Err(err);
         }
 
@@ -377,14 +780,12 @@ mod tests {
     extern crate tempdir;
     use crate::flowgger::utils::test_utils::rfc_test_utils::new_date_time;
     use tempdir::TempDir;
-    use time::Month;
+    use time::{Month, UtcOffset};
 
     fn build_pattern_list(count: u32, length: usize) -> Vec<String> {
         let mut pattern_list = Vec::new();
         for i in 0..count {
-            let mut pattern_str = std::iter::repeat(i.to_string())
-                .take(length)
-                .collect::<String>();
+            let mut pattern_str = std::iter::repeat_n(i.to_string(), length).collect::<String>();
             pattern_str.push('\n');
             pattern_list.push(pattern_str);
         }
@@ -534,6 +935,318 @@ mod tests {
         Ok(())
     }
 
+    fn read_compressed(path: &Path, compression: FileCompression) -> String {
+        let file = File::open(path).unwrap();
+        let mut decoder: Box<dyn io::Read> = match compression {
+            FileCompression::None => Box::new(file),
+            FileCompression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(file)),
+            #[cfg(feature = "zstd")]
+            FileCompression::Zstd => Box::new(zstd::stream::read::Decoder::new(file).unwrap()),
+        };
+        let mut contents = String::new();
+        decoder.read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    fn check_rotation_files_compressed(compression: FileCompression) -> Result<(), io::Error> {
+        let ext = compression.extension().unwrap();
+        let tmp_dir = TempDir::new("test_rotation_files_compressed")?;
+        let file_base = tmp_dir.path().join(format!("test_log.log.{}", ext));
+        let file_rotated = tmp_dir.path().join(format!("test_log.0.{}", ext));
+
+        let test_patterns = build_pattern_list(3, 6);
+
+        let mut rotating_file =
+            RotatingFile::new(&file_base, 16, 0, 2, "").with_compression(compression);
+        assert!(rotating_file.open().is_ok());
+        let _ = rotating_file.write(test_patterns[0].as_bytes());
+        let _ = rotating_file.write(test_patterns[1].as_bytes());
+
+        // The rotated file is complete, and keeps the extension
+        let _ = rotating_file.write(test_patterns[2].as_bytes());
+        assert_eq!(
+            read_compressed(&file_rotated, compression),
+            format!("{}{}", test_patterns[0], test_patterns[1])
+        );
+        drop(rotating_file);
+
+        // Appending to an existing file starts a new stream
+        let mut rotating_file =
+            RotatingFile::new(&file_base, 0, 0, 2, "").with_compression(compression);
+        assert!(rotating_file.open().is_ok());
+        let _ = rotating_file.write(test_patterns[0].as_bytes());
+        drop(rotating_file);
+        assert_eq!(
+            read_compressed(&file_base, compression),
+            format!("{}{}", test_patterns[2], test_patterns[0])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotation_files_gzip() -> Result<(), io::Error> {
+        check_rotation_files_compressed(FileCompression::Gzip)
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_rotation_files_zstd() -> Result<(), io::Error> {
+        check_rotation_files_compressed(FileCompression::Zstd)
+    }
+
+    #[test]
+    fn test_rotation_files_manifest() -> Result<(), io::Error> {
+        let tmp_dir = TempDir::new("test_rotation_files_manifest")?;
+        let file_base = tmp_dir.path().join("test_log.log");
+        let file_rotated = tmp_dir.path().join("test_log.0");
+        let file_rotated2 = tmp_dir.path().join("test_log.1");
+
+        let test_patterns = build_pattern_list(5, 6);
+
+        let mut rotating_file = RotatingFile::new(&file_base, 16, 0, 2, "").with_manifest(true);
+        assert!(rotating_file.open().is_ok());
+        // The manifest has the range of the record timestamps, in whatever order they are written
+        rotating_file.set_record_timestamp(Some(new_date_time(
+            2015,
+            Month::August,
+            6,
+            11,
+            16,
+            0,
+            0,
+        )));
+        let _ = rotating_file.write(test_patterns[0].as_bytes());
+        rotating_file.set_record_timestamp(Some(new_date_time(
+            2015,
+            Month::August,
+            6,
+            11,
+            15,
+            24,
+            0,
+        )));
+        let _ = rotating_file.write(test_patterns[1].as_bytes());
+        let _ = rotating_file.write(test_patterns[2].as_bytes());
+
+        let manifest = fs::read_to_string(manifest_path(&file_rotated))?
+            .parse::<Value>()
+            .unwrap();
+        assert_eq!(manifest["file"].as_str(), Some("test_log.0"));
+        assert_eq!(
+            manifest["first_timestamp"].as_str(),
+            Some("2015-08-06T11:15:24Z")
+        );
+        assert_eq!(
+            manifest["last_timestamp"].as_str(),
+            Some("2015-08-06T11:16:00Z")
+        );
+        assert_eq!(manifest["records"].as_integer(), Some(2));
+        let sha256: String = Sha256::digest(fs::read(&file_rotated)?)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        assert_eq!(manifest["sha256"].as_str(), Some(sha256.as_str()));
+
+        // The manifest follows its file when it gets shifted
+        let _ = rotating_file.write(test_patterns[3].as_bytes());
+        let _ = rotating_file.write(test_patterns[4].as_bytes());
+        let manifest = fs::read_to_string(manifest_path(&file_rotated2))?
+            .parse::<Value>()
+            .unwrap();
+        assert_eq!(manifest["file"].as_str(), Some("test_log.1"));
+        assert_eq!(manifest["records"].as_integer(), Some(2));
+        let manifest = fs::read_to_string(manifest_path(&file_rotated))?
+            .parse::<Value>()
+            .unwrap();
+        assert_eq!(manifest["records"].as_integer(), Some(2));
+        assert!(fs::metadata(manifest_path(&file_base)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotation_calendar() -> Result<(), io::Error> {
+        let paris = time_tz::timezones::get_by_name("Europe/Paris");
+        let now = new_date_time(2015, Month::January, 6, 23, 15, 24, 637);
+        let (start, end) = RotationCalendar::Hourly.period(now, None);
+        assert_eq!(start, new_date_time(2015, Month::January, 6, 23, 0, 0, 0));
+        assert_eq!(end, new_date_time(2015, Month::January, 7, 0, 0, 0, 0));
+        let (start, end) = RotationCalendar::Daily.period(now, paris);
+        assert_eq!(
+            (start.day(), start.hour(), start.offset().whole_hours()),
+            (7, 0, 1)
+        );
+        assert_eq!(end, new_date_time(2015, Month::January, 7, 23, 0, 0, 0));
+
+        // Files are named after the start of their period
+        let tmp_dir = TempDir::new("test_rotation_calendar")?;
+        let file_base = tmp_dir.path().join("test_log.log");
+        let mut rotating_file = RotatingFile::new(&file_base, 0, 0, 10, "[year][month][day]")
+            .with_calendar(Some(RotationCalendar::Daily), paris);
+        rotating_file.now_time_mock = now;
+        assert!(rotating_file.is_time_triggered());
+        assert!(rotating_file.open().is_ok());
+        assert_eq!(
+            rotating_file.current_path,
+            tmp_dir.path().join("test_log-20150107.log")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotation_calendar_dst() {
+        let paris = time_tz::timezones::get_by_name("Europe/Paris");
+        let utc = |month, day, hour, min| new_date_time(2015, month, day, hour, min, 0, 0);
+        let period = |calendar: RotationCalendar, now, tz| {
+            let (start, end) = calendar.period(now, tz);
+            (
+                start.to_offset(UtcOffset::UTC),
+                end.to_offset(UtcOffset::UTC),
+            )
+        };
+
+        // The clocks go forward from 02:00 to 03:00 on March 29th, so the day lasts 23 hours
+        assert_eq!(
+            period(RotationCalendar::Daily, utc(Month::March, 29, 12, 0), paris),
+            (utc(Month::March, 28, 23, 0), utc(Month::March, 29, 22, 0))
+        );
+        assert_eq!(
+            period(
+                RotationCalendar::Hourly,
+                utc(Month::March, 29, 0, 30),
+                paris
+            ),
+            (utc(Month::March, 29, 0, 0), utc(Month::March, 29, 1, 0))
+        );
+        assert_eq!(
+            period(
+                RotationCalendar::Hourly,
+                utc(Month::March, 29, 1, 30),
+                paris
+            ),
+            (utc(Month::March, 29, 1, 0), utc(Month::March, 29, 2, 0))
+        );
+
+        // They go back from 03:00 to 02:00 on October 25th, so the day lasts 25 hours, and both
+        // 02:00 hours go to the same file
+        assert_eq!(
+            period(
+                RotationCalendar::Daily,
+                utc(Month::October, 25, 12, 0),
+                paris
+            ),
+            (
+                utc(Month::October, 24, 22, 0),
+                utc(Month::October, 25, 23, 0)
+            )
+        );
+        for now in [
+            utc(Month::October, 25, 0, 30),
+            utc(Month::October, 25, 1, 30),
+        ] {
+            assert_eq!(
+                period(RotationCalendar::Hourly, now, paris),
+                (utc(Month::October, 25, 0, 0), utc(Month::October, 25, 2, 0))
+            );
+        }
+
+        // In São Paulo, the clocks went forward at midnight on November 4th, 2018, so that day started at 01:00
+        let sao_paulo = time_tz::timezones::get_by_name("America/Sao_Paulo");
+        let now = new_date_time(2018, Month::November, 4, 12, 0, 0, 0);
+        let (start, end) = RotationCalendar::Daily.period(now, sao_paulo);
+        assert_eq!(start, new_date_time(2018, Month::November, 4, 3, 0, 0, 0));
+        assert_eq!((start.hour(), start.offset().whole_hours()), (1, -2));
+        assert_eq!(end, new_date_time(2018, Month::November, 5, 2, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_rotation_name_template() -> Result<(), io::Error> {
+        let now = new_date_time(2015, Month::August, 6, 11, 15, 24, 637);
+        assert_eq!(
+            render_template("app-%Y/%m/%d/%H%M%S-%j-100%%-%q.log", now),
+            "app-2015/08/06/111524-218-100%-%q.log"
+        );
+
+        let tmp_dir = TempDir::new("test_rotation_name_template")?;
+        let template = tmp_dir.path().join("app-%Y/%m/%d/%H.log");
+        let mut rotating_file = RotatingFile::new(&template, 0, 0, 10, "")
+            .with_calendar(Some(RotationCalendar::Hourly), None)
+            .with_name_template(true);
+        rotating_file.now_time_mock = now;
+        assert!(rotating_file.open().is_ok());
+        rotating_file.write_all(b"first\n")?;
+        rotating_file.now_time_mock = now + Duration::HOUR;
+        rotating_file.write_all(b"second\n")?;
+        assert_eq!(
+            fs::read_to_string(tmp_dir.path().join("app-2015/08/06/11.log"))?,
+            "first\n"
+        );
+        assert_eq!(
+            fs::read_to_string(tmp_dir.path().join("app-2015/08/06/12.log"))?,
+            "second\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotation_on_request() -> Result<(), io::Error> {
+        static TEST_ROTATION_REQUESTS: AtomicU64 = AtomicU64::new(0);
+        let tmp_dir = TempDir::new("test_rotation_on_request")?;
+
+        // Size triggered files are shifted
+        let file_base = tmp_dir.path().join("test_log.log");
+        let mut rotating_file = RotatingFile::new(&file_base, 1024, 0, 2, "");
+        rotating_file.rotation_requests = &TEST_ROTATION_REQUESTS;
+        assert!(rotating_file.open().is_ok());
+        rotating_file.write_all(b"first\n")?;
+        TEST_ROTATION_REQUESTS.fetch_add(1, Ordering::Relaxed);
+        rotating_file.write_all(b"second\n")?;
+        rotating_file.write_all(b"third\n")?;
+        assert_eq!(
+            fs::read_to_string(tmp_dir.path().join("test_log.0"))?,
+            "first\n"
+        );
+        assert_eq!(fs::read_to_string(&file_base)?, "second\nthird\n");
+
+        // Files without triggers are opened again, once moved away
+        let file_base = tmp_dir.path().join("plain.log");
+        let moved = tmp_dir.path().join("plain.log.1");
+        let mut rotating_file = RotatingFile::new(&file_base, 0, 0, 2, "");
+        rotating_file.rotation_requests = &TEST_ROTATION_REQUESTS;
+        rotating_file.handled_rotation_requests = TEST_ROTATION_REQUESTS.load(Ordering::Relaxed);
+        assert!(rotating_file.open().is_ok());
+        rotating_file.write_all(b"first\n")?;
+        fs::rename(&file_base, &moved)?;
+        TEST_ROTATION_REQUESTS.fetch_add(1, Ordering::Relaxed);
+        rotating_file.write_all(b"second\n")?;
+        assert_eq!(fs::read_to_string(&moved)?, "first\n");
+        assert_eq!(fs::read_to_string(&file_base)?, "second\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_data() -> Result<(), io::Error> {
+        let tmp_dir = TempDir::new("test_sync_data")?;
+        let file_base = tmp_dir.path().join("test_log.log");
+        let mut rotating_file = RotatingFile::new(&file_base, 8, 0, 2, "");
+        assert!(rotating_file.open().is_ok());
+        rotating_file.write_all(b"first\n")?;
+        rotating_file.sync_data()?;
+        assert_eq!(fs::read_to_string(&file_base)?, "first\n");
+
+        // Once rotated, the new file is the one synced
+        rotating_file.write_all(b"second\n")?;
+        rotating_file.sync_data()?;
+        let handle = rotating_file.current_handle.as_ref().unwrap();
+        assert_eq!(handle.metadata()?.len(), 7);
+
+        Ok(())
+    }
+
     #[test]
     fn test_file_invalid_path() {
         let file_base = "/some/crazy/path/test_log.log";
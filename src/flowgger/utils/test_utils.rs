@@ -1,11 +1,17 @@
 #[cfg(test)]
 pub mod rfc_test_utils {
-    use crate::flowgger::utils;
+    use crate::flowgger::record::Timestamp;
     use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time};
 
     /// Converts a partial date to a timestamp in ms assuming the year is the current one
     #[inline]
-    pub fn ts_from_partial_date_time(month: Month, day: u8, hour: u8, min: u8, sec: u8) -> f64 {
+    pub fn ts_from_partial_date_time(
+        month: Month,
+        day: u8,
+        hour: u8,
+        min: u8,
+        sec: u8,
+    ) -> Timestamp {
         ts_from_date_time(
             OffsetDateTime::now_utc().year(),
             month,
@@ -44,8 +50,45 @@ pub mod rfc_test_utils {
         min: u8,
         sec: u8,
         msec: u16,
-    ) -> f64 {
+    ) -> Timestamp {
         let dt = new_date_time(year, month, day, hour, min, sec, msec);
-        utils::PreciseTimestamp::from_offset_datetime(dt).as_f64()
+        Timestamp::from_offset_datetime(dt)
+    }
+}
+
+#[cfg(test)]
+pub mod record_test_utils {
+    use crate::flowgger::decoder::Decoder;
+    use crate::flowgger::encoder::Encoder;
+    use crate::flowgger::record::{Record, Timestamp};
+
+    /// Timestamp of the records decoded by `TestDecoder`
+    pub const TEST_TS: f64 = 1385053862.3072;
+
+    /// Decoder of records from "example.org" with the line as the message, rejecting "invalid" lines
+    #[derive(Clone)]
+    pub struct TestDecoder;
+
+    impl Decoder for TestDecoder {
+        fn decode<'a>(&self, line: &'a str) -> Result<Record<'a>, &'static str> {
+            if line == "invalid" {
+                return Err("Invalid record");
+            }
+            Ok(Record::builder()
+                .ts(Timestamp::from_secs_f64(TEST_TS))
+                .hostname("example.org")
+                .msg(line)
+                .build())
+        }
+    }
+
+    /// Encoder writing the message of the records, or nothing without one
+    #[derive(Clone)]
+    pub struct MsgEncoder;
+
+    impl Encoder for MsgEncoder {
+        fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
+            Ok(record.msg.unwrap_or_default().into_owned().into_bytes())
+        }
     }
 }
@@ -0,0 +1,101 @@
+use crate::flowgger::config::Config;
+use core_affinity::CoreId;
+use serde::Deserialize;
+use std::io::{stderr, Write};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Settings of the CPU affinity, in the `[input]` or `[output]` section
+#[derive(Deserialize)]
+pub struct CpuAffinitySettings {
+    cpu_affinity: Option<Vec<usize>>,
+}
+
+/// CPUs the worker threads of an input or an output are pinned to. Workers are assigned to the listed
+/// CPUs in turn.
+#[derive(Clone, Default)]
+pub struct CpuAffinity {
+    cpus: Arc<[usize]>,
+}
+
+impl CpuAffinity {
+    /// # Parameters
+    /// - `section`: "input" or "output"
+    /// - '<section>.cpu_affinity': Optional. List of CPU ids. Threads are not pinned if it is not set.
+    pub fn new(config: &Config, section: &str) -> CpuAffinity {
+        let settings: CpuAffinitySettings = config.settings(section);
+        match settings.cpu_affinity {
+            None => CpuAffinity::default(),
+            Some(cpus) => CpuAffinity { cpus: cpus.into() },
+        }
+    }
+
+    /// CPU of the worker number `index`, if threads are pinned
+    pub fn cpu(&self, index: usize) -> Option<usize> {
+        if self.cpus.is_empty() {
+            return None;
+        }
+        Some(self.cpus[index % self.cpus.len()])
+    }
+}
+
+/// Spawn a named thread, i.e. "flowgger-output-tls-0", pinned to a CPU if one is given
+pub fn spawn<F, T>(name: String, cpu: Option<usize>, f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    thread::Builder::new()
+        .name(name)
+        .spawn(move || {
+            if let Some(cpu) = cpu {
+                pin_current(cpu);
+            }
+            f()
+        })
+        .expect("Unable to spawn a thread")
+}
+
+/// Pin the current thread to a CPU
+pub fn pin_current(cpu: usize) {
+    if !core_affinity::set_for_current(CoreId { id: cpu }) {
+        let _ = writeln!(
+            stderr(),
+            "Unable to pin thread [{}] to CPU {}",
+            thread::current().name().unwrap_or("-"),
+            cpu
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_affinity() {
+        let config = Config::from_string("[output]\ncpu_affinity = [2, 3]\n").unwrap();
+        let affinity = CpuAffinity::new(&config, "output");
+        assert_eq!(affinity.cpu(0), Some(2));
+        assert_eq!(affinity.cpu(1), Some(3));
+        assert_eq!(affinity.cpu(2), Some(2));
+        assert_eq!(CpuAffinity::new(&config, "input").cpu(0), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "for key `input.cpu_affinity`")]
+    fn test_cpu_affinity_invalid() {
+        let config = Config::from_string("[input]\ncpu_affinity = [-1]\n").unwrap();
+        let _ = CpuAffinity::new(&config, "input");
+    }
+
+    #[test]
+    fn test_spawn_named() {
+        let name = spawn("flowgger-test".to_owned(), None, || {
+            thread::current().name().map(|name| name.to_owned())
+        })
+        .join()
+        .unwrap();
+        assert_eq!(name.as_deref(), Some("flowgger-test"));
+    }
+}
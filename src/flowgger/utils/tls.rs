@@ -0,0 +1,280 @@
+use crate::flowgger::config::Config;
+use openssl::ex_data::Index;
+use openssl::ssl::{
+    ErrorCode, Ssl, SslContextBuilder, SslOptions, SslRef, SslSession, SslSessionCacheMode,
+    SslStream, SslVersion,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const DEFAULT_INPUT_SESSION_RESUMPTION: bool = false;
+const DEFAULT_OUTPUT_SESSION_RESUMPTION: bool = true;
+const SESSION_ID_CONTEXT: &[u8] = b"flowgger";
+const TICKET_WAIT: Duration = Duration::from_millis(200);
+const TICKET_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Settings of the TLS protocol, shared by the TLS input and output, in the `[input]` or `[output]` section
+#[derive(Deserialize)]
+pub struct TlsProtocolSettings {
+    tls_min_version: Option<String>,
+    tls_ciphersuites: Option<String>,
+    tls_session_resumption: Option<bool>,
+}
+
+/// Set the minimum protocol version and the TLS 1.3 ciphersuites, shared by the TLS input and output.
+/// The 'tls_ciphers' list only applies to TLS 1.2.
+///
+/// # Parameters
+/// - `section`: "input" or "output"
+/// - '<section>.tls_min_version': Optional. "TLS1.2" or "TLS1.3".
+/// - '<section>.tls_ciphersuites': Optional. TLS 1.3 ciphersuites, i.e. "TLS_AES_256_GCM_SHA384:TLS_AES_128_GCM_SHA256".
+pub fn set_protocol_options(ctx: &mut SslContextBuilder, config: &Config, section: &str) {
+    let settings: TlsProtocolSettings = config.settings(section);
+    if let Some(version) = settings.tls_min_version {
+        let version = match version.to_uppercase().as_ref() {
+            "TLS1.2" | "1.2" => SslVersion::TLS1_2,
+            "TLS1.3" | "1.3" => SslVersion::TLS1_3,
+            _ => panic!(
+                r#"{}.tls_min_version must be "TLS1.2" or "TLS1.3""#,
+                section
+            ),
+        };
+        ctx.set_min_proto_version(Some(version))
+            .expect("Unable to set the minimum TLS version");
+    }
+    if let Some(ciphersuites) = settings.tls_ciphersuites {
+        ctx.set_ciphersuites(&ciphersuites)
+            .expect("Unsupported TLS 1.3 ciphersuites");
+    }
+}
+
+/// Whether TLS sessions are resumed, so that reconnections only take an abbreviated handshake
+///
+/// # Parameters
+/// - `section`: "input" or "output"
+/// - '<section>.tls_session_resumption': Optional. Default is false for the input, since resumed
+///   handshakes skip the verification of the client certificate, and true for the output.
+pub fn session_resumption(config: &Config, section: &str) -> bool {
+    let default = if section == "input" {
+        DEFAULT_INPUT_SESSION_RESUMPTION
+    } else {
+        DEFAULT_OUTPUT_SESSION_RESUMPTION
+    };
+    let settings: TlsProtocolSettings = config.settings(section);
+    settings.tls_session_resumption.unwrap_or(default)
+}
+
+/// Let clients resume their sessions, with session tickets and the session cache, or prevent it
+pub fn set_server_session_resumption(ctx: &mut SslContextBuilder, enabled: bool) {
+    if enabled {
+        // Without a session ID context, the sessions of verified clients can't be resumed
+        ctx.set_session_id_context(SESSION_ID_CONTEXT)
+            .expect("Unable to set the TLS session ID context");
+    } else {
+        ctx.set_session_cache_mode(SslSessionCacheMode::OFF);
+        ctx.set_options(SslOptions::NO_TICKET);
+    }
+}
+
+/// The server a connection is made to, and whether it sent a new session
+struct SessionKey {
+    server: String,
+    received: bool,
+}
+
+/// The last session of every server a client connected to, resumed when connecting to it again
+#[derive(Clone)]
+pub struct SessionCache {
+    sessions: Arc<Mutex<HashMap<String, SslSession>>>,
+    index: Index<Ssl, SessionKey>,
+}
+
+impl SessionCache {
+    /// Keep the sessions negotiated by the connections of the client context `ctx`
+    pub fn new(ctx: &mut SslContextBuilder) -> SessionCache {
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        let index =
+            Ssl::new_ex_index::<SessionKey>().expect("Unable to allocate a TLS session index");
+        ctx.set_session_cache_mode(SslSessionCacheMode::CLIENT | SslSessionCacheMode::NO_INTERNAL);
+        let new_sessions = Arc::clone(&sessions);
+        ctx.set_new_session_callback(move |ssl, session| {
+            // OpenSSL marks the session of a connection that was not shut down as not resumable, which is
+            // how most connections of an output end, so a copy of it is kept instead
+            let session = match session.to_der().and_then(|der| SslSession::from_der(&der)) {
+                Ok(session) => session,
+                Err(_) => return,
+            };
+            if let Some(key) = ssl.ex_data_mut(index) {
+                key.received = true;
+                new_sessions
+                    .lock()
+                    .unwrap()
+                    .insert(key.server.clone(), session);
+            }
+        });
+        SessionCache { sessions, index }
+    }
+
+    /// Offer the last session with `server`, if any, before the handshake of `ssl`
+    pub fn resume(&self, ssl: &mut SslRef, server: &str) {
+        let key = SessionKey {
+            server: server.to_owned(),
+            received: false,
+        };
+        ssl.set_ex_data(self.index, key);
+        if let Some(session) = self.sessions.lock().unwrap().get(server) {
+            // The session was negotiated by a connection of the same context. If the server doesn't
+            // accept it any more, a full handshake is made.
+            let _ = unsafe { ssl.set_session(session) };
+        }
+    }
+
+    /// Receive the session tickets that TLS 1.3 servers send after the handshake.
+    ///
+    /// Clients that only write would never read them, so they are waited for, for at most `TICKET_WAIT`.
+    /// Errors are left to the writes that follow, only the ones of the socket options are returned.
+    /// Records are never sent as TLS 1.3 early data (0-RTT), that could be replayed.
+    pub fn receive_tickets(&self, stream: &mut SslStream<TcpStream>) -> io::Result<()> {
+        if stream.ssl().version2() != Some(SslVersion::TLS1_3) {
+            return Ok(());
+        }
+        let deadline = Instant::now() + TICKET_WAIT;
+        stream
+            .get_ref()
+            .set_read_timeout(Some(TICKET_POLL_INTERVAL))?;
+        let mut buf = [0; 1];
+        while !self.received(stream) && Instant::now() < deadline {
+            match stream.ssl_read(&mut buf) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(ref e) if e.code() == ErrorCode::WANT_READ => {}
+                // The connection is broken, which the next write reports
+                Err(_) => break,
+            }
+        }
+        stream.get_ref().set_read_timeout(None)
+    }
+
+    fn received(&self, stream: &SslStream<TcpStream>) -> bool {
+        stream
+            .ssl()
+            .ex_data(self.index)
+            .is_some_and(|key| key.received)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::asn1::Asn1Time;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::ssl::{SslAcceptor, SslConnector, SslContext, SslMethod, SslVerifyMode};
+    use openssl::x509::{X509NameBuilder, X509};
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn context(config: &str) -> SslContextBuilder {
+        let config = Config::from_string(config).unwrap();
+        let mut ctx = SslContext::builder(SslMethod::tls()).unwrap();
+        set_protocol_options(&mut ctx, &config, "output");
+        ctx
+    }
+
+    #[test]
+    fn test_tls_min_version() {
+        let mut ctx = context("[output]\ntls_min_version = \"TLS1.3\"\n");
+        assert_eq!(ctx.min_proto_version(), Some(SslVersion::TLS1_3));
+        let mut ctx = context("[output]\ntls_min_version = \"tls1.2\"\n");
+        assert_eq!(ctx.min_proto_version(), Some(SslVersion::TLS1_2));
+    }
+
+    #[test]
+    #[should_panic(expected = r#"output.tls_min_version must be "TLS1.2" or "TLS1.3""#)]
+    fn test_tls_min_version_invalid() {
+        let _ = context("[output]\ntls_min_version = \"TLS1.0\"\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "Unsupported TLS 1.3 ciphersuites")]
+    fn test_tls_ciphersuites_invalid() {
+        let _ = context("[output]\ntls_ciphersuites = \"TLS_NOT_A_CIPHERSUITE\"\n");
+    }
+
+    /// A server with a self-signed certificate, accepting `connections` connections
+    fn test_server(version: SslVersion, connections: usize) -> std::net::SocketAddr {
+        let key = PKey::from_ec_key(
+            EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap(),
+        )
+        .unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "localhost").unwrap();
+        let name = name.build();
+        let mut cert = X509::builder().unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+        let cert = cert.build();
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_certificate(&cert).unwrap();
+        acceptor.set_private_key(&key).unwrap();
+        acceptor.set_max_proto_version(Some(version)).unwrap();
+        set_server_session_resumption(&mut acceptor, true);
+        let acceptor = acceptor.build();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for client in listener.incoming().take(connections) {
+                if let Ok(mut stream) = acceptor.accept(client.unwrap()) {
+                    let _ = stream.read_to_end(&mut Vec::new());
+                }
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn test_session_resumption_config() {
+        let config = Config::from_string("").unwrap();
+        assert!(!session_resumption(&config, "input"));
+        assert!(session_resumption(&config, "output"));
+        let config = Config::from_string("[input]\ntls_session_resumption = true\n").unwrap();
+        assert!(session_resumption(&config, "input"));
+    }
+
+    #[test]
+    fn test_session_cache() {
+        for version in [SslVersion::TLS1_2, SslVersion::TLS1_3] {
+            let addr = test_server(version, 2);
+            let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+            connector.set_verify(SslVerifyMode::NONE);
+            let sessions = SessionCache::new(&mut connector);
+            let connector = connector.build();
+            let reused: Vec<bool> = (0..2)
+                .map(|_| {
+                    let mut ssl = connector.configure().unwrap();
+                    sessions.resume(&mut ssl, "localhost");
+                    let client = TcpStream::connect(addr).unwrap();
+                    let mut stream = ssl.connect("localhost", client).unwrap();
+                    sessions.receive_tickets(&mut stream).unwrap();
+                    assert_eq!(stream.ssl().version2(), Some(version));
+                    stream.ssl().session_reused()
+                })
+                .collect();
+            assert_eq!(reused, vec![false, true], "{:?}", version);
+        }
+    }
+}
@@ -2,11 +2,23 @@
 #[macro_use]
 extern crate may;
 
-#[cfg(feature = "capnp-recompile")]
+#[cfg(feature = "capnp")]
+#[allow(clippy::all, mismatched_lifetime_syntaxes)]
 pub mod record_capnp;
 
 pub mod flowgger;
 
+pub use crate::flowgger::daemon;
+#[cfg(fuzzing)]
+pub use crate::flowgger::fuzzing;
+#[cfg(feature = "test-util")]
+pub use crate::flowgger::test_util;
+pub use crate::flowgger::Notifier;
+pub use crate::flowgger::{
+    Facility, Record, RecordBuilder, SDValue, Severity, StructuredData, Timestamp,
+};
+use std::sync::Arc;
+
 /// Start a flowgger instance starting from a file path
 ///
 /// # Parameters
@@ -16,5 +28,82 @@ pub mod flowgger;
 /// This panics when the configuration file was not able to be parsed, when there's non supported input/outputs or encoder/decoders in the configuration
 /// file.
 pub fn start(config_file: &str) {
-    flowgger::start(config_file);
+    flowgger::start(config_file, None);
+}
+
+/// Start a flowgger instance starting from a file path, and get notified about the deliveries made by the output
+///
+/// # Parameters
+/// - `config_file`: path to a configuration file in &str format
+/// - `notifier`: called by the output for each batch of records, once they have been delivered or failed to be
+///
+/// # Panics
+/// Same as `start`
+pub fn start_with_notifier(config_file: &str, notifier: Arc<dyn Notifier>) {
+    flowgger::start(config_file, Some(notifier));
+}
+
+/// Verify the hash chain of files written by the file output with 'output.file_hash_chain_key'
+///
+/// # Parameters
+/// - `config_file`: path to the configuration file the files were written with
+/// - `paths`: files to verify, oldest first, the chain continuing from one file to the next
+/// - `previous_tag`: tag of the last line of the file preceding the first one, in hex, if the chain
+///   doesn't start in the first file
+/// - `allow_continuation`: accept a first line continuing a chain started in an unlisted file, unverified
+///
+/// # Returns
+/// The number of lines verified, or the location of the first line breaking the chain
+#[cfg(feature = "file")]
+pub fn verify_hash_chain(
+    config_file: &str,
+    paths: &[&str],
+    previous_tag: Option<&str>,
+    allow_continuation: bool,
+) -> Result<u64, String> {
+    flowgger::verify_hash_chain(config_file, paths, previous_tag, allow_continuation)
+}
+
+/// Decode records and print them, to debug a device format without a whole pipeline
+///
+/// # Parameters
+/// - `config_file`: optional configuration file, for the settings of the decoder
+/// - `format`: optional input format, overriding 'input.format'
+/// - `record`: record to decode, every line of stdin being decoded if it is `None`
+///
+/// # Returns
+/// The number of records that could not be decoded
+pub fn decode(config_file: Option<&str>, format: Option<&str>, record: Option<&str>) -> usize {
+    flowgger::decode(config_file, format, record)
+}
+
+/// Decode records, encode them with the output format and print them
+///
+/// # Parameters
+/// - `config_file`: optional configuration file, for the settings of the decoder and of the encoder
+/// - `input_format`: optional input format, overriding 'input.format'
+/// - `format`: optional output format, overriding 'output.format'
+/// - `record`: record to encode, every line of stdin being encoded if it is `None`
+///
+/// # Returns
+/// The number of records that could not be decoded or encoded
+pub fn encode(
+    config_file: Option<&str>,
+    input_format: Option<&str>,
+    format: Option<&str>,
+    record: Option<&str>,
+) -> usize {
+    flowgger::encode(config_file, input_format, format, record)
+}
+
+/// Starter configuration for an input type and an output type, with their settings and default values
+///
+/// # Parameters
+/// - `input_type`: type of the input, the default input type if it is `None`
+/// - `output_type`: type of the output, the default output type if it is `None`
+///
+/// # Returns
+/// The configuration, in the TOML format, or an error if a type is unknown or hasn't been compiled in
+pub fn config_init(input_type: Option<&str>, output_type: Option<&str>) -> Result<String, String> {
+    flowgger::config_init(input_type, output_type)
 }
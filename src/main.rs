@@ -1,7 +1,10 @@
 extern crate flowgger;
 
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, Command};
+use flowgger::daemon;
 use std::io::{stderr, Write};
+use std::path::{self, Path};
+use std::process::exit;
 
 const DEFAULT_CONFIG_FILE: &str = "flowgger.toml";
 const FLOWGGER_VERSION_STRING: &str = env!("CARGO_PKG_VERSION");
@@ -16,11 +19,275 @@ fn main() {
                 .value_name("FILE")
                 .index(1),
         )
+        .arg(
+            Arg::new("pidfile")
+                .long("pidfile")
+                .help("Write the process id to this file")
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("background")
+                .short('d')
+                .long("background")
+                .help(
+                    "Detach and run in the background, from the root directory, once every input is \
+                     listening. Errors are discarded once started",
+                )
+                .action(ArgAction::SetTrue)
+                .conflicts_with("foreground"),
+        )
+        .arg(
+            Arg::new("foreground")
+                .short('f')
+                .long("foreground")
+                .help("Run in the foreground (default)")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("service")
+                .long("service")
+                .help("Install, uninstall or run as a Windows service")
+                .value_name("COMMAND")
+                .value_parser(["install", "uninstall", "run"])
+                .conflicts_with_all(["background", "pidfile"]),
+        )
+        .subcommand(
+            Command::new("verify-chain")
+                .about("Verify the hash chain of files written with output.file_hash_chain_key")
+                .arg(
+                    Arg::new("config_file")
+                        .short('c')
+                        .long("config")
+                        .help("Configuration file the files were written with")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::new("previous_tag")
+                        .long("previous-tag")
+                        .help("Tag of the last line of the file preceding the first file, in hex")
+                        .value_name("TAG"),
+                )
+                .arg(
+                    Arg::new("allow_continuation")
+                        .long("allow-continuation")
+                        .help("Accept a first line continuing a chain started in an unlisted file")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("previous_tag"),
+                )
+                .arg(
+                    Arg::new("files")
+                        .help("Files to verify, oldest first")
+                        .value_name("FILES")
+                        .num_args(1..)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("decode")
+                .about(
+                    "Decode records and print them, from the command line or one per line of stdin",
+                )
+                .arg(config_arg())
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("Input format, overriding input.format")
+                        .value_name("FORMAT"),
+                )
+                .arg(record_arg()),
+        )
+        .subcommand(
+            Command::new("encode")
+                .about("Decode records, encode them with the output format and print them")
+                .arg(config_arg())
+                .arg(
+                    Arg::new("input_format")
+                        .long("input-format")
+                        .help("Input format, overriding input.format")
+                        .value_name("FORMAT"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("Output format, overriding output.format")
+                        .value_name("FORMAT"),
+                )
+                .arg(record_arg()),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Configuration helpers")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("init")
+                        .about("Print a starter configuration for an input and an output")
+                        .arg(
+                            Arg::new("input")
+                                .long("input")
+                                .help("Input type, i.e. udp, tcp, tls or file")
+                                .value_name("TYPE"),
+                        )
+                        .arg(
+                            Arg::new("output")
+                                .long("output")
+                                .help("Output type, i.e. kafka, tls, file or debug")
+                                .value_name("TYPE"),
+                        ),
+                ),
+        )
+        .args_conflicts_with_subcommands(true)
         .get_matches();
+    if let Some(("init", matches)) = matches
+        .subcommand_matches("config")
+        .and_then(|matches| matches.subcommand())
+    {
+        match flowgger::config_init(
+            matches.get_one::<String>("input").map(|s| s.as_ref()),
+            matches.get_one::<String>("output").map(|s| s.as_ref()),
+        ) {
+            Ok(config) => print!("{}", config),
+            Err(e) => daemon::fail(daemon::EXIT_USAGE, &e),
+        }
+        return;
+    }
+    if let Some(matches) = matches.subcommand_matches("decode") {
+        let failures = flowgger::decode(
+            matches.get_one::<String>("config_file").map(|s| s.as_ref()),
+            matches.get_one::<String>("format").map(|s| s.as_ref()),
+            matches.get_one::<String>("record").map(|s| s.as_ref()),
+        );
+        exit(if failures > 0 {
+            daemon::EXIT_DATAERR
+        } else {
+            0
+        });
+    }
+    if let Some(matches) = matches.subcommand_matches("encode") {
+        let failures = flowgger::encode(
+            matches.get_one::<String>("config_file").map(|s| s.as_ref()),
+            matches
+                .get_one::<String>("input_format")
+                .map(|s| s.as_ref()),
+            matches.get_one::<String>("format").map(|s| s.as_ref()),
+            matches.get_one::<String>("record").map(|s| s.as_ref()),
+        );
+        exit(if failures > 0 {
+            daemon::EXIT_DATAERR
+        } else {
+            0
+        });
+    }
+    if let Some(matches) = matches.subcommand_matches("verify-chain") {
+        let config_file = matches
+            .get_one::<String>("config_file")
+            .map(|s| s.as_ref())
+            .unwrap_or(DEFAULT_CONFIG_FILE);
+        let paths: Vec<&str> = matches
+            .get_many::<String>("files")
+            .unwrap()
+            .map(|s| s.as_ref())
+            .collect();
+        let previous_tag = matches
+            .get_one::<String>("previous_tag")
+            .map(|s| s.as_ref());
+        verify_chain(
+            config_file,
+            &paths,
+            previous_tag,
+            matches.get_flag("allow_continuation"),
+        );
+        return;
+    }
     let config_file = matches
         .get_one::<String>("config_file")
         .map(|s| s.as_ref())
         .unwrap_or(DEFAULT_CONFIG_FILE);
+    let pidfile = matches.get_one::<String>("pidfile").map(Path::new);
+    if let Some(command) = matches.get_one::<String>("service") {
+        service(command, config_file);
+        return;
+    }
     let _ = writeln!(stderr(), "Flowgger {}", FLOWGGER_VERSION_STRING);
-    flowgger::start(config_file)
+    let mut config_file = config_file.to_owned();
+    let mut pidfile = pidfile.map(Path::to_path_buf);
+    if matches.get_flag("background") {
+        // Paths given on the command line stay relative to the directory flowgger was started from
+        if let Ok(path) = path::absolute(&config_file) {
+            config_file = path.to_string_lossy().into_owned();
+        }
+        pidfile = pidfile.map(|pidfile| path::absolute(&pidfile).unwrap_or(pidfile));
+        background();
+    }
+    if let Some(pidfile) = pidfile {
+        daemon::write_pidfile(&pidfile);
+    }
+    daemon::exit_on_panic();
+    flowgger::start(&config_file);
+}
+
+fn config_arg() -> Arg {
+    Arg::new("config_file")
+        .short('c')
+        .long("config")
+        .help("Configuration file with the settings of the decoder and of the encoder")
+        .value_name("FILE")
+}
+
+fn record_arg() -> Arg {
+    Arg::new("record")
+        .help("Record to handle, instead of every line of stdin")
+        .value_name("RECORD")
+}
+
+#[cfg(feature = "file")]
+fn verify_chain(
+    config_file: &str,
+    paths: &[&str],
+    previous_tag: Option<&str>,
+    allow_continuation: bool,
+) {
+    match flowgger::verify_hash_chain(config_file, paths, previous_tag, allow_continuation) {
+        Ok(count) => println!("{} lines verified", count),
+        Err(e) => daemon::fail(daemon::EXIT_DATAERR, &e),
+    }
+}
+
+#[cfg(not(feature = "file"))]
+fn verify_chain(
+    _config_file: &str,
+    _paths: &[&str],
+    _previous_tag: Option<&str>,
+    _allow_continuation: bool,
+) {
+    daemon::fail(
+        daemon::EXIT_USAGE,
+        "Support for the file output hasn't been compiled in",
+    );
+}
+
+#[cfg(unix)]
+fn background() {
+    daemon::background();
+}
+
+#[cfg(not(unix))]
+fn background() {
+    daemon::fail(
+        daemon::EXIT_USAGE,
+        "Running in the background is only supported on Unix, use --service on Windows",
+    );
+}
+
+#[cfg(windows)]
+fn service(command: &str, config_file: &str) {
+    match command {
+        "install" => daemon::windows_service::install(config_file),
+        "uninstall" => daemon::windows_service::uninstall(),
+        _ => daemon::windows_service::run(config_file),
+    }
+}
+
+#[cfg(not(windows))]
+fn service(_command: &str, _config_file: &str) {
+    daemon::fail(daemon::EXIT_USAGE, "Services are only supported on Windows");
 }
@@ -174,6 +174,10 @@ pub mod record {
         pub fn has_extra(&self) -> bool {
             !self.reader.get_pointer_field(8).is_null()
         }
+        #[inline]
+        pub fn get_version(self) -> u16 {
+            self.reader.get_data_field::<u16>(5)
+        }
     }
 
     pub struct Builder<'a> {
@@ -463,6 +467,14 @@ pub mod record {
         pub fn has_extra(&self) -> bool {
             !self.builder.get_pointer_field(8).is_null()
         }
+        #[inline]
+        pub fn get_version(self) -> u16 {
+            self.builder.get_data_field::<u16>(5)
+        }
+        #[inline]
+        pub fn set_version(&mut self, value: u16) {
+            self.builder.set_data_field::<u16>(5, value);
+        }
     }
 
     pub struct Pipeline {